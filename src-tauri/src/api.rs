@@ -1,24 +1,161 @@
 use crate::controllers::{
-    annotation_controller, auth_controller, book_controller, bookmark_controller,
-    reading_progress_controller, search_controller, user_controller,
+    annotation_controller, api_key_controller, audit_controller, auth_controller,
+    book_controller, bookmark_controller, history_controller, library_controller,
+    metadata_controller, opds2_controller, opds_controller, password_controller,
+    reading_progress_controller, registration_controller, search_controller, session_controller,
+    two_factor_controller, user_controller,
 };
+use crate::services::{book_service, token_service::Tokenizer};
 use axum::routing::{delete, get, post, put};
 use axum::Router;
 use std::net::SocketAddr;
 use tokio::net::TcpListener;
 
-pub fn start() {
+/// Every route of the server, without the connection info `start` adds
+pub fn router() -> Router {
     // TODO: Add Swagger documentation
     // TODO: Test all routes
-    let api: Router<()> = Router::new()
+    Router::new()
         .route("/", get(|| async { "Hello, World!" }))
         .route("/register", post(user_controller::create_user))
+        .route("/registration", get(registration_controller::get_status))
+        .route("/registration", put(registration_controller::set_policy))
+        .route("/invites", get(registration_controller::list_invites))
+        .route("/invites", post(registration_controller::create_invite))
+        .route("/invites/{id}", delete(registration_controller::revoke_invite))
         .route("/list_users", get(user_controller::list_users))
         .route("/user", get(user_controller::get_user))
+        .route("/user", put(user_controller::update_profile))
+        .route("/users", post(user_controller::admin_create_user))
+        .route("/users/{id}", put(user_controller::update_user))
+        .route("/users/{id}", delete(user_controller::delete_user))
+        .route("/users/{id}/disable", post(user_controller::disable_user))
+        .route("/users/{id}/enable", post(user_controller::enable_user))
+        .route("/user/password", post(password_controller::change_password))
+        .route("/user/two_factor", get(two_factor_controller::get_status))
+        .route("/user/two_factor", post(two_factor_controller::enroll))
+        .route(
+            "/user/two_factor/confirm",
+            post(two_factor_controller::confirm),
+        )
+        .route(
+            "/user/two_factor/disable",
+            post(two_factor_controller::disable),
+        )
+        .route(
+            "/user/two_factor/recovery_codes",
+            post(two_factor_controller::regenerate_recovery_codes),
+        )
+        .route(
+            "/users/{id}/two_factor",
+            delete(two_factor_controller::admin_reset),
+        )
+        .route("/password/forgot", post(password_controller::forgot_password))
+        .route("/password/reset", post(password_controller::reset_password))
+        .route("/users/{id}/unlock", post(user_controller::unlock_user))
+        .route(
+            "/users/{id}/password_reset",
+            post(password_controller::admin_reset_password),
+        )
         .route("/login", post(auth_controller::login))
+        .route("/login/two_factor", post(auth_controller::login_two_factor))
         .route("/refresh", post(auth_controller::refresh))
         .route("/logout", post(auth_controller::logout))
+        .route("/sessions", get(session_controller::list_sessions))
+        .route("/sessions", delete(session_controller::revoke_all_sessions))
+        .route("/sessions/{id}", delete(session_controller::revoke_session))
+        .route("/api_keys", get(api_key_controller::list_api_keys))
+        .route("/api_keys", post(api_key_controller::create_api_key))
+        .route("/api_keys/{id}", delete(api_key_controller::revoke_api_key))
+        .route("/audit", get(audit_controller::list_events))
+        .route("/book/{id}", delete(book_controller::delete_book))
         .route("/book/{id}/content", get(book_controller::get_book_content))
+        .route(
+            "/book/{id}/metadata/matches",
+            get(metadata_controller::get_metadata_matches),
+        )
+        .route(
+            "/book/{id}/metadata/apply",
+            post(metadata_controller::apply_metadata),
+        )
+        .route(
+            "/book/{id}/metadata/sources",
+            get(metadata_controller::get_metadata_sources),
+        )
+        .route("/book/{id}/metadata", put(history_controller::edit_metadata))
+        .route("/book/{id}/history", get(history_controller::get_history))
+        .route(
+            "/history/{id}/revert",
+            post(history_controller::revert_change),
+        )
+        .route(
+            "/history/batch/{batch_id}/revert",
+            post(history_controller::revert_batch),
+        )
+        .route("/libraries", get(library_controller::list_libraries))
+        .route("/libraries", post(library_controller::create_library))
+        .route(
+            "/libraries/{id}/scan",
+            post(library_controller::scan_library),
+        )
+        .route(
+            "/libraries/{id}/access",
+            put(library_controller::update_access),
+        )
+        .route(
+            "/libraries/{id}/shares",
+            get(library_controller::list_shares),
+        )
+        .route(
+            "/libraries/{id}/shares",
+            post(library_controller::add_share),
+        )
+        .route(
+            "/libraries/{id}/shares/{share_id}",
+            delete(library_controller::remove_share),
+        )
+        .route(
+            "/libraries/import/calibre",
+            post(library_controller::import_calibre),
+        )
+        .route("/opds", get(opds_controller::root))
+        .route("/opds/recent", get(opds_controller::recent))
+        .route("/opds/opensearch.xml", get(opds_controller::opensearch))
+        .route("/opds/search", get(opds_controller::search))
+        .route("/opds/books", get(opds_controller::all_books))
+        .route(
+            "/opds/books/{id}/download/{format}",
+            get(opds_controller::download),
+        )
+        .route("/opds/books/{id}/pages/{page}", get(opds_controller::page))
+        .route("/opds/books/{id}/cover", get(opds_controller::cover))
+        .route("/opds/books/{id}/thumbnail", get(opds_controller::thumbnail))
+        .route("/opds/authors", get(opds_controller::authors))
+        .route("/opds/authors/{id}", get(opds_controller::author_books))
+        .route("/opds/publishers", get(opds_controller::publishers))
+        .route(
+            "/opds/publishers/{id}",
+            get(opds_controller::publisher_books),
+        )
+        .route("/opds/libraries", get(opds_controller::libraries))
+        .route("/opds/libraries/{id}", get(opds_controller::library_books))
+        .route("/opds/v2", get(opds2_controller::root))
+        .route("/opds/v2/recent", get(opds2_controller::recent))
+        .route("/opds/v2/search", get(opds2_controller::search))
+        .route("/opds/v2/books", get(opds2_controller::books))
+        .route("/opds/v2/books/{id}", get(opds2_controller::publication))
+        .route("/opds/v2/authors", get(opds2_controller::authors))
+        .route("/opds/v2/authors/{id}", get(opds2_controller::author_books))
+        .route("/opds/v2/publishers", get(opds2_controller::publishers))
+        .route(
+            "/opds/v2/publishers/{id}",
+            get(opds2_controller::publisher_books),
+        )
+        .route("/opds/v2/libraries", get(opds2_controller::libraries))
+        .route(
+            "/opds/v2/libraries/{id}",
+            get(opds2_controller::library_books),
+        )
         .route("/bookmarks", post(bookmark_controller::create_bookmark))
         .route("/bookmarks", get(bookmark_controller::get_bookmarks))
         .route(
@@ -50,14 +187,34 @@ pub fn start() {
         .route("/search/books", get(search_controller::search_books))
         .route("/search/authors", get(search_controller::search_authors))
         .route("/books", get(search_controller::list_all_books))
-        .with_state(());
+        .with_state(())
+}
+
+pub fn start() {
+    let api = router();
 
     tokio::spawn(async move {
+        if let Err(e) = Tokenizer::init().await {
+            eprintln!("Not starting server, JWT signing can't be set up: {}", e);
+            return;
+        }
+        match book_service::normalize_stored_isbns().await {
+            Ok(0) => (),
+            Ok(count) => println!("Stored the ISBNs of {} books as ISBN-13", count),
+            Err(e) => eprintln!("Failed to normalize stored ISBNs: {}", e),
+        }
+
         let addr = SocketAddr::from(([127, 0, 0, 1], 3000));
 
         println!("Starting server on {}", addr);
 
-        match axum::serve(TcpListener::bind(addr).await.expect("Failed to bind on address"), api).await {
+        // Connection info lets the login throttle see client addresses
+        match axum::serve(
+            TcpListener::bind(addr).await.expect("Failed to bind on address"),
+            api.into_make_service_with_connect_info::<SocketAddr>(),
+        )
+        .await
+        {
             Ok(_) => (),
             Err(e) => eprintln!("Error starting server: {}", e),
         }
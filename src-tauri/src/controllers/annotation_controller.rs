@@ -2,51 +2,36 @@ use crate::{
     data::models::annotations::{NewAnnotation, UpdateAnnotation},
     data::repos::implementors::annotation_repo::AnnotationRepo,
     data::repos::traits::repository::Repository,
-    services::token_service::Tokenizer,
 };
 use axum::{
     extract::{Path, Query},
-    http::{HeaderMap, StatusCode},
+    http::StatusCode,
     response::IntoResponse,
     Json,
 };
 use chrono::Utc;
 use serde::Deserialize;
 
+use super::auth_middleware::{MemberUser, SyncUser};
 use super::dto::annotation_dto::{AnnotationDTO, NewAnnotationDTO, UpdateAnnotationDTO};
+use super::library_controller::require_book;
 
 #[derive(Deserialize)]
 pub struct BookQueryParams {
     book_id: i32,
 }
 
-async fn get_user_from_token(headers: &HeaderMap) -> Result<i32, StatusCode> {
-    let token = headers
-        .get("Authorization")
-        .and_then(|v| v.to_str().ok())
-        .and_then(|v| v.strip_prefix("Bearer "))
-        .ok_or(StatusCode::UNAUTHORIZED)?;
-
-    let tokenizer = Tokenizer::get_instance().await;
-    let claims = tokenizer
-        .decode_token(token)
-        .map_err(|_| StatusCode::UNAUTHORIZED)?;
-
-    Ok(claims.sub)
-}
-
 pub async fn create_annotation(
-    headers: HeaderMap,
+    user: MemberUser,
     Json(payload): Json<NewAnnotationDTO>,
 ) -> impl IntoResponse {
-    let user_id = match get_user_from_token(&headers).await {
-        Ok(id) => id,
-        Err(status) => return (status, "Unauthorized").into_response(),
-    };
+    if let Err(response) = require_book(user.id, payload.book_id).await {
+        return response;
+    }
 
     let repo = AnnotationRepo::new().await;
     let new_annotation = NewAnnotation {
-        user_id,
+        user_id: user.id,
         book_id: payload.book_id,
         chapter_title: payload.chapter_title.as_deref(),
         start_position: &payload.start_position,
@@ -63,16 +48,11 @@ pub async fn create_annotation(
 }
 
 pub async fn get_annotations(
-    headers: HeaderMap,
+    user: SyncUser,
     Query(params): Query<BookQueryParams>,
 ) -> impl IntoResponse {
-    let user_id = match get_user_from_token(&headers).await {
-        Ok(id) => id,
-        Err(status) => return (status, Json(Vec::<AnnotationDTO>::new())).into_response(),
-    };
-
     let repo = AnnotationRepo::new().await;
-    match repo.get_by_user_and_book(user_id, params.book_id).await {
+    match repo.get_by_user_and_book(user.id, params.book_id).await {
         Ok(Some(annotations)) => {
             let dtos: Vec<AnnotationDTO> = annotations
                 .into_iter()
@@ -102,16 +82,18 @@ pub async fn get_annotations(
 }
 
 pub async fn update_annotation(
-    headers: HeaderMap,
+    user: MemberUser,
     Path(annotation_id): Path<i32>,
     Json(payload): Json<UpdateAnnotationDTO>,
 ) -> impl IntoResponse {
-    let _user_id = match get_user_from_token(&headers).await {
-        Ok(id) => id,
-        Err(status) => return (status, "Unauthorized").into_response(),
-    };
-
     let repo = AnnotationRepo::new().await;
+    // Annotations of other users are reported as missing
+    match repo.get_by_id(annotation_id).await {
+        Ok(Some(item)) if item.user_id == user.id => (),
+        Ok(_) => return (StatusCode::NOT_FOUND, "Annotation not found").into_response(),
+        Err(_) => return (StatusCode::INTERNAL_SERVER_ERROR, "Database error").into_response(),
+    }
+
     let now = Utc::now().format("%Y-%m-%d %H:%M:%S").to_string();
     let update = UpdateAnnotation {
         chapter_title: payload.chapter_title.as_deref(),
@@ -130,15 +112,17 @@ pub async fn update_annotation(
 }
 
 pub async fn delete_annotation(
-    headers: HeaderMap,
+    user: MemberUser,
     Path(annotation_id): Path<i32>,
 ) -> impl IntoResponse {
-    let _user_id = match get_user_from_token(&headers).await {
-        Ok(id) => id,
-        Err(status) => return (status, "Unauthorized").into_response(),
-    };
-
     let repo = AnnotationRepo::new().await;
+    // Annotations of other users are reported as missing
+    match repo.get_by_id(annotation_id).await {
+        Ok(Some(item)) if item.user_id == user.id => (),
+        Ok(_) => return (StatusCode::NOT_FOUND, "Annotation not found").into_response(),
+        Err(_) => return (StatusCode::INTERNAL_SERVER_ERROR, "Database error").into_response(),
+    }
+
     match repo.delete(annotation_id).await {
         Ok(_) => (StatusCode::OK, "Annotation deleted").into_response(),
         Err(_) => (StatusCode::INTERNAL_SERVER_ERROR, "Database error").into_response(),
@@ -0,0 +1,117 @@
+use axum::{extract::Path, http::StatusCode, response::IntoResponse, Json};
+
+use crate::{
+    data::models::{api_keys::ApiScope, audit_events::AuditAction},
+    services::{
+        api_key_service::{ApiKeyCreation, ApiKeyService},
+        audit_service::{AuditEntry, AuditService, RequestOrigin},
+        session_service::MAX_EXPIRY_DAYS,
+    },
+};
+
+use super::auth_middleware::LoginUser;
+use super::dto::api_key_dto::{ApiKeyDTO, CreatedApiKeyDTO, NewApiKeyDTO};
+
+pub async fn create_api_key(
+    user: LoginUser,
+    origin: RequestOrigin,
+    Json(payload): Json<NewApiKeyDTO>,
+) -> impl IntoResponse {
+    let mut scopes = Vec::new();
+    for name in &payload.scopes {
+        match ApiScope::parse(name) {
+            Some(scope) => scopes.push(scope),
+            None => {
+                return (StatusCode::BAD_REQUEST, format!("Unknown scope: {}", name))
+                    .into_response()
+            }
+        }
+    }
+
+    let service = ApiKeyService::new().await;
+    match service
+        .create(user.id, &payload.name, &scopes, payload.expires_in_days)
+        .await
+    {
+        Ok(ApiKeyCreation::Created(created)) => {
+            AuditService::new()
+                .await
+                .record(
+                    &origin,
+                    AuditAction::ApiKeyCreated,
+                    AuditEntry::by(user.id)
+                        .target("api_key", created.api_key.api_key_id)
+                        .details(format!("scopes: {}", created.api_key.scopes)),
+                )
+                .await;
+            (
+                StatusCode::CREATED,
+                Json(CreatedApiKeyDTO {
+                    key: created.key,
+                    api_key: ApiKeyDTO::from(created.api_key),
+                }),
+            )
+                .into_response()
+        }
+        Ok(ApiKeyCreation::MissingName) => {
+            (StatusCode::BAD_REQUEST, "A name is required").into_response()
+        }
+        Ok(ApiKeyCreation::MissingScopes) => {
+            (StatusCode::BAD_REQUEST, "At least one scope is required").into_response()
+        }
+        Ok(ApiKeyCreation::InvalidExpiry) => (
+            StatusCode::BAD_REQUEST,
+            format!("expires_in_days must be between 1 and {}", MAX_EXPIRY_DAYS),
+        )
+            .into_response(),
+        Ok(ApiKeyCreation::AdminScopeDenied) => (
+            StatusCode::FORBIDDEN,
+            "Only admins can create keys with the admin scope",
+        )
+            .into_response(),
+        Err(e) => {
+            eprintln!("Failed to create API key: {}", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, "Database error").into_response()
+        }
+    }
+}
+
+pub async fn list_api_keys(user: LoginUser) -> impl IntoResponse {
+    let service = ApiKeyService::new().await;
+    match service.list(user.id).await {
+        Ok(keys) => {
+            let dtos: Vec<ApiKeyDTO> = keys.into_iter().map(ApiKeyDTO::from).collect();
+            (StatusCode::OK, Json(dtos)).into_response()
+        }
+        Err(e) => {
+            eprintln!("Failed to list API keys: {}", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, "Database error").into_response()
+        }
+    }
+}
+
+pub async fn revoke_api_key(
+    user: LoginUser,
+    origin: RequestOrigin,
+    Path(id): Path<i32>,
+) -> impl IntoResponse {
+    let service = ApiKeyService::new().await;
+    match service.revoke(user.id, id).await {
+        Ok(true) => {
+            AuditService::new()
+                .await
+                .record(
+                    &origin,
+                    AuditAction::ApiKeyRevoked,
+                    AuditEntry::by(user.id).target("api_key", id),
+                )
+                .await;
+            (StatusCode::OK, "API key revoked").into_response()
+        }
+        Ok(false) => (StatusCode::NOT_FOUND, "API key not found").into_response(),
+        Err(e) => {
+            eprintln!("Failed to revoke API key: {}", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, "Database error").into_response()
+        }
+    }
+}
@@ -0,0 +1,85 @@
+use axum::{
+    extract::Query,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use chrono::{DateTime, NaiveDate, Utc};
+
+use crate::{
+    controllers::{
+        auth_middleware::AdminUser,
+        dto::audit_dto::{AuditEventDTO, AuditPageDTO, AuditQuery},
+    },
+    data::{models::audit_events::AuditAction, repos::implementors::audit_event_repo::AuditFilter},
+    services::audit_service::AuditService,
+};
+
+/// A point in time in the stored UTC form, from RFC 3339 or a plain date
+fn parse_time(value: &str) -> Option<String> {
+    let value = value.trim();
+    let time = match DateTime::parse_from_rfc3339(value) {
+        Ok(time) => time.with_timezone(&Utc).naive_utc(),
+        Err(_) => NaiveDate::parse_from_str(value, "%Y-%m-%d")
+            .ok()?
+            .and_hms_opt(0, 0, 0)?,
+    };
+    Some(time.format("%Y-%m-%d %H:%M:%S").to_string())
+}
+
+fn bad_request(message: &'static str) -> Response {
+    (StatusCode::BAD_REQUEST, message).into_response()
+}
+
+/// Pages through the security audit log, newest first, admins only (GET /audit)
+pub async fn list_events(_admin: AdminUser, Query(query): Query<AuditQuery>) -> impl IntoResponse {
+    let action = match query.action.as_deref() {
+        None => None,
+        Some(value) => match AuditAction::parse(value) {
+            Some(action) => Some(action.as_str().to_string()),
+            None => return bad_request("Unknown action"),
+        },
+    };
+    let mut times = [None, None];
+    for (parsed, value) in times.iter_mut().zip([&query.since, &query.until]) {
+        if let Some(value) = value {
+            match parse_time(value) {
+                Some(time) => *parsed = Some(time),
+                None => return bad_request("Times must be RFC 3339 or YYYY-MM-DD"),
+            }
+        }
+    }
+    let [since, until] = times;
+
+    let filter = AuditFilter {
+        user_id: query.user_id,
+        action,
+        target_type: query.target_type.clone(),
+        target_id: query.target_id,
+        ip: query.ip.clone(),
+        since,
+        until,
+    };
+    let (page, per_page) = (query.page(), query.per_page());
+    match AuditService::new()
+        .await
+        .events(&filter, page, per_page)
+        .await
+    {
+        Ok((events, total)) => (
+            StatusCode::OK,
+            Json(AuditPageDTO {
+                events: events.into_iter().map(AuditEventDTO::from).collect(),
+                total,
+                page,
+                per_page,
+                retention_days: AuditService::retention_days(),
+            }),
+        )
+            .into_response(),
+        Err(e) => {
+            eprintln!("Failed to load audit events: {}", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, "Database error").into_response()
+        }
+    }
+}
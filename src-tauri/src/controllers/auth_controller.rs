@@ -1,11 +1,32 @@
 use crate::{
-    data::repos::implementors::user_repo::UserRepo,
-    services::{authentication_service::AuthenticationService, token_service},
+    controllers::{
+        auth_cookies::{
+            clear_session_cookies, csrf_matches, generate_csrf_token, session_cookies,
+            REFRESH_COOKIE,
+        },
+        auth_middleware::{csrf_rejected, throttled_response},
+    },
+    data::models::audit_events::AuditAction,
+    services::{
+        audit_service::{AuditEntry, AuditService, RequestOrigin},
+        authentication_service::{AuthenticationService, LoginOutcome},
+        session_service::{IssuedTokens, RefreshOutcome, SessionService},
+        token_service::Tokenizer,
+        two_factor_service::{ChallengeOutcome, TwoFactorService},
+    },
 };
-use axum::{http::StatusCode, response::IntoResponse, Json};
+use axum::{
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use axum_extra::extract::CookieJar;
 use serde::{Deserialize, Serialize};
 
-use super::dto::login_dto::LoginDTO;
+use super::dto::{
+    login_dto::LoginDTO,
+    two_factor_dto::{LoginChallengeDTO, TwoFactorLoginDTO},
+};
 
 #[derive(Deserialize)]
 pub struct RefreshTokenDTO {
@@ -16,104 +37,345 @@ pub struct RefreshTokenDTO {
 struct TokenResponse {
     access_token: String,
     refresh_token: String,
+    session_id: i32,
 }
 
-pub async fn login(Json(payload): Json<LoginDTO>) -> impl IntoResponse {
-    let user_repo = UserRepo::new().await;
-    let auth_service = AuthenticationService::new();
-    // TODO: Refactor
-    match user_repo.search_by_username(&payload.username).await {
-        Ok(Some(mut users)) => {
-            if let Some(user) = users.pop() { // TODO: Make sure username matches only one user
-                match auth_service.verify_password(&payload.password, &user.password_hash) {
-                    Ok(true) => {
-                        let tokenizer = token_service::Tokenizer::get_instance().await;
-                        match tokenizer.generate_token(user.user_id) {
-                            Ok(access_token) => {
-                                let refresh_token = tokenizer.generate_refresh_token();
-                                // Store refresh token in database
-                                if let Err(_) = user_repo
-                                    .update_refresh_token(user.user_id, &refresh_token)
-                                    .await
-                                {
-                                    return (
-                                        StatusCode::INTERNAL_SERVER_ERROR,
-                                        "Failed to store refresh token",
-                                    )
-                                        .into_response();
-                                }
-
-                                (
-                                    StatusCode::OK,
-                                    Json(TokenResponse {
-                                        access_token,
-                                        refresh_token,
-                                    }),
-                                )
-                                    .into_response()
-                            }
-                            Err(_) => (
-                                StatusCode::INTERNAL_SERVER_ERROR,
-                                "Failed to generate token",
-                            )
-                                .into_response(),
-                        }
-                    }
-                    Ok(false) => (StatusCode::UNAUTHORIZED, "Invalid credentials").into_response(),
-                    Err(_) => (
-                        StatusCode::INTERNAL_SERVER_ERROR,
-                        "Password verification failed",
-                    )
-                        .into_response(),
-                }
-            } else {
-                (StatusCode::UNAUTHORIZED, "Invalid credentials").into_response()
-            }
+impl From<IssuedTokens> for TokenResponse {
+    fn from(tokens: IssuedTokens) -> Self {
+        TokenResponse {
+            access_token: tokens.access_token,
+            refresh_token: tokens.refresh_token,
+            session_id: tokens.session_id,
         }
-        Ok(None) => (StatusCode::UNAUTHORIZED, "Invalid credentials").into_response(),
-        Err(_) => (StatusCode::INTERNAL_SERVER_ERROR, "Database error").into_response(),
     }
 }
 
-pub async fn refresh(Json(payload): Json<RefreshTokenDTO>) -> impl IntoResponse {
-    let user_repo = UserRepo::new().await;
-
-    match user_repo.get_by_refresh_token(&payload.refresh_token).await {
-        Ok(Some(user)) => {
-            let tokenizer = token_service::Tokenizer::get_instance().await;
-            match tokenizer.generate_token(user.user_id) {
-                Ok(access_token) => (
-                    StatusCode::OK,
-                    Json(TokenResponse {
-                        access_token,
-                        refresh_token: payload.refresh_token,
-                    }),
+/// Answer of a cookie sign-in, the tokens themselves stay out of reach of scripts
+#[derive(Serialize)]
+struct CookieSessionResponse {
+    session_id: i32,
+    /// Sent back in the `X-CSRF-Token` header of state-changing requests
+    csrf_token: String,
+}
+
+/// Hands new tokens to the client, in the body or as cookies
+async fn tokens_response(tokens: IssuedTokens, use_cookies: bool, jar: CookieJar) -> Response {
+    if !use_cookies {
+        return (StatusCode::OK, Json(TokenResponse::from(tokens))).into_response();
+    }
+    let access_seconds = Tokenizer::get_instance().await.expiration_duration;
+    let csrf_token = generate_csrf_token();
+    let session_id = tokens.session_id;
+    (
+        StatusCode::OK,
+        session_cookies(jar, tokens, &csrf_token, access_seconds),
+        Json(CookieSessionResponse {
+            session_id,
+            csrf_token,
+        }),
+    )
+        .into_response()
+}
+
+/// Expires the cookies of a browser session that ended, API clients get none
+fn ended_session(jar: CookieJar, use_cookies: bool) -> CookieJar {
+    if use_cookies {
+        clear_session_cookies(jar)
+    } else {
+        jar
+    }
+}
+
+/// The refresh token of `/refresh` and `/logout`, from the body or from the cookie of a
+/// browser session, `Err` answers the request
+fn refresh_token(
+    payload: Option<Json<RefreshTokenDTO>>,
+    jar: &CookieJar,
+    headers: &HeaderMap,
+) -> Result<(String, bool), Box<Response>> {
+    if let Some(Json(payload)) = payload {
+        return Ok((payload.refresh_token, false));
+    }
+    match jar.get(REFRESH_COOKIE) {
+        Some(cookie) if csrf_matches(jar, headers) => Ok((cookie.value().to_string(), true)),
+        Some(_) => Err(Box::new(csrf_rejected())),
+        None => Err(Box::new(
+            (StatusCode::UNAUTHORIZED, "Missing refresh token").into_response(),
+        )),
+    }
+}
+
+pub async fn login(origin: RequestOrigin, Json(payload): Json<LoginDTO>) -> impl IntoResponse {
+    let auth_service = AuthenticationService::new();
+    let audit = AuditService::new().await;
+    let attempt = AuditEntry::attempt(&payload.username);
+
+    let user_id = match auth_service
+        .login(&payload.username, &payload.password, origin.ip)
+        .await
+    {
+        Ok(LoginOutcome::Success(user_id)) => user_id,
+        Ok(LoginOutcome::InvalidCredentials) => {
+            audit
+                .record(&origin, AuditAction::LoginFailed, attempt)
+                .await;
+            return (StatusCode::UNAUTHORIZED, "Invalid credentials").into_response();
+        }
+        Ok(LoginOutcome::Throttled(throttled)) => {
+            audit
+                .record(
+                    &origin,
+                    AuditAction::LoginFailed,
+                    attempt.details("throttled"),
+                )
+                .await;
+            return throttled_response(throttled);
+        }
+        Ok(LoginOutcome::Disabled) => {
+            audit
+                .record(
+                    &origin,
+                    AuditAction::LoginFailed,
+                    attempt.details("account disabled"),
                 )
-                    .into_response(),
-                Err(_) => (
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    "Failed to generate token",
+                .await;
+            return (StatusCode::FORBIDDEN, "Account disabled").into_response();
+        }
+        Ok(LoginOutcome::TwoFactorRequired(user_id)) => {
+            return match TwoFactorService::new()
+                .await
+                .start_challenge(
+                    user_id,
+                    payload.device_name.as_deref(),
+                    origin.user_agent.as_deref(),
                 )
-                    .into_response(),
+                .await
+            {
+                Ok(challenge) => {
+                    (StatusCode::OK, Json(LoginChallengeDTO::from(challenge))).into_response()
+                }
+                Err(e) => {
+                    eprintln!("Failed to start two-factor challenge: {}", e);
+                    (StatusCode::INTERNAL_SERVER_ERROR, "Failed to start session").into_response()
+                }
+            };
+        }
+        Err(e) => {
+            eprintln!("Failed to verify password: {}", e);
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Password verification failed",
+            )
+                .into_response();
+        }
+    };
+
+    start_session(
+        &origin,
+        user_id,
+        payload.device_name.as_deref(),
+        payload.use_cookies,
+    )
+    .await
+}
+
+async fn start_session(
+    origin: &RequestOrigin,
+    user_id: i32,
+    device_name: Option<&str>,
+    use_cookies: bool,
+) -> Response {
+    let session_service = SessionService::new().await;
+    match session_service
+        .create(user_id, device_name, origin.user_agent.as_deref())
+        .await
+    {
+        Ok(tokens) => {
+            AuditService::new()
+                .await
+                .record(
+                    origin,
+                    AuditAction::Login,
+                    AuditEntry::by(user_id).target("session", tokens.session_id),
+                )
+                .await;
+            tokens_response(tokens, use_cookies, CookieJar::new()).await
+        }
+        Err(e) => {
+            eprintln!("Failed to start session: {}", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, "Failed to start session").into_response()
+        }
+    }
+}
+
+async fn failed_code(origin: &RequestOrigin, user_id: Option<i32>, details: &str) {
+    if let Some(user_id) = user_id {
+        AuditService::new()
+            .await
+            .record(
+                origin,
+                AuditAction::LoginFailed,
+                AuditEntry::by(user_id).details(details),
+            )
+            .await;
+    }
+}
+
+/// Second step of a login for users with two-factor authentication, trades the
+/// challenge token from `/login` and a code for a session
+pub async fn login_two_factor(
+    origin: RequestOrigin,
+    Json(payload): Json<TwoFactorLoginDTO>,
+) -> impl IntoResponse {
+    let service = TwoFactorService::new().await;
+    // Looked up first, a wrong code may drop the challenge
+    let owner = service
+        .challenge_owner(&payload.challenge_token)
+        .await
+        .unwrap_or_else(|e| {
+            eprintln!("Failed to look up two-factor challenge: {}", e);
+            None
+        });
+    match service
+        .complete_challenge(&payload.challenge_token, &payload.code, origin.ip)
+        .await
+    {
+        Ok(ChallengeOutcome::Completed {
+            user_id,
+            device_name,
+            user_agent,
+        }) => {
+            // The session is named after the device that gave the password
+            let session_origin = RequestOrigin {
+                ip: origin.ip,
+                user_agent,
+            };
+            start_session(
+                &session_origin,
+                user_id,
+                device_name.as_deref(),
+                payload.use_cookies,
+            )
+            .await
+        }
+        Ok(ChallengeOutcome::InvalidChallenge) => (
+            StatusCode::UNAUTHORIZED,
+            "Invalid or expired challenge, log in again",
+        )
+            .into_response(),
+        Ok(ChallengeOutcome::InvalidCode) => {
+            failed_code(&origin, owner, "wrong two-factor code").await;
+            (StatusCode::UNAUTHORIZED, "Invalid code").into_response()
+        }
+        Ok(ChallengeOutcome::Throttled(throttled)) => {
+            failed_code(&origin, owner, "throttled").await;
+            throttled_response(throttled)
+        }
+        Err(e) => {
+            eprintln!("Failed to complete two-factor challenge: {}", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, "Database error").into_response()
+        }
+    }
+}
+
+/// Rotates a refresh token sent in the body, or the one in the cookies of a browser
+/// session together with the CSRF header
+pub async fn refresh(
+    origin: RequestOrigin,
+    jar: CookieJar,
+    headers: HeaderMap,
+    payload: Option<Json<RefreshTokenDTO>>,
+) -> impl IntoResponse {
+    let (token, use_cookies) = match refresh_token(payload, &jar, &headers) {
+        Ok(token) => token,
+        Err(response) => return *response,
+    };
+    let session_service = SessionService::new().await;
+    let audit = AuditService::new().await;
+
+    match session_service
+        .refresh(&token, origin.user_agent.as_deref())
+        .await
+    {
+        Ok(RefreshOutcome::Rotated(tokens)) => {
+            if let Ok(Some(user_id)) = session_service.token_owner(&tokens.refresh_token).await {
+                audit
+                    .record(
+                        &origin,
+                        AuditAction::TokenRefreshed,
+                        AuditEntry::by(user_id).target("session", tokens.session_id),
+                    )
+                    .await;
+            }
+            tokens_response(tokens, use_cookies, jar).await
+        }
+        Ok(RefreshOutcome::Invalid) => (
+            StatusCode::UNAUTHORIZED,
+            ended_session(jar, use_cookies),
+            "Invalid refresh token",
+        )
+            .into_response(),
+        Ok(RefreshOutcome::Reused) => {
+            if let Ok(Some(user_id)) = session_service.token_owner(&token).await {
+                audit
+                    .record(
+                        &origin,
+                        AuditAction::RefreshTokenReused,
+                        AuditEntry::by(user_id),
+                    )
+                    .await;
             }
+            (
+                StatusCode::UNAUTHORIZED,
+                ended_session(jar, use_cookies),
+                "Refresh token was already used, the session has been revoked",
+            )
+                .into_response()
+        }
+        Err(e) => {
+            eprintln!("Failed to refresh session: {}", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, "Database error").into_response()
         }
-        Ok(None) => (StatusCode::UNAUTHORIZED, "Invalid refresh token").into_response(),
-        Err(_) => (StatusCode::INTERNAL_SERVER_ERROR, "Database error").into_response(),
     }
 }
 
-pub async fn logout(Json(payload): Json<RefreshTokenDTO>) -> impl IntoResponse {
-    let user_repo = UserRepo::new().await;
+/// Ends the session of a refresh token from the body or the cookies, which are cleared
+pub async fn logout(
+    origin: RequestOrigin,
+    jar: CookieJar,
+    headers: HeaderMap,
+    payload: Option<Json<RefreshTokenDTO>>,
+) -> impl IntoResponse {
+    let (token, use_cookies) = match refresh_token(payload, &jar, &headers) {
+        Ok(token) => token,
+        Err(response) => return *response,
+    };
+    let session_service = SessionService::new().await;
 
-    // Find user by refresh token and clear it
-    match user_repo.get_by_refresh_token(&payload.refresh_token).await {
-        Ok(Some(user)) => {
-            if let Err(_) = user_repo.update_refresh_token(user.user_id, "").await {
-                return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to logout").into_response();
+    match session_service.revoke_token(&token).await {
+        Ok(true) => {
+            if let Ok(Some(user_id)) = session_service.token_owner(&token).await {
+                AuditService::new()
+                    .await
+                    .record(&origin, AuditAction::Logout, AuditEntry::by(user_id))
+                    .await;
             }
-            (StatusCode::OK, "Logged out successfully").into_response()
+            (
+                StatusCode::OK,
+                ended_session(jar, use_cookies),
+                "Logged out successfully",
+            )
+                .into_response()
+        }
+        Ok(false) => (
+            StatusCode::UNAUTHORIZED,
+            ended_session(jar, use_cookies),
+            "Invalid refresh token",
+        )
+            .into_response(),
+        Err(e) => {
+            eprintln!("Failed to logout: {}", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, "Failed to logout").into_response()
         }
-        Ok(None) => (StatusCode::UNAUTHORIZED, "Invalid refresh token").into_response(),
-        Err(_) => (StatusCode::INTERNAL_SERVER_ERROR, "Database error").into_response(),
     }
 }
@@ -0,0 +1,93 @@
+use std::env;
+
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use axum::http::HeaderMap;
+use axum_extra::extract::cookie::{Cookie, CookieJar, SameSite};
+use dotenvy::dotenv;
+use once_cell::sync::Lazy;
+use time::Duration;
+
+use crate::services::session_service::{refresh_token_days, IssuedTokens};
+
+/// Holds the access JWT of browsers that signed in with cookies
+pub const ACCESS_COOKIE: &str = "stellaron_access";
+pub const REFRESH_COOKIE: &str = "stellaron_refresh";
+/// Readable by the page, which echoes it in [`CSRF_HEADER`] on state-changing requests
+pub const CSRF_COOKIE: &str = "stellaron_csrf";
+pub const CSRF_HEADER: &str = "x-csrf-token";
+
+/// Cookies are only sent over HTTPS unless `AUTH_COOKIE_SECURE` is `false`, for servers
+/// reached over plain HTTP on a home network
+static SECURE: Lazy<bool> = Lazy::new(|| {
+    dotenv().ok();
+
+    env::var("AUTH_COOKIE_SECURE").as_deref() != Ok("false")
+});
+
+pub fn generate_csrf_token() -> String {
+    let mut bytes = [0u8; 32];
+    OsRng.fill_bytes(&mut bytes);
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn cookie(
+    name: &'static str,
+    value: String,
+    http_only: bool,
+    max_age: Duration,
+) -> Cookie<'static> {
+    Cookie::build((name, value))
+        .path("/")
+        .http_only(http_only)
+        .secure(*SECURE)
+        .same_site(SameSite::Strict)
+        .max_age(max_age)
+        .build()
+}
+
+/// Adds the cookies of a new or refreshed session. The access cookie lives as long as the
+/// JWT in it, the others as long as the refresh token.
+pub fn session_cookies(
+    jar: CookieJar,
+    tokens: IssuedTokens,
+    csrf_token: &str,
+    access_seconds: i64,
+) -> CookieJar {
+    let session = Duration::days(refresh_token_days());
+    jar.add(cookie(
+        ACCESS_COOKIE,
+        tokens.access_token,
+        true,
+        Duration::seconds(access_seconds),
+    ))
+    .add(cookie(REFRESH_COOKIE, tokens.refresh_token, true, session))
+    .add(cookie(CSRF_COOKIE, csrf_token.to_string(), false, session))
+}
+
+/// Expires the session cookies in the browser
+pub fn clear_session_cookies(jar: CookieJar) -> CookieJar {
+    [ACCESS_COOKIE, REFRESH_COOKIE, CSRF_COOKIE]
+        .into_iter()
+        .fold(jar, |jar, name| {
+            jar.remove(Cookie::build(name).path("/").build())
+        })
+}
+
+/// Double-submit check: the header has to repeat the CSRF cookie. Other sites can make a
+/// browser send the cookies but can't read them to set the header.
+pub fn csrf_matches(jar: &CookieJar, headers: &HeaderMap) -> bool {
+    let Some(cookie) = jar.get(CSRF_COOKIE).map(|c| c.value()) else {
+        return false;
+    };
+    let Some(header) = headers.get(CSRF_HEADER).and_then(|v| v.to_str().ok()) else {
+        return false;
+    };
+    // Compared in constant time so the token can't be guessed byte by byte
+    !cookie.is_empty()
+        && cookie.len() == header.len()
+        && cookie
+            .bytes()
+            .zip(header.bytes())
+            .fold(0u8, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
@@ -1,17 +1,284 @@
+use std::{
+    convert::Infallible,
+    net::{IpAddr, SocketAddr},
+};
+
 use axum::{
-    extract::FromRequestParts,
-    http::{request::Parts, StatusCode},
+    extract::{ConnectInfo, FromRequestParts},
+    http::{header, request::Parts, StatusCode},
     response::{IntoResponse, Response},
     RequestPartsExt,
 };
 use axum_extra::{
-    headers::{authorization::Bearer, Authorization},
+    extract::CookieJar,
+    headers::{
+        authorization::{Basic, Bearer},
+        Authorization,
+    },
     TypedHeader,
 };
 use serde::{Deserialize, Serialize};
 
-use crate::services::token_service;
+use crate::{
+    controllers::auth_cookies::{csrf_matches, ACCESS_COOKIE},
+    data::{
+        models::{
+            api_keys::{ApiKeys, ApiScope},
+            users::Role,
+        },
+        repos::{implementors::user_repo::UserRepo, traits::repository::Repository},
+    },
+    services::{
+        api_key_service::{is_api_key, ApiKeyService},
+        audit_service::RequestOrigin,
+        authentication_service::{AuthenticationService, LoginOutcome},
+        proxy_auth_service::{ProxyAuthService, ProxyIdentity},
+        throttle_service::Throttled,
+        token_service,
+    },
+};
+
+/// Challenge sent to clients that can only authenticate with a username and password
+const BASIC_CHALLENGE: &str = "Basic realm=\"Stellaron\", charset=\"UTF-8\"";
+
+/// Address of the connected client, if the server was started with connection info
+pub fn client_ip(parts: &Parts) -> Option<IpAddr> {
+    parts
+        .extensions
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| addr.ip())
+}
+
+/// Client address for handlers, never rejects a request
+#[derive(Debug, Clone, Copy)]
+pub struct ClientIp(pub Option<IpAddr>);
+
+impl<S> FromRequestParts<S> for ClientIp
+where
+    S: Send + Sync,
+{
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        Ok(ClientIp(client_ip(parts)))
+    }
+}
+
+/// Address and user agent of the client for the audit log, never rejects a request
+impl<S> FromRequestParts<S> for RequestOrigin
+where
+    S: Send + Sync,
+{
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        Ok(request_origin(parts))
+    }
+}
+
+fn request_origin(parts: &Parts) -> RequestOrigin {
+    RequestOrigin {
+        ip: client_ip(parts),
+        user_agent: parts
+            .headers
+            .get(header::USER_AGENT)
+            .and_then(|v| v.to_str().ok())
+            .filter(|v| !v.is_empty())
+            .map(str::to_string),
+    }
+}
+
+/// How a request authenticated
+enum Credential {
+    /// Signed in with a password or JWT, everything the user's role allows is open
+    Login(i32),
+    /// An API key, limited to its scopes
+    Key(ApiKeys),
+}
+
+impl Credential {
+    fn has_scope(&self, scope: ApiScope) -> bool {
+        match self {
+            Credential::Login(_) => true,
+            Credential::Key(key) => key.has_scope(scope),
+        }
+    }
+
+    /// The user behind the credential, if it may use routes of `scope`
+    fn require_scope(&self, scope: ApiScope) -> Result<i32, AuthError> {
+        match self {
+            Credential::Login(id) => Ok(*id),
+            Credential::Key(key) if key.has_scope(scope) => Ok(key.user_id),
+            Credential::Key(_) => Err(AuthError::Forbidden),
+        }
+    }
+}
+
+async fn api_key(key: &str) -> Result<Option<ApiKeys>, AuthError> {
+    ApiKeyService::new()
+        .await
+        .authenticate(key)
+        .await
+        .map_err(|e| {
+            eprintln!("Failed to verify API key: {:?}", e);
+            AuthError::Internal
+        })
+}
+
+async fn jwt(token: &str) -> Result<i32, AuthError> {
+    let tokenizer = token_service::Tokenizer::get_instance().await;
+    match tokenizer.verify(token).await {
+        Ok(Some(claims)) => Ok(claims.sub),
+        Ok(None) => Err(AuthError::InvalidToken),
+        Err(e) => {
+            eprintln!("Failed to check token revocation: {}", e);
+            Err(AuthError::Internal)
+        }
+    }
+}
+
+/// A user signed in by a trusted authenticating proxy, when proxy authentication is on
+async fn proxy_credential(parts: &Parts) -> Result<Option<Credential>, AuthError> {
+    let Some(service) = ProxyAuthService::from_env() else {
+        return Ok(None);
+    };
+    match service
+        .authenticate(&request_origin(parts), &parts.headers)
+        .await
+    {
+        Ok(ProxyIdentity::Absent) => Ok(None),
+        Ok(ProxyIdentity::User(id)) => Ok(Some(Credential::Login(id))),
+        Ok(ProxyIdentity::Rejected(message)) => Err(AuthError::ProxyUserRejected(message)),
+        Err(e) => {
+            eprintln!("Failed to authenticate proxy user: {}", e);
+            Err(AuthError::Internal)
+        }
+    }
+}
+
+/// The JWT of a browser session kept in cookies. Browsers send cookies along with
+/// requests other sites make, so those that change something need the CSRF header too.
+async fn cookie_credential(parts: &Parts) -> Result<Option<Credential>, AuthError> {
+    let jar = CookieJar::from_headers(&parts.headers);
+    let Some(access) = jar.get(ACCESS_COOKIE) else {
+        return Ok(None);
+    };
+    if !parts.method.is_safe() && !csrf_matches(&jar, &parts.headers) {
+        return Err(AuthError::CsrfRejected);
+    }
+    Ok(Some(Credential::Login(jwt(access.value()).await?)))
+}
+
+/// A proxy user, a Bearer JWT, an API key or a cookie session
+async fn bearer_credential(parts: &mut Parts) -> Result<Credential, AuthError> {
+    if let Some(credential) = proxy_credential(parts).await? {
+        return Ok(credential);
+    }
+    // An explicit Authorization header wins over cookies the browser adds on its own
+    if !parts.headers.contains_key(header::AUTHORIZATION) {
+        if let Some(credential) = cookie_credential(parts).await? {
+            return Ok(credential);
+        }
+    }
+    let TypedHeader(Authorization(bearer)) = parts
+        .extract::<TypedHeader<Authorization<Bearer>>>()
+        .await
+        .map_err(|_| AuthError::InvalidToken)?;
+
+    if is_api_key(bearer.token()) {
+        return match api_key(bearer.token()).await? {
+            Some(key) => Ok(Credential::Key(key)),
+            None => Err(AuthError::InvalidToken),
+        };
+    }
+    Ok(Credential::Login(jwt(bearer.token()).await?))
+}
+
+/// Basic credentials, where the password may be an API key of the named user, or
+/// anything [`bearer_credential`] accepts
+async fn device_credential(parts: &mut Parts) -> Result<Credential, AuthError> {
+    if let Some(credential) = proxy_credential(parts).await? {
+        return Ok(credential);
+    }
+    if let Ok(TypedHeader(Authorization(basic))) =
+        parts.extract::<TypedHeader<Authorization<Basic>>>().await
+    {
+        if is_api_key(basic.password()) {
+            let Some(key) = api_key(basic.password()).await? else {
+                return Err(AuthError::InvalidCredentials);
+            };
+            return match UserRepo::new().await.get_by_id(key.user_id).await {
+                Ok(Some(user)) if user.username == basic.username() => Ok(Credential::Key(key)),
+                Ok(_) => Err(AuthError::InvalidCredentials),
+                Err(e) => {
+                    eprintln!("Failed to look up API key owner: {}", e);
+                    Err(AuthError::Internal)
+                }
+            };
+        }
+
+        let outcome = AuthenticationService::new()
+            .verify_credentials(basic.username(), basic.password(), client_ip(parts))
+            .await
+            .map_err(|e| {
+                eprintln!("Failed to verify credentials: {:?}", e);
+                AuthError::Internal
+            })?;
+        return match outcome {
+            LoginOutcome::Success(id) => Ok(Credential::Login(id)),
+            LoginOutcome::InvalidCredentials => Err(AuthError::InvalidCredentials),
+            LoginOutcome::Throttled(throttled) => Err(AuthError::Throttled(throttled)),
+            LoginOutcome::Disabled => Err(AuthError::AccountDisabled),
+            LoginOutcome::TwoFactorRequired(_) => Err(AuthError::TwoFactorRequired),
+        };
+    }
+
+    bearer_credential(parts).await.map_err(|e| match e {
+        AuthError::InvalidToken => AuthError::InvalidCredentials,
+        e => e,
+    })
+}
+
+/// Checks that a signed in user still exists, isn't disabled and has at least the
+/// `required` role. Tokens issued before an account was disabled stop working here.
+async fn require_role(id: i32, required: Role) -> Result<(), AuthError> {
+    match UserRepo::new().await.get_by_id(id).await {
+        Ok(Some(user)) if user.is_disabled() => Err(AuthError::AccountDisabled),
+        Ok(Some(user)) if user.role().allows(required) => Ok(()),
+        Ok(Some(_)) => Err(AuthError::Forbidden),
+        Ok(None) => Err(AuthError::InvalidToken),
+        Err(e) => {
+            eprintln!("Failed to look up user role: {}", e);
+            Err(AuthError::Internal)
+        }
+    }
+}
+
+/// A user signed in with a JWT. API keys are refused, so a leaked key can't be used
+/// to manage the account, its sessions or other keys.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LoginUser {
+    pub id: i32,
+}
+
+impl<S> FromRequestParts<S> for LoginUser
+where
+    S: Send + Sync,
+{
+    type Rejection = AuthError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        match bearer_credential(parts).await? {
+            Credential::Login(id) => {
+                require_role(id, Role::Guest).await?;
+                Ok(LoginUser { id })
+            }
+            Credential::Key(_) => Err(AuthError::Forbidden),
+        }
+    }
+}
 
+/// A signed in user browsing the catalog, API keys need the `catalog` scope
 #[derive(Debug, Serialize, Deserialize)]
 pub struct AuthUser {
     pub id: i32,
@@ -24,29 +291,199 @@ where
     type Rejection = AuthError;
 
     async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
-        let TypedHeader(Authorization(bearer)) = parts
-            .extract::<TypedHeader<Authorization<Bearer>>>()
-            .await
-            .map_err(|_| AuthError::InvalidToken)?;
+        let id = bearer_credential(parts)
+            .await?
+            .require_scope(ApiScope::Catalog)?;
+        require_role(id, Role::Guest).await?;
+        Ok(AuthUser { id })
+    }
+}
+
+/// A signed in user reading their own progress, bookmarks or annotations, API keys
+/// need the `progress` scope
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SyncUser {
+    pub id: i32,
+}
+
+impl<S> FromRequestParts<S> for SyncUser
+where
+    S: Send + Sync,
+{
+    type Rejection = AuthError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let id = bearer_credential(parts)
+            .await?
+            .require_scope(ApiScope::Progress)?;
+        require_role(id, Role::Guest).await?;
+        Ok(SyncUser { id })
+    }
+}
+
+/// A signed in user who may change their own data, guests are rejected and API keys
+/// need the `progress` scope
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MemberUser {
+    pub id: i32,
+}
+
+impl<S> FromRequestParts<S> for MemberUser
+where
+    S: Send + Sync,
+{
+    type Rejection = AuthError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let id = bearer_credential(parts)
+            .await?
+            .require_scope(ApiScope::Progress)?;
+        require_role(id, Role::User).await?;
+        Ok(MemberUser { id })
+    }
+}
+
+/// A signed in administrator, API keys need the `admin` scope
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AdminUser {
+    pub id: i32,
+}
 
-        let tokenizer = token_service::Tokenizer::get_instance().await;
-        let claims = tokenizer
-            .decode_token(bearer.token())
-            .map_err(|_| AuthError::InvalidToken)?;
+impl<S> FromRequestParts<S> for AdminUser
+where
+    S: Send + Sync,
+{
+    type Rejection = AuthError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let id = bearer_credential(parts)
+            .await?
+            .require_scope(ApiScope::Admin)?;
+        require_role(id, Role::Admin).await?;
+        Ok(AdminUser { id })
+    }
+}
+
+/// User of routes e-readers and OPDS clients talk to directly. Most of them can't obtain
+/// a JWT, so HTTP Basic credentials are accepted next to a Bearer token, and an API key
+/// can stand in for the password. API keys need the `catalog` scope.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DeviceUser {
+    pub id: i32,
+}
+
+impl<S> FromRequestParts<S> for DeviceUser
+where
+    S: Send + Sync,
+{
+    type Rejection = AuthError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let id = device_credential(parts)
+            .await?
+            .require_scope(ApiScope::Catalog)?;
+        require_role(id, Role::Guest).await?;
+        Ok(DeviceUser { id })
+    }
+}
+
+/// Like [`DeviceUser`], for routes that hand out book files or pages. API keys need
+/// the `download` scope.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DownloadUser {
+    pub id: i32,
+    /// Whether reading may move the user's position, API keys need the `progress` scope
+    pub tracks_progress: bool,
+}
+
+impl<S> FromRequestParts<S> for DownloadUser
+where
+    S: Send + Sync,
+{
+    type Rejection = AuthError;
 
-        Ok(AuthUser { id: claims.sub })
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let credential = device_credential(parts).await?;
+        let id = credential.require_scope(ApiScope::Download)?;
+        require_role(id, Role::Guest).await?;
+        Ok(DownloadUser {
+            id,
+            tracks_progress: credential.has_scope(ApiScope::Progress),
+        })
     }
 }
 
 pub enum AuthError {
     InvalidToken,
+    /// Missing or wrong credentials on a route that accepts Basic authentication
+    InvalidCredentials,
+    /// Signed in, but the user's role or the API key's scopes don't allow the request
+    Forbidden,
+    /// Too many failed sign-ins for the account or address
+    Throttled(Throttled),
+    /// Valid credentials of an account an admin has disabled
+    AccountDisabled,
+    /// A password over Basic authentication for an account with two-factor authentication
+    TwoFactorRequired,
+    /// A trusted proxy named a user that has no account and can't get one
+    ProxyUserRejected(&'static str),
+    /// A state-changing request authenticated by cookies without the CSRF header
+    CsrfRejected,
+    Internal,
 }
 
 impl IntoResponse for AuthError {
     fn into_response(self) -> Response {
-        let (status, error_message) = match self {
-            AuthError::InvalidToken => (StatusCode::UNAUTHORIZED, "Invalid or missing token"),
-        };
-        (status, error_message).into_response()
+        match self {
+            AuthError::InvalidToken => {
+                (StatusCode::UNAUTHORIZED, "Invalid or missing token").into_response()
+            }
+            AuthError::InvalidCredentials => (
+                StatusCode::UNAUTHORIZED,
+                [(header::WWW_AUTHENTICATE, BASIC_CHALLENGE)],
+                "Invalid or missing credentials",
+            )
+                .into_response(),
+            AuthError::Forbidden => (StatusCode::FORBIDDEN, "Forbidden").into_response(),
+            AuthError::Throttled(throttled) => throttled_response(throttled),
+            AuthError::AccountDisabled => {
+                (StatusCode::FORBIDDEN, "Account disabled").into_response()
+            }
+            AuthError::TwoFactorRequired => (
+                StatusCode::UNAUTHORIZED,
+                [(header::WWW_AUTHENTICATE, BASIC_CHALLENGE)],
+                "Two-factor authentication is enabled, use an API key as the password",
+            )
+                .into_response(),
+            AuthError::ProxyUserRejected(message) => {
+                (StatusCode::FORBIDDEN, message).into_response()
+            }
+            AuthError::CsrfRejected => csrf_rejected(),
+            AuthError::Internal => {
+                (StatusCode::INTERNAL_SERVER_ERROR, "Internal server error").into_response()
+            }
+        }
     }
 }
+
+/// 403 for cookie-authenticated requests without a matching CSRF token, shared with the
+/// refresh and logout routes
+pub fn csrf_rejected() -> Response {
+    (StatusCode::FORBIDDEN, "Missing or invalid CSRF token").into_response()
+}
+
+/// 429 with a `Retry-After` header, shared with the login route
+pub fn throttled_response(throttled: Throttled) -> Response {
+    let message = match throttled {
+        Throttled::Wait(_) => "Too many failed attempts, try again later",
+        Throttled::Locked(_) => "Account temporarily locked after too many failed attempts",
+    };
+    // Round up so clients don't retry a moment too early
+    let seconds = throttled.retry_after().as_secs() + 1;
+    (
+        StatusCode::TOO_MANY_REQUESTS,
+        [(header::RETRY_AFTER, seconds.to_string())],
+        message,
+    )
+        .into_response()
+}
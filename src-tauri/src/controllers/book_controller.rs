@@ -1,7 +1,14 @@
 use crate::{
-    controllers::auth_middleware::AuthUser,
-    data::repos::{implementors::book_repo::BookRepo, traits::repository::Repository},
+    controllers::auth_middleware::{AdminUser, DownloadUser},
+    data::{
+        models::audit_events::AuditAction,
+        repos::{implementors::book_repo::BookRepo, traits::repository::Repository},
+    },
     handlers::epub_handler,
+    services::{
+        audit_service::{AuditEntry, AuditService, RequestOrigin},
+        library_access_service::LibraryAccessService,
+    },
 };
 use axum::{
     extract::Path,
@@ -9,10 +16,11 @@ use axum::{
     response::{IntoResponse, Json},
 };
 
-pub async fn get_book_content(_user: AuthUser, Path(book_id): Path<i32>) -> impl IntoResponse {
-    let book_repo = BookRepo::new().await;
+pub async fn get_book_content(user: DownloadUser, Path(book_id): Path<i32>) -> impl IntoResponse {
+    let access = LibraryAccessService::new().await;
 
-    match book_repo.get_by_id(book_id).await {
+    // Books in libraries the user can't see are reported as missing
+    match access.book(user.id, book_id).await {
         Ok(Some(book)) => {
             if let Some(file_path) = book.file_path {
                 match epub_handler::get_epub_content(&file_path).await {
@@ -37,3 +45,41 @@ pub async fn get_book_content(_user: AuthUser, Path(book_id): Path<i32>) -> impl
         }
     }
 }
+
+/// Removes a book from the catalog, admins only (DELETE /book/{id}). The file on disk is
+/// kept, a later scan of its library adds it again.
+pub async fn delete_book(
+    admin: AdminUser,
+    origin: RequestOrigin,
+    Path(book_id): Path<i32>,
+) -> impl IntoResponse {
+    let repo = BookRepo::new().await;
+    let book = match repo.get_by_id(book_id).await {
+        Ok(Some(book)) => book,
+        Ok(None) => return (StatusCode::NOT_FOUND, "Book not found").into_response(),
+        Err(e) => {
+            eprintln!("Failed to get book: {}", e);
+            return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to get book").into_response();
+        }
+    };
+
+    match repo.delete(book_id).await {
+        Ok(()) => {
+            AuditService::new()
+                .await
+                .record(
+                    &origin,
+                    AuditAction::BookDeleted,
+                    AuditEntry::by(admin.id)
+                        .target("book", book_id)
+                        .details(format!("title: {}", book.title)),
+                )
+                .await;
+            StatusCode::NO_CONTENT.into_response()
+        }
+        Err(e) => {
+            eprintln!("Failed to delete book: {}", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, "Failed to delete book").into_response()
+        }
+    }
+}
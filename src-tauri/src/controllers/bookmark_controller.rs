@@ -1,49 +1,35 @@
 use crate::{
     data::models::bookmarks::NewBookmark, data::repos::implementors::bookmark_repo::BookmarkRepo,
-    data::repos::traits::repository::Repository, services::token_service::Tokenizer,
+    data::repos::traits::repository::Repository,
 };
 use axum::{
     extract::{Path, Query},
-    http::{HeaderMap, StatusCode},
+    http::StatusCode,
     response::IntoResponse,
     Json,
 };
 use serde::Deserialize;
 
+use super::auth_middleware::{MemberUser, SyncUser};
 use super::dto::bookmark_dto::{BookmarkDTO, NewBookmarkDTO};
+use super::library_controller::require_book;
 
 #[derive(Deserialize)]
 pub struct BookQueryParams {
     book_id: i32,
 }
 
-async fn get_user_from_token(headers: &HeaderMap) -> Result<i32, StatusCode> {
-    let token = headers
-        .get("Authorization")
-        .and_then(|v| v.to_str().ok())
-        .and_then(|v| v.strip_prefix("Bearer "))
-        .ok_or(StatusCode::UNAUTHORIZED)?;
-
-    let tokenizer = Tokenizer::get_instance().await;
-    let claims = tokenizer
-        .decode_token(token)
-        .map_err(|_| StatusCode::UNAUTHORIZED)?;
-
-    Ok(claims.sub)
-}
-
 pub async fn create_bookmark(
-    headers: HeaderMap,
+    user: MemberUser,
     Json(payload): Json<NewBookmarkDTO>,
 ) -> impl IntoResponse {
-    let user_id = match get_user_from_token(&headers).await {
-        Ok(id) => id,
-        Err(status) => return (status, "Unauthorized").into_response(),
-    };
+    if let Err(response) = require_book(user.id, payload.book_id).await {
+        return response;
+    }
 
     let repo = BookmarkRepo::new().await;
     let new_bookmark = NewBookmark {
-        user_id,
+        user_id: user.id,
         book_id: payload.book_id,
         chapter_title: payload.chapter_title.as_deref(),
         page_number: payload.page_number,
@@ -57,16 +43,11 @@ pub async fn create_bookmark(
 }
 
 pub async fn get_bookmarks(
-    headers: HeaderMap,
+    user: SyncUser,
     Query(params): Query<BookQueryParams>,
 ) -> impl IntoResponse {
-    let user_id = match get_user_from_token(&headers).await {
-        Ok(id) => id,
-        Err(status) => return (status, Json(Vec::<BookmarkDTO>::new())).into_response(),
-    };
-
     let repo = BookmarkRepo::new().await;
-    match repo.get_by_user_and_book(user_id, params.book_id).await {
+    match repo.get_by_user_and_book(user.id, params.book_id).await {
         Ok(Some(bookmarks)) => {
             let dtos: Vec<BookmarkDTO> = bookmarks
                 .into_iter()
@@ -91,16 +72,15 @@ pub async fn get_bookmarks(
     }
 }
 
-pub async fn delete_bookmark(
-    headers: HeaderMap,
-    Path(bookmark_id): Path<i32>,
-) -> impl IntoResponse {
-    let _user_id = match get_user_from_token(&headers).await {
-        Ok(id) => id,
-        Err(status) => return (status, "Unauthorized").into_response(),
-    };
-
+pub async fn delete_bookmark(user: MemberUser, Path(bookmark_id): Path<i32>) -> impl IntoResponse {
     let repo = BookmarkRepo::new().await;
+    // Bookmarks of other users are reported as missing
+    match repo.get_by_id(bookmark_id).await {
+        Ok(Some(item)) if item.user_id == user.id => (),
+        Ok(_) => return (StatusCode::NOT_FOUND, "Bookmark not found").into_response(),
+        Err(_) => return (StatusCode::INTERNAL_SERVER_ERROR, "Database error").into_response(),
+    }
+
     match repo.delete(bookmark_id).await {
         Ok(_) => (StatusCode::OK, "Bookmark deleted").into_response(),
         Err(_) => (StatusCode::INTERNAL_SERVER_ERROR, "Database error").into_response(),
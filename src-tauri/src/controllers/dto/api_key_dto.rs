@@ -0,0 +1,49 @@
+use serde::{Deserialize, Serialize};
+
+use crate::data::models::api_keys::ApiKeys;
+
+#[derive(Deserialize)]
+pub struct NewApiKeyDTO {
+    pub name: String,
+    /// Any of `catalog`, `download`, `progress` and `admin`
+    pub scopes: Vec<String>,
+    /// Never expires when left out
+    pub expires_in_days: Option<i64>,
+}
+
+#[derive(Serialize)]
+pub struct ApiKeyDTO {
+    pub api_key_id: i32,
+    pub name: String,
+    pub prefix: String,
+    pub scopes: Vec<String>,
+    pub created_at: Option<String>,
+    pub last_used_at: Option<String>,
+    pub expires_at: Option<String>,
+}
+
+impl From<ApiKeys> for ApiKeyDTO {
+    fn from(api_key: ApiKeys) -> Self {
+        ApiKeyDTO {
+            api_key_id: api_key.api_key_id,
+            scopes: api_key
+                .scopes()
+                .iter()
+                .map(|s| s.as_str().to_string())
+                .collect(),
+            name: api_key.name,
+            prefix: api_key.prefix,
+            created_at: api_key.created_at,
+            last_used_at: api_key.last_used_at,
+            expires_at: api_key.expires_at,
+        }
+    }
+}
+
+/// Returned once when a key is created, the key can't be retrieved afterwards
+#[derive(Serialize)]
+pub struct CreatedApiKeyDTO {
+    pub key: String,
+    #[serde(flatten)]
+    pub api_key: ApiKeyDTO,
+}
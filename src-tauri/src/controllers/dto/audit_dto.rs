@@ -0,0 +1,79 @@
+use serde::{Deserialize, Serialize};
+
+use crate::data::models::audit_events::AuditEvents;
+
+/// Events shown per page when the query doesn't say
+pub const DEFAULT_PER_PAGE: i64 = 50;
+pub const MAX_PER_PAGE: i64 = 500;
+
+/// Filters of `GET /audit`, all optional
+#[derive(Deserialize, Default)]
+pub struct AuditQuery {
+    pub user_id: Option<i32>,
+    /// e.g. `login_failed` or `role_changed`
+    pub action: Option<String>,
+    pub target_type: Option<String>,
+    pub target_id: Option<i32>,
+    pub ip: Option<String>,
+    /// RFC 3339 or `YYYY-MM-DD`, inclusive
+    pub since: Option<String>,
+    /// RFC 3339 or `YYYY-MM-DD`, exclusive
+    pub until: Option<String>,
+    /// Starting at 1
+    pub page: Option<i64>,
+    pub per_page: Option<i64>,
+}
+
+impl AuditQuery {
+    pub fn page(&self) -> i64 {
+        self.page.unwrap_or(1).max(1)
+    }
+
+    pub fn per_page(&self) -> i64 {
+        self.per_page
+            .unwrap_or(DEFAULT_PER_PAGE)
+            .clamp(1, MAX_PER_PAGE)
+    }
+}
+
+#[derive(Serialize)]
+pub struct AuditEventDTO {
+    pub event_id: i32,
+    pub occurred_at: String,
+    pub action: String,
+    pub user_id: Option<i32>,
+    pub username: Option<String>,
+    pub target_type: Option<String>,
+    pub target_id: Option<i32>,
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+    pub details: Option<String>,
+}
+
+impl From<AuditEvents> for AuditEventDTO {
+    fn from(event: AuditEvents) -> Self {
+        AuditEventDTO {
+            event_id: event.event_id,
+            occurred_at: event.occurred_at,
+            action: event.action,
+            user_id: event.user_id,
+            username: event.username,
+            target_type: event.target_type,
+            target_id: event.target_id,
+            ip: event.ip,
+            user_agent: event.user_agent,
+            details: event.details,
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct AuditPageDTO {
+    pub events: Vec<AuditEventDTO>,
+    /// All events matching the filters
+    pub total: i64,
+    pub page: i64,
+    pub per_page: i64,
+    /// `None` if events are kept forever
+    pub retention_days: Option<i64>,
+}
@@ -0,0 +1,94 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    data::models::metadata_history::MetadataHistory, services::metadata_service::MetadataField,
+};
+
+#[derive(Serialize)]
+pub struct MetadataHistoryDTO {
+    pub history_id: i32,
+    pub book_id: i32,
+    pub field: String,
+    pub old_value: Option<String>,
+    pub new_value: Option<String>,
+    pub source: String,
+    pub user_id: Option<i32>,
+    pub batch_id: String,
+    pub created_at: Option<String>,
+    pub reverted_at: Option<String>,
+}
+
+impl From<MetadataHistory> for MetadataHistoryDTO {
+    fn from(entry: MetadataHistory) -> Self {
+        MetadataHistoryDTO {
+            history_id: entry.history_id,
+            book_id: entry.book_id,
+            field: entry.field,
+            old_value: entry.old_value,
+            new_value: entry.new_value,
+            source: entry.source,
+            user_id: entry.user_id,
+            batch_id: entry.batch_id,
+            created_at: entry.created_at,
+            reverted_at: entry.reverted_at,
+        }
+    }
+}
+
+/// Fields to change by hand, omitted fields are left untouched.
+/// An empty string or list clears the field.
+#[derive(Deserialize, Default)]
+pub struct EditMetadataDTO {
+    pub title: Option<String>,
+    pub authors: Option<Vec<String>>,
+    pub publisher: Option<String>,
+    pub published_date: Option<String>,
+    pub isbn: Option<String>,
+    pub description: Option<String>,
+    pub language: Option<String>,
+    pub series: Option<String>,
+    pub series_index: Option<f32>,
+    pub rating: Option<i32>,
+    pub tags: Option<Vec<String>>,
+}
+
+impl EditMetadataDTO {
+    /// Converts the edit to the values stored in the history
+    pub fn into_values(self) -> Vec<(MetadataField, Option<String>)> {
+        fn text(value: String) -> Option<String> {
+            let value = value.trim();
+            (!value.is_empty()).then(|| value.to_string())
+        }
+        fn list(values: Vec<String>) -> Option<String> {
+            let values: Vec<String> = values.into_iter().filter_map(text).collect();
+            (!values.is_empty()).then(|| serde_json::to_string(&values).unwrap_or_default())
+        }
+
+        let mut values = Vec::new();
+        let mut push = |field, value: Option<Option<String>>| {
+            if let Some(value) = value {
+                values.push((field, value));
+            }
+        };
+
+        push(MetadataField::Title, self.title.map(text));
+        push(MetadataField::Authors, self.authors.map(list));
+        push(MetadataField::Publisher, self.publisher.map(text));
+        push(MetadataField::PublishedDate, self.published_date.map(text));
+        push(MetadataField::Isbn, self.isbn.map(text));
+        push(MetadataField::Description, self.description.map(text));
+        push(MetadataField::Language, self.language.map(text));
+        push(MetadataField::Series, self.series.map(text));
+        push(
+            MetadataField::SeriesIndex,
+            self.series_index.map(|i| Some(i.to_string())),
+        );
+        push(
+            MetadataField::Rating,
+            self.rating.map(|r| Some(r.to_string())),
+        );
+        push(MetadataField::Tags, self.tags.map(list));
+
+        values
+    }
+}
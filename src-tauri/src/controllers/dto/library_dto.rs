@@ -0,0 +1,61 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::services::library_service::ColumnTarget;
+
+#[derive(Serialize)]
+pub struct LibraryDTO {
+    pub library_id: i32,
+    pub name: String,
+    pub path: String,
+    pub added_at: Option<String>,
+    pub owner_id: Option<i32>,
+    /// `public` or `private`
+    pub visibility: String,
+}
+
+#[derive(Deserialize)]
+pub struct NewLibraryDTO {
+    pub name: String,
+    pub path: String,
+    /// `public` (the default) or `private`
+    pub visibility: Option<String>,
+}
+
+/// Changes who can see a library, missing fields are kept
+#[derive(Deserialize)]
+pub struct LibraryAccessDTO {
+    pub visibility: Option<String>,
+    /// Hands the library to another user, admins only
+    pub owner_id: Option<i32>,
+}
+
+/// Shares a library with either a user or everyone with at least a role
+#[derive(Deserialize)]
+pub struct NewLibraryShareDTO {
+    pub user_id: Option<i32>,
+    pub role: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct LibraryShareDTO {
+    pub share_id: i32,
+    pub user_id: Option<i32>,
+    pub role: Option<String>,
+    pub created_at: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct CalibreImportDTO {
+    /// Directory containing the Calibre `metadata.db`
+    pub path: String,
+    /// Overrides where user columns go, keyed by lookup name without `#`
+    #[serde(default)]
+    pub column_mapping: HashMap<String, ColumnTarget>,
+}
+
+#[derive(Deserialize)]
+pub struct ScanLibraryDTO {
+    /// Path templates to try instead of the configured ones, e.g. `{author}/{title}.{ext}`
+    pub templates: Option<Vec<String>>,
+}
@@ -4,6 +4,12 @@ use serde::{Deserialize, Serialize};
 pub struct LoginDTO {
     pub username: String,
     pub password: String,
+    /// Shown in the session list, e.g. "Kobo Libra" or "Work laptop"
+    #[serde(default)]
+    pub device_name: Option<String>,
+    /// Keep the tokens in HttpOnly cookies instead of the response, for the web frontend
+    #[serde(default)]
+    pub use_cookies: bool,
 }
 
 // NOTE: Add from and into implementations if needed
@@ -0,0 +1,38 @@
+use serde::{Deserialize, Serialize};
+
+use crate::data::models::book_metadata_sources::BookMetadataSources;
+
+use crate::services::metadata_service::MetadataField;
+
+#[derive(Deserialize)]
+pub struct MetadataMatchQuery {
+    pub isbn: Option<String>,
+    pub title: Option<String>,
+    pub author: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct ApplyMetadataDTO {
+    /// `id` of a candidate returned by the matches endpoint
+    pub candidate_id: Option<String>,
+    /// Applies the provider's first match of this ISBN when no `candidate_id` is given
+    pub isbn: Option<String>,
+    /// Fields to update, all differing fields are applied when omitted
+    pub fields: Option<Vec<MetadataField>>,
+}
+
+#[derive(Serialize)]
+pub struct MetadataSourceDTO {
+    pub field: String,
+    /// `embedded`, `path` or `default`
+    pub source: String,
+}
+
+impl From<BookMetadataSources> for MetadataSourceDTO {
+    fn from(source: BookMetadataSources) -> Self {
+        MetadataSourceDTO {
+            field: source.field,
+            source: source.source,
+        }
+    }
+}
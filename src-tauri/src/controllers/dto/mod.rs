@@ -1,5 +1,15 @@
 pub mod annotation_dto;
+pub mod api_key_dto;
+pub mod audit_dto;
 pub mod bookmark_dto;
+pub mod history_dto;
+pub mod library_dto;
 pub mod login_dto;
+pub mod metadata_dto;
+pub mod opds_dto;
+pub mod password_dto;
 pub mod reading_progress_dto;
+pub mod registration_dto;
+pub mod session_dto;
+pub mod two_factor_dto;
 pub mod user_dto;
@@ -0,0 +1,105 @@
+use serde::Deserialize;
+
+use crate::{
+    data::repos::implementors::book_repo::{BookFacets, BookOrder, BookScope},
+    opds::facets::{parse_order, parse_read_state, FacetSelection},
+};
+
+#[derive(Deserialize, Default)]
+pub struct PageQuery {
+    /// Page of an acquisition feed, starting at 1
+    pub page: Option<i64>,
+}
+
+impl PageQuery {
+    pub fn page(&self) -> i64 {
+        self.page.unwrap_or(1)
+    }
+}
+
+/// Page and facets of an acquisition feed
+#[derive(Deserialize, Default)]
+pub struct OpdsFacetDTO {
+    pub page: Option<i64>,
+    /// `title`, `author`, `newest` or `published`
+    pub sort: Option<String>,
+    pub format: Option<String>,
+    pub language: Option<String>,
+    /// `unread`, `reading` or `finished`
+    pub status: Option<String>,
+    pub tag: Option<i32>,
+    pub series: Option<i32>,
+}
+
+impl OpdsFacetDTO {
+    pub fn page(&self) -> i64 {
+        self.page.unwrap_or(1)
+    }
+
+    /// Unknown values are ignored so a stale link still shows books
+    pub fn selection(&self) -> FacetSelection {
+        let text = |value: &Option<String>| {
+            value
+                .as_deref()
+                .map(str::trim)
+                .filter(|v| !v.is_empty())
+                .map(str::to_string)
+        };
+        FacetSelection {
+            order: self
+                .sort
+                .as_deref()
+                .and_then(parse_order)
+                .unwrap_or(BookOrder::Title),
+            facets: BookFacets {
+                format: text(&self.format).map(|f| f.to_lowercase()),
+                language: text(&self.language),
+                tag_id: self.tag,
+                series_id: self.series,
+                user_id: None,
+                read_state: self.status.as_deref().and_then(parse_read_state),
+                // Narrowed down to the user's libraries when the feed is built
+                scope: BookScope::All,
+            },
+        }
+    }
+}
+
+#[derive(Deserialize, Default)]
+pub struct OpdsSearchDTO {
+    /// OpenSearch `{searchTerms}`
+    pub q: Option<String>,
+    /// OpenSearch `{atom:author}`
+    pub author: Option<String>,
+    /// OpenSearch `{atom:title}`
+    pub title: Option<String>,
+    pub page: Option<i64>,
+}
+
+#[derive(Deserialize, Default)]
+pub struct PageStreamDTO {
+    /// Kept as text, readers that don't fill the template send it empty or unexpanded
+    #[serde(rename = "maxWidth")]
+    pub max_width: Option<String>,
+}
+
+impl PageStreamDTO {
+    pub fn max_width(&self) -> Option<u32> {
+        self.max_width.as_deref()?.parse().ok().filter(|w| *w > 0)
+    }
+}
+
+#[derive(Deserialize, Default)]
+pub struct Opds2ListDTO {
+    pub page: Option<i64>,
+    /// `title`, `author`, `newest` or `published`, books are sorted by title otherwise
+    pub order: Option<String>,
+}
+
+#[derive(Deserialize, Default)]
+pub struct Opds2SearchDTO {
+    pub query: Option<String>,
+    pub author: Option<String>,
+    pub title: Option<String>,
+    pub page: Option<i64>,
+}
@@ -0,0 +1,25 @@
+use serde::Deserialize;
+
+#[derive(Deserialize)]
+pub struct ChangePasswordDTO {
+    pub current_password: String,
+    pub new_password: String,
+}
+
+#[derive(Deserialize)]
+pub struct ForgotPasswordDTO {
+    pub email: String,
+}
+
+#[derive(Deserialize)]
+pub struct ResetPasswordDTO {
+    pub token: String,
+    pub new_password: String,
+}
+
+#[derive(Deserialize, Default)]
+pub struct AdminResetQuery {
+    /// Also invalidate the current password and sign the user out
+    #[serde(default)]
+    pub force: bool,
+}
@@ -0,0 +1,52 @@
+use serde::{Deserialize, Serialize};
+
+use crate::data::models::invites::Invites;
+
+/// Public state of the server, clients show the setup screen while `setup_required` is set
+#[derive(Serialize)]
+pub struct RegistrationStatusDTO {
+    pub setup_required: bool,
+    /// `open`, `closed` or `invite_only`
+    pub registration: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct RegistrationPolicyDTO {
+    pub registration: String,
+}
+
+#[derive(Deserialize)]
+pub struct NewInviteDTO {
+    /// Role of the invited account, `user` when left out
+    pub role: Option<String>,
+    pub expires_in_days: Option<i64>,
+}
+
+#[derive(Serialize)]
+pub struct InviteDTO {
+    pub invite_id: i32,
+    pub role: String,
+    pub created_by: Option<i32>,
+    pub created_at: Option<String>,
+    pub expires_at: String,
+}
+
+impl From<Invites> for InviteDTO {
+    fn from(invite: Invites) -> Self {
+        InviteDTO {
+            invite_id: invite.invite_id,
+            role: invite.role,
+            created_by: invite.created_by,
+            created_at: invite.created_at,
+            expires_at: invite.expires_at,
+        }
+    }
+}
+
+/// Returned once when an invite is created, the code can't be retrieved afterwards
+#[derive(Serialize)]
+pub struct CreatedInviteDTO {
+    pub code: String,
+    #[serde(flatten)]
+    pub invite: InviteDTO,
+}
@@ -0,0 +1,26 @@
+use serde::Serialize;
+
+use crate::data::models::sessions::Sessions;
+
+#[derive(Serialize)]
+pub struct SessionDTO {
+    pub session_id: i32,
+    pub device_name: Option<String>,
+    pub user_agent: Option<String>,
+    pub created_at: Option<String>,
+    pub last_used_at: Option<String>,
+    pub expires_at: String,
+}
+
+impl From<Sessions> for SessionDTO {
+    fn from(session: Sessions) -> Self {
+        SessionDTO {
+            session_id: session.session_id,
+            device_name: session.device_name,
+            user_agent: session.user_agent,
+            created_at: session.created_at,
+            last_used_at: session.last_used_at,
+            expires_at: session.expires_at,
+        }
+    }
+}
@@ -0,0 +1,75 @@
+use serde::{Deserialize, Serialize};
+
+use crate::services::two_factor_service::{Challenge, Enrollment, TwoFactorStatus};
+
+/// A TOTP code or one of the recovery codes
+#[derive(Deserialize)]
+pub struct TwoFactorCodeDTO {
+    pub code: String,
+}
+
+#[derive(Deserialize)]
+pub struct TwoFactorLoginDTO {
+    pub challenge_token: String,
+    pub code: String,
+    /// See [`LoginDTO`](super::login_dto::LoginDTO)
+    #[serde(default)]
+    pub use_cookies: bool,
+}
+
+/// Answer of `/login` for users with two-factor authentication, the token is completed
+/// at `/login/two_factor`
+#[derive(Serialize)]
+pub struct LoginChallengeDTO {
+    pub two_factor_required: bool,
+    pub challenge_token: String,
+    pub expires_at: String,
+}
+
+impl From<Challenge> for LoginChallengeDTO {
+    fn from(challenge: Challenge) -> Self {
+        LoginChallengeDTO {
+            two_factor_required: true,
+            challenge_token: challenge.token,
+            expires_at: challenge.expires_at,
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct TwoFactorStatusDTO {
+    pub enabled: bool,
+    pub pending: bool,
+    pub recovery_codes_left: i64,
+}
+
+impl From<TwoFactorStatus> for TwoFactorStatusDTO {
+    fn from(status: TwoFactorStatus) -> Self {
+        TwoFactorStatusDTO {
+            enabled: status.enabled,
+            pending: status.pending,
+            recovery_codes_left: status.recovery_codes_left,
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct EnrollmentDTO {
+    pub secret: String,
+    pub otpauth_uri: String,
+}
+
+impl From<Enrollment> for EnrollmentDTO {
+    fn from(enrollment: Enrollment) -> Self {
+        EnrollmentDTO {
+            secret: enrollment.secret,
+            otpauth_uri: enrollment.uri,
+        }
+    }
+}
+
+/// Shown once, only their hashes are kept
+#[derive(Serialize)]
+pub struct RecoveryCodesDTO {
+    pub recovery_codes: Vec<String>,
+}
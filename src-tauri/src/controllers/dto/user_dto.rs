@@ -2,10 +2,12 @@ use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct UserDTO {
+    pub user_id: i32,
     pub username: String,
     pub email: String,
     pub role: Option<String>,
     pub created_at: Option<String>,
+    pub disabled: bool,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -15,4 +17,21 @@ pub struct NewUserDTO {
     pub password: String,
     pub role: Option<String>,
     pub created_at: Option<String>,
+    /// Needed by `/register` when registration is invite-only
+    pub invite_code: Option<String>,
+}
+
+/// Admin edit of an account, missing fields are left unchanged
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct UpdateUserDTO {
+    pub username: Option<String>,
+    pub email: Option<String>,
+    pub role: Option<String>,
+}
+
+/// A user's edit of their own account
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct UpdateProfileDTO {
+    pub username: Option<String>,
+    pub email: Option<String>,
 }
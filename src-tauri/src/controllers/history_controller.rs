@@ -0,0 +1,96 @@
+use axum::{extract::Path, http::StatusCode, response::IntoResponse, Json};
+
+use crate::{
+    controllers::auth_middleware::AdminUser,
+    data::models::metadata_history::MetadataHistory,
+    parsers::isbn,
+    services::{
+        history_service::{HistoryError, HistoryService},
+        metadata_service::MetadataField,
+    },
+};
+
+use super::dto::history_dto::{EditMetadataDTO, MetadataHistoryDTO};
+
+fn to_dtos(entries: Vec<MetadataHistory>) -> Vec<MetadataHistoryDTO> {
+    entries.into_iter().map(MetadataHistoryDTO::from).collect()
+}
+
+/// Turns the result of a revert into a response, an empty revert means it was already undone
+fn revert_response(
+    result: Result<Option<Vec<MetadataHistory>>, HistoryError>,
+) -> impl IntoResponse {
+    match result {
+        Ok(Some(changes)) if changes.is_empty() => {
+            (StatusCode::CONFLICT, "Already reverted").into_response()
+        }
+        Ok(Some(changes)) => (StatusCode::OK, Json(to_dtos(changes))).into_response(),
+        Ok(None) => (StatusCode::NOT_FOUND, "Change not found").into_response(),
+        Err(e) => {
+            eprintln!("Failed to revert metadata change: {}", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, "Failed to revert change").into_response()
+        }
+    }
+}
+
+/// Lists every recorded metadata change of a book, most recent first
+pub async fn get_history(_user: AdminUser, Path(book_id): Path<i32>) -> impl IntoResponse {
+    let service = HistoryService::new().await;
+
+    match service.get_history(book_id).await {
+        Ok(Some(entries)) => (StatusCode::OK, Json(to_dtos(entries))).into_response(),
+        Ok(None) => (StatusCode::NOT_FOUND, "Book not found").into_response(),
+        Err(e) => {
+            eprintln!("Failed to get metadata history: {}", e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to get metadata history",
+            )
+                .into_response()
+        }
+    }
+}
+
+/// Edits the metadata of a book by hand and returns the recorded changes
+pub async fn edit_metadata(
+    user: AdminUser,
+    Path(book_id): Path<i32>,
+    Json(payload): Json<EditMetadataDTO>,
+) -> impl IntoResponse {
+    let values = payload.into_values();
+    let invalid_isbn = values
+        .iter()
+        .find_map(|(field, value)| (*field == MetadataField::Isbn).then_some(value.as_deref()))
+        .flatten()
+        .filter(|value| isbn::normalize(value).is_none());
+    if let Some(value) = invalid_isbn {
+        return (
+            StatusCode::BAD_REQUEST,
+            format!("Not a valid ISBN: {}", value),
+        )
+            .into_response();
+    }
+
+    let service = HistoryService::new().await;
+
+    match service.edit(book_id, user.id, &values).await {
+        Ok(Some(changes)) => (StatusCode::OK, Json(to_dtos(changes))).into_response(),
+        Ok(None) => (StatusCode::NOT_FOUND, "Book not found").into_response(),
+        Err(e) => {
+            eprintln!("Failed to edit metadata: {}", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, "Failed to edit metadata").into_response()
+        }
+    }
+}
+
+/// Restores the old value of a single change
+pub async fn revert_change(user: AdminUser, Path(history_id): Path<i32>) -> impl IntoResponse {
+    let service = HistoryService::new().await;
+    revert_response(service.revert_change(history_id, user.id).await)
+}
+
+/// Restores the old values of every change made in a batch
+pub async fn revert_batch(user: AdminUser, Path(batch_id): Path<String>) -> impl IntoResponse {
+    let service = HistoryService::new().await;
+    revert_response(service.revert_batch(&batch_id, user.id).await)
+}
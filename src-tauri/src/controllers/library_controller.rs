@@ -0,0 +1,353 @@
+use crate::{
+    controllers::auth_middleware::{AdminUser, AuthUser, MemberUser},
+    data::{
+        models::{
+            audit_events::AuditAction,
+            libraries::{NewLibrary, Visibility},
+            users::Role,
+        },
+        repos::{implementors::library_repo::LibraryRepo, traits::repository::Repository},
+    },
+    handlers::calibre_handler,
+    services::{
+        audit_service::{AuditEntry, AuditService, RequestOrigin},
+        library_access_service::{AccessError, LibraryAccessService, LibraryChange, ShareTarget},
+        library_service::{CalibreImportOptions, LibraryService},
+    },
+};
+use axum::{
+    extract::Path,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use std::path::PathBuf;
+
+use super::dto::library_dto::{
+    CalibreImportDTO, LibraryAccessDTO, LibraryDTO, LibraryShareDTO, NewLibraryDTO,
+    NewLibraryShareDTO, ScanLibraryDTO,
+};
+
+/// Sends the result of an access change, `done` builds the response for a change that went through
+fn access_response<T>(
+    result: Result<LibraryChange<T>, AccessError>,
+    done: impl FnOnce(T) -> Response,
+) -> Response {
+    match result {
+        Ok(LibraryChange::Done(value)) => done(value),
+        Ok(LibraryChange::NotFound) => (StatusCode::NOT_FOUND, "Not found").into_response(),
+        Ok(LibraryChange::Forbidden) => (
+            StatusCode::FORBIDDEN,
+            "Only the owner or an admin can change this library",
+        )
+            .into_response(),
+        Ok(LibraryChange::Invalid(message)) => (StatusCode::BAD_REQUEST, message).into_response(),
+        Ok(LibraryChange::Conflict) => (
+            StatusCode::CONFLICT,
+            "The library is already shared with them",
+        )
+            .into_response(),
+        Err(e) => {
+            eprintln!("Error changing library access: {}", e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to change library access",
+            )
+                .into_response()
+        }
+    }
+}
+
+/// `Err` for a visibility that doesn't exist
+fn parse_visibility(visibility: Option<&str>) -> Result<Option<Visibility>, ()> {
+    match visibility {
+        None => Ok(None),
+        Some(value) => Visibility::parse(value).map(Some).ok_or(()),
+    }
+}
+
+fn unknown_visibility() -> Response {
+    (
+        StatusCode::BAD_REQUEST,
+        "Visibility must be public or private",
+    )
+        .into_response()
+}
+
+/// Checks that the user can see a book, books in libraries they can't see are reported as
+/// missing so their ids don't leak
+pub async fn require_book(user_id: i32, book_id: i32) -> Result<(), Response> {
+    match LibraryAccessService::new()
+        .await
+        .book(user_id, book_id)
+        .await
+    {
+        Ok(Some(_)) => Ok(()),
+        Ok(None) => Err((StatusCode::NOT_FOUND, "Book not found").into_response()),
+        Err(e) => {
+            eprintln!("Failed to check book access: {}", e);
+            Err((StatusCode::INTERNAL_SERVER_ERROR, "Database error").into_response())
+        }
+    }
+}
+
+/// Lists the libraries the user can see
+pub async fn list_libraries(user: AuthUser) -> impl IntoResponse {
+    let service = LibraryAccessService::new().await;
+
+    match service.libraries(user.id).await {
+        Ok(libraries) => {
+            let dtos: Vec<LibraryDTO> = libraries.into_iter().map(LibraryDTO::from).collect();
+            (StatusCode::OK, Json(dtos)).into_response()
+        }
+        Err(e) => {
+            eprintln!("Failed to list libraries: {}", e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to list libraries",
+            )
+                .into_response()
+        }
+    }
+}
+
+pub async fn create_library(
+    user: AdminUser,
+    origin: RequestOrigin,
+    Json(payload): Json<NewLibraryDTO>,
+) -> impl IntoResponse {
+    let library_repo = LibraryRepo::new().await;
+
+    if !PathBuf::from(&payload.path).is_dir() {
+        return (StatusCode::BAD_REQUEST, "Library path is not a directory").into_response();
+    }
+    let Ok(visibility) = parse_visibility(payload.visibility.as_deref()) else {
+        return unknown_visibility();
+    };
+
+    match library_repo
+        .add(NewLibrary {
+            name: &payload.name,
+            path: &payload.path,
+            added_by: Some(user.id),
+            visibility: visibility.map(|v| v.as_str()),
+        })
+        .await
+    {
+        Ok(_) => {
+            let created = library_repo.get_by_path(&payload.path).await;
+            let mut entry = AuditEntry::by(user.id).details(format!("path: {}", payload.path));
+            if let Ok(Some(library)) = created {
+                entry = entry.target("library", library.library_id);
+            }
+            AuditService::new()
+                .await
+                .record(&origin, AuditAction::LibraryCreated, entry)
+                .await;
+            (StatusCode::CREATED, "Library created").into_response()
+        }
+        Err(e) => {
+            eprintln!("Failed to create library: {}", e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to create library",
+            )
+                .into_response()
+        }
+    }
+}
+
+/// Imports a Calibre library and returns a report of what was and wasn't carried over
+pub async fn import_calibre(
+    user: AdminUser,
+    origin: RequestOrigin,
+    Json(payload): Json<CalibreImportDTO>,
+) -> impl IntoResponse {
+    let root = PathBuf::from(&payload.path);
+    if !calibre_handler::is_calibre_library(&root) {
+        return (StatusCode::BAD_REQUEST, "No Calibre library found at path").into_response();
+    }
+
+    let service = LibraryService::new().await;
+    let options = CalibreImportOptions {
+        column_mapping: payload.column_mapping,
+    };
+
+    match service.import_calibre(root, user.id, options).await {
+        Ok(report) => {
+            AuditService::new()
+                .await
+                .record(
+                    &origin,
+                    AuditAction::LibraryImported,
+                    AuditEntry::by(user.id)
+                        .target("library", report.library_id)
+                        .details(format!(
+                            "imported {}, skipped {}, failed {}",
+                            report.imported, report.skipped, report.failed
+                        )),
+                )
+                .await;
+            (StatusCode::OK, Json(report)).into_response()
+        }
+        Err(e) => {
+            eprintln!("Failed to import Calibre library: {}", e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to import Calibre library",
+            )
+                .into_response()
+        }
+    }
+}
+
+/// Adds new EPUBs found below the library root, inferring missing metadata from their paths
+pub async fn scan_library(
+    user: AdminUser,
+    origin: RequestOrigin,
+    Path(library_id): Path<i32>,
+    Json(payload): Json<ScanLibraryDTO>,
+) -> impl IntoResponse {
+    let service = LibraryService::new().await;
+
+    match service
+        .scan_library(library_id, user.id, payload.templates)
+        .await
+    {
+        Ok(Some(report)) => {
+            AuditService::new()
+                .await
+                .record(
+                    &origin,
+                    AuditAction::LibraryScanned,
+                    AuditEntry::by(user.id)
+                        .target("library", library_id)
+                        .details(format!(
+                            "added {}, skipped {}, failed {}",
+                            report.added, report.skipped, report.failed
+                        )),
+                )
+                .await;
+            (StatusCode::OK, Json(report)).into_response()
+        }
+        Ok(None) => (StatusCode::NOT_FOUND, "Library not found").into_response(),
+        Err(e) => {
+            eprintln!("Failed to scan library: {}", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, "Failed to scan library").into_response()
+        }
+    }
+}
+
+/// Changes the visibility or owner of a library (PUT /libraries/{id}/access)
+pub async fn update_access(
+    user: MemberUser,
+    origin: RequestOrigin,
+    Path(library_id): Path<i32>,
+    Json(payload): Json<LibraryAccessDTO>,
+) -> impl IntoResponse {
+    let Ok(visibility) = parse_visibility(payload.visibility.as_deref()) else {
+        return unknown_visibility();
+    };
+
+    let service = LibraryAccessService::new().await;
+    let result = service
+        .set_access(user.id, library_id, visibility, payload.owner_id)
+        .await;
+    if let Ok(LibraryChange::Done(library)) = &result {
+        AuditService::new()
+            .await
+            .record(
+                &origin,
+                AuditAction::LibraryAccessChanged,
+                AuditEntry::by(user.id)
+                    .target("library", library_id)
+                    .details(format!(
+                        "visibility: {}, owner: {}",
+                        library.visibility().as_str(),
+                        library
+                            .added_by
+                            .map_or_else(|| "none".to_string(), |id| id.to_string())
+                    )),
+            )
+            .await;
+    }
+    access_response(result, |library| {
+        (StatusCode::OK, Json(LibraryDTO::from(*library))).into_response()
+    })
+}
+
+pub async fn list_shares(user: MemberUser, Path(library_id): Path<i32>) -> impl IntoResponse {
+    let service = LibraryAccessService::new().await;
+    access_response(service.shares(user.id, library_id).await, |shares| {
+        let dtos: Vec<LibraryShareDTO> = shares.into_iter().map(LibraryShareDTO::from).collect();
+        (StatusCode::OK, Json(dtos)).into_response()
+    })
+}
+
+/// Shares a library with a user or a role (POST /libraries/{id}/shares)
+pub async fn add_share(
+    user: MemberUser,
+    origin: RequestOrigin,
+    Path(library_id): Path<i32>,
+    Json(payload): Json<NewLibraryShareDTO>,
+) -> impl IntoResponse {
+    let target = match (payload.user_id, payload.role.as_deref()) {
+        (Some(id), None) => ShareTarget::User(id),
+        (None, Some(role)) => match Role::parse(role) {
+            Some(role) => ShareTarget::Role(role),
+            None => return (StatusCode::BAD_REQUEST, "Unknown role").into_response(),
+        },
+        _ => {
+            return (
+                StatusCode::BAD_REQUEST,
+                "Either a user_id or a role is required",
+            )
+                .into_response()
+        }
+    };
+
+    let service = LibraryAccessService::new().await;
+    let result = service.share(user.id, library_id, target).await;
+    if let Ok(LibraryChange::Done(share)) = &result {
+        let with = match (share.user_id, share.role.as_deref()) {
+            (Some(id), _) => format!("user {}", id),
+            (None, Some(role)) => format!("role {}", role),
+            (None, None) => "nobody".to_string(),
+        };
+        AuditService::new()
+            .await
+            .record(
+                &origin,
+                AuditAction::LibraryShared,
+                AuditEntry::by(user.id)
+                    .target("library", library_id)
+                    .details(format!("share {} with {}", share.share_id, with)),
+            )
+            .await;
+    }
+    access_response(result, |share| {
+        (StatusCode::CREATED, Json(LibraryShareDTO::from(*share))).into_response()
+    })
+}
+
+pub async fn remove_share(
+    user: MemberUser,
+    origin: RequestOrigin,
+    Path((library_id, share_id)): Path<(i32, i32)>,
+) -> impl IntoResponse {
+    let service = LibraryAccessService::new().await;
+    let result = service.unshare(user.id, library_id, share_id).await;
+    if let Ok(LibraryChange::Done(())) = &result {
+        AuditService::new()
+            .await
+            .record(
+                &origin,
+                AuditAction::LibraryUnshared,
+                AuditEntry::by(user.id)
+                    .target("library", library_id)
+                    .details(format!("share {}", share_id)),
+            )
+            .await;
+    }
+    access_response(result, |_| StatusCode::NO_CONTENT.into_response())
+}
@@ -0,0 +1,117 @@
+use crate::{
+    controllers::auth_middleware::{AdminUser, AuthUser},
+    data::repos::implementors::book_metadata_source_repo::BookMetadataSourceRepo,
+    services::metadata_service::{MetadataMatch, MetadataService},
+};
+use axum::{
+    extract::{Path, Query},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+
+use super::dto::metadata_dto::{ApplyMetadataDTO, MetadataMatchQuery, MetadataSourceDTO};
+use super::library_controller::require_book;
+
+/// Looks up external metadata for a book and returns every candidate with its per-field diff
+pub async fn get_metadata_matches(
+    _user: AdminUser,
+    Path(book_id): Path<i32>,
+    Query(params): Query<MetadataMatchQuery>,
+) -> impl IntoResponse {
+    let service = MetadataService::from_env();
+
+    match service
+        .find_matches(
+            book_id,
+            params.isbn.as_deref(),
+            params.title.as_deref(),
+            params.author.as_deref(),
+        )
+        .await
+    {
+        Ok(Some(matches)) => (StatusCode::OK, Json(matches)).into_response(),
+        Ok(None) => (StatusCode::NOT_FOUND, "Book not found").into_response(),
+        Err(e) => {
+            eprintln!("Failed to look up metadata: {}", e);
+            (StatusCode::BAD_GATEWAY, Json(Vec::<MetadataMatch>::new())).into_response()
+        }
+    }
+}
+
+/// Updates a book with the selected fields of a candidate and returns the applied changes
+pub async fn apply_metadata(
+    user: AdminUser,
+    Path(book_id): Path<i32>,
+    Json(payload): Json<ApplyMetadataDTO>,
+) -> impl IntoResponse {
+    let service = MetadataService::from_env();
+
+    if payload.candidate_id.is_none() && payload.isbn.is_none() {
+        return (
+            StatusCode::BAD_REQUEST,
+            "A candidate_id or isbn is required",
+        )
+            .into_response();
+    }
+    let candidate = match service
+        .resolve(payload.candidate_id.as_deref(), payload.isbn.as_deref())
+        .await
+    {
+        Ok(Some(candidate)) => candidate,
+        Ok(None) => return (StatusCode::NOT_FOUND, "Candidate not found").into_response(),
+        Err(e) => {
+            eprintln!("Failed to look up metadata: {}", e);
+            return (StatusCode::BAD_GATEWAY, "Failed to look up metadata").into_response();
+        }
+    };
+
+    match service
+        .apply_match(
+            book_id,
+            &candidate,
+            payload.fields.as_deref(),
+            Some(user.id),
+        )
+        .await
+    {
+        Ok(Some(changes)) => (StatusCode::OK, Json(changes)).into_response(),
+        Ok(None) => (StatusCode::NOT_FOUND, "Book not found").into_response(),
+        Err(e) => {
+            eprintln!("Failed to apply metadata: {}", e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to apply metadata",
+            )
+                .into_response()
+        }
+    }
+}
+
+/// Lists where each stored field of a book came from: the file itself, its path or a fallback
+pub async fn get_metadata_sources(user: AuthUser, Path(book_id): Path<i32>) -> impl IntoResponse {
+    if let Err(response) = require_book(user.id, book_id).await {
+        return response;
+    }
+
+    let repo = BookMetadataSourceRepo::new().await;
+
+    match repo.get_by_book(book_id).await {
+        Ok(sources) => {
+            let sources: Vec<MetadataSourceDTO> = sources
+                .unwrap_or_default()
+                .into_iter()
+                .map(MetadataSourceDTO::from)
+                .collect();
+            (StatusCode::OK, Json(sources)).into_response()
+        }
+        Err(e) => {
+            eprintln!("Failed to get metadata sources: {}", e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to get metadata sources",
+            )
+                .into_response()
+        }
+    }
+}
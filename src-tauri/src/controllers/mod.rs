@@ -1,10 +1,21 @@
 pub mod annotation_controller;
+pub mod api_key_controller;
+pub mod audit_controller;
 pub mod auth_controller;
+pub mod auth_cookies;
 pub mod auth_middleware;
 pub mod book_controller;
 pub mod bookmark_controller;
 pub mod dto;
+pub mod history_controller;
+pub mod library_controller;
+pub mod metadata_controller;
+pub mod opds2_controller;
 pub mod opds_controller;
+pub mod password_controller;
 pub mod reading_progress_controller;
+pub mod registration_controller;
 pub mod search_controller;
+pub mod session_controller;
+pub mod two_factor_controller;
 pub mod user_controller;
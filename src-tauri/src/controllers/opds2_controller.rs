@@ -0,0 +1,133 @@
+use axum::{
+    extract::{Path, Query},
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::Serialize;
+
+use crate::{
+    controllers::{
+        auth_middleware::DeviceUser,
+        dto::opds_dto::{Opds2ListDTO, Opds2SearchDTO, PageQuery},
+    },
+    data::repos::implementors::book_repo::BookOrder,
+    opds::{
+        facets::parse_order,
+        opds2::{OPDS_JSON_TYPE, PUBLICATION_TYPE},
+        search::SearchQuery,
+    },
+    services::{opds2_service::Opds2Service, opds_service::OpdsError},
+};
+
+/// Sends a feed as JSON with its OPDS content type, or a 404 when the feed's subject doesn't exist
+fn json_response<T: Serialize>(
+    result: Result<Option<T>, OpdsError>,
+    content_type: &'static str,
+) -> Response {
+    match result {
+        // The header replaces the `application/json` set by `Json`
+        Ok(Some(feed)) => (
+            StatusCode::OK,
+            [(header::CONTENT_TYPE, content_type)],
+            Json(feed),
+        )
+            .into_response(),
+        Ok(None) => (StatusCode::NOT_FOUND, "Not found").into_response(),
+        Err(e) => {
+            eprintln!("Failed to build OPDS 2.0 feed: {}", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, "Failed to build feed").into_response()
+        }
+    }
+}
+
+fn list_params(params: &Opds2ListDTO) -> (BookOrder, i64) {
+    let order = params
+        .order
+        .as_deref()
+        .and_then(parse_order)
+        .unwrap_or(BookOrder::Title);
+    (order, params.page.unwrap_or(1))
+}
+
+pub async fn root(user: DeviceUser) -> impl IntoResponse {
+    let service = Opds2Service::new().await.for_user(user.id);
+    json_response(service.root().await.map(Some), OPDS_JSON_TYPE)
+}
+
+pub async fn recent(user: DeviceUser, Query(query): Query<PageQuery>) -> impl IntoResponse {
+    let service = Opds2Service::new().await.for_user(user.id);
+    json_response(service.recent(query.page()).await.map(Some), OPDS_JSON_TYPE)
+}
+
+pub async fn books(user: DeviceUser, Query(params): Query<Opds2ListDTO>) -> impl IntoResponse {
+    let service = Opds2Service::new().await.for_user(user.id);
+    let (order, page) = list_params(&params);
+    json_response(service.books(order, page).await.map(Some), OPDS_JSON_TYPE)
+}
+
+pub async fn publication(user: DeviceUser, Path(book_id): Path<i32>) -> impl IntoResponse {
+    let service = Opds2Service::new().await.for_user(user.id);
+    json_response(service.publication(book_id).await, PUBLICATION_TYPE)
+}
+
+pub async fn authors(user: DeviceUser) -> impl IntoResponse {
+    let service = Opds2Service::new().await.for_user(user.id);
+    json_response(service.authors().await.map(Some), OPDS_JSON_TYPE)
+}
+
+pub async fn author_books(
+    user: DeviceUser,
+    Path(author_id): Path<i32>,
+    Query(params): Query<Opds2ListDTO>,
+) -> impl IntoResponse {
+    let service = Opds2Service::new().await.for_user(user.id);
+    let (order, page) = list_params(&params);
+    json_response(
+        service.author_books(author_id, order, page).await,
+        OPDS_JSON_TYPE,
+    )
+}
+
+pub async fn publishers(user: DeviceUser) -> impl IntoResponse {
+    let service = Opds2Service::new().await.for_user(user.id);
+    json_response(service.publishers().await.map(Some), OPDS_JSON_TYPE)
+}
+
+pub async fn publisher_books(
+    user: DeviceUser,
+    Path(publisher_id): Path<i32>,
+    Query(params): Query<Opds2ListDTO>,
+) -> impl IntoResponse {
+    let service = Opds2Service::new().await.for_user(user.id);
+    let (order, page) = list_params(&params);
+    json_response(
+        service.publisher_books(publisher_id, order, page).await,
+        OPDS_JSON_TYPE,
+    )
+}
+
+pub async fn libraries(user: DeviceUser) -> impl IntoResponse {
+    let service = Opds2Service::new().await.for_user(user.id);
+    json_response(service.libraries().await.map(Some), OPDS_JSON_TYPE)
+}
+
+pub async fn library_books(
+    user: DeviceUser,
+    Path(library_id): Path<i32>,
+    Query(params): Query<Opds2ListDTO>,
+) -> impl IntoResponse {
+    let service = Opds2Service::new().await.for_user(user.id);
+    let (order, page) = list_params(&params);
+    json_response(
+        service.library_books(library_id, order, page).await,
+        OPDS_JSON_TYPE,
+    )
+}
+
+pub async fn search(user: DeviceUser, Query(params): Query<Opds2SearchDTO>) -> impl IntoResponse {
+    let service = Opds2Service::new().await.for_user(user.id);
+    let page = params.page.unwrap_or(1);
+    let query = SearchQuery::new(params.query, params.author, params.title);
+    json_response(service.search(&query, page).await.map(Some), OPDS_JSON_TYPE)
+}
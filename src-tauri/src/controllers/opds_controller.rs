@@ -1 +1,245 @@
-// OPDS endpoints controller implementations go here
+use axum::{
+    extract::{Path, Query},
+    http::{header, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+};
+use axum_extra::response::file_stream::FileStream;
+
+use crate::{
+    controllers::{
+        auth_middleware::{DeviceUser, DownloadUser},
+        dto::opds_dto::{OpdsFacetDTO, OpdsSearchDTO, PageQuery, PageStreamDTO},
+    },
+    opds::{
+        acquisition::{format_mime_type, image_mime_type},
+        feed_builder::{ACQUISITION_TYPE, NAVIGATION_TYPE},
+        search::{SearchQuery, OPENSEARCH_TYPE},
+    },
+    services::{
+        library_access_service::LibraryAccessService,
+        opds_service::{OpdsError, OpdsService},
+        page_service::PageService,
+    },
+};
+
+/// Sends a feed with its OPDS content type, or a 404 when the feed's subject doesn't exist
+fn feed_response(
+    result: Result<Option<String>, OpdsError>,
+    content_type: &'static str,
+) -> Response {
+    match result {
+        Ok(Some(xml)) => {
+            (StatusCode::OK, [(header::CONTENT_TYPE, content_type)], xml).into_response()
+        }
+        Ok(None) => (StatusCode::NOT_FOUND, "Not found").into_response(),
+        Err(e) => {
+            eprintln!("Failed to build OPDS feed: {}", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, "Failed to build feed").into_response()
+        }
+    }
+}
+
+pub async fn root(_user: DeviceUser) -> impl IntoResponse {
+    let service = OpdsService::new().await;
+    feed_response(service.root().await.map(Some), NAVIGATION_TYPE)
+}
+
+/// Streams a file with the given content type, `FileStream` always sends octet-stream
+async fn file_response(path: &str, content_type: &'static str) -> Response {
+    match FileStream::from_path(path).await {
+        Ok(stream) => {
+            let mut response = stream.into_response();
+            response
+                .headers_mut()
+                .insert(header::CONTENT_TYPE, HeaderValue::from_static(content_type));
+            response
+        }
+        Err(e) => {
+            eprintln!("Failed to open {}: {}", path, e);
+            (StatusCode::NOT_FOUND, "File not found").into_response()
+        }
+    }
+}
+
+pub async fn recent(user: DeviceUser, Query(query): Query<PageQuery>) -> impl IntoResponse {
+    let service = OpdsService::new().await.for_user(user.id);
+    feed_response(
+        service.recent(query.page()).await.map(Some),
+        ACQUISITION_TYPE,
+    )
+}
+
+pub async fn all_books(user: DeviceUser, Query(query): Query<OpdsFacetDTO>) -> impl IntoResponse {
+    let service = OpdsService::new().await.for_user(user.id);
+    feed_response(
+        service
+            .all_books(&query.selection(), query.page())
+            .await
+            .map(Some),
+        ACQUISITION_TYPE,
+    )
+}
+
+pub async fn authors(user: DeviceUser) -> impl IntoResponse {
+    let service = OpdsService::new().await.for_user(user.id);
+    feed_response(service.authors().await.map(Some), NAVIGATION_TYPE)
+}
+
+pub async fn author_books(
+    user: DeviceUser,
+    Path(author_id): Path<i32>,
+    Query(query): Query<OpdsFacetDTO>,
+) -> impl IntoResponse {
+    let service = OpdsService::new().await.for_user(user.id);
+    feed_response(
+        service
+            .author_books(author_id, &query.selection(), query.page())
+            .await,
+        ACQUISITION_TYPE,
+    )
+}
+
+pub async fn publishers(user: DeviceUser) -> impl IntoResponse {
+    let service = OpdsService::new().await.for_user(user.id);
+    feed_response(service.publishers().await.map(Some), NAVIGATION_TYPE)
+}
+
+pub async fn publisher_books(
+    user: DeviceUser,
+    Path(publisher_id): Path<i32>,
+    Query(query): Query<OpdsFacetDTO>,
+) -> impl IntoResponse {
+    let service = OpdsService::new().await.for_user(user.id);
+    feed_response(
+        service
+            .publisher_books(publisher_id, &query.selection(), query.page())
+            .await,
+        ACQUISITION_TYPE,
+    )
+}
+
+pub async fn libraries(user: DeviceUser) -> impl IntoResponse {
+    let service = OpdsService::new().await.for_user(user.id);
+    feed_response(service.libraries().await.map(Some), NAVIGATION_TYPE)
+}
+
+pub async fn library_books(
+    user: DeviceUser,
+    Path(library_id): Path<i32>,
+    Query(query): Query<OpdsFacetDTO>,
+) -> impl IntoResponse {
+    let service = OpdsService::new().await.for_user(user.id);
+    feed_response(
+        service
+            .library_books(library_id, &query.selection(), query.page())
+            .await,
+        ACQUISITION_TYPE,
+    )
+}
+
+pub async fn download(
+    user: DownloadUser,
+    Path((book_id, format)): Path<(i32, String)>,
+) -> impl IntoResponse {
+    let service = OpdsService::new().await.for_user(user.id);
+    match service.download(book_id, &format).await {
+        Ok(Some(file)) => file_response(&file.path, format_mime_type(&file.format)).await,
+        Ok(None) => (StatusCode::NOT_FOUND, "Not found").into_response(),
+        Err(e) => {
+            eprintln!("Failed to find book file: {}", e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to find book file",
+            )
+                .into_response()
+        }
+    }
+}
+
+pub async fn cover(user: DeviceUser, Path(book_id): Path<i32>) -> impl IntoResponse {
+    let service = OpdsService::new().await.for_user(user.id);
+    match service.cover(book_id).await {
+        Ok(Some(path)) => file_response(&path, image_mime_type(&path)).await,
+        Ok(None) => (StatusCode::NOT_FOUND, "Not found").into_response(),
+        Err(e) => {
+            eprintln!("Failed to find cover: {}", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, "Failed to find cover").into_response()
+        }
+    }
+}
+
+pub async fn thumbnail(user: DeviceUser, Path(book_id): Path<i32>) -> impl IntoResponse {
+    let service = OpdsService::new().await.for_user(user.id);
+    match service.thumbnail(book_id).await {
+        Ok(Some(data)) => {
+            (StatusCode::OK, [(header::CONTENT_TYPE, "image/png")], data).into_response()
+        }
+        Ok(None) => (StatusCode::NOT_FOUND, "Not found").into_response(),
+        Err(e) => {
+            eprintln!("Failed to create thumbnail: {}", e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to create thumbnail",
+            )
+                .into_response()
+        }
+    }
+}
+
+pub async fn opensearch(_user: DeviceUser) -> impl IntoResponse {
+    let service = OpdsService::new().await;
+    feed_response(service.opensearch().await.map(Some), OPENSEARCH_TYPE)
+}
+
+pub async fn search(user: DeviceUser, Query(params): Query<OpdsSearchDTO>) -> impl IntoResponse {
+    let service = OpdsService::new().await.for_user(user.id);
+    let page = params.page.unwrap_or(1);
+    let query = SearchQuery::new(params.q, params.author, params.title);
+    feed_response(
+        service.search(&query, page).await.map(Some),
+        ACQUISITION_TYPE,
+    )
+}
+
+/// OPDS-PSE page, `page` starts at 0. Serving a page also moves the user's reading position
+/// there, unless an API key without the `progress` scope was used.
+pub async fn page(
+    user: DownloadUser,
+    Path((book_id, page)): Path<(i32, usize)>,
+    Query(params): Query<PageStreamDTO>,
+) -> impl IntoResponse {
+    match LibraryAccessService::new()
+        .await
+        .book(user.id, book_id)
+        .await
+    {
+        Ok(Some(_)) => {}
+        Ok(None) => return (StatusCode::NOT_FOUND, "Not found").into_response(),
+        Err(e) => {
+            eprintln!("Failed to check book access: {}", e);
+            return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to read page").into_response();
+        }
+    }
+
+    let service = PageService::new().await;
+    match service.page(book_id, page, params.max_width()).await {
+        Ok(Some(image)) => {
+            if user.tracks_progress {
+                if let Err(e) = service.record_progress(user.id, book_id, page).await {
+                    eprintln!("Failed to record reading progress: {}", e);
+                }
+            }
+            (
+                StatusCode::OK,
+                [(header::CONTENT_TYPE, image.mime_type)],
+                image.data,
+            )
+                .into_response()
+        }
+        Ok(None) => (StatusCode::NOT_FOUND, "Not found").into_response(),
+        Err(e) => {
+            eprintln!("Failed to read page: {}", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, "Failed to read page").into_response()
+        }
+    }
+}
@@ -0,0 +1,211 @@
+use axum::{
+    extract::{Path, Query},
+    http::{header, StatusCode},
+    response::IntoResponse,
+    Json,
+};
+
+use crate::{
+    data::models::audit_events::AuditAction,
+    services::{
+        audit_service::{AuditEntry, AuditService, RequestOrigin},
+        password_service::{PasswordChange, PasswordReset, PasswordService, MIN_PASSWORD_LENGTH},
+    },
+};
+
+use super::auth_middleware::{throttled_response, AdminUser, LoginUser};
+use super::dto::password_dto::{
+    AdminResetQuery, ChangePasswordDTO, ForgotPasswordDTO, ResetPasswordDTO,
+};
+
+fn too_short() -> axum::response::Response {
+    (
+        StatusCode::BAD_REQUEST,
+        format!(
+            "Password must be at least {} characters long",
+            MIN_PASSWORD_LENGTH
+        ),
+    )
+        .into_response()
+}
+
+/// Changes the password of the signed in user (/user/password)
+pub async fn change_password(
+    user: LoginUser,
+    origin: RequestOrigin,
+    Json(payload): Json<ChangePasswordDTO>,
+) -> impl IntoResponse {
+    let service = PasswordService::from_env();
+    let audit = AuditService::new().await;
+    match service
+        .change_password(user.id, &payload.current_password, &payload.new_password)
+        .await
+    {
+        Ok(PasswordChange::Changed) => {
+            audit
+                .record(
+                    &origin,
+                    AuditAction::PasswordChanged,
+                    AuditEntry::by(user.id),
+                )
+                .await;
+            (StatusCode::OK, "Password changed").into_response()
+        }
+        Ok(PasswordChange::WrongPassword) => {
+            audit
+                .record(
+                    &origin,
+                    AuditAction::LoginFailed,
+                    AuditEntry::by(user.id).details("wrong current password"),
+                )
+                .await;
+            (StatusCode::FORBIDDEN, "Current password is incorrect").into_response()
+        }
+        Ok(PasswordChange::TooShort) => too_short(),
+        Ok(PasswordChange::Throttled(throttled)) => throttled_response(throttled),
+        Ok(PasswordChange::UserNotFound) => {
+            (StatusCode::NOT_FOUND, "User not found").into_response()
+        }
+        Err(e) => {
+            eprintln!("Failed to change password: {}", e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to change password",
+            )
+                .into_response()
+        }
+    }
+}
+
+/// Mails a reset token if the address belongs to an account (/password/forgot).
+/// Always answers the same way so addresses can't be probed. The token is created and
+/// mailed in the background, or the response time would tell known addresses apart.
+pub async fn forgot_password(
+    origin: RequestOrigin,
+    Json(payload): Json<ForgotPasswordDTO>,
+) -> impl IntoResponse {
+    let service = PasswordService::from_env();
+    if !service.can_send_mail() {
+        return (
+            StatusCode::SERVICE_UNAVAILABLE,
+            "Password reset by mail isn't set up on this server",
+        )
+            .into_response();
+    }
+    // Counted per address whether or not it has an account, so this tells nothing either
+    if let Err(throttled) = service.throttle_reset_request(&payload.email, origin.ip) {
+        let seconds = throttled.retry_after().as_secs() + 1;
+        return (
+            StatusCode::TOO_MANY_REQUESTS,
+            [(header::RETRY_AFTER, seconds.to_string())],
+            "Too many reset requests, try again later",
+        )
+            .into_response();
+    }
+    tokio::spawn(async move {
+        if let Err(e) = service.request_reset(&payload.email).await {
+            eprintln!("Failed to send password reset: {}", e);
+        }
+    });
+    (
+        StatusCode::ACCEPTED,
+        "If the address belongs to an account, a reset mail has been sent",
+    )
+        .into_response()
+}
+
+/// Sets a new password with a mailed token (/password/reset)
+pub async fn reset_password(
+    origin: RequestOrigin,
+    Json(payload): Json<ResetPasswordDTO>,
+) -> impl IntoResponse {
+    let service = PasswordService::from_env();
+    // Looked up first, the token is used up by the reset
+    let owner = service
+        .reset_token_owner(&payload.token)
+        .await
+        .unwrap_or_else(|e| {
+            eprintln!("Failed to look up reset token: {}", e);
+            None
+        });
+    match service
+        .reset_password(&payload.token, &payload.new_password)
+        .await
+    {
+        Ok(PasswordReset::Reset) => {
+            if let Some(user_id) = owner {
+                AuditService::new()
+                    .await
+                    .record(
+                        &origin,
+                        AuditAction::PasswordReset,
+                        AuditEntry::by(user_id).target("user", user_id),
+                    )
+                    .await;
+            }
+            (StatusCode::OK, "Password reset").into_response()
+        }
+        Ok(PasswordReset::InvalidToken) => {
+            (StatusCode::BAD_REQUEST, "Invalid or expired reset token").into_response()
+        }
+        Ok(PasswordReset::TooShort) => too_short(),
+        Err(e) => {
+            eprintln!("Failed to reset password: {}", e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to reset password",
+            )
+                .into_response()
+        }
+    }
+}
+
+/// Mails a reset token to another user, admins only (/users/{id}/password_reset).
+/// With `?force=true` the current password stops working and the user is signed out.
+pub async fn admin_reset_password(
+    admin: AdminUser,
+    origin: RequestOrigin,
+    Path(id): Path<i32>,
+    Query(query): Query<AdminResetQuery>,
+) -> impl IntoResponse {
+    let service = PasswordService::from_env();
+    if !service.can_send_mail() {
+        return (
+            StatusCode::SERVICE_UNAVAILABLE,
+            "Password reset by mail isn't set up on this server",
+        )
+            .into_response();
+    }
+    let sent = if query.force {
+        service.force_reset(id).await
+    } else {
+        service.request_reset_for_user(id).await
+    };
+    match sent {
+        Ok(true) => {
+            let details = if query.force {
+                "forced, reset mail sent"
+            } else {
+                "reset mail sent"
+            };
+            AuditService::new()
+                .await
+                .record(
+                    &origin,
+                    AuditAction::PasswordReset,
+                    AuditEntry::by(admin.id).target("user", id).details(details),
+                )
+                .await;
+            (StatusCode::ACCEPTED, "Reset mail sent").into_response()
+        }
+        Ok(false) => (StatusCode::NOT_FOUND, "User not found").into_response(),
+        Err(e) => {
+            eprintln!("Failed to send password reset: {}", e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to send reset mail",
+            )
+                .into_response()
+        }
+    }
+}
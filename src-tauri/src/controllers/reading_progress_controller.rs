@@ -1,50 +1,30 @@
 use crate::{
     data::models::reading_progress::NewReadingProgress,
     data::repos::implementors::reading_progress_repo::ReadingProgressRepo,
-    services::token_service::Tokenizer,
-};
-use axum::{
-    extract::Query,
-    http::{HeaderMap, StatusCode},
-    response::IntoResponse,
-    Json,
 };
+use axum::{extract::Query, http::StatusCode, response::IntoResponse, Json};
 use serde::Deserialize;
 
+use super::auth_middleware::{MemberUser, SyncUser};
 use super::dto::reading_progress_dto::{ReadingProgressDTO, UpdateProgressDTO};
+use super::library_controller::require_book;
 
 #[derive(Deserialize)]
 pub struct BookQueryParams {
     book_id: i32,
 }
 
-async fn get_user_from_token(headers: &HeaderMap) -> Result<i32, StatusCode> {
-    let token = headers
-        .get("Authorization")
-        .and_then(|v| v.to_str().ok())
-        .and_then(|v| v.strip_prefix("Bearer "))
-        .ok_or(StatusCode::UNAUTHORIZED)?;
-
-    let tokenizer = Tokenizer::get_instance().await;
-    let claims = tokenizer
-        .decode_token(token)
-        .map_err(|_| StatusCode::UNAUTHORIZED)?;
-
-    Ok(claims.sub)
-}
-
 pub async fn update_progress(
-    headers: HeaderMap,
+    user: MemberUser,
     Json(payload): Json<UpdateProgressDTO>,
 ) -> impl IntoResponse {
-    let user_id = match get_user_from_token(&headers).await {
-        Ok(id) => id,
-        Err(status) => return (status, "Unauthorized").into_response(),
-    };
+    if let Err(response) = require_book(user.id, payload.book_id).await {
+        return response;
+    }
 
     let repo = ReadingProgressRepo::new().await;
     let progress = NewReadingProgress {
-        user_id,
+        user_id: user.id,
         book_id: payload.book_id,
         current_position: &payload.current_position,
         chapter_title: payload.chapter_title.as_deref(),
@@ -59,16 +39,11 @@ pub async fn update_progress(
 }
 
 pub async fn get_progress(
-    headers: HeaderMap,
+    user: SyncUser,
     Query(params): Query<BookQueryParams>,
 ) -> impl IntoResponse {
-    let user_id = match get_user_from_token(&headers).await {
-        Ok(id) => id,
-        Err(status) => return (status, Json(None::<ReadingProgressDTO>)).into_response(),
-    };
-
     let repo = ReadingProgressRepo::new().await;
-    match repo.get_by_user_and_book(user_id, params.book_id).await {
+    match repo.get_by_user_and_book(user.id, params.book_id).await {
         Ok(Some(progress)) => {
             let dto = ReadingProgressDTO {
                 progress_id: progress.progress_id,
@@ -91,14 +66,9 @@ pub async fn get_progress(
     }
 }
 
-pub async fn get_all_progress(headers: HeaderMap) -> impl IntoResponse {
-    let user_id = match get_user_from_token(&headers).await {
-        Ok(id) => id,
-        Err(status) => return (status, Json(Vec::<ReadingProgressDTO>::new())).into_response(),
-    };
-
+pub async fn get_all_progress(user: SyncUser) -> impl IntoResponse {
     let repo = ReadingProgressRepo::new().await;
-    match repo.get_by_user(user_id).await {
+    match repo.get_by_user(user.id).await {
         Ok(Some(progress_list)) => {
             let dtos: Vec<ReadingProgressDTO> = progress_list
                 .into_iter()
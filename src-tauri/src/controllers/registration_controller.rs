@@ -0,0 +1,123 @@
+use axum::{extract::Path, http::StatusCode, response::IntoResponse, Json};
+
+use crate::{
+    data::models::users::Role,
+    services::{
+        registration_service::{InviteCreation, RegistrationPolicy, RegistrationService},
+        session_service::MAX_EXPIRY_DAYS,
+    },
+};
+
+use super::auth_middleware::AdminUser;
+use super::dto::registration_dto::{
+    CreatedInviteDTO, InviteDTO, NewInviteDTO, RegistrationPolicyDTO, RegistrationStatusDTO,
+};
+
+/// Whether the server still needs its first account and how registration works (/registration)
+pub async fn get_status() -> impl IntoResponse {
+    let service = RegistrationService::new().await;
+    match (service.setup_required().await, service.policy().await) {
+        (Ok(setup_required), Ok(policy)) => (
+            StatusCode::OK,
+            Json(RegistrationStatusDTO {
+                setup_required,
+                registration: policy.as_str().to_string(),
+            }),
+        )
+            .into_response(),
+        (Err(e), _) | (_, Err(e)) => {
+            eprintln!("Failed to read registration status: {}", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, "Database error").into_response()
+        }
+    }
+}
+
+/// Sets the registration policy, admins only (PUT /registration)
+pub async fn set_policy(
+    _admin: AdminUser,
+    Json(payload): Json<RegistrationPolicyDTO>,
+) -> impl IntoResponse {
+    let Some(policy) = RegistrationPolicy::parse(&payload.registration) else {
+        return (
+            StatusCode::BAD_REQUEST,
+            "Registration must be open, closed or invite_only",
+        )
+            .into_response();
+    };
+    let service = RegistrationService::new().await;
+    match service.set_policy(policy).await {
+        Ok(()) => (
+            StatusCode::OK,
+            Json(RegistrationPolicyDTO {
+                registration: policy.as_str().to_string(),
+            }),
+        )
+            .into_response(),
+        Err(e) => {
+            eprintln!("Failed to set registration policy: {}", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, "Database error").into_response()
+        }
+    }
+}
+
+pub async fn create_invite(
+    admin: AdminUser,
+    Json(payload): Json<NewInviteDTO>,
+) -> impl IntoResponse {
+    let role = match payload.role.as_deref().map(Role::parse) {
+        None => Role::User,
+        Some(Some(role)) => role,
+        Some(None) => return (StatusCode::BAD_REQUEST, "Unknown role").into_response(),
+    };
+
+    let service = RegistrationService::new().await;
+    match service
+        .create_invite(admin.id, role, payload.expires_in_days)
+        .await
+    {
+        Ok(InviteCreation::Created(created)) => (
+            StatusCode::CREATED,
+            Json(CreatedInviteDTO {
+                code: created.code,
+                invite: InviteDTO::from(created.invite),
+            }),
+        )
+            .into_response(),
+        Ok(InviteCreation::InvalidExpiry) => (
+            StatusCode::BAD_REQUEST,
+            format!("expires_in_days must be between 1 and {}", MAX_EXPIRY_DAYS),
+        )
+            .into_response(),
+        Err(e) => {
+            eprintln!("Failed to create invite: {}", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, "Database error").into_response()
+        }
+    }
+}
+
+/// Invites that haven't been used or expired, admins only
+pub async fn list_invites(_admin: AdminUser) -> impl IntoResponse {
+    let service = RegistrationService::new().await;
+    match service.list_invites().await {
+        Ok(invites) => {
+            let dtos: Vec<InviteDTO> = invites.into_iter().map(InviteDTO::from).collect();
+            (StatusCode::OK, Json(dtos)).into_response()
+        }
+        Err(e) => {
+            eprintln!("Failed to list invites: {}", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, "Database error").into_response()
+        }
+    }
+}
+
+pub async fn revoke_invite(_admin: AdminUser, Path(id): Path<i32>) -> impl IntoResponse {
+    let service = RegistrationService::new().await;
+    match service.revoke_invite(id).await {
+        Ok(true) => (StatusCode::OK, "Invite revoked").into_response(),
+        Ok(false) => (StatusCode::NOT_FOUND, "Invite not found").into_response(),
+        Err(e) => {
+            eprintln!("Failed to revoke invite: {}", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, "Database error").into_response()
+        }
+    }
+}
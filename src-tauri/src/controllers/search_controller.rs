@@ -1,6 +1,11 @@
 use crate::{
-    data::repos::implementors::{author_repo::AuthorRepo, book_repo::BookRepo},
+    controllers::auth_middleware::AuthUser,
+    data::repos::implementors::{
+        author_repo::AuthorRepo,
+        book_repo::{BookRepo, BookScope},
+    },
     data::repos::traits::repository::Repository,
+    services::library_access_service::LibraryAccessService,
 };
 use axum::{extract::Query, http::StatusCode, response::IntoResponse, Json};
 use serde::{Deserialize, Serialize};
@@ -25,8 +30,26 @@ pub struct SearchBookDTO {
     pub cover_image_path: Option<String>,
 }
 
-pub async fn search_books(Query(params): Query<SearchQuery>) -> impl IntoResponse {
+/// The libraries the user can see, `None` if that couldn't be looked up
+async fn user_scope(user_id: i32) -> Option<BookScope> {
+    match LibraryAccessService::new().await.scope(user_id).await {
+        Ok(scope) => Some(scope),
+        Err(e) => {
+            eprintln!("Failed to look up library access: {}", e);
+            None
+        }
+    }
+}
+
+pub async fn search_books(user: AuthUser, Query(params): Query<SearchQuery>) -> impl IntoResponse {
     let book_repo = BookRepo::new().await;
+    let Some(scope) = user_scope(user.id).await else {
+        return (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(Vec::<SearchBookDTO>::new()),
+        )
+            .into_response();
+    };
 
     // If general query is provided, search across all fields
     if let Some(query) = params.q {
@@ -34,6 +57,7 @@ pub async fn search_books(Query(params): Query<SearchQuery>) -> impl IntoRespons
             Ok(Some(books)) => {
                 let dtos: Vec<SearchBookDTO> = books
                     .into_iter()
+                    .filter(|b| scope.contains(b))
                     .map(|b| SearchBookDTO {
                         book_id: b.book_id,
                         title: b.title,
@@ -63,6 +87,7 @@ pub async fn search_books(Query(params): Query<SearchQuery>) -> impl IntoRespons
             Ok(Some(books)) => {
                 let dtos: Vec<SearchBookDTO> = books
                     .into_iter()
+                    .filter(|b| scope.contains(b))
                     .map(|b| SearchBookDTO {
                         book_id: b.book_id,
                         title: b.title,
@@ -92,6 +117,7 @@ pub async fn search_books(Query(params): Query<SearchQuery>) -> impl IntoRespons
             Ok(Some(books)) => {
                 let dtos: Vec<SearchBookDTO> = books
                     .into_iter()
+                    .filter(|b| scope.contains(b))
                     .map(|b| SearchBookDTO {
                         book_id: b.book_id,
                         title: b.title,
@@ -125,13 +151,44 @@ pub struct AuthorDTO {
     pub name: String,
 }
 
-pub async fn search_authors(Query(params): Query<SearchQuery>) -> impl IntoResponse {
+pub async fn search_authors(
+    user: AuthUser,
+    Query(params): Query<SearchQuery>,
+) -> impl IntoResponse {
     if let Some(query) = params.author.or(params.q) {
+        // Only authors of books the user can see are found
+        let visible = match user_scope(user.id).await {
+            Some(BookScope::All) => None,
+            Some(scope) => match BookRepo::new().await.author_ids(&scope).await {
+                Ok(ids) => Some(ids),
+                Err(e) => {
+                    eprintln!("Failed to look up visible authors: {}", e);
+                    return (
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                        Json(Vec::<AuthorDTO>::new()),
+                    )
+                        .into_response();
+                }
+            },
+            None => {
+                return (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Json(Vec::<AuthorDTO>::new()),
+                )
+                    .into_response();
+            }
+        };
+
         let author_repo = AuthorRepo::new().await;
         match author_repo.search_by_name(&query).await {
             Ok(Some(authors)) => {
                 let dtos: Vec<AuthorDTO> = authors
                     .into_iter()
+                    .filter(|a| {
+                        visible
+                            .as_ref()
+                            .is_none_or(|ids| ids.contains(&a.author_id))
+                    })
                     .map(|a| AuthorDTO {
                         author_id: a.author_id,
                         name: a.name,
@@ -155,12 +212,21 @@ pub async fn search_authors(Query(params): Query<SearchQuery>) -> impl IntoRespo
     (StatusCode::BAD_REQUEST, Json(Vec::<AuthorDTO>::new())).into_response()
 }
 
-pub async fn list_all_books() -> impl IntoResponse {
+pub async fn list_all_books(user: AuthUser) -> impl IntoResponse {
     let book_repo = BookRepo::new().await;
+    let Some(scope) = user_scope(user.id).await else {
+        return (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(Vec::<SearchBookDTO>::new()),
+        )
+            .into_response();
+    };
+
     match book_repo.get_all().await {
         Ok(Some(books)) => {
             let dtos: Vec<SearchBookDTO> = books
                 .into_iter()
+                .filter(|b| scope.contains(b))
                 .map(|b| SearchBookDTO {
                     book_id: b.book_id,
                     title: b.title,
@@ -0,0 +1,73 @@
+use axum::{extract::Path, http::StatusCode, response::IntoResponse, Json};
+
+use crate::{
+    data::models::audit_events::AuditAction,
+    services::{
+        audit_service::{AuditEntry, AuditService, RequestOrigin},
+        session_service::SessionService,
+    },
+};
+
+use super::auth_middleware::LoginUser;
+use super::dto::session_dto::SessionDTO;
+
+pub async fn list_sessions(user: LoginUser) -> impl IntoResponse {
+    let service = SessionService::new().await;
+    match service.list(user.id).await {
+        Ok(sessions) => {
+            let dtos: Vec<SessionDTO> = sessions.into_iter().map(SessionDTO::from).collect();
+            (StatusCode::OK, Json(dtos)).into_response()
+        }
+        Err(e) => {
+            eprintln!("Failed to list sessions: {}", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, "Database error").into_response()
+        }
+    }
+}
+
+pub async fn revoke_session(
+    user: LoginUser,
+    origin: RequestOrigin,
+    Path(id): Path<i32>,
+) -> impl IntoResponse {
+    let service = SessionService::new().await;
+    match service.revoke(user.id, id).await {
+        Ok(true) => {
+            AuditService::new()
+                .await
+                .record(
+                    &origin,
+                    AuditAction::SessionRevoked,
+                    AuditEntry::by(user.id).target("session", id),
+                )
+                .await;
+            (StatusCode::OK, "Session revoked").into_response()
+        }
+        Ok(false) => (StatusCode::NOT_FOUND, "Session not found").into_response(),
+        Err(e) => {
+            eprintln!("Failed to revoke session: {}", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, "Database error").into_response()
+        }
+    }
+}
+
+pub async fn revoke_all_sessions(user: LoginUser, origin: RequestOrigin) -> impl IntoResponse {
+    let service = SessionService::new().await;
+    match service.revoke_all(user.id).await {
+        Ok(()) => {
+            AuditService::new()
+                .await
+                .record(
+                    &origin,
+                    AuditAction::SessionRevoked,
+                    AuditEntry::by(user.id).details("all sessions"),
+                )
+                .await;
+            (StatusCode::OK, "All sessions revoked").into_response()
+        }
+        Err(e) => {
+            eprintln!("Failed to revoke sessions: {}", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, "Database error").into_response()
+        }
+    }
+}
@@ -0,0 +1,167 @@
+use axum::{
+    extract::Path,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+
+use crate::{
+    controllers::{
+        auth_middleware::{throttled_response, AdminUser, LoginUser},
+        dto::two_factor_dto::*,
+    },
+    data::models::audit_events::AuditAction,
+    services::{
+        audit_service::{AuditEntry, AuditService, RequestOrigin},
+        two_factor_service::{TwoFactorChange, TwoFactorError, TwoFactorService},
+    },
+};
+
+/// Answers a [`TwoFactorChange`], `done` builds the response when it succeeded
+fn change_response<T>(
+    result: Result<TwoFactorChange<T>, TwoFactorError>,
+    done: impl FnOnce(T) -> Response,
+) -> Response {
+    match result {
+        Ok(TwoFactorChange::Done(value)) => done(value),
+        Ok(TwoFactorChange::NotEnrolled) => (
+            StatusCode::CONFLICT,
+            "No enrollment is waiting for confirmation",
+        )
+            .into_response(),
+        Ok(TwoFactorChange::AlreadyEnabled) => (
+            StatusCode::CONFLICT,
+            "Two-factor authentication is already enabled",
+        )
+            .into_response(),
+        Ok(TwoFactorChange::NotEnabled) => (
+            StatusCode::CONFLICT,
+            "Two-factor authentication isn't enabled",
+        )
+            .into_response(),
+        Ok(TwoFactorChange::InvalidCode) => {
+            (StatusCode::UNAUTHORIZED, "Invalid code").into_response()
+        }
+        Ok(TwoFactorChange::Throttled(throttled)) => throttled_response(throttled),
+        Err(e) => {
+            eprintln!("Failed to change two-factor authentication: {}", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, "Database error").into_response()
+        }
+    }
+}
+
+/// Records `action` if the change went through
+async fn record_change<T>(
+    result: &Result<TwoFactorChange<T>, TwoFactorError>,
+    origin: &RequestOrigin,
+    action: AuditAction,
+    user_id: i32,
+) {
+    if matches!(result, Ok(TwoFactorChange::Done(_))) {
+        AuditService::new()
+            .await
+            .record(
+                origin,
+                action,
+                AuditEntry::by(user_id).target("user", user_id),
+            )
+            .await;
+    }
+}
+
+fn recovery_codes(codes: Vec<String>) -> Response {
+    (
+        StatusCode::OK,
+        Json(RecoveryCodesDTO {
+            recovery_codes: codes,
+        }),
+    )
+        .into_response()
+}
+
+pub async fn get_status(user: LoginUser) -> impl IntoResponse {
+    let service = TwoFactorService::new().await;
+    match service.status(user.id).await {
+        Ok(status) => (StatusCode::OK, Json(TwoFactorStatusDTO::from(status))).into_response(),
+        Err(e) => {
+            eprintln!("Failed to load two-factor status: {}", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, "Database error").into_response()
+        }
+    }
+}
+
+/// Generates a secret, which takes effect once [`confirm`] is given a code from it
+pub async fn enroll(user: LoginUser) -> impl IntoResponse {
+    let service = TwoFactorService::new().await;
+    change_response(service.enroll(user.id).await, |enrollment| {
+        (StatusCode::OK, Json(EnrollmentDTO::from(enrollment))).into_response()
+    })
+}
+
+pub async fn confirm(
+    user: LoginUser,
+    origin: RequestOrigin,
+    Json(payload): Json<TwoFactorCodeDTO>,
+) -> impl IntoResponse {
+    let service = TwoFactorService::new().await;
+    let result = service.confirm(user.id, &payload.code).await;
+    record_change(&result, &origin, AuditAction::TwoFactorEnabled, user.id).await;
+    change_response(result, recovery_codes)
+}
+
+pub async fn disable(
+    user: LoginUser,
+    origin: RequestOrigin,
+    Json(payload): Json<TwoFactorCodeDTO>,
+) -> impl IntoResponse {
+    let service = TwoFactorService::new().await;
+    let result = service.disable(user.id, &payload.code).await;
+    record_change(&result, &origin, AuditAction::TwoFactorDisabled, user.id).await;
+    change_response(result, |()| StatusCode::NO_CONTENT.into_response())
+}
+
+pub async fn regenerate_recovery_codes(
+    user: LoginUser,
+    Json(payload): Json<TwoFactorCodeDTO>,
+) -> impl IntoResponse {
+    let service = TwoFactorService::new().await;
+    change_response(
+        service
+            .regenerate_recovery_codes(user.id, &payload.code)
+            .await,
+        recovery_codes,
+    )
+}
+
+/// Turns two-factor authentication off for a user who lost their authenticator
+pub async fn admin_reset(
+    admin: AdminUser,
+    origin: RequestOrigin,
+    Path(id): Path<i32>,
+) -> impl IntoResponse {
+    let service = TwoFactorService::new().await;
+    match service.reset(id).await {
+        Ok(true) => {
+            AuditService::new()
+                .await
+                .record(
+                    &origin,
+                    AuditAction::TwoFactorDisabled,
+                    AuditEntry::by(admin.id)
+                        .target("user", id)
+                        .details("reset by an admin"),
+                )
+                .await;
+            StatusCode::NO_CONTENT.into_response()
+        }
+        Ok(false) => (
+            StatusCode::NOT_FOUND,
+            "Two-factor authentication isn't enabled",
+        )
+            .into_response(),
+        Err(e) => {
+            eprintln!("Failed to reset two-factor authentication: {}", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, "Database error").into_response()
+        }
+    }
+}
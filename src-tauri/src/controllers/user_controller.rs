@@ -1,74 +1,79 @@
 use axum::{
     body::Body,
+    extract::Path,
     http::StatusCode,
     response::{IntoResponse, Response},
     Json,
 };
 
 use crate::{
-    controllers::{auth_middleware::AuthUser, dto::user_dto::*},
-    data::repos::implementors::user_repo::UserRepo,
+    controllers::{
+        auth_middleware::{AdminUser, AuthUser, LoginUser},
+        dto::user_dto::*,
+    },
+    data::{
+        models::{audit_events::AuditAction, users::Role},
+        repos::implementors::user_repo::UserRepo,
+    },
+    services::{
+        audit_service::{AuditEntry, AuditService, RequestOrigin},
+        registration_service::{Registration, RegistrationService},
+        user_service::{UserChange, UserEdit, UserService, UserServiceError},
+    },
 };
 use crate::{
-    data::repos::traits::repository::Repository,
-    services::authentication_service::AuthenticationService,
+    data::repos::traits::repository::Repository, services::throttle_service::LoginThrottle,
 };
-// TODO: Test this endpoint
-/// Endpoint to register a new user (/register)
-/// Password is hashed using Argon2 before storing in the database
-pub async fn create_user(Json(user): Json<NewUserDTO>) -> impl IntoResponse {
-    let auth = AuthenticationService::new();
-    let repo = UserRepo::new().await;
-
-    use crate::data::models::users::NewUser;
-    let hashed_password = match auth.hash_password(&user.password) {
-        Ok(h) => h,
-        Err(e) => {
-            eprintln!("Error hashing password: {}", e);
-            return Response::builder()
-                .status(StatusCode::INTERNAL_SERVER_ERROR)
-                .body(Body::from("Failed to process password"))
-                .unwrap();
+/// Endpoint to register a new user (/register). The first account of a new server
+/// becomes its admin, after that the registration policy applies.
+pub async fn create_user(origin: RequestOrigin, Json(user): Json<NewUserDTO>) -> impl IntoResponse {
+    let service = RegistrationService::new().await;
+    match service
+        .register(
+            &user.username,
+            &user.email,
+            &user.password,
+            user.invite_code.as_deref(),
+        )
+        .await
+    {
+        Ok(Registration::Created(created)) => {
+            let details = if user.invite_code.is_some() {
+                "registered with an invite"
+            } else {
+                "registered"
+            };
+            AuditService::new()
+                .await
+                .record(
+                    &origin,
+                    AuditAction::UserCreated,
+                    AuditEntry::by(created.user_id)
+                        .target("user", created.user_id)
+                        .details(details),
+                )
+                .await;
+            (StatusCode::CREATED, Json(UserDTO::from(*created))).into_response()
         }
-    };
-
-    let new_user = NewUser {
-        username: &user.username,
-        email: &user.email,
-        role: user.role.as_deref(),
-        password_hash: &hashed_password,
-    };
-
-    match repo.add(new_user).await {
-        Ok(_) => (),
+        Ok(Registration::Closed) => {
+            (StatusCode::FORBIDDEN, "Registration is closed").into_response()
+        }
+        Ok(Registration::InvalidInvite) => {
+            (StatusCode::FORBIDDEN, "A valid invite code is required").into_response()
+        }
+        Ok(Registration::Rejected(change)) => change_response(Ok(change), StatusCode::CREATED),
         Err(e) => {
             eprintln!("Error creating user: {}", e);
-            return Response::builder()
-                .status(StatusCode::INTERNAL_SERVER_ERROR)
-                .body(Body::from("Failed to create user"))
-                .unwrap();
+            (StatusCode::INTERNAL_SERVER_ERROR, "Failed to create user").into_response()
         }
     }
-
-    return Response::builder()
-        .status(StatusCode::CREATED)
-        .body(Body::from("User created"))
-        .unwrap();
 }
 
-/// List all users - for testing purposes
-pub async fn list_users() -> Json<Vec<UserDTO>> {
+/// List all users, admins only
+pub async fn list_users(_user: AdminUser) -> Json<Vec<UserDTO>> {
     let repo = UserRepo::new().await;
     let users = match repo.get_all().await {
-        Ok(Some(user_list)) => user_list
-            .into_iter()
-            .map(|u| UserDTO {
-                username: u.username,
-                email: u.email,
-                role: u.role,
-                created_at: u.created_at,
-            })
-            .collect(),
+        Ok(Some(user_list)) => user_list.into_iter().map(UserDTO::from).collect(),
         Ok(None) => vec![],
         Err(e) => {
             eprintln!("Error fetching users: {}", e);
@@ -83,12 +88,7 @@ pub async fn get_user(user: AuthUser) -> impl IntoResponse {
     let repo = UserRepo::new().await;
     return match repo.get_by_id(user.id).await {
         Ok(Some(user)) => {
-            let user_response = UserDTO {
-                username: user.username,
-                email: user.email,
-                role: user.role,
-                created_at: user.created_at,
-            };
+            let user_response = UserDTO::from(user);
             Response::builder()
                 .status(StatusCode::OK)
                 .header("Content-Type", "application/json")
@@ -110,3 +110,245 @@ pub async fn get_user(user: AuthUser) -> impl IntoResponse {
         }
     };
 }
+
+/// Lifts a login lockout of a user, admins only (/users/{id}/unlock)
+pub async fn unlock_user(
+    admin: AdminUser,
+    origin: RequestOrigin,
+    Path(id): Path<i32>,
+) -> impl IntoResponse {
+    let repo = UserRepo::new().await;
+    match repo.get_by_id(id).await {
+        Ok(Some(user)) => {
+            LoginThrottle::global().unlock(&user.username);
+            AuditService::new()
+                .await
+                .record(
+                    &origin,
+                    AuditAction::UserUnlocked,
+                    AuditEntry::by(admin.id).target("user", id),
+                )
+                .await;
+            (StatusCode::OK, "Account unlocked").into_response()
+        }
+        Ok(None) => (StatusCode::NOT_FOUND, "User not found").into_response(),
+        Err(e) => {
+            eprintln!("Error fetching user: {}", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, "Failed to fetch user").into_response()
+        }
+    }
+}
+
+/// Answers a [`UserChange`], `status` is used when it succeeded
+fn change_response(result: Result<UserChange, UserServiceError>, status: StatusCode) -> Response {
+    match result {
+        Ok(UserChange::Done(user)) => (status, Json(UserDTO::from(*user))).into_response(),
+        Ok(UserChange::NotFound) => (StatusCode::NOT_FOUND, "User not found").into_response(),
+        Ok(UserChange::Invalid(message)) => (StatusCode::BAD_REQUEST, message).into_response(),
+        Ok(UserChange::Conflict(message)) => (StatusCode::CONFLICT, message).into_response(),
+        Ok(UserChange::LastAdmin) => (
+            StatusCode::CONFLICT,
+            "The last active admin can't be demoted, disabled or deleted",
+        )
+            .into_response(),
+        Err(e) => {
+            eprintln!("Error changing user: {}", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, "Failed to change user").into_response()
+        }
+    }
+}
+
+/// Records `action` if the change went through
+async fn record_change(
+    result: &Result<UserChange, UserServiceError>,
+    origin: &RequestOrigin,
+    action: AuditAction,
+    entry: AuditEntry,
+) {
+    if matches!(result, Ok(UserChange::Done(_))) {
+        AuditService::new()
+            .await
+            .record(origin, action, entry)
+            .await;
+    }
+}
+
+/// `Err` for a role name that doesn't exist
+fn parse_role(role: Option<&str>) -> Result<Option<Role>, ()> {
+    match role {
+        None => Ok(None),
+        Some(value) => Role::parse(value).map(Some).ok_or(()),
+    }
+}
+
+fn unknown_role() -> Response {
+    (StatusCode::BAD_REQUEST, "Unknown role").into_response()
+}
+
+/// Creates an account with any role, admins only (POST /users)
+pub async fn admin_create_user(
+    admin: AdminUser,
+    origin: RequestOrigin,
+    Json(user): Json<NewUserDTO>,
+) -> impl IntoResponse {
+    let role = match parse_role(user.role.as_deref()) {
+        Ok(role) => role.unwrap_or(Role::User),
+        Err(()) => return unknown_role(),
+    };
+    let service = UserService::new().await;
+    let result = service
+        .create(&user.username, &user.email, &user.password, role)
+        .await;
+    if let Ok(UserChange::Done(created)) = &result {
+        AuditService::new()
+            .await
+            .record(
+                &origin,
+                AuditAction::UserCreated,
+                AuditEntry::by(admin.id)
+                    .target("user", created.user_id)
+                    .details(format!("role: {}", role.as_str())),
+            )
+            .await;
+    }
+    change_response(result, StatusCode::CREATED)
+}
+
+/// Changes username, email or role of an account, admins only (PUT /users/{id})
+pub async fn update_user(
+    admin: AdminUser,
+    origin: RequestOrigin,
+    Path(id): Path<i32>,
+    Json(edit): Json<UpdateUserDTO>,
+) -> impl IntoResponse {
+    let role = match parse_role(edit.role.as_deref()) {
+        Ok(role) => role,
+        Err(()) => return unknown_role(),
+    };
+    // Read before the update so a role change can be told apart
+    let previous = match UserRepo::new().await.get_by_id(id).await {
+        Ok(user) => user.map(|user| user.role()),
+        Err(e) => {
+            eprintln!("Error fetching user: {}", e);
+            None
+        }
+    };
+    let service = UserService::new().await;
+    let result = service
+        .update(
+            id,
+            UserEdit {
+                username: edit.username.as_deref(),
+                email: edit.email.as_deref(),
+                role,
+            },
+        )
+        .await;
+    if edit.username.is_some() || edit.email.is_some() {
+        record_change(
+            &result,
+            &origin,
+            AuditAction::UserUpdated,
+            AuditEntry::by(admin.id).target("user", id),
+        )
+        .await;
+    }
+    if let (Some(from), Some(to)) = (previous, role) {
+        if from != to {
+            record_change(
+                &result,
+                &origin,
+                AuditAction::RoleChanged,
+                AuditEntry::by(admin.id).target("user", id).details(format!(
+                    "{} -> {}",
+                    from.as_str(),
+                    to.as_str()
+                )),
+            )
+            .await;
+        }
+    }
+    change_response(result, StatusCode::OK)
+}
+
+/// Disables an account and signs it out everywhere, admins only (/users/{id}/disable)
+pub async fn disable_user(
+    admin: AdminUser,
+    origin: RequestOrigin,
+    Path(id): Path<i32>,
+) -> impl IntoResponse {
+    let service = UserService::new().await;
+    let result = service.set_disabled(id, true).await;
+    record_change(
+        &result,
+        &origin,
+        AuditAction::UserDisabled,
+        AuditEntry::by(admin.id).target("user", id),
+    )
+    .await;
+    change_response(result, StatusCode::OK)
+}
+
+/// Re-enables a disabled account, admins only (/users/{id}/enable)
+pub async fn enable_user(
+    admin: AdminUser,
+    origin: RequestOrigin,
+    Path(id): Path<i32>,
+) -> impl IntoResponse {
+    let service = UserService::new().await;
+    let result = service.set_disabled(id, false).await;
+    record_change(
+        &result,
+        &origin,
+        AuditAction::UserEnabled,
+        AuditEntry::by(admin.id).target("user", id),
+    )
+    .await;
+    change_response(result, StatusCode::OK)
+}
+
+/// Deletes an account with its personal data, admins only (DELETE /users/{id}).
+/// See `UserRepo::delete` for what is kept.
+pub async fn delete_user(
+    admin: AdminUser,
+    origin: RequestOrigin,
+    Path(id): Path<i32>,
+) -> impl IntoResponse {
+    let service = UserService::new().await;
+    match service.delete(id).await {
+        Ok(UserChange::Done(user)) => {
+            AuditService::new()
+                .await
+                .record(
+                    &origin,
+                    AuditAction::UserDeleted,
+                    AuditEntry::by(admin.id)
+                        .target("user", id)
+                        .details(format!("username: {}", user.username)),
+                )
+                .await;
+            StatusCode::NO_CONTENT.into_response()
+        }
+        result => change_response(result, StatusCode::OK),
+    }
+}
+
+/// Changes the signed in user's own username or email (PUT /user)
+pub async fn update_profile(
+    user: LoginUser,
+    origin: RequestOrigin,
+    Json(edit): Json<UpdateProfileDTO>,
+) -> impl IntoResponse {
+    let service = UserService::new().await;
+    let result = service
+        .update_profile(user.id, edit.username.as_deref(), edit.email.as_deref())
+        .await;
+    record_change(
+        &result,
+        &origin,
+        AuditAction::UserUpdated,
+        AuditEntry::by(user.id).target("user", user.id),
+    )
+    .await;
+    change_response(result, StatusCode::OK)
+}
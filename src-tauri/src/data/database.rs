@@ -1,23 +1,24 @@
-use std::{
-    env,
-    sync::{
-        atomic::{AtomicBool, Ordering},
-        Arc,
-    },
-};
+use std::{env, sync::Arc};
 
-use diesel::SqliteConnection;
+use diesel::{
+    result::{DatabaseErrorKind, Error},
+    SqliteConnection,
+};
 use diesel_async::{
     pooled_connection::{
         deadpool::{Hook, HookError, Object, Pool, PoolError},
         AsyncDieselConnectionManager,
     },
+    scoped_futures::ScopedBoxFuture,
     sync_connection_wrapper::SyncConnectionWrapper,
-    SimpleAsyncConnection,
+    AsyncConnection, SimpleAsyncConnection,
 };
 use dotenvy::dotenv;
 use once_cell::sync::Lazy;
-use tokio::sync::Mutex;
+use tokio::sync::{Mutex, MutexGuard};
+
+/// Connection handed out by the pool, the `_in` methods of the repos take it
+pub type DbConnection = SyncConnectionWrapper<SqliteConnection>;
 
 /// Returns a connection from the database connection pool
 ///
@@ -57,39 +58,64 @@ static DB_POOL: Lazy<Pool<SyncConnectionWrapper<SqliteConnection>>> = Lazy::new(
     Pool::builder(config)
         .post_create(Hook::async_fn(
             |conn: &mut SyncConnectionWrapper<SqliteConnection>, _meta| {
+                // Apart from journal_mode the pragmas only apply to the connection they
+                // are set on, every pooled connection needs them
                 Box::pin(async move {
-                    if !PRAGMAS_SET.load(Ordering::Relaxed) {
-                        let result = conn
-                            .batch_execute(
-                                "
-                                PRAGMA foreign_keys = ON;
-                                PRAGMA journal_mode = WAL;
-                                PRAGMA synchronous = NORMAL;
-                                PRAGMA mmap_size = 30000000000;
-                            ",
-                            )
-                            .await;
-                        if let Ok(_) = result {
-                            PRAGMAS_SET.store(true, Ordering::Relaxed);
-                        }
-
-                        result.map_err(|e| {
-                            HookError::Message(format!("Failed to set SQLite pragmas: {e}").into())
-                        })
-                    } else {
-                        Ok(())
-                    }
+                    conn.batch_execute(
+                        "
+                        PRAGMA foreign_keys = ON;
+                        PRAGMA journal_mode = WAL;
+                        PRAGMA synchronous = NORMAL;
+                        PRAGMA mmap_size = 30000000000;
+                    ",
+                    )
+                    .await
+                    .map_err(|e| {
+                        HookError::Message(format!("Failed to set SQLite pragmas: {e}").into())
+                    })
                 })
             },
         ))
         .build()
         .expect("Failed to create SQLite connection pool")
 });
-// Indicates whether the SQLite pragmas have been set
-static PRAGMAS_SET: AtomicBool = AtomicBool::new(false);
 
 pub fn lock_db() -> Arc<Mutex<()>> {
     return DB_LOCK.clone();
 }
 
 static DB_LOCK: Lazy<Arc<Mutex<()>>> = Lazy::new(|| Arc::new(Mutex::new(())));
+
+/// Runs writes of several repos in one transaction, either all of them are stored or none.
+/// Only the `_in` methods may be used inside, the others would wait for the lock held here.
+///
+/// # Usage
+///
+/// ```rust,ignore
+/// write_transaction(|conn| {
+///     async move {
+///         let author = AuthorRepo::new().await.get_or_create_in(conn, "Ursula K. Le Guin").await?;
+///         BookAuthorRepo::new().await.replace_authors_in(conn, book_id, &[author.author_id]).await
+///     }
+///     .scope_boxed()
+/// })
+/// .await?;
+/// ```
+pub async fn write_transaction<'a, R, E, F>(work: F) -> Result<R, E>
+where
+    F: for<'r> FnOnce(&'r mut DbConnection) -> ScopedBoxFuture<'a, 'r, Result<R, E>> + Send + 'a,
+    E: From<Error> + Send + 'a,
+    R: Send + 'a,
+{
+    let mut conn = connect_from_pool().await.map_err(|e| {
+        Error::DatabaseError(
+            DatabaseErrorKind::UnableToSendCommand,
+            Box::new(e.to_string()),
+        )
+    })?;
+
+    let db_lock = lock_db();
+    let _guard: MutexGuard<()> = db_lock.lock().await;
+
+    DbConnection::transaction(&mut conn, work).await
+}
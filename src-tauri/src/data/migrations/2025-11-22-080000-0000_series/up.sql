@@ -0,0 +1,5 @@
+CREATE TABLE series (
+    series_id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    name TEXT NOT NULL UNIQUE,
+    sort_name TEXT
+);
@@ -0,0 +1,4 @@
+CREATE TABLE tags (
+    tag_id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    name TEXT NOT NULL UNIQUE
+);
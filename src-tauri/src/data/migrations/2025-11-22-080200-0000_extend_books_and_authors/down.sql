@@ -0,0 +1,11 @@
+DROP INDEX IF EXISTS idx_books_library;
+DROP INDEX IF EXISTS idx_books_series;
+
+ALTER TABLE books DROP COLUMN library_id;
+ALTER TABLE books DROP COLUMN rating;
+ALTER TABLE books DROP COLUMN series_index;
+ALTER TABLE books DROP COLUMN series_id;
+ALTER TABLE books DROP COLUMN language;
+ALTER TABLE books DROP COLUMN description;
+
+ALTER TABLE authors DROP COLUMN sort_name;
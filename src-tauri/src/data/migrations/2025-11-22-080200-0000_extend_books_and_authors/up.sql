@@ -0,0 +1,12 @@
+ALTER TABLE authors ADD COLUMN sort_name TEXT;
+
+ALTER TABLE books ADD COLUMN description TEXT;
+ALTER TABLE books ADD COLUMN language TEXT;
+ALTER TABLE books ADD COLUMN series_id INTEGER REFERENCES series(series_id) ON DELETE SET NULL;
+ALTER TABLE books ADD COLUMN series_index REAL;
+-- Rating on Calibre's scale: 0 to 10, two points per star
+ALTER TABLE books ADD COLUMN rating INTEGER;
+ALTER TABLE books ADD COLUMN library_id INTEGER REFERENCES libraries(library_id) ON DELETE SET NULL;
+
+CREATE INDEX idx_books_series ON books(series_id);
+CREATE INDEX idx_books_library ON books(library_id);
@@ -0,0 +1,8 @@
+CREATE TABLE book_identifiers (
+    identifier_id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    book_id INTEGER NOT NULL,
+    scheme TEXT NOT NULL,
+    value TEXT NOT NULL,
+    FOREIGN KEY (book_id) REFERENCES books(book_id) ON DELETE CASCADE,
+    UNIQUE(book_id, scheme)
+);
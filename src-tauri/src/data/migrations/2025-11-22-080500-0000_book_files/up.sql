@@ -0,0 +1,11 @@
+CREATE TABLE book_files (
+    file_id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    book_id INTEGER NOT NULL,
+    format TEXT NOT NULL,
+    file_path TEXT NOT NULL UNIQUE,
+    file_size BIGINT,
+    added_at TEXT DEFAULT (datetime('now')),
+    FOREIGN KEY (book_id) REFERENCES books(book_id) ON DELETE CASCADE
+);
+
+CREATE INDEX idx_book_files_book ON book_files(book_id);
@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+-- The original formatting of the ISBNs is not kept, so there is nothing to undo
+SELECT 1;
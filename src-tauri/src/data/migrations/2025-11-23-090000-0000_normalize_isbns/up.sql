@@ -0,0 +1,7 @@
+-- Strip the `urn:isbn:` prefix, hyphens and spaces from ISBNs stored before normalization.
+-- Check digits can't be validated in SQL, search still matches the ISBN-10 form of a book.
+UPDATE books
+SET isbn = UPPER(REPLACE(REPLACE(REPLACE(LOWER(TRIM(isbn)), 'urn:isbn:', ''), '-', ''), ' ', ''))
+WHERE isbn IS NOT NULL;
+
+UPDATE books SET isbn = NULL WHERE isbn = '';
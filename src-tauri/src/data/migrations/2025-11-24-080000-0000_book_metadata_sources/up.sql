@@ -0,0 +1,9 @@
+-- Where the stored value of a metadata field came from:
+-- 'embedded' (the file itself), 'path' (inferred from the file path) or 'default' (fallback)
+CREATE TABLE book_metadata_sources (
+    book_id INTEGER NOT NULL,
+    field TEXT NOT NULL,
+    source TEXT NOT NULL,
+    PRIMARY KEY (book_id, field),
+    FOREIGN KEY (book_id) REFERENCES books(book_id) ON DELETE CASCADE
+);
@@ -0,0 +1,20 @@
+-- Every change made to the metadata of a book.
+-- Changes made together (one scan, one provider match, one edit) share a batch_id.
+-- List fields (authors, tags) are stored as JSON arrays.
+CREATE TABLE metadata_history (
+    history_id INTEGER PRIMARY KEY AUTOINCREMENT,
+    book_id INTEGER NOT NULL,
+    field TEXT NOT NULL,
+    old_value TEXT,
+    new_value TEXT,
+    source TEXT NOT NULL, -- 'scan', 'user' or 'provider'
+    user_id INTEGER,
+    batch_id TEXT NOT NULL,
+    created_at TEXT DEFAULT (datetime('now')),
+    reverted_at TEXT,
+    FOREIGN KEY (book_id) REFERENCES books(book_id) ON DELETE CASCADE,
+    FOREIGN KEY (user_id) REFERENCES users(user_id) ON DELETE SET NULL
+);
+
+CREATE INDEX idx_metadata_history_book ON metadata_history(book_id);
+CREATE INDEX idx_metadata_history_batch ON metadata_history(batch_id);
@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE users ADD COLUMN refresh_token TEXT;
+DROP TABLE sessions;
@@ -0,0 +1,23 @@
+-- Refresh token sessions, replacing the single plaintext token in users.
+-- Only the SHA-256 hash of a refresh token is stored. Every refresh replaces the row
+-- with a new one of the same family, so presenting a replaced token means it was
+-- stolen or reused and the whole family is revoked.
+CREATE TABLE sessions (
+    session_id INTEGER PRIMARY KEY AUTOINCREMENT,
+    user_id INTEGER NOT NULL,
+    family_id TEXT NOT NULL,
+    token_hash TEXT NOT NULL UNIQUE,
+    device_name TEXT,
+    user_agent TEXT,
+    created_at TEXT DEFAULT (datetime('now')),
+    last_used_at TEXT DEFAULT (datetime('now')),
+    expires_at TEXT NOT NULL,
+    revoked_at TEXT,
+    replaced_by INTEGER,
+    FOREIGN KEY (user_id) REFERENCES users(user_id) ON DELETE CASCADE
+);
+
+CREATE INDEX idx_sessions_user ON sessions(user_id);
+CREATE INDEX idx_sessions_family ON sessions(family_id);
+
+ALTER TABLE users DROP COLUMN refresh_token;
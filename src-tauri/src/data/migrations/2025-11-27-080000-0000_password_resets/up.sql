@@ -0,0 +1,12 @@
+-- Single-use password reset tokens. Like sessions, only the SHA-256 hash of a token is stored.
+CREATE TABLE password_resets (
+    reset_id INTEGER PRIMARY KEY AUTOINCREMENT,
+    user_id INTEGER NOT NULL,
+    token_hash TEXT NOT NULL UNIQUE,
+    created_at TEXT DEFAULT (datetime('now')),
+    expires_at TEXT NOT NULL,
+    used_at TEXT,
+    FOREIGN KEY (user_id) REFERENCES users(user_id) ON DELETE CASCADE
+);
+
+CREATE INDEX idx_password_resets_user ON password_resets(user_id);
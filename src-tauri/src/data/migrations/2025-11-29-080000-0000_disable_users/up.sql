@@ -0,0 +1,2 @@
+-- Disabled accounts keep their data but can't sign in or use existing tokens and keys
+ALTER TABLE users ADD COLUMN disabled_at TEXT;
@@ -0,0 +1,20 @@
+-- Server-wide settings that admins change at runtime, such as the registration policy
+CREATE TABLE server_settings (
+    key TEXT PRIMARY KEY NOT NULL,
+    value TEXT NOT NULL,
+    updated_at TEXT DEFAULT (datetime('now'))
+);
+
+-- Single-use registration codes. Only the SHA-256 hash of a code is stored.
+CREATE TABLE invites (
+    invite_id INTEGER PRIMARY KEY AUTOINCREMENT,
+    code_hash TEXT NOT NULL UNIQUE,
+    role TEXT NOT NULL DEFAULT 'user',
+    created_by INTEGER,
+    created_at TEXT DEFAULT (datetime('now')),
+    expires_at TEXT NOT NULL,
+    used_at TEXT,
+    used_by INTEGER,
+    FOREIGN KEY (created_by) REFERENCES users(user_id) ON DELETE SET NULL,
+    FOREIGN KEY (used_by) REFERENCES users(user_id) ON DELETE SET NULL
+);
@@ -0,0 +1,2 @@
+ALTER TABLE sessions DROP COLUMN access_jti;
+DROP TABLE revoked_tokens;
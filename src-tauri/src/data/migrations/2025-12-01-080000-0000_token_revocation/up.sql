@@ -0,0 +1,8 @@
+-- Access tokens revoked before they expire. Rows are only needed until `expires_at`.
+CREATE TABLE revoked_tokens (
+    jti TEXT PRIMARY KEY NOT NULL,
+    expires_at TEXT NOT NULL
+);
+
+-- The `jti` of the access token last issued for a session, denied when the session ends
+ALTER TABLE sessions ADD COLUMN access_jti TEXT;
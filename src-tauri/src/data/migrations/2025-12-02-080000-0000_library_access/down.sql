@@ -0,0 +1,2 @@
+DROP TABLE library_shares;
+ALTER TABLE libraries DROP COLUMN visibility;
@@ -0,0 +1,18 @@
+-- Private libraries are only visible to their owner (`added_by`), admins and whoever
+-- they are shared with. Existing libraries stay visible to everyone.
+ALTER TABLE libraries ADD COLUMN visibility TEXT NOT NULL DEFAULT 'public';
+
+-- Grants access to a private library, either to a single user or to everyone with at least a role
+CREATE TABLE library_shares (
+    share_id INTEGER PRIMARY KEY AUTOINCREMENT,
+    library_id INTEGER NOT NULL,
+    user_id INTEGER,
+    role TEXT,
+    created_at TEXT DEFAULT (datetime('now')),
+    FOREIGN KEY (library_id) REFERENCES libraries(library_id) ON DELETE CASCADE,
+    FOREIGN KEY (user_id) REFERENCES users(user_id) ON DELETE CASCADE,
+    CHECK ((user_id IS NULL) <> (role IS NULL))
+);
+
+CREATE UNIQUE INDEX library_shares_user ON library_shares(library_id, user_id) WHERE user_id IS NOT NULL;
+CREATE UNIQUE INDEX library_shares_role ON library_shares(library_id, role) WHERE role IS NOT NULL;
@@ -0,0 +1,39 @@
+-- TOTP secrets. The secret has to be read back to check codes, so unlike tokens it
+-- can't be hashed. Until confirmed_at is set the enrollment has no effect on sign-in.
+CREATE TABLE user_totp (
+    user_id INTEGER PRIMARY KEY,
+    secret TEXT NOT NULL,
+    created_at TEXT DEFAULT (datetime('now')),
+    confirmed_at TEXT,
+    -- Time step of the last accepted code, so a code can't be used twice
+    last_step INTEGER,
+    FOREIGN KEY (user_id) REFERENCES users(user_id) ON DELETE CASCADE
+);
+
+-- Single-use recovery codes, stored as SHA-256 hashes
+CREATE TABLE recovery_codes (
+    code_id INTEGER PRIMARY KEY AUTOINCREMENT,
+    user_id INTEGER NOT NULL,
+    code_hash TEXT NOT NULL,
+    created_at TEXT DEFAULT (datetime('now')),
+    used_at TEXT,
+    FOREIGN KEY (user_id) REFERENCES users(user_id) ON DELETE CASCADE
+);
+
+CREATE INDEX idx_recovery_codes_user ON recovery_codes(user_id);
+
+-- Sign-ins waiting for a second factor. The password was right, the token handed out
+-- by /login is exchanged for a session once a code is given.
+CREATE TABLE login_challenges (
+    challenge_id INTEGER PRIMARY KEY AUTOINCREMENT,
+    user_id INTEGER NOT NULL,
+    token_hash TEXT NOT NULL UNIQUE,
+    device_name TEXT,
+    user_agent TEXT,
+    attempts INTEGER NOT NULL DEFAULT 0,
+    created_at TEXT DEFAULT (datetime('now')),
+    expires_at TEXT NOT NULL,
+    FOREIGN KEY (user_id) REFERENCES users(user_id) ON DELETE CASCADE
+);
+
+CREATE INDEX idx_login_challenges_user ON login_challenges(user_id);
@@ -0,0 +1,2 @@
+DROP TRIGGER audit_events_append_only;
+DROP TABLE audit_events;
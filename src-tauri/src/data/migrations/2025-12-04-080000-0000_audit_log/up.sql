@@ -0,0 +1,26 @@
+-- Security relevant events. Rows are never changed, they are only removed once they are
+-- older than the retention period. The actor isn't a foreign key and their name is
+-- copied, so events outlive deleted accounts.
+CREATE TABLE audit_events (
+    event_id INTEGER PRIMARY KEY AUTOINCREMENT,
+    occurred_at TEXT NOT NULL DEFAULT (datetime('now')),
+    action TEXT NOT NULL,
+    user_id INTEGER,
+    username TEXT,
+    -- What was acted on, e.g. 'user' and its id
+    target_type TEXT,
+    target_id INTEGER,
+    ip TEXT,
+    user_agent TEXT,
+    details TEXT
+);
+
+CREATE INDEX idx_audit_events_occurred ON audit_events(occurred_at);
+CREATE INDEX idx_audit_events_user ON audit_events(user_id);
+CREATE INDEX idx_audit_events_action ON audit_events(action);
+
+CREATE TRIGGER audit_events_append_only
+BEFORE UPDATE ON audit_events
+BEGIN
+    SELECT RAISE(ABORT, 'audit events are append-only');
+END;
@@ -0,0 +1 @@
+-- The backfilled libraries are correct, nothing to undo
@@ -0,0 +1,21 @@
+-- Books without a library are only visible to admins, so put books imported before
+-- `library_id` was set into the library whose root holds their file, the deepest one wins
+UPDATE books
+SET library_id = (
+    SELECT libraries.library_id
+    FROM libraries
+    WHERE substr(
+        COALESCE(books.file_path, (
+            SELECT book_files.file_path
+            FROM book_files
+            WHERE book_files.book_id = books.book_id
+            ORDER BY book_files.file_id
+            LIMIT 1
+        )),
+        1,
+        length(rtrim(libraries.path, '/')) + 1
+    ) = rtrim(libraries.path, '/') || '/'
+    ORDER BY length(rtrim(libraries.path, '/')) DESC
+    LIMIT 1
+)
+WHERE library_id IS NULL;
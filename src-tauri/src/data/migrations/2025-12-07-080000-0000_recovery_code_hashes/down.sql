@@ -0,0 +1 @@
+-- The deleted codes can't be restored, nothing to undo
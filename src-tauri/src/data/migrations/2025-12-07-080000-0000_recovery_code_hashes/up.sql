@@ -0,0 +1,3 @@
+-- Recovery codes are argon2 hashes now, the old SHA-256 ones can't be checked anymore.
+-- Users with two-factor on have to generate new codes.
+DELETE FROM recovery_codes WHERE code_hash NOT LIKE '$argon2%';
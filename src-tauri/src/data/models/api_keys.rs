@@ -0,0 +1,106 @@
+use diesel::prelude::*;
+
+use crate::data::models::schema::*;
+use crate::data::models::users::Users;
+
+/// A key a user created for a device or script, see [`ApiScope`] for what it may do
+#[derive(Queryable, Identifiable, Associations, Selectable, PartialEq, Debug, Clone)]
+#[diesel(table_name = api_keys)]
+#[diesel(primary_key(api_key_id))]
+#[diesel(belongs_to(Users, foreign_key = user_id))]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+pub struct ApiKeys {
+    pub api_key_id: i32,
+    pub user_id: i32,
+    pub name: String,
+    /// Start of the key, shown so users can tell their keys apart
+    pub prefix: String,
+    pub key_hash: String,
+    pub scopes: String,
+    pub created_at: Option<String>,
+    pub last_used_at: Option<String>,
+    pub expires_at: Option<String>,
+    pub revoked_at: Option<String>,
+}
+
+/// Part of the API a key gives access to. The owner's role still applies, so an
+/// `admin` key of a user who is no longer an admin is refused.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ApiScope {
+    /// Browse and search the catalog, including the OPDS feeds
+    Catalog,
+    /// Download books and stream their pages
+    Download,
+    /// Read and sync reading progress, bookmarks and annotations
+    Progress,
+    /// Routes reserved for admins
+    Admin,
+}
+
+impl ApiScope {
+    pub const ALL: [ApiScope; 4] = [
+        ApiScope::Catalog,
+        ApiScope::Download,
+        ApiScope::Progress,
+        ApiScope::Admin,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ApiScope::Catalog => "catalog",
+            ApiScope::Download => "download",
+            ApiScope::Progress => "progress",
+            ApiScope::Admin => "admin",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<ApiScope> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "catalog" => Some(ApiScope::Catalog),
+            "download" => Some(ApiScope::Download),
+            "progress" => Some(ApiScope::Progress),
+            "admin" => Some(ApiScope::Admin),
+            _ => None,
+        }
+    }
+
+    /// Stored form of a set of scopes
+    pub fn join(scopes: &[ApiScope]) -> String {
+        ApiScope::ALL
+            .iter()
+            .filter(|scope| scopes.contains(scope))
+            .map(|scope| scope.as_str())
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+}
+
+impl ApiKeys {
+    /// Unknown scopes are ignored
+    pub fn scopes(&self) -> Vec<ApiScope> {
+        self.scopes.split(',').filter_map(ApiScope::parse).collect()
+    }
+
+    pub fn has_scope(&self, scope: ApiScope) -> bool {
+        self.scopes().contains(&scope)
+    }
+}
+
+#[derive(Insertable, PartialEq, Debug)]
+#[diesel(table_name = api_keys)]
+pub struct NewApiKey<'a> {
+    pub user_id: i32,
+    pub name: &'a str,
+    pub prefix: &'a str,
+    pub key_hash: &'a str,
+    pub scopes: &'a str,
+    pub expires_at: Option<&'a str>,
+}
+
+#[derive(AsChangeset, PartialEq, Debug, Default)]
+#[diesel(table_name = api_keys)]
+pub struct UpdateApiKey<'a> {
+    pub name: Option<&'a str>,
+    pub last_used_at: Option<&'a str>,
+    pub revoked_at: Option<&'a str>,
+}
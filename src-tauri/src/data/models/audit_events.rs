@@ -0,0 +1,136 @@
+use diesel::prelude::*;
+
+use crate::data::models::schema::*;
+
+/// A security relevant event, see the audit log migration
+#[derive(Queryable, Identifiable, Selectable, PartialEq, Debug, Clone)]
+#[diesel(table_name = audit_events)]
+#[diesel(primary_key(event_id))]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+pub struct AuditEvents {
+    pub event_id: i32,
+    pub occurred_at: String,
+    pub action: String,
+    /// Who did it, `None` for failed sign-ins of unknown accounts
+    pub user_id: Option<i32>,
+    /// Copied at the time of the event, it may have changed since
+    pub username: Option<String>,
+    pub target_type: Option<String>,
+    pub target_id: Option<i32>,
+    pub ip: Option<String>,
+    pub user_agent: Option<String>,
+    pub details: Option<String>,
+}
+
+#[derive(Insertable, PartialEq, Debug, Default)]
+#[diesel(table_name = audit_events)]
+pub struct NewAuditEvent<'a> {
+    pub action: &'a str,
+    pub user_id: Option<i32>,
+    pub username: Option<&'a str>,
+    pub target_type: Option<&'a str>,
+    pub target_id: Option<i32>,
+    pub ip: Option<&'a str>,
+    pub user_agent: Option<&'a str>,
+    pub details: Option<&'a str>,
+}
+
+/// What happened, stored as text in `audit_events.action`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuditAction {
+    Login,
+    /// Wrong password or code, throttled attempts and disabled accounts
+    LoginFailed,
+    TokenRefreshed,
+    /// A replaced refresh token was used again, its session family was revoked
+    RefreshTokenReused,
+    Logout,
+    SessionRevoked,
+    PasswordChanged,
+    PasswordReset,
+    TwoFactorEnabled,
+    TwoFactorDisabled,
+    UserCreated,
+    UserUpdated,
+    RoleChanged,
+    UserDisabled,
+    UserEnabled,
+    UserDeleted,
+    UserUnlocked,
+    ApiKeyCreated,
+    ApiKeyRevoked,
+    LibraryCreated,
+    LibraryImported,
+    LibraryScanned,
+    LibraryAccessChanged,
+    LibraryShared,
+    LibraryUnshared,
+    BookDeleted,
+}
+
+impl AuditAction {
+    pub const ALL: [AuditAction; 26] = [
+        AuditAction::Login,
+        AuditAction::LoginFailed,
+        AuditAction::TokenRefreshed,
+        AuditAction::RefreshTokenReused,
+        AuditAction::Logout,
+        AuditAction::SessionRevoked,
+        AuditAction::PasswordChanged,
+        AuditAction::PasswordReset,
+        AuditAction::TwoFactorEnabled,
+        AuditAction::TwoFactorDisabled,
+        AuditAction::UserCreated,
+        AuditAction::UserUpdated,
+        AuditAction::RoleChanged,
+        AuditAction::UserDisabled,
+        AuditAction::UserEnabled,
+        AuditAction::UserDeleted,
+        AuditAction::UserUnlocked,
+        AuditAction::ApiKeyCreated,
+        AuditAction::ApiKeyRevoked,
+        AuditAction::LibraryCreated,
+        AuditAction::LibraryImported,
+        AuditAction::LibraryScanned,
+        AuditAction::LibraryAccessChanged,
+        AuditAction::LibraryShared,
+        AuditAction::LibraryUnshared,
+        AuditAction::BookDeleted,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AuditAction::Login => "login",
+            AuditAction::LoginFailed => "login_failed",
+            AuditAction::TokenRefreshed => "token_refreshed",
+            AuditAction::RefreshTokenReused => "refresh_token_reused",
+            AuditAction::Logout => "logout",
+            AuditAction::SessionRevoked => "session_revoked",
+            AuditAction::PasswordChanged => "password_changed",
+            AuditAction::PasswordReset => "password_reset",
+            AuditAction::TwoFactorEnabled => "two_factor_enabled",
+            AuditAction::TwoFactorDisabled => "two_factor_disabled",
+            AuditAction::UserCreated => "user_created",
+            AuditAction::UserUpdated => "user_updated",
+            AuditAction::RoleChanged => "role_changed",
+            AuditAction::UserDisabled => "user_disabled",
+            AuditAction::UserEnabled => "user_enabled",
+            AuditAction::UserDeleted => "user_deleted",
+            AuditAction::UserUnlocked => "user_unlocked",
+            AuditAction::ApiKeyCreated => "api_key_created",
+            AuditAction::ApiKeyRevoked => "api_key_revoked",
+            AuditAction::LibraryCreated => "library_created",
+            AuditAction::LibraryImported => "library_imported",
+            AuditAction::LibraryScanned => "library_scanned",
+            AuditAction::LibraryAccessChanged => "library_access_changed",
+            AuditAction::LibraryShared => "library_shared",
+            AuditAction::LibraryUnshared => "library_unshared",
+            AuditAction::BookDeleted => "book_deleted",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<AuditAction> {
+        let value = value.trim().to_ascii_lowercase();
+        AuditAction::ALL.into_iter().find(|a| a.as_str() == value)
+    }
+}
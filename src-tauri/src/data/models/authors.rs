@@ -9,22 +9,26 @@ use crate::data::models::schema::*;
 pub struct Authors {
     pub author_id: i32,
     pub name: String,
+    pub sort_name: Option<String>,
 }
 
 #[derive(Insertable, PartialEq, Debug)]
 #[diesel(table_name = authors)]
 pub struct NewAuthor<'a> {
     pub name: &'a str,
+    pub sort_name: Option<&'a str>,
 }
 
 #[derive(AsChangeset, PartialEq, Debug)]
 #[diesel(table_name = authors)]
 pub struct UpdateAuthor<'a> {
     pub name: Option<&'a str>,
+    pub sort_name: Option<&'a str>,
 }
 
 #[derive(AsChangeset, PartialEq, Debug)]
 #[diesel(table_name = authors)]
 pub struct AuthorForm<'a> {
     pub name: Option<&'a str>,
+    pub sort_name: Option<&'a str>,
 }
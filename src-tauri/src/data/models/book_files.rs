@@ -0,0 +1,34 @@
+use diesel::prelude::*;
+
+use crate::data::models::schema::*;
+
+/// A single file format of a book, `books.file_path` holds the preferred one
+#[derive(Queryable, Identifiable, Selectable, PartialEq, Debug)]
+#[diesel(table_name = book_files)]
+#[diesel(primary_key(file_id))]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+pub struct BookFiles {
+    pub file_id: i32,
+    pub book_id: i32,
+    pub format: String,
+    pub file_path: String,
+    pub file_size: Option<i64>,
+    pub added_at: Option<String>,
+}
+
+#[derive(Insertable, PartialEq, Debug)]
+#[diesel(table_name = book_files)]
+pub struct NewBookFile<'a> {
+    pub book_id: i32,
+    pub format: &'a str,
+    pub file_path: &'a str,
+    pub file_size: Option<i64>,
+}
+
+#[derive(AsChangeset, PartialEq, Debug)]
+#[diesel(table_name = book_files)]
+pub struct UpdateBookFile<'a> {
+    pub format: Option<&'a str>,
+    pub file_path: Option<&'a str>,
+    pub file_size: Option<i64>,
+}
@@ -0,0 +1,30 @@
+use diesel::prelude::*;
+
+use crate::data::models::schema::*;
+
+/// External identifier of a book (e.g. `isbn`, `goodreads`, `amazon`)
+#[derive(Queryable, Identifiable, Selectable, PartialEq, Debug)]
+#[diesel(table_name = book_identifiers)]
+#[diesel(primary_key(identifier_id))]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+pub struct BookIdentifiers {
+    pub identifier_id: i32,
+    pub book_id: i32,
+    pub scheme: String,
+    pub value: String,
+}
+
+#[derive(Insertable, PartialEq, Debug)]
+#[diesel(table_name = book_identifiers)]
+pub struct NewBookIdentifier<'a> {
+    pub book_id: i32,
+    pub scheme: &'a str,
+    pub value: &'a str,
+}
+
+#[derive(AsChangeset, PartialEq, Debug)]
+#[diesel(table_name = book_identifiers)]
+pub struct UpdateBookIdentifier<'a> {
+    pub scheme: Option<&'a str>,
+    pub value: Option<&'a str>,
+}
@@ -0,0 +1,16 @@
+use diesel::prelude::*;
+
+use crate::data::models::books::Books;
+use crate::data::models::schema::*;
+
+/// Records whether a metadata field of a book was embedded, inferred from the path or a fallback
+#[derive(Queryable, Identifiable, Associations, Selectable, PartialEq, Insertable, Debug)]
+#[diesel(table_name = book_metadata_sources)]
+#[diesel(primary_key(book_id, field))]
+#[diesel(belongs_to(Books, foreign_key = book_id))]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+pub struct BookMetadataSources {
+    pub book_id: i32,
+    pub field: String,
+    pub source: String,
+}
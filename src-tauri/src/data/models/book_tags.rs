@@ -0,0 +1,15 @@
+use diesel::prelude::*;
+
+use crate::data::models::books::Books;
+use crate::data::models::schema::*;
+use crate::data::models::tags::Tags;
+
+#[derive(Queryable, Identifiable, Associations, PartialEq, Insertable, Debug)]
+#[diesel(table_name = book_tags)]
+#[diesel(primary_key(book_id, tag_id))]
+#[diesel(belongs_to(Books, foreign_key = book_id))]
+#[diesel(belongs_to(Tags, foreign_key = tag_id))]
+pub struct BookTags {
+    pub book_id: i32,
+    pub tag_id: i32,
+}
@@ -16,9 +16,16 @@ pub struct Books {
     pub file_path: Option<String>,
     pub cover_image_path: Option<String>,
     pub added_at: Option<String>,
+    pub description: Option<String>,
+    pub language: Option<String>,
+    pub series_id: Option<i32>,
+    pub series_index: Option<f32>,
+    /// Calibre scale: 0 to 10, two points per star
+    pub rating: Option<i32>,
+    pub library_id: Option<i32>,
 }
 
-#[derive(Insertable, PartialEq, Debug)]
+#[derive(Insertable, PartialEq, Debug, Default)]
 #[diesel(table_name = books)]
 pub struct NewBook<'a> {
     pub title: &'a str,
@@ -28,9 +35,16 @@ pub struct NewBook<'a> {
     pub file_type: Option<&'a str>,
     pub file_path: Option<&'a str>,
     pub cover_image_path: Option<&'a str>,
+    pub added_at: Option<&'a str>,
+    pub description: Option<&'a str>,
+    pub language: Option<&'a str>,
+    pub series_id: Option<i32>,
+    pub series_index: Option<f32>,
+    pub rating: Option<i32>,
+    pub library_id: Option<i32>,
 }
 
-#[derive(AsChangeset, PartialEq, Debug)]
+#[derive(AsChangeset, PartialEq, Debug, Default)]
 #[diesel(table_name = books)]
 pub struct UpdateBook<'a> {
     pub title: Option<&'a str>,
@@ -40,4 +54,27 @@ pub struct UpdateBook<'a> {
     pub file_type: Option<&'a str>,
     pub file_path: Option<&'a str>,
     pub cover_image_path: Option<&'a str>,
+    pub description: Option<&'a str>,
+    pub language: Option<&'a str>,
+    pub series_id: Option<i32>,
+    pub series_index: Option<f32>,
+    pub rating: Option<i32>,
+    pub library_id: Option<i32>,
+}
+
+/// Changes to the metadata columns of a book. Unlike [`UpdateBook`], nullable columns
+/// can be cleared: `None` leaves a column untouched, `Some(None)` sets it to NULL.
+#[derive(AsChangeset, PartialEq, Debug, Default)]
+#[diesel(table_name = books)]
+pub struct EditBook<'a> {
+    pub title: Option<&'a str>,
+    pub published_date: Option<Option<&'a str>>,
+    pub publisher_id: Option<Option<i32>>,
+    pub isbn: Option<Option<&'a str>>,
+    pub cover_image_path: Option<Option<&'a str>>,
+    pub description: Option<Option<&'a str>>,
+    pub language: Option<Option<&'a str>>,
+    pub series_id: Option<Option<i32>>,
+    pub series_index: Option<Option<f32>>,
+    pub rating: Option<Option<i32>>,
 }
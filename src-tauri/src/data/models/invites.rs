@@ -0,0 +1,49 @@
+use diesel::prelude::*;
+
+use crate::data::models::schema::*;
+use crate::data::models::users::Role;
+
+/// A single-use registration code handed out by an admin
+#[derive(Queryable, Identifiable, Selectable, PartialEq, Debug, Clone)]
+#[diesel(table_name = invites)]
+#[diesel(primary_key(invite_id))]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+pub struct Invites {
+    pub invite_id: i32,
+    pub code_hash: String,
+    /// Role the registered account gets
+    pub role: String,
+    pub created_by: Option<i32>,
+    pub created_at: Option<String>,
+    pub expires_at: String,
+    pub used_at: Option<String>,
+    pub used_by: Option<i32>,
+}
+
+impl Invites {
+    /// Unknown roles fall back to the least permissions, like [`Users::role`](super::users::Users::role)
+    pub fn role(&self) -> Role {
+        Role::parse(&self.role).unwrap_or(Role::Guest)
+    }
+
+    /// Whether the code can still be redeemed at `now`
+    pub fn is_usable(&self, now: &str) -> bool {
+        self.used_at.is_none() && self.expires_at.as_str() > now
+    }
+}
+
+#[derive(Insertable, PartialEq, Debug)]
+#[diesel(table_name = invites)]
+pub struct NewInvite<'a> {
+    pub code_hash: &'a str,
+    pub role: &'a str,
+    pub created_by: Option<i32>,
+    pub expires_at: &'a str,
+}
+
+#[derive(AsChangeset, PartialEq, Debug, Default)]
+#[diesel(table_name = invites)]
+pub struct UpdateInvite<'a> {
+    pub role: Option<&'a str>,
+    pub expires_at: Option<&'a str>,
+}
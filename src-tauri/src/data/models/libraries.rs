@@ -10,8 +10,47 @@ pub struct Library {
     pub library_id: i32,
     pub name: String,
     pub path: String,
+    /// Owner of the library
     pub added_by: Option<i32>,
     pub added_at: Option<String>,
+    pub visibility: String,
+}
+
+/// Who can see the books of a library besides its owner and admins, stored as text
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Visibility {
+    /// Only the users and roles the library is shared with
+    Private,
+    /// Everyone signed in
+    Public,
+}
+
+impl Visibility {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Visibility::Private => "private",
+            Visibility::Public => "public",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Visibility> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "private" => Some(Visibility::Private),
+            "public" => Some(Visibility::Public),
+            _ => None,
+        }
+    }
+}
+
+impl Library {
+    /// Unknown values keep the library private
+    pub fn visibility(&self) -> Visibility {
+        Visibility::parse(&self.visibility).unwrap_or(Visibility::Private)
+    }
+
+    pub fn is_owned_by(&self, user_id: i32) -> bool {
+        self.added_by == Some(user_id)
+    }
 }
 
 #[derive(Insertable, PartialEq, Debug)]
@@ -20,6 +59,8 @@ pub struct NewLibrary<'a> {
     pub name: &'a str,
     pub path: &'a str,
     pub added_by: Option<i32>,
+    /// Defaults to public
+    pub visibility: Option<&'a str>,
 }
 
 #[derive(AsChangeset, PartialEq, Debug)]
@@ -28,4 +69,5 @@ pub struct UpdateLibrary<'a> {
     pub name: Option<&'a str>,
     pub path: Option<&'a str>,
     pub added_by: Option<i32>,
+    pub visibility: Option<&'a str>,
 }
@@ -0,0 +1,32 @@
+use diesel::prelude::*;
+
+use crate::data::models::schema::*;
+use crate::data::models::users::Role;
+
+/// Access to a private library for one user, or for everyone with at least a role
+#[derive(Queryable, Identifiable, Selectable, PartialEq, Debug, Clone)]
+#[diesel(table_name = library_shares)]
+#[diesel(primary_key(share_id))]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+pub struct LibraryShares {
+    pub share_id: i32,
+    pub library_id: i32,
+    pub user_id: Option<i32>,
+    pub role: Option<String>,
+    pub created_at: Option<String>,
+}
+
+impl LibraryShares {
+    /// `None` for user shares and unknown roles
+    pub fn role(&self) -> Option<Role> {
+        self.role.as_deref().and_then(Role::parse)
+    }
+}
+
+#[derive(Insertable, PartialEq, Debug)]
+#[diesel(table_name = library_shares)]
+pub struct NewLibraryShare<'a> {
+    pub library_id: i32,
+    pub user_id: Option<i32>,
+    pub role: Option<&'a str>,
+}
@@ -0,0 +1,33 @@
+use diesel::prelude::*;
+
+use crate::data::models::schema::*;
+use crate::data::models::users::Users;
+
+/// A sign-in that passed the password check and waits for a second factor
+#[derive(Queryable, Identifiable, Associations, Selectable, PartialEq, Debug, Clone)]
+#[diesel(table_name = login_challenges)]
+#[diesel(primary_key(challenge_id))]
+#[diesel(belongs_to(Users, foreign_key = user_id))]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+pub struct LoginChallenges {
+    pub challenge_id: i32,
+    pub user_id: i32,
+    pub token_hash: String,
+    /// Kept from the login request for the session it turns into
+    pub device_name: Option<String>,
+    pub user_agent: Option<String>,
+    /// Wrong codes given so far
+    pub attempts: i32,
+    pub created_at: Option<String>,
+    pub expires_at: String,
+}
+
+#[derive(Insertable, PartialEq, Debug)]
+#[diesel(table_name = login_challenges)]
+pub struct NewLoginChallenge<'a> {
+    pub user_id: i32,
+    pub token_hash: &'a str,
+    pub device_name: Option<&'a str>,
+    pub user_agent: Option<&'a str>,
+    pub expires_at: &'a str,
+}
@@ -0,0 +1,41 @@
+use diesel::prelude::*;
+
+use crate::data::models::books::Books;
+use crate::data::models::schema::*;
+
+/// A single change to a metadata field of a book
+#[derive(Queryable, Identifiable, Associations, Selectable, PartialEq, Debug)]
+#[diesel(table_name = metadata_history)]
+#[diesel(primary_key(history_id))]
+#[diesel(belongs_to(Books, foreign_key = book_id))]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+pub struct MetadataHistory {
+    pub history_id: i32,
+    pub book_id: i32,
+    pub field: String,
+    pub old_value: Option<String>,
+    pub new_value: Option<String>,
+    pub source: String,
+    pub user_id: Option<i32>,
+    pub batch_id: String,
+    pub created_at: Option<String>,
+    pub reverted_at: Option<String>,
+}
+
+#[derive(Insertable, PartialEq, Debug)]
+#[diesel(table_name = metadata_history)]
+pub struct NewMetadataHistory<'a> {
+    pub book_id: i32,
+    pub field: &'a str,
+    pub old_value: Option<&'a str>,
+    pub new_value: Option<&'a str>,
+    pub source: &'a str,
+    pub user_id: Option<i32>,
+    pub batch_id: &'a str,
+}
+
+#[derive(AsChangeset, PartialEq, Debug)]
+#[diesel(table_name = metadata_history)]
+pub struct UpdateMetadataHistory<'a> {
+    pub reverted_at: Option<&'a str>,
+}
@@ -1,11 +1,29 @@
 pub mod annotations;
+pub mod api_keys;
+pub mod audit_events;
 pub mod authors;
 pub mod book_authors;
+pub mod book_files;
+pub mod book_identifiers;
+pub mod book_metadata_sources;
+pub mod book_tags;
 pub mod bookmarks;
 pub mod books;
+pub mod invites;
 pub mod libraries;
+pub mod library_shares;
+pub mod login_challenges;
+pub mod metadata_history;
+pub mod password_resets;
 pub mod publishers;
 pub mod reading_progress;
+pub mod recovery_codes;
+pub mod revoked_tokens;
 pub mod schema;
+pub mod series;
+pub mod server_settings;
+pub mod sessions;
+pub mod tags;
 pub mod user_library;
+pub mod user_totp;
 pub mod users;
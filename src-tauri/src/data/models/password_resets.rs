@@ -0,0 +1,34 @@
+use diesel::prelude::*;
+
+use crate::data::models::schema::*;
+use crate::data::models::users::Users;
+
+/// A password reset token that was mailed to a user
+#[derive(Queryable, Identifiable, Associations, Selectable, PartialEq, Debug, Clone)]
+#[diesel(table_name = password_resets)]
+#[diesel(primary_key(reset_id))]
+#[diesel(belongs_to(Users, foreign_key = user_id))]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+pub struct PasswordResets {
+    pub reset_id: i32,
+    pub user_id: i32,
+    pub token_hash: String,
+    pub created_at: Option<String>,
+    pub expires_at: String,
+    pub used_at: Option<String>,
+}
+
+#[derive(Insertable, PartialEq, Debug)]
+#[diesel(table_name = password_resets)]
+pub struct NewPasswordReset<'a> {
+    pub user_id: i32,
+    pub token_hash: &'a str,
+    pub expires_at: &'a str,
+}
+
+#[derive(AsChangeset, PartialEq, Debug, Default)]
+#[diesel(table_name = password_resets)]
+pub struct UpdatePasswordReset<'a> {
+    pub expires_at: Option<&'a str>,
+    pub used_at: Option<&'a str>,
+}
@@ -0,0 +1,25 @@
+use diesel::prelude::*;
+
+use crate::data::models::schema::*;
+use crate::data::models::users::Users;
+
+/// A single-use code that stands in for a TOTP code
+#[derive(Queryable, Identifiable, Associations, Selectable, PartialEq, Debug, Clone)]
+#[diesel(table_name = recovery_codes)]
+#[diesel(primary_key(code_id))]
+#[diesel(belongs_to(Users, foreign_key = user_id))]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+pub struct RecoveryCodes {
+    pub code_id: i32,
+    pub user_id: i32,
+    pub code_hash: String,
+    pub created_at: Option<String>,
+    pub used_at: Option<String>,
+}
+
+#[derive(Insertable, PartialEq, Debug)]
+#[diesel(table_name = recovery_codes)]
+pub struct NewRecoveryCode<'a> {
+    pub user_id: i32,
+    pub code_hash: &'a str,
+}
@@ -0,0 +1,13 @@
+use diesel::prelude::*;
+
+use crate::data::models::schema::*;
+
+/// An access token that must be refused although its signature and expiry are valid
+#[derive(Queryable, Insertable, Selectable, PartialEq, Debug, Clone)]
+#[diesel(table_name = revoked_tokens)]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+pub struct RevokedToken {
+    pub jti: String,
+    /// When the token expires anyway, the row can be dropped after that
+    pub expires_at: String,
+}
@@ -16,10 +16,41 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    api_keys (api_key_id) {
+        api_key_id -> Integer,
+        user_id -> Integer,
+        name -> Text,
+        prefix -> Text,
+        key_hash -> Text,
+        scopes -> Text,
+        created_at -> Nullable<Text>,
+        last_used_at -> Nullable<Text>,
+        expires_at -> Nullable<Text>,
+        revoked_at -> Nullable<Text>,
+    }
+}
+
+diesel::table! {
+    audit_events (event_id) {
+        event_id -> Integer,
+        occurred_at -> Text,
+        action -> Text,
+        user_id -> Nullable<Integer>,
+        username -> Nullable<Text>,
+        target_type -> Nullable<Text>,
+        target_id -> Nullable<Integer>,
+        ip -> Nullable<Text>,
+        user_agent -> Nullable<Text>,
+        details -> Nullable<Text>,
+    }
+}
+
 diesel::table! {
     authors (author_id) {
         author_id -> Integer,
         name -> Text,
+        sort_name -> Nullable<Text>,
     }
 }
 
@@ -30,6 +61,41 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    book_files (file_id) {
+        file_id -> Integer,
+        book_id -> Integer,
+        format -> Text,
+        file_path -> Text,
+        file_size -> Nullable<BigInt>,
+        added_at -> Nullable<Text>,
+    }
+}
+
+diesel::table! {
+    book_identifiers (identifier_id) {
+        identifier_id -> Integer,
+        book_id -> Integer,
+        scheme -> Text,
+        value -> Text,
+    }
+}
+
+diesel::table! {
+    book_metadata_sources (book_id, field) {
+        book_id -> Integer,
+        field -> Text,
+        source -> Text,
+    }
+}
+
+diesel::table! {
+    book_tags (book_id, tag_id) {
+        book_id -> Integer,
+        tag_id -> Integer,
+    }
+}
+
 diesel::table! {
     bookmarks (bookmark_id) {
         bookmark_id -> Nullable<Integer>,
@@ -53,6 +119,25 @@ diesel::table! {
         file_path -> Nullable<Text>,
         cover_image_path -> Nullable<Text>,
         added_at -> Nullable<Text>,
+        description -> Nullable<Text>,
+        language -> Nullable<Text>,
+        series_id -> Nullable<Integer>,
+        series_index -> Nullable<Float>,
+        rating -> Nullable<Integer>,
+        library_id -> Nullable<Integer>,
+    }
+}
+
+diesel::table! {
+    invites (invite_id) {
+        invite_id -> Integer,
+        code_hash -> Text,
+        role -> Text,
+        created_by -> Nullable<Integer>,
+        created_at -> Nullable<Text>,
+        expires_at -> Text,
+        used_at -> Nullable<Text>,
+        used_by -> Nullable<Integer>,
     }
 }
 
@@ -63,6 +148,56 @@ diesel::table! {
         path -> Text,
         added_by -> Nullable<Integer>,
         added_at -> Nullable<Text>,
+        visibility -> Text,
+    }
+}
+
+diesel::table! {
+    library_shares (share_id) {
+        share_id -> Integer,
+        library_id -> Integer,
+        user_id -> Nullable<Integer>,
+        role -> Nullable<Text>,
+        created_at -> Nullable<Text>,
+    }
+}
+
+diesel::table! {
+    login_challenges (challenge_id) {
+        challenge_id -> Integer,
+        user_id -> Integer,
+        token_hash -> Text,
+        device_name -> Nullable<Text>,
+        user_agent -> Nullable<Text>,
+        attempts -> Integer,
+        created_at -> Nullable<Text>,
+        expires_at -> Text,
+    }
+}
+
+diesel::table! {
+    metadata_history (history_id) {
+        history_id -> Integer,
+        book_id -> Integer,
+        field -> Text,
+        old_value -> Nullable<Text>,
+        new_value -> Nullable<Text>,
+        source -> Text,
+        user_id -> Nullable<Integer>,
+        batch_id -> Text,
+        created_at -> Nullable<Text>,
+        reverted_at -> Nullable<Text>,
+    }
+}
+
+diesel::table! {
+    password_resets (reset_id) {
+        reset_id -> Integer,
+        user_id -> Integer,
+        token_hash -> Text,
+        created_at -> Nullable<Text>,
+        expires_at -> Text,
+        used_at -> Nullable<Text>,
     }
 }
 
@@ -86,6 +221,63 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    recovery_codes (code_id) {
+        code_id -> Integer,
+        user_id -> Integer,
+        code_hash -> Text,
+        created_at -> Nullable<Text>,
+        used_at -> Nullable<Text>,
+    }
+}
+
+diesel::table! {
+    revoked_tokens (jti) {
+        jti -> Text,
+        expires_at -> Text,
+    }
+}
+
+diesel::table! {
+    series (series_id) {
+        series_id -> Integer,
+        name -> Text,
+        sort_name -> Nullable<Text>,
+    }
+}
+
+diesel::table! {
+    server_settings (key) {
+        key -> Text,
+        value -> Text,
+        updated_at -> Nullable<Text>,
+    }
+}
+
+diesel::table! {
+    sessions (session_id) {
+        session_id -> Integer,
+        user_id -> Integer,
+        family_id -> Text,
+        token_hash -> Text,
+        device_name -> Nullable<Text>,
+        user_agent -> Nullable<Text>,
+        created_at -> Nullable<Text>,
+        last_used_at -> Nullable<Text>,
+        expires_at -> Text,
+        revoked_at -> Nullable<Text>,
+        replaced_by -> Nullable<Integer>,
+        access_jti -> Nullable<Text>,
+    }
+}
+
+diesel::table! {
+    tags (tag_id) {
+        tag_id -> Integer,
+        name -> Text,
+    }
+}
+
 diesel::table! {
     user_library (user_id, book_id) {
         user_id -> Integer,
@@ -94,6 +286,16 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    user_totp (user_id) {
+        user_id -> Integer,
+        secret -> Text,
+        created_at -> Nullable<Text>,
+        confirmed_at -> Nullable<Text>,
+        last_step -> Nullable<BigInt>,
+    }
+}
+
 diesel::table! {
     users (user_id) {
         user_id -> Integer,
@@ -102,32 +304,67 @@ diesel::table! {
         role -> Nullable<Text>,
         password_hash -> Text,
         created_at -> Nullable<Text>,
-        refresh_token -> Nullable<Text>,
+        disabled_at -> Nullable<Text>,
     }
 }
 
 diesel::joinable!(annotations -> books (book_id));
 diesel::joinable!(annotations -> users (user_id));
+diesel::joinable!(api_keys -> users (user_id));
 diesel::joinable!(book_authors -> authors (author_id));
 diesel::joinable!(book_authors -> books (book_id));
+diesel::joinable!(book_files -> books (book_id));
+diesel::joinable!(book_identifiers -> books (book_id));
+diesel::joinable!(book_metadata_sources -> books (book_id));
+diesel::joinable!(book_tags -> books (book_id));
+diesel::joinable!(book_tags -> tags (tag_id));
 diesel::joinable!(bookmarks -> books (book_id));
 diesel::joinable!(bookmarks -> users (user_id));
+diesel::joinable!(books -> libraries (library_id));
 diesel::joinable!(books -> publishers (publisher_id));
+diesel::joinable!(books -> series (series_id));
 diesel::joinable!(libraries -> users (added_by));
+diesel::joinable!(library_shares -> libraries (library_id));
+diesel::joinable!(library_shares -> users (user_id));
+diesel::joinable!(login_challenges -> users (user_id));
+diesel::joinable!(metadata_history -> books (book_id));
+diesel::joinable!(metadata_history -> users (user_id));
+diesel::joinable!(password_resets -> users (user_id));
 diesel::joinable!(reading_progress -> books (book_id));
 diesel::joinable!(reading_progress -> users (user_id));
+diesel::joinable!(recovery_codes -> users (user_id));
+diesel::joinable!(sessions -> users (user_id));
 diesel::joinable!(user_library -> books (book_id));
 diesel::joinable!(user_library -> users (user_id));
+diesel::joinable!(user_totp -> users (user_id));
 
 diesel::allow_tables_to_appear_in_same_query!(
     annotations,
+    api_keys,
+    audit_events,
     authors,
     book_authors,
+    book_files,
+    book_identifiers,
+    book_metadata_sources,
+    book_tags,
     bookmarks,
     books,
+    invites,
     libraries,
+    library_shares,
+    login_challenges,
+    metadata_history,
+    password_resets,
     publishers,
     reading_progress,
+    recovery_codes,
+    revoked_tokens,
+    series,
+    server_settings,
+    sessions,
+    tags,
     user_library,
+    user_totp,
     users,
 );
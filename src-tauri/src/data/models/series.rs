@@ -0,0 +1,27 @@
+use diesel::prelude::*;
+
+use crate::data::models::schema::*;
+
+#[derive(Queryable, Identifiable, Selectable, PartialEq, Debug)]
+#[diesel(table_name = series)]
+#[diesel(primary_key(series_id))]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+pub struct Series {
+    pub series_id: i32,
+    pub name: String,
+    pub sort_name: Option<String>,
+}
+
+#[derive(Insertable, PartialEq, Debug)]
+#[diesel(table_name = series)]
+pub struct NewSeries<'a> {
+    pub name: &'a str,
+    pub sort_name: Option<&'a str>,
+}
+
+#[derive(AsChangeset, PartialEq, Debug)]
+#[diesel(table_name = series)]
+pub struct UpdateSeries<'a> {
+    pub name: Option<&'a str>,
+    pub sort_name: Option<&'a str>,
+}
@@ -0,0 +1,13 @@
+use diesel::prelude::*;
+
+use crate::data::models::schema::*;
+
+/// A server-wide setting stored as text under a fixed key
+#[derive(Queryable, Selectable, PartialEq, Debug, Clone)]
+#[diesel(table_name = server_settings)]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+pub struct ServerSetting {
+    pub key: String,
+    pub value: String,
+    pub updated_at: Option<String>,
+}
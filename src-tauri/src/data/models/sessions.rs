@@ -0,0 +1,51 @@
+use diesel::prelude::*;
+
+use crate::data::models::schema::*;
+use crate::data::models::users::Users;
+
+/// A refresh token issued to one device, see the sessions migration for the rotation scheme
+#[derive(Queryable, Identifiable, Associations, Selectable, PartialEq, Debug, Clone)]
+#[diesel(table_name = sessions)]
+#[diesel(primary_key(session_id))]
+#[diesel(belongs_to(Users, foreign_key = user_id))]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+pub struct Sessions {
+    pub session_id: i32,
+    pub user_id: i32,
+    /// Shared by every token issued since the same login
+    pub family_id: String,
+    pub token_hash: String,
+    pub device_name: Option<String>,
+    pub user_agent: Option<String>,
+    pub created_at: Option<String>,
+    pub last_used_at: Option<String>,
+    pub expires_at: String,
+    pub revoked_at: Option<String>,
+    /// Session that took over when this token was refreshed
+    pub replaced_by: Option<i32>,
+    /// Id of the access token issued with this refresh token
+    pub access_jti: Option<String>,
+}
+
+#[derive(Insertable, PartialEq, Debug)]
+#[diesel(table_name = sessions)]
+pub struct NewSession<'a> {
+    pub user_id: i32,
+    pub family_id: &'a str,
+    pub token_hash: &'a str,
+    pub device_name: Option<&'a str>,
+    pub user_agent: Option<&'a str>,
+    /// Kept from the original login when a token is rotated, defaults to now
+    pub created_at: Option<&'a str>,
+    pub expires_at: &'a str,
+    pub access_jti: Option<&'a str>,
+}
+
+#[derive(AsChangeset, PartialEq, Debug, Default)]
+#[diesel(table_name = sessions)]
+pub struct UpdateSession<'a> {
+    pub device_name: Option<&'a str>,
+    pub last_used_at: Option<&'a str>,
+    pub revoked_at: Option<&'a str>,
+    pub replaced_by: Option<i32>,
+}
@@ -0,0 +1,24 @@
+use diesel::prelude::*;
+
+use crate::data::models::schema::*;
+
+#[derive(Queryable, Identifiable, Selectable, PartialEq, Debug)]
+#[diesel(table_name = tags)]
+#[diesel(primary_key(tag_id))]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+pub struct Tags {
+    pub tag_id: i32,
+    pub name: String,
+}
+
+#[derive(Insertable, PartialEq, Debug)]
+#[diesel(table_name = tags)]
+pub struct NewTag<'a> {
+    pub name: &'a str,
+}
+
+#[derive(AsChangeset, PartialEq, Debug)]
+#[diesel(table_name = tags)]
+pub struct UpdateTag<'a> {
+    pub name: Option<&'a str>,
+}
@@ -0,0 +1,33 @@
+use diesel::prelude::*;
+
+use crate::data::models::schema::*;
+use crate::data::models::users::Users;
+
+/// A user's TOTP secret, only checked at sign-in once it is confirmed
+#[derive(Queryable, Identifiable, Associations, Selectable, PartialEq, Debug, Clone)]
+#[diesel(table_name = user_totp)]
+#[diesel(primary_key(user_id))]
+#[diesel(belongs_to(Users, foreign_key = user_id))]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+pub struct UserTotp {
+    pub user_id: i32,
+    /// Base32 encoded, the way authenticator apps take it
+    pub secret: String,
+    pub created_at: Option<String>,
+    pub confirmed_at: Option<String>,
+    /// Time step of the last accepted code
+    pub last_step: Option<i64>,
+}
+
+impl UserTotp {
+    pub fn is_confirmed(&self) -> bool {
+        self.confirmed_at.is_some()
+    }
+}
+
+#[derive(Insertable, PartialEq, Debug)]
+#[diesel(table_name = user_totp)]
+pub struct NewUserTotp<'a> {
+    pub user_id: i32,
+    pub secret: &'a str,
+}
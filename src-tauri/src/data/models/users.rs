@@ -13,7 +13,56 @@ pub struct Users {
     pub role: Option<String>,
     pub password_hash: String,
     pub created_at: Option<String>,
-    pub refresh_token: Option<String>,
+    /// Set while an admin has disabled the account
+    pub disabled_at: Option<String>,
+}
+
+/// What a user may do, stored as text in `users.role`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Role {
+    /// Can browse and read, but not change anything, including their own bookmarks
+    Guest,
+    User,
+    /// Also manages libraries, users and metadata
+    Admin,
+}
+
+impl Role {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::Guest => "guest",
+            Role::User => "user",
+            Role::Admin => "admin",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Role> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "guest" => Some(Role::Guest),
+            "user" => Some(Role::User),
+            "admin" => Some(Role::Admin),
+            _ => None,
+        }
+    }
+
+    /// Whether this role grants at least the permissions of `required`
+    pub fn allows(&self, required: Role) -> bool {
+        *self >= required
+    }
+}
+
+impl Users {
+    /// Missing or unknown roles get the least permissions
+    pub fn role(&self) -> Role {
+        self.role
+            .as_deref()
+            .and_then(Role::parse)
+            .unwrap_or(Role::Guest)
+    }
+
+    pub fn is_disabled(&self) -> bool {
+        self.disabled_at.is_some()
+    }
 }
 
 #[derive(Insertable, PartialEq, Debug)]
@@ -21,7 +70,8 @@ pub struct Users {
 pub struct NewUser<'a> {
     pub username: &'a str,
     pub email: &'a str,
-    pub role: Option<&'a str>,
+    /// Required, the column default would make every new account an admin
+    pub role: &'a str,
     pub password_hash: &'a str,
 }
 
@@ -32,5 +82,4 @@ pub struct UpdateUser<'a> {
     pub email: Option<&'a str>,
     pub role: Option<&'a str>,
     pub password_hash: Option<&'a str>,
-    pub refresh_token: Option<&'a str>,
 }
@@ -0,0 +1,291 @@
+use async_trait::async_trait;
+use diesel::prelude::*;
+use diesel::result::{DatabaseErrorKind, Error};
+use diesel_async::scoped_futures::ScopedFutureExt;
+use diesel_async::{AsyncConnection, RunQueryDsl};
+use tokio::sync::MutexGuard;
+
+use crate::data::{
+    database::{connect_from_pool, lock_db},
+    models::api_keys::{ApiKeys, NewApiKey, UpdateApiKey},
+    repos::traits::repository::Repository,
+};
+
+pub struct ApiKeyRepo;
+
+impl ApiKeyRepo {
+    pub async fn new() -> Self {
+        ApiKeyRepo
+    }
+
+    pub async fn get_by_key_hash(&self, hash: &str) -> Result<Option<ApiKeys>, Error> {
+        use crate::data::models::schema::api_keys::dsl::*;
+
+        let mut conn = connect_from_pool().await.map_err(|e| {
+            Error::DatabaseError(
+                DatabaseErrorKind::UnableToSendCommand,
+                Box::new(e.to_string()),
+            )
+        })?;
+
+        match api_keys
+            .filter(key_hash.eq(hash))
+            .first::<ApiKeys>(&mut conn)
+            .await
+        {
+            Ok(value) => Ok(Some(value)),
+            Err(Error::NotFound) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Keys of a user that weren't revoked, newest first. Expired keys are included.
+    pub async fn get_by_user(&self, uid: i32) -> Result<Option<Vec<ApiKeys>>, Error> {
+        use crate::data::models::schema::api_keys::dsl::*;
+
+        let mut conn = connect_from_pool().await.map_err(|e| {
+            Error::DatabaseError(
+                DatabaseErrorKind::UnableToSendCommand,
+                Box::new(e.to_string()),
+            )
+        })?;
+
+        match api_keys
+            .filter(user_id.eq(uid))
+            .filter(revoked_at.is_null())
+            .order(api_key_id.desc())
+            .load::<ApiKeys>(&mut conn)
+            .await
+        {
+            Ok(value) if value.is_empty() => Ok(None),
+            Ok(value) => Ok(Some(value)),
+            Err(Error::NotFound) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Inserts a key and returns the id it was assigned
+    pub async fn add_returning_id(&self, new_item: NewApiKey<'_>) -> Result<i32, Error> {
+        use crate::data::models::schema::api_keys::dsl::*;
+
+        let mut conn = connect_from_pool().await.map_err(|e| {
+            Error::DatabaseError(
+                DatabaseErrorKind::UnableToSendCommand,
+                Box::new(e.to_string()),
+            )
+        })?;
+
+        let db_lock = lock_db();
+        let _guard: MutexGuard<()> = db_lock.lock().await;
+
+        conn.transaction(|connection| {
+            async move {
+                diesel::insert_into(api_keys)
+                    .values(new_item)
+                    .execute(connection)
+                    .await?;
+
+                api_keys
+                    .select(api_key_id)
+                    .order(api_key_id.desc())
+                    .first::<i32>(connection)
+                    .await
+            }
+            .scope_boxed()
+        })
+        .await
+    }
+
+    /// Revokes a key of the user, `false` if the user has no such unrevoked key
+    pub async fn revoke(&self, id: i32, uid: i32, now: &str) -> Result<bool, Error> {
+        use crate::data::models::schema::api_keys::dsl::*;
+
+        let mut conn = connect_from_pool().await.map_err(|e| {
+            Error::DatabaseError(
+                DatabaseErrorKind::UnableToSendCommand,
+                Box::new(e.to_string()),
+            )
+        })?;
+
+        let db_lock = lock_db();
+        let _guard: MutexGuard<()> = db_lock.lock().await;
+
+        conn.transaction(|connection| {
+            async move {
+                let updated = diesel::update(
+                    api_keys
+                        .filter(api_key_id.eq(id))
+                        .filter(user_id.eq(uid))
+                        .filter(revoked_at.is_null()),
+                )
+                .set(revoked_at.eq(now))
+                .execute(connection)
+                .await?;
+                Ok(updated > 0)
+            }
+            .scope_boxed()
+        })
+        .await
+    }
+
+    /// Records a use of the key. Skipped if it was already recorded after `stale_before`,
+    /// so a busy client doesn't take the write lock on every request.
+    pub async fn touch(&self, id: i32, now: &str, stale_before: &str) -> Result<(), Error> {
+        use crate::data::models::schema::api_keys::dsl::*;
+
+        let mut conn = connect_from_pool().await.map_err(|e| {
+            Error::DatabaseError(
+                DatabaseErrorKind::UnableToSendCommand,
+                Box::new(e.to_string()),
+            )
+        })?;
+
+        let db_lock = lock_db();
+        let _guard: MutexGuard<()> = db_lock.lock().await;
+
+        conn.transaction(|connection| {
+            async move {
+                diesel::update(
+                    api_keys
+                        .filter(api_key_id.eq(id))
+                        .filter(last_used_at.is_null().or(last_used_at.lt(stale_before))),
+                )
+                .set(last_used_at.eq(now))
+                .execute(connection)
+                .await?;
+                Ok(())
+            }
+            .scope_boxed()
+        })
+        .await
+    }
+}
+
+#[async_trait]
+impl Repository for ApiKeyRepo {
+    type Item = ApiKeys;
+    type NewItem<'a> = NewApiKey<'a>;
+    type Form<'a> = UpdateApiKey<'a>;
+    type Id = i32;
+
+    async fn get_all(&self) -> Result<Option<Vec<Self::Item>>, Error> {
+        use crate::data::models::schema::api_keys::dsl::*;
+
+        let mut conn = connect_from_pool().await.map_err(|e| {
+            Error::DatabaseError(
+                DatabaseErrorKind::UnableToSendCommand,
+                Box::new(e.to_string()),
+            )
+        })?;
+
+        match api_keys.load::<Self::Item>(&mut conn).await {
+            Ok(value) if value.is_empty() => Ok(None),
+            Ok(value) => Ok(Some(value)),
+            Err(Error::NotFound) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn get_by_id(&self, id: Self::Id) -> Result<Option<Self::Item>, Error> {
+        use crate::data::models::schema::api_keys::dsl::*;
+
+        let mut conn = connect_from_pool().await.map_err(|e| {
+            Error::DatabaseError(
+                DatabaseErrorKind::UnableToSendCommand,
+                Box::new(e.to_string()),
+            )
+        })?;
+
+        match api_keys
+            .filter(api_key_id.eq(id))
+            .first::<ApiKeys>(&mut conn)
+            .await
+        {
+            Ok(value) => Ok(Some(value)),
+            Err(Error::NotFound) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn add<'a>(&self, new_item: Self::NewItem<'a>) -> Result<(), Error> {
+        use crate::data::models::schema::api_keys::dsl::*;
+
+        let mut conn = connect_from_pool().await.map_err(|e| {
+            Error::DatabaseError(
+                DatabaseErrorKind::UnableToSendCommand,
+                Box::new(e.to_string()),
+            )
+        })?;
+
+        let db_lock = lock_db();
+        let _guard: MutexGuard<()> = db_lock.lock().await;
+
+        conn.transaction(|connection| {
+            async move {
+                diesel::insert_into(api_keys)
+                    .values(new_item)
+                    .execute(connection)
+                    .await?;
+                Ok(())
+            }
+            .scope_boxed()
+        })
+        .await
+    }
+
+    async fn update<'a>(&self, id: Self::Id, updated_item: Self::Form<'a>) -> Result<(), Error> {
+        use crate::data::models::schema::api_keys::dsl::*;
+
+        // Diesel rejects an update without any column to set
+        if updated_item == UpdateApiKey::default() {
+            return Ok(());
+        }
+
+        let mut conn = connect_from_pool().await.map_err(|e| {
+            Error::DatabaseError(
+                DatabaseErrorKind::UnableToSendCommand,
+                Box::new(e.to_string()),
+            )
+        })?;
+
+        let db_lock = lock_db();
+        let _guard: MutexGuard<()> = db_lock.lock().await;
+
+        conn.transaction(|connection| {
+            async move {
+                diesel::update(api_keys.filter(api_key_id.eq(id)))
+                    .set(updated_item)
+                    .execute(connection)
+                    .await?;
+                Ok(())
+            }
+            .scope_boxed()
+        })
+        .await
+    }
+
+    async fn delete(&self, id: Self::Id) -> Result<(), Error> {
+        use crate::data::models::schema::api_keys::dsl::*;
+
+        let mut conn = connect_from_pool().await.map_err(|e| {
+            Error::DatabaseError(
+                DatabaseErrorKind::UnableToSendCommand,
+                Box::new(e.to_string()),
+            )
+        })?;
+
+        let db_lock = lock_db();
+        let _guard: MutexGuard<()> = db_lock.lock().await;
+
+        conn.transaction(|connection| {
+            async move {
+                diesel::delete(api_keys.filter(api_key_id.eq(id)))
+                    .execute(connection)
+                    .await?;
+                Ok(())
+            }
+            .scope_boxed()
+        })
+        .await
+    }
+}
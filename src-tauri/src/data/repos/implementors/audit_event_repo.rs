@@ -0,0 +1,137 @@
+use diesel::prelude::*;
+use diesel::result::{DatabaseErrorKind, Error};
+use diesel::sqlite::Sqlite;
+use diesel_async::scoped_futures::ScopedFutureExt;
+use diesel_async::{AsyncConnection, RunQueryDsl};
+use tokio::sync::MutexGuard;
+
+use crate::data::{
+    database::{connect_from_pool, lock_db},
+    models::{
+        audit_events::{AuditEvents, NewAuditEvent},
+        schema::audit_events,
+    },
+};
+
+/// Narrows a query of the audit log, every field that is set has to match
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AuditFilter {
+    pub user_id: Option<i32>,
+    pub action: Option<String>,
+    pub target_type: Option<String>,
+    pub target_id: Option<i32>,
+    pub ip: Option<String>,
+    /// Inclusive, in the stored `YYYY-MM-DD HH:MM:SS` form
+    pub since: Option<String>,
+    /// Exclusive, in the stored form
+    pub until: Option<String>,
+}
+
+fn filtered(filter: &AuditFilter) -> audit_events::BoxedQuery<'_, Sqlite> {
+    let mut query = audit_events::table.into_boxed();
+    if let Some(id) = filter.user_id {
+        query = query.filter(audit_events::user_id.eq(id));
+    }
+    if let Some(action) = &filter.action {
+        query = query.filter(audit_events::action.eq(action));
+    }
+    if let Some(target_type) = &filter.target_type {
+        query = query.filter(audit_events::target_type.eq(target_type));
+    }
+    if let Some(id) = filter.target_id {
+        query = query.filter(audit_events::target_id.eq(id));
+    }
+    if let Some(ip) = &filter.ip {
+        query = query.filter(audit_events::ip.eq(ip));
+    }
+    if let Some(since) = &filter.since {
+        query = query.filter(audit_events::occurred_at.ge(since));
+    }
+    if let Some(until) = &filter.until {
+        query = query.filter(audit_events::occurred_at.lt(until));
+    }
+    query
+}
+
+/// The audit log is append-only, there is no update and events are only deleted by age
+pub struct AuditEventRepo;
+
+impl AuditEventRepo {
+    pub async fn new() -> Self {
+        AuditEventRepo
+    }
+
+    pub async fn add(&self, new_item: NewAuditEvent<'_>) -> Result<(), Error> {
+        let mut conn = connect_from_pool().await.map_err(|e| {
+            Error::DatabaseError(
+                DatabaseErrorKind::UnableToSendCommand,
+                Box::new(e.to_string()),
+            )
+        })?;
+
+        let db_lock = lock_db();
+        let _guard: MutexGuard<()> = db_lock.lock().await;
+
+        conn.transaction(|connection| {
+            async move {
+                diesel::insert_into(audit_events::table)
+                    .values(new_item)
+                    .execute(connection)
+                    .await?;
+                Ok(())
+            }
+            .scope_boxed()
+        })
+        .await
+    }
+
+    /// Matching events, newest first, with the number of all matches
+    pub async fn get_page(
+        &self,
+        filter: &AuditFilter,
+        offset: i64,
+        limit: i64,
+    ) -> Result<(Vec<AuditEvents>, i64), Error> {
+        let mut conn = connect_from_pool().await.map_err(|e| {
+            Error::DatabaseError(
+                DatabaseErrorKind::UnableToSendCommand,
+                Box::new(e.to_string()),
+            )
+        })?;
+
+        let total = filtered(filter)
+            .count()
+            .get_result::<i64>(&mut conn)
+            .await?;
+        let events = filtered(filter)
+            .order(audit_events::event_id.desc())
+            .offset(offset)
+            .limit(limit)
+            .load::<AuditEvents>(&mut conn)
+            .await?;
+        Ok((events, total))
+    }
+
+    /// Removes the events that happened before `cutoff`, returning how many there were
+    pub async fn delete_before(&self, cutoff: &str) -> Result<usize, Error> {
+        let mut conn = connect_from_pool().await.map_err(|e| {
+            Error::DatabaseError(
+                DatabaseErrorKind::UnableToSendCommand,
+                Box::new(e.to_string()),
+            )
+        })?;
+
+        let db_lock = lock_db();
+        let _guard: MutexGuard<()> = db_lock.lock().await;
+
+        conn.transaction(|connection| {
+            async move {
+                diesel::delete(audit_events::table.filter(audit_events::occurred_at.lt(cutoff)))
+                    .execute(connection)
+                    .await
+            }
+            .scope_boxed()
+        })
+        .await
+    }
+}
@@ -5,7 +5,7 @@ use diesel_async::{scoped_futures::ScopedFutureExt, AsyncConnection, RunQueryDsl
 use tokio::sync::MutexGuard;
 
 use crate::data::{
-    database::{connect_from_pool, lock_db},
+    database::{connect_from_pool, lock_db, DbConnection},
     models::authors::{AuthorForm, Authors, NewAuthor},
     repos::traits::repository::Repository,
 };
@@ -38,6 +38,108 @@ impl AuthorRepo {
             Err(e) => Err(e),
         };
     }
+
+    pub async fn get_by_name(&self, name_query: &str) -> Result<Option<Authors>, Error> {
+        use crate::data::models::schema::authors::dsl::*;
+
+        let mut conn = connect_from_pool().await.map_err(|e| {
+            Error::DatabaseError(
+                DatabaseErrorKind::UnableToSendCommand,
+                Box::new(e.to_string()),
+            )
+        })?;
+
+        match authors
+            .filter(name.eq(name_query))
+            .first::<Authors>(&mut conn)
+            .await
+        {
+            Ok(value) => Ok(Some(value)),
+            Err(Error::NotFound) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+    /// Returns the author with the exact given name, creating it first if it doesn't exist
+    pub async fn get_or_create(&self, author_name: &str) -> Result<Authors, Error> {
+        let mut conn = connect_from_pool().await.map_err(|e| {
+            Error::DatabaseError(
+                DatabaseErrorKind::UnableToSendCommand,
+                Box::new(e.to_string()),
+            )
+        })?;
+
+        let db_lock = lock_db();
+        let _guard: MutexGuard<()> = db_lock.lock().await;
+
+        self.get_or_create_in(&mut conn, author_name).await
+    }
+
+    /// Same as [`Self::get_or_create`], on the connection of a running transaction
+    pub async fn get_or_create_in(
+        &self,
+        conn: &mut DbConnection,
+        author_name: &str,
+    ) -> Result<Authors, Error> {
+        use crate::data::models::schema::authors::dsl::*;
+
+        conn.transaction(|connection| {
+            async move {
+                let existing = authors
+                    .filter(name.eq(author_name))
+                    .first::<Authors>(connection)
+                    .await
+                    .optional()?;
+
+                if let Some(author) = existing {
+                    return Ok(author);
+                }
+
+                diesel::insert_into(authors)
+                    .values(NewAuthor {
+                        name: author_name,
+                        sort_name: None,
+                    })
+                    .execute(connection)
+                    .await?;
+
+                authors
+                    .filter(name.eq(author_name))
+                    .order(author_id.desc())
+                    .first::<Authors>(connection)
+                    .await
+            }
+            .scope_boxed()
+        })
+        .await
+    }
+
+    /// Same as [`Self::update`], on the connection of a running transaction
+    pub async fn update_in(
+        &self,
+        conn: &mut DbConnection,
+        id: i32,
+        updated_item: AuthorForm<'_>,
+    ) -> Result<(), Error> {
+        use crate::data::models::schema::authors::dsl::*;
+
+        match conn
+            .transaction(|connection| {
+                async move {
+                    diesel::update(authors.filter(author_id.eq(id)))
+                        .set(updated_item)
+                        .execute(connection)
+                        .await?;
+
+                    Ok(())
+                }
+                .scope_boxed()
+            })
+            .await
+        {
+            Ok(author) => Ok(author),
+            Err(e) => Err(e),
+        }
+    }
 }
 
 #[async_trait]
@@ -118,8 +220,6 @@ impl Repository for AuthorRepo {
     }
 
     async fn update<'a>(&self, id: Self::Id, updated_item: Self::Form<'a>) -> Result<(), Error> {
-        use crate::data::models::schema::authors::dsl::*;
-
         let mut conn = connect_from_pool().await.map_err(|e| {
             Error::DatabaseError(
                 DatabaseErrorKind::UnableToSendCommand,
@@ -130,23 +230,7 @@ impl Repository for AuthorRepo {
         let db_lock = lock_db();
         let _guard: MutexGuard<()> = db_lock.lock().await;
 
-        match conn
-            .transaction(|connection| {
-                async move {
-                    diesel::update(authors.filter(author_id.eq(id)))
-                        .set(updated_item)
-                        .execute(connection)
-                        .await?;
-
-                    Ok(())
-                }
-                .scope_boxed()
-            })
-            .await
-        {
-            Ok(author) => Ok(author),
-            Err(e) => Err(e),
-        }
+        self.update_in(&mut conn, id, updated_item).await
     }
 
     async fn delete(&self, id: Self::Id) -> Result<(), Error> {
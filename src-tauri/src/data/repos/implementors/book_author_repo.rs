@@ -6,7 +6,7 @@ use diesel_async::{AsyncConnection, RunQueryDsl};
 use tokio::sync::MutexGuard;
 
 use crate::data::{
-    database::{connect_from_pool, lock_db},
+    database::{connect_from_pool, lock_db, DbConnection},
     models::{authors::Authors, book_authors::BookAuthors, books::Books},
     repos::traits::repository::Repository,
 };
@@ -19,8 +19,6 @@ impl BookAuthorRepo {
     }
 
     pub async fn get_authors_by_book(&self, bid: i32) -> Result<Option<Vec<Authors>>, Error> {
-        use crate::data::models::schema::{authors, book_authors};
-
         let mut conn = connect_from_pool().await.map_err(|e| {
             Error::DatabaseError(
                 DatabaseErrorKind::UnableToSendCommand,
@@ -28,11 +26,22 @@ impl BookAuthorRepo {
             )
         })?;
 
+        self.get_authors_by_book_in(&mut conn, bid).await
+    }
+
+    /// Same as [`Self::get_authors_by_book`], on the connection of a running transaction
+    pub async fn get_authors_by_book_in(
+        &self,
+        conn: &mut DbConnection,
+        bid: i32,
+    ) -> Result<Option<Vec<Authors>>, Error> {
+        use crate::data::models::schema::{authors, book_authors};
+
         return match book_authors::table
             .inner_join(authors::table.on(authors::author_id.eq(book_authors::author_id)))
             .filter(book_authors::book_id.eq(bid))
-            .select((authors::author_id, authors::name))
-            .load::<Authors>(&mut conn)
+            .select(Authors::as_select())
+            .load::<Authors>(conn)
             .await
         {
             Ok(value) if value.is_empty() => Ok(None),
@@ -55,17 +64,7 @@ impl BookAuthorRepo {
         return match book_authors::table
             .inner_join(books::table.on(books::book_id.eq(book_authors::book_id)))
             .filter(book_authors::author_id.eq(aid))
-            .select((
-                books::book_id,
-                books::title,
-                books::published_date,
-                books::publisher_id,
-                books::isbn,
-                books::file_type,
-                books::file_path,
-                books::cover_image_path,
-                books::added_at,
-            ))
+            .select(Books::as_select())
             .load::<Books>(&mut conn)
             .await
         {
@@ -75,6 +74,58 @@ impl BookAuthorRepo {
             Err(e) => Err(e),
         };
     }
+
+    /// Replaces every author link of a book with the given authors in a single transaction
+    pub async fn replace_authors(&self, bid: i32, author_ids: &[i32]) -> Result<(), Error> {
+        let mut conn = connect_from_pool().await.map_err(|e| {
+            Error::DatabaseError(
+                DatabaseErrorKind::UnableToSendCommand,
+                Box::new(e.to_string()),
+            )
+        })?;
+
+        let db_lock = lock_db();
+        let _guard: MutexGuard<()> = db_lock.lock().await;
+
+        self.replace_authors_in(&mut conn, bid, author_ids).await
+    }
+
+    /// Same as [`Self::replace_authors`], on the connection of a running transaction
+    pub async fn replace_authors_in(
+        &self,
+        conn: &mut DbConnection,
+        bid: i32,
+        author_ids: &[i32],
+    ) -> Result<(), Error> {
+        use crate::data::models::schema::book_authors::dsl::*;
+
+        let links: Vec<BookAuthors> = author_ids
+            .iter()
+            .map(|aid| BookAuthors {
+                book_id: bid,
+                author_id: *aid,
+            })
+            .collect();
+
+        conn.transaction(|connection| {
+            async move {
+                diesel::delete(book_authors.filter(book_id.eq(bid)))
+                    .execute(connection)
+                    .await?;
+
+                for link in links {
+                    diesel::insert_into(book_authors)
+                        .values(link)
+                        .on_conflict_do_nothing()
+                        .execute(connection)
+                        .await?;
+                }
+                Ok(())
+            }
+            .scope_boxed()
+        })
+        .await
+    }
 }
 
 #[async_trait]
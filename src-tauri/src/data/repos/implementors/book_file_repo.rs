@@ -0,0 +1,208 @@
+use async_trait::async_trait;
+use diesel::prelude::*;
+use diesel::result::{DatabaseErrorKind, Error};
+use diesel_async::scoped_futures::ScopedFutureExt;
+use diesel_async::{AsyncConnection, RunQueryDsl};
+use tokio::sync::MutexGuard;
+
+use crate::data::{
+    database::{connect_from_pool, lock_db, DbConnection},
+    models::book_files::{BookFiles, NewBookFile, UpdateBookFile},
+    repos::traits::repository::Repository,
+};
+
+pub struct BookFileRepo;
+
+impl BookFileRepo {
+    pub async fn new() -> Self {
+        BookFileRepo
+    }
+
+    pub async fn get_by_book(&self, bid: i32) -> Result<Option<Vec<BookFiles>>, Error> {
+        use crate::data::models::schema::book_files::dsl::*;
+
+        let mut conn = connect_from_pool().await.map_err(|e| {
+            Error::DatabaseError(
+                DatabaseErrorKind::UnableToSendCommand,
+                Box::new(e.to_string()),
+            )
+        })?;
+
+        match book_files
+            .filter(book_id.eq(bid))
+            .load::<BookFiles>(&mut conn)
+            .await
+        {
+            Ok(value) if value.is_empty() => Ok(None),
+            Ok(value) => Ok(Some(value)),
+            Err(Error::NotFound) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    pub async fn get_by_path(&self, path: &str) -> Result<Option<BookFiles>, Error> {
+        use crate::data::models::schema::book_files::dsl::*;
+
+        let mut conn = connect_from_pool().await.map_err(|e| {
+            Error::DatabaseError(
+                DatabaseErrorKind::UnableToSendCommand,
+                Box::new(e.to_string()),
+            )
+        })?;
+
+        match book_files
+            .filter(file_path.eq(path))
+            .first::<BookFiles>(&mut conn)
+            .await
+        {
+            Ok(value) => Ok(Some(value)),
+            Err(Error::NotFound) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Same as [`Self::add`], on the connection of a running transaction
+    pub async fn add_in(
+        &self,
+        conn: &mut DbConnection,
+        new_item: NewBookFile<'_>,
+    ) -> Result<(), Error> {
+        use crate::data::models::schema::book_files::dsl::*;
+
+        match conn
+            .transaction(|connection| {
+                async move {
+                    diesel::insert_into(book_files)
+                        .values(new_item)
+                        .execute(connection)
+                        .await?;
+
+                    Ok(())
+                }
+                .scope_boxed()
+            })
+            .await
+        {
+            Ok(_) => Ok(()),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+#[async_trait]
+impl Repository for BookFileRepo {
+    type Item = BookFiles;
+    type NewItem<'a> = NewBookFile<'a>;
+    type Form<'a> = UpdateBookFile<'a>;
+    type Id = i32;
+
+    async fn get_all(&self) -> Result<Option<Vec<Self::Item>>, Error> {
+        use crate::data::models::schema::book_files::dsl::*;
+
+        let mut conn = connect_from_pool().await.map_err(|e| {
+            Error::DatabaseError(
+                DatabaseErrorKind::UnableToSendCommand,
+                Box::new(e.to_string()),
+            )
+        })?;
+
+        match book_files.load::<Self::Item>(&mut conn).await {
+            Ok(value) if value.is_empty() => Ok(None),
+            Ok(value) => Ok(Some(value)),
+            Err(Error::NotFound) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn get_by_id(&self, id: Self::Id) -> Result<Option<Self::Item>, Error> {
+        use crate::data::models::schema::{book_files as book_file, book_files::dsl::*};
+
+        let mut conn = connect_from_pool().await.map_err(|e| {
+            Error::DatabaseError(
+                DatabaseErrorKind::UnableToSendCommand,
+                Box::new(e.to_string()),
+            )
+        })?;
+
+        match book_files
+            .filter(book_file::file_id.eq(id))
+            .first::<BookFiles>(&mut conn)
+            .await
+        {
+            Ok(value) => Ok(Some(value)),
+            Err(Error::NotFound) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn add<'a>(&self, new_item: Self::NewItem<'a>) -> Result<(), Error> {
+        let mut conn = connect_from_pool().await.map_err(|e| {
+            Error::DatabaseError(
+                DatabaseErrorKind::UnableToSendCommand,
+                Box::new(e.to_string()),
+            )
+        })?;
+
+        let db_lock = lock_db();
+        let _guard: MutexGuard<()> = db_lock.lock().await;
+
+        self.add_in(&mut conn, new_item).await
+    }
+
+    async fn update<'a>(&self, id: Self::Id, updated_item: Self::Form<'a>) -> Result<(), Error> {
+        use crate::data::models::schema::book_files::dsl::*;
+
+        let mut conn = connect_from_pool().await.map_err(|e| {
+            Error::DatabaseError(
+                DatabaseErrorKind::UnableToSendCommand,
+                Box::new(e.to_string()),
+            )
+        })?;
+
+        let db_lock = lock_db();
+        let _guard: MutexGuard<()> = db_lock.lock().await;
+
+        conn.transaction(|connection| {
+            async move {
+                diesel::update(book_files.filter(file_id.eq(id)))
+                    .set(updated_item)
+                    .execute(connection)
+                    .await?;
+
+                Ok(())
+            }
+            .scope_boxed()
+        })
+        .await
+    }
+
+    async fn delete(&self, id: Self::Id) -> Result<(), Error> {
+        use crate::data::models::schema::book_files::dsl::*;
+
+        let mut conn = connect_from_pool().await.map_err(|e| {
+            Error::DatabaseError(
+                DatabaseErrorKind::UnableToSendCommand,
+                Box::new(e.to_string()),
+            )
+        })?;
+
+        let db_lock = lock_db();
+        let _guard: MutexGuard<()> = db_lock.lock().await;
+
+        match conn
+            .transaction(|connection| {
+                async move {
+                    diesel::delete(book_files.filter(file_id.eq(id)))
+                        .execute(connection)
+                        .await?;
+                    Ok(())
+                }
+                .scope_boxed()
+            })
+            .await
+        {
+            Ok(_) => Ok(()),
+            Err(e) => Err(e),
+        }
+    }
+}
@@ -0,0 +1,257 @@
+use async_trait::async_trait;
+use diesel::prelude::*;
+use diesel::result::{DatabaseErrorKind, Error};
+use diesel_async::scoped_futures::ScopedFutureExt;
+use diesel_async::{AsyncConnection, RunQueryDsl};
+use tokio::sync::MutexGuard;
+
+use crate::data::{
+    database::{connect_from_pool, lock_db, DbConnection},
+    models::book_identifiers::{BookIdentifiers, NewBookIdentifier, UpdateBookIdentifier},
+    repos::traits::repository::Repository,
+};
+
+pub struct BookIdentifierRepo;
+
+impl BookIdentifierRepo {
+    pub async fn new() -> Self {
+        BookIdentifierRepo
+    }
+
+    pub async fn get_by_book(&self, bid: i32) -> Result<Option<Vec<BookIdentifiers>>, Error> {
+        use crate::data::models::schema::book_identifiers::dsl::*;
+
+        let mut conn = connect_from_pool().await.map_err(|e| {
+            Error::DatabaseError(
+                DatabaseErrorKind::UnableToSendCommand,
+                Box::new(e.to_string()),
+            )
+        })?;
+
+        match book_identifiers
+            .filter(book_id.eq(bid))
+            .order(scheme.asc())
+            .load::<BookIdentifiers>(&mut conn)
+            .await
+        {
+            Ok(found) if found.is_empty() => Ok(None),
+            Ok(found) => Ok(Some(found)),
+            Err(Error::NotFound) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    pub async fn get_by_value(
+        &self,
+        scheme_name: &str,
+        value_query: &str,
+    ) -> Result<Option<BookIdentifiers>, Error> {
+        use crate::data::models::schema::book_identifiers::dsl::*;
+
+        let mut conn = connect_from_pool().await.map_err(|e| {
+            Error::DatabaseError(
+                DatabaseErrorKind::UnableToSendCommand,
+                Box::new(e.to_string()),
+            )
+        })?;
+
+        match book_identifiers
+            .filter(scheme.eq(scheme_name).and(value.eq(value_query)))
+            .first::<BookIdentifiers>(&mut conn)
+            .await
+        {
+            Ok(found) => Ok(Some(found)),
+            Err(Error::NotFound) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Sets the value of an identifier scheme for a book, replacing any previous value
+    pub async fn set_identifier(
+        &self,
+        bid: i32,
+        scheme_name: &str,
+        new_value: &str,
+    ) -> Result<(), Error> {
+        let mut conn = connect_from_pool().await.map_err(|e| {
+            Error::DatabaseError(
+                DatabaseErrorKind::UnableToSendCommand,
+                Box::new(e.to_string()),
+            )
+        })?;
+
+        let db_lock = lock_db();
+        let _guard: MutexGuard<()> = db_lock.lock().await;
+
+        self.set_identifier_in(&mut conn, bid, scheme_name, new_value)
+            .await
+    }
+
+    /// Same as [`Self::set_identifier`], on the connection of a running transaction
+    pub async fn set_identifier_in(
+        &self,
+        conn: &mut DbConnection,
+        bid: i32,
+        scheme_name: &str,
+        new_value: &str,
+    ) -> Result<(), Error> {
+        use crate::data::models::schema::book_identifiers::dsl::*;
+
+        conn.transaction(|connection| {
+            async move {
+                diesel::insert_into(book_identifiers)
+                    .values(NewBookIdentifier {
+                        book_id: bid,
+                        scheme: scheme_name,
+                        value: new_value,
+                    })
+                    .on_conflict((book_id, scheme))
+                    .do_update()
+                    .set(value.eq(new_value))
+                    .execute(connection)
+                    .await?;
+                Ok(())
+            }
+            .scope_boxed()
+        })
+        .await
+    }
+}
+
+#[async_trait]
+impl Repository for BookIdentifierRepo {
+    type Item = BookIdentifiers;
+    type NewItem<'a> = NewBookIdentifier<'a>;
+    type Form<'a> = UpdateBookIdentifier<'a>;
+    type Id = i32;
+
+    async fn get_all(&self) -> Result<Option<Vec<Self::Item>>, Error> {
+        use crate::data::models::schema::book_identifiers::dsl::*;
+
+        let mut conn = connect_from_pool().await.map_err(|e| {
+            Error::DatabaseError(
+                DatabaseErrorKind::UnableToSendCommand,
+                Box::new(e.to_string()),
+            )
+        })?;
+
+        match book_identifiers.load::<Self::Item>(&mut conn).await {
+            Ok(found) if found.is_empty() => Ok(None),
+            Ok(found) => Ok(Some(found)),
+            Err(Error::NotFound) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn get_by_id(&self, id: Self::Id) -> Result<Option<Self::Item>, Error> {
+        use crate::data::models::schema::{
+            book_identifiers as book_identifier, book_identifiers::dsl::*,
+        };
+
+        let mut conn = connect_from_pool().await.map_err(|e| {
+            Error::DatabaseError(
+                DatabaseErrorKind::UnableToSendCommand,
+                Box::new(e.to_string()),
+            )
+        })?;
+
+        match book_identifiers
+            .filter(book_identifier::identifier_id.eq(id))
+            .first::<BookIdentifiers>(&mut conn)
+            .await
+        {
+            Ok(found) => Ok(Some(found)),
+            Err(Error::NotFound) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn add<'a>(&self, new_item: Self::NewItem<'a>) -> Result<(), Error> {
+        use crate::data::models::schema::book_identifiers::dsl::*;
+
+        let mut conn = connect_from_pool().await.map_err(|e| {
+            Error::DatabaseError(
+                DatabaseErrorKind::UnableToSendCommand,
+                Box::new(e.to_string()),
+            )
+        })?;
+
+        let db_lock = lock_db();
+        let _guard: MutexGuard<()> = db_lock.lock().await;
+
+        match conn
+            .transaction(|connection| {
+                async move {
+                    diesel::insert_into(book_identifiers)
+                        .values(new_item)
+                        .execute(connection)
+                        .await?;
+
+                    Ok(())
+                }
+                .scope_boxed()
+            })
+            .await
+        {
+            Ok(_) => Ok(()),
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn update<'a>(&self, id: Self::Id, updated_item: Self::Form<'a>) -> Result<(), Error> {
+        use crate::data::models::schema::book_identifiers::dsl::*;
+
+        let mut conn = connect_from_pool().await.map_err(|e| {
+            Error::DatabaseError(
+                DatabaseErrorKind::UnableToSendCommand,
+                Box::new(e.to_string()),
+            )
+        })?;
+
+        let db_lock = lock_db();
+        let _guard: MutexGuard<()> = db_lock.lock().await;
+
+        conn.transaction(|connection| {
+            async move {
+                diesel::update(book_identifiers.filter(identifier_id.eq(id)))
+                    .set(updated_item)
+                    .execute(connection)
+                    .await?;
+
+                Ok(())
+            }
+            .scope_boxed()
+        })
+        .await
+    }
+
+    async fn delete(&self, id: Self::Id) -> Result<(), Error> {
+        use crate::data::models::schema::book_identifiers::dsl::*;
+
+        let mut conn = connect_from_pool().await.map_err(|e| {
+            Error::DatabaseError(
+                DatabaseErrorKind::UnableToSendCommand,
+                Box::new(e.to_string()),
+            )
+        })?;
+
+        let db_lock = lock_db();
+        let _guard: MutexGuard<()> = db_lock.lock().await;
+
+        match conn
+            .transaction(|connection| {
+                async move {
+                    diesel::delete(book_identifiers.filter(identifier_id.eq(id)))
+                        .execute(connection)
+                        .await?;
+                    Ok(())
+                }
+                .scope_boxed()
+            })
+            .await
+        {
+            Ok(_) => Ok(()),
+            Err(e) => Err(e),
+        }
+    }
+}
@@ -0,0 +1,176 @@
+use async_trait::async_trait;
+use diesel::prelude::*;
+use diesel::result::{DatabaseErrorKind, Error};
+use diesel_async::scoped_futures::ScopedFutureExt;
+use diesel_async::{AsyncConnection, RunQueryDsl};
+use tokio::sync::MutexGuard;
+
+use crate::data::{
+    database::{connect_from_pool, lock_db, DbConnection},
+    models::book_metadata_sources::BookMetadataSources,
+    repos::traits::repository::Repository,
+};
+
+pub struct BookMetadataSourceRepo;
+
+impl BookMetadataSourceRepo {
+    pub async fn new() -> Self {
+        BookMetadataSourceRepo
+    }
+
+    pub async fn get_by_book(&self, bid: i32) -> Result<Option<Vec<BookMetadataSources>>, Error> {
+        use crate::data::models::schema::book_metadata_sources::dsl::*;
+
+        let mut conn = connect_from_pool().await.map_err(|e| {
+            Error::DatabaseError(
+                DatabaseErrorKind::UnableToSendCommand,
+                Box::new(e.to_string()),
+            )
+        })?;
+
+        match book_metadata_sources
+            .filter(book_id.eq(bid))
+            .order(field.asc())
+            .load::<BookMetadataSources>(&mut conn)
+            .await
+        {
+            Ok(value) if value.is_empty() => Ok(None),
+            Ok(value) => Ok(Some(value)),
+            Err(Error::NotFound) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Sets the source of the given fields, leaving the other fields of the book untouched
+    pub async fn set_sources(&self, bid: i32, sources: &[(&str, &str)]) -> Result<(), Error> {
+        let mut conn = connect_from_pool().await.map_err(|e| {
+            Error::DatabaseError(
+                DatabaseErrorKind::UnableToSendCommand,
+                Box::new(e.to_string()),
+            )
+        })?;
+
+        let db_lock = lock_db();
+        let _guard: MutexGuard<()> = db_lock.lock().await;
+
+        self.set_sources_in(&mut conn, bid, sources).await
+    }
+
+    /// Same as [`Self::set_sources`], on the connection of a running transaction
+    pub async fn set_sources_in(
+        &self,
+        conn: &mut DbConnection,
+        bid: i32,
+        sources: &[(&str, &str)],
+    ) -> Result<(), Error> {
+        use crate::data::models::schema::book_metadata_sources::dsl::*;
+
+        let rows: Vec<BookMetadataSources> = sources
+            .iter()
+            .map(|(f, s)| BookMetadataSources {
+                book_id: bid,
+                field: f.to_string(),
+                source: s.to_string(),
+            })
+            .collect();
+
+        conn.transaction(|connection| {
+            async move {
+                for row in rows {
+                    diesel::insert_into(book_metadata_sources)
+                        .values(&row)
+                        .on_conflict((book_id, field))
+                        .do_update()
+                        .set(source.eq(&row.source))
+                        .execute(connection)
+                        .await?;
+                }
+                Ok(())
+            }
+            .scope_boxed()
+        })
+        .await
+    }
+}
+
+#[async_trait]
+impl Repository for BookMetadataSourceRepo {
+    type Item = BookMetadataSources;
+    type NewItem<'a> = BookMetadataSources;
+    type Form<'a> = BookMetadataSources;
+    type Id = (i32, String); // Tuple: (book_id, field)
+
+    async fn get_all(&self) -> Result<Option<Vec<Self::Item>>, Error> {
+        use crate::data::models::schema::book_metadata_sources::dsl::*;
+
+        let mut conn = connect_from_pool().await.map_err(|e| {
+            Error::DatabaseError(
+                DatabaseErrorKind::UnableToSendCommand,
+                Box::new(e.to_string()),
+            )
+        })?;
+
+        match book_metadata_sources.load::<Self::Item>(&mut conn).await {
+            Ok(value) if value.is_empty() => Ok(None),
+            Ok(value) => Ok(Some(value)),
+            Err(Error::NotFound) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn get_by_id(&self, id: Self::Id) -> Result<Option<Self::Item>, Error> {
+        use crate::data::models::schema::book_metadata_sources::dsl::*;
+
+        let mut conn = connect_from_pool().await.map_err(|e| {
+            Error::DatabaseError(
+                DatabaseErrorKind::UnableToSendCommand,
+                Box::new(e.to_string()),
+            )
+        })?;
+
+        match book_metadata_sources
+            .filter(book_id.eq(id.0).and(field.eq(id.1)))
+            .first::<BookMetadataSources>(&mut conn)
+            .await
+        {
+            Ok(value) => Ok(Some(value)),
+            Err(Error::NotFound) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn add<'a>(&self, new_item: Self::NewItem<'a>) -> Result<(), Error> {
+        self.set_sources(new_item.book_id, &[(&new_item.field, &new_item.source)])
+            .await
+    }
+
+    async fn update<'a>(&self, id: Self::Id, updated_item: Self::Form<'a>) -> Result<(), Error> {
+        self.set_sources(id.0, &[(&id.1, &updated_item.source)])
+            .await
+    }
+
+    async fn delete(&self, id: Self::Id) -> Result<(), Error> {
+        use crate::data::models::schema::book_metadata_sources::dsl::*;
+
+        let mut conn = connect_from_pool().await.map_err(|e| {
+            Error::DatabaseError(
+                DatabaseErrorKind::UnableToSendCommand,
+                Box::new(e.to_string()),
+            )
+        })?;
+
+        let db_lock = lock_db();
+        let _guard: MutexGuard<()> = db_lock.lock().await;
+
+        conn.transaction(|connection| {
+            async move {
+                diesel::delete(book_metadata_sources.filter(book_id.eq(id.0).and(field.eq(id.1))))
+                    .execute(connection)
+                    .await?;
+                Ok(())
+            }
+            .scope_boxed()
+        })
+        .await
+    }
+}
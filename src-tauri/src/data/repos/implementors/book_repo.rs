@@ -1,15 +1,195 @@
 use async_trait::async_trait;
+use diesel::dsl::{count_star, sql};
 use diesel::prelude::*;
 use diesel::result::{DatabaseErrorKind, Error};
+use diesel::sql_types::{Bool, Text};
+use diesel::sqlite::Sqlite;
 use diesel_async::scoped_futures::ScopedFutureExt;
 use diesel_async::{AsyncConnection, RunQueryDsl};
 use tokio::sync::MutexGuard;
 
 use crate::data::{
-    database::{connect_from_pool, lock_db},
-    models::books::{Books, NewBook, UpdateBook},
+    database::{connect_from_pool, lock_db, DbConnection},
+    models::{
+        books::{Books, EditBook, NewBook, UpdateBook},
+        schema::{
+            book_authors, book_files, book_tags, books, reading_progress, series, tags,
+            user_library,
+        },
+    },
     repos::traits::repository::Repository,
 };
+use crate::parsers::isbn;
+
+/// Which books a paged query returns
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BookFilter {
+    All,
+    Author(i32),
+    Publisher(i32),
+    Library(i32),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BookOrder {
+    /// Alphabetical, ignoring case
+    Title,
+    /// By the sort name of the first author, ignoring case
+    Author,
+    /// Most recently added first
+    Newest,
+    /// Most recently published first, books without a date last
+    Published,
+}
+
+/// How far a user got with a book, by their reading progress
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReadState {
+    /// Never opened
+    Unread,
+    /// Opened but not finished
+    Reading,
+    /// Read to the end
+    Finished,
+}
+
+/// Which libraries' books a user can see, see
+/// [`LibraryAccessService`](crate::services::library_access_service::LibraryAccessService)
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum BookScope {
+    #[default]
+    All,
+    /// Books in these libraries, books that aren't in any library are left out
+    Libraries(Vec<i32>),
+}
+
+impl BookScope {
+    pub fn contains(&self, book: &Books) -> bool {
+        match self {
+            BookScope::All => true,
+            BookScope::Libraries(ids) => book.library_id.is_some_and(|id| ids.contains(&id)),
+        }
+    }
+}
+
+/// Further narrows the books of a [`BookFilter`], every set field has to match
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BookFacets {
+    /// Format of any of the book's files, compared ignoring case
+    pub format: Option<String>,
+    pub language: Option<String>,
+    pub tag_id: Option<i32>,
+    pub series_id: Option<i32>,
+    /// User whose progress `read_state` refers to, the state is ignored without one
+    pub user_id: Option<i32>,
+    pub read_state: Option<ReadState>,
+    /// Libraries the books have to be in
+    pub scope: BookScope,
+}
+
+/// Values the books of a [`BookFilter`] can be narrowed down by
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FacetValues {
+    /// Lowercase formats, sorted
+    pub formats: Vec<String>,
+    /// Language codes, sorted
+    pub languages: Vec<String>,
+    /// Most used tags as ids and names, sorted by name
+    pub tags: Vec<(i32, String)>,
+    /// Most used series as ids and names, sorted by name
+    pub series: Vec<(i32, String)>,
+}
+
+/// Number of tags and series offered as facets, large libraries have thousands of tags
+const FACET_VALUE_LIMIT: i64 = 20;
+
+fn filtered_books(filter: BookFilter, scope: &BookScope) -> books::BoxedQuery<'static, Sqlite> {
+    let query = match scope {
+        BookScope::All => books::table.into_boxed(),
+        BookScope::Libraries(ids) => books::table
+            .into_boxed()
+            .filter(books::library_id.eq_any(ids.clone())),
+    };
+    match filter {
+        BookFilter::All => query,
+        BookFilter::Author(id) => query.filter(
+            books::book_id.eq_any(
+                book_authors::table
+                    .filter(book_authors::author_id.eq(id))
+                    .select(book_authors::book_id),
+            ),
+        ),
+        BookFilter::Publisher(id) => query.filter(books::publisher_id.eq(id)),
+        BookFilter::Library(id) => query.filter(books::library_id.eq(id)),
+    }
+}
+
+fn faceted_books(filter: BookFilter, facets: &BookFacets) -> books::BoxedQuery<'static, Sqlite> {
+    let mut query = filtered_books(filter, &facets.scope);
+
+    if let Some(format) = &facets.format {
+        // Books without recorded files are only known by their main file's type.
+        // LIKE compares ASCII case-insensitively, so wildcards are escaped.
+        let pattern = format
+            .replace('\\', "\\\\")
+            .replace('%', "\\%")
+            .replace('_', "\\_");
+        query = query.filter(
+            books::book_id
+                .eq_any(
+                    book_files::table
+                        .filter(book_files::format.like(pattern.clone()).escape('\\'))
+                        .select(book_files::book_id),
+                )
+                .or(books::file_type
+                    .like(pattern)
+                    .escape('\\')
+                    .and(books::book_id.ne_all(book_files::table.select(book_files::book_id)))),
+        );
+    }
+    if let Some(language) = &facets.language {
+        query = query.filter(books::language.eq(language.clone()));
+    }
+    if let Some(id) = facets.tag_id {
+        query = query.filter(
+            books::book_id.eq_any(
+                book_tags::table
+                    .filter(book_tags::tag_id.eq(id))
+                    .select(book_tags::book_id),
+            ),
+        );
+    }
+    if let Some(id) = facets.series_id {
+        query = query.filter(books::series_id.eq(id));
+    }
+    if let (Some(user), Some(state)) = (facets.user_id, facets.read_state) {
+        let progress = reading_progress::table.filter(reading_progress::user_id.eq(user));
+        query = match state {
+            ReadState::Unread => {
+                query.filter(books::book_id.ne_all(progress.select(reading_progress::book_id)))
+            }
+            ReadState::Reading => query.filter(
+                books::book_id.eq_any(
+                    progress
+                        .filter(
+                            reading_progress::progress_percentage
+                                .lt(100.0)
+                                .or(reading_progress::progress_percentage.is_null()),
+                        )
+                        .select(reading_progress::book_id),
+                ),
+            ),
+            ReadState::Finished => query.filter(
+                books::book_id.eq_any(
+                    progress
+                        .filter(reading_progress::progress_percentage.ge(100.0))
+                        .select(reading_progress::book_id),
+                ),
+            ),
+        };
+    }
+    query
+}
 
 pub struct BookRepo;
 
@@ -18,6 +198,47 @@ impl BookRepo {
         BookRepo
     }
 
+    /// Inserts a book and returns the id it was assigned
+    pub async fn add_returning_id(&self, new_item: NewBook<'_>) -> Result<i32, Error> {
+        let mut conn = connect_from_pool().await.map_err(|e| {
+            Error::DatabaseError(
+                DatabaseErrorKind::UnableToSendCommand,
+                Box::new(e.to_string()),
+            )
+        })?;
+
+        let db_lock = lock_db();
+        let _guard: MutexGuard<()> = db_lock.lock().await;
+
+        self.add_returning_id_in(&mut conn, new_item).await
+    }
+
+    /// Same as [`Self::add_returning_id`], on the connection of a running transaction
+    pub async fn add_returning_id_in(
+        &self,
+        conn: &mut DbConnection,
+        new_item: NewBook<'_>,
+    ) -> Result<i32, Error> {
+        use crate::data::models::schema::books::dsl::*;
+
+        conn.transaction(|connection| {
+            async move {
+                diesel::insert_into(books)
+                    .values(new_item)
+                    .execute(connection)
+                    .await?;
+
+                books
+                    .select(book_id)
+                    .order(book_id.desc())
+                    .first::<i32>(connection)
+                    .await
+            }
+            .scope_boxed()
+        })
+        .await
+    }
+
     pub async fn search_by_title(&self, title_query: &str) -> Result<Option<Vec<Books>>, Error> {
         use crate::data::models::schema::books::dsl::*;
 
@@ -63,7 +284,7 @@ impl BookRepo {
     }
 
     pub async fn search_by_isbn(&self, isbn_query: &str) -> Result<Option<Vec<Books>>, Error> {
-        use crate::data::models::schema::books::dsl::*;
+        use crate::data::models::schema::books;
 
         let mut conn = connect_from_pool().await.map_err(|e| {
             Error::DatabaseError(
@@ -72,8 +293,49 @@ impl BookRepo {
             )
         })?;
 
-        return match books
-            .filter(isbn.like(format!("%{}%", isbn_query)))
+        let result = match isbn::normalize(isbn_query) {
+            Some(canonical) => {
+                books::table
+                    .filter(books::isbn.eq(canonical))
+                    .load::<Books>(&mut conn)
+                    .await
+            }
+            None => {
+                let digits = isbn::strip(isbn_query);
+                if digits.is_empty() {
+                    return Ok(None);
+                }
+                books::table
+                    .filter(books::isbn.like(format!("%{}%", digits)))
+                    .load::<Books>(&mut conn)
+                    .await
+            }
+        };
+
+        match result {
+            Ok(value) if value.is_empty() => Ok(None),
+            Ok(value) => Ok(Some(value)),
+            Err(Error::NotFound) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Returns the books whose ISBN is not stored as 13 digits, e.g. ISBN-10s from older scans
+    pub async fn get_with_uncanonical_isbn(&self) -> Result<Option<Vec<Books>>, Error> {
+        use crate::data::models::schema::books;
+
+        let mut conn = connect_from_pool().await.map_err(|e| {
+            Error::DatabaseError(
+                DatabaseErrorKind::UnableToSendCommand,
+                Box::new(e.to_string()),
+            )
+        })?;
+
+        match books::table
+            .filter(books::isbn.is_not_null())
+            .filter(sql::<Bool>(
+                "(length(books.isbn) != 13 OR books.isbn GLOB '*[^0-9]*')",
+            ))
             .load::<Books>(&mut conn)
             .await
         {
@@ -81,7 +343,266 @@ impl BookRepo {
             Ok(value) => Ok(Some(value)),
             Err(Error::NotFound) => Ok(None),
             Err(e) => Err(e),
+        }
+    }
+
+    /// Returns one page of books along with the total number of books matching the filter
+    pub async fn get_page(
+        &self,
+        filter: BookFilter,
+        facets: &BookFacets,
+        order: BookOrder,
+        offset: i64,
+        limit: i64,
+    ) -> Result<(Vec<Books>, i64), Error> {
+        let mut conn = connect_from_pool().await.map_err(|e| {
+            Error::DatabaseError(
+                DatabaseErrorKind::UnableToSendCommand,
+                Box::new(e.to_string()),
+            )
+        })?;
+
+        let total = faceted_books(filter, facets)
+            .count()
+            .get_result::<i64>(&mut conn)
+            .await?;
+
+        let query = match order {
+            BookOrder::Title => faceted_books(filter, facets)
+                .order(sql::<Text>("title COLLATE NOCASE"))
+                .then_order_by(books::book_id.asc()),
+            BookOrder::Author => faceted_books(filter, facets)
+                .order(sql::<Text>(
+                    "(SELECT MIN(COALESCE(a.sort_name, a.name)) FROM book_authors ba \
+                     JOIN authors a ON a.author_id = ba.author_id \
+                     WHERE ba.book_id = books.book_id) COLLATE NOCASE",
+                ))
+                .then_order_by(sql::<Text>("title COLLATE NOCASE"))
+                .then_order_by(books::book_id.asc()),
+            BookOrder::Newest => faceted_books(filter, facets)
+                .order(books::added_at.desc())
+                .then_order_by(books::book_id.desc()),
+            BookOrder::Published => faceted_books(filter, facets)
+                .order(sql::<Bool>("published_date IS NULL"))
+                .then_order_by(books::published_date.desc())
+                .then_order_by(books::book_id.desc()),
         };
+        let page = query
+            .offset(offset)
+            .limit(limit)
+            .load::<Books>(&mut conn)
+            .await?;
+
+        Ok((page, total))
+    }
+
+    /// Collects the formats, languages, tags and series of the books matching the filter
+    pub async fn facet_values(
+        &self,
+        filter: BookFilter,
+        scope: &BookScope,
+    ) -> Result<FacetValues, Error> {
+        let mut conn = connect_from_pool().await.map_err(|e| {
+            Error::DatabaseError(
+                DatabaseErrorKind::UnableToSendCommand,
+                Box::new(e.to_string()),
+            )
+        })?;
+
+        let ids = || filtered_books(filter, scope).select(books::book_id);
+
+        let mut formats: Vec<String> = book_files::table
+            .filter(book_files::book_id.eq_any(ids()))
+            .select(book_files::format)
+            .distinct()
+            .load::<String>(&mut conn)
+            .await?;
+        formats.extend(
+            filtered_books(filter, scope)
+                .filter(books::book_id.ne_all(book_files::table.select(book_files::book_id)))
+                .select(books::file_type)
+                .distinct()
+                .load::<Option<String>>(&mut conn)
+                .await?
+                .into_iter()
+                .flatten(),
+        );
+        let mut formats: Vec<String> = formats
+            .into_iter()
+            .map(|f| f.to_lowercase())
+            .filter(|f| !f.is_empty())
+            .collect();
+        formats.sort();
+        formats.dedup();
+
+        let languages = filtered_books(filter, scope)
+            .filter(books::language.is_not_null())
+            .select(books::language)
+            .distinct()
+            .order(books::language.asc())
+            .load::<Option<String>>(&mut conn)
+            .await?
+            .into_iter()
+            .flatten()
+            .collect();
+
+        let mut tags = book_tags::table
+            .inner_join(tags::table)
+            .filter(book_tags::book_id.eq_any(ids()))
+            .group_by((tags::tag_id, tags::name))
+            .select((tags::tag_id, tags::name))
+            .order((count_star().desc(), tags::tag_id.asc()))
+            .limit(FACET_VALUE_LIMIT)
+            .load::<(i32, String)>(&mut conn)
+            .await?;
+        tags.sort_by_key(|(_, name)| name.to_lowercase());
+
+        let mut series = series::table
+            .inner_join(books::table)
+            .filter(books::book_id.eq_any(ids()))
+            .group_by((series::series_id, series::name))
+            .select((series::series_id, series::name))
+            .order((count_star().desc(), series::series_id.asc()))
+            .limit(FACET_VALUE_LIMIT)
+            .load::<(i32, String)>(&mut conn)
+            .await?;
+        series.sort_by_key(|(_, name)| name.to_lowercase());
+
+        Ok(FacetValues {
+            formats,
+            languages,
+            tags,
+            series,
+        })
+    }
+
+    /// Authors of at least one book in the scope
+    pub async fn author_ids(&self, scope: &BookScope) -> Result<Vec<i32>, Error> {
+        let mut conn = connect_from_pool().await.map_err(|e| {
+            Error::DatabaseError(
+                DatabaseErrorKind::UnableToSendCommand,
+                Box::new(e.to_string()),
+            )
+        })?;
+
+        book_authors::table
+            .filter(
+                book_authors::book_id
+                    .eq_any(filtered_books(BookFilter::All, scope).select(books::book_id)),
+            )
+            .select(book_authors::author_id)
+            .distinct()
+            .load::<i32>(&mut conn)
+            .await
+    }
+
+    /// Publishers of at least one book in the scope
+    pub async fn publisher_ids(&self, scope: &BookScope) -> Result<Vec<i32>, Error> {
+        let mut conn = connect_from_pool().await.map_err(|e| {
+            Error::DatabaseError(
+                DatabaseErrorKind::UnableToSendCommand,
+                Box::new(e.to_string()),
+            )
+        })?;
+
+        Ok(filtered_books(BookFilter::All, scope)
+            .filter(books::publisher_id.is_not_null())
+            .select(books::publisher_id)
+            .distinct()
+            .load::<Option<i32>>(&mut conn)
+            .await?
+            .into_iter()
+            .flatten()
+            .collect())
+    }
+
+    /// Applies an [`EditBook`], which unlike `update` can also clear columns
+    pub async fn edit(&self, bid: i32, edit: EditBook<'_>) -> Result<(), Error> {
+        let mut conn = connect_from_pool().await.map_err(|e| {
+            Error::DatabaseError(
+                DatabaseErrorKind::UnableToSendCommand,
+                Box::new(e.to_string()),
+            )
+        })?;
+
+        let db_lock = lock_db();
+        let _guard: MutexGuard<()> = db_lock.lock().await;
+
+        self.edit_in(&mut conn, bid, edit).await
+    }
+
+    /// Same as [`Self::edit`], on the connection of a running transaction
+    pub async fn edit_in(
+        &self,
+        conn: &mut DbConnection,
+        bid: i32,
+        edit: EditBook<'_>,
+    ) -> Result<(), Error> {
+        use crate::data::models::schema::books::dsl::*;
+
+        // Diesel rejects an update without any column to set
+        if edit == EditBook::default() {
+            return Ok(());
+        }
+
+        conn.transaction(|connection| {
+            async move {
+                diesel::update(books.filter(book_id.eq(bid)))
+                    .set(edit)
+                    .execute(connection)
+                    .await?;
+                Ok(())
+            }
+            .scope_boxed()
+        })
+        .await
+    }
+
+    /// Same as [`Self::get_by_id`], on the connection of a running transaction
+    pub async fn get_by_id_in(
+        &self,
+        conn: &mut DbConnection,
+        id: i32,
+    ) -> Result<Option<Books>, Error> {
+        use crate::data::models::schema::{books as book, books::dsl::*};
+
+        match books
+            .filter(book::book_id.eq(id))
+            .first::<Books>(conn)
+            .await
+        {
+            Ok(value) => Ok(Some(value)),
+            Err(Error::NotFound) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Same as [`Self::update`], on the connection of a running transaction
+    pub async fn update_in(
+        &self,
+        conn: &mut DbConnection,
+        id: i32,
+        updated_item: UpdateBook<'_>,
+    ) -> Result<(), Error> {
+        use crate::data::models::schema::books::dsl::*;
+
+        match conn
+            .transaction(|connection| {
+                async move {
+                    diesel::update(books.filter(book_id.eq(id)))
+                        .set(updated_item)
+                        .execute(connection)
+                        .await?;
+
+                    Ok(())
+                }
+                .scope_boxed()
+            })
+            .await
+        {
+            Ok(book) => Ok(book),
+            Err(e) => Err(e),
+        }
     }
 }
 
@@ -111,8 +632,6 @@ impl Repository for BookRepo {
     }
 
     async fn get_by_id(&self, id: Self::Id) -> Result<Option<Self::Item>, Error> {
-        use crate::data::models::schema::{books as book, books::dsl::*};
-
         let mut conn = connect_from_pool().await.map_err(|e| {
             Error::DatabaseError(
                 DatabaseErrorKind::UnableToSendCommand,
@@ -120,15 +639,7 @@ impl Repository for BookRepo {
             )
         })?;
 
-        match books
-            .filter(book::book_id.eq(id))
-            .first::<Books>(&mut conn)
-            .await
-        {
-            Ok(value) => Ok(Some(value)),
-            Err(Error::NotFound) => Ok(None),
-            Err(e) => Err(e),
-        }
+        self.get_by_id_in(&mut conn, id).await
     }
 
     async fn add<'a>(&self, new_item: Self::NewItem<'a>) -> Result<(), Error> {
@@ -164,8 +675,6 @@ impl Repository for BookRepo {
     }
 
     async fn update<'a>(&self, id: Self::Id, updated_item: Self::Form<'a>) -> Result<(), Error> {
-        use crate::data::models::schema::books::dsl::*;
-
         let mut conn = connect_from_pool().await.map_err(|e| {
             Error::DatabaseError(
                 DatabaseErrorKind::UnableToSendCommand,
@@ -176,23 +685,7 @@ impl Repository for BookRepo {
         let db_lock = lock_db();
         let _guard: MutexGuard<()> = db_lock.lock().await;
 
-        match conn
-            .transaction(|connection| {
-                async move {
-                    diesel::update(books.filter(book_id.eq(id)))
-                        .set(updated_item)
-                        .execute(connection)
-                        .await?;
-
-                    Ok(())
-                }
-                .scope_boxed()
-            })
-            .await
-        {
-            Ok(book) => Ok(book),
-            Err(e) => Err(e),
-        }
+        self.update_in(&mut conn, id, updated_item).await
     }
 
     async fn delete(&self, id: Self::Id) -> Result<(), Error> {
@@ -211,6 +704,10 @@ impl Repository for BookRepo {
         match conn
             .transaction(|connection| {
                 async move {
+                    // The only reference to books that doesn't cascade
+                    diesel::delete(user_library::table.filter(user_library::book_id.eq(id)))
+                        .execute(connection)
+                        .await?;
                     diesel::delete(books.filter(book_id.eq(id)))
                         .execute(connection)
                         .await?;
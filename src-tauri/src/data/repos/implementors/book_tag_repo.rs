@@ -0,0 +1,243 @@
+use async_trait::async_trait;
+use diesel::prelude::*;
+use diesel::result::{DatabaseErrorKind, Error};
+use diesel_async::scoped_futures::ScopedFutureExt;
+use diesel_async::{AsyncConnection, RunQueryDsl};
+use tokio::sync::MutexGuard;
+
+use crate::data::{
+    database::{connect_from_pool, lock_db, DbConnection},
+    models::{book_tags::BookTags, books::Books, tags::Tags},
+    repos::traits::repository::Repository,
+};
+
+pub struct BookTagRepo;
+
+impl BookTagRepo {
+    pub async fn new() -> Self {
+        BookTagRepo
+    }
+
+    pub async fn get_tags_by_book(&self, bid: i32) -> Result<Option<Vec<Tags>>, Error> {
+        let mut conn = connect_from_pool().await.map_err(|e| {
+            Error::DatabaseError(
+                DatabaseErrorKind::UnableToSendCommand,
+                Box::new(e.to_string()),
+            )
+        })?;
+
+        self.get_tags_by_book_in(&mut conn, bid).await
+    }
+
+    /// Same as [`Self::get_tags_by_book`], on the connection of a running transaction
+    pub async fn get_tags_by_book_in(
+        &self,
+        conn: &mut DbConnection,
+        bid: i32,
+    ) -> Result<Option<Vec<Tags>>, Error> {
+        use crate::data::models::schema::{book_tags, tags};
+
+        return match book_tags::table
+            .inner_join(tags::table.on(tags::tag_id.eq(book_tags::tag_id)))
+            .filter(book_tags::book_id.eq(bid))
+            .select(Tags::as_select())
+            .load::<Tags>(conn)
+            .await
+        {
+            Ok(value) if value.is_empty() => Ok(None),
+            Ok(value) => Ok(Some(value)),
+            Err(Error::NotFound) => Ok(None),
+            Err(e) => Err(e),
+        };
+    }
+
+    pub async fn get_books_by_tag(&self, tid: i32) -> Result<Option<Vec<Books>>, Error> {
+        use crate::data::models::schema::{book_tags, books};
+
+        let mut conn = connect_from_pool().await.map_err(|e| {
+            Error::DatabaseError(
+                DatabaseErrorKind::UnableToSendCommand,
+                Box::new(e.to_string()),
+            )
+        })?;
+
+        return match book_tags::table
+            .inner_join(books::table.on(books::book_id.eq(book_tags::book_id)))
+            .filter(book_tags::tag_id.eq(tid))
+            .select(Books::as_select())
+            .load::<Books>(&mut conn)
+            .await
+        {
+            Ok(value) if value.is_empty() => Ok(None),
+            Ok(value) => Ok(Some(value)),
+            Err(Error::NotFound) => Ok(None),
+            Err(e) => Err(e),
+        };
+    }
+
+    /// Replaces every tag link of a book with the given tags in a single transaction
+    pub async fn replace_tags(&self, bid: i32, tag_ids: &[i32]) -> Result<(), Error> {
+        let mut conn = connect_from_pool().await.map_err(|e| {
+            Error::DatabaseError(
+                DatabaseErrorKind::UnableToSendCommand,
+                Box::new(e.to_string()),
+            )
+        })?;
+
+        let db_lock = lock_db();
+        let _guard: MutexGuard<()> = db_lock.lock().await;
+
+        self.replace_tags_in(&mut conn, bid, tag_ids).await
+    }
+
+    /// Same as [`Self::replace_tags`], on the connection of a running transaction
+    pub async fn replace_tags_in(
+        &self,
+        conn: &mut DbConnection,
+        bid: i32,
+        tag_ids: &[i32],
+    ) -> Result<(), Error> {
+        use crate::data::models::schema::book_tags::dsl::*;
+
+        let links: Vec<BookTags> = tag_ids
+            .iter()
+            .map(|tid| BookTags {
+                book_id: bid,
+                tag_id: *tid,
+            })
+            .collect();
+
+        conn.transaction(|connection| {
+            async move {
+                diesel::delete(book_tags.filter(book_id.eq(bid)))
+                    .execute(connection)
+                    .await?;
+
+                for link in links {
+                    diesel::insert_into(book_tags)
+                        .values(link)
+                        .on_conflict_do_nothing()
+                        .execute(connection)
+                        .await?;
+                }
+                Ok(())
+            }
+            .scope_boxed()
+        })
+        .await
+    }
+}
+
+#[async_trait]
+impl Repository for BookTagRepo {
+    type Item = BookTags;
+    type NewItem<'a> = BookTags; // Insertable is same as the main struct
+    type Form<'a> = BookTags; // No update form needed for junction tables
+    type Id = (i32, i32); // Tuple: (book_id, tag_id)
+
+    async fn get_all(&self) -> Result<Option<Vec<Self::Item>>, Error> {
+        use crate::data::models::schema::book_tags::dsl::*;
+
+        let mut conn = connect_from_pool().await.map_err(|e| {
+            Error::DatabaseError(
+                DatabaseErrorKind::UnableToSendCommand,
+                Box::new(e.to_string()),
+            )
+        })?;
+
+        match book_tags.load::<Self::Item>(&mut conn).await {
+            Ok(value) if value.is_empty() => Ok(None),
+            Ok(value) => Ok(Some(value)),
+            Err(Error::NotFound) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn get_by_id(&self, id: Self::Id) -> Result<Option<Self::Item>, Error> {
+        use crate::data::models::schema::book_tags::dsl::*;
+
+        let mut conn = connect_from_pool().await.map_err(|e| {
+            Error::DatabaseError(
+                DatabaseErrorKind::UnableToSendCommand,
+                Box::new(e.to_string()),
+            )
+        })?;
+
+        match book_tags
+            .filter(book_id.eq(id.0).and(tag_id.eq(id.1)))
+            .first::<BookTags>(&mut conn)
+            .await
+        {
+            Ok(value) => Ok(Some(value)),
+            Err(Error::NotFound) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn add<'a>(&self, new_item: Self::NewItem<'a>) -> Result<(), Error> {
+        use crate::data::models::schema::book_tags::dsl::*;
+
+        let mut conn = connect_from_pool().await.map_err(|e| {
+            Error::DatabaseError(
+                DatabaseErrorKind::UnableToSendCommand,
+                Box::new(e.to_string()),
+            )
+        })?;
+
+        let db_lock = lock_db();
+        let _guard: MutexGuard<()> = db_lock.lock().await;
+
+        match conn
+            .transaction(|connection| {
+                async move {
+                    diesel::insert_into(book_tags)
+                        .values(new_item)
+                        .execute(connection)
+                        .await?;
+
+                    Ok(())
+                }
+                .scope_boxed()
+            })
+            .await
+        {
+            Ok(_) => Ok(()),
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn update<'a>(&self, _id: Self::Id, _updated_item: Self::Form<'a>) -> Result<(), Error> {
+        // Junction tables typically don't support updates - delete and re-add instead
+        Err(Error::NotFound)
+    }
+
+    async fn delete(&self, id: Self::Id) -> Result<(), Error> {
+        use crate::data::models::schema::book_tags::dsl::*;
+
+        let mut conn = connect_from_pool().await.map_err(|e| {
+            Error::DatabaseError(
+                DatabaseErrorKind::UnableToSendCommand,
+                Box::new(e.to_string()),
+            )
+        })?;
+
+        let db_lock = lock_db();
+        let _guard: MutexGuard<()> = db_lock.lock().await;
+
+        match conn
+            .transaction(|connection| {
+                async move {
+                    diesel::delete(book_tags.filter(book_id.eq(id.0).and(tag_id.eq(id.1))))
+                        .execute(connection)
+                        .await?;
+                    Ok(())
+                }
+                .scope_boxed()
+            })
+            .await
+        {
+            Ok(_) => Ok(()),
+            Err(e) => Err(e),
+        }
+    }
+}
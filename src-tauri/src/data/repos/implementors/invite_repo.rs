@@ -0,0 +1,234 @@
+use async_trait::async_trait;
+use diesel::prelude::*;
+use diesel::result::{DatabaseErrorKind, Error};
+use diesel_async::scoped_futures::ScopedFutureExt;
+use diesel_async::{AsyncConnection, RunQueryDsl};
+use tokio::sync::MutexGuard;
+
+use crate::data::{
+    database::{connect_from_pool, lock_db},
+    models::invites::{Invites, NewInvite, UpdateInvite},
+    repos::traits::repository::Repository,
+};
+
+pub struct InviteRepo;
+
+impl InviteRepo {
+    pub async fn new() -> Self {
+        InviteRepo
+    }
+
+    pub async fn get_by_code_hash(&self, hash: &str) -> Result<Option<Invites>, Error> {
+        use crate::data::models::schema::invites::dsl::*;
+
+        let mut conn = connect_from_pool().await.map_err(|e| {
+            Error::DatabaseError(
+                DatabaseErrorKind::UnableToSendCommand,
+                Box::new(e.to_string()),
+            )
+        })?;
+
+        match invites
+            .filter(code_hash.eq(hash))
+            .first::<Invites>(&mut conn)
+            .await
+        {
+            Ok(value) => Ok(Some(value)),
+            Err(Error::NotFound) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    pub async fn add_returning_id(&self, new_item: NewInvite<'_>) -> Result<i32, Error> {
+        use crate::data::models::schema::invites::dsl::*;
+
+        let mut conn = connect_from_pool().await.map_err(|e| {
+            Error::DatabaseError(
+                DatabaseErrorKind::UnableToSendCommand,
+                Box::new(e.to_string()),
+            )
+        })?;
+
+        let db_lock = lock_db();
+        let _guard: MutexGuard<()> = db_lock.lock().await;
+
+        conn.transaction(|connection| {
+            async move {
+                diesel::insert_into(invites)
+                    .values(new_item)
+                    .execute(connection)
+                    .await?;
+
+                invites
+                    .select(invite_id)
+                    .order(invite_id.desc())
+                    .first::<i32>(connection)
+                    .await
+            }
+            .scope_boxed()
+        })
+        .await
+    }
+
+    /// Marks an unused, unexpired invite as redeemed by `user`. Returns `false` if it
+    /// was already used or expired at `now`.
+    pub async fn consume(&self, id: i32, user: i32, now: &str) -> Result<bool, Error> {
+        use crate::data::models::schema::invites::dsl::*;
+
+        let mut conn = connect_from_pool().await.map_err(|e| {
+            Error::DatabaseError(
+                DatabaseErrorKind::UnableToSendCommand,
+                Box::new(e.to_string()),
+            )
+        })?;
+
+        let db_lock = lock_db();
+        let _guard: MutexGuard<()> = db_lock.lock().await;
+
+        conn.transaction(|connection| {
+            async move {
+                let updated = diesel::update(
+                    invites
+                        .filter(invite_id.eq(id))
+                        .filter(used_at.is_null())
+                        .filter(expires_at.gt(now)),
+                )
+                .set((used_at.eq(now), used_by.eq(user)))
+                .execute(connection)
+                .await?;
+                Ok(updated > 0)
+            }
+            .scope_boxed()
+        })
+        .await
+    }
+}
+
+#[async_trait]
+impl Repository for InviteRepo {
+    type Item = Invites;
+    type NewItem<'a> = NewInvite<'a>;
+    type Form<'a> = UpdateInvite<'a>;
+    type Id = i32;
+
+    async fn get_all(&self) -> Result<Option<Vec<Self::Item>>, Error> {
+        use crate::data::models::schema::invites::dsl::*;
+
+        let mut conn = connect_from_pool().await.map_err(|e| {
+            Error::DatabaseError(
+                DatabaseErrorKind::UnableToSendCommand,
+                Box::new(e.to_string()),
+            )
+        })?;
+
+        match invites.load::<Self::Item>(&mut conn).await {
+            Ok(value) if value.is_empty() => Ok(None),
+            Ok(value) => Ok(Some(value)),
+            Err(Error::NotFound) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn get_by_id(&self, id: Self::Id) -> Result<Option<Self::Item>, Error> {
+        use crate::data::models::schema::invites::dsl::*;
+
+        let mut conn = connect_from_pool().await.map_err(|e| {
+            Error::DatabaseError(
+                DatabaseErrorKind::UnableToSendCommand,
+                Box::new(e.to_string()),
+            )
+        })?;
+
+        match invites
+            .filter(invite_id.eq(id))
+            .first::<Invites>(&mut conn)
+            .await
+        {
+            Ok(value) => Ok(Some(value)),
+            Err(Error::NotFound) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn add<'a>(&self, new_item: Self::NewItem<'a>) -> Result<(), Error> {
+        use crate::data::models::schema::invites::dsl::*;
+
+        let mut conn = connect_from_pool().await.map_err(|e| {
+            Error::DatabaseError(
+                DatabaseErrorKind::UnableToSendCommand,
+                Box::new(e.to_string()),
+            )
+        })?;
+
+        let db_lock = lock_db();
+        let _guard: MutexGuard<()> = db_lock.lock().await;
+
+        conn.transaction(|connection| {
+            async move {
+                diesel::insert_into(invites)
+                    .values(new_item)
+                    .execute(connection)
+                    .await?;
+                Ok(())
+            }
+            .scope_boxed()
+        })
+        .await
+    }
+
+    async fn update<'a>(&self, id: Self::Id, updated_item: Self::Form<'a>) -> Result<(), Error> {
+        use crate::data::models::schema::invites::dsl::*;
+
+        // Diesel rejects an update without any column to set
+        if updated_item == UpdateInvite::default() {
+            return Ok(());
+        }
+
+        let mut conn = connect_from_pool().await.map_err(|e| {
+            Error::DatabaseError(
+                DatabaseErrorKind::UnableToSendCommand,
+                Box::new(e.to_string()),
+            )
+        })?;
+
+        let db_lock = lock_db();
+        let _guard: MutexGuard<()> = db_lock.lock().await;
+
+        conn.transaction(|connection| {
+            async move {
+                diesel::update(invites.filter(invite_id.eq(id)))
+                    .set(updated_item)
+                    .execute(connection)
+                    .await?;
+                Ok(())
+            }
+            .scope_boxed()
+        })
+        .await
+    }
+
+    async fn delete(&self, id: Self::Id) -> Result<(), Error> {
+        use crate::data::models::schema::invites::dsl::*;
+
+        let mut conn = connect_from_pool().await.map_err(|e| {
+            Error::DatabaseError(
+                DatabaseErrorKind::UnableToSendCommand,
+                Box::new(e.to_string()),
+            )
+        })?;
+
+        let db_lock = lock_db();
+        let _guard: MutexGuard<()> = db_lock.lock().await;
+
+        conn.transaction(|connection| {
+            async move {
+                diesel::delete(invites.filter(invite_id.eq(id)))
+                    .execute(connection)
+                    .await?;
+                Ok(())
+            }
+            .scope_boxed()
+        })
+        .await
+    }
+}
@@ -5,7 +5,7 @@ use diesel_async::{scoped_futures::ScopedFutureExt, AsyncConnection, RunQueryDsl
 
 use crate::data::{
     database::{connect_from_pool, lock_db},
-    models::libraries::{Library, NewLibrary, UpdateLibrary},
+    models::libraries::{Library, NewLibrary, UpdateLibrary, Visibility},
     repos::traits::repository::Repository,
 };
 
@@ -37,6 +37,60 @@ impl LibraryRepo {
             Err(e) => Err(e),
         };
     }
+
+    pub async fn get_by_path(&self, path_query: &str) -> Result<Option<Library>, Error> {
+        use crate::data::models::schema::libraries::dsl::*;
+
+        let mut conn = connect_from_pool().await.map_err(|e| {
+            Error::DatabaseError(
+                DatabaseErrorKind::UnableToSendCommand,
+                Box::new(e.to_string()),
+            )
+        })?;
+
+        match libraries
+            .filter(path.eq(path_query))
+            .first::<Library>(&mut conn)
+            .await
+        {
+            Ok(value) => Ok(Some(value)),
+            Err(Error::NotFound) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Ids of the libraries that are public, owned by the user, or shared with the
+    /// user or one of `roles`
+    pub async fn visible_ids(&self, user: i32, roles: Vec<&str>) -> Result<Vec<i32>, Error> {
+        use crate::data::models::schema::{libraries::dsl::*, library_shares};
+
+        let mut conn = connect_from_pool().await.map_err(|e| {
+            Error::DatabaseError(
+                DatabaseErrorKind::UnableToSendCommand,
+                Box::new(e.to_string()),
+            )
+        })?;
+
+        let shared = library_shares::table
+            .filter(
+                library_shares::user_id
+                    .eq(user)
+                    .or(library_shares::role.eq_any(roles)),
+            )
+            .select(library_shares::library_id);
+
+        libraries
+            .filter(
+                visibility
+                    .eq(Visibility::Public.as_str())
+                    .or(added_by.eq(user))
+                    .or(library_id.eq_any(shared)),
+            )
+            .select(library_id)
+            .order(library_id.asc())
+            .load::<i32>(&mut conn)
+            .await
+    }
 }
 
 #[async_trait]
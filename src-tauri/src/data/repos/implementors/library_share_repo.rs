@@ -0,0 +1,102 @@
+use diesel::prelude::*;
+use diesel::result::{DatabaseErrorKind, Error};
+use diesel_async::scoped_futures::ScopedFutureExt;
+use diesel_async::{AsyncConnection, RunQueryDsl};
+use tokio::sync::MutexGuard;
+
+use crate::data::{
+    database::{connect_from_pool, lock_db},
+    models::library_shares::{LibraryShares, NewLibraryShare},
+};
+
+pub struct LibraryShareRepo;
+
+impl LibraryShareRepo {
+    pub async fn new() -> Self {
+        LibraryShareRepo
+    }
+
+    pub async fn get_by_library(&self, lid: i32) -> Result<Option<Vec<LibraryShares>>, Error> {
+        use crate::data::models::schema::library_shares::dsl::*;
+
+        let mut conn = connect_from_pool().await.map_err(|e| {
+            Error::DatabaseError(
+                DatabaseErrorKind::UnableToSendCommand,
+                Box::new(e.to_string()),
+            )
+        })?;
+
+        match library_shares
+            .filter(library_id.eq(lid))
+            .order(share_id.asc())
+            .load::<LibraryShares>(&mut conn)
+            .await
+        {
+            Ok(value) if value.is_empty() => Ok(None),
+            Ok(value) => Ok(Some(value)),
+            Err(Error::NotFound) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    pub async fn add_returning_id(&self, new_item: NewLibraryShare<'_>) -> Result<i32, Error> {
+        use crate::data::models::schema::library_shares::dsl::*;
+
+        let mut conn = connect_from_pool().await.map_err(|e| {
+            Error::DatabaseError(
+                DatabaseErrorKind::UnableToSendCommand,
+                Box::new(e.to_string()),
+            )
+        })?;
+
+        let db_lock = lock_db();
+        let _guard: MutexGuard<()> = db_lock.lock().await;
+
+        conn.transaction(|connection| {
+            async move {
+                diesel::insert_into(library_shares)
+                    .values(new_item)
+                    .execute(connection)
+                    .await?;
+
+                library_shares
+                    .select(share_id)
+                    .order(share_id.desc())
+                    .first::<i32>(connection)
+                    .await
+            }
+            .scope_boxed()
+        })
+        .await
+    }
+
+    /// Removes a share of the given library, `false` if it has no such share
+    pub async fn delete_from_library(&self, lid: i32, id: i32) -> Result<bool, Error> {
+        use crate::data::models::schema::library_shares::dsl::*;
+
+        let mut conn = connect_from_pool().await.map_err(|e| {
+            Error::DatabaseError(
+                DatabaseErrorKind::UnableToSendCommand,
+                Box::new(e.to_string()),
+            )
+        })?;
+
+        let db_lock = lock_db();
+        let _guard: MutexGuard<()> = db_lock.lock().await;
+
+        conn.transaction(|connection| {
+            async move {
+                let deleted = diesel::delete(
+                    library_shares
+                        .filter(share_id.eq(id))
+                        .filter(library_id.eq(lid)),
+                )
+                .execute(connection)
+                .await?;
+                Ok(deleted > 0)
+            }
+            .scope_boxed()
+        })
+        .await
+    }
+}
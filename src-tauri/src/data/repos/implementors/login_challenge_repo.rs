@@ -0,0 +1,123 @@
+use diesel::prelude::*;
+use diesel::result::{DatabaseErrorKind, Error};
+use diesel_async::scoped_futures::ScopedFutureExt;
+use diesel_async::{AsyncConnection, RunQueryDsl};
+use tokio::sync::MutexGuard;
+
+use crate::data::{
+    database::{connect_from_pool, lock_db},
+    models::login_challenges::{LoginChallenges, NewLoginChallenge},
+};
+
+pub struct LoginChallengeRepo;
+
+impl LoginChallengeRepo {
+    pub async fn new() -> Self {
+        LoginChallengeRepo
+    }
+
+    pub async fn get_by_token_hash(&self, hash: &str) -> Result<Option<LoginChallenges>, Error> {
+        use crate::data::models::schema::login_challenges::dsl::*;
+
+        let mut conn = connect_from_pool().await.map_err(|e| {
+            Error::DatabaseError(
+                DatabaseErrorKind::UnableToSendCommand,
+                Box::new(e.to_string()),
+            )
+        })?;
+
+        match login_challenges
+            .filter(token_hash.eq(hash))
+            .first::<LoginChallenges>(&mut conn)
+            .await
+        {
+            Ok(value) => Ok(Some(value)),
+            Err(Error::NotFound) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Stores a new challenge, dropping the ones that expired before `now`
+    pub async fn add(&self, new_item: NewLoginChallenge<'_>, now: &str) -> Result<(), Error> {
+        use crate::data::models::schema::login_challenges::dsl::*;
+
+        let mut conn = connect_from_pool().await.map_err(|e| {
+            Error::DatabaseError(
+                DatabaseErrorKind::UnableToSendCommand,
+                Box::new(e.to_string()),
+            )
+        })?;
+
+        let db_lock = lock_db();
+        let _guard: MutexGuard<()> = db_lock.lock().await;
+
+        conn.transaction(|connection| {
+            async move {
+                diesel::delete(login_challenges.filter(expires_at.le(now)))
+                    .execute(connection)
+                    .await?;
+                diesel::insert_into(login_challenges)
+                    .values(new_item)
+                    .execute(connection)
+                    .await?;
+                Ok(())
+            }
+            .scope_boxed()
+        })
+        .await
+    }
+
+    /// Counts a wrong code against the challenge
+    pub async fn record_attempt(&self, id: i32) -> Result<(), Error> {
+        use crate::data::models::schema::login_challenges::dsl::*;
+
+        let mut conn = connect_from_pool().await.map_err(|e| {
+            Error::DatabaseError(
+                DatabaseErrorKind::UnableToSendCommand,
+                Box::new(e.to_string()),
+            )
+        })?;
+
+        let db_lock = lock_db();
+        let _guard: MutexGuard<()> = db_lock.lock().await;
+
+        conn.transaction(|connection| {
+            async move {
+                diesel::update(login_challenges.filter(challenge_id.eq(id)))
+                    .set(attempts.eq(attempts + 1))
+                    .execute(connection)
+                    .await?;
+                Ok(())
+            }
+            .scope_boxed()
+        })
+        .await
+    }
+
+    /// Removes a challenge, `false` if it was already gone. Completing a challenge
+    /// deletes it, so only one request can turn it into a session.
+    pub async fn delete(&self, id: i32) -> Result<bool, Error> {
+        use crate::data::models::schema::login_challenges::dsl::*;
+
+        let mut conn = connect_from_pool().await.map_err(|e| {
+            Error::DatabaseError(
+                DatabaseErrorKind::UnableToSendCommand,
+                Box::new(e.to_string()),
+            )
+        })?;
+
+        let db_lock = lock_db();
+        let _guard: MutexGuard<()> = db_lock.lock().await;
+
+        conn.transaction(|connection| {
+            async move {
+                let deleted = diesel::delete(login_challenges.filter(challenge_id.eq(id)))
+                    .execute(connection)
+                    .await?;
+                Ok(deleted > 0)
+            }
+            .scope_boxed()
+        })
+        .await
+    }
+}
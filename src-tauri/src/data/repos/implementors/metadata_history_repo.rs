@@ -0,0 +1,253 @@
+use async_trait::async_trait;
+use diesel::prelude::*;
+use diesel::result::{DatabaseErrorKind, Error};
+use diesel_async::scoped_futures::ScopedFutureExt;
+use diesel_async::{AsyncConnection, RunQueryDsl};
+use tokio::sync::MutexGuard;
+
+use crate::data::{
+    database::{connect_from_pool, lock_db, DbConnection},
+    models::metadata_history::{MetadataHistory, NewMetadataHistory, UpdateMetadataHistory},
+    repos::traits::repository::Repository,
+};
+
+pub struct MetadataHistoryRepo;
+
+impl MetadataHistoryRepo {
+    pub async fn new() -> Self {
+        MetadataHistoryRepo
+    }
+
+    /// Returns the history of a book, most recent change first
+    pub async fn get_by_book(&self, bid: i32) -> Result<Option<Vec<MetadataHistory>>, Error> {
+        use crate::data::models::schema::metadata_history::dsl::*;
+
+        let mut conn = connect_from_pool().await.map_err(|e| {
+            Error::DatabaseError(
+                DatabaseErrorKind::UnableToSendCommand,
+                Box::new(e.to_string()),
+            )
+        })?;
+
+        match metadata_history
+            .filter(book_id.eq(bid))
+            .order(history_id.desc())
+            .load::<MetadataHistory>(&mut conn)
+            .await
+        {
+            Ok(value) if value.is_empty() => Ok(None),
+            Ok(value) => Ok(Some(value)),
+            Err(Error::NotFound) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Returns the changes of a batch in the order they were made
+    pub async fn get_by_batch(&self, batch: &str) -> Result<Option<Vec<MetadataHistory>>, Error> {
+        use crate::data::models::schema::metadata_history::dsl::*;
+
+        let mut conn = connect_from_pool().await.map_err(|e| {
+            Error::DatabaseError(
+                DatabaseErrorKind::UnableToSendCommand,
+                Box::new(e.to_string()),
+            )
+        })?;
+
+        match metadata_history
+            .filter(batch_id.eq(batch))
+            .order(history_id.asc())
+            .load::<MetadataHistory>(&mut conn)
+            .await
+        {
+            Ok(value) if value.is_empty() => Ok(None),
+            Ok(value) => Ok(Some(value)),
+            Err(Error::NotFound) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Inserts several changes in a single transaction
+    pub async fn add_all<'a>(&self, entries: Vec<NewMetadataHistory<'a>>) -> Result<(), Error> {
+        if entries.is_empty() {
+            return Ok(());
+        }
+
+        let mut conn = connect_from_pool().await.map_err(|e| {
+            Error::DatabaseError(
+                DatabaseErrorKind::UnableToSendCommand,
+                Box::new(e.to_string()),
+            )
+        })?;
+
+        let db_lock = lock_db();
+        let _guard: MutexGuard<()> = db_lock.lock().await;
+
+        self.add_all_in(&mut conn, entries).await
+    }
+
+    /// Same as [`Self::add_all`], on the connection of a running transaction
+    pub async fn add_all_in(
+        &self,
+        conn: &mut DbConnection,
+        entries: Vec<NewMetadataHistory<'_>>,
+    ) -> Result<(), Error> {
+        use crate::data::models::schema::metadata_history::dsl::*;
+
+        if entries.is_empty() {
+            return Ok(());
+        }
+
+        conn.transaction(|connection| {
+            async move {
+                for entry in entries {
+                    diesel::insert_into(metadata_history)
+                        .values(entry)
+                        .execute(connection)
+                        .await?;
+                }
+                Ok(())
+            }
+            .scope_boxed()
+        })
+        .await
+    }
+
+    pub async fn mark_reverted(&self, ids: &[i32]) -> Result<(), Error> {
+        let mut conn = connect_from_pool().await.map_err(|e| {
+            Error::DatabaseError(
+                DatabaseErrorKind::UnableToSendCommand,
+                Box::new(e.to_string()),
+            )
+        })?;
+
+        let db_lock = lock_db();
+        let _guard: MutexGuard<()> = db_lock.lock().await;
+
+        self.mark_reverted_in(&mut conn, ids).await
+    }
+
+    /// Same as [`Self::mark_reverted`], on the connection of a running transaction
+    pub async fn mark_reverted_in(
+        &self,
+        conn: &mut DbConnection,
+        ids: &[i32],
+    ) -> Result<(), Error> {
+        use crate::data::models::schema::metadata_history::dsl::*;
+
+        let now = chrono::Utc::now().format("%Y-%m-%d %H:%M:%S").to_string();
+
+        conn.transaction(|connection| {
+            async move {
+                diesel::update(metadata_history.filter(history_id.eq_any(ids)))
+                    .set(reverted_at.eq(now))
+                    .execute(connection)
+                    .await?;
+                Ok(())
+            }
+            .scope_boxed()
+        })
+        .await
+    }
+}
+
+#[async_trait]
+impl Repository for MetadataHistoryRepo {
+    type Item = MetadataHistory;
+    type NewItem<'a> = NewMetadataHistory<'a>;
+    type Form<'a> = UpdateMetadataHistory<'a>;
+    type Id = i32;
+
+    async fn get_all(&self) -> Result<Option<Vec<Self::Item>>, Error> {
+        use crate::data::models::schema::metadata_history::dsl::*;
+
+        let mut conn = connect_from_pool().await.map_err(|e| {
+            Error::DatabaseError(
+                DatabaseErrorKind::UnableToSendCommand,
+                Box::new(e.to_string()),
+            )
+        })?;
+
+        match metadata_history.load::<Self::Item>(&mut conn).await {
+            Ok(value) if value.is_empty() => Ok(None),
+            Ok(value) => Ok(Some(value)),
+            Err(Error::NotFound) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn get_by_id(&self, id: Self::Id) -> Result<Option<Self::Item>, Error> {
+        use crate::data::models::schema::metadata_history::dsl::*;
+
+        let mut conn = connect_from_pool().await.map_err(|e| {
+            Error::DatabaseError(
+                DatabaseErrorKind::UnableToSendCommand,
+                Box::new(e.to_string()),
+            )
+        })?;
+
+        match metadata_history
+            .filter(history_id.eq(id))
+            .first::<MetadataHistory>(&mut conn)
+            .await
+        {
+            Ok(value) => Ok(Some(value)),
+            Err(Error::NotFound) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn add<'a>(&self, new_item: Self::NewItem<'a>) -> Result<(), Error> {
+        self.add_all(vec![new_item]).await
+    }
+
+    async fn update<'a>(&self, id: Self::Id, updated_item: Self::Form<'a>) -> Result<(), Error> {
+        use crate::data::models::schema::metadata_history::dsl::*;
+
+        let mut conn = connect_from_pool().await.map_err(|e| {
+            Error::DatabaseError(
+                DatabaseErrorKind::UnableToSendCommand,
+                Box::new(e.to_string()),
+            )
+        })?;
+
+        let db_lock = lock_db();
+        let _guard: MutexGuard<()> = db_lock.lock().await;
+
+        conn.transaction(|connection| {
+            async move {
+                diesel::update(metadata_history.filter(history_id.eq(id)))
+                    .set(updated_item)
+                    .execute(connection)
+                    .await?;
+                Ok(())
+            }
+            .scope_boxed()
+        })
+        .await
+    }
+
+    async fn delete(&self, id: Self::Id) -> Result<(), Error> {
+        use crate::data::models::schema::metadata_history::dsl::*;
+
+        let mut conn = connect_from_pool().await.map_err(|e| {
+            Error::DatabaseError(
+                DatabaseErrorKind::UnableToSendCommand,
+                Box::new(e.to_string()),
+            )
+        })?;
+
+        let db_lock = lock_db();
+        let _guard: MutexGuard<()> = db_lock.lock().await;
+
+        conn.transaction(|connection| {
+            async move {
+                diesel::delete(metadata_history.filter(history_id.eq(id)))
+                    .execute(connection)
+                    .await?;
+                Ok(())
+            }
+            .scope_boxed()
+        })
+        .await
+    }
+}
@@ -1,10 +1,28 @@
 pub mod annotation_repo;
+pub mod api_key_repo;
+pub mod audit_event_repo;
 pub mod author_repo;
 pub mod book_author_repo;
+pub mod book_file_repo;
+pub mod book_identifier_repo;
+pub mod book_metadata_source_repo;
 pub mod book_repo;
+pub mod book_tag_repo;
 pub mod bookmark_repo;
+pub mod invite_repo;
 pub mod library_repo;
+pub mod library_share_repo;
+pub mod login_challenge_repo;
+pub mod metadata_history_repo;
+pub mod password_reset_repo;
 pub mod publisher_repo;
 pub mod reading_progress_repo;
+pub mod recovery_code_repo;
+pub mod revoked_token_repo;
+pub mod series_repo;
+pub mod session_repo;
+pub mod setting_repo;
+pub mod tag_repo;
 pub mod user_library_repo;
+pub mod user_totp_repo;
 pub mod user_repo;
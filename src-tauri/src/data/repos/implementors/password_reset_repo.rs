@@ -0,0 +1,289 @@
+use async_trait::async_trait;
+use diesel::prelude::*;
+use diesel::result::{DatabaseErrorKind, Error};
+use diesel_async::scoped_futures::ScopedFutureExt;
+use diesel_async::{AsyncConnection, RunQueryDsl};
+use tokio::sync::MutexGuard;
+
+use crate::data::{
+    database::{connect_from_pool, lock_db},
+    models::password_resets::{NewPasswordReset, PasswordResets, UpdatePasswordReset},
+    repos::traits::repository::Repository,
+};
+
+pub struct PasswordResetRepo;
+
+impl PasswordResetRepo {
+    pub async fn new() -> Self {
+        PasswordResetRepo
+    }
+
+    pub async fn get_by_token_hash(&self, hash: &str) -> Result<Option<PasswordResets>, Error> {
+        use crate::data::models::schema::password_resets::dsl::*;
+
+        let mut conn = connect_from_pool().await.map_err(|e| {
+            Error::DatabaseError(
+                DatabaseErrorKind::UnableToSendCommand,
+                Box::new(e.to_string()),
+            )
+        })?;
+
+        match password_resets
+            .filter(token_hash.eq(hash))
+            .first::<PasswordResets>(&mut conn)
+            .await
+        {
+            Ok(value) => Ok(Some(value)),
+            Err(Error::NotFound) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Newest token of a user that wasn't used and is still valid at `now`
+    pub async fn get_pending(&self, uid: i32, now: &str) -> Result<Option<PasswordResets>, Error> {
+        use crate::data::models::schema::password_resets::dsl::*;
+
+        let mut conn = connect_from_pool().await.map_err(|e| {
+            Error::DatabaseError(
+                DatabaseErrorKind::UnableToSendCommand,
+                Box::new(e.to_string()),
+            )
+        })?;
+
+        match password_resets
+            .filter(user_id.eq(uid))
+            .filter(used_at.is_null())
+            .filter(expires_at.gt(now))
+            .order(reset_id.desc())
+            .first::<PasswordResets>(&mut conn)
+            .await
+        {
+            Ok(value) => Ok(Some(value)),
+            Err(Error::NotFound) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Drops the tokens of a user that weren't used
+    pub async fn delete_unused(&self, uid: i32) -> Result<(), Error> {
+        use crate::data::models::schema::password_resets::dsl::*;
+
+        let mut conn = connect_from_pool().await.map_err(|e| {
+            Error::DatabaseError(
+                DatabaseErrorKind::UnableToSendCommand,
+                Box::new(e.to_string()),
+            )
+        })?;
+
+        let db_lock = lock_db();
+        let _guard: MutexGuard<()> = db_lock.lock().await;
+
+        conn.transaction(|connection| {
+            async move {
+                diesel::delete(
+                    password_resets
+                        .filter(user_id.eq(uid))
+                        .filter(used_at.is_null()),
+                )
+                .execute(connection)
+                .await?;
+                Ok(())
+            }
+            .scope_boxed()
+        })
+        .await
+    }
+
+    /// Stores a new token for a user, dropping the user's older tokens so only the
+    /// latest mail can be used
+    pub async fn replace_for_user(&self, new_item: NewPasswordReset<'_>) -> Result<(), Error> {
+        use crate::data::models::schema::password_resets::dsl::*;
+
+        let mut conn = connect_from_pool().await.map_err(|e| {
+            Error::DatabaseError(
+                DatabaseErrorKind::UnableToSendCommand,
+                Box::new(e.to_string()),
+            )
+        })?;
+
+        let db_lock = lock_db();
+        let _guard: MutexGuard<()> = db_lock.lock().await;
+
+        conn.transaction(|connection| {
+            async move {
+                diesel::delete(password_resets.filter(user_id.eq(new_item.user_id)))
+                    .execute(connection)
+                    .await?;
+                diesel::insert_into(password_resets)
+                    .values(new_item)
+                    .execute(connection)
+                    .await?;
+                Ok(())
+            }
+            .scope_boxed()
+        })
+        .await
+    }
+
+    /// Marks an unused, unexpired token as used. Returns `false` if it was already
+    /// used or expired at `now`, so each token can be redeemed only once.
+    pub async fn consume(&self, id: i32, now: &str) -> Result<bool, Error> {
+        use crate::data::models::schema::password_resets::dsl::*;
+
+        let mut conn = connect_from_pool().await.map_err(|e| {
+            Error::DatabaseError(
+                DatabaseErrorKind::UnableToSendCommand,
+                Box::new(e.to_string()),
+            )
+        })?;
+
+        let db_lock = lock_db();
+        let _guard: MutexGuard<()> = db_lock.lock().await;
+
+        conn.transaction(|connection| {
+            async move {
+                let updated = diesel::update(
+                    password_resets
+                        .filter(reset_id.eq(id))
+                        .filter(used_at.is_null())
+                        .filter(expires_at.gt(now)),
+                )
+                .set(used_at.eq(now))
+                .execute(connection)
+                .await?;
+                Ok(updated > 0)
+            }
+            .scope_boxed()
+        })
+        .await
+    }
+}
+
+#[async_trait]
+impl Repository for PasswordResetRepo {
+    type Item = PasswordResets;
+    type NewItem<'a> = NewPasswordReset<'a>;
+    type Form<'a> = UpdatePasswordReset<'a>;
+    type Id = i32;
+
+    async fn get_all(&self) -> Result<Option<Vec<Self::Item>>, Error> {
+        use crate::data::models::schema::password_resets::dsl::*;
+
+        let mut conn = connect_from_pool().await.map_err(|e| {
+            Error::DatabaseError(
+                DatabaseErrorKind::UnableToSendCommand,
+                Box::new(e.to_string()),
+            )
+        })?;
+
+        match password_resets.load::<Self::Item>(&mut conn).await {
+            Ok(value) if value.is_empty() => Ok(None),
+            Ok(value) => Ok(Some(value)),
+            Err(Error::NotFound) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn get_by_id(&self, id: Self::Id) -> Result<Option<Self::Item>, Error> {
+        use crate::data::models::schema::password_resets::dsl::*;
+
+        let mut conn = connect_from_pool().await.map_err(|e| {
+            Error::DatabaseError(
+                DatabaseErrorKind::UnableToSendCommand,
+                Box::new(e.to_string()),
+            )
+        })?;
+
+        match password_resets
+            .filter(reset_id.eq(id))
+            .first::<PasswordResets>(&mut conn)
+            .await
+        {
+            Ok(value) => Ok(Some(value)),
+            Err(Error::NotFound) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn add<'a>(&self, new_item: Self::NewItem<'a>) -> Result<(), Error> {
+        use crate::data::models::schema::password_resets::dsl::*;
+
+        let mut conn = connect_from_pool().await.map_err(|e| {
+            Error::DatabaseError(
+                DatabaseErrorKind::UnableToSendCommand,
+                Box::new(e.to_string()),
+            )
+        })?;
+
+        let db_lock = lock_db();
+        let _guard: MutexGuard<()> = db_lock.lock().await;
+
+        conn.transaction(|connection| {
+            async move {
+                diesel::insert_into(password_resets)
+                    .values(new_item)
+                    .execute(connection)
+                    .await?;
+                Ok(())
+            }
+            .scope_boxed()
+        })
+        .await
+    }
+
+    async fn update<'a>(&self, id: Self::Id, updated_item: Self::Form<'a>) -> Result<(), Error> {
+        use crate::data::models::schema::password_resets::dsl::*;
+
+        // Diesel rejects an update without any column to set
+        if updated_item == UpdatePasswordReset::default() {
+            return Ok(());
+        }
+
+        let mut conn = connect_from_pool().await.map_err(|e| {
+            Error::DatabaseError(
+                DatabaseErrorKind::UnableToSendCommand,
+                Box::new(e.to_string()),
+            )
+        })?;
+
+        let db_lock = lock_db();
+        let _guard: MutexGuard<()> = db_lock.lock().await;
+
+        conn.transaction(|connection| {
+            async move {
+                diesel::update(password_resets.filter(reset_id.eq(id)))
+                    .set(updated_item)
+                    .execute(connection)
+                    .await?;
+                Ok(())
+            }
+            .scope_boxed()
+        })
+        .await
+    }
+
+    async fn delete(&self, id: Self::Id) -> Result<(), Error> {
+        use crate::data::models::schema::password_resets::dsl::*;
+
+        let mut conn = connect_from_pool().await.map_err(|e| {
+            Error::DatabaseError(
+                DatabaseErrorKind::UnableToSendCommand,
+                Box::new(e.to_string()),
+            )
+        })?;
+
+        let db_lock = lock_db();
+        let _guard: MutexGuard<()> = db_lock.lock().await;
+
+        conn.transaction(|connection| {
+            async move {
+                diesel::delete(password_resets.filter(reset_id.eq(id)))
+                    .execute(connection)
+                    .await?;
+                Ok(())
+            }
+            .scope_boxed()
+        })
+        .await
+    }
+}
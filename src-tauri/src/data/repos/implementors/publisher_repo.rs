@@ -6,7 +6,7 @@ use diesel_async::{AsyncConnection, RunQueryDsl};
 use tokio::sync::MutexGuard;
 
 use crate::data::{
-    database::{connect_from_pool, lock_db},
+    database::{connect_from_pool, lock_db, DbConnection},
     models::publishers::{NewPublisher, Publishers, UpdatePublisher},
     repos::traits::repository::Repository,
 };
@@ -39,6 +39,98 @@ impl PublisherRepo {
             Err(e) => Err(e),
         };
     }
+
+    pub async fn get_by_name(&self, name_query: &str) -> Result<Option<Publishers>, Error> {
+        use crate::data::models::schema::publishers::dsl::*;
+
+        let mut conn = connect_from_pool().await.map_err(|e| {
+            Error::DatabaseError(
+                DatabaseErrorKind::UnableToSendCommand,
+                Box::new(e.to_string()),
+            )
+        })?;
+
+        match publishers
+            .filter(name.eq(name_query))
+            .first::<Publishers>(&mut conn)
+            .await
+        {
+            Ok(value) => Ok(Some(value)),
+            Err(Error::NotFound) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+    /// Returns the publisher with the exact given name, creating it first if it doesn't exist
+    pub async fn get_or_create(&self, publisher_name: &str) -> Result<Publishers, Error> {
+        let mut conn = connect_from_pool().await.map_err(|e| {
+            Error::DatabaseError(
+                DatabaseErrorKind::UnableToSendCommand,
+                Box::new(e.to_string()),
+            )
+        })?;
+
+        let db_lock = lock_db();
+        let _guard: MutexGuard<()> = db_lock.lock().await;
+
+        self.get_or_create_in(&mut conn, publisher_name).await
+    }
+
+    /// Same as [`Self::get_or_create`], on the connection of a running transaction
+    pub async fn get_or_create_in(
+        &self,
+        conn: &mut DbConnection,
+        publisher_name: &str,
+    ) -> Result<Publishers, Error> {
+        use crate::data::models::schema::publishers::dsl::*;
+
+        conn.transaction(|connection| {
+            async move {
+                let existing = publishers
+                    .filter(name.eq(publisher_name))
+                    .first::<Publishers>(connection)
+                    .await
+                    .optional()?;
+
+                if let Some(publisher) = existing {
+                    return Ok(publisher);
+                }
+
+                diesel::insert_into(publishers)
+                    .values(NewPublisher {
+                        name: publisher_name,
+                    })
+                    .execute(connection)
+                    .await?;
+
+                publishers
+                    .filter(name.eq(publisher_name))
+                    .order(publisher_id.desc())
+                    .first::<Publishers>(connection)
+                    .await
+            }
+            .scope_boxed()
+        })
+        .await
+    }
+
+    /// Same as [`Self::get_by_id`], on the connection of a running transaction
+    pub async fn get_by_id_in(
+        &self,
+        conn: &mut DbConnection,
+        id: i32,
+    ) -> Result<Option<Publishers>, Error> {
+        use crate::data::models::schema::{publishers as publisher, publishers::dsl::*};
+
+        match publishers
+            .filter(publisher::publisher_id.eq(id))
+            .first::<Publishers>(conn)
+            .await
+        {
+            Ok(value) => Ok(Some(value)),
+            Err(Error::NotFound) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
 }
 
 #[async_trait]
@@ -67,8 +159,6 @@ impl Repository for PublisherRepo {
     }
 
     async fn get_by_id(&self, id: Self::Id) -> Result<Option<Self::Item>, Error> {
-        use crate::data::models::schema::{publishers as publisher, publishers::dsl::*};
-
         let mut conn = connect_from_pool().await.map_err(|e| {
             Error::DatabaseError(
                 DatabaseErrorKind::UnableToSendCommand,
@@ -76,15 +166,7 @@ impl Repository for PublisherRepo {
             )
         })?;
 
-        match publishers
-            .filter(publisher::publisher_id.eq(id))
-            .first::<Publishers>(&mut conn)
-            .await
-        {
-            Ok(value) => Ok(Some(value)),
-            Err(Error::NotFound) => Ok(None),
-            Err(e) => Err(e),
-        }
+        self.get_by_id_in(&mut conn, id).await
     }
 
     async fn add<'a>(&self, new_item: Self::NewItem<'a>) -> Result<(), Error> {
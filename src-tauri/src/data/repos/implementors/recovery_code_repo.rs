@@ -0,0 +1,121 @@
+use diesel::prelude::*;
+use diesel::result::{DatabaseErrorKind, Error};
+use diesel_async::scoped_futures::ScopedFutureExt;
+use diesel_async::{AsyncConnection, RunQueryDsl};
+use tokio::sync::MutexGuard;
+
+use crate::data::{
+    database::{connect_from_pool, lock_db},
+    models::recovery_codes::{NewRecoveryCode, RecoveryCodes},
+};
+
+pub struct RecoveryCodeRepo;
+
+impl RecoveryCodeRepo {
+    pub async fn new() -> Self {
+        RecoveryCodeRepo
+    }
+
+    /// Recovery codes of a user that haven't been used yet
+    pub async fn count_unused(&self, uid: i32) -> Result<i64, Error> {
+        use crate::data::models::schema::recovery_codes::dsl::*;
+
+        let mut conn = connect_from_pool().await.map_err(|e| {
+            Error::DatabaseError(
+                DatabaseErrorKind::UnableToSendCommand,
+                Box::new(e.to_string()),
+            )
+        })?;
+
+        recovery_codes
+            .filter(user_id.eq(uid))
+            .filter(used_at.is_null())
+            .count()
+            .get_result::<i64>(&mut conn)
+            .await
+    }
+
+    /// Replaces every code of a user with the given hashes
+    pub async fn replace_for_user(&self, uid: i32, hashes: Vec<String>) -> Result<(), Error> {
+        use crate::data::models::schema::recovery_codes::dsl::*;
+
+        let mut conn = connect_from_pool().await.map_err(|e| {
+            Error::DatabaseError(
+                DatabaseErrorKind::UnableToSendCommand,
+                Box::new(e.to_string()),
+            )
+        })?;
+
+        let db_lock = lock_db();
+        let _guard: MutexGuard<()> = db_lock.lock().await;
+
+        conn.transaction(|connection| {
+            async move {
+                diesel::delete(recovery_codes.filter(user_id.eq(uid)))
+                    .execute(connection)
+                    .await?;
+                for hash in &hashes {
+                    diesel::insert_into(recovery_codes)
+                        .values(NewRecoveryCode {
+                            user_id: uid,
+                            code_hash: hash,
+                        })
+                        .execute(connection)
+                        .await?;
+                }
+                Ok(())
+            }
+            .scope_boxed()
+        })
+        .await
+    }
+
+    /// Codes of a user that haven't been used yet
+    pub async fn get_unused(&self, uid: i32) -> Result<Vec<RecoveryCodes>, Error> {
+        use crate::data::models::schema::recovery_codes::dsl::*;
+
+        let mut conn = connect_from_pool().await.map_err(|e| {
+            Error::DatabaseError(
+                DatabaseErrorKind::UnableToSendCommand,
+                Box::new(e.to_string()),
+            )
+        })?;
+
+        recovery_codes
+            .filter(user_id.eq(uid))
+            .filter(used_at.is_null())
+            .load::<RecoveryCodes>(&mut conn)
+            .await
+    }
+
+    /// Marks a code as used. Returns `false` if it was already used.
+    pub async fn consume(&self, id: i32, now: &str) -> Result<bool, Error> {
+        use crate::data::models::schema::recovery_codes::dsl::*;
+
+        let mut conn = connect_from_pool().await.map_err(|e| {
+            Error::DatabaseError(
+                DatabaseErrorKind::UnableToSendCommand,
+                Box::new(e.to_string()),
+            )
+        })?;
+
+        let db_lock = lock_db();
+        let _guard: MutexGuard<()> = db_lock.lock().await;
+
+        conn.transaction(|connection| {
+            async move {
+                let updated = diesel::update(
+                    recovery_codes
+                        .filter(code_id.eq(id))
+                        .filter(used_at.is_null()),
+                )
+                .set(used_at.eq(now))
+                .execute(connection)
+                .await?;
+                Ok(updated == 1)
+            }
+            .scope_boxed()
+        })
+        .await
+    }
+}
@@ -0,0 +1,71 @@
+use diesel::prelude::*;
+use diesel::result::{DatabaseErrorKind, Error};
+use diesel_async::scoped_futures::ScopedFutureExt;
+use diesel_async::{AsyncConnection, RunQueryDsl};
+use tokio::sync::MutexGuard;
+
+use crate::data::{
+    database::{connect_from_pool, lock_db},
+    models::revoked_tokens::RevokedToken,
+};
+
+/// Denylist of access token ids. Entries are only looked up by `jti`, so this
+/// doesn't implement `Repository`.
+pub struct RevokedTokenRepo;
+
+impl RevokedTokenRepo {
+    pub async fn new() -> Self {
+        RevokedTokenRepo
+    }
+
+    /// Adds tokens to the denylist and drops entries that expired before `now`
+    pub async fn add_all(&self, tokens: Vec<RevokedToken>, now: &str) -> Result<(), Error> {
+        use crate::data::models::schema::revoked_tokens::dsl::*;
+
+        let mut conn = connect_from_pool().await.map_err(|e| {
+            Error::DatabaseError(
+                DatabaseErrorKind::UnableToSendCommand,
+                Box::new(e.to_string()),
+            )
+        })?;
+
+        let db_lock = lock_db();
+        let _guard: MutexGuard<()> = db_lock.lock().await;
+
+        conn.transaction(|connection| {
+            async move {
+                diesel::delete(revoked_tokens.filter(expires_at.le(now)))
+                    .execute(connection)
+                    .await?;
+                for token in tokens {
+                    diesel::insert_into(revoked_tokens)
+                        .values(&token)
+                        .on_conflict_do_nothing()
+                        .execute(connection)
+                        .await?;
+                }
+                Ok(())
+            }
+            .scope_boxed()
+        })
+        .await
+    }
+
+    pub async fn is_revoked(&self, token_id: &str) -> Result<bool, Error> {
+        use crate::data::models::schema::revoked_tokens::dsl::*;
+
+        let mut conn = connect_from_pool().await.map_err(|e| {
+            Error::DatabaseError(
+                DatabaseErrorKind::UnableToSendCommand,
+                Box::new(e.to_string()),
+            )
+        })?;
+
+        let count = revoked_tokens
+            .filter(jti.eq(token_id))
+            .count()
+            .get_result::<i64>(&mut conn)
+            .await?;
+        Ok(count > 0)
+    }
+}
@@ -0,0 +1,261 @@
+use async_trait::async_trait;
+use diesel::prelude::*;
+use diesel::result::{DatabaseErrorKind, Error};
+use diesel_async::scoped_futures::ScopedFutureExt;
+use diesel_async::{AsyncConnection, RunQueryDsl};
+use tokio::sync::MutexGuard;
+
+use crate::data::{
+    database::{connect_from_pool, lock_db, DbConnection},
+    models::series::{NewSeries, Series, UpdateSeries},
+    repos::traits::repository::Repository,
+};
+
+pub struct SeriesRepo;
+
+impl SeriesRepo {
+    pub async fn new() -> Self {
+        SeriesRepo
+    }
+
+    pub async fn search_by_name(&self, name_query: &str) -> Result<Option<Vec<Series>>, Error> {
+        use crate::data::models::schema::series::dsl::*;
+
+        let mut conn = connect_from_pool().await.map_err(|e| {
+            Error::DatabaseError(
+                DatabaseErrorKind::UnableToSendCommand,
+                Box::new(e.to_string()),
+            )
+        })?;
+
+        return match series
+            .filter(name.like(format!("%{}%", name_query)))
+            .load::<Series>(&mut conn)
+            .await
+        {
+            Ok(value) if value.is_empty() => Ok(None),
+            Ok(value) => Ok(Some(value)),
+            Err(Error::NotFound) => Ok(None),
+            Err(e) => Err(e),
+        };
+    }
+
+    pub async fn get_by_name(&self, name_query: &str) -> Result<Option<Series>, Error> {
+        use crate::data::models::schema::series::dsl::*;
+
+        let mut conn = connect_from_pool().await.map_err(|e| {
+            Error::DatabaseError(
+                DatabaseErrorKind::UnableToSendCommand,
+                Box::new(e.to_string()),
+            )
+        })?;
+
+        match series
+            .filter(name.eq(name_query))
+            .first::<Series>(&mut conn)
+            .await
+        {
+            Ok(value) => Ok(Some(value)),
+            Err(Error::NotFound) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+    /// Returns the series with the exact given name, creating it first if it doesn't exist
+    pub async fn get_or_create(&self, series_name: &str) -> Result<Series, Error> {
+        let mut conn = connect_from_pool().await.map_err(|e| {
+            Error::DatabaseError(
+                DatabaseErrorKind::UnableToSendCommand,
+                Box::new(e.to_string()),
+            )
+        })?;
+
+        let db_lock = lock_db();
+        let _guard: MutexGuard<()> = db_lock.lock().await;
+
+        self.get_or_create_in(&mut conn, series_name).await
+    }
+
+    /// Same as [`Self::get_or_create`], on the connection of a running transaction
+    pub async fn get_or_create_in(
+        &self,
+        conn: &mut DbConnection,
+        series_name: &str,
+    ) -> Result<Series, Error> {
+        use crate::data::models::schema::series::dsl::*;
+
+        conn.transaction(|connection| {
+            async move {
+                let existing = series
+                    .filter(name.eq(series_name))
+                    .first::<Series>(connection)
+                    .await
+                    .optional()?;
+
+                if let Some(found) = existing {
+                    return Ok(found);
+                }
+
+                diesel::insert_into(series)
+                    .values(NewSeries {
+                        name: series_name,
+                        sort_name: None,
+                    })
+                    .execute(connection)
+                    .await?;
+
+                series
+                    .filter(name.eq(series_name))
+                    .order(series_id.desc())
+                    .first::<Series>(connection)
+                    .await
+            }
+            .scope_boxed()
+        })
+        .await
+    }
+
+    /// Same as [`Self::get_by_id`], on the connection of a running transaction
+    pub async fn get_by_id_in(
+        &self,
+        conn: &mut DbConnection,
+        id: i32,
+    ) -> Result<Option<Series>, Error> {
+        use crate::data::models::schema::{series as series_table, series::dsl::*};
+
+        match series
+            .filter(series_table::series_id.eq(id))
+            .first::<Series>(conn)
+            .await
+        {
+            Ok(value) => Ok(Some(value)),
+            Err(Error::NotFound) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+#[async_trait]
+impl Repository for SeriesRepo {
+    type Item = Series;
+    type NewItem<'a> = NewSeries<'a>;
+    type Form<'a> = UpdateSeries<'a>;
+    type Id = i32;
+
+    async fn get_all(&self) -> Result<Option<Vec<Self::Item>>, Error> {
+        use crate::data::models::schema::series::dsl::*;
+
+        let mut conn = connect_from_pool().await.map_err(|e| {
+            Error::DatabaseError(
+                DatabaseErrorKind::UnableToSendCommand,
+                Box::new(e.to_string()),
+            )
+        })?;
+
+        match series.load::<Self::Item>(&mut conn).await {
+            Ok(value) if value.is_empty() => Ok(None),
+            Ok(value) => Ok(Some(value)),
+            Err(Error::NotFound) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn get_by_id(&self, id: Self::Id) -> Result<Option<Self::Item>, Error> {
+        let mut conn = connect_from_pool().await.map_err(|e| {
+            Error::DatabaseError(
+                DatabaseErrorKind::UnableToSendCommand,
+                Box::new(e.to_string()),
+            )
+        })?;
+
+        self.get_by_id_in(&mut conn, id).await
+    }
+
+    async fn add<'a>(&self, new_item: Self::NewItem<'a>) -> Result<(), Error> {
+        use crate::data::models::schema::series::dsl::*;
+
+        let mut conn = connect_from_pool().await.map_err(|e| {
+            Error::DatabaseError(
+                DatabaseErrorKind::UnableToSendCommand,
+                Box::new(e.to_string()),
+            )
+        })?;
+
+        let db_lock = lock_db();
+        let _guard: MutexGuard<()> = db_lock.lock().await;
+
+        match conn
+            .transaction(|connection| {
+                async move {
+                    diesel::insert_into(series)
+                        .values(new_item)
+                        .execute(connection)
+                        .await?;
+
+                    Ok(())
+                }
+                .scope_boxed()
+            })
+            .await
+        {
+            Ok(_) => Ok(()),
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn update<'a>(&self, id: Self::Id, updated_item: Self::Form<'a>) -> Result<(), Error> {
+        use crate::data::models::schema::series::dsl::*;
+
+        let mut conn = connect_from_pool().await.map_err(|e| {
+            Error::DatabaseError(
+                DatabaseErrorKind::UnableToSendCommand,
+                Box::new(e.to_string()),
+            )
+        })?;
+
+        let db_lock = lock_db();
+        let _guard: MutexGuard<()> = db_lock.lock().await;
+
+        conn.transaction(|connection| {
+            async move {
+                diesel::update(series.filter(series_id.eq(id)))
+                    .set(updated_item)
+                    .execute(connection)
+                    .await?;
+
+                Ok(())
+            }
+            .scope_boxed()
+        })
+        .await
+    }
+
+    async fn delete(&self, id: Self::Id) -> Result<(), Error> {
+        use crate::data::models::schema::series::dsl::*;
+
+        let mut conn = connect_from_pool().await.map_err(|e| {
+            Error::DatabaseError(
+                DatabaseErrorKind::UnableToSendCommand,
+                Box::new(e.to_string()),
+            )
+        })?;
+
+        let db_lock = lock_db();
+        let _guard: MutexGuard<()> = db_lock.lock().await;
+
+        match conn
+            .transaction(|connection| {
+                async move {
+                    diesel::delete(series.filter(series_id.eq(id)))
+                        .execute(connection)
+                        .await?;
+                    Ok(())
+                }
+                .scope_boxed()
+            })
+            .await
+        {
+            Ok(_) => Ok(()),
+            Err(e) => Err(e),
+        }
+    }
+}
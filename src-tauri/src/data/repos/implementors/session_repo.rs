@@ -0,0 +1,368 @@
+use async_trait::async_trait;
+use diesel::prelude::*;
+use diesel::result::{DatabaseErrorKind, Error};
+use diesel_async::scoped_futures::ScopedFutureExt;
+use diesel_async::{AsyncConnection, RunQueryDsl};
+use tokio::sync::MutexGuard;
+
+use crate::data::{
+    database::{connect_from_pool, lock_db},
+    models::sessions::{NewSession, Sessions, UpdateSession},
+    repos::traits::repository::Repository,
+};
+
+pub struct SessionRepo;
+
+impl SessionRepo {
+    pub async fn new() -> Self {
+        SessionRepo
+    }
+
+    pub async fn get_by_token_hash(&self, hash: &str) -> Result<Option<Sessions>, Error> {
+        use crate::data::models::schema::sessions::dsl::*;
+
+        let mut conn = connect_from_pool().await.map_err(|e| {
+            Error::DatabaseError(
+                DatabaseErrorKind::UnableToSendCommand,
+                Box::new(e.to_string()),
+            )
+        })?;
+
+        match sessions
+            .filter(token_hash.eq(hash))
+            .first::<Sessions>(&mut conn)
+            .await
+        {
+            Ok(value) => Ok(Some(value)),
+            Err(Error::NotFound) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Sessions of a user that are neither revoked nor expired at `now`, most recently used first
+    pub async fn get_active_by_user(
+        &self,
+        uid: i32,
+        now: &str,
+    ) -> Result<Option<Vec<Sessions>>, Error> {
+        use crate::data::models::schema::sessions::dsl::*;
+
+        let mut conn = connect_from_pool().await.map_err(|e| {
+            Error::DatabaseError(
+                DatabaseErrorKind::UnableToSendCommand,
+                Box::new(e.to_string()),
+            )
+        })?;
+
+        match sessions
+            .filter(user_id.eq(uid))
+            .filter(revoked_at.is_null())
+            .filter(expires_at.gt(now))
+            .order((last_used_at.desc(), session_id.desc()))
+            .load::<Sessions>(&mut conn)
+            .await
+        {
+            Ok(value) if value.is_empty() => Ok(None),
+            Ok(value) => Ok(Some(value)),
+            Err(Error::NotFound) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Inserts a session and returns the id it was assigned
+    pub async fn add_returning_id(&self, new_item: NewSession<'_>) -> Result<i32, Error> {
+        use crate::data::models::schema::sessions::dsl::*;
+
+        let mut conn = connect_from_pool().await.map_err(|e| {
+            Error::DatabaseError(
+                DatabaseErrorKind::UnableToSendCommand,
+                Box::new(e.to_string()),
+            )
+        })?;
+
+        let db_lock = lock_db();
+        let _guard: MutexGuard<()> = db_lock.lock().await;
+
+        conn.transaction(|connection| {
+            async move {
+                diesel::insert_into(sessions)
+                    .values(new_item)
+                    .execute(connection)
+                    .await?;
+
+                sessions
+                    .select(session_id)
+                    .order(session_id.desc())
+                    .first::<i32>(connection)
+                    .await
+            }
+            .scope_boxed()
+        })
+        .await
+    }
+
+    /// Replaces a session with a new one of the same family and returns the new id.
+    /// Returns `None` without inserting anything if the old session was already
+    /// revoked, e.g. by a concurrent refresh with the same token.
+    pub async fn rotate(
+        &self,
+        old_id: i32,
+        new_item: NewSession<'_>,
+        now: &str,
+    ) -> Result<Option<i32>, Error> {
+        use crate::data::models::schema::sessions::dsl::*;
+
+        let mut conn = connect_from_pool().await.map_err(|e| {
+            Error::DatabaseError(
+                DatabaseErrorKind::UnableToSendCommand,
+                Box::new(e.to_string()),
+            )
+        })?;
+
+        let db_lock = lock_db();
+        let _guard: MutexGuard<()> = db_lock.lock().await;
+
+        conn.transaction(|connection| {
+            async move {
+                let revoked = diesel::update(
+                    sessions
+                        .filter(session_id.eq(old_id))
+                        .filter(revoked_at.is_null()),
+                )
+                .set(revoked_at.eq(now))
+                .execute(connection)
+                .await?;
+                if revoked == 0 {
+                    return Ok(None);
+                }
+
+                diesel::insert_into(sessions)
+                    .values(new_item)
+                    .execute(connection)
+                    .await?;
+                let new_id = sessions
+                    .select(session_id)
+                    .order(session_id.desc())
+                    .first::<i32>(connection)
+                    .await?;
+
+                diesel::update(sessions.filter(session_id.eq(old_id)))
+                    .set(replaced_by.eq(new_id))
+                    .execute(connection)
+                    .await?;
+                Ok(Some(new_id))
+            }
+            .scope_boxed()
+        })
+        .await
+    }
+
+    /// Revokes every session of a family that isn't revoked yet and returns the
+    /// access token ids issued with them
+    pub async fn revoke_family(&self, family: &str, now: &str) -> Result<Vec<String>, Error> {
+        use crate::data::models::schema::sessions::dsl::*;
+
+        let mut conn = connect_from_pool().await.map_err(|e| {
+            Error::DatabaseError(
+                DatabaseErrorKind::UnableToSendCommand,
+                Box::new(e.to_string()),
+            )
+        })?;
+
+        let db_lock = lock_db();
+        let _guard: MutexGuard<()> = db_lock.lock().await;
+
+        conn.transaction(|connection| {
+            async move {
+                let jtis = sessions
+                    .filter(family_id.eq(family))
+                    .filter(revoked_at.is_null())
+                    .select(access_jti)
+                    .load::<Option<String>>(connection)
+                    .await?;
+                diesel::update(
+                    sessions
+                        .filter(family_id.eq(family))
+                        .filter(revoked_at.is_null()),
+                )
+                .set(revoked_at.eq(now))
+                .execute(connection)
+                .await?;
+                Ok(jtis.into_iter().flatten().collect())
+            }
+            .scope_boxed()
+        })
+        .await
+    }
+
+    /// Revokes every session of a user that isn't revoked yet and returns the access
+    /// token ids issued with them
+    pub async fn revoke_all_by_user(&self, uid: i32, now: &str) -> Result<Vec<String>, Error> {
+        use crate::data::models::schema::sessions::dsl::*;
+
+        let mut conn = connect_from_pool().await.map_err(|e| {
+            Error::DatabaseError(
+                DatabaseErrorKind::UnableToSendCommand,
+                Box::new(e.to_string()),
+            )
+        })?;
+
+        let db_lock = lock_db();
+        let _guard: MutexGuard<()> = db_lock.lock().await;
+
+        conn.transaction(|connection| {
+            async move {
+                let jtis = sessions
+                    .filter(user_id.eq(uid))
+                    .filter(revoked_at.is_null())
+                    .select(access_jti)
+                    .load::<Option<String>>(connection)
+                    .await?;
+                diesel::update(
+                    sessions
+                        .filter(user_id.eq(uid))
+                        .filter(revoked_at.is_null()),
+                )
+                .set(revoked_at.eq(now))
+                .execute(connection)
+                .await?;
+                Ok(jtis.into_iter().flatten().collect())
+            }
+            .scope_boxed()
+        })
+        .await
+    }
+
+    /// Removes sessions of a user that expired before `now`
+    pub async fn delete_expired_by_user(&self, uid: i32, now: &str) -> Result<(), Error> {
+        use crate::data::models::schema::sessions::dsl::*;
+
+        let mut conn = connect_from_pool().await.map_err(|e| {
+            Error::DatabaseError(
+                DatabaseErrorKind::UnableToSendCommand,
+                Box::new(e.to_string()),
+            )
+        })?;
+
+        let db_lock = lock_db();
+        let _guard: MutexGuard<()> = db_lock.lock().await;
+
+        conn.transaction(|connection| {
+            async move {
+                diesel::delete(sessions.filter(user_id.eq(uid)).filter(expires_at.le(now)))
+                    .execute(connection)
+                    .await?;
+                Ok(())
+            }
+            .scope_boxed()
+        })
+        .await
+    }
+}
+
+#[async_trait]
+impl Repository for SessionRepo {
+    type Item = Sessions;
+    type NewItem<'a> = NewSession<'a>;
+    type Form<'a> = UpdateSession<'a>;
+    type Id = i32;
+
+    async fn get_all(&self) -> Result<Option<Vec<Self::Item>>, Error> {
+        use crate::data::models::schema::sessions::dsl::*;
+
+        let mut conn = connect_from_pool().await.map_err(|e| {
+            Error::DatabaseError(
+                DatabaseErrorKind::UnableToSendCommand,
+                Box::new(e.to_string()),
+            )
+        })?;
+
+        match sessions.load::<Self::Item>(&mut conn).await {
+            Ok(value) if value.is_empty() => Ok(None),
+            Ok(value) => Ok(Some(value)),
+            Err(Error::NotFound) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn get_by_id(&self, id: Self::Id) -> Result<Option<Self::Item>, Error> {
+        use crate::data::models::schema::sessions::dsl::*;
+
+        let mut conn = connect_from_pool().await.map_err(|e| {
+            Error::DatabaseError(
+                DatabaseErrorKind::UnableToSendCommand,
+                Box::new(e.to_string()),
+            )
+        })?;
+
+        match sessions
+            .filter(session_id.eq(id))
+            .first::<Sessions>(&mut conn)
+            .await
+        {
+            Ok(value) => Ok(Some(value)),
+            Err(Error::NotFound) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn add<'a>(&self, new_item: Self::NewItem<'a>) -> Result<(), Error> {
+        self.add_returning_id(new_item).await.map(|_| ())
+    }
+
+    async fn update<'a>(&self, id: Self::Id, updated_item: Self::Form<'a>) -> Result<(), Error> {
+        use crate::data::models::schema::sessions::dsl::*;
+
+        // Diesel rejects an update without any column to set
+        if updated_item == UpdateSession::default() {
+            return Ok(());
+        }
+
+        let mut conn = connect_from_pool().await.map_err(|e| {
+            Error::DatabaseError(
+                DatabaseErrorKind::UnableToSendCommand,
+                Box::new(e.to_string()),
+            )
+        })?;
+
+        let db_lock = lock_db();
+        let _guard: MutexGuard<()> = db_lock.lock().await;
+
+        conn.transaction(|connection| {
+            async move {
+                diesel::update(sessions.filter(session_id.eq(id)))
+                    .set(updated_item)
+                    .execute(connection)
+                    .await?;
+                Ok(())
+            }
+            .scope_boxed()
+        })
+        .await
+    }
+
+    async fn delete(&self, id: Self::Id) -> Result<(), Error> {
+        use crate::data::models::schema::sessions::dsl::*;
+
+        let mut conn = connect_from_pool().await.map_err(|e| {
+            Error::DatabaseError(
+                DatabaseErrorKind::UnableToSendCommand,
+                Box::new(e.to_string()),
+            )
+        })?;
+
+        let db_lock = lock_db();
+        let _guard: MutexGuard<()> = db_lock.lock().await;
+
+        conn.transaction(|connection| {
+            async move {
+                diesel::delete(sessions.filter(session_id.eq(id)))
+                    .execute(connection)
+                    .await?;
+                Ok(())
+            }
+            .scope_boxed()
+        })
+        .await
+    }
+}
@@ -0,0 +1,75 @@
+use diesel::prelude::*;
+use diesel::result::{DatabaseErrorKind, Error};
+use diesel::sql_types::{Nullable, Text};
+use diesel_async::scoped_futures::ScopedFutureExt;
+use diesel_async::{AsyncConnection, RunQueryDsl};
+use tokio::sync::MutexGuard;
+
+use crate::data::{
+    database::{connect_from_pool, lock_db},
+    models::server_settings::ServerSetting,
+};
+
+/// Key-value store for [`ServerSetting`]s. Settings are addressed by key only, so this
+/// doesn't implement `Repository`.
+pub struct SettingRepo;
+
+impl SettingRepo {
+    pub async fn new() -> Self {
+        SettingRepo
+    }
+
+    pub async fn get(&self, name: &str) -> Result<Option<ServerSetting>, Error> {
+        use crate::data::models::schema::server_settings::dsl::*;
+
+        let mut conn = connect_from_pool().await.map_err(|e| {
+            Error::DatabaseError(
+                DatabaseErrorKind::UnableToSendCommand,
+                Box::new(e.to_string()),
+            )
+        })?;
+
+        match server_settings
+            .filter(key.eq(name))
+            .first::<ServerSetting>(&mut conn)
+            .await
+        {
+            Ok(setting) => Ok(Some(setting)),
+            Err(Error::NotFound) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Stores a setting, replacing its previous value
+    pub async fn set(&self, name: &str, new_value: &str) -> Result<(), Error> {
+        use crate::data::models::schema::server_settings::dsl::*;
+
+        let mut conn = connect_from_pool().await.map_err(|e| {
+            Error::DatabaseError(
+                DatabaseErrorKind::UnableToSendCommand,
+                Box::new(e.to_string()),
+            )
+        })?;
+
+        let db_lock = lock_db();
+        let _guard: MutexGuard<()> = db_lock.lock().await;
+
+        conn.transaction(|connection| {
+            async move {
+                diesel::insert_into(server_settings)
+                    .values((key.eq(name), value.eq(new_value)))
+                    .on_conflict(key)
+                    .do_update()
+                    .set((
+                        value.eq(new_value),
+                        updated_at.eq(diesel::dsl::sql::<Nullable<Text>>("datetime('now')")),
+                    ))
+                    .execute(connection)
+                    .await?;
+                Ok(())
+            }
+            .scope_boxed()
+        })
+        .await
+    }
+}
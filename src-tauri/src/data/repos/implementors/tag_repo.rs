@@ -0,0 +1,248 @@
+use async_trait::async_trait;
+use diesel::prelude::*;
+use diesel::result::{DatabaseErrorKind, Error};
+use diesel_async::scoped_futures::ScopedFutureExt;
+use diesel_async::{AsyncConnection, RunQueryDsl};
+use tokio::sync::MutexGuard;
+
+use crate::data::{
+    database::{connect_from_pool, lock_db, DbConnection},
+    models::tags::{NewTag, Tags, UpdateTag},
+    repos::traits::repository::Repository,
+};
+
+pub struct TagRepo;
+
+impl TagRepo {
+    pub async fn new() -> Self {
+        TagRepo
+    }
+
+    pub async fn search_by_name(&self, name_query: &str) -> Result<Option<Vec<Tags>>, Error> {
+        use crate::data::models::schema::tags::dsl::*;
+
+        let mut conn = connect_from_pool().await.map_err(|e| {
+            Error::DatabaseError(
+                DatabaseErrorKind::UnableToSendCommand,
+                Box::new(e.to_string()),
+            )
+        })?;
+
+        return match tags
+            .filter(name.like(format!("%{}%", name_query)))
+            .load::<Tags>(&mut conn)
+            .await
+        {
+            Ok(value) if value.is_empty() => Ok(None),
+            Ok(value) => Ok(Some(value)),
+            Err(Error::NotFound) => Ok(None),
+            Err(e) => Err(e),
+        };
+    }
+
+    pub async fn get_by_name(&self, name_query: &str) -> Result<Option<Tags>, Error> {
+        use crate::data::models::schema::tags::dsl::*;
+
+        let mut conn = connect_from_pool().await.map_err(|e| {
+            Error::DatabaseError(
+                DatabaseErrorKind::UnableToSendCommand,
+                Box::new(e.to_string()),
+            )
+        })?;
+
+        match tags
+            .filter(name.eq(name_query))
+            .first::<Tags>(&mut conn)
+            .await
+        {
+            Ok(value) => Ok(Some(value)),
+            Err(Error::NotFound) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+    /// Returns the tag with the exact given name, creating it first if it doesn't exist
+    pub async fn get_or_create(&self, tag_name: &str) -> Result<Tags, Error> {
+        let mut conn = connect_from_pool().await.map_err(|e| {
+            Error::DatabaseError(
+                DatabaseErrorKind::UnableToSendCommand,
+                Box::new(e.to_string()),
+            )
+        })?;
+
+        let db_lock = lock_db();
+        let _guard: MutexGuard<()> = db_lock.lock().await;
+
+        self.get_or_create_in(&mut conn, tag_name).await
+    }
+
+    /// Same as [`Self::get_or_create`], on the connection of a running transaction
+    pub async fn get_or_create_in(
+        &self,
+        conn: &mut DbConnection,
+        tag_name: &str,
+    ) -> Result<Tags, Error> {
+        use crate::data::models::schema::tags::dsl::*;
+
+        conn.transaction(|connection| {
+            async move {
+                let existing = tags
+                    .filter(name.eq(tag_name))
+                    .first::<Tags>(connection)
+                    .await
+                    .optional()?;
+
+                if let Some(tag) = existing {
+                    return Ok(tag);
+                }
+
+                diesel::insert_into(tags)
+                    .values(NewTag { name: tag_name })
+                    .execute(connection)
+                    .await?;
+
+                tags.filter(name.eq(tag_name))
+                    .order(tag_id.desc())
+                    .first::<Tags>(connection)
+                    .await
+            }
+            .scope_boxed()
+        })
+        .await
+    }
+}
+
+#[async_trait]
+impl Repository for TagRepo {
+    type Item = Tags;
+    type NewItem<'a> = NewTag<'a>;
+    type Form<'a> = UpdateTag<'a>;
+    type Id = i32;
+
+    async fn get_all(&self) -> Result<Option<Vec<Self::Item>>, Error> {
+        use crate::data::models::schema::tags::dsl::*;
+
+        let mut conn = connect_from_pool().await.map_err(|e| {
+            Error::DatabaseError(
+                DatabaseErrorKind::UnableToSendCommand,
+                Box::new(e.to_string()),
+            )
+        })?;
+
+        match tags.load::<Self::Item>(&mut conn).await {
+            Ok(value) if value.is_empty() => Ok(None),
+            Ok(value) => Ok(Some(value)),
+            Err(Error::NotFound) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn get_by_id(&self, id: Self::Id) -> Result<Option<Self::Item>, Error> {
+        use crate::data::models::schema::{tags as tag, tags::dsl::*};
+
+        let mut conn = connect_from_pool().await.map_err(|e| {
+            Error::DatabaseError(
+                DatabaseErrorKind::UnableToSendCommand,
+                Box::new(e.to_string()),
+            )
+        })?;
+
+        match tags
+            .filter(tag::tag_id.eq(id))
+            .first::<Tags>(&mut conn)
+            .await
+        {
+            Ok(value) => Ok(Some(value)),
+            Err(Error::NotFound) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn add<'a>(&self, new_item: Self::NewItem<'a>) -> Result<(), Error> {
+        use crate::data::models::schema::tags::dsl::*;
+
+        let mut conn = connect_from_pool().await.map_err(|e| {
+            Error::DatabaseError(
+                DatabaseErrorKind::UnableToSendCommand,
+                Box::new(e.to_string()),
+            )
+        })?;
+
+        let db_lock = lock_db();
+        let _guard: MutexGuard<()> = db_lock.lock().await;
+
+        match conn
+            .transaction(|connection| {
+                async move {
+                    diesel::insert_into(tags)
+                        .values(new_item)
+                        .execute(connection)
+                        .await?;
+
+                    Ok(())
+                }
+                .scope_boxed()
+            })
+            .await
+        {
+            Ok(_) => Ok(()),
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn update<'a>(&self, id: Self::Id, updated_item: Self::Form<'a>) -> Result<(), Error> {
+        use crate::data::models::schema::tags::dsl::*;
+
+        let mut conn = connect_from_pool().await.map_err(|e| {
+            Error::DatabaseError(
+                DatabaseErrorKind::UnableToSendCommand,
+                Box::new(e.to_string()),
+            )
+        })?;
+
+        let db_lock = lock_db();
+        let _guard: MutexGuard<()> = db_lock.lock().await;
+
+        conn.transaction(|connection| {
+            async move {
+                diesel::update(tags.filter(tag_id.eq(id)))
+                    .set(updated_item)
+                    .execute(connection)
+                    .await?;
+
+                Ok(())
+            }
+            .scope_boxed()
+        })
+        .await
+    }
+
+    async fn delete(&self, id: Self::Id) -> Result<(), Error> {
+        use crate::data::models::schema::tags::dsl::*;
+
+        let mut conn = connect_from_pool().await.map_err(|e| {
+            Error::DatabaseError(
+                DatabaseErrorKind::UnableToSendCommand,
+                Box::new(e.to_string()),
+            )
+        })?;
+
+        let db_lock = lock_db();
+        let _guard: MutexGuard<()> = db_lock.lock().await;
+
+        match conn
+            .transaction(|connection| {
+                async move {
+                    diesel::delete(tags.filter(tag_id.eq(id)))
+                        .execute(connection)
+                        .await?;
+                    Ok(())
+                }
+                .scope_boxed()
+            })
+            .await
+        {
+            Ok(_) => Ok(()),
+            Err(e) => Err(e),
+        }
+    }
+}
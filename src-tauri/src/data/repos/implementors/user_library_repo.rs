@@ -6,7 +6,7 @@ use diesel_async::{AsyncConnection, RunQueryDsl};
 use tokio::sync::MutexGuard;
 
 use crate::data::{
-    database::{connect_from_pool, lock_db},
+    database::{connect_from_pool, lock_db, DbConnection},
     models::{
         books::Books,
         user_library::{NewUserLibrary, UserLibrary},
@@ -56,17 +56,7 @@ impl UserLibraryRepo {
         return match user_library::table
             .inner_join(books::table.on(books::book_id.eq(user_library::book_id)))
             .filter(user_library::user_id.eq(uid))
-            .select((
-                books::book_id,
-                books::title,
-                books::published_date,
-                books::publisher_id,
-                books::isbn,
-                books::file_type,
-                books::file_path,
-                books::cover_image_path,
-                books::added_at,
-            ))
+            .select(Books::as_select())
             .load::<Books>(&mut conn)
             .await
         {
@@ -76,6 +66,33 @@ impl UserLibraryRepo {
             Err(e) => Err(e),
         };
     }
+
+    /// Same as [`Self::add`], on the connection of a running transaction
+    pub async fn add_in(
+        &self,
+        conn: &mut DbConnection,
+        new_item: NewUserLibrary,
+    ) -> Result<(), Error> {
+        use crate::data::models::schema::user_library::dsl::*;
+
+        match conn
+            .transaction(|connection| {
+                async move {
+                    diesel::insert_into(user_library)
+                        .values(new_item)
+                        .execute(connection)
+                        .await?;
+
+                    Ok(())
+                }
+                .scope_boxed()
+            })
+            .await
+        {
+            Ok(_) => Ok(()),
+            Err(e) => Err(e),
+        }
+    }
 }
 
 #[async_trait]
@@ -125,8 +142,6 @@ impl Repository for UserLibraryRepo {
     }
 
     async fn add<'a>(&self, new_item: Self::NewItem<'a>) -> Result<(), Error> {
-        use crate::data::models::schema::user_library::dsl::*;
-
         let mut conn = connect_from_pool().await.map_err(|e| {
             Error::DatabaseError(
                 DatabaseErrorKind::UnableToSendCommand,
@@ -137,23 +152,7 @@ impl Repository for UserLibraryRepo {
         let db_lock = lock_db();
         let _guard: MutexGuard<()> = db_lock.lock().await;
 
-        match conn
-            .transaction(|connection| {
-                async move {
-                    diesel::insert_into(user_library)
-                        .values(new_item)
-                        .execute(connection)
-                        .await?;
-
-                    Ok(())
-                }
-                .scope_boxed()
-            })
-            .await
-        {
-            Ok(_) => Ok(()),
-            Err(e) => Err(e),
-        }
+        self.add_in(&mut conn, new_item).await
     }
 
     async fn update<'a>(&self, _id: Self::Id, _updated_item: Self::Form<'a>) -> Result<(), Error> {
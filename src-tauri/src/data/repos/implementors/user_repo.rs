@@ -7,7 +7,7 @@ use tokio::sync::MutexGuard;
 
 use crate::data::{
     database::{connect_from_pool, lock_db},
-    models::users::{NewUser, UpdateUser, Users},
+    models::users::{NewUser, Role, UpdateUser, Users},
     repos::traits::repository::Repository,
 };
 
@@ -18,7 +18,30 @@ impl UserRepo {
         UserRepo
     }
 
-    pub async fn update_refresh_token(&self, id: i32, token: &str) -> Result<(), Error> {
+    /// Exact, case-sensitive username lookup
+    pub async fn get_by_username(&self, name: &str) -> Result<Option<Users>, Error> {
+        use crate::data::models::schema::users::dsl::*;
+
+        let mut conn = connect_from_pool().await.map_err(|e| {
+            Error::DatabaseError(
+                DatabaseErrorKind::UnableToSendCommand,
+                Box::new(e.to_string()),
+            )
+        })?;
+
+        match users
+            .filter(username.eq(name))
+            .first::<Users>(&mut conn)
+            .await
+        {
+            Ok(value) => Ok(Some(value)),
+            Err(Error::NotFound) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Sets or clears `disabled_at`
+    pub async fn set_disabled(&self, id: i32, at: Option<&str>) -> Result<(), Error> {
         use crate::data::models::schema::users::dsl::*;
 
         let mut conn = connect_from_pool().await.map_err(|e| {
@@ -34,7 +57,7 @@ impl UserRepo {
         conn.transaction(|connection| {
             async move {
                 diesel::update(users.filter(user_id.eq(id)))
-                    .set(refresh_token.eq(token))
+                    .set(disabled_at.eq(at))
                     .execute(connection)
                     .await?;
                 Ok(())
@@ -44,7 +67,8 @@ impl UserRepo {
         .await
     }
 
-    pub async fn get_by_refresh_token(&self, token: &str) -> Result<Option<Users>, Error> {
+    /// Admins that aren't disabled
+    pub async fn count_active_admins(&self) -> Result<i64, Error> {
         use crate::data::models::schema::users::dsl::*;
 
         let mut conn = connect_from_pool().await.map_err(|e| {
@@ -54,16 +78,27 @@ impl UserRepo {
             )
         })?;
 
-        match users
-            .filter(refresh_token.eq(token))
-            .first::<Users>(&mut conn)
+        users
+            .filter(role.eq(Role::Admin.as_str()))
+            .filter(disabled_at.is_null())
+            .count()
+            .get_result::<i64>(&mut conn)
             .await
-        {
-            Ok(value) => Ok(Some(value)),
-            Err(Error::NotFound) => Ok(None),
-            Err(e) => Err(e),
-        }
     }
+
+    pub async fn count(&self) -> Result<i64, Error> {
+        use crate::data::models::schema::users::dsl::*;
+
+        let mut conn = connect_from_pool().await.map_err(|e| {
+            Error::DatabaseError(
+                DatabaseErrorKind::UnableToSendCommand,
+                Box::new(e.to_string()),
+            )
+        })?;
+
+        users.count().get_result::<i64>(&mut conn).await
+    }
+
     // TODO: Create a more reliable search that returns only one user
     pub async fn search_by_username(
         &self,
@@ -222,7 +257,11 @@ impl Repository for UserRepo {
         }
     }
 
+    /// Deletes the user. Progress, bookmarks, annotations, sessions, API keys, reset
+    /// tokens and the personal book list go with it; libraries and metadata history
+    /// they created are kept with their author cleared.
     async fn delete(&self, id: Self::Id) -> Result<(), Error> {
+        use crate::data::models::schema::user_library;
         use crate::data::models::schema::users::dsl::*;
 
         let mut conn = connect_from_pool().await.map_err(|e| {
@@ -238,6 +277,10 @@ impl Repository for UserRepo {
         match conn
             .transaction(|connection| {
                 async move {
+                    // The only table referencing users without an ON DELETE action
+                    diesel::delete(user_library::table.filter(user_library::user_id.eq(id)))
+                        .execute(connection)
+                        .await?;
                     diesel::delete(users.filter(user_id.eq(id)))
                         .execute(connection)
                         .await?;
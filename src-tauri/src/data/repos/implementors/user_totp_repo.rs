@@ -0,0 +1,163 @@
+use diesel::prelude::*;
+use diesel::result::{DatabaseErrorKind, Error};
+use diesel_async::scoped_futures::ScopedFutureExt;
+use diesel_async::{AsyncConnection, RunQueryDsl};
+use tokio::sync::MutexGuard;
+
+use crate::data::{
+    database::{connect_from_pool, lock_db},
+    models::user_totp::{NewUserTotp, UserTotp},
+};
+
+pub struct UserTotpRepo;
+
+impl UserTotpRepo {
+    pub async fn new() -> Self {
+        UserTotpRepo
+    }
+
+    pub async fn get_by_user(&self, uid: i32) -> Result<Option<UserTotp>, Error> {
+        use crate::data::models::schema::user_totp::dsl::*;
+
+        let mut conn = connect_from_pool().await.map_err(|e| {
+            Error::DatabaseError(
+                DatabaseErrorKind::UnableToSendCommand,
+                Box::new(e.to_string()),
+            )
+        })?;
+
+        match user_totp
+            .filter(user_id.eq(uid))
+            .first::<UserTotp>(&mut conn)
+            .await
+        {
+            Ok(value) => Ok(Some(value)),
+            Err(Error::NotFound) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Stores a new secret for a user, replacing an enrollment that was never confirmed
+    pub async fn replace_for_user(&self, new_item: NewUserTotp<'_>) -> Result<(), Error> {
+        use crate::data::models::schema::user_totp::dsl::*;
+
+        let mut conn = connect_from_pool().await.map_err(|e| {
+            Error::DatabaseError(
+                DatabaseErrorKind::UnableToSendCommand,
+                Box::new(e.to_string()),
+            )
+        })?;
+
+        let db_lock = lock_db();
+        let _guard: MutexGuard<()> = db_lock.lock().await;
+
+        conn.transaction(|connection| {
+            async move {
+                diesel::delete(user_totp.filter(user_id.eq(new_item.user_id)))
+                    .execute(connection)
+                    .await?;
+                diesel::insert_into(user_totp)
+                    .values(new_item)
+                    .execute(connection)
+                    .await?;
+                Ok(())
+            }
+            .scope_boxed()
+        })
+        .await
+    }
+
+    /// Marks a pending enrollment as confirmed by the code of `step`. Returns `false`
+    /// if there is none or it was already confirmed.
+    pub async fn confirm(&self, uid: i32, step: i64, now: &str) -> Result<bool, Error> {
+        use crate::data::models::schema::user_totp::dsl::*;
+
+        let mut conn = connect_from_pool().await.map_err(|e| {
+            Error::DatabaseError(
+                DatabaseErrorKind::UnableToSendCommand,
+                Box::new(e.to_string()),
+            )
+        })?;
+
+        let db_lock = lock_db();
+        let _guard: MutexGuard<()> = db_lock.lock().await;
+
+        conn.transaction(|connection| {
+            async move {
+                let updated = diesel::update(
+                    user_totp
+                        .filter(user_id.eq(uid))
+                        .filter(confirmed_at.is_null()),
+                )
+                .set((confirmed_at.eq(now), last_step.eq(step)))
+                .execute(connection)
+                .await?;
+                Ok(updated > 0)
+            }
+            .scope_boxed()
+        })
+        .await
+    }
+
+    /// Records the code of `step` as used. Returns `false` if a code of that step or a
+    /// later one was already accepted, so each code works only once.
+    pub async fn use_step(&self, uid: i32, step: i64) -> Result<bool, Error> {
+        use crate::data::models::schema::user_totp::dsl::*;
+
+        let mut conn = connect_from_pool().await.map_err(|e| {
+            Error::DatabaseError(
+                DatabaseErrorKind::UnableToSendCommand,
+                Box::new(e.to_string()),
+            )
+        })?;
+
+        let db_lock = lock_db();
+        let _guard: MutexGuard<()> = db_lock.lock().await;
+
+        conn.transaction(|connection| {
+            async move {
+                let updated = diesel::update(
+                    user_totp
+                        .filter(user_id.eq(uid))
+                        .filter(last_step.is_null().or(last_step.lt(step))),
+                )
+                .set(last_step.eq(step))
+                .execute(connection)
+                .await?;
+                Ok(updated > 0)
+            }
+            .scope_boxed()
+        })
+        .await
+    }
+
+    /// Removes the secret and the recovery codes of a user, `false` if there was no secret
+    pub async fn delete_for_user(&self, uid: i32) -> Result<bool, Error> {
+        use crate::data::models::schema::recovery_codes;
+        use crate::data::models::schema::user_totp::dsl::*;
+
+        let mut conn = connect_from_pool().await.map_err(|e| {
+            Error::DatabaseError(
+                DatabaseErrorKind::UnableToSendCommand,
+                Box::new(e.to_string()),
+            )
+        })?;
+
+        let db_lock = lock_db();
+        let _guard: MutexGuard<()> = db_lock.lock().await;
+
+        conn.transaction(|connection| {
+            async move {
+                diesel::delete(recovery_codes::table.filter(recovery_codes::user_id.eq(uid)))
+                    .execute(connection)
+                    .await?;
+                let deleted = diesel::delete(user_totp.filter(user_id.eq(uid)))
+                    .execute(connection)
+                    .await?;
+                Ok(deleted > 0)
+            }
+            .scope_boxed()
+        })
+        .await
+    }
+}
@@ -0,0 +1,382 @@
+use diesel::prelude::*;
+use diesel::sql_query;
+use diesel::sql_types::{Double, Integer, Nullable, Text};
+use diesel::sqlite::SqliteConnection;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+pub type CalibreError = Box<dyn std::error::Error + Send + Sync>;
+
+// Reads a Calibre library directly from its `metadata.db` and folder layout.
+// Schema reference: https://github.com/kovidgoyal/calibre/blob/master/resources/metadata_sqlite.sql
+
+pub struct CalibreLibrary {
+    pub root: PathBuf,
+    pub custom_columns: Vec<CalibreCustomColumn>,
+    pub books: Vec<CalibreBook>,
+}
+
+pub struct CalibreAuthor {
+    pub name: String,
+    pub sort: Option<String>,
+}
+
+pub struct CalibreFormat {
+    /// Upper-case format name as Calibre stores it, e.g. `EPUB`
+    pub format: String,
+    pub path: PathBuf,
+    pub size: Option<i64>,
+}
+
+/// A user defined column of the Calibre library
+pub struct CalibreCustomColumn {
+    pub id: i32,
+    pub label: String,
+    pub name: String,
+    pub datatype: String,
+    pub is_multiple: bool,
+}
+
+pub struct CalibreCustomValue {
+    pub column_id: i32,
+    pub values: Vec<String>,
+    /// Series index for columns of the `series` datatype
+    pub index: Option<f64>,
+}
+
+pub struct CalibreBook {
+    pub calibre_id: i32,
+    pub uuid: Option<String>,
+    pub title: String,
+    pub published_date: Option<String>,
+    pub authors: Vec<CalibreAuthor>,
+    pub series: Option<String>,
+    pub series_index: Option<f64>,
+    pub tags: Vec<String>,
+    pub publisher: Option<String>,
+    /// (type, value) pairs such as `("isbn", "9780140328721")`
+    pub identifiers: Vec<(String, String)>,
+    /// Calibre scale: 0 to 10, two points per star
+    pub rating: Option<i32>,
+    pub comments: Option<String>,
+    pub languages: Vec<String>,
+    pub formats: Vec<CalibreFormat>,
+    pub cover_path: Option<PathBuf>,
+    pub custom_values: Vec<CalibreCustomValue>,
+}
+
+#[derive(QueryableByName)]
+struct BookRow {
+    #[diesel(sql_type = Integer)]
+    id: i32,
+    #[diesel(sql_type = Nullable<Text>)]
+    uuid: Option<String>,
+    #[diesel(sql_type = Text)]
+    title: String,
+    #[diesel(sql_type = Nullable<Text>)]
+    pubdate: Option<String>,
+    #[diesel(sql_type = Nullable<Double>)]
+    series_index: Option<f64>,
+    #[diesel(sql_type = Nullable<Text>)]
+    isbn: Option<String>,
+    #[diesel(sql_type = Text)]
+    path: String,
+    #[diesel(sql_type = Integer)]
+    has_cover: i32,
+}
+
+#[derive(QueryableByName)]
+struct AuthorRow {
+    #[diesel(sql_type = Integer)]
+    book: i32,
+    #[diesel(sql_type = Text)]
+    name: String,
+    #[diesel(sql_type = Nullable<Text>)]
+    sort: Option<String>,
+}
+
+#[derive(QueryableByName)]
+struct LinkRow {
+    #[diesel(sql_type = Integer)]
+    book: i32,
+    #[diesel(sql_type = Text)]
+    value: String,
+}
+
+#[derive(QueryableByName)]
+struct IdentifierRow {
+    #[diesel(sql_type = Integer)]
+    book: i32,
+    #[diesel(sql_type = Text)]
+    kind: String,
+    #[diesel(sql_type = Text)]
+    value: String,
+}
+
+#[derive(QueryableByName)]
+struct RatingRow {
+    #[diesel(sql_type = Integer)]
+    book: i32,
+    #[diesel(sql_type = Integer)]
+    rating: i32,
+}
+
+#[derive(QueryableByName)]
+struct DataRow {
+    #[diesel(sql_type = Integer)]
+    book: i32,
+    #[diesel(sql_type = Text)]
+    format: String,
+    #[diesel(sql_type = Nullable<Integer>)]
+    size: Option<i32>,
+    #[diesel(sql_type = Text)]
+    name: String,
+}
+
+#[derive(QueryableByName)]
+struct CustomColumnRow {
+    #[diesel(sql_type = Integer)]
+    id: i32,
+    #[diesel(sql_type = Text)]
+    label: String,
+    #[diesel(sql_type = Text)]
+    name: String,
+    #[diesel(sql_type = Text)]
+    datatype: String,
+    #[diesel(sql_type = Integer)]
+    is_multiple: i32,
+    #[diesel(sql_type = Integer)]
+    normalized: i32,
+}
+
+#[derive(QueryableByName)]
+struct CustomValueRow {
+    #[diesel(sql_type = Integer)]
+    book: i32,
+    #[diesel(sql_type = Text)]
+    value: String,
+    #[diesel(sql_type = Nullable<Double>)]
+    extra: Option<f64>,
+}
+
+/// Returns true if the directory looks like a Calibre library
+pub fn is_calibre_library(root: &Path) -> bool {
+    root.join("metadata.db").is_file()
+}
+
+/// Reads every book of the Calibre library found at `root`.
+/// The database is opened read-only so the Calibre library itself is never modified.
+pub async fn read_calibre_library(root: PathBuf) -> Result<CalibreLibrary, CalibreError> {
+    tokio::task::spawn_blocking(move || read_library_blocking(root)).await?
+}
+
+fn read_library_blocking(root: PathBuf) -> Result<CalibreLibrary, CalibreError> {
+    let db_path = root.join("metadata.db");
+    if !db_path.is_file() {
+        return Err(format!("No Calibre metadata.db found in {}", root.display()).into());
+    }
+    let url = format!("file:{}?mode=ro", db_path.to_string_lossy());
+    let mut conn = SqliteConnection::establish(&url)?;
+
+    let book_rows = sql_query(
+        "SELECT id, uuid, title, pubdate, series_index, isbn, path, has_cover FROM books ORDER BY id",
+    )
+    .load::<BookRow>(&mut conn)?;
+
+    let mut authors: HashMap<i32, Vec<CalibreAuthor>> = HashMap::new();
+    for row in sql_query(
+        "SELECT l.book AS book, a.name AS name, a.sort AS sort \
+         FROM books_authors_link l JOIN authors a ON a.id = l.author ORDER BY l.id",
+    )
+    .load::<AuthorRow>(&mut conn)?
+    {
+        authors.entry(row.book).or_default().push(CalibreAuthor {
+            name: row.name,
+            sort: row.sort,
+        });
+    }
+
+    let series = load_links(
+        &mut conn,
+        "SELECT l.book AS book, s.name AS value \
+         FROM books_series_link l JOIN series s ON s.id = l.series",
+    )?;
+    let tags = load_links(
+        &mut conn,
+        "SELECT l.book AS book, t.name AS value \
+         FROM books_tags_link l JOIN tags t ON t.id = l.tag ORDER BY t.name",
+    )?;
+    let publishers = load_links(
+        &mut conn,
+        "SELECT l.book AS book, p.name AS value \
+         FROM books_publishers_link l JOIN publishers p ON p.id = l.publisher",
+    )?;
+    let comments = load_links(&mut conn, "SELECT book, text AS value FROM comments")?;
+    let languages = load_links(
+        &mut conn,
+        "SELECT l.book AS book, g.lang_code AS value \
+         FROM books_languages_link l JOIN languages g ON g.id = l.lang_code ORDER BY l.item_order",
+    )?;
+
+    let mut identifiers: HashMap<i32, Vec<(String, String)>> = HashMap::new();
+    for row in sql_query("SELECT book, type AS kind, val AS value FROM identifiers ORDER BY type")
+        .load::<IdentifierRow>(&mut conn)?
+    {
+        identifiers
+            .entry(row.book)
+            .or_default()
+            .push((row.kind, row.value));
+    }
+
+    let ratings: HashMap<i32, i32> = sql_query(
+        "SELECT l.book AS book, r.rating AS rating \
+         FROM books_ratings_link l JOIN ratings r ON r.id = l.rating",
+    )
+    .load::<RatingRow>(&mut conn)?
+    .into_iter()
+    .map(|r| (r.book, r.rating))
+    .collect();
+
+    let mut data: HashMap<i32, Vec<DataRow>> = HashMap::new();
+    for row in sql_query("SELECT book, format, uncompressed_size AS size, name FROM data")
+        .load::<DataRow>(&mut conn)?
+    {
+        data.entry(row.book).or_default().push(row);
+    }
+
+    let column_rows = sql_query(
+        "SELECT id, label, name, datatype, is_multiple, normalized \
+         FROM custom_columns WHERE mark_for_delete = 0 ORDER BY id",
+    )
+    .load::<CustomColumnRow>(&mut conn)?;
+
+    let mut custom_values: HashMap<i32, Vec<CalibreCustomValue>> = HashMap::new();
+    for column in &column_rows {
+        // Composite columns are computed by Calibre at display time and have no stored values
+        if column.datatype == "composite" {
+            continue;
+        }
+        let query = if column.normalized != 0 {
+            let extra = if column.datatype == "series" {
+                "l.extra"
+            } else {
+                "NULL"
+            };
+            format!(
+                "SELECT l.book AS book, CAST(c.value AS TEXT) AS value, {extra} AS extra \
+                 FROM books_custom_column_{id}_link l JOIN custom_column_{id} c ON c.id = l.value \
+                 ORDER BY l.id",
+                extra = extra,
+                id = column.id
+            )
+        } else {
+            format!(
+                "SELECT book, CAST(value AS TEXT) AS value, NULL AS extra \
+                 FROM custom_column_{}",
+                column.id
+            )
+        };
+
+        let mut by_book: HashMap<i32, CalibreCustomValue> = HashMap::new();
+        for row in sql_query(query).load::<CustomValueRow>(&mut conn)? {
+            let entry = by_book.entry(row.book).or_insert(CalibreCustomValue {
+                column_id: column.id,
+                values: Vec::new(),
+                index: None,
+            });
+            entry.values.push(row.value);
+            if row.extra.is_some() {
+                entry.index = row.extra;
+            }
+        }
+        for (book, value) in by_book {
+            custom_values.entry(book).or_default().push(value);
+        }
+    }
+
+    let books = book_rows
+        .into_iter()
+        .map(|row| {
+            let book_dir = root.join(&row.path);
+            let formats = data
+                .remove(&row.id)
+                .unwrap_or_default()
+                .into_iter()
+                .map(|d| CalibreFormat {
+                    path: book_dir.join(format!("{}.{}", d.name, d.format.to_lowercase())),
+                    format: d.format,
+                    size: d.size.map(i64::from),
+                })
+                .collect();
+            let cover = book_dir.join("cover.jpg");
+            let has_series = series.contains_key(&row.id);
+
+            CalibreBook {
+                calibre_id: row.id,
+                uuid: row.uuid,
+                title: row.title,
+                published_date: row.pubdate.as_deref().and_then(parse_calibre_date),
+                authors: authors.remove(&row.id).unwrap_or_default(),
+                series: first_of(&series, row.id),
+                series_index: if has_series { row.series_index } else { None },
+                tags: tags.get(&row.id).cloned().unwrap_or_default(),
+                publisher: first_of(&publishers, row.id),
+                identifiers: identifiers.remove(&row.id).unwrap_or_else(|| {
+                    // Older libraries only have the legacy isbn column filled
+                    row.isbn
+                        .filter(|i| !i.is_empty())
+                        .map(|i| vec![("isbn".to_string(), i)])
+                        .unwrap_or_default()
+                }),
+                rating: ratings.get(&row.id).copied().filter(|r| *r > 0),
+                comments: first_of(&comments, row.id),
+                languages: languages.get(&row.id).cloned().unwrap_or_default(),
+                formats,
+                cover_path: (row.has_cover != 0 && cover.is_file()).then_some(cover),
+                custom_values: custom_values.remove(&row.id).unwrap_or_default(),
+            }
+        })
+        .collect();
+
+    let custom_columns = column_rows
+        .into_iter()
+        .map(|c| CalibreCustomColumn {
+            id: c.id,
+            label: c.label,
+            name: c.name,
+            datatype: c.datatype,
+            is_multiple: c.is_multiple != 0,
+        })
+        .collect();
+
+    Ok(CalibreLibrary {
+        root,
+        custom_columns,
+        books,
+    })
+}
+
+fn load_links(
+    conn: &mut SqliteConnection,
+    query: &str,
+) -> Result<HashMap<i32, Vec<String>>, CalibreError> {
+    let mut links: HashMap<i32, Vec<String>> = HashMap::new();
+    for row in sql_query(query).load::<LinkRow>(conn)? {
+        links.entry(row.book).or_default().push(row.value);
+    }
+    Ok(links)
+}
+
+fn first_of(links: &HashMap<i32, Vec<String>>, book: i32) -> Option<String> {
+    links.get(&book).and_then(|v| v.first().cloned())
+}
+
+/// Calibre stores `0101-01-01` when no publication date is known
+fn parse_calibre_date(value: &str) -> Option<String> {
+    let date = value.get(..10)?;
+    if date.starts_with("0101") {
+        None
+    } else {
+        Some(date.to_string())
+    }
+}
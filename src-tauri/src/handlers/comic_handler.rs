@@ -0,0 +1,107 @@
+use std::cmp::Ordering;
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::iter::Peekable;
+use std::path::Path;
+use std::str::Chars;
+
+use zip::ZipArchive;
+
+pub type ComicError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Extensions of the archive entries that are pages
+const PAGE_EXTENSIONS: [&str; 5] = ["jpg", "jpeg", "png", "gif", "webp"];
+
+/// Largest page image that is served
+const MAX_PAGE_BYTES: u64 = 64 * 1024 * 1024;
+
+/// Media type of a page by its file name
+pub fn page_mime_type(name: &str) -> &'static str {
+    match extension(name).as_deref() {
+        Some("png") => "image/png",
+        Some("gif") => "image/gif",
+        Some("webp") => "image/webp",
+        _ => "image/jpeg",
+    }
+}
+
+fn extension(name: &str) -> Option<String> {
+    Path::new(name)
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_ascii_lowercase())
+}
+
+fn take_number(chars: &mut Peekable<Chars>) -> String {
+    let mut digits = String::new();
+    while let Some(c) = chars.peek().filter(|c| c.is_ascii_digit()) {
+        digits.push(*c);
+        chars.next();
+    }
+    digits
+}
+
+/// Compares names the way people number pages, so `page2` comes before `page10`
+pub fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let mut a = a.chars().peekable();
+    let mut b = b.chars().peekable();
+    loop {
+        match (a.peek().copied(), b.peek().copied()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) if x.is_ascii_digit() && y.is_ascii_digit() => {
+                let x = take_number(&mut a);
+                let y = take_number(&mut b);
+                let (x_trimmed, y_trimmed) = (x.trim_start_matches('0'), y.trim_start_matches('0'));
+                let order = x_trimmed
+                    .len()
+                    .cmp(&y_trimmed.len())
+                    .then_with(|| x_trimmed.cmp(y_trimmed));
+                if order != Ordering::Equal {
+                    return order;
+                }
+            }
+            (Some(x), Some(y)) => {
+                let order = x.to_lowercase().cmp(y.to_lowercase());
+                if order != Ordering::Equal {
+                    return order;
+                }
+                a.next();
+                b.next();
+            }
+        }
+    }
+}
+
+fn open(path: &str) -> Result<ZipArchive<BufReader<File>>, ComicError> {
+    Ok(ZipArchive::new(BufReader::new(File::open(path)?))?)
+}
+
+/// Names of the page images of a CBZ archive, in reading order
+pub fn page_names(path: &str) -> Result<Vec<String>, ComicError> {
+    let archive = open(path)?;
+    let mut names: Vec<String> = archive
+        .file_names()
+        .filter(|name| !name.ends_with('/'))
+        // macOS resource forks look like pages but aren't
+        .filter(|name| !name.starts_with("__MACOSX/"))
+        .filter(|name| extension(name).is_some_and(|ext| PAGE_EXTENSIONS.contains(&ext.as_str())))
+        .map(str::to_string)
+        .collect();
+    names.sort_by(|a, b| natural_cmp(a, b));
+    Ok(names)
+}
+
+/// Reads a page image by its entry name, along with its media type
+pub fn read_page(path: &str, name: &str) -> Result<(Vec<u8>, &'static str), ComicError> {
+    let mut archive = open(path)?;
+    let file = archive.by_name(name)?;
+    // The size in the archive header can't be trusted, so the read itself is capped
+    let mut data = Vec::new();
+    file.take(MAX_PAGE_BYTES + 1).read_to_end(&mut data)?;
+    if data.len() as u64 > MAX_PAGE_BYTES {
+        return Err(format!("Page {} is larger than {} bytes", name, MAX_PAGE_BYTES).into());
+    }
+    Ok((data, page_mime_type(name)))
+}
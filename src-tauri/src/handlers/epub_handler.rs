@@ -1,3 +1,4 @@
+use crate::parsers::isbn;
 use base64::{engine::general_purpose, Engine as _};
 use rbook::{prelude::*, Ebook, Epub};
 use regex::Regex;
@@ -9,8 +10,9 @@ use walkdir::WalkDir;
 /// # This module uses the `rbook` crate to handle EPUB files with the 'threadsafe' feature enabled.
 /// Documentation: https://docs.rs/rbook/latest/rbook/
 // A struct to hold metadata parsed from an EPUB file.
+// Fields missing from the file are left empty, fallbacks are applied when the book is stored.
 pub struct BookMetadata {
-    pub title: String,
+    pub title: Option<String>,
     pub authors: Vec<String>,
     pub published_date: Option<String>,
     pub publishers: Vec<String>,
@@ -53,30 +55,26 @@ pub async fn parse_epub_meta(
 
         let title = metadata
             .title()
-            .map(|t| t.value().to_string())
-            .unwrap_or_else(|| "Unknown Title".to_string());
+            .map(|t| t.value().trim().to_string())
+            .filter(|t| !is_placeholder(t));
 
-        let mut authors: Vec<String> = metadata.creators().map(|c| c.value().to_string()).collect();
+        let authors: Vec<String> = metadata
+            .creators()
+            .map(|c| c.value().trim().to_string())
+            .filter(|c| !is_placeholder(c))
+            .collect();
 
-        let mut publishers: Vec<String> = metadata
+        let publishers: Vec<String> = metadata
             .publishers()
-            .map(|p| p.value().to_string())
+            .map(|p| p.value().trim().to_string())
+            .filter(|p| !is_placeholder(p))
             .collect::<Vec<String>>();
 
-        if publishers.is_empty() {
-            publishers.push("Unknown Publisher".to_string());
-        }
-
-        if authors.is_empty() {
-            authors.push("Unknown Author".to_string());
-        }
-
         let published_date = metadata.publication_date().map(|d| d.to_string());
 
         let isbn = metadata
             .identifiers()
-            .find(|i| i.value().starts_with("urn:isbn:"))
-            .map(|i| i.value().to_string());
+            .find_map(|i| isbn::from_identifier(i.value(), i.scheme().map(|s| s.code())));
 
         let cover_data = if let Some(cover_image) = book.manifest().images().next() {
             let mime_type = cover_image.resource_kind().as_str().to_string();
@@ -101,6 +99,19 @@ pub async fn parse_epub_meta(
     .await?
 }
 
+/// Tools writing EPUBs often fill in these values instead of leaving a field empty
+fn is_placeholder(value: &str) -> bool {
+    const PLACEHOLDERS: [&str; 6] = [
+        "",
+        "unknown",
+        "unknown title",
+        "unknown author",
+        "unknown publisher",
+        "untitled",
+    ];
+    PLACEHOLDERS.contains(&value.to_lowercase().as_str())
+}
+
 /// Stores a cover image to disk and returns the path.
 /// The cover is stored in a `covers` subdirectory of the current working directory.
 pub async fn store_cover_to_disk(
@@ -1 +1,4 @@
+pub mod calibre_handler;
+pub mod comic_handler;
 pub mod epub_handler;
+pub mod pdf_handler;
@@ -0,0 +1,92 @@
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::ops::Range;
+
+use once_cell::sync::Lazy;
+use regex::bytes::Regex;
+
+pub type PdfError = Box<dyn std::error::Error + Send + Sync>;
+
+// PDFs can't be rendered here, so only scanned PDFs can be streamed page by page:
+// those hold one JPEG per page, and the JPEG is the page. A PDF qualifies when it has
+// exactly as many JPEG images as pages, which are then taken in the order they are stored.
+
+static PAGE: Lazy<Regex> = Lazy::new(|| Regex::new(r"/Type\s*/Page\b").unwrap());
+static STREAM_START: Lazy<Regex> = Lazy::new(|| Regex::new(r">>\s*stream(?:\r\n|\n|\r)").unwrap());
+static LENGTH: Lazy<Regex> = Lazy::new(|| Regex::new(r"/Length\s+(\d+)(\s+\d+\s+R)?").unwrap());
+static IMAGE: Lazy<Regex> = Lazy::new(|| Regex::new(r"/Subtype\s*/Image\b").unwrap());
+static JPEG: Lazy<Regex> = Lazy::new(|| Regex::new(r"/DCTDecode\b").unwrap());
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
+
+fn rfind(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).rposition(|w| w == needle)
+}
+
+/// Number of page objects, pages inside compressed object streams aren't seen
+fn page_count(data: &[u8]) -> usize {
+    PAGE.find_iter(data).count()
+}
+
+/// Byte ranges of the JPEG images stored in the document, in file order
+fn jpeg_streams(data: &[u8]) -> Vec<Range<usize>> {
+    let mut streams = Vec::new();
+    let mut pos = 0;
+    while let Some(m) = STREAM_START.find_at(data, pos) {
+        let start = m.end();
+        let dict_start = rfind(&data[..m.start()], b"obj").unwrap_or(0);
+        let dict = &data[dict_start..m.start()];
+
+        // The length is only usable when it is given directly instead of as a reference
+        let length = LENGTH
+            .captures(dict)
+            .filter(|c| c.get(2).is_none())
+            .and_then(|c| std::str::from_utf8(&c[1]).ok()?.parse::<usize>().ok());
+        let end = match length
+            .and_then(|length| start.checked_add(length))
+            .filter(|end| *end <= data.len())
+        {
+            Some(end) => end,
+            None => match find(&data[start..], b"endstream") {
+                Some(offset) => {
+                    let mut end = start + offset;
+                    while end > start && matches!(data[end - 1], b'\r' | b'\n') {
+                        end -= 1;
+                    }
+                    end
+                }
+                None => break,
+            },
+        };
+
+        if IMAGE.is_match(dict) && JPEG.is_match(dict) {
+            streams.push(start..end);
+        }
+        pos = end;
+    }
+    streams
+}
+
+/// The JPEG of every page, `None` if the PDF isn't a scan with one JPEG per page
+pub fn page_images(data: &[u8]) -> Option<Vec<Range<usize>>> {
+    let pages = page_count(data);
+    let images = jpeg_streams(data);
+    (pages > 0 && images.len() == pages).then_some(images)
+}
+
+/// The JPEG of every page, empty if the PDF can't be streamed
+pub fn streamable_pages(path: &str) -> Result<Vec<Range<usize>>, PdfError> {
+    let data = std::fs::read(path)?;
+    Ok(page_images(&data).unwrap_or_default())
+}
+
+/// Reads the JPEG of a page found by `streamable_pages`, without loading the rest of the file
+pub fn read_page(path: &str, range: Range<usize>) -> Result<Vec<u8>, PdfError> {
+    let mut file = File::open(path)?;
+    file.seek(SeekFrom::Start(range.start as u64))?;
+    let mut data = Vec::new();
+    file.take(range.len() as u64).read_to_end(&mut data)?;
+    Ok(data)
+}
@@ -1 +1,243 @@
+use crate::{
+    data::models::books::Books,
+    opds::{
+        feed_builder::{timestamp, Entry, FeedBuilder, Link, ACQUISITION_TYPE},
+        navigation::{feed, href},
+    },
+};
 
+pub const REL_ACQUISITION: &str = "http://opds-spec.org/acquisition";
+pub const REL_IMAGE: &str = "http://opds-spec.org/image";
+pub const REL_THUMBNAIL: &str = "http://opds-spec.org/image/thumbnail";
+/// OPDS Page Streaming Extension link to single pages
+pub const REL_PSE_STREAM: &str = "http://vaemendis.net/opds-pse/stream";
+pub const REL_FIRST: &str = "first";
+pub const REL_LAST: &str = "last";
+pub const REL_NEXT: &str = "next";
+pub const REL_PREVIOUS: &str = "previous";
+
+/// A book with everything its catalog entry shows
+#[derive(Debug)]
+pub struct BookDetails {
+    pub book: Books,
+    pub authors: Vec<String>,
+    pub publisher: Option<String>,
+    pub tags: Vec<String>,
+    /// Identifier schemes and values, e.g. `("calibre", "<uuid>")`
+    pub identifiers: Vec<(String, String)>,
+    /// Formats (lowercase extensions) of the files that can be downloaded
+    pub formats: Vec<String>,
+    /// Number of pages that can be streamed, `None` if the book can't be streamed
+    pub page_count: Option<usize>,
+    pub last_read: Option<LastRead>,
+}
+
+/// Reading position of the user a feed is built for
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LastRead {
+    /// Page index, starting at 0 like the stream link's `{pageNumber}`
+    pub page: usize,
+    pub read_at: Option<String>,
+}
+
+/// Media type of a book file by its format
+pub fn format_mime_type(format: &str) -> &'static str {
+    match format.to_ascii_lowercase().as_str() {
+        "epub" => "application/epub+zip",
+        "kepub" => "application/kepub+zip",
+        "pdf" => "application/pdf",
+        "mobi" | "prc" => "application/x-mobipocket-ebook",
+        "azw" | "azw3" => "application/vnd.amazon.ebook",
+        "cbz" => "application/vnd.comicbook+zip",
+        "cbr" => "application/vnd.comicbook-rar",
+        "fb2" => "application/x-fictionbook+xml",
+        "djvu" => "image/vnd.djvu",
+        "rtf" => "application/rtf",
+        "txt" => "text/plain",
+        "html" | "htm" => "text/html",
+        _ => "application/octet-stream",
+    }
+}
+
+/// Media type of a stored cover by its extension
+pub fn image_mime_type(path: &str) -> &'static str {
+    match path
+        .rsplit('.')
+        .next()
+        .map(|e| e.to_ascii_lowercase())
+        .as_deref()
+    {
+        Some("png") => "image/png",
+        Some("gif") => "image/gif",
+        _ => "image/jpeg",
+    }
+}
+
+/// Descriptions imported from Calibre are HTML, entries only carry their text
+pub fn plain_text(html: &str) -> String {
+    let fragment = scraper::Html::parse_fragment(html);
+    let text: Vec<&str> = fragment.root_element().text().collect();
+    text.join(" ")
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Identifiers in the URN form readers recognize
+pub fn identifier_urn(scheme: &str, value: &str) -> String {
+    match scheme.to_ascii_lowercase().as_str() {
+        "isbn" => format!("urn:isbn:{}", value),
+        "calibre" | "uuid" => format!("urn:uuid:{}", value),
+        scheme => format!("{}:{}", scheme, value),
+    }
+}
+
+pub fn book_entry(details: &BookDetails) -> Entry {
+    let book = &details.book;
+    let book_path = format!("/books/{}", book.book_id);
+
+    let mut entry = Entry::new(
+        &format!("urn:stellaron:book:{}", book.book_id),
+        &book.title,
+        &timestamp(book.added_at.as_deref()),
+    );
+    entry.authors = details.authors.clone();
+
+    if let Some(publisher) = &details.publisher {
+        entry.dc.push(("publisher".to_string(), publisher.clone()));
+    }
+    if let Some(language) = &book.language {
+        entry.dc.push(("language".to_string(), language.clone()));
+    }
+    if let Some(issued) = &book.published_date {
+        entry.dc.push(("issued".to_string(), issued.clone()));
+    }
+    if let Some(isbn) = &book.isbn {
+        entry
+            .dc
+            .push(("identifier".to_string(), identifier_urn("isbn", isbn)));
+    }
+    for (scheme, value) in &details.identifiers {
+        let urn = identifier_urn(scheme, value);
+        if !entry.dc.iter().any(|(_, v)| *v == urn) {
+            entry.dc.push(("identifier".to_string(), urn));
+        }
+    }
+
+    entry.categories = details
+        .tags
+        .iter()
+        .map(|t| (t.clone(), t.clone()))
+        .collect();
+    entry.summary = book
+        .description
+        .as_deref()
+        .map(plain_text)
+        .filter(|s| !s.is_empty());
+
+    if let Some(cover) = &book.cover_image_path {
+        let mime_type = image_mime_type(cover);
+        entry.links.push(Link::new(
+            REL_IMAGE,
+            &href(&format!("{}/cover", book_path)),
+            mime_type,
+        ));
+        // Only PNG covers can be scaled down, readers scale other covers themselves
+        if mime_type == "image/png" {
+            entry.links.push(Link::new(
+                REL_THUMBNAIL,
+                &href(&format!("{}/thumbnail", book_path)),
+                mime_type,
+            ));
+        }
+    }
+    for format in &details.formats {
+        entry.links.push(
+            Link::new(
+                REL_ACQUISITION,
+                &href(&format!("{}/download/{}", book_path, format)),
+                format_mime_type(format),
+            )
+            .title(&format.to_uppercase()),
+        );
+    }
+
+    if let Some(count) = details.page_count {
+        let mut link = Link::new(
+            REL_PSE_STREAM,
+            &href(&format!(
+                "{}/pages/{{pageNumber}}?maxWidth={{maxWidth}}",
+                book_path
+            )),
+            "image/jpeg",
+        )
+        .attribute("pse:count", &count.to_string());
+        if let Some(last_read) = &details.last_read {
+            link = link.attribute("pse:lastRead", &last_read.page.to_string());
+            if let Some(read_at) = &last_read.read_at {
+                link = link.attribute("pse:lastReadDate", &timestamp(Some(read_at)));
+            }
+        }
+        entry.links.push(link);
+    }
+
+    entry
+}
+
+/// Position of a feed page, `number` starts at 1
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Page {
+    pub number: i64,
+    pub size: i64,
+    pub total: i64,
+}
+
+impl Page {
+    pub fn last(&self) -> i64 {
+        ((self.total + self.size - 1) / self.size).max(1)
+    }
+}
+
+fn page_href(path: &str, number: i64) -> String {
+    let separator = if path.contains('?') { '&' } else { '?' };
+    format!("{}{}page={}", href(path), separator, number)
+}
+
+/// Adds first, previous, next and last links for a paginated feed
+pub fn paginate(mut builder: FeedBuilder, path: &str, page: Page) -> FeedBuilder {
+    let last = page.last();
+    builder = builder
+        .link(Link::new(REL_FIRST, &page_href(path, 1), ACQUISITION_TYPE))
+        .link(Link::new(
+            REL_LAST,
+            &page_href(path, last),
+            ACQUISITION_TYPE,
+        ));
+    if page.number > 1 {
+        builder = builder.link(Link::new(
+            REL_PREVIOUS,
+            &page_href(path, (page.number - 1).min(last)),
+            ACQUISITION_TYPE,
+        ));
+    }
+    if page.number < last {
+        builder = builder.link(Link::new(
+            REL_NEXT,
+            &page_href(path, page.number + 1),
+            ACQUISITION_TYPE,
+        ));
+    }
+    builder
+}
+
+/// Feed listing one page of books, `path` is relative to the catalog root
+pub fn books_feed(
+    id: &str,
+    title: &str,
+    path: &str,
+    books: &[BookDetails],
+    page: Page,
+) -> FeedBuilder {
+    let builder = feed(id, title, path, ACQUISITION_TYPE).entries(books.iter().map(book_entry));
+    paginate(builder, path, page)
+}
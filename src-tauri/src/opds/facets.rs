@@ -0,0 +1,235 @@
+use crate::{
+    data::repos::implementors::book_repo::{BookFacets, BookOrder, FacetValues, ReadState},
+    opds::{
+        acquisition::{books_feed, BookDetails, Page},
+        feed_builder::{FeedBuilder, Link, ACQUISITION_TYPE},
+        navigation::href,
+        search::percent_encode,
+    },
+};
+
+pub const REL_FACET: &str = "http://opds-spec.org/facet";
+
+/// Sort order and filters picked through the facet links of a book listing
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FacetSelection {
+    pub order: BookOrder,
+    pub facets: BookFacets,
+}
+
+impl Default for FacetSelection {
+    fn default() -> Self {
+        FacetSelection {
+            order: BookOrder::Title,
+            facets: BookFacets::default(),
+        }
+    }
+}
+
+pub fn order_name(order: BookOrder) -> &'static str {
+    match order {
+        BookOrder::Title => "title",
+        BookOrder::Author => "author",
+        BookOrder::Newest => "newest",
+        BookOrder::Published => "published",
+    }
+}
+
+pub fn parse_order(name: &str) -> Option<BookOrder> {
+    match name {
+        "title" => Some(BookOrder::Title),
+        "author" => Some(BookOrder::Author),
+        "newest" => Some(BookOrder::Newest),
+        "published" => Some(BookOrder::Published),
+        _ => None,
+    }
+}
+
+pub fn read_state_name(state: ReadState) -> &'static str {
+    match state {
+        ReadState::Unread => "unread",
+        ReadState::Reading => "reading",
+        ReadState::Finished => "finished",
+    }
+}
+
+pub fn parse_read_state(name: &str) -> Option<ReadState> {
+    match name {
+        "unread" => Some(ReadState::Unread),
+        "reading" => Some(ReadState::Reading),
+        "finished" => Some(ReadState::Finished),
+        _ => None,
+    }
+}
+
+impl FacetSelection {
+    /// Query string selecting this order and these filters, without the leading `?`
+    pub fn query(&self) -> String {
+        let facets = &self.facets;
+        let mut params = vec![format!("sort={}", order_name(self.order))];
+        if let Some(format) = &facets.format {
+            params.push(format!("format={}", percent_encode(format)));
+        }
+        if let Some(language) = &facets.language {
+            params.push(format!("language={}", percent_encode(language)));
+        }
+        if let Some(state) = facets.read_state {
+            params.push(format!("status={}", read_state_name(state)));
+        }
+        if let Some(id) = facets.tag_id {
+            params.push(format!("tag={}", id));
+        }
+        if let Some(id) = facets.series_id {
+            params.push(format!("series={}", id));
+        }
+        params.join("&")
+    }
+
+    /// `path` with the query string, relative to the catalog root
+    pub fn path(&self, path: &str) -> String {
+        format!("{}?{}", path, self.query())
+    }
+}
+
+/// Link to `selection`, marked as the active facet of its group when `active`
+fn facet_link(
+    path: &str,
+    group: &str,
+    title: &str,
+    selection: &FacetSelection,
+    active: bool,
+) -> Link {
+    let link = Link::new(REL_FACET, &href(&selection.path(path)), ACQUISITION_TYPE)
+        .title(title)
+        .attribute("opds:facetGroup", group);
+    if active {
+        link.attribute("opds:activeFacet", "true")
+    } else {
+        link
+    }
+}
+
+/// Links for one filter group, starting with one that clears the filter.
+/// `apply` sets the filter on a copy of the current selection.
+fn filter_group<T: PartialEq + Clone>(
+    path: &str,
+    current: &FacetSelection,
+    group: &str,
+    active: Option<&T>,
+    values: Vec<(T, String)>,
+    apply: impl Fn(&mut BookFacets, Option<T>),
+) -> Vec<Link> {
+    let mut all = current.clone();
+    apply(&mut all.facets, None);
+    let mut links = vec![facet_link(path, group, "All", &all, active.is_none())];
+
+    for (value, title) in values {
+        let mut selection = current.clone();
+        let is_active = active == Some(&value);
+        apply(&mut selection.facets, Some(value));
+        links.push(facet_link(path, group, &title, &selection, is_active));
+    }
+    links
+}
+
+/// Facet links for a book listing under `path`. Groups without anything to choose
+/// from are left out, the read status group unless the selection is for a user.
+pub fn facet_links(path: &str, current: &FacetSelection, values: &FacetValues) -> Vec<Link> {
+    let mut links: Vec<Link> = [
+        (BookOrder::Title, "Title"),
+        (BookOrder::Author, "Author"),
+        (BookOrder::Newest, "Recently added"),
+        (BookOrder::Published, "Publication date"),
+    ]
+    .into_iter()
+    .map(|(order, title)| {
+        let selection = FacetSelection {
+            order,
+            ..current.clone()
+        };
+        facet_link(path, "Sort by", title, &selection, order == current.order)
+    })
+    .collect();
+
+    let facets = &current.facets;
+    if !values.formats.is_empty() {
+        links.extend(filter_group(
+            path,
+            current,
+            "Format",
+            facets.format.as_ref(),
+            values
+                .formats
+                .iter()
+                .map(|f| (f.clone(), f.to_uppercase()))
+                .collect(),
+            |f, v| f.format = v,
+        ));
+    }
+    if !values.languages.is_empty() {
+        links.extend(filter_group(
+            path,
+            current,
+            "Language",
+            facets.language.as_ref(),
+            values
+                .languages
+                .iter()
+                .map(|l| (l.clone(), l.clone()))
+                .collect(),
+            |f, v| f.language = v,
+        ));
+    }
+    if facets.user_id.is_some() {
+        links.extend(filter_group(
+            path,
+            current,
+            "Status",
+            facets.read_state.as_ref(),
+            vec![
+                (ReadState::Unread, "Unread".to_string()),
+                (ReadState::Reading, "Reading".to_string()),
+                (ReadState::Finished, "Finished".to_string()),
+            ],
+            |f, v| f.read_state = v,
+        ));
+    }
+    if !values.tags.is_empty() {
+        links.extend(filter_group(
+            path,
+            current,
+            "Tag",
+            facets.tag_id.as_ref(),
+            values.tags.clone(),
+            |f, v| f.tag_id = v,
+        ));
+    }
+    if !values.series.is_empty() {
+        links.extend(filter_group(
+            path,
+            current,
+            "Series",
+            facets.series_id.as_ref(),
+            values.series.clone(),
+            |f, v| f.series_id = v,
+        ));
+    }
+    links
+}
+
+/// Book listing with facet links, the selection is kept in the self and paging links
+pub fn faceted_feed(
+    id: &str,
+    title: &str,
+    path: &str,
+    selection: &FacetSelection,
+    values: &FacetValues,
+    books: &[BookDetails],
+    page: Page,
+) -> FeedBuilder {
+    let mut builder = books_feed(id, title, &selection.path(path), books, page);
+    for link in facet_links(path, selection, values) {
+        builder = builder.link(link);
+    }
+    builder
+}
@@ -1 +1,269 @@
+use chrono::{NaiveDateTime, SecondsFormat, Utc};
 
+// Minimal Atom writer for OPDS 1.2 catalogs.
+// Feeds are small and flat, so they are written directly as text instead of going
+// through a DOM; every value passes through `escape` on the way out.
+
+pub const ATOM_NS: &str = "http://www.w3.org/2005/Atom";
+pub const DC_NS: &str = "http://purl.org/dc/terms/";
+pub const OPDS_NS: &str = "http://opds-spec.org/2010/catalog";
+pub const PSE_NS: &str = "http://vaemendis.net/opds-pse/ns";
+
+/// Content type of feeds listing other feeds
+pub const NAVIGATION_TYPE: &str = "application/atom+xml;profile=opds-catalog;kind=navigation";
+/// Content type of feeds listing books
+pub const ACQUISITION_TYPE: &str = "application/atom+xml;profile=opds-catalog;kind=acquisition";
+
+pub const REL_SELF: &str = "self";
+pub const REL_START: &str = "start";
+pub const REL_UP: &str = "up";
+pub const REL_SUBSECTION: &str = "subsection";
+/// Books sorted by the date they were added
+pub const REL_NEW: &str = "http://opds-spec.org/sort/new";
+
+/// Escapes text for use in element content and attribute values
+pub fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            // Control characters other than whitespace are not allowed in XML 1.0
+            c if c.is_control() && !matches!(c, '\n' | '\r' | '\t') => {}
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Current time in the RFC 3339 format Atom requires
+pub fn now() -> String {
+    Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+/// Converts an SQLite `datetime('now')` value to RFC 3339, falling back to the current time
+pub fn timestamp(value: Option<&str>) -> String {
+    value
+        .and_then(|v| NaiveDateTime::parse_from_str(v, "%Y-%m-%d %H:%M:%S").ok())
+        .map(|dt| dt.and_utc().to_rfc3339_opts(SecondsFormat::Secs, true))
+        .unwrap_or_else(now)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Link {
+    pub rel: String,
+    pub href: String,
+    pub link_type: String,
+    pub title: Option<String>,
+    /// Extension attributes such as `opds:facetGroup`, written as given
+    pub attributes: Vec<(String, String)>,
+}
+
+impl Link {
+    pub fn new(rel: &str, href: &str, link_type: &str) -> Self {
+        Link {
+            rel: rel.to_string(),
+            href: href.to_string(),
+            link_type: link_type.to_string(),
+            title: None,
+            attributes: Vec::new(),
+        }
+    }
+
+    pub fn title(mut self, title: &str) -> Self {
+        self.title = Some(title.to_string());
+        self
+    }
+
+    pub fn attribute(mut self, name: &str, value: &str) -> Self {
+        self.attributes.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    fn write(&self, out: &mut String, indent: &str) {
+        out.push_str(&format!(
+            "{}<link rel=\"{}\" href=\"{}\" type=\"{}\"",
+            indent,
+            escape(&self.rel),
+            escape(&self.href),
+            escape(&self.link_type)
+        ));
+        if let Some(title) = &self.title {
+            out.push_str(&format!(" title=\"{}\"", escape(title)));
+        }
+        for (name, value) in &self.attributes {
+            out.push_str(&format!(" {}=\"{}\"", name, escape(value)));
+        }
+        out.push_str("/>\n");
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Entry {
+    pub id: String,
+    pub title: String,
+    pub updated: String,
+    pub authors: Vec<String>,
+    pub summary: Option<String>,
+    /// Plain text content, shown by most readers as the entry description
+    pub content: Option<String>,
+    /// Dublin Core elements, e.g. `("language", "en")` becomes `<dc:language>en</dc:language>`
+    pub dc: Vec<(String, String)>,
+    /// Category terms and labels
+    pub categories: Vec<(String, String)>,
+    pub links: Vec<Link>,
+}
+
+impl Entry {
+    pub fn new(id: &str, title: &str, updated: &str) -> Self {
+        Entry {
+            id: id.to_string(),
+            title: title.to_string(),
+            updated: updated.to_string(),
+            ..Default::default()
+        }
+    }
+
+    pub fn link(mut self, link: Link) -> Self {
+        self.links.push(link);
+        self
+    }
+
+    pub fn content(mut self, content: &str) -> Self {
+        self.content = Some(content.to_string());
+        self
+    }
+
+    fn write(&self, out: &mut String) {
+        out.push_str("  <entry>\n");
+        out.push_str(&format!("    <id>{}</id>\n", escape(&self.id)));
+        out.push_str(&format!("    <title>{}</title>\n", escape(&self.title)));
+        out.push_str(&format!(
+            "    <updated>{}</updated>\n",
+            escape(&self.updated)
+        ));
+        for author in &self.authors {
+            out.push_str(&format!(
+                "    <author><name>{}</name></author>\n",
+                escape(author)
+            ));
+        }
+        for (name, value) in &self.dc {
+            out.push_str(&format!(
+                "    <dc:{0}>{1}</dc:{0}>\n",
+                escape(name),
+                escape(value)
+            ));
+        }
+        for (term, label) in &self.categories {
+            out.push_str(&format!(
+                "    <category term=\"{}\" label=\"{}\"/>\n",
+                escape(term),
+                escape(label)
+            ));
+        }
+        if let Some(summary) = &self.summary {
+            out.push_str(&format!(
+                "    <summary type=\"text\">{}</summary>\n",
+                escape(summary)
+            ));
+        }
+        if let Some(content) = &self.content {
+            out.push_str(&format!(
+                "    <content type=\"text\">{}</content>\n",
+                escape(content)
+            ));
+        }
+        for link in &self.links {
+            link.write(out, "    ");
+        }
+        out.push_str("  </entry>\n");
+    }
+}
+
+/// Builds an Atom feed.
+///
+/// # Usage
+///
+/// ```rust,ignore
+/// let xml = FeedBuilder::new("urn:stellaron:root", "Stellaron")
+///     .link(Link::new(REL_SELF, "/opds", NAVIGATION_TYPE))
+///     .entry(Entry::new("urn:stellaron:books", "All books", &now()))
+///     .build();
+/// ```
+#[derive(Debug, Clone)]
+pub struct FeedBuilder {
+    id: String,
+    title: String,
+    updated: String,
+    author: Option<String>,
+    links: Vec<Link>,
+    entries: Vec<Entry>,
+}
+
+impl FeedBuilder {
+    pub fn new(id: &str, title: &str) -> Self {
+        FeedBuilder {
+            id: id.to_string(),
+            title: title.to_string(),
+            updated: now(),
+            author: None,
+            links: Vec::new(),
+            entries: Vec::new(),
+        }
+    }
+
+    pub fn updated(mut self, updated: &str) -> Self {
+        self.updated = updated.to_string();
+        self
+    }
+
+    /// Feed level author, Atom requires one when an entry has no author of its own
+    pub fn author(mut self, name: &str) -> Self {
+        self.author = Some(name.to_string());
+        self
+    }
+
+    pub fn link(mut self, link: Link) -> Self {
+        self.links.push(link);
+        self
+    }
+
+    pub fn entry(mut self, entry: Entry) -> Self {
+        self.entries.push(entry);
+        self
+    }
+
+    pub fn entries<I: IntoIterator<Item = Entry>>(mut self, entries: I) -> Self {
+        self.entries.extend(entries);
+        self
+    }
+
+    pub fn build(&self) -> String {
+        let mut out = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+        out.push_str(&format!(
+            "<feed xmlns=\"{}\" xmlns:dc=\"{}\" xmlns:opds=\"{}\" xmlns:pse=\"{}\">\n",
+            ATOM_NS, DC_NS, OPDS_NS, PSE_NS
+        ));
+        out.push_str(&format!("  <id>{}</id>\n", escape(&self.id)));
+        out.push_str(&format!("  <title>{}</title>\n", escape(&self.title)));
+        out.push_str(&format!("  <updated>{}</updated>\n", escape(&self.updated)));
+        if let Some(author) = &self.author {
+            out.push_str(&format!(
+                "  <author><name>{}</name></author>\n",
+                escape(author)
+            ));
+        }
+        for link in &self.links {
+            link.write(&mut out, "  ");
+        }
+        for entry in &self.entries {
+            entry.write(&mut out);
+        }
+        out.push_str("</feed>\n");
+        out
+    }
+}
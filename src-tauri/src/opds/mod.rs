@@ -1,4 +1,6 @@
 pub mod acquisition;
+pub mod facets;
 pub mod feed_builder;
 pub mod navigation;
+pub mod opds2;
 pub mod search;
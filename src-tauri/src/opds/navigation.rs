@@ -1 +1,137 @@
+use crate::{
+    data::models::{authors::Authors, libraries::Library, publishers::Publishers},
+    opds::feed_builder::{
+        now, Entry, FeedBuilder, Link, ACQUISITION_TYPE, NAVIGATION_TYPE, REL_NEW, REL_SELF,
+        REL_START, REL_SUBSECTION, REL_UP,
+    },
+    opds::search::{DESCRIPTION_PATH, OPENSEARCH_TYPE, REL_SEARCH},
+};
 
+/// Path the catalog is served under, every feed link is relative to the server root
+pub const ROOT: &str = "/opds";
+pub const CATALOG_TITLE: &str = "Stellaron";
+
+pub fn href(path: &str) -> String {
+    format!("{}{}", ROOT, path)
+}
+
+fn search_link() -> Link {
+    Link::new(REL_SEARCH, &href(DESCRIPTION_PATH), OPENSEARCH_TYPE)
+}
+
+/// Creates a feed with the self, start, up and search links every catalog feed carries
+pub fn feed(id: &str, title: &str, path: &str, kind: &str) -> FeedBuilder {
+    FeedBuilder::new(id, title)
+        .author(CATALOG_TITLE)
+        .link(Link::new(REL_SELF, &href(path), kind))
+        .link(Link::new(REL_START, ROOT, NAVIGATION_TYPE))
+        .link(Link::new(REL_UP, ROOT, NAVIGATION_TYPE))
+        .link(search_link())
+}
+
+/// Entry linking to another feed
+fn subsection(id: &str, title: &str, path: &str, kind: &str, content: &str) -> Entry {
+    Entry::new(id, title, &now())
+        .link(Link::new(REL_SUBSECTION, &href(path), kind))
+        .content(content)
+}
+
+pub fn root_feed() -> FeedBuilder {
+    FeedBuilder::new("urn:stellaron:root", CATALOG_TITLE)
+        .author(CATALOG_TITLE)
+        .link(Link::new(REL_SELF, ROOT, NAVIGATION_TYPE))
+        .link(Link::new(REL_START, ROOT, NAVIGATION_TYPE))
+        .link(search_link())
+        .entry(
+            Entry::new("urn:stellaron:recent", "Recently added", &now())
+                .link(Link::new(REL_NEW, &href("/recent"), ACQUISITION_TYPE))
+                .link(Link::new(
+                    REL_SUBSECTION,
+                    &href("/recent"),
+                    ACQUISITION_TYPE,
+                ))
+                .content("The newest books in the library"),
+        )
+        .entry(subsection(
+            "urn:stellaron:books",
+            "All books",
+            "/books",
+            ACQUISITION_TYPE,
+            "Every book, sorted by title",
+        ))
+        .entry(subsection(
+            "urn:stellaron:authors",
+            "Authors",
+            "/authors",
+            NAVIGATION_TYPE,
+            "Books by author",
+        ))
+        .entry(subsection(
+            "urn:stellaron:publishers",
+            "Publishers",
+            "/publishers",
+            NAVIGATION_TYPE,
+            "Books by publisher",
+        ))
+        .entry(subsection(
+            "urn:stellaron:libraries",
+            "Libraries",
+            "/libraries",
+            NAVIGATION_TYPE,
+            "Books by library",
+        ))
+}
+
+pub fn authors_feed(authors: &[Authors]) -> FeedBuilder {
+    feed(
+        "urn:stellaron:authors",
+        "Authors",
+        "/authors",
+        NAVIGATION_TYPE,
+    )
+    .entries(authors.iter().map(|a| {
+        subsection(
+            &format!("urn:stellaron:author:{}", a.author_id),
+            &a.name,
+            &format!("/authors/{}", a.author_id),
+            ACQUISITION_TYPE,
+            &format!("Books by {}", a.name),
+        )
+    }))
+}
+
+pub fn publishers_feed(publishers: &[Publishers]) -> FeedBuilder {
+    feed(
+        "urn:stellaron:publishers",
+        "Publishers",
+        "/publishers",
+        NAVIGATION_TYPE,
+    )
+    .entries(publishers.iter().map(|p| {
+        subsection(
+            &format!("urn:stellaron:publisher:{}", p.publisher_id),
+            &p.name,
+            &format!("/publishers/{}", p.publisher_id),
+            ACQUISITION_TYPE,
+            &format!("Books published by {}", p.name),
+        )
+    }))
+}
+
+pub fn libraries_feed(libraries: &[Library]) -> FeedBuilder {
+    feed(
+        "urn:stellaron:libraries",
+        "Libraries",
+        "/libraries",
+        NAVIGATION_TYPE,
+    )
+    .entries(libraries.iter().map(|l| {
+        subsection(
+            &format!("urn:stellaron:library:{}", l.library_id),
+            &l.name,
+            &format!("/libraries/{}", l.library_id),
+            ACQUISITION_TYPE,
+            &format!("Books in {}", l.name),
+        )
+    }))
+}
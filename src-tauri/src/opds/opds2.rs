@@ -0,0 +1,377 @@
+use serde::Serialize;
+
+use crate::{
+    data::repos::implementors::book_repo::BookOrder,
+    opds::{
+        acquisition::{
+            format_mime_type, identifier_urn, image_mime_type, plain_text, BookDetails, Page,
+            REL_ACQUISITION, REL_FIRST, REL_LAST, REL_NEXT, REL_PREVIOUS,
+        },
+        facets::order_name,
+        feed_builder::{timestamp, REL_SELF, REL_START},
+        navigation::{self, CATALOG_TITLE},
+        search::REL_SEARCH,
+    },
+};
+
+// OPDS 2.0 catalog, the JSON counterpart of the Atom feeds.
+// Feeds are plain serde structs; empty collections are left out because readers
+// tell navigation feeds from publication feeds by which of them is present.
+
+/// Path the JSON catalog is served under
+pub const ROOT: &str = "/opds/v2";
+
+pub const OPDS_JSON_TYPE: &str = "application/opds+json";
+pub const PUBLICATION_TYPE: &str = "application/opds-publication+json";
+const SCHEMA_BOOK: &str = "http://schema.org/Book";
+
+pub fn href(path: &str) -> String {
+    format!("{}{}", ROOT, path)
+}
+
+#[derive(Serialize, Debug, Clone, Default, PartialEq)]
+pub struct Link {
+    pub href: String,
+    #[serde(rename = "type", skip_serializing_if = "Option::is_none")]
+    pub link_type: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rel: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    /// Set when `href` is a URI template
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub templated: Option<bool>,
+}
+
+impl Link {
+    pub fn new(href: &str, link_type: &str) -> Self {
+        Link {
+            href: href.to_string(),
+            link_type: Some(link_type.to_string()),
+            ..Default::default()
+        }
+    }
+
+    pub fn rel(mut self, rel: &str) -> Self {
+        self.rel = Some(rel.to_string());
+        self
+    }
+
+    pub fn title(mut self, title: &str) -> Self {
+        self.title = Some(title.to_string());
+        self
+    }
+
+    pub fn templated(mut self) -> Self {
+        self.templated = Some(true);
+        self
+    }
+}
+
+#[derive(Serialize, Debug, Clone, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Metadata {
+    pub title: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub number_of_items: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub items_per_page: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub current_page: Option<i64>,
+}
+
+impl Metadata {
+    pub fn new(title: &str) -> Self {
+        Metadata {
+            title: title.to_string(),
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Contributor {
+    pub name: String,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Subject {
+    pub name: String,
+}
+
+#[derive(Serialize, Debug, Clone, Default, PartialEq)]
+pub struct PublicationMetadata {
+    #[serde(rename = "@type")]
+    pub schema_type: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub identifier: Option<String>,
+    pub title: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub author: Vec<Contributor>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub publisher: Vec<Contributor>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub published: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub modified: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub subject: Vec<Subject>,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Publication {
+    pub metadata: PublicationMetadata,
+    pub links: Vec<Link>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub images: Vec<Link>,
+}
+
+/// A group of links offering other views of the same feed, the current one has `rel: self`
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Facet {
+    pub metadata: Metadata,
+    pub links: Vec<Link>,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Group {
+    pub metadata: Metadata,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub links: Vec<Link>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub navigation: Vec<Link>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub publications: Vec<Publication>,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Feed {
+    pub metadata: Metadata,
+    pub links: Vec<Link>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub navigation: Vec<Link>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub publications: Vec<Publication>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub facets: Vec<Facet>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub groups: Vec<Group>,
+}
+
+/// Templated link to the search feed
+pub fn search_link() -> Link {
+    Link::new(&href("/search{?query,title,author}"), OPDS_JSON_TYPE)
+        .rel(REL_SEARCH)
+        .templated()
+}
+
+/// Creates a feed with the self, start and search links every catalog feed carries
+pub fn feed(title: &str, path: &str) -> Feed {
+    Feed {
+        metadata: Metadata::new(title),
+        links: vec![
+            Link::new(&href(path), OPDS_JSON_TYPE).rel(REL_SELF),
+            Link::new(ROOT, OPDS_JSON_TYPE).rel(REL_START),
+            search_link(),
+        ],
+        navigation: Vec::new(),
+        publications: Vec::new(),
+        facets: Vec::new(),
+        groups: Vec::new(),
+    }
+}
+
+fn navigation_link(title: &str, path: &str) -> Link {
+    Link::new(&href(path), OPDS_JSON_TYPE).title(title)
+}
+
+pub fn publication(details: &BookDetails) -> Publication {
+    let book = &details.book;
+    let atom_path = format!("/books/{}", book.book_id);
+
+    let identifier = book
+        .isbn
+        .as_deref()
+        .map(|isbn| identifier_urn("isbn", isbn))
+        .or_else(|| {
+            details
+                .identifiers
+                .first()
+                .map(|(scheme, value)| identifier_urn(scheme, value))
+        })
+        .unwrap_or_else(|| format!("urn:stellaron:book:{}", book.book_id));
+
+    let metadata = PublicationMetadata {
+        schema_type: SCHEMA_BOOK.to_string(),
+        identifier: Some(identifier),
+        title: book.title.clone(),
+        author: details
+            .authors
+            .iter()
+            .map(|name| Contributor { name: name.clone() })
+            .collect(),
+        publisher: details
+            .publisher
+            .iter()
+            .map(|name| Contributor { name: name.clone() })
+            .collect(),
+        language: book.language.clone(),
+        published: book.published_date.clone(),
+        modified: Some(timestamp(book.added_at.as_deref())),
+        description: book
+            .description
+            .as_deref()
+            .map(plain_text)
+            .filter(|d| !d.is_empty()),
+        subject: details
+            .tags
+            .iter()
+            .map(|name| Subject { name: name.clone() })
+            .collect(),
+    };
+
+    let mut links =
+        vec![Link::new(&href(&format!("/books/{}", book.book_id)), PUBLICATION_TYPE).rel(REL_SELF)];
+    for format in &details.formats {
+        links.push(
+            Link::new(
+                &navigation::href(&format!("{}/download/{}", atom_path, format)),
+                format_mime_type(format),
+            )
+            .rel(REL_ACQUISITION)
+            .title(&format.to_uppercase()),
+        );
+    }
+
+    let images = match &book.cover_image_path {
+        Some(cover) => vec![Link::new(
+            &navigation::href(&format!("{}/cover", atom_path)),
+            image_mime_type(cover),
+        )],
+        None => Vec::new(),
+    };
+
+    Publication {
+        metadata,
+        links,
+        images,
+    }
+}
+
+fn page_href(path: &str, number: i64) -> String {
+    let separator = if path.contains('?') { '&' } else { '?' };
+    format!("{}{}page={}", href(path), separator, number)
+}
+
+/// Feed listing one page of publications, `path` is relative to the JSON catalog root
+pub fn publications_feed(title: &str, path: &str, books: &[BookDetails], page: Page) -> Feed {
+    let mut feed = feed(title, path);
+    feed.metadata.number_of_items = Some(page.total);
+    feed.metadata.items_per_page = Some(page.size);
+    feed.metadata.current_page = Some(page.number);
+
+    let last = page.last();
+    feed.links
+        .push(Link::new(&page_href(path, 1), OPDS_JSON_TYPE).rel(REL_FIRST));
+    feed.links
+        .push(Link::new(&page_href(path, last), OPDS_JSON_TYPE).rel(REL_LAST));
+    if page.number > 1 {
+        feed.links.push(
+            Link::new(
+                &page_href(path, (page.number - 1).min(last)),
+                OPDS_JSON_TYPE,
+            )
+            .rel(REL_PREVIOUS),
+        );
+    }
+    if page.number < last {
+        feed.links
+            .push(Link::new(&page_href(path, page.number + 1), OPDS_JSON_TYPE).rel(REL_NEXT));
+    }
+
+    feed.publications = books.iter().map(publication).collect();
+    feed
+}
+
+/// Facet switching the order of a book listing, `path` has no query string
+pub fn order_facet(path: &str, active: BookOrder) -> Facet {
+    let links = [
+        (BookOrder::Title, "Title"),
+        (BookOrder::Author, "Author"),
+        (BookOrder::Newest, "Recently added"),
+        (BookOrder::Published, "Publication date"),
+    ]
+    .into_iter()
+    .map(|(order, title)| {
+        let link = Link::new(
+            &href(&format!("{}?order={}", path, order_name(order))),
+            OPDS_JSON_TYPE,
+        )
+        .title(title);
+        if order == active {
+            link.rel(REL_SELF)
+        } else {
+            link
+        }
+    })
+    .collect();
+
+    Facet {
+        metadata: Metadata::new("Sort by"),
+        links,
+    }
+}
+
+/// Listing of books with the order facet, the order is kept in every page link
+pub fn sorted_feed(
+    title: &str,
+    path: &str,
+    order: BookOrder,
+    books: &[BookDetails],
+    page: Page,
+) -> Feed {
+    let mut feed = publications_feed(
+        title,
+        &format!("{}?order={}", path, order_name(order)),
+        books,
+        page,
+    );
+    feed.facets.push(order_facet(path, order));
+    feed
+}
+
+/// The catalog entry point, with the newest books shown as a group
+pub fn root_feed(recent: &[BookDetails]) -> Feed {
+    let mut feed = feed(CATALOG_TITLE, "");
+    feed.navigation = vec![
+        navigation_link("Recently added", "/recent"),
+        navigation_link("All books", "/books"),
+        navigation_link("Authors", "/authors"),
+        navigation_link("Publishers", "/publishers"),
+        navigation_link("Libraries", "/libraries"),
+    ];
+    if !recent.is_empty() {
+        feed.groups.push(Group {
+            metadata: Metadata::new("Recently added"),
+            links: vec![Link::new(&href("/recent"), OPDS_JSON_TYPE).rel(REL_SELF)],
+            navigation: Vec::new(),
+            publications: recent.iter().map(publication).collect(),
+        });
+    }
+    feed
+}
+
+/// Feed of links to other feeds, given as titles and paths
+pub fn navigation_feed(title: &str, path: &str, items: &[(String, String)]) -> Feed {
+    let mut feed = feed(title, path);
+    feed.metadata.number_of_items = Some(items.len() as i64);
+    feed.navigation = items
+        .iter()
+        .map(|(title, path)| navigation_link(title, path))
+        .collect();
+    feed
+}
@@ -1 +1,139 @@
+use crate::opds::{
+    acquisition::{books_feed, BookDetails, Page},
+    feed_builder::{escape, ACQUISITION_TYPE, ATOM_NS},
+    navigation::{href, CATALOG_TITLE},
+};
 
+pub const OPENSEARCH_NS: &str = "http://a9.com/-/spec/opensearch/1.1/";
+/// Content type of the OpenSearch description document
+pub const OPENSEARCH_TYPE: &str = "application/opensearchdescription+xml";
+pub const REL_SEARCH: &str = "search";
+
+/// Path of the description document, relative to the catalog root
+pub const DESCRIPTION_PATH: &str = "/opensearch.xml";
+pub const SEARCH_PATH: &str = "/search";
+
+/// A catalog search, every part that is given has to match
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SearchQuery {
+    /// Free text matched against titles, authors and ISBNs
+    pub terms: Option<String>,
+    pub author: Option<String>,
+    pub title: Option<String>,
+}
+
+impl SearchQuery {
+    /// Readers fill unused template parameters with empty strings, those are ignored
+    pub fn new(terms: Option<String>, author: Option<String>, title: Option<String>) -> Self {
+        let clean = |value: Option<String>| {
+            value
+                .map(|v| v.trim().to_string())
+                .filter(|v| !v.is_empty())
+        };
+        SearchQuery {
+            terms: clean(terms),
+            author: clean(author),
+            title: clean(title),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.terms.is_none() && self.author.is_none() && self.title.is_none()
+    }
+
+    /// Search path with the query string, relative to the catalog root
+    pub fn path(&self) -> String {
+        self.path_with("q")
+    }
+
+    /// Search path sending the search terms as `terms_param`
+    pub fn path_with(&self, terms_param: &str) -> String {
+        let params: Vec<String> = [
+            (terms_param, &self.terms),
+            ("author", &self.author),
+            ("title", &self.title),
+        ]
+        .iter()
+        .filter_map(|(name, value)| {
+            value
+                .as_ref()
+                .map(|v| format!("{}={}", name, percent_encode(v)))
+        })
+        .collect();
+
+        if params.is_empty() {
+            SEARCH_PATH.to_string()
+        } else {
+            format!("{}?{}", SEARCH_PATH, params.join("&"))
+        }
+    }
+
+    fn describe(&self) -> String {
+        let mut parts = Vec::new();
+        if let Some(terms) = &self.terms {
+            parts.push(format!("\"{}\"", terms));
+        }
+        if let Some(title) = &self.title {
+            parts.push(format!("title \"{}\"", title));
+        }
+        if let Some(author) = &self.author {
+            parts.push(format!("author \"{}\"", author));
+        }
+        parts.join(", ")
+    }
+}
+
+/// Encodes a query string value, keeping only unreserved characters as they are
+pub fn percent_encode(value: &str) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                encoded.push(byte as char)
+            }
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+/// The OpenSearch description readers fetch to learn how to search the catalog
+pub fn description() -> String {
+    let template = format!(
+        "{}?q={{searchTerms}}&author={{atom:author?}}&title={{atom:title?}}",
+        href(SEARCH_PATH)
+    );
+
+    let mut out = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    out.push_str(&format!(
+        "<OpenSearchDescription xmlns=\"{}\" xmlns:atom=\"{}\">\n",
+        OPENSEARCH_NS, ATOM_NS
+    ));
+    out.push_str(&format!(
+        "  <ShortName>{}</ShortName>\n",
+        escape(CATALOG_TITLE)
+    ));
+    out.push_str(&format!(
+        "  <Description>Search the {} catalog by title, author or ISBN</Description>\n",
+        escape(CATALOG_TITLE)
+    ));
+    out.push_str("  <InputEncoding>UTF-8</InputEncoding>\n");
+    out.push_str("  <OutputEncoding>UTF-8</OutputEncoding>\n");
+    out.push_str(&format!(
+        "  <Url type=\"{}\" template=\"{}\"/>\n",
+        escape(ACQUISITION_TYPE),
+        escape(&template)
+    ));
+    out.push_str("</OpenSearchDescription>\n");
+    out
+}
+
+/// Feed listing one page of search results
+pub fn search_feed(query: &SearchQuery, books: &[BookDetails], page: Page) -> String {
+    let title = if query.is_empty() {
+        "Search".to_string()
+    } else {
+        format!("Search results for {}", query.describe())
+    };
+    books_feed("urn:stellaron:search", &title, &query.path(), books, page).build()
+}
@@ -0,0 +1,143 @@
+// ISBN parsing and normalization.
+// Every ISBN is stored in one canonical form: the 13 digit ISBN without hyphens or prefixes.
+// ISBN-10s are converted to their `978` ISBN-13 equivalent.
+
+/// Prefixes commonly found in front of an ISBN in EPUB identifiers and user input
+const ISBN_PREFIXES: [&str; 5] = ["urn:isbn:", "isbn-13", "isbn-10", "isbn13", "isbn10"];
+
+/// ONIX codelist 5 codes used by legacy `identifier-type` refinements
+const ONIX_ISBN_CODES: [&str; 2] = ["02", "15"];
+
+/// Removes prefixes, hyphens and whitespace, leaving only the digits and a possible `X` check digit
+pub fn strip(input: &str) -> String {
+    let mut value = input.trim();
+    let lower = value.to_ascii_lowercase();
+
+    if let Some(prefix) = ISBN_PREFIXES.iter().find(|p| lower.starts_with(*p)) {
+        value = &value[prefix.len()..];
+    } else if lower.starts_with("isbn") {
+        value = &value[4..];
+    }
+
+    value
+        .trim_start_matches([':', ' '])
+        .chars()
+        .filter(|c| c.is_ascii_digit() || *c == 'x' || *c == 'X')
+        .map(|c| c.to_ascii_uppercase())
+        .collect()
+}
+
+/// Checks the length and check digit of a stripped ISBN-10
+pub fn is_valid_isbn10(isbn: &str) -> bool {
+    let bytes = isbn.as_bytes();
+    if bytes.len() != 10 || !bytes[..9].iter().all(u8::is_ascii_digit) {
+        return false;
+    }
+
+    let check = match bytes[9] {
+        b'X' => 10,
+        c if c.is_ascii_digit() => (c - b'0') as u32,
+        _ => return false,
+    };
+    let sum: u32 = bytes[..9]
+        .iter()
+        .enumerate()
+        .map(|(i, c)| (10 - i as u32) * (c - b'0') as u32)
+        .sum();
+
+    (sum + check).is_multiple_of(11)
+}
+
+/// Checks the length, prefix and check digit of a stripped ISBN-13
+pub fn is_valid_isbn13(isbn: &str) -> bool {
+    let bytes = isbn.as_bytes();
+    if bytes.len() != 13 || !bytes.iter().all(u8::is_ascii_digit) {
+        return false;
+    }
+    if !(isbn.starts_with("978") || isbn.starts_with("979")) {
+        return false;
+    }
+
+    isbn13_check_digit(&isbn[..12]) == Some(bytes[12] - b'0')
+}
+
+fn isbn13_check_digit(first_twelve: &str) -> Option<u8> {
+    let sum = first_twelve
+        .chars()
+        .enumerate()
+        .map(|(i, c)| c.to_digit(10).map(|d| if i % 2 == 0 { d } else { d * 3 }))
+        .sum::<Option<u32>>()?;
+
+    Some(((10 - sum % 10) % 10) as u8)
+}
+
+/// Converts a valid ISBN-10 to its ISBN-13 form
+pub fn isbn10_to_isbn13(isbn10: &str) -> Option<String> {
+    let stripped = strip(isbn10);
+    if !is_valid_isbn10(&stripped) {
+        return None;
+    }
+
+    let first_twelve = format!("978{}", &stripped[..9]);
+    let check = isbn13_check_digit(&first_twelve)?;
+    Some(format!("{}{}", first_twelve, check))
+}
+
+/// Converts an ISBN-13 back to ISBN-10, only possible for the `978` prefix
+pub fn isbn13_to_isbn10(isbn13: &str) -> Option<String> {
+    let stripped = strip(isbn13);
+    if !is_valid_isbn13(&stripped) || !stripped.starts_with("978") {
+        return None;
+    }
+
+    let body = &stripped[3..12];
+    let sum: u32 = body
+        .chars()
+        .enumerate()
+        .filter_map(|(i, c)| c.to_digit(10).map(|d| (10 - i as u32) * d))
+        .sum();
+    let check = match (11 - sum % 11) % 11 {
+        10 => 'X',
+        d => char::from_digit(d, 10)?,
+    };
+    Some(format!("{}{}", body, check))
+}
+
+/// Returns the canonical ISBN-13 for any ISBN-10 or ISBN-13 input, or `None` if it isn't a valid ISBN
+pub fn normalize(input: &str) -> Option<String> {
+    let stripped = strip(input);
+
+    match stripped.len() {
+        10 => isbn10_to_isbn13(&stripped),
+        13 if is_valid_isbn13(&stripped) => Some(stripped),
+        _ => None,
+    }
+}
+
+/// Returns true if an identifier scheme (e.g. `opf:scheme="ISBN"`) denotes an ISBN
+pub fn is_isbn_scheme(scheme: &str) -> bool {
+    let scheme = scheme.trim();
+    scheme.eq_ignore_ascii_case("isbn")
+        || scheme.eq_ignore_ascii_case("isbn-10")
+        || scheme.eq_ignore_ascii_case("isbn-13")
+        || ONIX_ISBN_CODES.contains(&scheme)
+}
+
+/// Pulls an ISBN out of an identifier value and its optional scheme.
+/// Without an ISBN scheme only bare numbers or ISBN prefixed values are considered,
+/// so other identifiers such as `urn:uuid:` are never mistaken for an ISBN.
+pub fn from_identifier(value: &str, scheme: Option<&str>) -> Option<String> {
+    if scheme.is_some_and(is_isbn_scheme) {
+        return normalize(value);
+    }
+
+    let lower = value.trim().to_ascii_lowercase();
+    if lower.starts_with("urn:") && !lower.starts_with("urn:isbn:") {
+        return None;
+    }
+    if lower.chars().any(|c| c.is_ascii_alphabetic() && c != 'x') && !lower.contains("isbn") {
+        return None;
+    }
+
+    normalize(value)
+}
@@ -1 +1,2 @@
-
+pub mod isbn;
+pub mod path_template;
@@ -0,0 +1,194 @@
+use regex::Regex;
+use serde::Serialize;
+use std::path::{Component, Path};
+
+// Path templates describe how a library lays out its files, e.g.
+// `{author}/{series}/{series_index} - {title}.{ext}`.
+// A template is matched against the end of a path relative to the library root,
+// so extra leading folders (e.g. `Fiction/`) don't prevent a match.
+
+/// Templates tried in order when none are configured
+pub const DEFAULT_TEMPLATES: [&str; 4] = [
+    "{author}/{series}/{series_index} - {title}.{ext}",
+    "{author}/{title}.{ext}",
+    "{author} - {title}.{ext}",
+    "{title}.{ext}",
+];
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum TemplateField {
+    Author,
+    Title,
+    Series,
+    SeriesIndex,
+    Year,
+    Publisher,
+    Isbn,
+    Ext,
+}
+
+impl TemplateField {
+    fn from_name(name: &str) -> Option<TemplateField> {
+        match name {
+            "author" => Some(TemplateField::Author),
+            "title" => Some(TemplateField::Title),
+            "series" => Some(TemplateField::Series),
+            "series_index" => Some(TemplateField::SeriesIndex),
+            "year" => Some(TemplateField::Year),
+            "publisher" => Some(TemplateField::Publisher),
+            "isbn" => Some(TemplateField::Isbn),
+            "ext" => Some(TemplateField::Ext),
+            _ => None,
+        }
+    }
+
+    fn pattern(&self) -> &'static str {
+        match self {
+            TemplateField::SeriesIndex => r"(\d+(?:\.\d+)?)",
+            TemplateField::Year => r"(\d{4})",
+            TemplateField::Isbn => r"([0-9Xx-]{10,17})",
+            TemplateField::Ext => r"([^/.]+)",
+            _ => r"([^/]+?)",
+        }
+    }
+}
+
+/// Metadata read from a file path, fields the template doesn't contain are left empty
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct InferredMetadata {
+    pub title: Option<String>,
+    pub authors: Vec<String>,
+    pub series: Option<String>,
+    pub series_index: Option<f32>,
+    pub published_date: Option<String>,
+    pub publisher: Option<String>,
+    pub isbn: Option<String>,
+}
+
+#[derive(Debug)]
+pub struct PathTemplate {
+    source: String,
+    regex: Regex,
+    fields: Vec<TemplateField>,
+}
+
+impl PathTemplate {
+    /// Compiles a template, failing on unknown or unclosed placeholders
+    pub fn parse(template: &str) -> Result<PathTemplate, String> {
+        let template = template.trim().trim_start_matches('/');
+        if template.is_empty() {
+            return Err("Template is empty".to_string());
+        }
+
+        let mut pattern = String::from("(?:^|/)");
+        let mut fields = Vec::new();
+        let mut rest = template;
+
+        while let Some(start) = rest.find('{') {
+            pattern.push_str(&regex::escape(&rest[..start]));
+            let end = rest[start..]
+                .find('}')
+                .map(|e| start + e)
+                .ok_or_else(|| format!("Unclosed placeholder in template '{}'", template))?;
+            let name = &rest[start + 1..end];
+            let field = TemplateField::from_name(name)
+                .ok_or_else(|| format!("Unknown placeholder '{{{}}}' in template", name))?;
+            pattern.push_str(field.pattern());
+            fields.push(field);
+            rest = &rest[end + 1..];
+        }
+        pattern.push_str(&regex::escape(rest));
+        pattern.push('$');
+
+        if !fields.contains(&TemplateField::Title) {
+            return Err(format!("Template '{}' has no {{title}}", template));
+        }
+
+        let regex = Regex::new(&pattern).map_err(|e| e.to_string())?;
+        Ok(PathTemplate {
+            source: template.to_string(),
+            regex,
+            fields,
+        })
+    }
+
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// Reads metadata from a path relative to the library root, `None` if the template doesn't match
+    pub fn infer(&self, relative_path: &Path) -> Option<InferredMetadata> {
+        let path = to_template_path(relative_path);
+        let captures = self.regex.captures(&path)?;
+        let mut inferred = InferredMetadata::default();
+
+        for (i, field) in self.fields.iter().enumerate() {
+            let Some(value) = captures.get(i + 1).map(|m| clean(m.as_str())) else {
+                continue;
+            };
+            if value.is_empty() {
+                continue;
+            }
+            match field {
+                TemplateField::Author => {
+                    for author in value.split([';', '&']).map(str::trim) {
+                        if !author.is_empty() && !inferred.authors.iter().any(|a| a == author) {
+                            inferred.authors.push(author.to_string());
+                        }
+                    }
+                }
+                TemplateField::Title => inferred.title = Some(value),
+                TemplateField::Series => inferred.series = Some(value),
+                TemplateField::SeriesIndex => inferred.series_index = value.parse().ok(),
+                TemplateField::Year => inferred.published_date = Some(value),
+                TemplateField::Publisher => inferred.publisher = Some(value),
+                TemplateField::Isbn => inferred.isbn = Some(value),
+                TemplateField::Ext => {}
+            }
+        }
+
+        Some(inferred)
+    }
+}
+
+/// Compiles every template, returning the errors of the ones that failed
+pub fn parse_templates<S: AsRef<str>>(templates: &[S]) -> (Vec<PathTemplate>, Vec<String>) {
+    let mut compiled = Vec::new();
+    let mut errors = Vec::new();
+    for template in templates {
+        match PathTemplate::parse(template.as_ref()) {
+            Ok(t) => compiled.push(t),
+            Err(e) => errors.push(e),
+        }
+    }
+    (compiled, errors)
+}
+
+/// Returns the metadata of the first template matching the path
+pub fn infer_from_path(
+    templates: &[PathTemplate],
+    relative_path: &Path,
+) -> Option<InferredMetadata> {
+    templates.iter().find_map(|t| t.infer(relative_path))
+}
+
+/// Joins the path components with `/` regardless of platform
+fn to_template_path(path: &Path) -> String {
+    path.components()
+        .filter_map(|c| match c {
+            Component::Normal(s) => Some(s.to_string_lossy()),
+            _ => None,
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Underscores are a common stand-in for spaces in file names
+fn clean(value: &str) -> String {
+    value
+        .replace('_', " ")
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
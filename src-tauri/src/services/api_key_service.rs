@@ -0,0 +1,158 @@
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use chrono::Duration;
+
+use crate::{
+    data::{
+        models::{
+            api_keys::{ApiKeys, ApiScope, NewApiKey},
+            users::Role,
+        },
+        repos::{
+            implementors::{api_key_repo::ApiKeyRepo, user_repo::UserRepo},
+            traits::repository::Repository,
+        },
+    },
+    services::session_service::{db_time, db_time_in_days, hash_token},
+};
+
+pub type ApiKeyError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Every key starts with this, which tells them apart from JWTs and passwords
+pub const API_KEY_PREFIX: &str = "stk_";
+
+/// Characters of a key kept in clear, the prefix and 8 random ones
+const DISPLAY_PREFIX_LEN: usize = API_KEY_PREFIX.len() + 8;
+
+/// `last_used_at` is only written again after this long
+const LAST_USED_RESOLUTION_SECS: i64 = 60;
+
+/// A key that was just created, the only time its full value is available
+#[derive(Debug, Clone, PartialEq)]
+pub struct CreatedApiKey {
+    pub key: String,
+    pub api_key: ApiKeys,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ApiKeyCreation {
+    Created(Box<CreatedApiKey>),
+    MissingName,
+    MissingScopes,
+    InvalidExpiry,
+    /// Only admins can hand out the admin scope
+    AdminScopeDenied,
+}
+
+pub fn is_api_key(value: &str) -> bool {
+    value.starts_with(API_KEY_PREFIX)
+}
+
+fn generate_key() -> String {
+    let mut bytes = [0u8; 32];
+    OsRng.fill_bytes(&mut bytes);
+    let random: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+    format!("{}{}", API_KEY_PREFIX, random)
+}
+
+pub struct ApiKeyService;
+
+impl ApiKeyService {
+    pub async fn new() -> Self {
+        ApiKeyService
+    }
+
+    /// Creates a key for the user, `expires_in_days` of `None` never expires
+    pub async fn create(
+        &self,
+        user_id: i32,
+        name: &str,
+        scopes: &[ApiScope],
+        expires_in_days: Option<i64>,
+    ) -> Result<ApiKeyCreation, ApiKeyError> {
+        let name = name.trim();
+        if name.is_empty() {
+            return Ok(ApiKeyCreation::MissingName);
+        }
+        if scopes.is_empty() {
+            return Ok(ApiKeyCreation::MissingScopes);
+        }
+        let expires_at = match expires_in_days.map(db_time_in_days) {
+            Some(None) => return Ok(ApiKeyCreation::InvalidExpiry),
+            Some(at) => at,
+            None => None,
+        };
+        let Some(user) = UserRepo::new().await.get_by_id(user_id).await? else {
+            return Err(format!("User {} not found", user_id).into());
+        };
+        if scopes.contains(&ApiScope::Admin) && !user.role().allows(Role::Admin) {
+            return Ok(ApiKeyCreation::AdminScopeDenied);
+        }
+
+        let key = generate_key();
+        let repo = ApiKeyRepo::new().await;
+        let id = repo
+            .add_returning_id(NewApiKey {
+                user_id,
+                name,
+                prefix: &key[..DISPLAY_PREFIX_LEN],
+                key_hash: &hash_token(&key),
+                scopes: &ApiScope::join(scopes),
+                expires_at: expires_at.as_deref(),
+            })
+            .await?;
+        let api_key = repo
+            .get_by_id(id)
+            .await?
+            .ok_or("Created API key not found")?;
+
+        Ok(ApiKeyCreation::Created(Box::new(CreatedApiKey {
+            key,
+            api_key,
+        })))
+    }
+
+    /// Keys of the user that weren't revoked, newest first
+    pub async fn list(&self, user_id: i32) -> Result<Vec<ApiKeys>, ApiKeyError> {
+        Ok(ApiKeyRepo::new()
+            .await
+            .get_by_user(user_id)
+            .await?
+            .unwrap_or_default())
+    }
+
+    /// Revokes one of the user's keys, `false` if the user has no such key
+    pub async fn revoke(&self, user_id: i32, api_key_id: i32) -> Result<bool, ApiKeyError> {
+        Ok(ApiKeyRepo::new()
+            .await
+            .revoke(api_key_id, user_id, &db_time(Duration::zero()))
+            .await?)
+    }
+
+    /// Looks up a presented key, `None` if it is unknown, revoked or expired
+    pub async fn authenticate(&self, key: &str) -> Result<Option<ApiKeys>, ApiKeyError> {
+        if !is_api_key(key) {
+            return Ok(None);
+        }
+        let repo = ApiKeyRepo::new().await;
+        let Some(api_key) = repo.get_by_key_hash(&hash_token(key)).await? else {
+            return Ok(None);
+        };
+        let now = db_time(Duration::zero());
+        if api_key.revoked_at.is_some()
+            || api_key
+                .expires_at
+                .as_deref()
+                .is_some_and(|at| at <= now.as_str())
+        {
+            return Ok(None);
+        }
+
+        repo.touch(
+            api_key.api_key_id,
+            &now,
+            &db_time(Duration::seconds(-LAST_USED_RESOLUTION_SECS)),
+        )
+        .await?;
+        Ok(Some(api_key))
+    }
+}
@@ -0,0 +1,185 @@
+use std::{
+    env,
+    net::IpAddr,
+    sync::Mutex,
+    time::{Duration as StdDuration, Instant},
+};
+
+use chrono::Duration;
+use dotenvy::dotenv;
+use once_cell::sync::Lazy;
+
+use crate::{
+    data::{
+        models::audit_events::{AuditAction, AuditEvents, NewAuditEvent},
+        repos::{
+            implementors::{
+                audit_event_repo::{AuditEventRepo, AuditFilter},
+                user_repo::UserRepo,
+            },
+            traits::repository::Repository,
+        },
+    },
+    services::session_service::db_time,
+};
+
+pub type AuditError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Days events are kept, configurable through `AUDIT_RETENTION_DAYS`. `0` keeps them forever.
+static RETENTION_DAYS: Lazy<i64> = Lazy::new(|| {
+    dotenv().ok();
+
+    env::var("AUDIT_RETENTION_DAYS")
+        .ok()
+        .and_then(|v| v.parse::<i64>().ok())
+        .filter(|v| *v >= 0)
+        .unwrap_or(365)
+});
+
+/// Old events are removed while recording new ones, at most this often
+const PURGE_INTERVAL: StdDuration = StdDuration::from_secs(60 * 60);
+
+static LAST_PURGE: Lazy<Mutex<Option<Instant>>> = Lazy::new(|| Mutex::new(None));
+
+/// Longest user agent that is stored, clients can send anything
+const MAX_USER_AGENT: usize = 512;
+
+/// Where a request came from
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RequestOrigin {
+    pub ip: Option<IpAddr>,
+    pub user_agent: Option<String>,
+}
+
+/// Who did something to what. The username is looked up when only the id is known.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AuditEntry {
+    pub user_id: Option<i32>,
+    pub username: Option<String>,
+    pub target: Option<(&'static str, i32)>,
+    pub details: Option<String>,
+}
+
+impl AuditEntry {
+    pub fn by(user_id: i32) -> Self {
+        AuditEntry {
+            user_id: Some(user_id),
+            ..Default::default()
+        }
+    }
+
+    /// An attempt under a name that may not belong to any account
+    pub fn attempt(username: &str) -> Self {
+        AuditEntry {
+            username: Some(username.to_string()),
+            ..Default::default()
+        }
+    }
+
+    pub fn target(mut self, kind: &'static str, id: i32) -> Self {
+        self.target = Some((kind, id));
+        self
+    }
+
+    pub fn details(mut self, details: impl Into<String>) -> Self {
+        self.details = Some(details.into());
+        self
+    }
+}
+
+/// Keeps the security audit log, recording errors are only logged
+pub struct AuditService;
+
+impl AuditService {
+    pub async fn new() -> Self {
+        AuditService
+    }
+
+    /// Days events are kept, `None` if they are kept forever
+    pub fn retention_days() -> Option<i64> {
+        Some(*RETENTION_DAYS).filter(|days| *days > 0)
+    }
+
+    pub async fn record(&self, origin: &RequestOrigin, action: AuditAction, entry: AuditEntry) {
+        if let Err(e) = self.try_record(origin, action, entry).await {
+            eprintln!("Failed to record audit event {}: {}", action.as_str(), e);
+        }
+    }
+
+    async fn try_record(
+        &self,
+        origin: &RequestOrigin,
+        action: AuditAction,
+        entry: AuditEntry,
+    ) -> Result<(), AuditError> {
+        let username = match (&entry.username, entry.user_id) {
+            (Some(name), _) => Some(name.clone()),
+            (None, Some(id)) => UserRepo::new()
+                .await
+                .get_by_id(id)
+                .await?
+                .map(|user| user.username),
+            (None, None) => None,
+        };
+        let ip = origin.ip.map(|ip| ip.to_string());
+        let user_agent = origin
+            .user_agent
+            .as_deref()
+            .map(|ua| match ua.char_indices().nth(MAX_USER_AGENT) {
+                Some((end, _)) => &ua[..end],
+                None => ua,
+            });
+
+        AuditEventRepo::new()
+            .await
+            .add(NewAuditEvent {
+                action: action.as_str(),
+                user_id: entry.user_id,
+                username: username.as_deref(),
+                target_type: entry.target.map(|(kind, _)| kind),
+                target_id: entry.target.map(|(_, id)| id),
+                ip: ip.as_deref(),
+                user_agent,
+                details: entry.details.as_deref(),
+            })
+            .await?;
+
+        let due = {
+            let mut last = LAST_PURGE.lock().unwrap();
+            let due = last.is_none_or(|at| at.elapsed() >= PURGE_INTERVAL);
+            if due {
+                *last = Some(Instant::now());
+            }
+            due
+        };
+        if due {
+            self.purge_expired().await?;
+        }
+        Ok(())
+    }
+
+    /// A page of matching events, newest first, with the number of all matches
+    pub async fn events(
+        &self,
+        filter: &AuditFilter,
+        page: i64,
+        per_page: i64,
+    ) -> Result<(Vec<AuditEvents>, i64), AuditError> {
+        let offset = (page.max(1) - 1) * per_page;
+        Ok(AuditEventRepo::new()
+            .await
+            .get_page(filter, offset, per_page)
+            .await?)
+    }
+
+    /// Removes events older than the retention period, returning how many there were
+    pub async fn purge_expired(&self) -> Result<usize, AuditError> {
+        let Some(days) = Self::retention_days() else {
+            return Ok(0);
+        };
+        Ok(AuditEventRepo::new()
+            .await
+            .delete_before(&db_time(-Duration::days(days)))
+            .await?)
+    }
+}
@@ -1,6 +1,85 @@
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
 use argon2::{Argon2, PasswordHash, PasswordHasher, PasswordVerifier};
 
-use argon2::password_hash::{self, rand_core::OsRng, SaltString};
+use argon2::password_hash::{
+    self,
+    rand_core::{OsRng, RngCore},
+    SaltString,
+};
+use once_cell::sync::Lazy;
+use sha2::{Digest, Sha256};
+
+use crate::{
+    data::{models::users::Users, repos::implementors::user_repo::UserRepo},
+    services::{
+        throttle_service::{LoginThrottle, Throttled},
+        two_factor_service::TwoFactorService,
+    },
+};
+
+pub type AuthServiceError = Box<dyn std::error::Error + Send + Sync>;
+
+/// How long a verified Basic credential is accepted without running argon2 again
+const CREDENTIAL_TTL: Duration = Duration::from_secs(10 * 60);
+
+/// A successful Basic credential check, the password only as a keyed digest
+struct VerifiedCredential {
+    digest: [u8; 32],
+    /// Hash the password was checked against, a changed password invalidates the entry
+    password_hash: String,
+    user_id: i32,
+    verified_at: Instant,
+}
+
+static CACHE_KEY: Lazy<[u8; 32]> = Lazy::new(|| {
+    let mut key = [0u8; 32];
+    OsRng.fill_bytes(&mut key);
+    key
+});
+
+static VERIFIED: Lazy<Mutex<HashMap<String, VerifiedCredential>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+fn credential_digest(username: &str, password: &str) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update(*CACHE_KEY);
+    hasher.update(username.as_bytes());
+    hasher.update([0]);
+    hasher.update(password.as_bytes());
+    hasher.finalize().into()
+}
+
+/// Checked for unknown usernames so they take as long to reject as known ones
+static DUMMY_HASH: Lazy<String> = Lazy::new(|| {
+    let mut password = [0u8; 16];
+    OsRng.fill_bytes(&mut password);
+    AuthenticationService::new()
+        .hash_password(&format!("{:?}", password))
+        .expect("Failed to hash the dummy password")
+});
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LoginOutcome {
+    Success(i32),
+    InvalidCredentials,
+    /// Too many failures for the account or address, the password wasn't checked
+    Throttled(Throttled),
+    /// The password was right, but an admin has disabled the account
+    Disabled,
+    /// The password was right, but a two-factor code is still needed
+    TwoFactorRequired(i32),
+}
+
+enum Attempt {
+    Valid(Users),
+    Rejected(LoginOutcome),
+}
 
 pub struct AuthenticationService;
 
@@ -33,9 +112,143 @@ impl AuthenticationService {
         }
     }
 
+    /// [`Self::hash_password`] on a blocking thread, argon2 would stall the async workers
+    pub async fn hash_password_async(&self, password: &str) -> Result<String, AuthServiceError> {
+        let password = password.to_string();
+        let hash = tokio::task::spawn_blocking(move || {
+            AuthenticationService::new().hash_password(&password)
+        })
+        .await?
+        .map_err(|e| e.to_string())?;
+        Ok(hash)
+    }
+
+    /// [`Self::verify_password`] on a blocking thread
+    pub async fn verify_password_async(
+        &self,
+        password: &str,
+        hash: &str,
+    ) -> Result<bool, AuthServiceError> {
+        let password = password.to_string();
+        let hash = hash.to_string();
+        let valid = tokio::task::spawn_blocking(move || {
+            AuthenticationService::new().verify_password(&password, &hash)
+        })
+        .await?
+        .map_err(|e| e.to_string())?;
+        Ok(valid)
+    }
+
+    /// Checks a password against the throttle, the account and its hash, recording the result
+    async fn attempt(
+        &self,
+        username: &str,
+        password: &str,
+        ip: Option<IpAddr>,
+    ) -> Result<Attempt, AuthServiceError> {
+        let throttle = LoginThrottle::global();
+        if let Err(throttled) = throttle.check(username, ip) {
+            return Ok(Attempt::Rejected(LoginOutcome::Throttled(throttled)));
+        }
+
+        let user = UserRepo::new().await.get_by_username(username).await?;
+        let hash = match &user {
+            Some(user) => user.password_hash.clone(),
+            None => DUMMY_HASH.clone(),
+        };
+        let valid = self.verify_password_async(password, &hash).await?;
+
+        match user {
+            Some(user) if valid => {
+                // Failed codes count too, the password alone mustn't reset them
+                if !user.is_disabled()
+                    && TwoFactorService::new()
+                        .await
+                        .is_enabled(user.user_id)
+                        .await?
+                {
+                    return Ok(Attempt::Rejected(LoginOutcome::TwoFactorRequired(
+                        user.user_id,
+                    )));
+                }
+                throttle.record_success(username);
+                if user.is_disabled() {
+                    return Ok(Attempt::Rejected(LoginOutcome::Disabled));
+                }
+                Ok(Attempt::Valid(user))
+            }
+            _ => {
+                throttle.record_failure(username, ip);
+                Ok(Attempt::Rejected(LoginOutcome::InvalidCredentials))
+            }
+        }
+    }
+
+    /// Signs a user in with a username and password, see [`LoginThrottle`] for the limits
+    pub async fn login(
+        &self,
+        username: &str,
+        password: &str,
+        ip: Option<IpAddr>,
+    ) -> Result<LoginOutcome, AuthServiceError> {
+        match self.attempt(username, password, ip).await? {
+            Attempt::Valid(user) => Ok(LoginOutcome::Success(user.user_id)),
+            Attempt::Rejected(outcome) => Ok(outcome),
+        }
+    }
+
     pub fn hash_and_verify(&self, password: &str) -> Result<String, password_hash::Error> {
         let hash = self.hash_password(password)?;
         self.verify_password(password, &hash)?;
         Ok(hash)
     }
+
+    /// Checks Basic credentials like [`Self::login`], caching successes for
+    /// [`CREDENTIAL_TTL`]
+    pub async fn verify_credentials(
+        &self,
+        username: &str,
+        password: &str,
+        ip: Option<IpAddr>,
+    ) -> Result<LoginOutcome, AuthServiceError> {
+        if let Err(throttled) = LoginThrottle::global().check(username, ip) {
+            return Ok(LoginOutcome::Throttled(throttled));
+        }
+
+        let digest = credential_digest(username, password);
+        let cached = VERIFIED.lock().unwrap().get(username).and_then(|cached| {
+            (cached.digest == digest && cached.verified_at.elapsed() < CREDENTIAL_TTL)
+                .then(|| (cached.user_id, cached.password_hash.clone()))
+        });
+        if let Some((user_id, password_hash)) = cached {
+            // A changed password or a recreated account invalidates the entry
+            if let Some(user) = UserRepo::new().await.get_by_username(username).await? {
+                if user.user_id == user_id && user.password_hash == password_hash {
+                    if user.is_disabled() {
+                        return Ok(LoginOutcome::Disabled);
+                    }
+                    // Enabled since the credential was cached
+                    if TwoFactorService::new().await.is_enabled(user_id).await? {
+                        return Ok(LoginOutcome::TwoFactorRequired(user_id));
+                    }
+                    return Ok(LoginOutcome::Success(user_id));
+                }
+            }
+        }
+
+        let user = match self.attempt(username, password, ip).await? {
+            Attempt::Valid(user) => user,
+            Attempt::Rejected(outcome) => return Ok(outcome),
+        };
+        VERIFIED.lock().unwrap().insert(
+            username.to_string(),
+            VerifiedCredential {
+                digest,
+                password_hash: user.password_hash,
+                user_id: user.user_id,
+                verified_at: Instant::now(),
+            },
+        );
+        Ok(LoginOutcome::Success(user.user_id))
+    }
 }
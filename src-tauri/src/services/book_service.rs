@@ -1,8 +1,213 @@
-// TODO: Business logic for scanning Ebook files
-// like storing metadata in the database, handling covers, etc.
+use std::path::Path;
 
-pub async fn store_metadata() -> Result<(), Box<dyn std::error::Error>> {
-    // Placeholder for future implementation
-    // Logic for storing metadata in the database
-    unimplemented!()
+use diesel_async::scoped_futures::ScopedFutureExt;
+
+use crate::{
+    data::{
+        database::write_transaction,
+        models::{
+            book_files::NewBookFile,
+            books::{EditBook, NewBook, UpdateBook},
+        },
+        repos::{
+            implementors::{
+                author_repo::AuthorRepo, book_author_repo::BookAuthorRepo,
+                book_file_repo::BookFileRepo, book_identifier_repo::BookIdentifierRepo,
+                book_metadata_source_repo::BookMetadataSourceRepo, book_repo::BookRepo,
+                publisher_repo::PublisherRepo, series_repo::SeriesRepo,
+            },
+            traits::repository::Repository,
+        },
+    },
+    handlers::epub_handler::{store_cover_to_disk, BookMetadata},
+    parsers::{isbn, path_template::InferredMetadata},
+    services::history_service::{HistoryBatch, HistoryService},
+};
+
+pub type BookServiceError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Value was read from the file itself
+pub const SOURCE_EMBEDDED: &str = "embedded";
+/// Value was inferred from the file path through a path template
+pub const SOURCE_PATH: &str = "path";
+/// Neither had a value, a fallback was stored
+pub const SOURCE_DEFAULT: &str = "default";
+
+const FALLBACK_TITLE: &str = "Unknown Title";
+const FALLBACK_AUTHOR: &str = "Unknown Author";
+
+/// Picks the embedded value over the inferred one and tells where it came from
+fn pick<T>(embedded: Option<T>, inferred: Option<T>) -> Option<(T, &'static str)> {
+    embedded
+        .map(|v| (v, SOURCE_EMBEDDED))
+        .or_else(|| inferred.map(|v| (v, SOURCE_PATH)))
+}
+
+/// Stores a scanned book and its relations, recording sources and history under `batch`
+pub async fn store_metadata(
+    metadata: BookMetadata,
+    inferred: Option<InferredMetadata>,
+    library_id: Option<i32>,
+    batch: &HistoryBatch,
+) -> Result<i32, BookServiceError> {
+    let inferred = inferred.unwrap_or_default();
+    let mut sources: Vec<(&str, &str)> = Vec::new();
+
+    let (title, title_source) = pick(metadata.title, inferred.title)
+        .unwrap_or_else(|| (FALLBACK_TITLE.to_string(), SOURCE_DEFAULT));
+    sources.push(("title", title_source));
+
+    let non_empty = |authors: Vec<String>| (!authors.is_empty()).then_some(authors);
+    let (authors, authors_source) = pick(non_empty(metadata.authors), non_empty(inferred.authors))
+        .unwrap_or_else(|| (vec![FALLBACK_AUTHOR.to_string()], SOURCE_DEFAULT));
+    sources.push(("authors", authors_source));
+
+    let publisher = pick(metadata.publishers.into_iter().next(), inferred.publisher);
+    let published_date = pick(metadata.published_date, inferred.published_date);
+    let isbn = pick(
+        metadata.isbn,
+        inferred.isbn.as_deref().and_then(isbn::normalize),
+    );
+    let series = pick(None, inferred.series);
+    let series_index = pick(None, inferred.series_index);
+
+    for (field, value) in [
+        ("publisher", publisher.as_ref().map(|p| p.1)),
+        ("published_date", published_date.as_ref().map(|p| p.1)),
+        ("isbn", isbn.as_ref().map(|p| p.1)),
+        ("series", series.as_ref().map(|p| p.1)),
+        ("series_index", series_index.as_ref().map(|p| p.1)),
+    ] {
+        if let Some(source) = value {
+            sources.push((field, source));
+        }
+    }
+
+    let publisher_id = match &publisher {
+        Some((name, _)) => Some(
+            PublisherRepo::new()
+                .await
+                .get_or_create(name)
+                .await?
+                .publisher_id,
+        ),
+        None => None,
+    };
+    let series_id = match &series {
+        Some((name, _)) => Some(SeriesRepo::new().await.get_or_create(name).await?.series_id),
+        None => None,
+    };
+
+    let file_type = Path::new(&metadata.file_path)
+        .extension()
+        .map(|e| e.to_string_lossy().to_lowercase());
+
+    let book_repo = BookRepo::new().await;
+    let book_id = book_repo
+        .add_returning_id(NewBook {
+            title: &title,
+            published_date: published_date.as_ref().map(|p| p.0.as_str()),
+            publisher_id,
+            isbn: isbn.as_ref().map(|i| i.0.as_str()),
+            file_type: file_type.as_deref(),
+            file_path: Some(&metadata.file_path),
+            series_id,
+            series_index: series_index.map(|i| i.0),
+            library_id,
+            ..Default::default()
+        })
+        .await?;
+
+    let author_repo = AuthorRepo::new().await;
+    let mut author_ids = Vec::new();
+    for author in &authors {
+        author_ids.push(author_repo.get_or_create(author).await?.author_id);
+    }
+    BookAuthorRepo::new()
+        .await
+        .replace_authors(book_id, &author_ids)
+        .await?;
+
+    let file_size = tokio::fs::metadata(&metadata.file_path)
+        .await
+        .ok()
+        .map(|m| m.len() as i64);
+    BookFileRepo::new()
+        .await
+        .add(NewBookFile {
+            book_id,
+            format: file_type.as_deref().unwrap_or_default(),
+            file_path: &metadata.file_path,
+            file_size,
+        })
+        .await?;
+
+    if let Some((data, mime_type)) = &metadata.cover_data {
+        let cover_path = store_cover_to_disk(data, mime_type, &format!("book_{}", book_id)).await?;
+        book_repo
+            .update(
+                book_id,
+                UpdateBook {
+                    cover_image_path: Some(&cover_path),
+                    ..Default::default()
+                },
+            )
+            .await?;
+    }
+
+    BookMetadataSourceRepo::new()
+        .await
+        .set_sources(book_id, &sources)
+        .await?;
+
+    HistoryService::new()
+        .await
+        .record_created(book_id, batch)
+        .await?;
+
+    Ok(book_id)
+}
+
+/// Stores the ISBNs of older scans as ISBN-13 so lookups only need to match one form.
+/// A value that is not a valid ISBN is moved to the book's identifiers and the ISBN cleared.
+/// Returns how many books were changed.
+pub async fn normalize_stored_isbns() -> Result<usize, BookServiceError> {
+    let books = BookRepo::new()
+        .await
+        .get_with_uncanonical_isbn()
+        .await?
+        .unwrap_or_default();
+
+    for book in &books {
+        let Some(stored) = book.isbn.as_deref() else {
+            continue;
+        };
+        let canonical = isbn::normalize(stored);
+        write_transaction(|conn| {
+            async move {
+                BookRepo::new()
+                    .await
+                    .edit_in(
+                        conn,
+                        book.book_id,
+                        EditBook {
+                            isbn: Some(canonical.as_deref()),
+                            ..Default::default()
+                        },
+                    )
+                    .await?;
+                if canonical.is_none() {
+                    BookIdentifierRepo::new()
+                        .await
+                        .set_identifier_in(conn, book.book_id, "isbn", stored)
+                        .await?;
+                }
+                Ok::<_, BookServiceError>(())
+            }
+            .scope_boxed()
+        })
+        .await?;
+    }
+
+    Ok(books.len())
 }
@@ -0,0 +1,453 @@
+use diesel_async::scoped_futures::ScopedFutureExt;
+use uuid::Uuid;
+
+use crate::{
+    data::{
+        database::{connect_from_pool, write_transaction, DbConnection},
+        models::{
+            books::EditBook,
+            metadata_history::{MetadataHistory, NewMetadataHistory},
+        },
+        repos::{
+            implementors::{
+                author_repo::AuthorRepo, book_author_repo::BookAuthorRepo, book_repo::BookRepo,
+                book_tag_repo::BookTagRepo, metadata_history_repo::MetadataHistoryRepo,
+                publisher_repo::PublisherRepo, series_repo::SeriesRepo, tag_repo::TagRepo,
+            },
+            traits::repository::Repository,
+        },
+    },
+    parsers::isbn,
+    services::metadata_service::MetadataField,
+};
+
+pub type HistoryError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Change made while scanning or importing files
+pub const SOURCE_SCAN: &str = "scan";
+/// Change made by hand, including reverts
+pub const SOURCE_USER: &str = "user";
+/// Change applied from a metadata provider match
+pub const SOURCE_PROVIDER: &str = "provider";
+
+/// Every tracked field of a book as stored in the history
+pub type FieldValues = Vec<(MetadataField, Option<String>)>;
+
+/// Groups the changes made by one action so they can be reverted together
+#[derive(Debug, Clone)]
+pub struct HistoryBatch {
+    pub batch_id: String,
+    pub source: &'static str,
+    pub user_id: Option<i32>,
+}
+
+impl HistoryBatch {
+    pub fn new(source: &'static str, user_id: Option<i32>) -> Self {
+        HistoryBatch {
+            batch_id: Uuid::new_v4().to_string(),
+            source,
+            user_id,
+        }
+    }
+}
+
+fn list_value(values: Vec<String>) -> Option<String> {
+    (!values.is_empty()).then(|| serde_json::to_string(&values).unwrap_or_default())
+}
+
+fn parse_list(value: Option<&str>) -> Result<Vec<String>, HistoryError> {
+    match value {
+        Some(json) => Ok(serde_json::from_str(json)?),
+        None => Ok(Vec::new()),
+    }
+}
+
+pub struct HistoryService;
+
+impl HistoryService {
+    pub async fn new() -> Self {
+        HistoryService
+    }
+
+    /// Reads the current value of every tracked field, `None` if the book doesn't exist
+    pub async fn current_values(&self, book_id: i32) -> Result<Option<FieldValues>, HistoryError> {
+        let mut conn = connect_from_pool().await?;
+        self.current_values_in(&mut conn, book_id).await
+    }
+
+    /// Same as [`Self::current_values`], on the connection of a running transaction
+    pub async fn current_values_in(
+        &self,
+        conn: &mut DbConnection,
+        book_id: i32,
+    ) -> Result<Option<FieldValues>, HistoryError> {
+        let Some(book) = BookRepo::new().await.get_by_id_in(conn, book_id).await? else {
+            return Ok(None);
+        };
+
+        let authors = BookAuthorRepo::new()
+            .await
+            .get_authors_by_book_in(conn, book_id)
+            .await?
+            .unwrap_or_default()
+            .into_iter()
+            .map(|a| a.name)
+            .collect();
+        let tags = BookTagRepo::new()
+            .await
+            .get_tags_by_book_in(conn, book_id)
+            .await?
+            .unwrap_or_default()
+            .into_iter()
+            .map(|t| t.name)
+            .collect();
+        let publisher = match book.publisher_id {
+            Some(id) => PublisherRepo::new()
+                .await
+                .get_by_id_in(conn, id)
+                .await?
+                .map(|p| p.name),
+            None => None,
+        };
+        let series = match book.series_id {
+            Some(id) => SeriesRepo::new()
+                .await
+                .get_by_id_in(conn, id)
+                .await?
+                .map(|s| s.name),
+            None => None,
+        };
+
+        Ok(Some(vec![
+            (MetadataField::Title, Some(book.title)),
+            (MetadataField::Authors, list_value(authors)),
+            (MetadataField::Publisher, publisher),
+            (MetadataField::PublishedDate, book.published_date),
+            (MetadataField::Isbn, book.isbn),
+            (MetadataField::Cover, book.cover_image_path),
+            (MetadataField::Description, book.description),
+            (MetadataField::Language, book.language),
+            (MetadataField::Series, series),
+            (
+                MetadataField::SeriesIndex,
+                book.series_index.map(|i| i.to_string()),
+            ),
+            (MetadataField::Rating, book.rating.map(|r| r.to_string())),
+            (MetadataField::Tags, list_value(tags)),
+        ]))
+    }
+
+    /// Records every field that differs between `before` and the current state of the book
+    pub async fn record_changes(
+        &self,
+        book_id: i32,
+        batch: &HistoryBatch,
+        before: &FieldValues,
+    ) -> Result<(), HistoryError> {
+        write_transaction(|conn| {
+            async move { self.record_changes_in(conn, book_id, batch, before).await }.scope_boxed()
+        })
+        .await
+    }
+
+    /// Same as [`Self::record_changes`], on the connection of a running transaction
+    pub async fn record_changes_in(
+        &self,
+        conn: &mut DbConnection,
+        book_id: i32,
+        batch: &HistoryBatch,
+        before: &FieldValues,
+    ) -> Result<(), HistoryError> {
+        let Some(after) = self.current_values_in(conn, book_id).await? else {
+            return Ok(());
+        };
+
+        let entries: Vec<NewMetadataHistory> = after
+            .iter()
+            .filter_map(|(field, new_value)| {
+                let old_value = before
+                    .iter()
+                    .find(|(f, _)| f == field)
+                    .and_then(|(_, v)| v.as_deref());
+                (old_value != new_value.as_deref()).then_some(NewMetadataHistory {
+                    book_id,
+                    field: field.as_str(),
+                    old_value,
+                    new_value: new_value.as_deref(),
+                    source: batch.source,
+                    user_id: batch.user_id,
+                    batch_id: &batch.batch_id,
+                })
+            })
+            .collect();
+
+        MetadataHistoryRepo::new()
+            .await
+            .add_all_in(conn, entries)
+            .await?;
+        Ok(())
+    }
+
+    /// Records the values of a book that was just added, as changes from nothing
+    pub async fn record_created(
+        &self,
+        book_id: i32,
+        batch: &HistoryBatch,
+    ) -> Result<(), HistoryError> {
+        self.record_changes(book_id, batch, &Vec::new()).await
+    }
+
+    /// Same as [`Self::record_created`], on the connection of a running transaction
+    pub async fn record_created_in(
+        &self,
+        conn: &mut DbConnection,
+        book_id: i32,
+        batch: &HistoryBatch,
+    ) -> Result<(), HistoryError> {
+        self.record_changes_in(conn, book_id, batch, &Vec::new())
+            .await
+    }
+
+    /// Stores the given values on a book. A `None` title is ignored as every book needs one.
+    pub async fn write_values(
+        &self,
+        book_id: i32,
+        values: &[(MetadataField, Option<String>)],
+    ) -> Result<(), HistoryError> {
+        write_transaction(|conn| {
+            async move { self.write_values_in(conn, book_id, values).await }.scope_boxed()
+        })
+        .await
+    }
+
+    /// Same as [`Self::write_values`], on the connection of a running transaction
+    pub async fn write_values_in(
+        &self,
+        conn: &mut DbConnection,
+        book_id: i32,
+        values: &[(MetadataField, Option<String>)],
+    ) -> Result<(), HistoryError> {
+        let mut edit = EditBook::default();
+        let mut publisher_id = None;
+        let mut series_id = None;
+
+        for (field, value) in values {
+            let value = value.as_deref();
+            match field {
+                MetadataField::Title => edit.title = value,
+                MetadataField::PublishedDate => edit.published_date = Some(value),
+                MetadataField::Cover => edit.cover_image_path = Some(value),
+                MetadataField::Description => edit.description = Some(value),
+                MetadataField::Language => edit.language = Some(value),
+                MetadataField::SeriesIndex => {
+                    edit.series_index = Some(value.map(str::parse).transpose()?)
+                }
+                MetadataField::Rating => edit.rating = Some(value.map(str::parse).transpose()?),
+                MetadataField::Isbn => {}
+                MetadataField::Publisher => {
+                    publisher_id = Some(match value {
+                        Some(name) => Some(
+                            PublisherRepo::new()
+                                .await
+                                .get_or_create_in(conn, name)
+                                .await?
+                                .publisher_id,
+                        ),
+                        None => None,
+                    });
+                }
+                MetadataField::Series => {
+                    series_id = Some(match value {
+                        Some(name) => Some(
+                            SeriesRepo::new()
+                                .await
+                                .get_or_create_in(conn, name)
+                                .await?
+                                .series_id,
+                        ),
+                        None => None,
+                    });
+                }
+                MetadataField::Authors => {
+                    let author_repo = AuthorRepo::new().await;
+                    let mut author_ids = Vec::new();
+                    for name in parse_list(value)? {
+                        author_ids.push(author_repo.get_or_create_in(conn, &name).await?.author_id);
+                    }
+                    BookAuthorRepo::new()
+                        .await
+                        .replace_authors_in(conn, book_id, &author_ids)
+                        .await?;
+                }
+                MetadataField::Tags => {
+                    let tag_repo = TagRepo::new().await;
+                    let mut tag_ids = Vec::new();
+                    for name in parse_list(value)? {
+                        tag_ids.push(tag_repo.get_or_create_in(conn, &name).await?.tag_id);
+                    }
+                    BookTagRepo::new()
+                        .await
+                        .replace_tags_in(conn, book_id, &tag_ids)
+                        .await?;
+                }
+            }
+        }
+        edit.publisher_id = publisher_id;
+        edit.series_id = series_id;
+
+        let canonical_isbn = match values
+            .iter()
+            .find(|(field, _)| *field == MetadataField::Isbn)
+        {
+            Some((_, Some(value))) => Some(Some(
+                isbn::normalize(value).ok_or_else(|| format!("Not a valid ISBN: {}", value))?,
+            )),
+            Some((_, None)) => Some(None),
+            None => None,
+        };
+        edit.isbn = canonical_isbn.as_ref().map(|v| v.as_deref());
+
+        BookRepo::new().await.edit_in(conn, book_id, edit).await?;
+        Ok(())
+    }
+
+    /// Edits a book by hand and returns the recorded changes, `None` if the book doesn't exist
+    pub async fn edit(
+        &self,
+        book_id: i32,
+        user_id: i32,
+        values: &[(MetadataField, Option<String>)],
+    ) -> Result<Option<Vec<MetadataHistory>>, HistoryError> {
+        let batch = &HistoryBatch::new(SOURCE_USER, Some(user_id));
+        let found = write_transaction(|conn| {
+            async move {
+                let Some(before) = self.current_values_in(conn, book_id).await? else {
+                    return Ok(false);
+                };
+                self.write_values_in(conn, book_id, values).await?;
+                self.record_changes_in(conn, book_id, batch, &before)
+                    .await?;
+                Ok::<_, HistoryError>(true)
+            }
+            .scope_boxed()
+        })
+        .await?;
+
+        if !found {
+            return Ok(None);
+        }
+        self.batch_changes(&batch.batch_id).await.map(Some)
+    }
+
+    /// Restores the old value of a single change, `None` if the change doesn't exist
+    pub async fn revert_change(
+        &self,
+        history_id: i32,
+        user_id: i32,
+    ) -> Result<Option<Vec<MetadataHistory>>, HistoryError> {
+        let Some(entry) = MetadataHistoryRepo::new()
+            .await
+            .get_by_id(history_id)
+            .await?
+        else {
+            return Ok(None);
+        };
+        self.revert(vec![entry], user_id).await.map(Some)
+    }
+
+    /// Restores the old values of every change in a batch that wasn't reverted yet
+    pub async fn revert_batch(
+        &self,
+        batch_id: &str,
+        user_id: i32,
+    ) -> Result<Option<Vec<MetadataHistory>>, HistoryError> {
+        let Some(entries) = MetadataHistoryRepo::new()
+            .await
+            .get_by_batch(batch_id)
+            .await?
+        else {
+            return Ok(None);
+        };
+        self.revert(entries, user_id).await.map(Some)
+    }
+
+    async fn revert(
+        &self,
+        entries: Vec<MetadataHistory>,
+        user_id: i32,
+    ) -> Result<Vec<MetadataHistory>, HistoryError> {
+        let batch = &HistoryBatch::new(SOURCE_USER, Some(user_id));
+
+        let mut entries: Vec<MetadataHistory> = entries
+            .into_iter()
+            .filter(|e| e.reverted_at.is_none())
+            .collect();
+        // Most recent first, so a field changed twice ends up at its oldest value
+        entries.sort_by_key(|e| std::cmp::Reverse(e.history_id));
+
+        let mut book_ids: Vec<i32> = entries.iter().map(|e| e.book_id).collect();
+        book_ids.sort_unstable();
+        book_ids.dedup();
+
+        // Either every change is reverted and marked as such, or none is
+        let reverted = write_transaction(|conn| {
+            async move {
+                let mut reverted = Vec::new();
+                for book_id in book_ids {
+                    let Some(before) = self.current_values_in(conn, book_id).await? else {
+                        continue;
+                    };
+                    for entry in entries.iter().filter(|e| e.book_id == book_id) {
+                        let Some(field) = MetadataField::from_name(&entry.field) else {
+                            continue;
+                        };
+                        self.write_values_in(conn, book_id, &[(field, entry.old_value.clone())])
+                            .await?;
+                        reverted.push(entry.history_id);
+                    }
+                    self.record_changes_in(conn, book_id, batch, &before)
+                        .await?;
+                }
+
+                if !reverted.is_empty() {
+                    MetadataHistoryRepo::new()
+                        .await
+                        .mark_reverted_in(conn, &reverted)
+                        .await?;
+                }
+                Ok::<_, HistoryError>(reverted)
+            }
+            .scope_boxed()
+        })
+        .await?;
+
+        if reverted.is_empty() {
+            return Ok(Vec::new());
+        }
+        self.batch_changes(&batch.batch_id).await
+    }
+
+    async fn batch_changes(&self, batch_id: &str) -> Result<Vec<MetadataHistory>, HistoryError> {
+        Ok(MetadataHistoryRepo::new()
+            .await
+            .get_by_batch(batch_id)
+            .await?
+            .unwrap_or_default())
+    }
+
+    pub async fn get_history(
+        &self,
+        book_id: i32,
+    ) -> Result<Option<Vec<MetadataHistory>>, HistoryError> {
+        if BookRepo::new().await.get_by_id(book_id).await?.is_none() {
+            return Ok(None);
+        }
+        Ok(Some(
+            MetadataHistoryRepo::new()
+                .await
+                .get_by_book(book_id)
+                .await?
+                .unwrap_or_default(),
+        ))
+    }
+}
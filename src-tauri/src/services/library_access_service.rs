@@ -0,0 +1,253 @@
+use diesel::result::{DatabaseErrorKind, Error};
+
+use crate::data::{
+    models::{
+        books::Books,
+        libraries::{Library, UpdateLibrary, Visibility},
+        library_shares::{LibraryShares, NewLibraryShare},
+        users::Role,
+    },
+    repos::{
+        implementors::{
+            book_repo::{BookRepo, BookScope},
+            library_repo::LibraryRepo,
+            library_share_repo::LibraryShareRepo,
+            user_repo::UserRepo,
+        },
+        traits::repository::Repository,
+    },
+};
+
+pub type AccessError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Whom a private library is shared with
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShareTarget {
+    User(i32),
+    /// Everyone with at least this role
+    Role(Role),
+}
+
+#[derive(Debug, PartialEq)]
+pub enum LibraryChange<T> {
+    Done(T),
+    /// The library doesn't exist or the user can't see it
+    NotFound,
+    /// The user can see the library but doesn't manage it
+    Forbidden,
+    Invalid(&'static str),
+    /// The library is already shared with the user or role
+    Conflict,
+}
+
+/// Whether the user manages a library, with their role if they do
+enum Managed {
+    Library(Role),
+    NotFound,
+    Forbidden,
+}
+
+/// Which libraries a user can see: admins every one, others public, own and shared
+/// libraries. Books in no library are admin-only.
+pub struct LibraryAccessService;
+
+impl LibraryAccessService {
+    pub async fn new() -> Self {
+        LibraryAccessService
+    }
+
+    /// The libraries the user can see, nothing for unknown users
+    pub async fn scope(&self, user_id: i32) -> Result<BookScope, AccessError> {
+        let Some(user) = UserRepo::new().await.get_by_id(user_id).await? else {
+            return Ok(BookScope::Libraries(Vec::new()));
+        };
+        let role = user.role();
+        if role == Role::Admin {
+            return Ok(BookScope::All);
+        }
+
+        // A share with a role covers every role above it
+        let roles = [Role::Guest, Role::User, Role::Admin]
+            .into_iter()
+            .filter(|r| role.allows(*r))
+            .map(|r| r.as_str())
+            .collect();
+        let ids = LibraryRepo::new().await.visible_ids(user_id, roles).await?;
+        Ok(BookScope::Libraries(ids))
+    }
+
+    /// The book if the user can see it, `None` if it doesn't exist or is hidden from them
+    pub async fn book(&self, user_id: i32, book_id: i32) -> Result<Option<Books>, AccessError> {
+        let Some(book) = BookRepo::new().await.get_by_id(book_id).await? else {
+            return Ok(None);
+        };
+        Ok(self.scope(user_id).await?.contains(&book).then_some(book))
+    }
+
+    /// Libraries the user can see
+    pub async fn libraries(&self, user_id: i32) -> Result<Vec<Library>, AccessError> {
+        let libraries = LibraryRepo::new()
+            .await
+            .get_all()
+            .await?
+            .unwrap_or_default();
+        Ok(match self.scope(user_id).await? {
+            BookScope::All => libraries,
+            BookScope::Libraries(ids) => libraries
+                .into_iter()
+                .filter(|l| ids.contains(&l.library_id))
+                .collect(),
+        })
+    }
+
+    /// Only admins and the owner manage a library, it is hidden from users who can't see it
+    async fn managed(&self, user_id: i32, library_id: i32) -> Result<Managed, AccessError> {
+        let Some(library) = LibraryRepo::new().await.get_by_id(library_id).await? else {
+            return Ok(Managed::NotFound);
+        };
+        let role = match UserRepo::new().await.get_by_id(user_id).await? {
+            Some(user) => user.role(),
+            None => return Ok(Managed::NotFound),
+        };
+        if role == Role::Admin || library.is_owned_by(user_id) {
+            return Ok(Managed::Library(role));
+        }
+
+        let visible = match self.scope(user_id).await? {
+            BookScope::All => true,
+            BookScope::Libraries(ids) => ids.contains(&library_id),
+        };
+        Ok(if visible {
+            Managed::Forbidden
+        } else {
+            Managed::NotFound
+        })
+    }
+
+    /// Changes who can see a library. Only admins hand a library to another owner.
+    pub async fn set_access(
+        &self,
+        user_id: i32,
+        library_id: i32,
+        visibility: Option<Visibility>,
+        owner: Option<i32>,
+    ) -> Result<LibraryChange<Box<Library>>, AccessError> {
+        let role = match self.managed(user_id, library_id).await? {
+            Managed::Library(role) => role,
+            Managed::NotFound => return Ok(LibraryChange::NotFound),
+            Managed::Forbidden => return Ok(LibraryChange::Forbidden),
+        };
+        if let Some(owner) = owner {
+            if role != Role::Admin {
+                return Ok(LibraryChange::Forbidden);
+            }
+            if UserRepo::new().await.get_by_id(owner).await?.is_none() {
+                return Ok(LibraryChange::Invalid("Owner doesn't exist"));
+            }
+        }
+
+        let repo = LibraryRepo::new().await;
+        repo.update(
+            library_id,
+            UpdateLibrary {
+                name: None,
+                path: None,
+                added_by: owner,
+                visibility: visibility.map(|v| v.as_str()),
+            },
+        )
+        .await?;
+        match repo.get_by_id(library_id).await? {
+            Some(library) => Ok(LibraryChange::Done(Box::new(library))),
+            None => Ok(LibraryChange::NotFound),
+        }
+    }
+
+    pub async fn shares(
+        &self,
+        user_id: i32,
+        library_id: i32,
+    ) -> Result<LibraryChange<Vec<LibraryShares>>, AccessError> {
+        match self.managed(user_id, library_id).await? {
+            Managed::Library(_) => Ok(LibraryChange::Done(
+                LibraryShareRepo::new()
+                    .await
+                    .get_by_library(library_id)
+                    .await?
+                    .unwrap_or_default(),
+            )),
+            Managed::NotFound => Ok(LibraryChange::NotFound),
+            Managed::Forbidden => Ok(LibraryChange::Forbidden),
+        }
+    }
+
+    /// Shares a library, which only matters while it is private
+    pub async fn share(
+        &self,
+        user_id: i32,
+        library_id: i32,
+        target: ShareTarget,
+    ) -> Result<LibraryChange<Box<LibraryShares>>, AccessError> {
+        match self.managed(user_id, library_id).await? {
+            Managed::Library(_) => {}
+            Managed::NotFound => return Ok(LibraryChange::NotFound),
+            Managed::Forbidden => return Ok(LibraryChange::Forbidden),
+        }
+        let new_share = match target {
+            ShareTarget::User(id) => {
+                if UserRepo::new().await.get_by_id(id).await?.is_none() {
+                    return Ok(LibraryChange::Invalid("User doesn't exist"));
+                }
+                NewLibraryShare {
+                    library_id,
+                    user_id: Some(id),
+                    role: None,
+                }
+            }
+            ShareTarget::Role(role) => NewLibraryShare {
+                library_id,
+                user_id: None,
+                role: Some(role.as_str()),
+            },
+        };
+
+        let repo = LibraryShareRepo::new().await;
+        let share_id = match repo.add_returning_id(new_share).await {
+            Ok(id) => id,
+            Err(Error::DatabaseError(DatabaseErrorKind::UniqueViolation, _)) => {
+                return Ok(LibraryChange::Conflict)
+            }
+            Err(e) => return Err(e.into()),
+        };
+        Ok(repo
+            .get_by_library(library_id)
+            .await?
+            .unwrap_or_default()
+            .into_iter()
+            .find(|s| s.share_id == share_id)
+            .map(|s| LibraryChange::Done(Box::new(s)))
+            .unwrap_or(LibraryChange::NotFound))
+    }
+
+    pub async fn unshare(
+        &self,
+        user_id: i32,
+        library_id: i32,
+        share_id: i32,
+    ) -> Result<LibraryChange<()>, AccessError> {
+        match self.managed(user_id, library_id).await? {
+            Managed::Library(_) => {}
+            Managed::NotFound => return Ok(LibraryChange::NotFound),
+            Managed::Forbidden => return Ok(LibraryChange::Forbidden),
+        }
+        if LibraryShareRepo::new()
+            .await
+            .delete_from_library(library_id, share_id)
+            .await?
+        {
+            Ok(LibraryChange::Done(()))
+        } else {
+            Ok(LibraryChange::NotFound)
+        }
+    }
+}
@@ -1 +1,585 @@
+use diesel_async::scoped_futures::ScopedFutureExt;
+use dotenvy::dotenv;
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::env;
+use std::path::PathBuf;
 
+use crate::{
+    data::{
+        database::write_transaction,
+        models::{
+            authors::AuthorForm,
+            book_files::NewBookFile,
+            books::{NewBook, UpdateBook},
+            libraries::{Library, NewLibrary},
+            user_library::NewUserLibrary,
+        },
+        repos::{
+            implementors::{
+                author_repo::AuthorRepo, book_author_repo::BookAuthorRepo,
+                book_file_repo::BookFileRepo, book_identifier_repo::BookIdentifierRepo,
+                book_repo::BookRepo, book_tag_repo::BookTagRepo, library_repo::LibraryRepo,
+                publisher_repo::PublisherRepo, series_repo::SeriesRepo, tag_repo::TagRepo,
+                user_library_repo::UserLibraryRepo,
+            },
+            traits::repository::Repository,
+        },
+    },
+    handlers::{
+        calibre_handler::{self, CalibreBook, CalibreCustomColumn},
+        epub_handler::{self, store_cover_to_disk},
+    },
+    parsers::{
+        isbn,
+        path_template::{self, DEFAULT_TEMPLATES},
+    },
+    services::{
+        book_service,
+        history_service::{HistoryBatch, HistoryService, SOURCE_SCAN},
+    },
+};
+
+pub type LibraryError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Path templates used by library scans, `;` separated in `PATH_TEMPLATES`
+static PATH_TEMPLATES: Lazy<Vec<String>> = Lazy::new(|| {
+    dotenv().ok();
+
+    match env::var("PATH_TEMPLATES") {
+        Ok(value) if !value.trim().is_empty() => value
+            .split(';')
+            .map(|t| t.trim().to_string())
+            .filter(|t| !t.is_empty())
+            .collect(),
+        _ => DEFAULT_TEMPLATES.iter().map(|t| t.to_string()).collect(),
+    }
+});
+
+/// Identifier scheme holding the Calibre uuid, used to recognise books imported before
+const CALIBRE_SCHEME: &str = "calibre";
+
+/// Formats in the order they are preferred as the main file of a book
+const PREFERRED_FORMATS: [&str; 6] = ["EPUB", "KEPUB", "PDF", "CBZ", "AZW3", "MOBI"];
+
+/// Where the values of a Calibre user column end up
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ColumnTarget {
+    Tags,
+    Series,
+    Rating,
+    Description,
+    Ignore,
+}
+
+impl ColumnTarget {
+    /// Default mapping of a Calibre column datatype
+    pub fn for_datatype(datatype: &str) -> ColumnTarget {
+        match datatype {
+            "text" | "enumeration" => ColumnTarget::Tags,
+            "series" => ColumnTarget::Series,
+            "rating" => ColumnTarget::Rating,
+            "comments" => ColumnTarget::Description,
+            _ => ColumnTarget::Ignore,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Default)]
+pub struct CalibreImportOptions {
+    /// Overrides the default mapping, keyed by the column lookup name without `#`
+    #[serde(default)]
+    pub column_mapping: HashMap<String, ColumnTarget>,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct ColumnReport {
+    pub label: String,
+    pub name: String,
+    pub datatype: String,
+    pub target: ColumnTarget,
+}
+
+/// Something from a Calibre book that could not be carried over
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct ImportIssue {
+    pub calibre_id: i32,
+    pub title: String,
+    pub message: String,
+}
+
+#[derive(Serialize, Debug, Default)]
+pub struct CalibreImportReport {
+    pub library_id: i32,
+    /// History batch of the imported values, revert it to undo the import's metadata
+    pub batch_id: String,
+    pub imported: usize,
+    pub skipped: usize,
+    pub failed: usize,
+    pub mapped_columns: Vec<ColumnReport>,
+    pub unmapped_columns: Vec<ColumnReport>,
+    pub issues: Vec<ImportIssue>,
+}
+
+/// A file the scan couldn't add
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct ScanIssue {
+    pub path: String,
+    pub message: String,
+}
+
+#[derive(Serialize, Debug, Default)]
+pub struct ScanReport {
+    pub library_id: i32,
+    /// History batch of the added values
+    pub batch_id: String,
+    pub added: usize,
+    pub skipped: usize,
+    pub failed: usize,
+    /// Templates that were used, in the order they were tried
+    pub templates: Vec<String>,
+    pub template_errors: Vec<String>,
+    pub issues: Vec<ScanIssue>,
+}
+
+pub struct LibraryService;
+
+impl LibraryService {
+    pub async fn new() -> Self {
+        LibraryService
+    }
+
+    /// Adds new EPUBs below the library root, inferring missing fields from path templates.
+    /// `None` if the library doesn't exist.
+    pub async fn scan_library(
+        &self,
+        library_id: i32,
+        user_id: i32,
+        templates: Option<Vec<String>>,
+    ) -> Result<Option<ScanReport>, LibraryError> {
+        let Some(library) = LibraryRepo::new().await.get_by_id(library_id).await? else {
+            return Ok(None);
+        };
+
+        let templates = templates.unwrap_or_else(|| PATH_TEMPLATES.clone());
+        let (compiled, template_errors) = path_template::parse_templates(&templates);
+        let batch = HistoryBatch::new(SOURCE_SCAN, Some(user_id));
+        let mut report = ScanReport {
+            library_id,
+            batch_id: batch.batch_id.clone(),
+            templates: compiled.iter().map(|t| t.source().to_string()).collect(),
+            template_errors,
+            ..Default::default()
+        };
+
+        let root = PathBuf::from(&library.path);
+        let book_file_repo = BookFileRepo::new().await;
+        let user_library_repo = UserLibraryRepo::new().await;
+
+        for path in epub_handler::scan_epubs(root.clone()).await? {
+            let file_path = path.to_string_lossy().to_string();
+            if book_file_repo.get_by_path(&file_path).await?.is_some() {
+                report.skipped += 1;
+                continue;
+            }
+
+            let relative = path.strip_prefix(&root).unwrap_or(&path);
+            let inferred = path_template::infer_from_path(&compiled, relative);
+
+            let result = match epub_handler::parse_epub_meta(file_path.clone()).await {
+                Ok(metadata) => {
+                    book_service::store_metadata(metadata, inferred, Some(library_id), &batch)
+                        .await
+                        .map_err(|e| e.to_string())
+                }
+                Err(e) => Err(format!("Failed to read EPUB: {}", e)),
+            };
+
+            match result {
+                Ok(book_id) => {
+                    user_library_repo
+                        .add(NewUserLibrary { user_id, book_id })
+                        .await?;
+                    report.added += 1;
+                }
+                Err(message) => {
+                    report.failed += 1;
+                    report.issues.push(ScanIssue {
+                        path: file_path,
+                        message,
+                    });
+                }
+            }
+        }
+
+        Ok(Some(report))
+    }
+
+    /// Imports a Calibre library at `root`, books imported before are skipped
+    pub async fn import_calibre(
+        &self,
+        root: PathBuf,
+        user_id: i32,
+        options: CalibreImportOptions,
+    ) -> Result<CalibreImportReport, LibraryError> {
+        let calibre = calibre_handler::read_calibre_library(root.clone()).await?;
+        let library = self.get_or_create_library(&root, user_id).await?;
+
+        let batch = HistoryBatch::new(SOURCE_SCAN, Some(user_id));
+        let mut report = CalibreImportReport {
+            library_id: library.library_id,
+            batch_id: batch.batch_id.clone(),
+            ..Default::default()
+        };
+
+        let mut targets: HashMap<i32, (&CalibreCustomColumn, ColumnTarget)> = HashMap::new();
+        for column in &calibre.custom_columns {
+            let target = options
+                .column_mapping
+                .get(&column.label)
+                .copied()
+                .unwrap_or_else(|| ColumnTarget::for_datatype(&column.datatype));
+            let column_report = ColumnReport {
+                label: column.label.clone(),
+                name: column.name.clone(),
+                datatype: column.datatype.clone(),
+                target,
+            };
+            if target == ColumnTarget::Ignore {
+                report.unmapped_columns.push(column_report);
+            } else {
+                report.mapped_columns.push(column_report);
+                targets.insert(column.id, (column, target));
+            }
+        }
+
+        for book in &calibre.books {
+            match self
+                .import_book(
+                    book,
+                    library.library_id,
+                    user_id,
+                    &targets,
+                    &batch,
+                    &mut report,
+                )
+                .await
+            {
+                Ok(true) => report.imported += 1,
+                Ok(false) => report.skipped += 1,
+                Err(e) => {
+                    report.failed += 1;
+                    report.issues.push(ImportIssue {
+                        calibre_id: book.calibre_id,
+                        title: book.title.clone(),
+                        message: format!("Import failed: {}", e),
+                    });
+                }
+            }
+        }
+
+        Ok(report)
+    }
+
+    async fn get_or_create_library(
+        &self,
+        root: &std::path::Path,
+        user_id: i32,
+    ) -> Result<Library, LibraryError> {
+        let library_repo = LibraryRepo::new().await;
+        let path = root.to_string_lossy();
+
+        if let Some(library) = library_repo.get_by_path(&path).await? {
+            return Ok(library);
+        }
+
+        let name = root
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_else(|| "Calibre Library".to_string());
+        library_repo
+            .add(NewLibrary {
+                name: &name,
+                path: &path,
+                added_by: Some(user_id),
+                visibility: None,
+            })
+            .await?;
+
+        library_repo
+            .get_by_path(&path)
+            .await?
+            .ok_or_else(|| "Library was not created".into())
+    }
+
+    /// Returns false when the book was already imported
+    async fn import_book(
+        &self,
+        book: &CalibreBook,
+        library_id: i32,
+        user_id: i32,
+        targets: &HashMap<i32, (&CalibreCustomColumn, ColumnTarget)>,
+        batch: &HistoryBatch,
+        report: &mut CalibreImportReport,
+    ) -> Result<bool, LibraryError> {
+        let book_file_repo = BookFileRepo::new().await;
+        let identifier_repo = BookIdentifierRepo::new().await;
+        if let Some(uuid) = &book.uuid {
+            if identifier_repo
+                .get_by_value(CALIBRE_SCHEME, uuid)
+                .await?
+                .is_some()
+            {
+                return Ok(false);
+            }
+        }
+
+        let mut issue = |message: String| {
+            report.issues.push(ImportIssue {
+                calibre_id: book.calibre_id,
+                title: book.title.clone(),
+                message,
+            })
+        };
+
+        let mut formats = Vec::new();
+        for format in &book.formats {
+            let path = format.path.to_string_lossy().to_string();
+            if book_file_repo.get_by_path(&path).await?.is_some() {
+                return Ok(false);
+            }
+            if format.path.is_file() {
+                formats.push((format, path));
+            } else {
+                issue(format!("{} file is missing: {}", format.format, path));
+            }
+        }
+
+        let mut series_name = book.series.clone();
+        let mut series_index = book.series_index;
+        let mut rating = book.rating;
+        let mut description = book.comments.clone();
+        let mut tags = book.tags.clone();
+
+        for value in &book.custom_values {
+            let Some((column, target)) = targets.get(&value.column_id) else {
+                continue;
+            };
+            let conflict = match target {
+                ColumnTarget::Tags => {
+                    for v in &value.values {
+                        if !tags.contains(v) {
+                            tags.push(v.clone());
+                        }
+                    }
+                    false
+                }
+                ColumnTarget::Series if series_name.is_none() => {
+                    series_name = value.values.first().cloned();
+                    series_index = value.index;
+                    false
+                }
+                ColumnTarget::Rating if rating.is_none() => {
+                    rating = value
+                        .values
+                        .first()
+                        .and_then(|v| v.parse::<f64>().ok())
+                        .map(
+                            // Integer and float columns may hold values outside the 0 to 10 scale
+                            |v| v.round().clamp(0.0, 10.0) as i32,
+                        );
+                    false
+                }
+                ColumnTarget::Description if description.is_none() => {
+                    description = Some(value.values.join(", "));
+                    false
+                }
+                ColumnTarget::Ignore => false,
+                _ => true,
+            };
+            if conflict {
+                issue(format!(
+                    "Column #{} was not carried over: the book already has a value for it",
+                    column.label
+                ));
+            }
+        }
+
+        let main_file = PREFERRED_FORMATS
+            .iter()
+            .find_map(|preferred| formats.iter().find(|(f, _)| f.format == *preferred))
+            .or_else(|| formats.first());
+        let file_type = main_file.map(|(f, _)| f.format.to_lowercase());
+        let file_path = main_file.map(|(_, path)| path.clone());
+        let isbn = book
+            .identifiers
+            .iter()
+            .find_map(|(scheme, value)| isbn::from_identifier(value, Some(scheme)));
+
+        let mut identifiers = Vec::new();
+        for (scheme, value) in &book.identifiers {
+            if !isbn::is_isbn_scheme(scheme) {
+                identifiers.push((scheme.as_str(), value.clone()));
+                continue;
+            }
+            match isbn::normalize(value) {
+                Some(canonical) => identifiers.push((scheme.as_str(), canonical)),
+                None => issue(format!("{} {} is not a valid ISBN", scheme, value)),
+            }
+        }
+
+        let cover_data = match &book.cover_path {
+            Some(cover_path) => match tokio::fs::read(cover_path).await {
+                Ok(data) => Some(data),
+                Err(e) => {
+                    issue(format!("Cover could not be read: {}", e));
+                    None
+                }
+            },
+            None => None,
+        };
+
+        // Everything of a book is stored or nothing, a failed import can simply be run again
+        write_transaction(|conn| {
+            async move {
+                let publisher_id = match &book.publisher {
+                    Some(name) => Some(
+                        PublisherRepo::new()
+                            .await
+                            .get_or_create_in(conn, name)
+                            .await?
+                            .publisher_id,
+                    ),
+                    None => None,
+                };
+                let series_id = match &series_name {
+                    Some(name) => Some(
+                        SeriesRepo::new()
+                            .await
+                            .get_or_create_in(conn, name)
+                            .await?
+                            .series_id,
+                    ),
+                    None => None,
+                };
+
+                let book_repo = BookRepo::new().await;
+                let book_id = book_repo
+                    .add_returning_id_in(
+                        conn,
+                        NewBook {
+                            title: &book.title,
+                            published_date: book.published_date.as_deref(),
+                            publisher_id,
+                            isbn: isbn.as_deref(),
+                            file_type: file_type.as_deref(),
+                            file_path: file_path.as_deref(),
+                            description: description.as_deref(),
+                            language: book.languages.first().map(|l| l.as_str()),
+                            series_id,
+                            series_index: series_index.map(|i| i as f32),
+                            rating,
+                            library_id: Some(library_id),
+                            ..Default::default()
+                        },
+                    )
+                    .await?;
+
+                if let Some(uuid) = &book.uuid {
+                    identifier_repo
+                        .set_identifier_in(conn, book_id, CALIBRE_SCHEME, uuid)
+                        .await?;
+                }
+
+                for (format, path) in &formats {
+                    book_file_repo
+                        .add_in(
+                            conn,
+                            NewBookFile {
+                                book_id,
+                                format: &format.format.to_lowercase(),
+                                file_path: path,
+                                file_size: format.size,
+                            },
+                        )
+                        .await?;
+                }
+
+                let author_repo = AuthorRepo::new().await;
+                let mut author_ids = Vec::new();
+                for calibre_author in &book.authors {
+                    let author = author_repo
+                        .get_or_create_in(conn, &calibre_author.name)
+                        .await?;
+                    if author.sort_name.is_none() && calibre_author.sort.is_some() {
+                        author_repo
+                            .update_in(
+                                conn,
+                                author.author_id,
+                                AuthorForm {
+                                    name: None,
+                                    sort_name: calibre_author.sort.as_deref(),
+                                },
+                            )
+                            .await?;
+                    }
+                    author_ids.push(author.author_id);
+                }
+                BookAuthorRepo::new()
+                    .await
+                    .replace_authors_in(conn, book_id, &author_ids)
+                    .await?;
+
+                let tag_repo = TagRepo::new().await;
+                let mut tag_ids = Vec::new();
+                for tag in &tags {
+                    tag_ids.push(tag_repo.get_or_create_in(conn, tag).await?.tag_id);
+                }
+                BookTagRepo::new()
+                    .await
+                    .replace_tags_in(conn, book_id, &tag_ids)
+                    .await?;
+
+                for (scheme, value) in &identifiers {
+                    identifier_repo
+                        .set_identifier_in(conn, book_id, scheme, value)
+                        .await?;
+                }
+
+                if let Some(data) = &cover_data {
+                    let stored =
+                        store_cover_to_disk(data, "image/jpeg", &format!("book_{}", book_id))
+                            .await?;
+                    book_repo
+                        .update_in(
+                            conn,
+                            book_id,
+                            UpdateBook {
+                                cover_image_path: Some(&stored),
+                                ..Default::default()
+                            },
+                        )
+                        .await?;
+                }
+
+                HistoryService::new()
+                    .await
+                    .record_created_in(conn, book_id, batch)
+                    .await?;
+
+                UserLibraryRepo::new()
+                    .await
+                    .add_in(conn, NewUserLibrary { user_id, book_id })
+                    .await?;
+
+                Ok::<_, LibraryError>(())
+            }
+            .scope_boxed()
+        })
+        .await?;
+
+        Ok(true)
+    }
+}
@@ -0,0 +1,172 @@
+use async_trait::async_trait;
+use dotenvy::dotenv;
+use lettre::{
+    message::{header::ContentType, Mailbox},
+    transport::smtp::authentication::Credentials,
+    AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor,
+};
+use once_cell::sync::Lazy;
+use std::{env, path::PathBuf, sync::Arc};
+use tokio::io::AsyncWriteExt;
+
+pub type MailError = Box<dyn std::error::Error + Send + Sync>;
+
+/// A plain text mail to a single recipient
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MailMessage {
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+}
+
+/// Delivers mails to users, e.g. password reset links
+#[async_trait]
+pub trait Mailer: Send + Sync {
+    async fn send(&self, message: &MailMessage) -> Result<(), MailError>;
+}
+
+/// How the connection to the SMTP server is secured
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SmtpSecurity {
+    /// Implicit TLS, usually on port 465
+    Tls,
+    /// Upgrade a plain connection, usually on port 587
+    StartTls,
+    /// Unencrypted, only meant for a relay on the same machine
+    None,
+}
+
+/// Sends mails through an SMTP server
+pub struct SmtpMailer {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    from: Mailbox,
+}
+
+impl SmtpMailer {
+    pub fn new(
+        host: &str,
+        port: Option<u16>,
+        security: SmtpSecurity,
+        credentials: Option<(String, String)>,
+        from: &str,
+    ) -> Result<Self, MailError> {
+        let mut builder = match security {
+            SmtpSecurity::Tls => AsyncSmtpTransport::<Tokio1Executor>::relay(host)?,
+            SmtpSecurity::StartTls => AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(host)?,
+            SmtpSecurity::None => AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(host),
+        };
+        if let Some(port) = port {
+            builder = builder.port(port);
+        }
+        if let Some((username, password)) = credentials {
+            builder = builder.credentials(Credentials::new(username, password));
+        }
+
+        Ok(SmtpMailer {
+            transport: builder.build(),
+            from: from.parse()?,
+        })
+    }
+}
+
+#[async_trait]
+impl Mailer for SmtpMailer {
+    async fn send(&self, message: &MailMessage) -> Result<(), MailError> {
+        let email = Message::builder()
+            .from(self.from.clone())
+            .to(message.to.parse()?)
+            .subject(&message.subject)
+            .header(ContentType::TEXT_PLAIN)
+            .body(message.body.clone())?;
+        self.transport.send(email).await?;
+        Ok(())
+    }
+}
+
+/// Writes mails to a file, or to stdout without a path, for local setups without SMTP
+pub struct LogMailer {
+    path: Option<PathBuf>,
+}
+
+impl LogMailer {
+    pub fn new<P: Into<PathBuf>>(path: Option<P>) -> Self {
+        LogMailer {
+            path: path.map(Into::into),
+        }
+    }
+}
+
+#[async_trait]
+impl Mailer for LogMailer {
+    async fn send(&self, message: &MailMessage) -> Result<(), MailError> {
+        let entry = format!(
+            "To: {}\nSubject: {}\n\n{}\n----\n",
+            message.to, message.subject, message.body
+        );
+        match &self.path {
+            Some(path) => {
+                let mut file = tokio::fs::OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(path)
+                    .await?;
+                file.write_all(entry.as_bytes()).await?;
+                // Tokio writes in the background, the mail is only on disk after a flush
+                file.flush().await?;
+            }
+            None => println!("{}", entry),
+        }
+        Ok(())
+    }
+}
+
+/// `MAILER` is `smtp` (`SMTP_*` and `MAIL_FROM`) or `log` (`MAIL_LOG_PATH` or stdout).
+/// Mails can't be sent without it, the log mailer writes reset tokens in clear.
+static MAILER: Lazy<Option<Arc<dyn Mailer>>> = Lazy::new(|| {
+    dotenv().ok();
+
+    match env::var("MAILER").as_deref() {
+        Ok("smtp") => (),
+        Ok("log") => {
+            return Some(Arc::new(LogMailer::new(env::var("MAIL_LOG_PATH").ok())));
+        }
+        Ok(other) => {
+            eprintln!("Unknown MAILER {}, mails can't be sent", other);
+            return None;
+        }
+        Err(_) => {
+            eprintln!("MAILER is not set, mails can't be sent");
+            return None;
+        }
+    }
+
+    let Ok(host) = env::var("SMTP_HOST") else {
+        eprintln!("MAILER is smtp but SMTP_HOST is not set, mails can't be sent");
+        return None;
+    };
+    let security = match env::var("SMTP_SECURITY").as_deref() {
+        Ok("tls") => SmtpSecurity::Tls,
+        Ok("none") => SmtpSecurity::None,
+        _ => SmtpSecurity::StartTls,
+    };
+    let port = env::var("SMTP_PORT")
+        .ok()
+        .and_then(|p| p.parse::<u16>().ok());
+    let credentials = env::var("SMTP_USERNAME")
+        .ok()
+        .map(|user| (user, env::var("SMTP_PASSWORD").unwrap_or_default()));
+    let from = env::var("MAIL_FROM").unwrap_or_else(|_| format!("Stellaron <stellaron@{}>", host));
+
+    match SmtpMailer::new(&host, port, security, credentials, &from) {
+        Ok(mailer) => Some(Arc::new(mailer)),
+        Err(e) => {
+            eprintln!("Invalid SMTP configuration, mails can't be sent: {}", e);
+            None
+        }
+    }
+});
+
+/// Mailer configured in the environment, `None` if mails can't be sent
+pub fn mailer_from_env() -> Option<Arc<dyn Mailer>> {
+    MAILER.clone()
+}
@@ -1 +1,772 @@
-// Metadata parsing and management service implementations go here
+use async_trait::async_trait;
+use diesel_async::scoped_futures::ScopedFutureExt;
+use dotenvy::dotenv;
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::{
+    env,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+use uuid::Uuid;
+
+use crate::{
+    data::{
+        database::write_transaction,
+        models::books::{Books, UpdateBook},
+        repos::{
+            implementors::{
+                author_repo::AuthorRepo, book_author_repo::BookAuthorRepo,
+                book_metadata_source_repo::BookMetadataSourceRepo, book_repo::BookRepo,
+                publisher_repo::PublisherRepo,
+            },
+            traits::repository::Repository,
+        },
+    },
+    handlers::epub_handler::store_cover_to_disk,
+    parsers::isbn,
+    services::history_service::{HistoryBatch, HistoryService, SOURCE_PROVIDER},
+};
+
+pub type MetadataError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Book metadata as returned by an external provider
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MetadataCandidate {
+    pub provider: String,
+    /// Identifier of the record at the provider, used to apply the candidate
+    pub id: Option<String>,
+    pub title: String,
+    pub authors: Vec<String>,
+    pub publisher: Option<String>,
+    pub published_date: Option<String>,
+    pub isbn: Option<String>,
+    pub cover_url: Option<String>,
+}
+
+/// A source of book metadata, looked up by ISBN or by title and author
+#[async_trait]
+pub trait MetadataProvider: Send + Sync {
+    /// Short identifier of the provider (e.g. `openlibrary`)
+    fn name(&self) -> &str;
+
+    async fn search_by_isbn(&self, isbn: &str) -> Result<Vec<MetadataCandidate>, MetadataError>;
+
+    async fn search_by_title_author(
+        &self,
+        title: &str,
+        author: Option<&str>,
+    ) -> Result<Vec<MetadataCandidate>, MetadataError>;
+
+    async fn get_by_id(&self, id: &str) -> Result<Option<MetadataCandidate>, MetadataError>;
+
+    /// Retrieves the cover of a candidate as `(data, mime_type)`
+    async fn fetch_cover(
+        &self,
+        candidate: &MetadataCandidate,
+    ) -> Result<Option<(Vec<u8>, String)>, MetadataError>;
+}
+
+// Subset of the Open Library `search.json` response used by the providers
+#[derive(Debug, Deserialize)]
+struct OpenLibrarySearchResponse {
+    #[serde(default)]
+    docs: Vec<OpenLibraryDoc>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OpenLibraryDoc {
+    key: Option<String>,
+    title: Option<String>,
+    #[serde(default)]
+    author_name: Vec<String>,
+    #[serde(default)]
+    publisher: Vec<String>,
+    #[serde(default)]
+    publish_date: Vec<String>,
+    first_publish_year: Option<i32>,
+    #[serde(default)]
+    isbn: Vec<String>,
+    cover_i: Option<i64>,
+}
+
+/// Limits for requests to Open Library, a stalled server would otherwise hold a request forever
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+/// Larger covers are refused instead of being read into memory
+const MAX_COVER_BYTES: usize = 10 * 1024 * 1024;
+
+const OPEN_LIBRARY_FIELDS: &str =
+    "key,title,author_name,publisher,publish_date,first_publish_year,isbn,cover_i";
+
+impl OpenLibraryDoc {
+    fn into_candidate<F>(self, provider: &str, cover_url: F) -> Option<MetadataCandidate>
+    where
+        F: Fn(i64) -> String,
+    {
+        let title = self.title?;
+        let isbn = self.isbn.iter().find_map(|i| isbn::normalize(i));
+        let published_date = self
+            .first_publish_year
+            .map(|year| year.to_string())
+            .or_else(|| self.publish_date.into_iter().next());
+
+        Some(MetadataCandidate {
+            provider: provider.to_string(),
+            id: self.key,
+            title,
+            authors: self.author_name,
+            publisher: self.publisher.into_iter().next(),
+            published_date,
+            isbn,
+            cover_url: self.cover_i.map(cover_url),
+        })
+    }
+}
+
+fn search_isbn(value: &str) -> String {
+    isbn::normalize(value).unwrap_or_else(|| isbn::strip(value))
+}
+
+/// Open Library keys look like `/works/OL45883W`
+fn is_open_library_key(id: &str) -> bool {
+    id.strip_prefix("/works/")
+        .or_else(|| id.strip_prefix("/books/"))
+        .is_some_and(|rest| !rest.is_empty() && rest.chars().all(|c| c.is_ascii_alphanumeric()))
+}
+
+/// Queries an Open Library compatible JSON API
+pub struct OpenLibraryProvider {
+    base_url: String,
+    covers_url: String,
+    client: reqwest::Client,
+}
+
+impl OpenLibraryProvider {
+    pub fn new(base_url: &str, covers_url: &str) -> Self {
+        OpenLibraryProvider {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            covers_url: covers_url.trim_end_matches('/').to_string(),
+            client: reqwest::Client::builder()
+                .connect_timeout(CONNECT_TIMEOUT)
+                .timeout(REQUEST_TIMEOUT)
+                .build()
+                .expect("Failed to build the Open Library HTTP client"),
+        }
+    }
+
+    /// Only covers on the configured covers host are downloaded
+    fn is_cover_url(&self, url: &str) -> bool {
+        let (Ok(url), Ok(covers)) = (
+            reqwest::Url::parse(url),
+            reqwest::Url::parse(&self.covers_url),
+        ) else {
+            return false;
+        };
+        url.scheme() == covers.scheme()
+            && url.host() == covers.host()
+            && url.port_or_known_default() == covers.port_or_known_default()
+            && url
+                .path()
+                .starts_with(&format!("{}/b/", covers.path().trim_end_matches('/')))
+    }
+
+    async fn search(
+        &self,
+        query: &[(&str, &str)],
+    ) -> Result<Vec<MetadataCandidate>, MetadataError> {
+        let response = self
+            .client
+            .get(format!("{}/search.json", self.base_url))
+            .query(query)
+            .query(&[("fields", OPEN_LIBRARY_FIELDS), ("limit", "10")])
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<OpenLibrarySearchResponse>()
+            .await?;
+
+        Ok(response
+            .docs
+            .into_iter()
+            .filter_map(|doc| {
+                doc.into_candidate(self.name(), |id| {
+                    format!("{}/b/id/{}-L.jpg", self.covers_url, id)
+                })
+            })
+            .collect())
+    }
+}
+
+#[async_trait]
+impl MetadataProvider for OpenLibraryProvider {
+    fn name(&self) -> &str {
+        "openlibrary"
+    }
+
+    async fn search_by_isbn(&self, isbn: &str) -> Result<Vec<MetadataCandidate>, MetadataError> {
+        self.search(&[("isbn", &search_isbn(isbn))]).await
+    }
+
+    async fn search_by_title_author(
+        &self,
+        title: &str,
+        author: Option<&str>,
+    ) -> Result<Vec<MetadataCandidate>, MetadataError> {
+        match author {
+            Some(author) => self.search(&[("title", title), ("author", author)]).await,
+            None => self.search(&[("title", title)]).await,
+        }
+    }
+
+    async fn get_by_id(&self, id: &str) -> Result<Option<MetadataCandidate>, MetadataError> {
+        if !is_open_library_key(id) {
+            return Ok(None);
+        }
+        let query = format!("key:\"{}\"", id);
+        Ok(self
+            .search(&[("q", &query)])
+            .await?
+            .into_iter()
+            .find(|c| c.id.as_deref() == Some(id)))
+    }
+
+    async fn fetch_cover(
+        &self,
+        candidate: &MetadataCandidate,
+    ) -> Result<Option<(Vec<u8>, String)>, MetadataError> {
+        let Some(url) = &candidate.cover_url else {
+            return Ok(None);
+        };
+        if !self.is_cover_url(url) {
+            return Err(format!("Cover URL is not on {}: {}", self.covers_url, url).into());
+        }
+
+        // `default=false` makes Open Library answer 404 instead of a placeholder image
+        let response = self
+            .client
+            .get(url)
+            .query(&[("default", "false")])
+            .send()
+            .await?;
+
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+
+        let mut response = response.error_for_status()?;
+        if response
+            .content_length()
+            .is_some_and(|len| len > MAX_COVER_BYTES as u64)
+        {
+            return Err(format!("Cover is larger than {} bytes: {}", MAX_COVER_BYTES, url).into());
+        }
+        let mime_type = response
+            .headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or("image/jpeg")
+            .to_string();
+
+        // The length header is optional, the body is checked while it is read
+        let mut data = Vec::new();
+        while let Some(chunk) = response.chunk().await? {
+            if data.len() + chunk.len() > MAX_COVER_BYTES {
+                return Err(
+                    format!("Cover is larger than {} bytes: {}", MAX_COVER_BYTES, url).into(),
+                );
+            }
+            data.extend_from_slice(&chunk);
+        }
+
+        Ok(Some((data, mime_type)))
+    }
+}
+
+/// Offline provider reading a `search.json` file, covers come from `covers/` next to it
+pub struct FixtureMetadataProvider {
+    path: PathBuf,
+}
+
+impl FixtureMetadataProvider {
+    pub fn new<P: AsRef<Path>>(path: P) -> Self {
+        FixtureMetadataProvider {
+            path: path.as_ref().to_path_buf(),
+        }
+    }
+
+    fn cover_dir(&self) -> PathBuf {
+        self.path
+            .parent()
+            .map(|p| p.join("covers"))
+            .unwrap_or_else(|| PathBuf::from("covers"))
+    }
+
+    async fn load(&self) -> Result<Vec<MetadataCandidate>, MetadataError> {
+        let contents = tokio::fs::read_to_string(&self.path).await?;
+        let response: OpenLibrarySearchResponse = serde_json::from_str(&contents)?;
+        let cover_dir = self.cover_dir();
+
+        Ok(response
+            .docs
+            .into_iter()
+            .filter_map(|doc| {
+                doc.into_candidate(self.name(), |id| {
+                    cover_dir
+                        .join(format!("{}.jpg", id))
+                        .to_string_lossy()
+                        .to_string()
+                })
+            })
+            .collect())
+    }
+}
+
+#[async_trait]
+impl MetadataProvider for FixtureMetadataProvider {
+    fn name(&self) -> &str {
+        "fixture"
+    }
+
+    async fn search_by_isbn(&self, isbn: &str) -> Result<Vec<MetadataCandidate>, MetadataError> {
+        let isbn = search_isbn(isbn);
+        Ok(self
+            .load()
+            .await?
+            .into_iter()
+            .filter(|c| c.isbn.as_deref().map(search_isbn).as_deref() == Some(isbn.as_str()))
+            .collect())
+    }
+
+    async fn search_by_title_author(
+        &self,
+        title: &str,
+        author: Option<&str>,
+    ) -> Result<Vec<MetadataCandidate>, MetadataError> {
+        let title = title.to_lowercase();
+        let author = author.map(|a| a.to_lowercase());
+
+        Ok(self
+            .load()
+            .await?
+            .into_iter()
+            .filter(|c| c.title.to_lowercase().contains(&title))
+            .filter(|c| match &author {
+                Some(author) => c.authors.iter().any(|a| a.to_lowercase().contains(author)),
+                None => true,
+            })
+            .collect())
+    }
+
+    async fn get_by_id(&self, id: &str) -> Result<Option<MetadataCandidate>, MetadataError> {
+        Ok(self
+            .load()
+            .await?
+            .into_iter()
+            .find(|c| c.id.as_deref() == Some(id)))
+    }
+
+    async fn fetch_cover(
+        &self,
+        candidate: &MetadataCandidate,
+    ) -> Result<Option<(Vec<u8>, String)>, MetadataError> {
+        let Some(path) = &candidate.cover_url else {
+            return Ok(None);
+        };
+        let inside = Path::new(path)
+            .strip_prefix(self.cover_dir())
+            .is_ok_and(|rest| {
+                rest.components()
+                    .all(|c| matches!(c, std::path::Component::Normal(_)))
+            });
+        if !inside {
+            return Err(format!("Cover is outside of the fixture covers: {}", path).into());
+        }
+
+        match tokio::fs::read(path).await {
+            Ok(data) => Ok(Some((data, "image/jpeg".to_string()))),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+/// Provider configured through `METADATA_PROVIDER` (`openlibrary` or `fixture`)
+static METADATA_PROVIDER: Lazy<Arc<dyn MetadataProvider>> = Lazy::new(|| {
+    dotenv().ok();
+
+    match env::var("METADATA_PROVIDER").as_deref() {
+        Ok("fixture") => {
+            let path = env::var("METADATA_FIXTURE_PATH")
+                .unwrap_or_else(|_| "metadata_fixture.json".to_string());
+            Arc::new(FixtureMetadataProvider::new(path))
+        }
+        _ => {
+            let base_url = env::var("OPENLIBRARY_BASE_URL")
+                .unwrap_or_else(|_| "https://openlibrary.org".to_string());
+            let covers_url = env::var("OPENLIBRARY_COVERS_URL")
+                .unwrap_or_else(|_| "https://covers.openlibrary.org".to_string());
+            Arc::new(OpenLibraryProvider::new(&base_url, &covers_url))
+        }
+    }
+});
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MetadataField {
+    Title,
+    Authors,
+    Publisher,
+    PublishedDate,
+    Isbn,
+    Cover,
+    Description,
+    Language,
+    Series,
+    SeriesIndex,
+    Rating,
+    Tags,
+}
+
+impl MetadataField {
+    /// Every field, in the order they are listed in a book's history
+    pub const ALL: [MetadataField; 12] = [
+        MetadataField::Title,
+        MetadataField::Authors,
+        MetadataField::Publisher,
+        MetadataField::PublishedDate,
+        MetadataField::Isbn,
+        MetadataField::Cover,
+        MetadataField::Description,
+        MetadataField::Language,
+        MetadataField::Series,
+        MetadataField::SeriesIndex,
+        MetadataField::Rating,
+        MetadataField::Tags,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            MetadataField::Title => "title",
+            MetadataField::Authors => "authors",
+            MetadataField::Publisher => "publisher",
+            MetadataField::PublishedDate => "published_date",
+            MetadataField::Isbn => "isbn",
+            MetadataField::Cover => "cover",
+            MetadataField::Description => "description",
+            MetadataField::Language => "language",
+            MetadataField::Series => "series",
+            MetadataField::SeriesIndex => "series_index",
+            MetadataField::Rating => "rating",
+            MetadataField::Tags => "tags",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<MetadataField> {
+        MetadataField::ALL.into_iter().find(|f| f.as_str() == name)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum FieldValue {
+    Text(String),
+    List(Vec<String>),
+}
+
+/// A single field that differs between a book and a candidate
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FieldChange {
+    pub field: MetadataField,
+    pub current: Option<FieldValue>,
+    pub proposed: Option<FieldValue>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MetadataMatch {
+    pub candidate: MetadataCandidate,
+    pub changes: Vec<FieldChange>,
+}
+
+/// Current metadata of a book, including the related rows
+#[derive(Debug)]
+pub struct BookSnapshot {
+    pub book: Books,
+    pub authors: Vec<String>,
+    pub publisher: Option<String>,
+    /// Where the current cover was downloaded from, if it came from a provider
+    pub cover_url: Option<String>,
+}
+
+/// Fields a candidate would change, fields it has no value for are left alone
+pub fn diff(current: &BookSnapshot, candidate: &MetadataCandidate) -> Vec<FieldChange> {
+    fn text(value: &Option<String>) -> Option<FieldValue> {
+        value.clone().map(FieldValue::Text)
+    }
+    // Differently formatted forms of the same ISBN are not a change, invalid ones are ignored
+    fn isbn_text(value: &Option<String>) -> Option<FieldValue> {
+        value
+            .as_deref()
+            .and_then(isbn::normalize)
+            .map(FieldValue::Text)
+    }
+
+    let mut changes = Vec::new();
+    let mut push = |field, current: Option<FieldValue>, proposed: Option<FieldValue>| {
+        if proposed.is_some() && current != proposed {
+            changes.push(FieldChange {
+                field,
+                current,
+                proposed,
+            });
+        }
+    };
+
+    push(
+        MetadataField::Title,
+        Some(FieldValue::Text(current.book.title.clone())),
+        Some(FieldValue::Text(candidate.title.clone())),
+    );
+    push(
+        MetadataField::Authors,
+        (!current.authors.is_empty()).then(|| FieldValue::List(current.authors.clone())),
+        (!candidate.authors.is_empty()).then(|| FieldValue::List(candidate.authors.clone())),
+    );
+    push(
+        MetadataField::Publisher,
+        text(&current.publisher),
+        text(&candidate.publisher),
+    );
+    push(
+        MetadataField::PublishedDate,
+        text(&current.book.published_date),
+        text(&candidate.published_date),
+    );
+    push(
+        MetadataField::Isbn,
+        isbn_text(&current.book.isbn),
+        isbn_text(&candidate.isbn),
+    );
+    // The stored cover is a local file, only a cover from another URL is a change
+    if candidate.cover_url.is_some() && candidate.cover_url != current.cover_url {
+        changes.push(FieldChange {
+            field: MetadataField::Cover,
+            current: text(&current.book.cover_image_path),
+            proposed: text(&candidate.cover_url),
+        });
+    }
+
+    changes
+}
+
+pub struct MetadataService {
+    provider: Arc<dyn MetadataProvider>,
+}
+
+impl MetadataService {
+    pub fn new(provider: Arc<dyn MetadataProvider>) -> Self {
+        MetadataService { provider }
+    }
+
+    pub fn from_env() -> Self {
+        MetadataService::new(METADATA_PROVIDER.clone())
+    }
+
+    pub async fn snapshot(&self, book_id: i32) -> Result<Option<BookSnapshot>, MetadataError> {
+        let Some(book) = BookRepo::new().await.get_by_id(book_id).await? else {
+            return Ok(None);
+        };
+
+        let authors = BookAuthorRepo::new()
+            .await
+            .get_authors_by_book(book_id)
+            .await?
+            .unwrap_or_default()
+            .into_iter()
+            .map(|a| a.name)
+            .collect();
+
+        let publisher = match book.publisher_id {
+            Some(id) => PublisherRepo::new()
+                .await
+                .get_by_id(id)
+                .await?
+                .map(|p| p.name),
+            None => None,
+        };
+
+        let cover_url = BookMetadataSourceRepo::new()
+            .await
+            .get_by_book(book_id)
+            .await?
+            .unwrap_or_default()
+            .into_iter()
+            .find(|s| s.field == MetadataField::Cover.as_str())
+            .map(|s| s.source);
+
+        Ok(Some(BookSnapshot {
+            book,
+            authors,
+            publisher,
+            cover_url,
+        }))
+    }
+
+    /// Candidates for a book with their diffs, by ISBN first and then title and author
+    pub async fn find_matches(
+        &self,
+        book_id: i32,
+        isbn: Option<&str>,
+        title: Option<&str>,
+        author: Option<&str>,
+    ) -> Result<Option<Vec<MetadataMatch>>, MetadataError> {
+        let Some(snapshot) = self.snapshot(book_id).await? else {
+            return Ok(None);
+        };
+
+        let mut candidates = Vec::new();
+        if let Some(isbn) = isbn.or(snapshot.book.isbn.as_deref()) {
+            candidates = self.provider.search_by_isbn(isbn).await?;
+        }
+
+        if candidates.is_empty() {
+            let title = title.unwrap_or(&snapshot.book.title);
+            let author = author.or(snapshot.authors.first().map(|a| a.as_str()));
+            candidates = self.provider.search_by_title_author(title, author).await?;
+        }
+
+        Ok(Some(
+            candidates
+                .into_iter()
+                .map(|candidate| MetadataMatch {
+                    changes: diff(&snapshot, &candidate),
+                    candidate,
+                })
+                .collect(),
+        ))
+    }
+
+    /// Looks a candidate up again by provider id, or else the first match of an ISBN
+    pub async fn resolve(
+        &self,
+        id: Option<&str>,
+        isbn: Option<&str>,
+    ) -> Result<Option<MetadataCandidate>, MetadataError> {
+        match (id, isbn) {
+            (Some(id), _) => self.provider.get_by_id(id).await,
+            (None, Some(isbn)) => Ok(self.provider.search_by_isbn(isbn).await?.into_iter().next()),
+            (None, None) => Ok(None),
+        }
+    }
+
+    /// Applies a candidate, or only its `fields`, as one history batch.
+    /// The cover is downloaded first, the book is only changed once nothing can fail anymore.
+    pub async fn apply_match(
+        &self,
+        book_id: i32,
+        candidate: &MetadataCandidate,
+        fields: Option<&[MetadataField]>,
+        user_id: Option<i32>,
+    ) -> Result<Option<Vec<FieldChange>>, MetadataError> {
+        let Some(snapshot) = self.snapshot(book_id).await? else {
+            return Ok(None);
+        };
+
+        let mut changes: Vec<FieldChange> = diff(&snapshot, candidate)
+            .into_iter()
+            .filter(|c| fields.map(|f| f.contains(&c.field)).unwrap_or(true))
+            .collect();
+
+        let mut cover_path = None;
+        if changes.iter().any(|c| c.field == MetadataField::Cover) {
+            match self.provider.fetch_cover(candidate).await? {
+                Some((data, mime_type)) => {
+                    // A new file name keeps the previous cover around for reverts
+                    let name = format!("book_{}_{}", book_id, Uuid::new_v4().simple());
+                    cover_path = Some(store_cover_to_disk(&data, &mime_type, &name).await?);
+                }
+                None => changes.retain(|c| c.field != MetadataField::Cover),
+            }
+        }
+
+        let has = |field| changes.iter().any(|c: &FieldChange| c.field == field);
+        let canonical_isbn = candidate.isbn.as_deref().and_then(isbn::normalize);
+        let set_authors = has(MetadataField::Authors);
+        let set_publisher = has(MetadataField::Publisher);
+        let mut update = UpdateBook {
+            title: has(MetadataField::Title).then_some(candidate.title.as_str()),
+            published_date: if has(MetadataField::PublishedDate) {
+                candidate.published_date.as_deref()
+            } else {
+                None
+            },
+            isbn: if has(MetadataField::Isbn) {
+                canonical_isbn.as_deref()
+            } else {
+                None
+            },
+            cover_image_path: cover_path.as_deref(),
+            ..Default::default()
+        };
+        // Remembered so the same cover isn't proposed again
+        let cover_source = cover_path.as_ref().and(candidate.cover_url.as_deref());
+        let batch = HistoryBatch::new(SOURCE_PROVIDER, user_id);
+
+        write_transaction(|conn| {
+            async move {
+                let history = HistoryService::new().await;
+                let before = history
+                    .current_values_in(conn, book_id)
+                    .await?
+                    .unwrap_or_default();
+
+                if set_authors {
+                    let author_repo = AuthorRepo::new().await;
+                    let mut author_ids = Vec::new();
+                    for name in &candidate.authors {
+                        author_ids.push(author_repo.get_or_create_in(conn, name).await?.author_id);
+                    }
+                    BookAuthorRepo::new()
+                        .await
+                        .replace_authors_in(conn, book_id, &author_ids)
+                        .await?;
+                }
+                if let Some(name) = candidate.publisher.as_deref().filter(|_| set_publisher) {
+                    let publisher = PublisherRepo::new()
+                        .await
+                        .get_or_create_in(conn, name)
+                        .await?;
+                    update.publisher_id = Some(publisher.publisher_id);
+                }
+
+                // Diesel rejects an update without any column to set
+                if update.title.is_some()
+                    || update.published_date.is_some()
+                    || update.publisher_id.is_some()
+                    || update.isbn.is_some()
+                    || update.cover_image_path.is_some()
+                {
+                    BookRepo::new()
+                        .await
+                        .update_in(conn, book_id, update)
+                        .await?;
+                }
+                if let Some(url) = cover_source {
+                    BookMetadataSourceRepo::new()
+                        .await
+                        .set_sources_in(conn, book_id, &[(MetadataField::Cover.as_str(), url)])
+                        .await?;
+                }
+
+                history
+                    .record_changes_in(conn, book_id, &batch, &before)
+                    .await
+            }
+            .scope_boxed()
+        })
+        .await?;
+
+        Ok(Some(changes))
+    }
+}
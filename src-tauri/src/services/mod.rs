@@ -1,7 +1,21 @@
+pub mod api_key_service;
+pub mod audit_service;
 pub mod authentication_service;
 pub mod book_service;
 pub mod cover_service;
+pub mod history_service;
+pub mod library_access_service;
 pub mod library_service;
+pub mod mail_service;
 pub mod metadata_service;
+pub mod opds2_service;
 pub mod opds_service;
+pub mod page_service;
+pub mod password_service;
+pub mod proxy_auth_service;
+pub mod registration_service;
+pub mod session_service;
+pub mod throttle_service;
 pub mod token_service;
+pub mod two_factor_service;
+pub mod user_service;
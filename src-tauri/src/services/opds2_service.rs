@@ -0,0 +1,193 @@
+use crate::{
+    data::repos::{
+        implementors::{
+            author_repo::AuthorRepo,
+            book_repo::{BookFilter, BookOrder},
+            library_repo::LibraryRepo,
+            publisher_repo::PublisherRepo,
+        },
+        traits::repository::Repository,
+    },
+    opds::{
+        opds2::{self, Feed, Publication},
+        search::SearchQuery,
+    },
+    services::opds_service::{OpdsError, OpdsService},
+};
+
+/// Number of recently added books shown on the catalog root
+const ROOT_GROUP_SIZE: usize = 10;
+
+/// Builds the OPDS 2.0 catalog, sharing its queries with the Atom catalog
+pub struct Opds2Service {
+    opds: OpdsService,
+}
+
+impl Opds2Service {
+    pub async fn new() -> Self {
+        Opds2Service {
+            opds: OpdsService::new().await,
+        }
+    }
+
+    /// Only lists books the user can see, see [`OpdsService::for_user`]
+    pub fn for_user(mut self, user_id: i32) -> Self {
+        self.opds = self.opds.for_user(user_id);
+        self
+    }
+
+    pub async fn root(&self) -> Result<Feed, OpdsError> {
+        let (mut recent, _) = self
+            .opds
+            .load_books(BookFilter::All, BookOrder::Newest, 1)
+            .await?;
+        recent.truncate(ROOT_GROUP_SIZE);
+        Ok(opds2::root_feed(&recent))
+    }
+
+    pub async fn recent(&self, page: i64) -> Result<Feed, OpdsError> {
+        let (books, page) = self
+            .opds
+            .load_books(BookFilter::All, BookOrder::Newest, page)
+            .await?;
+        Ok(opds2::publications_feed(
+            "Recently added",
+            "/recent",
+            &books,
+            page,
+        ))
+    }
+
+    pub async fn books(&self, order: BookOrder, page: i64) -> Result<Feed, OpdsError> {
+        let (books, page) = self.opds.load_books(BookFilter::All, order, page).await?;
+        Ok(opds2::sorted_feed(
+            "All books",
+            "/books",
+            order,
+            &books,
+            page,
+        ))
+    }
+
+    pub async fn authors(&self) -> Result<Feed, OpdsError> {
+        let items: Vec<(String, String)> = self
+            .opds
+            .sorted_authors()
+            .await?
+            .into_iter()
+            .map(|a| (a.name, format!("/authors/{}", a.author_id)))
+            .collect();
+        Ok(opds2::navigation_feed("Authors", "/authors", &items))
+    }
+
+    /// Returns `None` if the author doesn't exist
+    pub async fn author_books(
+        &self,
+        author_id: i32,
+        order: BookOrder,
+        page: i64,
+    ) -> Result<Option<Feed>, OpdsError> {
+        let Some(author) = AuthorRepo::new().await.get_by_id(author_id).await? else {
+            return Ok(None);
+        };
+        let (books, page) = self
+            .opds
+            .load_books(BookFilter::Author(author_id), order, page)
+            .await?;
+        Ok(Some(opds2::sorted_feed(
+            &author.name,
+            &format!("/authors/{}", author_id),
+            order,
+            &books,
+            page,
+        )))
+    }
+
+    pub async fn publishers(&self) -> Result<Feed, OpdsError> {
+        let items: Vec<(String, String)> = self
+            .opds
+            .sorted_publishers()
+            .await?
+            .into_iter()
+            .map(|p| (p.name, format!("/publishers/{}", p.publisher_id)))
+            .collect();
+        Ok(opds2::navigation_feed("Publishers", "/publishers", &items))
+    }
+
+    /// Returns `None` if the publisher doesn't exist
+    pub async fn publisher_books(
+        &self,
+        publisher_id: i32,
+        order: BookOrder,
+        page: i64,
+    ) -> Result<Option<Feed>, OpdsError> {
+        let Some(publisher) = PublisherRepo::new().await.get_by_id(publisher_id).await? else {
+            return Ok(None);
+        };
+        let (books, page) = self
+            .opds
+            .load_books(BookFilter::Publisher(publisher_id), order, page)
+            .await?;
+        Ok(Some(opds2::sorted_feed(
+            &publisher.name,
+            &format!("/publishers/{}", publisher_id),
+            order,
+            &books,
+            page,
+        )))
+    }
+
+    pub async fn libraries(&self) -> Result<Feed, OpdsError> {
+        let items: Vec<(String, String)> = self
+            .opds
+            .sorted_libraries()
+            .await?
+            .into_iter()
+            .map(|l| (l.name, format!("/libraries/{}", l.library_id)))
+            .collect();
+        Ok(opds2::navigation_feed("Libraries", "/libraries", &items))
+    }
+
+    /// Returns `None` if the library doesn't exist
+    pub async fn library_books(
+        &self,
+        library_id: i32,
+        order: BookOrder,
+        page: i64,
+    ) -> Result<Option<Feed>, OpdsError> {
+        let Some(library) = LibraryRepo::new().await.get_by_id(library_id).await? else {
+            return Ok(None);
+        };
+        let (books, page) = self
+            .opds
+            .load_books(BookFilter::Library(library_id), order, page)
+            .await?;
+        Ok(Some(opds2::sorted_feed(
+            &library.name,
+            &format!("/libraries/{}", library_id),
+            order,
+            &books,
+            page,
+        )))
+    }
+
+    pub async fn search(&self, query: &SearchQuery, page: i64) -> Result<Feed, OpdsError> {
+        let (books, page) = self.opds.load_search(query, page).await?;
+        let path = query.path_with("query");
+        Ok(opds2::publications_feed(
+            "Search results",
+            &path,
+            &books,
+            page,
+        ))
+    }
+
+    /// Returns `None` if the book doesn't exist
+    pub async fn publication(&self, book_id: i32) -> Result<Option<Publication>, OpdsError> {
+        Ok(self
+            .opds
+            .book_details(book_id)
+            .await?
+            .map(|details| opds2::publication(&details)))
+    }
+}
@@ -1 +1,580 @@
+use std::{collections::HashSet, env};
 
+use dotenvy::dotenv;
+use once_cell::sync::Lazy;
+
+use crate::{
+    data::{
+        models::{
+            authors::Authors, book_files::BookFiles, books::Books, libraries::Library,
+            publishers::Publishers,
+        },
+        repos::{
+            implementors::{
+                author_repo::AuthorRepo,
+                book_author_repo::BookAuthorRepo,
+                book_file_repo::BookFileRepo,
+                book_identifier_repo::BookIdentifierRepo,
+                book_repo::{BookFacets, BookFilter, BookOrder, BookRepo, BookScope},
+                book_tag_repo::BookTagRepo,
+                library_repo::LibraryRepo,
+                publisher_repo::PublisherRepo,
+                reading_progress_repo::ReadingProgressRepo,
+            },
+            traits::repository::Repository,
+        },
+    },
+    opds::{
+        acquisition::{books_feed, image_mime_type, BookDetails, LastRead, Page},
+        facets::{faceted_feed, FacetSelection},
+        navigation,
+        search::{self, SearchQuery},
+    },
+    parsers::isbn,
+    services::{
+        library_access_service::LibraryAccessService,
+        page_service::{downscale_png, PageService},
+    },
+};
+
+pub type OpdsError = Box<dyn std::error::Error + Send + Sync>;
+
+const THUMBNAIL_WIDTH: u32 = 240;
+
+/// Number of books per acquisition feed page, configurable through `OPDS_PAGE_SIZE`
+static PAGE_SIZE: Lazy<i64> = Lazy::new(|| {
+    dotenv().ok();
+
+    env::var("OPDS_PAGE_SIZE")
+        .ok()
+        .and_then(|v| v.parse::<i64>().ok())
+        .filter(|v| *v > 0)
+        .unwrap_or(50)
+});
+
+/// A file of a book as it is served for download
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BookDownload {
+    pub path: String,
+    pub format: String,
+}
+
+/// Builds the OPDS catalog feeds as Atom XML
+pub struct OpdsService {
+    /// Whose reading positions and visible libraries the feeds use
+    user_id: Option<i32>,
+}
+
+impl OpdsService {
+    pub async fn new() -> Self {
+        OpdsService { user_id: None }
+    }
+
+    pub fn for_user(mut self, user_id: i32) -> Self {
+        self.user_id = Some(user_id);
+        self
+    }
+
+    /// The libraries whose books feeds list, every library without a user
+    async fn scope(&self) -> Result<BookScope, OpdsError> {
+        match self.user_id {
+            Some(user_id) => LibraryAccessService::new().await.scope(user_id).await,
+            None => Ok(BookScope::All),
+        }
+    }
+
+    /// The book if it exists and the user can see it
+    async fn book(&self, book_id: i32) -> Result<Option<Books>, OpdsError> {
+        let Some(book) = BookRepo::new().await.get_by_id(book_id).await? else {
+            return Ok(None);
+        };
+        Ok(self.scope().await?.contains(&book).then_some(book))
+    }
+
+    /// Files a book can be downloaded as, the main file is used when no formats were recorded
+    pub async fn files(&self, book: &Books) -> Result<Vec<BookDownload>, OpdsError> {
+        let files: Vec<BookFiles> = BookFileRepo::new()
+            .await
+            .get_by_book(book.book_id)
+            .await?
+            .unwrap_or_default();
+        if !files.is_empty() {
+            return Ok(files
+                .into_iter()
+                .map(|f| BookDownload {
+                    path: f.file_path,
+                    format: f.format.to_lowercase(),
+                })
+                .collect());
+        }
+
+        let Some(path) = &book.file_path else {
+            return Ok(Vec::new());
+        };
+        let format = book
+            .file_type
+            .clone()
+            .or_else(|| {
+                std::path::Path::new(path)
+                    .extension()
+                    .map(|e| e.to_string_lossy().to_string())
+            })
+            .unwrap_or_default()
+            .to_lowercase();
+        Ok(vec![BookDownload {
+            path: path.clone(),
+            format,
+        }])
+    }
+
+    async fn details(&self, books: Vec<Books>) -> Result<Vec<BookDetails>, OpdsError> {
+        let book_author_repo = BookAuthorRepo::new().await;
+        let book_tag_repo = BookTagRepo::new().await;
+        let identifier_repo = BookIdentifierRepo::new().await;
+        let publisher_repo = PublisherRepo::new().await;
+        let progress_repo = ReadingProgressRepo::new().await;
+        let page_service = PageService::new().await;
+
+        let mut result = Vec::with_capacity(books.len());
+        for book in books {
+            let authors = book_author_repo
+                .get_authors_by_book(book.book_id)
+                .await?
+                .unwrap_or_default()
+                .into_iter()
+                .map(|a| a.name)
+                .collect();
+            let tags = book_tag_repo
+                .get_tags_by_book(book.book_id)
+                .await?
+                .unwrap_or_default()
+                .into_iter()
+                .map(|t| t.name)
+                .collect();
+            let identifiers = identifier_repo
+                .get_by_book(book.book_id)
+                .await?
+                .unwrap_or_default()
+                .into_iter()
+                .map(|i| (i.scheme, i.value))
+                .collect();
+            let publisher = match book.publisher_id {
+                Some(id) => publisher_repo.get_by_id(id).await?.map(|p| p.name),
+                None => None,
+            };
+            let mut formats: Vec<String> = self
+                .files(&book)
+                .await?
+                .into_iter()
+                .map(|f| f.format)
+                .filter(|f| !f.is_empty())
+                .collect();
+            formats.dedup();
+
+            let page_count = page_service.page_count(&book).await?;
+            let last_read = match (self.user_id, page_count) {
+                (Some(user_id), Some(_)) => progress_repo
+                    .get_by_user_and_book(user_id, book.book_id)
+                    .await?
+                    .and_then(|p| {
+                        Some(LastRead {
+                            page: p.page_number?.max(1) as usize - 1,
+                            read_at: p.last_read_at,
+                        })
+                    }),
+                _ => None,
+            };
+
+            result.push(BookDetails {
+                book,
+                authors,
+                publisher,
+                tags,
+                identifiers,
+                formats,
+                page_count,
+                last_read,
+            });
+        }
+        Ok(result)
+    }
+
+    /// Loads the details of a single book, `None` if it doesn't exist
+    pub async fn book_details(&self, book_id: i32) -> Result<Option<BookDetails>, OpdsError> {
+        let Some(book) = self.book(book_id).await? else {
+            return Ok(None);
+        };
+        Ok(self.details(vec![book]).await?.pop())
+    }
+
+    /// Loads one page of books with their details, `page` starts at 1
+    pub async fn load_books(
+        &self,
+        filter: BookFilter,
+        order: BookOrder,
+        page: i64,
+    ) -> Result<(Vec<BookDetails>, Page), OpdsError> {
+        self.load_faceted(filter, &BookFacets::default(), order, page)
+            .await
+    }
+
+    /// Like [`Self::load_books`], narrowed down by facets
+    pub async fn load_faceted(
+        &self,
+        filter: BookFilter,
+        facets: &BookFacets,
+        order: BookOrder,
+        page: i64,
+    ) -> Result<(Vec<BookDetails>, Page), OpdsError> {
+        let size = *PAGE_SIZE;
+        let number = page.max(1);
+        let facets = BookFacets {
+            scope: self.scope().await?,
+            ..facets.clone()
+        };
+        let (books, total) = BookRepo::new()
+            .await
+            .get_page(filter, &facets, order, (number - 1) * size, size)
+            .await?;
+
+        let books = self.details(books).await?;
+        let page = Page {
+            number,
+            size,
+            total,
+        };
+        Ok((books, page))
+    }
+
+    /// Builds one page of an acquisition feed with facet links
+    async fn books_page(
+        &self,
+        id: &str,
+        title: &str,
+        path: &str,
+        filter: BookFilter,
+        selection: &FacetSelection,
+        page: i64,
+    ) -> Result<String, OpdsError> {
+        // The read status refers to the user the feed is built for
+        let mut selection = selection.clone();
+        selection.facets.user_id = self.user_id;
+
+        let (books, page) = self
+            .load_faceted(filter, &selection.facets, selection.order, page)
+            .await?;
+        let values = BookRepo::new()
+            .await
+            .facet_values(filter, &self.scope().await?)
+            .await?;
+        Ok(faceted_feed(id, title, path, &selection, &values, &books, page).build())
+    }
+
+    /// Authors of the visible books, by sort name
+    pub async fn sorted_authors(&self) -> Result<Vec<Authors>, OpdsError> {
+        let mut authors = AuthorRepo::new().await.get_all().await?.unwrap_or_default();
+        let scope = self.scope().await?;
+        if scope != BookScope::All {
+            let ids = BookRepo::new().await.author_ids(&scope).await?;
+            authors.retain(|a| ids.contains(&a.author_id));
+        }
+        authors.sort_by_key(|a| a.sort_name.as_deref().unwrap_or(&a.name).to_lowercase());
+        Ok(authors)
+    }
+
+    pub async fn sorted_publishers(&self) -> Result<Vec<Publishers>, OpdsError> {
+        let mut publishers = PublisherRepo::new()
+            .await
+            .get_all()
+            .await?
+            .unwrap_or_default();
+        let scope = self.scope().await?;
+        if scope != BookScope::All {
+            let ids = BookRepo::new().await.publisher_ids(&scope).await?;
+            publishers.retain(|p| ids.contains(&p.publisher_id));
+        }
+        publishers.sort_by_key(|p| p.name.to_lowercase());
+        Ok(publishers)
+    }
+
+    pub async fn sorted_libraries(&self) -> Result<Vec<Library>, OpdsError> {
+        let mut libraries = match self.user_id {
+            Some(user_id) => LibraryAccessService::new().await.libraries(user_id).await?,
+            None => LibraryRepo::new()
+                .await
+                .get_all()
+                .await?
+                .unwrap_or_default(),
+        };
+        libraries.sort_by_key(|l| l.name.to_lowercase());
+        Ok(libraries)
+    }
+
+    pub async fn root(&self) -> Result<String, OpdsError> {
+        Ok(navigation::root_feed().build())
+    }
+
+    pub async fn recent(&self, page: i64) -> Result<String, OpdsError> {
+        let (books, page) = self
+            .load_books(BookFilter::All, BookOrder::Newest, page)
+            .await?;
+        Ok(books_feed(
+            "urn:stellaron:recent",
+            "Recently added",
+            "/recent",
+            &books,
+            page,
+        )
+        .build())
+    }
+
+    pub async fn all_books(
+        &self,
+        selection: &FacetSelection,
+        page: i64,
+    ) -> Result<String, OpdsError> {
+        self.books_page(
+            "urn:stellaron:books",
+            "All books",
+            "/books",
+            BookFilter::All,
+            selection,
+            page,
+        )
+        .await
+    }
+
+    pub async fn authors(&self) -> Result<String, OpdsError> {
+        let authors = self.sorted_authors().await?;
+        Ok(navigation::authors_feed(&authors).build())
+    }
+
+    /// Returns `None` if the author doesn't exist
+    pub async fn author_books(
+        &self,
+        author_id: i32,
+        selection: &FacetSelection,
+        page: i64,
+    ) -> Result<Option<String>, OpdsError> {
+        let Some(author) = AuthorRepo::new().await.get_by_id(author_id).await? else {
+            return Ok(None);
+        };
+        let feed = self
+            .books_page(
+                &format!("urn:stellaron:author:{}", author_id),
+                &author.name,
+                &format!("/authors/{}", author_id),
+                BookFilter::Author(author_id),
+                selection,
+                page,
+            )
+            .await?;
+        Ok(Some(feed))
+    }
+
+    pub async fn publishers(&self) -> Result<String, OpdsError> {
+        let publishers = self.sorted_publishers().await?;
+        Ok(navigation::publishers_feed(&publishers).build())
+    }
+
+    /// Returns `None` if the publisher doesn't exist
+    pub async fn publisher_books(
+        &self,
+        publisher_id: i32,
+        selection: &FacetSelection,
+        page: i64,
+    ) -> Result<Option<String>, OpdsError> {
+        let Some(publisher) = PublisherRepo::new().await.get_by_id(publisher_id).await? else {
+            return Ok(None);
+        };
+        let feed = self
+            .books_page(
+                &format!("urn:stellaron:publisher:{}", publisher_id),
+                &publisher.name,
+                &format!("/publishers/{}", publisher_id),
+                BookFilter::Publisher(publisher_id),
+                selection,
+                page,
+            )
+            .await?;
+        Ok(Some(feed))
+    }
+
+    pub async fn libraries(&self) -> Result<String, OpdsError> {
+        let libraries = self.sorted_libraries().await?;
+        Ok(navigation::libraries_feed(&libraries).build())
+    }
+
+    /// Returns `None` if the library doesn't exist
+    pub async fn library_books(
+        &self,
+        library_id: i32,
+        selection: &FacetSelection,
+        page: i64,
+    ) -> Result<Option<String>, OpdsError> {
+        let Some(library) = LibraryRepo::new().await.get_by_id(library_id).await? else {
+            return Ok(None);
+        };
+        if let BookScope::Libraries(ids) = self.scope().await? {
+            if !ids.contains(&library_id) {
+                return Ok(None);
+            }
+        }
+        let feed = self
+            .books_page(
+                &format!("urn:stellaron:library:{}", library_id),
+                &library.name,
+                &format!("/libraries/{}", library_id),
+                BookFilter::Library(library_id),
+                selection,
+                page,
+            )
+            .await?;
+        Ok(Some(feed))
+    }
+
+    /// Returns the file of a book in the given format, `None` if there is no such file
+    pub async fn download(
+        &self,
+        book_id: i32,
+        format: &str,
+    ) -> Result<Option<BookDownload>, OpdsError> {
+        let Some(book) = self.book(book_id).await? else {
+            return Ok(None);
+        };
+        Ok(self
+            .files(&book)
+            .await?
+            .into_iter()
+            .find(|f| f.format.eq_ignore_ascii_case(format)))
+    }
+
+    /// Returns the stored cover of a book, `None` if it has none
+    pub async fn cover(&self, book_id: i32) -> Result<Option<String>, OpdsError> {
+        Ok(self.book(book_id).await?.and_then(|b| b.cover_image_path))
+    }
+
+    /// A PNG cover scaled down for grid views, `None` if the book has no PNG cover
+    pub async fn thumbnail(&self, book_id: i32) -> Result<Option<Vec<u8>>, OpdsError> {
+        let Some(path) = self.cover(book_id).await? else {
+            return Ok(None);
+        };
+        if image_mime_type(&path) != "image/png" {
+            return Ok(None);
+        }
+        let data = match tokio::fs::read(&path).await {
+            Ok(data) => data,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let thumbnail = tokio::task::spawn_blocking(move || {
+            downscale_png(&data, THUMBNAIL_WIDTH).map(|scaled| scaled.unwrap_or(data))
+        })
+        .await??;
+        Ok(Some(thumbnail))
+    }
+
+    pub async fn opensearch(&self) -> Result<String, OpdsError> {
+        Ok(search::description())
+    }
+
+    /// Books written by any author whose name contains `name`
+    async fn books_by_author_name(&self, name: &str) -> Result<Vec<Books>, OpdsError> {
+        let book_author_repo = BookAuthorRepo::new().await;
+        let authors = AuthorRepo::new()
+            .await
+            .search_by_name(name)
+            .await?
+            .unwrap_or_default();
+
+        let mut books = Vec::new();
+        for author in authors {
+            books.extend(
+                book_author_repo
+                    .get_books_by_author(author.author_id)
+                    .await?
+                    .unwrap_or_default(),
+            );
+        }
+        Ok(books)
+    }
+
+    /// Books matching every part of the query, sorted by title
+    async fn search_books(&self, query: &SearchQuery) -> Result<Vec<Books>, OpdsError> {
+        let book_repo = BookRepo::new().await;
+
+        // Each part narrows down the books found by the previous ones
+        let mut found: Option<Vec<Books>> = None;
+        let mut narrow = |books: Vec<Books>| {
+            found = Some(match found.take() {
+                None => books,
+                Some(previous) => {
+                    let ids: HashSet<i32> = books.iter().map(|b| b.book_id).collect();
+                    previous
+                        .into_iter()
+                        .filter(|b| ids.contains(&b.book_id))
+                        .collect()
+                }
+            });
+        };
+
+        if let Some(terms) = &query.terms {
+            let mut books = book_repo.search_by_title(terms).await?.unwrap_or_default();
+            books.extend(self.books_by_author_name(terms).await?);
+            // Partial digit matches would find unrelated ISBNs, only complete ones are looked up
+            if isbn::normalize(terms).is_some() {
+                books.extend(book_repo.search_by_isbn(terms).await?.unwrap_or_default());
+            }
+            narrow(books);
+        }
+        if let Some(author) = &query.author {
+            narrow(self.books_by_author_name(author).await?);
+        }
+        if let Some(title) = &query.title {
+            narrow(book_repo.search_by_title(title).await?.unwrap_or_default());
+        }
+
+        let scope = self.scope().await?;
+        let mut books = found.unwrap_or_default();
+        books.retain(|b| scope.contains(b));
+        books.sort_by(|a, b| {
+            a.title
+                .to_lowercase()
+                .cmp(&b.title.to_lowercase())
+                .then(a.book_id.cmp(&b.book_id))
+        });
+        books.dedup_by_key(|b| b.book_id);
+        Ok(books)
+    }
+
+    /// Loads one page of search results with their details, `page` starts at 1
+    pub async fn load_search(
+        &self,
+        query: &SearchQuery,
+        page: i64,
+    ) -> Result<(Vec<BookDetails>, Page), OpdsError> {
+        let size = *PAGE_SIZE;
+        let number = page.max(1);
+        let books = self.search_books(query).await?;
+        let total = books.len() as i64;
+
+        let books = books
+            .into_iter()
+            .skip(((number - 1) * size) as usize)
+            .take(size as usize)
+            .collect();
+        let books = self.details(books).await?;
+        let page = Page {
+            number,
+            size,
+            total,
+        };
+        Ok((books, page))
+    }
+
+    /// Builds one page of search results
+    pub async fn search(&self, query: &SearchQuery, page: i64) -> Result<String, OpdsError> {
+        let (books, page) = self.load_search(query, page).await?;
+        Ok(search::search_feed(query, &books, page))
+    }
+}
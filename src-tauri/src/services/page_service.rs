@@ -0,0 +1,251 @@
+use std::{
+    collections::HashMap,
+    io::Cursor,
+    ops::Range,
+    sync::{Arc, Mutex},
+    time::SystemTime,
+};
+
+use once_cell::sync::Lazy;
+
+use crate::{
+    data::{
+        models::{books::Books, reading_progress::NewReadingProgress, users::Role},
+        repos::{
+            implementors::{
+                book_repo::BookRepo, reading_progress_repo::ReadingProgressRepo,
+                user_repo::UserRepo,
+            },
+            traits::repository::Repository,
+        },
+    },
+    handlers::{comic_handler, pdf_handler},
+    services::opds_service::{BookDownload, OpdsService},
+};
+
+pub type PageError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Where the pages of a file are, so serving a page doesn't scan the whole file again
+#[derive(Debug)]
+enum PageIndex {
+    /// Entry names of a comic archive, in reading order
+    Comic(Vec<String>),
+    /// Byte ranges of the page JPEGs of a scanned PDF
+    Pdf(Vec<Range<usize>>),
+}
+
+impl PageIndex {
+    fn len(&self) -> usize {
+        match self {
+            PageIndex::Comic(names) => names.len(),
+            PageIndex::Pdf(ranges) => ranges.len(),
+        }
+    }
+}
+
+/// A page index with the modification time of the file it was built from
+type CachedIndex = (SystemTime, Arc<PageIndex>);
+
+/// Page indexes by file path, indexing a PDF reads all of it
+static PAGE_INDEXES: Lazy<Mutex<HashMap<String, CachedIndex>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// A page image with its media type
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PageImage {
+    pub data: Vec<u8>,
+    pub mime_type: &'static str,
+}
+
+/// Serves single pages of comic archives and scanned PDFs
+pub struct PageService;
+
+impl PageService {
+    pub async fn new() -> Self {
+        PageService
+    }
+
+    /// The file pages are read from, comic archives are preferred over PDFs
+    async fn source(&self, book: &Books) -> Result<Option<BookDownload>, PageError> {
+        let files = OpdsService::new().await.files(book).await?;
+        Ok(["cbz", "pdf"]
+            .iter()
+            .find_map(|format| files.iter().find(|f| f.format == *format))
+            .cloned())
+    }
+
+    async fn index(&self, file: &BookDownload) -> Result<Arc<PageIndex>, PageError> {
+        let modified = tokio::fs::metadata(&file.path).await?.modified()?;
+        if let Some((cached_at, index)) = PAGE_INDEXES.lock().unwrap().get(&file.path) {
+            if *cached_at == modified {
+                return Ok(index.clone());
+            }
+        }
+
+        let path = file.path.clone();
+        let index = Arc::new(match file.format.as_str() {
+            "cbz" => PageIndex::Comic(
+                tokio::task::spawn_blocking(move || comic_handler::page_names(&path)).await??,
+            ),
+            _ => PageIndex::Pdf(
+                tokio::task::spawn_blocking(move || pdf_handler::streamable_pages(&path)).await??,
+            ),
+        });
+        PAGE_INDEXES
+            .lock()
+            .unwrap()
+            .insert(file.path.clone(), (modified, index.clone()));
+        Ok(index)
+    }
+
+    /// Number of pages that can be streamed, `None` if the book can't be streamed
+    pub async fn page_count(&self, book: &Books) -> Result<Option<usize>, PageError> {
+        let Some(file) = self.source(book).await? else {
+            return Ok(None);
+        };
+        // A missing or unreadable file only means the book can't be streamed
+        match self.index(&file).await.map(|index| index.len()) {
+            Ok(0) => Ok(None),
+            Ok(count) => Ok(Some(count)),
+            Err(e) => {
+                eprintln!("Failed to count pages of {}: {}", file.path, e);
+                Ok(None)
+            }
+        }
+    }
+
+    /// Reads a page, `index` starts at 0. Only PNG pages are scaled to `max_width`.
+    pub async fn page(
+        &self,
+        book_id: i32,
+        index: usize,
+        max_width: Option<u32>,
+    ) -> Result<Option<PageImage>, PageError> {
+        let Some(book) = BookRepo::new().await.get_by_id(book_id).await? else {
+            return Ok(None);
+        };
+        let Some(file) = self.source(&book).await? else {
+            return Ok(None);
+        };
+
+        let path = file.path.clone();
+        let page = match &*self.index(&file).await? {
+            PageIndex::Comic(names) => match names.get(index).cloned() {
+                Some(name) => {
+                    let (data, mime_type) =
+                        tokio::task::spawn_blocking(move || comic_handler::read_page(&path, &name))
+                            .await??;
+                    Some(PageImage { data, mime_type })
+                }
+                None => None,
+            },
+            PageIndex::Pdf(ranges) => match ranges.get(index).cloned() {
+                Some(range) => Some(PageImage {
+                    data: tokio::task::spawn_blocking(move || pdf_handler::read_page(&path, range))
+                        .await??,
+                    mime_type: "image/jpeg",
+                }),
+                None => None,
+            },
+        };
+
+        match (page, max_width) {
+            (Some(page), Some(max_width)) if page.mime_type == "image/png" => {
+                let scaled = tokio::task::spawn_blocking(move || {
+                    match downscale_png(&page.data, max_width) {
+                        Ok(Some(data)) => Ok(PageImage { data, ..page }),
+                        Ok(None) => Ok(page),
+                        Err(e) => Err(e),
+                    }
+                })
+                .await??;
+                Ok(Some(scaled))
+            }
+            (page, _) => Ok(page),
+        }
+    }
+
+    /// Stores a served page as the reading position, except for guests
+    pub async fn record_progress(
+        &self,
+        user_id: i32,
+        book_id: i32,
+        index: usize,
+    ) -> Result<(), PageError> {
+        match UserRepo::new().await.get_by_id(user_id).await? {
+            Some(user) if user.role().allows(Role::User) => (),
+            _ => return Ok(()),
+        }
+        let Some(book) = BookRepo::new().await.get_by_id(book_id).await? else {
+            return Ok(());
+        };
+        let Some(count) = self.page_count(&book).await? else {
+            return Ok(());
+        };
+
+        let page_number = index as i32 + 1;
+        let position = page_number.to_string();
+        ReadingProgressRepo::new()
+            .await
+            .upsert(NewReadingProgress {
+                user_id,
+                book_id,
+                current_position: &position,
+                chapter_title: None,
+                page_number: Some(page_number),
+                progress_percentage: Some(page_number as f32 / count as f32 * 100.0),
+            })
+            .await?;
+        Ok(())
+    }
+}
+
+/// Scales a PNG down to `max_width`, `None` if it is already narrow enough
+pub fn downscale_png(data: &[u8], max_width: u32) -> Result<Option<Vec<u8>>, PageError> {
+    let mut decoder = png::Decoder::new(Cursor::new(data));
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+    let mut reader = decoder.read_info()?;
+    let mut buffer = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buffer)?;
+
+    let (width, height) = (info.width, info.height);
+    if max_width == 0 || width <= max_width {
+        return Ok(None);
+    }
+    let channels = info.color_type.samples();
+    let new_width = max_width;
+    let new_height = ((height as u64 * new_width as u64) / width as u64).max(1) as u32;
+
+    // Every target pixel is the average of the source pixels it covers
+    let mut pixels = Vec::with_capacity(new_width as usize * new_height as usize * channels);
+    for y in 0..new_height {
+        let y0 = (y as u64 * height as u64 / new_height as u64) as usize;
+        let y1 = (((y + 1) as u64 * height as u64 / new_height as u64) as usize).max(y0 + 1);
+        for x in 0..new_width {
+            let x0 = (x as u64 * width as u64 / new_width as u64) as usize;
+            let x1 = (((x + 1) as u64 * width as u64 / new_width as u64) as usize).max(x0 + 1);
+            // A block of a large page scaled to a small width can exceed u32
+            let mut sums = [0u64; 4];
+            for row in y0..y1 {
+                let line = &buffer[row * info.line_size..];
+                for column in x0..x1 {
+                    for (c, sum) in sums.iter_mut().enumerate().take(channels) {
+                        *sum += line[column * channels + c] as u64;
+                    }
+                }
+            }
+            let area = ((y1 - y0) * (x1 - x0)) as u64;
+            pixels.extend(sums[..channels].iter().map(|sum| (sum / area) as u8));
+        }
+    }
+
+    let mut out = Vec::new();
+    {
+        let mut encoder = png::Encoder::new(&mut out, new_width, new_height);
+        encoder.set_color(info.color_type);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(&pixels)?;
+    }
+    Ok(Some(out))
+}
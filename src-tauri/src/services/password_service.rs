@@ -0,0 +1,294 @@
+use std::{env, net::IpAddr, sync::Arc, time::Duration as StdDuration};
+
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use chrono::Duration;
+use dotenvy::dotenv;
+use once_cell::sync::Lazy;
+
+use crate::{
+    data::{
+        models::{password_resets::NewPasswordReset, users::UpdateUser, users::Users},
+        repos::{
+            implementors::{password_reset_repo::PasswordResetRepo, user_repo::UserRepo},
+            traits::repository::Repository,
+        },
+    },
+    services::{
+        authentication_service::AuthenticationService,
+        mail_service::{mailer_from_env, MailMessage, Mailer},
+        session_service::{db_time, hash_token, SessionService},
+        throttle_service::{LoginThrottle, ThrottlePolicy, Throttled},
+    },
+};
+
+pub type PasswordError = Box<dyn std::error::Error + Send + Sync>;
+
+pub const MIN_PASSWORD_LENGTH: usize = 8;
+
+/// Minutes a reset token can be used, `PASSWORD_RESET_EXPIRATION_MINUTES`
+static RESET_TOKEN_MINUTES: Lazy<i64> = Lazy::new(|| {
+    dotenv().ok();
+
+    env::var("PASSWORD_RESET_EXPIRATION_MINUTES")
+        .ok()
+        .and_then(|v| v.parse::<i64>().ok())
+        .filter(|v| *v > 0)
+        .unwrap_or(60)
+});
+
+/// Minutes before a user with a pending reset mail gets another one
+const RESEND_MINUTES: i64 = 5;
+
+/// Every reset request for an address counts, the first already delays the next one
+const RESET_ADDRESS_POLICY: ThrottlePolicy = ThrottlePolicy {
+    free_attempts: 0,
+    base_delay: StdDuration::from_secs(60),
+    max_delay: StdDuration::from_secs(60 * 60),
+    lockout_after: None,
+    lockout_for: StdDuration::ZERO,
+    forget_after: StdDuration::from_secs(60 * 60),
+};
+
+/// A client may ask for a few addresses before its requests are delayed
+const RESET_IP_POLICY: ThrottlePolicy = ThrottlePolicy {
+    free_attempts: 5,
+    ..RESET_ADDRESS_POLICY
+};
+
+/// Unauthenticated reset requests, so the route can't be used to flood a mailbox
+static RESET_THROTTLE: Lazy<LoginThrottle> =
+    Lazy::new(|| LoginThrottle::new(RESET_ADDRESS_POLICY, RESET_IP_POLICY));
+
+/// Client page reset links point to, the mail has the bare token without it
+static RESET_URL: Lazy<Option<String>> = Lazy::new(|| {
+    dotenv().ok();
+
+    env::var("PASSWORD_RESET_URL")
+        .ok()
+        .filter(|v| !v.is_empty())
+});
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PasswordChange {
+    Changed,
+    WrongPassword,
+    TooShort,
+    UserNotFound,
+    Throttled(Throttled),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PasswordReset {
+    Reset,
+    /// Unknown, expired or already used token
+    InvalidToken,
+    TooShort,
+}
+
+fn generate_reset_token() -> String {
+    let mut bytes = [0u8; 32];
+    OsRng.fill_bytes(&mut bytes);
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+pub struct PasswordService {
+    mailer: Option<Arc<dyn Mailer>>,
+}
+
+impl PasswordService {
+    pub fn new(mailer: Arc<dyn Mailer>) -> Self {
+        PasswordService {
+            mailer: Some(mailer),
+        }
+    }
+
+    /// Without a configured mailer reset mails can't be sent
+    pub fn from_env() -> Self {
+        PasswordService {
+            mailer: mailer_from_env(),
+        }
+    }
+
+    pub fn can_send_mail(&self) -> bool {
+        self.mailer.is_some()
+    }
+
+    /// Counts a reset request, `Err` while the address or the client asked too often
+    pub fn throttle_reset_request(&self, email: &str, ip: Option<IpAddr>) -> Result<(), Throttled> {
+        RESET_THROTTLE.check(email, ip)?;
+        RESET_THROTTLE.record_failure(email, ip);
+        Ok(())
+    }
+
+    async fn store_password(&self, user_id: i32, password: &str) -> Result<(), PasswordError> {
+        let password_hash = AuthenticationService::new()
+            .hash_password_async(password)
+            .await?;
+        UserRepo::new()
+            .await
+            .update(
+                user_id,
+                UpdateUser {
+                    username: None,
+                    email: None,
+                    role: None,
+                    password_hash: Some(&password_hash),
+                },
+            )
+            .await?;
+        Ok(())
+    }
+
+    /// Changes the password of a signed in user after checking the current one
+    pub async fn change_password(
+        &self,
+        user_id: i32,
+        current: &str,
+        new: &str,
+    ) -> Result<PasswordChange, PasswordError> {
+        let Some(user) = UserRepo::new().await.get_by_id(user_id).await? else {
+            return Ok(PasswordChange::UserNotFound);
+        };
+        // The current password is throttled like a sign-in, or a stolen token could guess it
+        let throttle = LoginThrottle::global();
+        if let Err(throttled) = throttle.check(&user.username, None) {
+            return Ok(PasswordChange::Throttled(throttled));
+        }
+        let verified = AuthenticationService::new()
+            .verify_password_async(current, &user.password_hash)
+            .await?;
+        if !verified {
+            throttle.record_failure(&user.username, None);
+            return Ok(PasswordChange::WrongPassword);
+        }
+        throttle.record_success(&user.username);
+        if new.chars().count() < MIN_PASSWORD_LENGTH {
+            return Ok(PasswordChange::TooShort);
+        }
+
+        self.store_password(user_id, new).await?;
+        Ok(PasswordChange::Changed)
+    }
+
+    /// Mails a reset token to the owner of the address, unknown addresses are ignored.
+    /// A pending token is kept, so asking again can't invalidate the mail the user has.
+    pub async fn request_reset(&self, email: &str) -> Result<(), PasswordError> {
+        let Some(user) = UserRepo::new().await.search_by_email(email.trim()).await? else {
+            return Ok(());
+        };
+        let resend_after = db_time(Duration::minutes(-RESEND_MINUTES));
+        let pending = PasswordResetRepo::new()
+            .await
+            .get_pending(user.user_id, &db_time(Duration::zero()))
+            .await?;
+        if pending.is_some_and(|reset| reset.created_at.is_some_and(|at| at > resend_after)) {
+            return Ok(());
+        }
+        self.send_reset(&user, false).await
+    }
+
+    /// Mails a reset token to a user on behalf of an admin, `false` if there is no such user
+    pub async fn request_reset_for_user(&self, user_id: i32) -> Result<bool, PasswordError> {
+        match UserRepo::new().await.get_by_id(user_id).await? {
+            Some(user) => {
+                self.send_reset(&user, true).await?;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    /// Scrambles the password, signs the user out and mails a reset token
+    pub async fn force_reset(&self, user_id: i32) -> Result<bool, PasswordError> {
+        let Some(user) = UserRepo::new().await.get_by_id(user_id).await? else {
+            return Ok(false);
+        };
+        // The user would be left without a working password
+        if !self.can_send_mail() {
+            return Err("No mailer is configured".into());
+        }
+        self.store_password(user_id, &generate_reset_token())
+            .await?;
+        SessionService::new().await.revoke_all(user_id).await?;
+        self.send_reset(&user, true).await?;
+        Ok(true)
+    }
+
+    /// Mails a new token, `replace` drops the user's pending ones
+    async fn send_reset(&self, user: &Users, replace: bool) -> Result<(), PasswordError> {
+        let Some(mailer) = &self.mailer else {
+            return Err("No mailer is configured".into());
+        };
+        let token = generate_reset_token();
+        let reset = NewPasswordReset {
+            user_id: user.user_id,
+            token_hash: &hash_token(&token),
+            expires_at: &db_time(Duration::minutes(*RESET_TOKEN_MINUTES)),
+        };
+        let repo = PasswordResetRepo::new().await;
+        if replace {
+            repo.replace_for_user(reset).await?;
+        } else {
+            repo.add(reset).await?;
+        }
+
+        let instructions = match RESET_URL.as_deref() {
+            Some(url) => format!("Open {}?token={} to choose a new password.", url, token),
+            None => format!("Use this code to choose a new password:\n\n{}", token),
+        };
+        let body = format!(
+            "A password reset was requested for the Stellaron account \"{}\".\n\n{}\n\n\
+             The code expires in {} minutes and works once. If you didn't ask for this, \
+             you can ignore this mail.",
+            user.username, instructions, *RESET_TOKEN_MINUTES
+        );
+        mailer
+            .send(&MailMessage {
+                to: user.email.clone(),
+                subject: "Reset your Stellaron password".to_string(),
+                body,
+            })
+            .await
+    }
+
+    /// User a reset token was mailed to, whether or not it can still be used
+    pub async fn reset_token_owner(&self, token: &str) -> Result<Option<i32>, PasswordError> {
+        Ok(PasswordResetRepo::new()
+            .await
+            .get_by_token_hash(&hash_token(token))
+            .await?
+            .map(|reset| reset.user_id))
+    }
+
+    /// Sets a new password with a mailed token and signs the user out everywhere
+    pub async fn reset_password(
+        &self,
+        token: &str,
+        new: &str,
+    ) -> Result<PasswordReset, PasswordError> {
+        let repo = PasswordResetRepo::new().await;
+        let Some(reset) = repo.get_by_token_hash(&hash_token(token)).await? else {
+            return Ok(PasswordReset::InvalidToken);
+        };
+        let now = db_time(Duration::zero());
+        if reset.used_at.is_some() || reset.expires_at <= now {
+            return Ok(PasswordReset::InvalidToken);
+        }
+        // Checked before the token is used up, so the user can try again
+        if new.chars().count() < MIN_PASSWORD_LENGTH {
+            return Ok(PasswordReset::TooShort);
+        }
+        if !repo.consume(reset.reset_id, &now).await? {
+            return Ok(PasswordReset::InvalidToken);
+        }
+        // Other mails sent in the meantime stop working too
+        repo.delete_unused(reset.user_id).await?;
+
+        self.store_password(reset.user_id, new).await?;
+        SessionService::new()
+            .await
+            .revoke_all(reset.user_id)
+            .await?;
+        Ok(PasswordReset::Reset)
+    }
+}
@@ -0,0 +1,328 @@
+use std::{env, net::IpAddr, str::FromStr};
+
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use axum::http::{HeaderMap, HeaderName};
+use dotenvy::dotenv;
+use once_cell::sync::Lazy;
+
+use crate::{
+    data::{
+        models::{audit_events::AuditAction, users::Role},
+        repos::implementors::user_repo::UserRepo,
+    },
+    services::{
+        audit_service::{AuditEntry, AuditService, RequestOrigin},
+        registration_service::RegistrationService,
+        user_service::{UserChange, UserEdit, UserService},
+    },
+};
+
+pub type ProxyAuthError = Box<dyn std::error::Error + Send + Sync>;
+
+/// An address or network a proxy connects from, e.g. `10.0.0.0/8` or `127.0.0.1`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TrustedSource {
+    network: IpAddr,
+    prefix: u8,
+}
+
+impl TrustedSource {
+    pub fn parse(value: &str) -> Option<TrustedSource> {
+        let value = value.trim();
+        let (address, prefix) = match value.split_once('/') {
+            Some((address, prefix)) => (address, Some(prefix.parse::<u8>().ok()?)),
+            None => (value, None),
+        };
+        let network = IpAddr::from_str(address).ok()?.to_canonical();
+        let bits = if network.is_ipv4() { 32 } else { 128 };
+        let prefix = prefix.unwrap_or(bits);
+        (prefix <= bits).then_some(TrustedSource { network, prefix })
+    }
+
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        match (self.network, ip.to_canonical()) {
+            (IpAddr::V4(network), IpAddr::V4(ip)) => {
+                let mask = u32::MAX.checked_shl(32 - self.prefix as u32).unwrap_or(0);
+                u32::from(network) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(network), IpAddr::V6(ip)) => {
+                let mask = u128::MAX.checked_shl(128 - self.prefix as u32).unwrap_or(0);
+                u128::from(network) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+/// How identities asserted by an authenticating proxy are accepted
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProxyAuthConfig {
+    /// Only requests from these addresses may assert an identity
+    pub trusted: Vec<TrustedSource>,
+    pub user_header: HeaderName,
+    pub email_header: Option<HeaderName>,
+    pub groups_header: Option<HeaderName>,
+    /// Groups and the role they grant, the highest role of a user's groups wins
+    pub group_roles: Vec<(String, Role)>,
+    /// Role of provisioned users in none of the mapped groups
+    pub default_role: Role,
+    /// Whether unknown users get an account, otherwise they are rejected
+    pub auto_provision: bool,
+}
+
+impl ProxyAuthConfig {
+    /// Defaults of the Authelia-style `Remote-*` headers
+    pub fn new(trusted: Vec<TrustedSource>) -> Self {
+        ProxyAuthConfig {
+            trusted,
+            user_header: HeaderName::from_static("remote-user"),
+            email_header: Some(HeaderName::from_static("remote-email")),
+            groups_header: Some(HeaderName::from_static("remote-groups")),
+            group_roles: vec![],
+            default_role: Role::User,
+            auto_provision: true,
+        }
+    }
+
+    /// Parses `group=role` pairs separated by commas, e.g. `admins=admin,family=guest`
+    pub fn parse_group_roles(value: &str) -> Option<Vec<(String, Role)>> {
+        value
+            .split(',')
+            .map(str::trim)
+            .filter(|pair| !pair.is_empty())
+            .map(|pair| {
+                let (group, role) = pair.split_once('=')?;
+                Some((group.trim().to_string(), Role::parse(role)?))
+            })
+            .collect()
+    }
+
+    fn is_trusted(&self, ip: IpAddr) -> bool {
+        self.trusted.iter().any(|source| source.contains(ip))
+    }
+
+    /// The role the groups map to, `None` if no group is mapped
+    fn role_of(&self, groups: &[&str]) -> Option<Role> {
+        self.group_roles
+            .iter()
+            .filter(|(group, _)| groups.contains(&group.as_str()))
+            .map(|(_, role)| *role)
+            .max()
+    }
+}
+
+fn header_name(variable: &str, default: &'static str) -> Option<HeaderName> {
+    match env::var(variable) {
+        Ok(value) if value.trim().is_empty() => None,
+        Ok(value) => HeaderName::from_str(value.trim())
+            .map_err(|_| eprintln!("{} is not a valid header name, using {}", variable, default))
+            .ok()
+            .or(Some(HeaderName::from_static(default))),
+        Err(_) => Some(HeaderName::from_static(default)),
+    }
+}
+
+/// On with `PROXY_AUTH=true` and `PROXY_AUTH_TRUSTED` networks, see [`ProxyAuthConfig`]
+/// for the other `PROXY_AUTH_*` settings. The registration policy doesn't apply to proxy
+/// users, `PROXY_AUTH_AUTO_PROVISION=false` only lets existing users in.
+static CONFIG: Lazy<Option<ProxyAuthConfig>> = Lazy::new(|| {
+    dotenv().ok();
+
+    if env::var("PROXY_AUTH").ok().as_deref().map(str::trim) != Some("true") {
+        return None;
+    }
+    let trusted: Vec<TrustedSource> = env::var("PROXY_AUTH_TRUSTED")
+        .unwrap_or_default()
+        .split(',')
+        .filter(|v| !v.trim().is_empty())
+        .filter_map(|v| {
+            let source = TrustedSource::parse(v);
+            if source.is_none() {
+                eprintln!("Ignoring invalid trusted proxy address {}", v.trim());
+            }
+            source
+        })
+        .collect();
+    if trusted.is_empty() {
+        eprintln!("PROXY_AUTH is enabled but PROXY_AUTH_TRUSTED lists no proxy, it stays off");
+        return None;
+    }
+
+    let mut config = ProxyAuthConfig::new(trusted);
+    config.user_header = header_name("PROXY_AUTH_USER_HEADER", "remote-user")
+        .unwrap_or(HeaderName::from_static("remote-user"));
+    config.email_header = header_name("PROXY_AUTH_EMAIL_HEADER", "remote-email");
+    config.groups_header = header_name("PROXY_AUTH_GROUPS_HEADER", "remote-groups");
+    if let Ok(value) = env::var("PROXY_AUTH_GROUP_ROLES") {
+        match ProxyAuthConfig::parse_group_roles(&value) {
+            Some(group_roles) => config.group_roles = group_roles,
+            None => eprintln!("PROXY_AUTH_GROUP_ROLES is invalid, no group grants a role"),
+        }
+    }
+    if let Some(role) = env::var("PROXY_AUTH_DEFAULT_ROLE")
+        .ok()
+        .and_then(|v| Role::parse(&v))
+    {
+        config.default_role = role;
+    }
+    config.auto_provision = env::var("PROXY_AUTH_AUTO_PROVISION").as_deref() != Ok("false");
+    Some(config)
+});
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProxyIdentity {
+    /// Not from a trusted proxy or without the user header, other credentials apply
+    Absent,
+    User(i32),
+    /// Unknown user with provisioning off, or the account couldn't be created
+    Rejected(&'static str),
+}
+
+fn generate_password() -> String {
+    let mut bytes = [0u8; 32];
+    OsRng.fill_bytes(&mut bytes);
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Signs in users a trusted reverse proxy vouches for, other clients' headers are ignored
+pub struct ProxyAuthService {
+    config: ProxyAuthConfig,
+}
+
+impl ProxyAuthService {
+    pub fn new(config: ProxyAuthConfig) -> Self {
+        ProxyAuthService { config }
+    }
+
+    /// The service configured in the environment, `None` if proxy authentication is off
+    pub fn from_env() -> Option<Self> {
+        CONFIG.clone().map(ProxyAuthService::new)
+    }
+
+    fn header<'a>(headers: &'a HeaderMap, name: Option<&HeaderName>) -> Option<&'a str> {
+        headers
+            .get(name?)
+            .and_then(|v| v.to_str().ok())
+            .map(str::trim)
+            .filter(|v| !v.is_empty())
+    }
+
+    /// The user the proxy signed in, provisioned on first sight, role synced from groups
+    pub async fn authenticate(
+        &self,
+        origin: &RequestOrigin,
+        headers: &HeaderMap,
+    ) -> Result<ProxyIdentity, ProxyAuthError> {
+        if !origin.ip.is_some_and(|ip| self.config.is_trusted(ip)) {
+            return Ok(ProxyIdentity::Absent);
+        }
+        let Some(username) = Self::header(headers, Some(&self.config.user_header)) else {
+            return Ok(ProxyIdentity::Absent);
+        };
+        let groups: Option<Vec<&str>> = Self::header(headers, self.config.groups_header.as_ref())
+            .map(|v| v.split(',').map(str::trim).collect());
+        let mapped = groups.as_deref().and_then(|g| self.config.role_of(g));
+
+        let user = match UserRepo::new().await.get_by_username(username).await? {
+            Some(user) => user,
+            None if self.config.auto_provision => {
+                let email = Self::header(headers, self.config.email_header.as_ref());
+                return self.provision(origin, username, email, mapped).await;
+            }
+            None => return Ok(ProxyIdentity::Rejected("No account for the proxy user")),
+        };
+
+        // Without mapped groups the proxy says nothing about roles, the account keeps
+        // whatever an admin gave it
+        if groups.is_some() && !self.config.group_roles.is_empty() {
+            let role = mapped.unwrap_or(self.config.default_role);
+            if role != user.role() {
+                self.sync_role(origin, user.user_id, user.role(), role)
+                    .await?;
+            }
+        }
+        Ok(ProxyIdentity::User(user.user_id))
+    }
+
+    async fn provision(
+        &self,
+        origin: &RequestOrigin,
+        username: &str,
+        email: Option<&str>,
+        role: Option<Role>,
+    ) -> Result<ProxyIdentity, ProxyAuthError> {
+        let role = role.unwrap_or(self.config.default_role);
+        // Every account needs a password, proxy users never use theirs
+        let placeholder = format!("{}@proxy.invalid", username);
+        let email = email.filter(|e| e.contains('@')).unwrap_or(&placeholder);
+        let service = RegistrationService::new().await;
+
+        match service
+            .provision(username, email, &generate_password(), role)
+            .await?
+        {
+            UserChange::Done(user) => {
+                AuditService::new()
+                    .await
+                    .record(
+                        origin,
+                        AuditAction::UserCreated,
+                        AuditEntry::by(user.user_id)
+                            .target("user", user.user_id)
+                            .details(format!(
+                                "provisioned by proxy, role: {}",
+                                user.role().as_str()
+                            )),
+                    )
+                    .await;
+                Ok(ProxyIdentity::User(user.user_id))
+            }
+            // Another request of the same user may have created it in the meantime
+            UserChange::Conflict(message) => {
+                match UserRepo::new().await.get_by_username(username).await? {
+                    Some(user) => Ok(ProxyIdentity::User(user.user_id)),
+                    None => Ok(ProxyIdentity::Rejected(message)),
+                }
+            }
+            UserChange::Invalid(message) => Ok(ProxyIdentity::Rejected(message)),
+            UserChange::NotFound | UserChange::LastAdmin => {
+                Ok(ProxyIdentity::Rejected("The account couldn't be created"))
+            }
+        }
+    }
+
+    async fn sync_role(
+        &self,
+        origin: &RequestOrigin,
+        user_id: i32,
+        from: Role,
+        to: Role,
+    ) -> Result<(), ProxyAuthError> {
+        let edit = UserEdit {
+            role: Some(to),
+            ..Default::default()
+        };
+        match UserService::new().await.update(user_id, edit).await? {
+            UserChange::Done(_) => {
+                AuditService::new()
+                    .await
+                    .record(
+                        origin,
+                        AuditAction::RoleChanged,
+                        AuditEntry::by(user_id)
+                            .target("user", user_id)
+                            .details(format!(
+                                "{} -> {} from proxy groups",
+                                from.as_str(),
+                                to.as_str()
+                            )),
+                    )
+                    .await;
+            }
+            // The last admin keeps their role, the server would be unmanageable otherwise
+            UserChange::LastAdmin => {}
+            other => eprintln!("Failed to sync role of user {}: {:?}", user_id, other),
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,250 @@
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use chrono::Duration;
+use once_cell::sync::Lazy;
+use tokio::sync::Mutex;
+
+use crate::{
+    data::{
+        models::{
+            invites::{Invites, NewInvite},
+            users::{Role, Users},
+        },
+        repos::{
+            implementors::{
+                invite_repo::InviteRepo, setting_repo::SettingRepo, user_repo::UserRepo,
+            },
+            traits::repository::Repository,
+        },
+    },
+    services::{
+        session_service::{db_time, db_time_in_days, hash_token},
+        user_service::{UserChange, UserService},
+    },
+};
+
+pub type RegistrationError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Key of the policy in `server_settings`
+const POLICY_KEY: &str = "registration";
+
+/// How long an invite stays valid when the admin doesn't say
+pub const DEFAULT_INVITE_DAYS: i64 = 7;
+
+/// Registrations run one at a time, so two sign-ups on a fresh server can't both
+/// become the first admin and an invite can't be redeemed twice
+static REGISTRATION: Lazy<Mutex<()>> = Lazy::new(|| Mutex::new(()));
+
+/// Who may create an account through `/register` once the server is set up
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RegistrationPolicy {
+    Open,
+    /// Only admins create accounts
+    Closed,
+    /// A valid invite code is required
+    InviteOnly,
+}
+
+impl RegistrationPolicy {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RegistrationPolicy::Open => "open",
+            RegistrationPolicy::Closed => "closed",
+            RegistrationPolicy::InviteOnly => "invite_only",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<RegistrationPolicy> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "open" => Some(RegistrationPolicy::Open),
+            "closed" => Some(RegistrationPolicy::Closed),
+            "invite_only" | "invite-only" => Some(RegistrationPolicy::InviteOnly),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Registration {
+    /// The account was created, as admin if it was the first one
+    Created(Box<Users>),
+    Closed,
+    /// Missing, unknown, expired or used invite code
+    InvalidInvite,
+    /// The details were rejected, see [`UserChange`]
+    Rejected(UserChange),
+}
+
+/// An invite that was just created, the only time its code is available
+#[derive(Debug, Clone, PartialEq)]
+pub struct CreatedInvite {
+    pub code: String,
+    pub invite: Invites,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum InviteCreation {
+    Created(Box<CreatedInvite>),
+    InvalidExpiry,
+}
+
+fn generate_code() -> String {
+    let mut bytes = [0u8; 12];
+    OsRng.fill_bytes(&mut bytes);
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+pub struct RegistrationService;
+
+impl RegistrationService {
+    pub async fn new() -> Self {
+        RegistrationService
+    }
+
+    /// Whether the server has no accounts yet, the next registration becomes the admin
+    pub async fn setup_required(&self) -> Result<bool, RegistrationError> {
+        Ok(UserRepo::new().await.count().await? == 0)
+    }
+
+    /// The stored policy, closed until an admin opens registration
+    pub async fn policy(&self) -> Result<RegistrationPolicy, RegistrationError> {
+        let setting = SettingRepo::new().await.get(POLICY_KEY).await?;
+        Ok(setting
+            .and_then(|setting| RegistrationPolicy::parse(&setting.value))
+            .unwrap_or(RegistrationPolicy::Closed))
+    }
+
+    pub async fn set_policy(&self, policy: RegistrationPolicy) -> Result<(), RegistrationError> {
+        SettingRepo::new()
+            .await
+            .set(POLICY_KEY, policy.as_str())
+            .await?;
+        Ok(())
+    }
+
+    /// Registers an account under the current policy, the first account becomes admin
+    pub async fn register(
+        &self,
+        username: &str,
+        email: &str,
+        password: &str,
+        invite_code: Option<&str>,
+    ) -> Result<Registration, RegistrationError> {
+        let _guard = REGISTRATION.lock().await;
+        let now = db_time(Duration::zero());
+
+        let invite = match invite_code.map(str::trim).filter(|code| !code.is_empty()) {
+            Some(code) => match InviteRepo::new()
+                .await
+                .get_by_code_hash(&hash_token(code))
+                .await?
+            {
+                Some(invite) if invite.is_usable(&now) => Some(invite),
+                _ => return Ok(Registration::InvalidInvite),
+            },
+            None => None,
+        };
+
+        let role = if self.setup_required().await? {
+            Role::Admin
+        } else {
+            match (self.policy().await?, &invite) {
+                (_, Some(invite)) => invite.role(),
+                (RegistrationPolicy::Open, None) => Role::User,
+                (RegistrationPolicy::InviteOnly, None) => return Ok(Registration::InvalidInvite),
+                (RegistrationPolicy::Closed, None) => return Ok(Registration::Closed),
+            }
+        };
+
+        let user = match UserService::new()
+            .await
+            .create(username, email, password, role)
+            .await?
+        {
+            UserChange::Done(user) => user,
+            rejected => return Ok(Registration::Rejected(rejected)),
+        };
+        if let Some(invite) = invite {
+            if !InviteRepo::new()
+                .await
+                .consume(invite.invite_id, user.user_id, &now)
+                .await?
+            {
+                UserRepo::new().await.delete(user.user_id).await?;
+                return Ok(Registration::InvalidInvite);
+            }
+        }
+        Ok(Registration::Created(user))
+    }
+
+    /// Creates an account for a proxy user regardless of the policy, the first becomes admin
+    pub async fn provision(
+        &self,
+        username: &str,
+        email: &str,
+        password: &str,
+        role: Role,
+    ) -> Result<UserChange, RegistrationError> {
+        let _guard = REGISTRATION.lock().await;
+        let role = if self.setup_required().await? {
+            Role::Admin
+        } else {
+            role
+        };
+        UserService::new()
+            .await
+            .create(username, email, password, role)
+            .await
+    }
+
+    /// Creates an invite for a role, valid for `expires_in_days` or the default
+    pub async fn create_invite(
+        &self,
+        created_by: i32,
+        role: Role,
+        expires_in_days: Option<i64>,
+    ) -> Result<InviteCreation, RegistrationError> {
+        let Some(expires_at) = db_time_in_days(expires_in_days.unwrap_or(DEFAULT_INVITE_DAYS))
+        else {
+            return Ok(InviteCreation::InvalidExpiry);
+        };
+
+        let code = generate_code();
+        let repo = InviteRepo::new().await;
+        let id = repo
+            .add_returning_id(NewInvite {
+                code_hash: &hash_token(&code),
+                role: role.as_str(),
+                created_by: Some(created_by),
+                expires_at: &expires_at,
+            })
+            .await?;
+        let invite = repo
+            .get_by_id(id)
+            .await?
+            .ok_or("Created invite not found")?;
+        Ok(InviteCreation::Created(Box::new(CreatedInvite {
+            code,
+            invite,
+        })))
+    }
+
+    /// Invites that can still be redeemed
+    pub async fn list_invites(&self) -> Result<Vec<Invites>, RegistrationError> {
+        let now = db_time(Duration::zero());
+        let invites = InviteRepo::new().await.get_all().await?.unwrap_or_default();
+        Ok(invites
+            .into_iter()
+            .filter(|invite| invite.is_usable(&now))
+            .collect())
+    }
+
+    /// Withdraws an invite, `false` if there is no such invite
+    pub async fn revoke_invite(&self, id: i32) -> Result<bool, RegistrationError> {
+        let repo = InviteRepo::new().await;
+        if repo.get_by_id(id).await?.is_none() {
+            return Ok(false);
+        }
+        repo.delete(id).await?;
+        Ok(true)
+    }
+}
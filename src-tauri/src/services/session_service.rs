@@ -0,0 +1,237 @@
+use std::env;
+
+use chrono::{Duration, TimeDelta, Utc};
+use dotenvy::dotenv;
+use once_cell::sync::Lazy;
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+use crate::{
+    data::{
+        models::sessions::{NewSession, Sessions},
+        repos::{implementors::session_repo::SessionRepo, traits::repository::Repository},
+    },
+    services::token_service::Tokenizer,
+};
+
+pub type SessionError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Idle days before a refresh token expires, `REFRESH_TOKEN_EXPIRATION_DAYS`
+static REFRESH_TOKEN_DAYS: Lazy<i64> = Lazy::new(|| {
+    dotenv().ok();
+
+    env::var("REFRESH_TOKEN_EXPIRATION_DAYS")
+        .ok()
+        .and_then(|v| v.parse::<i64>().ok())
+        .filter(|v| *v > 0)
+        .unwrap_or(30)
+});
+
+/// Longest expiry a user can ask for, in days
+pub const MAX_EXPIRY_DAYS: i64 = 3650;
+
+/// Tokens handed to a client when it signs in or refreshes
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IssuedTokens {
+    pub access_token: String,
+    pub refresh_token: String,
+    pub session_id: i32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RefreshOutcome {
+    Rotated(IssuedTokens),
+    /// Unknown, expired or signed out token
+    Invalid,
+    /// The token was already replaced, every session of its family has been revoked
+    Reused,
+}
+
+/// Days a refresh token stays valid without being used
+pub fn refresh_token_days() -> i64 {
+    *REFRESH_TOKEN_DAYS
+}
+
+/// Refresh tokens are random, so an unsalted hash is enough to keep them out of the database
+pub fn hash_token(token: &str) -> String {
+    Sha256::digest(token.as_bytes())
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// Times are stored the way SQLite's `datetime('now')` writes them, so they compare as text
+pub fn db_time(offset: Duration) -> String {
+    (Utc::now() + offset)
+        .format("%Y-%m-%d %H:%M:%S")
+        .to_string()
+}
+
+/// `db_time` some days ahead, `None` unless `days` is between 1 and `MAX_EXPIRY_DAYS`
+pub fn db_time_in_days(days: i64) -> Option<String> {
+    if !(1..=MAX_EXPIRY_DAYS).contains(&days) {
+        return None;
+    }
+    TimeDelta::try_days(days)
+        .and_then(|offset| Utc::now().checked_add_signed(offset))
+        .map(|time| time.format("%Y-%m-%d %H:%M:%S").to_string())
+}
+
+pub struct SessionService;
+
+impl SessionService {
+    pub async fn new() -> Self {
+        SessionService
+    }
+
+    /// A new access token and its `jti`
+    async fn access_token(user_id: i32) -> Result<(String, String), SessionError> {
+        let (token, claims) = Tokenizer::get_instance().await.issue(user_id)?;
+        Ok((token, claims.jti))
+    }
+
+    /// Revokes a family and the access token issued with its live session
+    async fn revoke_family(repo: &SessionRepo, family: &str) -> Result<(), SessionError> {
+        let jtis = repo
+            .revoke_family(family, &db_time(Duration::zero()))
+            .await?;
+        Tokenizer::get_instance().await.revoke(jtis).await
+    }
+
+    /// Starts a new session after the user signed in
+    pub async fn create(
+        &self,
+        user_id: i32,
+        device_name: Option<&str>,
+        user_agent: Option<&str>,
+    ) -> Result<IssuedTokens, SessionError> {
+        let repo = SessionRepo::new().await;
+        let now = db_time(Duration::zero());
+        repo.delete_expired_by_user(user_id, &now).await?;
+
+        let refresh_token = Tokenizer::get_instance().await.generate_refresh_token();
+        let (access_token, access_jti) = Self::access_token(user_id).await?;
+        let family = Uuid::new_v4().to_string();
+        let session_id = repo
+            .add_returning_id(NewSession {
+                user_id,
+                family_id: &family,
+                token_hash: &hash_token(&refresh_token),
+                device_name,
+                user_agent,
+                created_at: None,
+                expires_at: &db_time(Duration::days(*REFRESH_TOKEN_DAYS)),
+                access_jti: Some(&access_jti),
+            })
+            .await?;
+
+        Ok(IssuedTokens {
+            access_token,
+            refresh_token,
+            session_id,
+        })
+    }
+
+    /// Exchanges a refresh token for new tokens, the presented one can't be used again
+    pub async fn refresh(
+        &self,
+        token: &str,
+        user_agent: Option<&str>,
+    ) -> Result<RefreshOutcome, SessionError> {
+        let repo = SessionRepo::new().await;
+        let Some(session) = repo.get_by_token_hash(&hash_token(token)).await? else {
+            return Ok(RefreshOutcome::Invalid);
+        };
+        let now = db_time(Duration::zero());
+
+        if session.replaced_by.is_some() {
+            Self::revoke_family(&repo, &session.family_id).await?;
+            return Ok(RefreshOutcome::Reused);
+        }
+        if session.revoked_at.is_some() || session.expires_at <= now {
+            return Ok(RefreshOutcome::Invalid);
+        }
+
+        let refresh_token = Tokenizer::get_instance().await.generate_refresh_token();
+        let (access_token, access_jti) = Self::access_token(session.user_id).await?;
+        let rotated = repo
+            .rotate(
+                session.session_id,
+                NewSession {
+                    user_id: session.user_id,
+                    family_id: &session.family_id,
+                    token_hash: &hash_token(&refresh_token),
+                    device_name: session.device_name.as_deref(),
+                    user_agent: user_agent.or(session.user_agent.as_deref()),
+                    created_at: session.created_at.as_deref(),
+                    expires_at: &db_time(Duration::days(*REFRESH_TOKEN_DAYS)),
+                    access_jti: Some(&access_jti),
+                },
+                &now,
+            )
+            .await?;
+
+        // Another request used the same token in the meantime
+        let Some(session_id) = rotated else {
+            Self::revoke_family(&repo, &session.family_id).await?;
+            return Ok(RefreshOutcome::Reused);
+        };
+
+        Ok(RefreshOutcome::Rotated(IssuedTokens {
+            access_token,
+            refresh_token,
+            session_id,
+        }))
+    }
+
+    /// User a refresh token was issued to, whether or not it is still valid
+    pub async fn token_owner(&self, token: &str) -> Result<Option<i32>, SessionError> {
+        Ok(SessionRepo::new()
+            .await
+            .get_by_token_hash(&hash_token(token))
+            .await?
+            .map(|session| session.user_id))
+    }
+
+    /// Ends the session a refresh token belongs to, `false` if it wasn't active
+    pub async fn revoke_token(&self, token: &str) -> Result<bool, SessionError> {
+        let repo = SessionRepo::new().await;
+        match repo.get_by_token_hash(&hash_token(token)).await? {
+            Some(session) if session.revoked_at.is_none() => {
+                Self::revoke_family(&repo, &session.family_id).await?;
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    /// Active sessions of a user, most recently used first
+    pub async fn list(&self, user_id: i32) -> Result<Vec<Sessions>, SessionError> {
+        Ok(SessionRepo::new()
+            .await
+            .get_active_by_user(user_id, &db_time(Duration::zero()))
+            .await?
+            .unwrap_or_default())
+    }
+
+    /// Ends one of the user's sessions, `false` if the user has no such active session
+    pub async fn revoke(&self, user_id: i32, session_id: i32) -> Result<bool, SessionError> {
+        let repo = SessionRepo::new().await;
+        match repo.get_by_id(session_id).await? {
+            Some(session) if session.user_id == user_id && session.revoked_at.is_none() => {
+                Self::revoke_family(&repo, &session.family_id).await?;
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    /// Ends every session of the user, with the access tokens issued for them
+    pub async fn revoke_all(&self, user_id: i32) -> Result<(), SessionError> {
+        let jtis = SessionRepo::new()
+            .await
+            .revoke_all_by_user(user_id, &db_time(Duration::zero()))
+            .await?;
+        Tokenizer::get_instance().await.revoke(jtis).await
+    }
+}
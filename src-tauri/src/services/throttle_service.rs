@@ -0,0 +1,237 @@
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use once_cell::sync::Lazy;
+
+/// How failed attempts for one account or address are punished
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ThrottlePolicy {
+    /// Failures allowed before attempts are delayed
+    pub free_attempts: u32,
+    /// Delay after the first failure past the free ones, doubled with every further failure
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+    /// Failures after which the key is locked for `lockout_for`
+    pub lockout_after: Option<u32>,
+    pub lockout_for: Duration,
+    /// Failures are forgotten after this long without a new one
+    pub forget_after: Duration,
+}
+
+/// Per account, unknown usernames are tracked the same way
+pub const ACCOUNT_POLICY: ThrottlePolicy = ThrottlePolicy {
+    free_attempts: 5,
+    base_delay: Duration::from_secs(1),
+    max_delay: Duration::from_secs(5 * 60),
+    lockout_after: Some(10),
+    lockout_for: Duration::from_secs(15 * 60),
+    forget_after: Duration::from_secs(15 * 60),
+};
+
+/// Per client address, never locked so a shared NAT can't lock everyone out
+pub const IP_POLICY: ThrottlePolicy = ThrottlePolicy {
+    free_attempts: 20,
+    base_delay: Duration::from_secs(1),
+    max_delay: Duration::from_secs(5 * 60),
+    lockout_after: None,
+    lockout_for: Duration::ZERO,
+    forget_after: Duration::from_secs(15 * 60),
+};
+
+/// Entries kept before pruning, stale ones go first and then the oldest, so random
+/// usernames can't grow the map
+const MAX_ENTRIES: usize = 10_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Throttled {
+    /// Too many recent failures, retry after the delay
+    Wait(Duration),
+    /// The account is locked until the delay passed or an admin unlocks it
+    Locked(Duration),
+}
+
+impl Throttled {
+    pub fn retry_after(&self) -> Duration {
+        match self {
+            Throttled::Wait(d) | Throttled::Locked(d) => *d,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum ThrottleKey {
+    Account(String),
+    Ip(IpAddr),
+}
+
+#[derive(Debug)]
+struct Attempts {
+    failures: u32,
+    last_failure: Instant,
+    blocked_until: Option<Instant>,
+    locked: bool,
+}
+
+/// Counts failed sign-ins per account and per client address
+pub struct LoginThrottle {
+    account_policy: ThrottlePolicy,
+    ip_policy: ThrottlePolicy,
+    entries: Mutex<HashMap<ThrottleKey, Attempts>>,
+}
+
+static LOGIN_THROTTLE: Lazy<LoginThrottle> =
+    Lazy::new(|| LoginThrottle::new(ACCOUNT_POLICY, IP_POLICY));
+
+fn account_key(username: &str) -> ThrottleKey {
+    ThrottleKey::Account(username.trim().to_lowercase())
+}
+
+impl LoginThrottle {
+    pub fn new(account_policy: ThrottlePolicy, ip_policy: ThrottlePolicy) -> Self {
+        LoginThrottle {
+            account_policy,
+            ip_policy,
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Throttle shared by every sign-in route
+    pub fn global() -> &'static LoginThrottle {
+        &LOGIN_THROTTLE
+    }
+
+    fn policy(&self, key: &ThrottleKey) -> &ThrottlePolicy {
+        match key {
+            ThrottleKey::Account(_) => &self.account_policy,
+            ThrottleKey::Ip(_) => &self.ip_policy,
+        }
+    }
+
+    fn keys(username: &str, ip: Option<IpAddr>) -> Vec<ThrottleKey> {
+        let mut keys = vec![account_key(username)];
+        keys.extend(ip.map(ThrottleKey::Ip));
+        keys
+    }
+
+    /// Whether an attempt for the account from the address may go ahead
+    pub fn check(&self, username: &str, ip: Option<IpAddr>) -> Result<(), Throttled> {
+        let now = Instant::now();
+        let entries = self.entries.lock().unwrap();
+        let mut locked: Option<Duration> = None;
+        let mut wait: Option<Duration> = None;
+        for key in Self::keys(username, ip) {
+            let Some(attempts) = entries.get(&key) else {
+                continue;
+            };
+            let Some(until) = attempts.blocked_until.filter(|until| *until > now) else {
+                continue;
+            };
+            let slot = if attempts.locked {
+                &mut locked
+            } else {
+                &mut wait
+            };
+            *slot = Some(slot.map_or(until - now, |d| d.max(until - now)));
+        }
+
+        // A lock wins over a delay
+        match (locked, wait) {
+            (Some(remaining), _) => Err(Throttled::Locked(remaining)),
+            (None, Some(remaining)) => Err(Throttled::Wait(remaining)),
+            (None, None) => Ok(()),
+        }
+    }
+
+    pub fn record_failure(&self, username: &str, ip: Option<IpAddr>) {
+        let now = Instant::now();
+        let keys = Self::keys(username, ip);
+        let mut entries = self.entries.lock().unwrap();
+        if entries.len() >= MAX_ENTRIES {
+            let account_policy = self.account_policy;
+            let ip_policy = self.ip_policy;
+            entries.retain(|key, attempts| {
+                let policy = match key {
+                    ThrottleKey::Account(_) => &account_policy,
+                    ThrottleKey::Ip(_) => &ip_policy,
+                };
+                attempts.blocked_until.is_some_and(|until| until > now)
+                    || now.duration_since(attempts.last_failure) < policy.forget_after
+            });
+
+            // Every entry is still recent, the ones that failed longest ago make room
+            let excess = (entries.len() + keys.len()).saturating_sub(MAX_ENTRIES);
+            if excess > 0 {
+                let mut oldest: Vec<(Instant, ThrottleKey)> = entries
+                    .iter()
+                    .map(|(key, attempts)| (attempts.last_failure, key.clone()))
+                    .collect();
+                oldest.sort_unstable_by_key(|(last_failure, _)| *last_failure);
+                for (_, key) in oldest.into_iter().take(excess) {
+                    entries.remove(&key);
+                }
+            }
+        }
+
+        for key in keys {
+            let policy = *self.policy(&key);
+            let attempts = entries.entry(key).or_insert(Attempts {
+                failures: 0,
+                last_failure: now,
+                blocked_until: None,
+                locked: false,
+            });
+
+            let lock_expired = attempts.locked && attempts.blocked_until.is_some_and(|u| u <= now);
+            if lock_expired || now.duration_since(attempts.last_failure) >= policy.forget_after {
+                attempts.failures = 0;
+                attempts.locked = false;
+                attempts.blocked_until = None;
+            }
+            attempts.failures += 1;
+            attempts.last_failure = now;
+
+            if policy
+                .lockout_after
+                .is_some_and(|limit| attempts.failures >= limit)
+            {
+                attempts.locked = true;
+                attempts.blocked_until = Some(now + policy.lockout_for);
+            } else if attempts.failures > policy.free_attempts {
+                let exponent = (attempts.failures - policy.free_attempts - 1).min(16);
+                let delay = policy
+                    .base_delay
+                    .saturating_mul(1 << exponent)
+                    .min(policy.max_delay);
+                attempts.blocked_until = Some(now + delay);
+            }
+        }
+    }
+
+    /// Clears the account's failures, the address keeps its count
+    pub fn record_success(&self, username: &str) {
+        self.entries.lock().unwrap().remove(&account_key(username));
+    }
+
+    /// Lifts a lockout or delay of an account, `false` if it had no failures recorded
+    pub fn unlock(&self, username: &str) -> bool {
+        self.entries
+            .lock()
+            .unwrap()
+            .remove(&account_key(username))
+            .is_some()
+    }
+
+    /// Whether the account is currently locked out
+    pub fn is_locked(&self, username: &str) -> bool {
+        let now = Instant::now();
+        self.entries
+            .lock()
+            .unwrap()
+            .get(&account_key(username))
+            .is_some_and(|a| a.locked && a.blocked_until.is_some_and(|until| until > now))
+    }
+}
@@ -1,79 +1,358 @@
+use argon2::password_hash::rand_core::{OsRng, RngCore};
 use chrono::{Duration, Utc};
 use dotenvy::dotenv;
-use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation};
-use once_cell::sync::Lazy;
+use jsonwebtoken::{
+    decode, decode_header, encode, errors::ErrorKind, Algorithm, DecodingKey, EncodingKey, Header,
+    Validation,
+};
 use serde::{Deserialize, Serialize};
-use std::env;
-use tokio::task;
+use sha2::{Digest, Sha256};
+use std::{env, fs};
+use tokio::{sync::OnceCell, task};
+use uuid::Uuid;
 
-#[derive(Debug, Serialize, Deserialize)]
+use crate::{
+    data::{
+        models::revoked_tokens::RevokedToken,
+        repos::implementors::{revoked_token_repo::RevokedTokenRepo, setting_repo::SettingRepo},
+    },
+    services::session_service::db_time,
+};
+
+pub type TokenServiceError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Key of the generated secret in `server_settings`, used when `JWT_SECRET` isn't set
+const GENERATED_SECRET_KEY: &str = "jwt_secret";
+
+/// Default `iss` and `aud` of issued tokens
+const DEFAULT_ISSUER: &str = "stellaron";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Claims {
     pub sub: i32,   // Subject (user_id)
     pub exp: usize, // Expiration time
+    pub iat: usize,
+    pub iss: String,
+    pub aud: String,
+    /// Unique id of the token, see [`Tokenizer::revoke`]
+    pub jti: String,
+}
+
+/// Key id of a secret or public key, a digest so the header doesn't reveal the key
+fn key_id(material: &[u8]) -> String {
+    Sha256::digest(material)[..8]
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+struct SigningKey {
+    kid: String,
+    algorithm: Algorithm,
+    key: EncodingKey,
+}
+
+struct VerifyingKey {
+    kid: String,
+    algorithm: Algorithm,
+    key: DecodingKey,
+}
+
+/// The signing key and every key tokens are still accepted with while rotating
+pub struct Keyring {
+    signing: SigningKey,
+    verifying: Vec<VerifyingKey>,
+}
+
+impl Keyring {
+    /// HS256 with a shared secret
+    pub fn hmac(secret: &[u8]) -> Keyring {
+        let kid = key_id(secret);
+        Keyring {
+            signing: SigningKey {
+                kid: kid.clone(),
+                algorithm: Algorithm::HS256,
+                key: EncodingKey::from_secret(secret),
+            },
+            verifying: vec![VerifyingKey {
+                kid,
+                algorithm: Algorithm::HS256,
+                key: DecodingKey::from_secret(secret),
+            }],
+        }
+    }
+
+    /// EdDSA or RS256 with a PEM key pair
+    pub fn from_pem(
+        algorithm: Algorithm,
+        private_pem: &[u8],
+        public_pem: &[u8],
+    ) -> Result<Keyring, jsonwebtoken::errors::Error> {
+        let key = match algorithm {
+            Algorithm::EdDSA => EncodingKey::from_ed_pem(private_pem)?,
+            Algorithm::RS256 => EncodingKey::from_rsa_pem(private_pem)?,
+            _ => return Err(ErrorKind::InvalidAlgorithm.into()),
+        };
+        let kid = key_id(public_pem);
+        let keyring = Keyring {
+            signing: SigningKey {
+                kid,
+                algorithm,
+                key,
+            },
+            verifying: Vec::new(),
+        };
+        keyring.accept_pem(algorithm, public_pem)
+    }
+
+    /// Also accepts tokens signed with a previous secret
+    pub fn accept_hmac(mut self, secret: &[u8]) -> Keyring {
+        self.verifying.push(VerifyingKey {
+            kid: key_id(secret),
+            algorithm: Algorithm::HS256,
+            key: DecodingKey::from_secret(secret),
+        });
+        self
+    }
+
+    /// Also accepts tokens signed with the private half of a previous public key
+    pub fn accept_pem(
+        mut self,
+        algorithm: Algorithm,
+        public_pem: &[u8],
+    ) -> Result<Keyring, jsonwebtoken::errors::Error> {
+        let key = match algorithm {
+            Algorithm::EdDSA => DecodingKey::from_ed_pem(public_pem)?,
+            Algorithm::RS256 => DecodingKey::from_rsa_pem(public_pem)?,
+            _ => return Err(ErrorKind::InvalidAlgorithm.into()),
+        };
+        self.verifying.push(VerifyingKey {
+            kid: key_id(public_pem),
+            algorithm,
+            key,
+        });
+        Ok(self)
+    }
+
+    /// Key ids tokens are accepted with, the signing key's first
+    pub fn key_ids(&self) -> Vec<&str> {
+        self.verifying.iter().map(|key| key.kid.as_str()).collect()
+    }
 }
 
 pub struct Tokenizer {
-    pub secret_key: String,
+    keyring: Keyring,
     pub expiration_duration: i64,
+    issuer: String,
+    audience: String,
 }
 
 impl Tokenizer {
+    pub fn new(keyring: Keyring, expiration_duration: i64, issuer: &str, audience: &str) -> Self {
+        Tokenizer {
+            keyring,
+            expiration_duration,
+            issuer: issuer.to_string(),
+            audience: audience.to_string(),
+        }
+    }
+
+    /// Sets up signing once, the server calls this before it starts so bad `JWT_*`
+    /// settings stop it instead of failing requests
+    pub async fn init() -> Result<&'static Tokenizer, TokenServiceError> {
+        TOKENIZER.get_or_try_init(Tokenizer::from_env).await
+    }
+
+    /// The tokenizer set up by `init`, set up here when `init` wasn't called
     pub async fn get_instance() -> &'static Tokenizer {
-        &TOKENIZER
+        match Self::init().await {
+            Ok(tokenizer) => tokenizer,
+            Err(e) => panic!("Failed to set up JWT signing: {}", e),
+        }
     }
-    // TODO: Test this function
-    pub fn generate_token(&self, user_id: i32) -> Result<String, jsonwebtoken::errors::Error> {
-        let expiration = Utc::now()
+
+    /// Keys from `JWT_ALGORITHM` (`HS256`, `EdDSA` or `RS256`), `JWT_SECRET` or the
+    /// `JWT_PRIVATE_KEY`/`JWT_PUBLIC_KEY` PEM paths, `JWT_PREVIOUS_SECRETS`,
+    /// `JWT_PREVIOUS_PUBLIC_KEYS`, `JWT_ISSUER` and `JWT_AUDIENCE`
+    async fn from_env() -> Result<Tokenizer, TokenServiceError> {
+        dotenv().ok(); // Load .env file
+
+        let algorithm = match env::var("JWT_ALGORITHM") {
+            Ok(name) => name
+                .parse::<Algorithm>()
+                .map_err(|_| format!("Unsupported JWT_ALGORITHM {}", name))?,
+            Err(_) => Algorithm::HS256,
+        };
+        let list = |name: &str| -> Vec<String> {
+            env::var(name)
+                .unwrap_or_default()
+                .split(',')
+                .map(str::trim)
+                .filter(|v| !v.is_empty())
+                .map(str::to_string)
+                .collect()
+        };
+
+        let mut keyring = match algorithm {
+            Algorithm::HS256 => {
+                let secret = match env::var("JWT_SECRET").ok().filter(|v| !v.is_empty()) {
+                    Some(secret) => secret,
+                    None => Self::generated_secret().await?,
+                };
+                if secret.len() < 32 {
+                    eprintln!("JWT_SECRET is shorter than 32 bytes, tokens are easy to forge");
+                }
+                Keyring::hmac(secret.as_bytes())
+            }
+            Algorithm::EdDSA | Algorithm::RS256 => {
+                let read = |name: &str| -> Result<Vec<u8>, TokenServiceError> {
+                    let path = env::var(name).map_err(|_| format!("{} is not set", name))?;
+                    fs::read(&path).map_err(|e| format!("Can't read {}: {}", path, e).into())
+                };
+                Keyring::from_pem(
+                    algorithm,
+                    &read("JWT_PRIVATE_KEY")?,
+                    &read("JWT_PUBLIC_KEY")?,
+                )?
+            }
+            _ => return Err(format!("Unsupported JWT_ALGORITHM {:?}", algorithm).into()),
+        };
+        for secret in list("JWT_PREVIOUS_SECRETS") {
+            keyring = keyring.accept_hmac(secret.as_bytes());
+        }
+        for path in list("JWT_PREVIOUS_PUBLIC_KEYS") {
+            let pem = fs::read(&path).map_err(|e| format!("Can't read {}: {}", path, e))?;
+            keyring = keyring.accept_pem(algorithm, &pem)?;
+        }
+
+        let expiration_duration = env::var("TOKEN_EXPIRATION_SECONDS")
+            .unwrap_or_else(|_| "3600".to_string())
+            .parse::<i64>()
+            .unwrap_or(3600);
+        let issuer = env::var("JWT_ISSUER").unwrap_or_else(|_| DEFAULT_ISSUER.to_string());
+        let audience = env::var("JWT_AUDIENCE").unwrap_or_else(|_| DEFAULT_ISSUER.to_string());
+
+        Ok(Tokenizer::new(
+            keyring,
+            expiration_duration,
+            &issuer,
+            &audience,
+        ))
+    }
+
+    /// The secret generated on first start, created if there is none yet
+    async fn generated_secret() -> Result<String, TokenServiceError> {
+        let repo = SettingRepo::new().await;
+        if let Some(setting) = repo.get(GENERATED_SECRET_KEY).await? {
+            return Ok(setting.value);
+        }
+        let mut bytes = [0u8; 32];
+        OsRng.fill_bytes(&mut bytes);
+        let secret: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+        repo.set(GENERATED_SECRET_KEY, &secret).await?;
+        Ok(secret)
+    }
+
+    fn claims(&self, user_id: i32) -> Claims {
+        let now = Utc::now();
+        let expiration = now
             .checked_add_signed(Duration::seconds(self.expiration_duration))
             .expect("valid timestamp")
             .timestamp() as usize;
 
-        let claims = Claims {
+        Claims {
             sub: user_id,
             exp: expiration,
-        };
+            iat: now.timestamp() as usize,
+            iss: self.issuer.clone(),
+            aud: self.audience.clone(),
+            jti: Uuid::new_v4().to_string(),
+        }
+    }
+
+    fn header(&self) -> Header {
+        let mut header = Header::new(self.keyring.signing.algorithm);
+        header.kid = Some(self.keyring.signing.kid.clone());
+        header
+    }
 
-        encode(
-            &Header::default(),
-            &claims,
-            &EncodingKey::from_secret(self.secret_key.as_ref()),
-        )
-    }
-    // TODO: Test this async function
-    pub async fn generate_token_async(&self, user_id: i32) -> Result<String, jsonwebtoken::errors::Error> {
-        let secret_key = self.secret_key.clone();
-        let expiration_duration = self.expiration_duration;
-
-        task::spawn_blocking(move || {
-            let expiration = Utc::now()
-                .checked_add_signed(Duration::seconds(expiration_duration))
-                .expect("valid timestamp")
-                .timestamp() as usize;
-
-            let claims = Claims {
-                sub: user_id,
-                exp: expiration,
-            };
-
-            encode(
-                &Header::default(),
-                &claims,
-                &EncodingKey::from_secret(secret_key.as_ref()),
-            )
-        })
-        .await
-        .unwrap()// Propagate panics from the spawned task
+    /// Signs a new access token and returns it with its claims
+    pub fn issue(&self, user_id: i32) -> Result<(String, Claims), jsonwebtoken::errors::Error> {
+        let claims = self.claims(user_id);
+        let token = encode(&self.header(), &claims, &self.keyring.signing.key)?;
+        Ok((token, claims))
     }
 
+    pub fn generate_token(&self, user_id: i32) -> Result<String, jsonwebtoken::errors::Error> {
+        self.issue(user_id).map(|(token, _)| token)
+    }
+
+    /// [`Self::generate_token`] on a blocking thread, RSA signing is slow
+    pub async fn generate_token_async(
+        &self,
+        user_id: i32,
+    ) -> Result<String, jsonwebtoken::errors::Error> {
+        let header = self.header();
+        let claims = self.claims(user_id);
+        let key = self.keyring.signing.key.clone();
+
+        task::spawn_blocking(move || encode(&header, &claims, &key))
+            .await
+            .unwrap() // Propagate panics from the spawned task
+    }
+
+    /// Checks the signature with the key named by `kid` and the standard claims
     pub fn decode_token(&self, token: &str) -> Result<Claims, jsonwebtoken::errors::Error> {
-        let mut validation = Validation::default();
+        let header = decode_header(token)?;
+        let key = header
+            .kid
+            .as_deref()
+            .and_then(|kid| self.keyring.verifying.iter().find(|key| key.kid == kid))
+            .ok_or(ErrorKind::InvalidToken)?;
+
+        let mut validation = Validation::new(key.algorithm);
         validation.validate_exp = true;
-        decode::<Claims>(
-            token,
-            &DecodingKey::from_secret(self.secret_key.as_ref()),
-            &validation,
-        )
-        .map(|data| data.claims)
+        validation.set_issuer(&[&self.issuer]);
+        validation.set_audience(&[&self.audience]);
+        // `sub` is a number here, which the spec check can't read, `Claims` requires it anyway
+        validation.set_required_spec_claims(&["exp", "iat", "iss", "aud"]);
+        decode::<Claims>(token, &key.key, &validation).map(|data| data.claims)
+    }
+
+    /// Like [`Self::decode_token`] but also refuses revoked tokens
+    pub async fn verify(&self, token: &str) -> Result<Option<Claims>, TokenServiceError> {
+        let Ok(claims) = self.decode_token(token) else {
+            return Ok(None);
+        };
+        if RevokedTokenRepo::new()
+            .await
+            .is_revoked(&claims.jti)
+            .await?
+        {
+            return Ok(None);
+        }
+        Ok(Some(claims))
+    }
+
+    /// Denies access tokens by `jti` until they would have expired anyway
+    pub async fn revoke(&self, jtis: Vec<String>) -> Result<(), TokenServiceError> {
+        if jtis.is_empty() {
+            return Ok(());
+        }
+        // Tokens can't outlive the configured lifetime, plus the validation leeway
+        let expires_at = db_time(Duration::seconds(self.expiration_duration + 60));
+        let tokens = jtis
+            .into_iter()
+            .map(|jti| RevokedToken {
+                jti,
+                expires_at: expires_at.clone(),
+            })
+            .collect();
+        RevokedTokenRepo::new()
+            .await
+            .add_all(tokens, &db_time(Duration::zero()))
+            .await?;
+        Ok(())
     }
 
     pub fn refresh_token(&self, user_id: i32) -> Result<String, jsonwebtoken::errors::Error> {
@@ -83,23 +362,8 @@ impl Tokenizer {
 
     pub fn generate_refresh_token(&self) -> String {
         // Generate a random refresh token (UUID-based)
-        use uuid::Uuid;
         Uuid::new_v4().to_string()
     }
 }
 
-static TOKENIZER: Lazy<Tokenizer> = Lazy::new(|| {
-    dotenv().ok(); // Load .env file
-
-    let secret_key = env::var("JWT_SECRET")
-        .unwrap_or_else(|_| "a_very_secret_key_that_should_be_in_env".to_string());
-    let expiration_duration = env::var("TOKEN_EXPIRATION_SECONDS")
-        .unwrap_or_else(|_| "3600".to_string())
-        .parse::<i64>()
-        .unwrap_or(3600);
-
-    Tokenizer {
-        secret_key,
-        expiration_duration,
-    }
-});
+static TOKENIZER: OnceCell<Tokenizer> = OnceCell::const_new();
@@ -0,0 +1,537 @@
+use std::{env, net::IpAddr};
+
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use chrono::{Duration, Utc};
+use dotenvy::dotenv;
+use hmac::{Hmac, Mac};
+use once_cell::sync::Lazy;
+use sha1::Sha1;
+
+use crate::{
+    data::{
+        models::{login_challenges::NewLoginChallenge, user_totp::NewUserTotp, users::Users},
+        repos::{
+            implementors::{
+                login_challenge_repo::LoginChallengeRepo, recovery_code_repo::RecoveryCodeRepo,
+                user_repo::UserRepo, user_totp_repo::UserTotpRepo,
+            },
+            traits::repository::Repository,
+        },
+    },
+    services::{
+        authentication_service::AuthenticationService,
+        session_service::{db_time, hash_token},
+        throttle_service::{LoginThrottle, Throttled},
+    },
+};
+
+pub type TwoFactorError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Seconds a TOTP code is valid for, what authenticator apps assume
+const TOTP_PERIOD: i64 = 30;
+const TOTP_DIGITS: usize = 6;
+/// Steps before and after the current one that are accepted, for clocks that drift
+const TOTP_SKEW: i64 = 1;
+/// RFC 4226 recommends 160 bits
+const SECRET_BYTES: usize = 20;
+
+pub const RECOVERY_CODE_COUNT: usize = 10;
+/// Characters of a recovery code, 80 random bits shown in groups of four
+const RECOVERY_CODE_LENGTH: usize = 16;
+
+/// Wrong codes after which a login challenge is dropped and the password has to be given again
+pub const MAX_CHALLENGE_ATTEMPTS: i32 = 5;
+
+const BASE32_ALPHABET: &[u8; 32] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+
+/// Minutes a login challenge stays open, `TWO_FACTOR_CHALLENGE_MINUTES`
+static CHALLENGE_MINUTES: Lazy<i64> = Lazy::new(|| {
+    dotenv().ok();
+
+    env::var("TWO_FACTOR_CHALLENGE_MINUTES")
+        .ok()
+        .and_then(|v| v.parse::<i64>().ok())
+        .filter(|v| *v > 0)
+        .unwrap_or(5)
+});
+
+/// Name authenticator apps list the account under, configurable through `TOTP_ISSUER`
+static ISSUER: Lazy<String> = Lazy::new(|| {
+    dotenv().ok();
+
+    env::var("TOTP_ISSUER")
+        .ok()
+        .filter(|v| !v.is_empty())
+        .unwrap_or_else(|| "Stellaron".to_string())
+});
+
+/// A new secret, to be shown to the user once
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Enrollment {
+    /// Base32, for apps that can't scan the URI
+    pub secret: String,
+    /// `otpauth://` URI, usually shown as a QR code
+    pub uri: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TwoFactorStatus {
+    pub enabled: bool,
+    /// A secret was generated but not confirmed yet
+    pub pending: bool,
+    pub recovery_codes_left: i64,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum TwoFactorChange<T> {
+    Done(T),
+    /// There is no enrollment waiting for confirmation
+    NotEnrolled,
+    /// Two-factor authentication is already on, it has to be disabled first
+    AlreadyEnabled,
+    /// Two-factor authentication is off for the user
+    NotEnabled,
+    InvalidCode,
+    Throttled(Throttled),
+}
+
+/// A sign-in waiting for its second factor
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Challenge {
+    pub token: String,
+    pub expires_at: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChallengeOutcome {
+    /// The code was right, a session can be started with what the login asked for
+    Completed {
+        user_id: i32,
+        device_name: Option<String>,
+        user_agent: Option<String>,
+    },
+    /// Unknown, expired or already completed challenge
+    InvalidChallenge,
+    InvalidCode,
+    Throttled(Throttled),
+}
+
+enum CodeCheck {
+    Valid,
+    Invalid,
+    Throttled(Throttled),
+}
+
+fn base32_encode(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity(bytes.len().div_ceil(5) * 8);
+    let mut buffer: u32 = 0;
+    let mut bits = 0;
+    for byte in bytes {
+        buffer = (buffer << 8) | u32::from(*byte);
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            out.push(BASE32_ALPHABET[((buffer >> bits) & 31) as usize] as char);
+        }
+    }
+    if bits > 0 {
+        out.push(BASE32_ALPHABET[((buffer << (5 - bits)) & 31) as usize] as char);
+    }
+    out
+}
+
+/// Decodes base32 the way people type it, ignoring case, spaces and padding
+fn base32_decode(text: &str) -> Option<Vec<u8>> {
+    let mut out = Vec::with_capacity(text.len() * 5 / 8);
+    let mut buffer: u32 = 0;
+    let mut bits = 0;
+    for c in text.chars().filter(|c| !c.is_whitespace() && *c != '=') {
+        let value = BASE32_ALPHABET
+            .iter()
+            .position(|a| *a as char == c.to_ascii_uppercase())?;
+        buffer = (buffer << 5) | value as u32;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((buffer >> bits) as u8);
+        }
+    }
+    Some(out)
+}
+
+/// Percent-encodes a label or parameter of the `otpauth://` URI
+fn uri_component(value: &str) -> String {
+    value
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+/// The TOTP time step of the current time
+pub fn current_step() -> i64 {
+    Utc::now().timestamp() / TOTP_PERIOD
+}
+
+/// The RFC 6238 code of a base32 secret for a time step, `None` if the secret isn't base32
+pub fn totp_code(secret: &str, step: i64) -> Option<String> {
+    let key = base32_decode(secret)?;
+    let mut mac = Hmac::<Sha1>::new_from_slice(&key).ok()?;
+    mac.update(&step.to_be_bytes());
+    let hash = mac.finalize().into_bytes();
+
+    // Dynamic truncation, RFC 4226 section 5.3
+    let offset = (hash[hash.len() - 1] & 0x0f) as usize;
+    let binary = u32::from_be_bytes([
+        hash[offset],
+        hash[offset + 1],
+        hash[offset + 2],
+        hash[offset + 3],
+    ]) & 0x7fff_ffff;
+    Some(format!(
+        "{:0width$}",
+        binary % 10u32.pow(TOTP_DIGITS as u32),
+        width = TOTP_DIGITS
+    ))
+}
+
+fn generate_recovery_code() -> String {
+    let mut bytes = [0u8; RECOVERY_CODE_LENGTH];
+    OsRng.fill_bytes(&mut bytes);
+    let code: String = bytes
+        .iter()
+        .map(|b| BASE32_ALPHABET[(*b % 32) as usize].to_ascii_lowercase() as char)
+        .collect();
+    (0..RECOVERY_CODE_LENGTH)
+        .step_by(4)
+        .map(|i| &code[i..i + 4])
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+/// Recovery codes are compared without the dash and case, the way they may be typed
+fn normalize_code(code: &str) -> String {
+    code.chars()
+        .filter(|c| !c.is_whitespace() && *c != '-')
+        .collect::<String>()
+        .to_ascii_lowercase()
+}
+
+fn generate_challenge_token() -> String {
+    let mut bytes = [0u8; 32];
+    OsRng.fill_bytes(&mut bytes);
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// TOTP two-factor sign-in with recovery codes, API keys are unaffected
+pub struct TwoFactorService;
+
+impl TwoFactorService {
+    pub async fn new() -> Self {
+        TwoFactorService
+    }
+
+    pub async fn status(&self, user_id: i32) -> Result<TwoFactorStatus, TwoFactorError> {
+        let totp = UserTotpRepo::new().await.get_by_user(user_id).await?;
+        let enabled = totp.as_ref().is_some_and(|t| t.is_confirmed());
+        let recovery_codes_left = if enabled {
+            RecoveryCodeRepo::new().await.count_unused(user_id).await?
+        } else {
+            0
+        };
+        Ok(TwoFactorStatus {
+            enabled,
+            pending: totp.is_some() && !enabled,
+            recovery_codes_left,
+        })
+    }
+
+    pub async fn is_enabled(&self, user_id: i32) -> Result<bool, TwoFactorError> {
+        Ok(UserTotpRepo::new()
+            .await
+            .get_by_user(user_id)
+            .await?
+            .is_some_and(|t| t.is_confirmed()))
+    }
+
+    /// Generates a secret, sign-in is unaffected until it is confirmed
+    pub async fn enroll(
+        &self,
+        user_id: i32,
+    ) -> Result<TwoFactorChange<Enrollment>, TwoFactorError> {
+        let Some(user) = UserRepo::new().await.get_by_id(user_id).await? else {
+            return Ok(TwoFactorChange::NotEnrolled);
+        };
+        let repo = UserTotpRepo::new().await;
+        if repo
+            .get_by_user(user_id)
+            .await?
+            .is_some_and(|t| t.is_confirmed())
+        {
+            return Ok(TwoFactorChange::AlreadyEnabled);
+        }
+
+        let mut bytes = [0u8; SECRET_BYTES];
+        OsRng.fill_bytes(&mut bytes);
+        let secret = base32_encode(&bytes);
+        repo.replace_for_user(NewUserTotp {
+            user_id,
+            secret: &secret,
+        })
+        .await?;
+
+        let issuer = uri_component(&ISSUER);
+        let uri = format!(
+            "otpauth://totp/{}:{}?secret={}&issuer={}&algorithm=SHA1&digits={}&period={}",
+            issuer,
+            uri_component(&user.username),
+            secret,
+            issuer,
+            TOTP_DIGITS,
+            TOTP_PERIOD
+        );
+        Ok(TwoFactorChange::Done(Enrollment { secret, uri }))
+    }
+
+    /// Turns two-factor authentication on with a first code, returns the recovery codes
+    pub async fn confirm(
+        &self,
+        user_id: i32,
+        code: &str,
+    ) -> Result<TwoFactorChange<Vec<String>>, TwoFactorError> {
+        let repo = UserTotpRepo::new().await;
+        let totp = match repo.get_by_user(user_id).await? {
+            Some(totp) if totp.is_confirmed() => return Ok(TwoFactorChange::AlreadyEnabled),
+            Some(totp) => totp,
+            None => return Ok(TwoFactorChange::NotEnrolled),
+        };
+        let Some(step) = Self::matching_step(&totp.secret, code) else {
+            return Ok(TwoFactorChange::InvalidCode);
+        };
+        if !repo
+            .confirm(user_id, step, &db_time(Duration::zero()))
+            .await?
+        {
+            return Ok(TwoFactorChange::AlreadyEnabled);
+        }
+        Ok(TwoFactorChange::Done(
+            self.replace_recovery_codes(user_id).await?,
+        ))
+    }
+
+    /// Turns two-factor authentication off, which takes a current code or recovery code
+    pub async fn disable(
+        &self,
+        user_id: i32,
+        code: &str,
+    ) -> Result<TwoFactorChange<()>, TwoFactorError> {
+        match self.check_code(user_id, code, None).await? {
+            Some(CodeCheck::Valid) => {}
+            Some(CodeCheck::Invalid) => return Ok(TwoFactorChange::InvalidCode),
+            Some(CodeCheck::Throttled(throttled)) => {
+                return Ok(TwoFactorChange::Throttled(throttled))
+            }
+            None => return Ok(TwoFactorChange::NotEnabled),
+        }
+        UserTotpRepo::new().await.delete_for_user(user_id).await?;
+        Ok(TwoFactorChange::Done(()))
+    }
+
+    /// Replaces the recovery codes, which takes a current code or recovery code
+    pub async fn regenerate_recovery_codes(
+        &self,
+        user_id: i32,
+        code: &str,
+    ) -> Result<TwoFactorChange<Vec<String>>, TwoFactorError> {
+        match self.check_code(user_id, code, None).await? {
+            Some(CodeCheck::Valid) => {}
+            Some(CodeCheck::Invalid) => return Ok(TwoFactorChange::InvalidCode),
+            Some(CodeCheck::Throttled(throttled)) => {
+                return Ok(TwoFactorChange::Throttled(throttled))
+            }
+            None => return Ok(TwoFactorChange::NotEnabled),
+        }
+        Ok(TwoFactorChange::Done(
+            self.replace_recovery_codes(user_id).await?,
+        ))
+    }
+
+    /// Admin reset for a lost authenticator, `false` if it wasn't on or pending
+    pub async fn reset(&self, user_id: i32) -> Result<bool, TwoFactorError> {
+        Ok(UserTotpRepo::new().await.delete_for_user(user_id).await?)
+    }
+
+    /// Starts a sign-in for a user whose password was right
+    pub async fn start_challenge(
+        &self,
+        user_id: i32,
+        device_name: Option<&str>,
+        user_agent: Option<&str>,
+    ) -> Result<Challenge, TwoFactorError> {
+        let token = generate_challenge_token();
+        let expires_at = db_time(Duration::minutes(*CHALLENGE_MINUTES));
+        LoginChallengeRepo::new()
+            .await
+            .add(
+                NewLoginChallenge {
+                    user_id,
+                    token_hash: &hash_token(&token),
+                    device_name,
+                    user_agent,
+                    expires_at: &expires_at,
+                },
+                &db_time(Duration::zero()),
+            )
+            .await?;
+        Ok(Challenge { token, expires_at })
+    }
+
+    /// User a challenge token was handed to, `None` if it is unknown
+    pub async fn challenge_owner(&self, token: &str) -> Result<Option<i32>, TwoFactorError> {
+        Ok(LoginChallengeRepo::new()
+            .await
+            .get_by_token_hash(&hash_token(token))
+            .await?
+            .map(|challenge| challenge.user_id))
+    }
+
+    /// Completes a sign-in with a code, wrong codes count as failed sign-ins
+    pub async fn complete_challenge(
+        &self,
+        token: &str,
+        code: &str,
+        ip: Option<IpAddr>,
+    ) -> Result<ChallengeOutcome, TwoFactorError> {
+        let repo = LoginChallengeRepo::new().await;
+        let Some(challenge) = repo.get_by_token_hash(&hash_token(token)).await? else {
+            return Ok(ChallengeOutcome::InvalidChallenge);
+        };
+        if challenge.expires_at <= db_time(Duration::zero()) {
+            repo.delete(challenge.challenge_id).await?;
+            return Ok(ChallengeOutcome::InvalidChallenge);
+        }
+
+        match self.check_code(challenge.user_id, code, ip).await? {
+            Some(CodeCheck::Valid) => {}
+            Some(CodeCheck::Invalid) => {
+                if challenge.attempts + 1 >= MAX_CHALLENGE_ATTEMPTS {
+                    repo.delete(challenge.challenge_id).await?;
+                } else {
+                    repo.record_attempt(challenge.challenge_id).await?;
+                }
+                return Ok(ChallengeOutcome::InvalidCode);
+            }
+            Some(CodeCheck::Throttled(throttled)) => {
+                return Ok(ChallengeOutcome::Throttled(throttled))
+            }
+            // Turned off since the password was checked, the password alone is enough now
+            None => {}
+        }
+
+        // Only the request that removes the challenge gets the session
+        if !repo.delete(challenge.challenge_id).await? {
+            return Ok(ChallengeOutcome::InvalidChallenge);
+        }
+        Ok(ChallengeOutcome::Completed {
+            user_id: challenge.user_id,
+            device_name: challenge.device_name,
+            user_agent: challenge.user_agent,
+        })
+    }
+
+    /// The step within the accepted window whose code matches, `None` if none does
+    fn matching_step(secret: &str, code: &str) -> Option<i64> {
+        let code = code.trim();
+        if code.len() != TOTP_DIGITS || !code.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+        let now = current_step();
+        (now - TOTP_SKEW..=now + TOTP_SKEW)
+            .find(|step| totp_code(secret, *step).is_some_and(|expected| expected == code))
+    }
+
+    /// Checks and uses up a TOTP or recovery code, `None` if two-factor is off
+    async fn check_code(
+        &self,
+        user_id: i32,
+        code: &str,
+        ip: Option<IpAddr>,
+    ) -> Result<Option<CodeCheck>, TwoFactorError> {
+        let totp_repo = UserTotpRepo::new().await;
+        let Some(totp) = totp_repo
+            .get_by_user(user_id)
+            .await?
+            .filter(|t| t.is_confirmed())
+        else {
+            return Ok(None);
+        };
+        let Some(user) = UserRepo::new().await.get_by_id(user_id).await? else {
+            return Ok(None);
+        };
+
+        let throttle = LoginThrottle::global();
+        if let Err(throttled) = throttle.check(&user.username, ip) {
+            return Ok(Some(CodeCheck::Throttled(throttled)));
+        }
+
+        let valid = match Self::matching_step(&totp.secret, code) {
+            // A code is only good once, even within its window
+            Some(step) => totp_repo.use_step(user_id, step).await?,
+            None => self.use_recovery_code(&user, code).await?,
+        };
+        if valid {
+            throttle.record_success(&user.username);
+            Ok(Some(CodeCheck::Valid))
+        } else {
+            throttle.record_failure(&user.username, ip);
+            Ok(Some(CodeCheck::Invalid))
+        }
+    }
+
+    async fn use_recovery_code(&self, user: &Users, code: &str) -> Result<bool, TwoFactorError> {
+        let code = normalize_code(code);
+        if code.len() != RECOVERY_CODE_LENGTH {
+            return Ok(false);
+        }
+        let repo = RecoveryCodeRepo::new().await;
+        let unused = repo.get_unused(user.user_id).await?;
+        // Every code has its own salt, so each unused one is checked
+        let matching = tokio::task::spawn_blocking(move || {
+            let auth = AuthenticationService::new();
+            unused
+                .into_iter()
+                .find(|c| auth.verify_password(&code, &c.code_hash).unwrap_or(false))
+                .map(|c| c.code_id)
+        })
+        .await?;
+        match matching {
+            Some(id) => Ok(repo.consume(id, &db_time(Duration::zero())).await?),
+            None => Ok(false),
+        }
+    }
+
+    async fn replace_recovery_codes(&self, user_id: i32) -> Result<Vec<String>, TwoFactorError> {
+        let codes: Vec<String> = (0..RECOVERY_CODE_COUNT)
+            .map(|_| generate_recovery_code())
+            .collect();
+        let normalized: Vec<String> = codes.iter().map(|code| normalize_code(code)).collect();
+        let hashes = tokio::task::spawn_blocking(move || {
+            let auth = AuthenticationService::new();
+            normalized
+                .iter()
+                .map(|code| auth.hash_password(code))
+                .collect::<Result<Vec<String>, _>>()
+        })
+        .await?
+        .map_err(|e| e.to_string())?;
+        RecoveryCodeRepo::new()
+            .await
+            .replace_for_user(user_id, hashes)
+            .await?;
+        Ok(codes)
+    }
+}
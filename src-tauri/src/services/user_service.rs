@@ -0,0 +1,215 @@
+use chrono::Duration;
+
+use crate::{
+    data::{
+        models::users::{NewUser, Role, UpdateUser, Users},
+        repos::{implementors::user_repo::UserRepo, traits::repository::Repository},
+    },
+    services::{
+        authentication_service::AuthenticationService,
+        password_service::MIN_PASSWORD_LENGTH,
+        session_service::{db_time, SessionService},
+    },
+};
+
+pub type UserServiceError = Box<dyn std::error::Error + Send + Sync>;
+
+#[derive(Debug, PartialEq)]
+pub enum UserChange {
+    Done(Box<Users>),
+    NotFound,
+    Invalid(&'static str),
+    /// Another account already uses the username or email
+    Conflict(&'static str),
+    /// The change would leave no active admin
+    LastAdmin,
+}
+
+/// Changes to an account, `None` keeps the current value
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct UserEdit<'a> {
+    pub username: Option<&'a str>,
+    pub email: Option<&'a str>,
+    pub role: Option<Role>,
+}
+
+fn clean(value: Option<&str>) -> Option<&str> {
+    value.map(str::trim)
+}
+
+pub struct UserService;
+
+impl UserService {
+    pub async fn new() -> Self {
+        UserService
+    }
+
+    /// Whether removing admin rights from the user would leave nobody to manage the server
+    async fn is_last_admin(&self, user: &Users) -> Result<bool, UserServiceError> {
+        if user.role() != Role::Admin || user.is_disabled() {
+            return Ok(false);
+        }
+        Ok(UserRepo::new().await.count_active_admins().await? <= 1)
+    }
+
+    async fn check_unique(
+        &self,
+        id: Option<i32>,
+        username: Option<&str>,
+        email: Option<&str>,
+    ) -> Result<Option<UserChange>, UserServiceError> {
+        let repo = UserRepo::new().await;
+        let taken = |user: Option<Users>| user.is_some_and(|u| Some(u.user_id) != id);
+
+        if let Some(username) = username {
+            if username.is_empty() {
+                return Ok(Some(UserChange::Invalid("Username can't be empty")));
+            }
+            if taken(repo.get_by_username(username).await?) {
+                return Ok(Some(UserChange::Conflict("Username is already taken")));
+            }
+        }
+        if let Some(email) = email {
+            if !email.contains('@') {
+                return Ok(Some(UserChange::Invalid("Email address is invalid")));
+            }
+            if taken(repo.search_by_email(email).await?) {
+                return Ok(Some(UserChange::Conflict(
+                    "Email address is already in use",
+                )));
+            }
+        }
+        Ok(None)
+    }
+
+    async fn reload(&self, id: i32) -> Result<UserChange, UserServiceError> {
+        Ok(match UserRepo::new().await.get_by_id(id).await? {
+            Some(user) => UserChange::Done(Box::new(user)),
+            None => UserChange::NotFound,
+        })
+    }
+
+    /// Creates an account with any role, for admins
+    pub async fn create(
+        &self,
+        username: &str,
+        email: &str,
+        password: &str,
+        role: Role,
+    ) -> Result<UserChange, UserServiceError> {
+        let (username, email) = (username.trim(), email.trim());
+        if let Some(rejected) = self.check_unique(None, Some(username), Some(email)).await? {
+            return Ok(rejected);
+        }
+        if password.chars().count() < MIN_PASSWORD_LENGTH {
+            return Ok(UserChange::Invalid("Password is too short"));
+        }
+
+        let password_hash = AuthenticationService::new()
+            .hash_password_async(password)
+            .await?;
+        let repo = UserRepo::new().await;
+        repo.add(NewUser {
+            username,
+            email,
+            role: role.as_str(),
+            password_hash: &password_hash,
+        })
+        .await?;
+
+        Ok(match repo.get_by_username(username).await? {
+            Some(user) => UserChange::Done(Box::new(user)),
+            None => UserChange::NotFound,
+        })
+    }
+
+    /// Changes username, email or role of an account
+    pub async fn update(
+        &self,
+        id: i32,
+        edit: UserEdit<'_>,
+    ) -> Result<UserChange, UserServiceError> {
+        let repo = UserRepo::new().await;
+        let Some(user) = repo.get_by_id(id).await? else {
+            return Ok(UserChange::NotFound);
+        };
+        let (username, email) = (clean(edit.username), clean(edit.email));
+        if let Some(rejected) = self.check_unique(Some(id), username, email).await? {
+            return Ok(rejected);
+        }
+        if edit.role.is_some_and(|role| role != Role::Admin) && self.is_last_admin(&user).await? {
+            return Ok(UserChange::LastAdmin);
+        }
+
+        if username.is_some() || email.is_some() || edit.role.is_some() {
+            repo.update(
+                id,
+                UpdateUser {
+                    username,
+                    email,
+                    role: edit.role.map(|role| role.as_str()),
+                    password_hash: None,
+                },
+            )
+            .await?;
+        }
+        self.reload(id).await
+    }
+
+    /// Changes the signed in user's own username or email, the role stays
+    pub async fn update_profile(
+        &self,
+        id: i32,
+        username: Option<&str>,
+        email: Option<&str>,
+    ) -> Result<UserChange, UserServiceError> {
+        self.update(
+            id,
+            UserEdit {
+                username,
+                email,
+                role: None,
+            },
+        )
+        .await
+    }
+
+    /// Disables or re-enables an account. Disabling signs the user out everywhere.
+    pub async fn set_disabled(
+        &self,
+        id: i32,
+        disabled: bool,
+    ) -> Result<UserChange, UserServiceError> {
+        let repo = UserRepo::new().await;
+        let Some(user) = repo.get_by_id(id).await? else {
+            return Ok(UserChange::NotFound);
+        };
+
+        if disabled {
+            if self.is_last_admin(&user).await? {
+                return Ok(UserChange::LastAdmin);
+            }
+            if !user.is_disabled() {
+                repo.set_disabled(id, Some(&db_time(Duration::zero())))
+                    .await?;
+            }
+            SessionService::new().await.revoke_all(id).await?;
+        } else {
+            repo.set_disabled(id, None).await?;
+        }
+        self.reload(id).await
+    }
+
+    /// Deletes an account, see [`UserRepo`]'s `delete` for what happens to its data
+    pub async fn delete(&self, id: i32) -> Result<UserChange, UserServiceError> {
+        let repo = UserRepo::new().await;
+        let Some(user) = repo.get_by_id(id).await? else {
+            return Ok(UserChange::NotFound);
+        };
+        if self.is_last_admin(&user).await? {
+            return Ok(UserChange::LastAdmin);
+        }
+        repo.delete(id).await?;
+        Ok(UserChange::Done(Box::new(user)))
+    }
+}
@@ -1,21 +1,15 @@
 //TODO: Move mappers (From and Into) here
-use crate::controllers::dto::user_dto::{NewUserDTO, UserDTO};
+use crate::controllers::dto::library_dto::{LibraryDTO, LibraryShareDTO};
+use crate::controllers::dto::user_dto::UserDTO;
+use crate::data::models::libraries::Library;
+use crate::data::models::library_shares::LibraryShares;
 use crate::data::models::users::Users;
 
-impl From<NewUserDTO> for UserDTO {
-    fn from(user: NewUserDTO) -> Self {
-        UserDTO {
-            username: user.username.to_string(),
-            email: user.email.to_string(),
-            created_at: user.created_at.map(|s| s.to_string()),
-            role: user.role.map(|s| s.to_string()),
-        }
-    }
-}
-
 impl From<Users> for UserDTO {
     fn from(user: Users) -> Self {
         UserDTO {
+            user_id: user.user_id,
+            disabled: user.is_disabled(),
             username: user.username,
             email: user.email,
             created_at: user.created_at,
@@ -23,3 +17,27 @@ impl From<Users> for UserDTO {
         }
     }
 }
+
+impl From<Library> for LibraryDTO {
+    fn from(library: Library) -> Self {
+        LibraryDTO {
+            library_id: library.library_id,
+            visibility: library.visibility().as_str().to_string(),
+            name: library.name,
+            path: library.path,
+            added_at: library.added_at,
+            owner_id: library.added_by,
+        }
+    }
+}
+
+impl From<LibraryShares> for LibraryShareDTO {
+    fn from(share: LibraryShares) -> Self {
+        LibraryShareDTO {
+            share_id: share.share_id,
+            user_id: share.user_id,
+            role: share.role,
+            created_at: share.created_at,
+        }
+    }
+}
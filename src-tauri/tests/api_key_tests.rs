@@ -0,0 +1,277 @@
+mod common;
+
+use axum::extract::FromRequestParts;
+use axum::http::{header, request::Parts, Request, StatusCode};
+use axum::response::IntoResponse;
+use base64::Engine;
+use diesel::prelude::*;
+use diesel_async::RunQueryDsl;
+
+use stellaron_lib::controllers::auth_middleware::{
+    AdminUser, AuthUser, DeviceUser, DownloadUser, LoginUser, MemberUser, SyncUser,
+};
+use stellaron_lib::data::database;
+use stellaron_lib::data::models::api_keys::ApiScope;
+use stellaron_lib::data::repos::implementors::api_key_repo::ApiKeyRepo;
+use stellaron_lib::data::repos::implementors::user_repo::UserRepo;
+use stellaron_lib::data::repos::traits::repository::Repository;
+use stellaron_lib::services::api_key_service::{ApiKeyCreation, ApiKeyService, CreatedApiKey};
+use stellaron_lib::services::session_service::hash_token;
+
+use common::{create_user, setup};
+
+async fn create_key(user_id: i32, scopes: &[ApiScope], expires: Option<i64>) -> CreatedApiKey {
+    match ApiKeyService::new()
+        .await
+        .create(user_id, "Kobo", scopes, expires)
+        .await
+        .expect("Failed to create API key")
+    {
+        ApiKeyCreation::Created(created) => *created,
+        other => panic!("Expected a key, got {:?}", other),
+    }
+}
+
+fn parts(authorization: &str) -> Parts {
+    let (parts, _) = Request::builder()
+        .header(header::AUTHORIZATION, authorization)
+        .body(())
+        .unwrap()
+        .into_parts();
+    parts
+}
+
+fn bearer(key: &str) -> String {
+    format!("Bearer {}", key)
+}
+
+fn basic(username: &str, password: &str) -> String {
+    let encoded =
+        base64::engine::general_purpose::STANDARD.encode(format!("{}:{}", username, password));
+    format!("Basic {}", encoded)
+}
+
+/// Runs an extractor and returns the user id or the rejection's status
+macro_rules! extract {
+    ($extractor:ty, $authorization:expr) => {{
+        let mut parts = parts(&$authorization);
+        <$extractor>::from_request_parts(&mut parts, &())
+            .await
+            .map(|user| user.id)
+            .map_err(|e| e.into_response().status())
+    }};
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn test_create_stores_hash() {
+    setup().await.expect("Setup failed");
+    let uid = create_user("key_owner", "user").await;
+    let created = create_key(uid, &[ApiScope::Progress, ApiScope::Catalog], Some(30)).await;
+
+    assert!(created.key.starts_with("stk_"));
+    assert!(created.key.starts_with(&created.api_key.prefix));
+    assert_eq!(created.api_key.scopes, "catalog,progress");
+    assert!(created.api_key.expires_at.is_some());
+    assert!(created.api_key.last_used_at.is_none());
+
+    let repo = ApiKeyRepo::new().await;
+    assert!(repo.get_by_key_hash(&created.key).await.unwrap().is_none());
+    assert_eq!(
+        repo.get_by_key_hash(&hash_token(&created.key))
+            .await
+            .unwrap()
+            .unwrap()
+            .api_key_id,
+        created.api_key.api_key_id
+    );
+
+    let service = ApiKeyService::new().await;
+    let authenticated = service.authenticate(&created.key).await.unwrap().unwrap();
+    assert_eq!(authenticated.user_id, uid);
+    let stored = repo
+        .get_by_id(created.api_key.api_key_id)
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(stored.last_used_at.is_some());
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn test_create_validation() {
+    setup().await.expect("Setup failed");
+    let uid = create_user("key_validation", "user").await;
+    let service = ApiKeyService::new().await;
+
+    assert_eq!(
+        service
+            .create(uid, " ", &[ApiScope::Catalog], None)
+            .await
+            .unwrap(),
+        ApiKeyCreation::MissingName
+    );
+    assert_eq!(
+        service.create(uid, "Kobo", &[], None).await.unwrap(),
+        ApiKeyCreation::MissingScopes
+    );
+    assert_eq!(
+        service
+            .create(uid, "Kobo", &[ApiScope::Catalog], Some(0))
+            .await
+            .unwrap(),
+        ApiKeyCreation::InvalidExpiry
+    );
+    // Expiries past the clock's range are refused instead of overflowing
+    for days in [3651, i64::MAX] {
+        assert_eq!(
+            service
+                .create(uid, "Kobo", &[ApiScope::Catalog], Some(days))
+                .await
+                .unwrap(),
+            ApiKeyCreation::InvalidExpiry
+        );
+    }
+    assert_eq!(
+        service
+            .create(uid, "Kobo", &[ApiScope::Admin], None)
+            .await
+            .unwrap(),
+        ApiKeyCreation::AdminScopeDenied
+    );
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn test_scopes_are_enforced() {
+    setup().await.expect("Setup failed");
+    let uid = create_user("key_scopes", "user").await;
+    let catalog = create_key(uid, &[ApiScope::Catalog], None).await.key;
+    let progress = create_key(uid, &[ApiScope::Progress], None).await.key;
+
+    assert_eq!(extract!(AuthUser, bearer(&catalog)), Ok(uid));
+    assert_eq!(extract!(DeviceUser, bearer(&catalog)), Ok(uid));
+    assert_eq!(
+        extract!(SyncUser, bearer(&catalog)),
+        Err(StatusCode::FORBIDDEN)
+    );
+    assert_eq!(
+        extract!(DownloadUser, bearer(&catalog)),
+        Err(StatusCode::FORBIDDEN)
+    );
+
+    assert_eq!(extract!(SyncUser, bearer(&progress)), Ok(uid));
+    assert_eq!(extract!(MemberUser, bearer(&progress)), Ok(uid));
+    assert_eq!(
+        extract!(AuthUser, bearer(&progress)),
+        Err(StatusCode::FORBIDDEN)
+    );
+
+    // Keys can't manage the account
+    assert_eq!(
+        extract!(LoginUser, bearer(&catalog)),
+        Err(StatusCode::FORBIDDEN)
+    );
+    assert_eq!(
+        extract!(AuthUser, bearer("stk_unknown")),
+        Err(StatusCode::UNAUTHORIZED)
+    );
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn test_admin_scope_follows_role() {
+    setup().await.expect("Setup failed");
+    let admin = create_user("key_admin", "admin").await;
+    let key = create_key(admin, &[ApiScope::Admin], None).await.key;
+    let catalog = create_key(admin, &[ApiScope::Catalog], None).await.key;
+
+    assert_eq!(extract!(AdminUser, bearer(&key)), Ok(admin));
+    assert_eq!(
+        extract!(AdminUser, bearer(&catalog)),
+        Err(StatusCode::FORBIDDEN)
+    );
+
+    // A demoted admin loses what the key granted
+    UserRepo::new()
+        .await
+        .update(
+            admin,
+            stellaron_lib::data::models::users::UpdateUser {
+                username: None,
+                email: None,
+                role: Some("user"),
+                password_hash: None,
+            },
+        )
+        .await
+        .unwrap();
+    assert_eq!(
+        extract!(AdminUser, bearer(&key)),
+        Err(StatusCode::FORBIDDEN)
+    );
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn test_basic_with_key_as_password() {
+    setup().await.expect("Setup failed");
+    let uid = create_user("key_basic", "user").await;
+    create_user("key_other", "user").await;
+    let key = create_key(uid, &[ApiScope::Catalog, ApiScope::Download], None)
+        .await
+        .key;
+
+    assert_eq!(extract!(DeviceUser, basic("key_basic", &key)), Ok(uid));
+    assert_eq!(extract!(DownloadUser, basic("key_basic", &key)), Ok(uid));
+    // The key only works for its owner
+    assert_eq!(
+        extract!(DeviceUser, basic("key_other", &key)),
+        Err(StatusCode::UNAUTHORIZED)
+    );
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn test_revoked_and_expired_keys() {
+    setup().await.expect("Setup failed");
+    let uid = create_user("key_revoke", "user").await;
+    let intruder = create_user("key_intruder", "user").await;
+    let service = ApiKeyService::new().await;
+
+    let created = create_key(uid, &[ApiScope::Catalog], None).await;
+    let other = create_key(uid, &[ApiScope::Catalog], None).await;
+    assert_eq!(service.list(uid).await.unwrap().len(), 2);
+
+    assert!(!service
+        .revoke(intruder, created.api_key.api_key_id)
+        .await
+        .unwrap());
+    assert!(service
+        .revoke(uid, created.api_key.api_key_id)
+        .await
+        .unwrap());
+    assert!(!service
+        .revoke(uid, created.api_key.api_key_id)
+        .await
+        .unwrap());
+    assert!(service.authenticate(&created.key).await.unwrap().is_none());
+    assert_eq!(
+        extract!(AuthUser, bearer(&created.key)),
+        Err(StatusCode::UNAUTHORIZED)
+    );
+
+    let keys = service.list(uid).await.unwrap();
+    assert_eq!(keys.len(), 1);
+    assert_eq!(keys[0].api_key_id, other.api_key.api_key_id);
+
+    let expired = create_key(uid, &[ApiScope::Catalog], None).await;
+    diesel::update(
+        stellaron_lib::data::models::schema::api_keys::table.find(expired.api_key.api_key_id),
+    )
+    .set(stellaron_lib::data::models::schema::api_keys::expires_at.eq("2020-01-01 00:00:00"))
+    .execute(&mut database::connect_from_pool().await.unwrap())
+    .await
+    .unwrap();
+    assert!(service.authenticate(&expired.key).await.unwrap().is_none());
+}
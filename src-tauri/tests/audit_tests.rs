@@ -0,0 +1,152 @@
+mod common;
+
+use std::net::{IpAddr, Ipv4Addr};
+
+use diesel::prelude::*;
+use diesel_async::RunQueryDsl;
+
+use stellaron_lib::data::database;
+use stellaron_lib::data::models::audit_events::AuditAction;
+use stellaron_lib::data::repos::implementors::audit_event_repo::{AuditEventRepo, AuditFilter};
+use stellaron_lib::services::audit_service::{AuditEntry, AuditService, RequestOrigin};
+
+use common::{create_user, setup};
+
+fn origin(last: u8) -> RequestOrigin {
+    RequestOrigin {
+        ip: Some(IpAddr::V4(Ipv4Addr::new(192, 0, 2, last))),
+        user_agent: Some("Koreader".to_string()),
+    }
+}
+
+#[test]
+fn test_actions_round_trip() {
+    for action in AuditAction::ALL {
+        assert_eq!(AuditAction::parse(action.as_str()), Some(action));
+    }
+    assert_eq!(
+        AuditAction::parse(" Login_Failed "),
+        Some(AuditAction::LoginFailed)
+    );
+    assert_eq!(AuditAction::parse("logged_in"), None);
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn test_record_and_query() {
+    setup().await.expect("Setup failed");
+    let admin_id = create_user("auditor", "user").await;
+    let user_id = create_user("audited", "user").await;
+    let service = AuditService::new().await;
+
+    service
+        .record(
+            &origin(1),
+            AuditAction::LoginFailed,
+            AuditEntry::attempt("nobody"),
+        )
+        .await;
+    service
+        .record(&origin(2), AuditAction::Login, AuditEntry::by(user_id))
+        .await;
+    service
+        .record(
+            &origin(1),
+            AuditAction::RoleChanged,
+            AuditEntry::by(admin_id)
+                .target("user", user_id)
+                .details("user -> admin"),
+        )
+        .await;
+
+    let (events, total) = service
+        .events(&AuditFilter::default(), 1, 50)
+        .await
+        .unwrap();
+    assert_eq!(total, 3);
+    // Newest first, with the actor's name looked up
+    assert_eq!(events[0].action, "role_changed");
+    assert_eq!(events[0].username.as_deref(), Some("auditor"));
+    assert_eq!(events[0].target_type.as_deref(), Some("user"));
+    assert_eq!(events[0].target_id, Some(user_id));
+    assert_eq!(events[0].ip.as_deref(), Some("192.0.2.1"));
+    assert_eq!(events[0].user_agent.as_deref(), Some("Koreader"));
+    // Unknown accounts keep the name that was tried
+    assert_eq!(events[2].user_id, None);
+    assert_eq!(events[2].username.as_deref(), Some("nobody"));
+
+    let by_ip = AuditFilter {
+        ip: Some("192.0.2.1".to_string()),
+        ..Default::default()
+    };
+    assert_eq!(service.events(&by_ip, 1, 50).await.unwrap().1, 2);
+    let by_action = AuditFilter {
+        action: Some("login".to_string()),
+        user_id: Some(user_id),
+        ..Default::default()
+    };
+    let (events, total) = service.events(&by_action, 1, 50).await.unwrap();
+    assert_eq!(total, 1);
+    assert_eq!(events[0].user_id, Some(user_id));
+
+    // Pages keep the total of all matches
+    let (events, total) = service.events(&AuditFilter::default(), 2, 2).await.unwrap();
+    assert_eq!((events.len(), total), (1, 3));
+    assert_eq!(events[0].action, "login_failed");
+
+    let future = AuditFilter {
+        since: Some("2999-01-01 00:00:00".to_string()),
+        ..Default::default()
+    };
+    assert_eq!(service.events(&future, 1, 50).await.unwrap().1, 0);
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn test_events_are_append_only() {
+    setup().await.expect("Setup failed");
+    let tamperer = create_user("tamperer", "user").await;
+    AuditService::new()
+        .await
+        .record(&origin(3), AuditAction::Logout, AuditEntry::by(tamperer))
+        .await;
+
+    let mut conn = database::connect_from_pool().await.unwrap();
+    use stellaron_lib::data::models::schema::audit_events::dsl::*;
+    let changed = diesel::update(audit_events)
+        .set(action.eq("login"))
+        .execute(&mut conn)
+        .await;
+    assert!(changed.is_err());
+    let actions: Vec<String> = audit_events.select(action).load(&mut conn).await.unwrap();
+    assert_eq!(actions, vec!["logout".to_string()]);
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn test_old_events_are_removed() {
+    setup().await.expect("Setup failed");
+    let service = AuditService::new().await;
+    service
+        .record(
+            &RequestOrigin::default(),
+            AuditAction::LoginFailed,
+            AuditEntry::attempt("old"),
+        )
+        .await;
+
+    let repo = AuditEventRepo::new().await;
+    assert_eq!(repo.delete_before("2000-01-01 00:00:00").await.unwrap(), 0);
+    // Within the default retention of a year
+    assert_eq!(service.purge_expired().await.unwrap(), 0);
+    assert_eq!(
+        service
+            .events(&AuditFilter::default(), 1, 50)
+            .await
+            .unwrap()
+            .1,
+        1
+    );
+    assert_eq!(repo.delete_before("2999-01-01 00:00:00").await.unwrap(), 1);
+    assert_eq!(AuditService::retention_days(), Some(365));
+}
@@ -32,7 +32,10 @@ async fn setup() -> Result<(), Error> {
 /// Helper function to create a test author
 async fn create_test_author(name_val: &str) -> Result<(), Error> {
     let repo = AuthorRepo::new().await;
-    let new_author = NewAuthor { name: name_val };
+    let new_author = NewAuthor {
+        name: name_val,
+        sort_name: None,
+    };
 
     repo.add(new_author).await
 }
@@ -116,6 +119,7 @@ async fn test_update_author() {
     let new_name = "Updated Name";
     let form = AuthorForm {
         name: Some(new_name),
+        sort_name: None,
     };
     let result = repo.update(author_id, form).await;
     assert!(result.is_ok());
@@ -176,3 +180,23 @@ async fn test_search_author_by_name() {
     assert!(results.iter().any(|a| a.name == "George Orwell"));
     assert!(results.iter().any(|a| a.name == "George R. R. Martin"));
 }
+
+#[tokio::test]
+#[serial_test::serial]
+async fn test_get_or_create_author() {
+    setup().await.expect("Failed to set up test");
+
+    let repo = AuthorRepo::new().await;
+    let created = repo
+        .get_or_create("Ursula K. Le Guin")
+        .await
+        .expect("Failed to create author");
+    let existing = repo
+        .get_or_create("Ursula K. Le Guin")
+        .await
+        .expect("Failed to get author");
+
+    assert_eq!(created.author_id, existing.author_id);
+    assert_eq!(repo.get_all().await.unwrap().unwrap().len(), 1);
+    assert!(repo.get_by_name("Le Guin").await.unwrap().is_none());
+}
@@ -0,0 +1,190 @@
+mod common;
+
+use axum::extract::FromRequestParts;
+use axum::http::{header, request::Parts, Method, Request, StatusCode};
+use axum::response::IntoResponse;
+use axum::Json;
+
+use stellaron_lib::api;
+use stellaron_lib::controllers::auth_middleware::{AdminUser, AuthUser, MemberUser};
+use stellaron_lib::controllers::dto::user_dto::NewUserDTO;
+use stellaron_lib::controllers::user_controller;
+use stellaron_lib::data::models::users::Role;
+use stellaron_lib::data::repos::implementors::user_repo::UserRepo;
+use stellaron_lib::data::repos::traits::repository::Repository;
+use stellaron_lib::services::audit_service::RequestOrigin;
+use stellaron_lib::services::registration_service::{RegistrationPolicy, RegistrationService};
+use stellaron_lib::services::token_service::Tokenizer;
+
+use common::{create_user, setup};
+
+/// Request parts carrying a bearer token for the user
+async fn parts_for(user_id: i32) -> Parts {
+    let token = Tokenizer::get_instance()
+        .await
+        .generate_token(user_id)
+        .expect("Failed to generate token");
+    let (parts, _) = Request::builder()
+        .header(header::AUTHORIZATION, format!("Bearer {}", token))
+        .body(())
+        .unwrap()
+        .into_parts();
+    parts
+}
+
+#[test]
+fn test_role_order() {
+    assert_eq!(Role::parse("Admin"), Some(Role::Admin));
+    assert_eq!(Role::parse(" guest "), Some(Role::Guest));
+    assert_eq!(Role::parse("owner"), None);
+
+    assert!(Role::Admin.allows(Role::User));
+    assert!(Role::User.allows(Role::User));
+    assert!(!Role::User.allows(Role::Admin));
+    assert!(!Role::Guest.allows(Role::User));
+    assert_eq!(Role::User.as_str(), "user");
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn test_role_extractors() {
+    setup().await.expect("Setup failed");
+    let admin = create_user("admin", "admin").await;
+    let user = create_user("user", "user").await;
+    let guest = create_user("guest", "guest").await;
+    // Users without a known role are treated as guests
+    let unknown = create_user("unknown", "owner").await;
+
+    for (id, member, is_admin) in [
+        (admin, true, true),
+        (user, true, false),
+        (guest, false, false),
+        (unknown, false, false),
+    ] {
+        let result = AuthUser::from_request_parts(&mut parts_for(id).await, &()).await;
+        assert_eq!(result.ok().map(|u| u.id), Some(id));
+
+        let result = MemberUser::from_request_parts(&mut parts_for(id).await, &()).await;
+        match result {
+            Ok(u) => assert!(member && u.id == id),
+            Err(e) => {
+                assert!(!member);
+                assert_eq!(e.into_response().status(), StatusCode::FORBIDDEN);
+            }
+        }
+
+        let result = AdminUser::from_request_parts(&mut parts_for(id).await, &()).await;
+        match result {
+            Ok(u) => assert!(is_admin && u.id == id),
+            Err(e) => {
+                assert!(!is_admin);
+                assert_eq!(e.into_response().status(), StatusCode::FORBIDDEN);
+            }
+        }
+    }
+
+    // A token of a deleted user is no longer accepted
+    UserRepo::new()
+        .await
+        .delete(admin)
+        .await
+        .expect("Failed to delete user");
+    let result = AdminUser::from_request_parts(&mut parts_for(admin).await, &()).await;
+    assert_eq!(
+        result.err().map(|e| e.into_response().status()),
+        Some(StatusCode::UNAUTHORIZED)
+    );
+}
+
+async fn register(username: &str, role: Option<&str>) -> StatusCode {
+    user_controller::create_user(
+        RequestOrigin::default(),
+        Json(NewUserDTO {
+            username: username.to_string(),
+            email: format!("{}@test.com", username),
+            password: "password".to_string(),
+            role: role.map(str::to_string),
+            created_at: None,
+            invite_code: None,
+        }),
+    )
+    .await
+    .into_response()
+    .status()
+}
+
+async fn role_of(username: &str) -> Role {
+    UserRepo::new()
+        .await
+        .get_by_username(username)
+        .await
+        .unwrap()
+        .unwrap()
+        .role()
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn test_first_registration_becomes_admin() {
+    setup().await.expect("Setup failed");
+
+    assert_eq!(register("owner", None).await, StatusCode::CREATED);
+    assert_eq!(role_of("owner").await, Role::Admin);
+
+    // Later registrations can't pick their own role
+    RegistrationService::new()
+        .await
+        .set_policy(RegistrationPolicy::Open)
+        .await
+        .unwrap();
+    assert_eq!(
+        register("visitor", Some("admin")).await,
+        StatusCode::CREATED
+    );
+    assert_eq!(role_of("visitor").await, Role::User);
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn test_management_routes_need_an_admin() {
+    setup().await.expect("Setup failed");
+    let admin = create_user("router_admin", "admin").await;
+    let user = create_user("router_user", "user").await;
+    let guest = create_user("router_guest", "guest").await;
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let base = format!("http://{}", listener.local_addr().unwrap());
+    tokio::spawn(async move { axum::serve(listener, api::router()).await });
+
+    let client = reqwest::Client::new();
+    let routes = [
+        (Method::POST, "/libraries".to_string()),
+        (Method::POST, "/libraries/999999/scan".to_string()),
+        (Method::POST, "/users".to_string()),
+        (Method::PUT, format!("/users/{}", guest)),
+        (Method::PUT, "/book/999999/metadata".to_string()),
+    ];
+    for (method, path) in &routes {
+        for (id, allowed) in [(guest, false), (user, false), (admin, true)] {
+            let token = Tokenizer::get_instance()
+                .await
+                .generate_token(id)
+                .expect("Failed to generate token");
+            let response = client
+                .request(method.clone(), format!("{}{}", base, path))
+                .bearer_auth(token)
+                .json(&serde_json::json!({}))
+                .send()
+                .await
+                .unwrap();
+            assert_eq!(
+                response.status() == StatusCode::FORBIDDEN,
+                !allowed,
+                "{} {} as user {}",
+                method,
+                path,
+                id
+            );
+        }
+    }
+}
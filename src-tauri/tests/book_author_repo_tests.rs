@@ -38,7 +38,10 @@ async fn setup() -> Result<(), Error> {
 /// Helper function to create a test author and return its ID
 async fn create_test_author(name_val: &str) -> i32 {
     let repo = AuthorRepo::new().await;
-    let new_author = NewAuthor { name: name_val };
+    let new_author = NewAuthor {
+        name: name_val,
+        sort_name: None,
+    };
     repo.add(new_author)
         .await
         .expect("Failed to create test author");
@@ -80,6 +83,7 @@ async fn create_test_book(title_val: &str, publisher_id_val: i32) -> i32 {
         file_type: None,
         file_path: None,
         cover_image_path: None,
+        ..Default::default()
     };
     repo.add(new_book)
         .await
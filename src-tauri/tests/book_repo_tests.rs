@@ -4,9 +4,11 @@ use diesel_async::RunQueryDsl;
 use stellaron_lib::data::database;
 use stellaron_lib::data::models::books::{NewBook, UpdateBook};
 use stellaron_lib::data::models::publishers::NewPublisher;
+use stellaron_lib::data::repos::implementors::book_identifier_repo::BookIdentifierRepo;
 use stellaron_lib::data::repos::implementors::book_repo::BookRepo;
 use stellaron_lib::data::repos::implementors::publisher_repo::PublisherRepo;
 use stellaron_lib::data::repos::traits::repository::Repository;
+use stellaron_lib::services::book_service;
 
 /// Helper function to clear the tables before each test
 async fn setup() -> Result<(), Error> {
@@ -61,6 +63,7 @@ async fn create_test_book(title_val: &str, publisher_id_val: i32) -> Result<(),
         file_type: None,
         file_path: None,
         cover_image_path: None,
+        ..Default::default()
     };
 
     repo.add(new_book).await
@@ -146,6 +149,7 @@ async fn test_update_book() {
         file_type: None,
         file_path: None,
         cover_image_path: None,
+        ..Default::default()
     };
     let result = repo.update(book_id, form).await;
     assert!(result.is_ok());
@@ -231,3 +235,93 @@ async fn test_search_book_by_publisher() {
     assert!(results.iter().any(|b| b.title == "Book 1"));
     assert!(results.iter().any(|b| b.title == "Book 3"));
 }
+
+#[tokio::test]
+#[serial_test::serial]
+async fn test_search_book_by_isbn_any_format() {
+    setup().await.expect("Failed to set up test");
+    let publisher_id = create_test_publisher("Test Publisher").await;
+
+    let repo = BookRepo::new().await;
+    repo.add(NewBook {
+        title: "Fantastic Mr Fox",
+        publisher_id: Some(publisher_id),
+        isbn: Some("9780140328721"),
+        ..Default::default()
+    })
+    .await
+    .unwrap();
+    // Stored as ISBN-10 before normalization existed
+    repo.add(NewBook {
+        title: "The Hobbit",
+        publisher_id: Some(publisher_id),
+        isbn: Some("0618002219"),
+        ..Default::default()
+    })
+    .await
+    .unwrap();
+    repo.add(NewBook {
+        title: "Notes",
+        publisher_id: Some(publisher_id),
+        isbn: Some("not an isbn"),
+        ..Default::default()
+    })
+    .await
+    .unwrap();
+
+    // Only the canonical form is matched, older values are converted once
+    assert!(repo
+        .search_by_isbn("978-0-618-00221-4")
+        .await
+        .expect("Failed to search by isbn")
+        .is_none());
+    assert_eq!(book_service::normalize_stored_isbns().await.unwrap(), 2);
+    assert_eq!(book_service::normalize_stored_isbns().await.unwrap(), 0);
+
+    for query in [
+        "978-0-14-032872-1",
+        "urn:isbn:0140328726",
+        "ISBN 0-14-032872-6",
+    ] {
+        let results = repo
+            .search_by_isbn(query)
+            .await
+            .expect("Failed to search by isbn")
+            .unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].title, "Fantastic Mr Fox");
+    }
+
+    let results = repo
+        .search_by_isbn("978-0-618-00221-4")
+        .await
+        .expect("Failed to search by isbn")
+        .unwrap();
+    assert_eq!(results[0].title, "The Hobbit");
+    assert_eq!(results[0].isbn.as_deref(), Some("9780618002214"));
+
+    // An invalid value is kept as an identifier instead of the ISBN
+    let notes = repo
+        .search_by_title("Notes")
+        .await
+        .unwrap()
+        .unwrap()
+        .remove(0);
+    assert_eq!(notes.isbn, None);
+    let identifiers = BookIdentifierRepo::new()
+        .await
+        .get_by_book(notes.book_id)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(identifiers[0].scheme, "isbn");
+    assert_eq!(identifiers[0].value, "not an isbn");
+
+    // Partial input falls back to a digit match
+    let results = repo
+        .search_by_isbn("0328")
+        .await
+        .expect("Failed to search by isbn")
+        .unwrap();
+    assert_eq!(results.len(), 1);
+}
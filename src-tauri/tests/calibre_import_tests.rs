@@ -0,0 +1,301 @@
+mod common;
+
+use diesel::connection::SimpleConnection;
+use diesel::{Connection, SqliteConnection};
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use stellaron_lib::data::repos::implementors::book_author_repo::BookAuthorRepo;
+use stellaron_lib::data::repos::implementors::book_file_repo::BookFileRepo;
+use stellaron_lib::data::repos::implementors::book_identifier_repo::BookIdentifierRepo;
+use stellaron_lib::data::repos::implementors::book_repo::BookRepo;
+use stellaron_lib::data::repos::implementors::book_tag_repo::BookTagRepo;
+use stellaron_lib::data::repos::implementors::series_repo::SeriesRepo;
+use stellaron_lib::data::repos::traits::repository::Repository;
+use stellaron_lib::services::library_service::{
+    CalibreImportOptions, ColumnTarget, LibraryService,
+};
+
+use common::{create_user, setup};
+
+/// Trimmed down version of the tables Calibre creates in `metadata.db`
+const CALIBRE_SCHEMA: &str = "
+    CREATE TABLE books (id INTEGER PRIMARY KEY, title TEXT NOT NULL, sort TEXT,
+        timestamp TIMESTAMP, pubdate TIMESTAMP, series_index REAL NOT NULL DEFAULT 1.0,
+        author_sort TEXT, isbn TEXT DEFAULT '', path TEXT NOT NULL DEFAULT '',
+        uuid TEXT, has_cover BOOL DEFAULT 0);
+    CREATE TABLE authors (id INTEGER PRIMARY KEY, name TEXT NOT NULL, sort TEXT, link TEXT);
+    CREATE TABLE books_authors_link (id INTEGER PRIMARY KEY, book INTEGER, author INTEGER);
+    CREATE TABLE series (id INTEGER PRIMARY KEY, name TEXT NOT NULL, sort TEXT);
+    CREATE TABLE books_series_link (id INTEGER PRIMARY KEY, book INTEGER, series INTEGER);
+    CREATE TABLE tags (id INTEGER PRIMARY KEY, name TEXT NOT NULL);
+    CREATE TABLE books_tags_link (id INTEGER PRIMARY KEY, book INTEGER, tag INTEGER);
+    CREATE TABLE publishers (id INTEGER PRIMARY KEY, name TEXT NOT NULL, sort TEXT);
+    CREATE TABLE books_publishers_link (id INTEGER PRIMARY KEY, book INTEGER, publisher INTEGER);
+    CREATE TABLE identifiers (id INTEGER PRIMARY KEY, book INTEGER, type TEXT, val TEXT);
+    CREATE TABLE ratings (id INTEGER PRIMARY KEY, rating INTEGER);
+    CREATE TABLE books_ratings_link (id INTEGER PRIMARY KEY, book INTEGER, rating INTEGER);
+    CREATE TABLE comments (id INTEGER PRIMARY KEY, book INTEGER, text TEXT);
+    CREATE TABLE languages (id INTEGER PRIMARY KEY, lang_code TEXT);
+    CREATE TABLE books_languages_link (id INTEGER PRIMARY KEY, book INTEGER,
+        lang_code INTEGER, item_order INTEGER DEFAULT 0);
+    CREATE TABLE data (id INTEGER PRIMARY KEY, book INTEGER, format TEXT,
+        uncompressed_size INTEGER, name TEXT);
+    CREATE TABLE custom_columns (id INTEGER PRIMARY KEY, label TEXT, name TEXT, datatype TEXT,
+        mark_for_delete BOOL DEFAULT 0, editable BOOL DEFAULT 1, display TEXT DEFAULT '{}',
+        is_multiple BOOL DEFAULT 0, normalized BOOL);
+    CREATE TABLE custom_column_1 (id INTEGER PRIMARY KEY, value TEXT);
+    CREATE TABLE books_custom_column_1_link (id INTEGER PRIMARY KEY, book INTEGER, value INTEGER);
+    CREATE TABLE custom_column_2 (id INTEGER PRIMARY KEY, book INTEGER, value INTEGER);
+    CREATE TABLE custom_column_3 (id INTEGER PRIMARY KEY, book INTEGER, value TEXT);
+";
+
+const CALIBRE_DATA: &str = "
+    INSERT INTO books VALUES
+        (1, 'Fantastic Mr Fox', 'Fantastic Mr Fox', '2020-01-01', '1970-01-01 00:00:00+00:00',
+         2.0, 'Dahl, Roald', '', 'Roald Dahl/Fantastic Mr Fox (1)',
+         '11111111-1111-1111-1111-111111111111', 1),
+        (2, 'Notes', 'Notes', '2020-01-01', '0101-01-01 00:00:00+00:00',
+         1.0, 'Unknown', '', 'Unknown/Notes (2)', '22222222-2222-2222-2222-222222222222', 0);
+    INSERT INTO authors VALUES (1, 'Roald Dahl', 'Dahl, Roald', ''), (2, 'Quentin Blake', 'Blake, Quentin', '');
+    INSERT INTO books_authors_link VALUES (1, 1, 1), (2, 1, 2);
+    INSERT INTO series VALUES (1, 'Dahl Classics', 'Dahl Classics');
+    INSERT INTO books_series_link VALUES (1, 1, 1);
+    INSERT INTO tags VALUES (1, 'Children'), (2, 'Fiction');
+    INSERT INTO books_tags_link VALUES (1, 1, 1), (2, 1, 2);
+    INSERT INTO publishers VALUES (1, 'Puffin', 'Puffin');
+    INSERT INTO books_publishers_link VALUES (1, 1, 1);
+    INSERT INTO identifiers VALUES (1, 1, 'isbn', '9780140328721'), (2, 1, 'goodreads', '6693');
+    INSERT INTO ratings VALUES (1, 8);
+    INSERT INTO books_ratings_link VALUES (1, 1, 1);
+    INSERT INTO comments VALUES (1, 1, '<p>A fox outwits three farmers.</p>');
+    INSERT INTO languages VALUES (1, 'eng');
+    INSERT INTO books_languages_link VALUES (1, 1, 1, 0);
+    INSERT INTO data VALUES (1, 1, 'EPUB', 4, 'Fantastic Mr Fox - Roald Dahl'),
+        (2, 1, 'PDF', 3, 'Fantastic Mr Fox - Roald Dahl');
+    INSERT INTO custom_columns (id, label, name, datatype, is_multiple, normalized) VALUES
+        (1, 'shelf', 'Shelf', 'text', 1, 1),
+        (2, 'pages', 'Pages', 'int', 0, 0),
+        (3, 'review', 'My Review', 'comments', 0, 0);
+    INSERT INTO custom_column_1 VALUES (1, 'Favourites');
+    INSERT INTO books_custom_column_1_link VALUES (1, 1, 1);
+    INSERT INTO custom_column_2 VALUES (1, 1, 96);
+    INSERT INTO custom_column_3 VALUES (1, 1, 'Loved it'), (2, 2, 'Scribbles');
+";
+
+/// Helper function to lay out a small Calibre library on disk and return its root
+fn create_calibre_library(name: &str) -> PathBuf {
+    let root = std::env::temp_dir().join(format!("stellaron_calibre_{}", name));
+    let _ = std::fs::remove_dir_all(&root);
+
+    let fox_dir = root.join("Roald Dahl/Fantastic Mr Fox (1)");
+    std::fs::create_dir_all(&fox_dir).unwrap();
+    std::fs::create_dir_all(root.join("Unknown/Notes (2)")).unwrap();
+    std::fs::write(fox_dir.join("Fantastic Mr Fox - Roald Dahl.epub"), b"epub").unwrap();
+    std::fs::write(fox_dir.join("Fantastic Mr Fox - Roald Dahl.pdf"), b"pdf").unwrap();
+    std::fs::write(fox_dir.join("cover.jpg"), b"jpeg").unwrap();
+
+    let url = root.join("metadata.db").to_string_lossy().to_string();
+    let mut conn = SqliteConnection::establish(&url).unwrap();
+    conn.batch_execute(CALIBRE_SCHEMA).unwrap();
+    conn.batch_execute(CALIBRE_DATA).unwrap();
+
+    root
+}
+
+async fn find_book_id(title_val: &str) -> i32 {
+    BookRepo::new()
+        .await
+        .search_by_title(title_val)
+        .await
+        .expect("Failed to search books")
+        .expect("Book was not imported")[0]
+        .book_id
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn test_import_calibre_library() {
+    setup().await.expect("Setup failed");
+    let user_id = create_user("calibre_user", "user").await;
+    let root = create_calibre_library("import");
+
+    let report = LibraryService::new()
+        .await
+        .import_calibre(root.clone(), user_id, CalibreImportOptions::default())
+        .await
+        .expect("Import failed");
+
+    assert_eq!(report.imported, 2);
+    assert_eq!(report.failed, 0);
+    assert_eq!(report.unmapped_columns.len(), 1);
+    assert_eq!(report.unmapped_columns[0].label, "pages");
+    // The review column can't replace the comments the first book already has
+    assert!(report
+        .issues
+        .iter()
+        .any(|i| i.calibre_id == 1 && i.message.contains("#review")));
+
+    let book_id = find_book_id("Fantastic Mr Fox").await;
+    let book = BookRepo::new()
+        .await
+        .get_by_id(book_id)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(book.published_date.as_deref(), Some("1970-01-01"));
+    assert_eq!(book.isbn.as_deref(), Some("9780140328721"));
+    assert_eq!(book.file_type.as_deref(), Some("epub"));
+    assert_eq!(book.rating, Some(8));
+    assert_eq!(book.language.as_deref(), Some("eng"));
+    assert_eq!(book.series_index, Some(2.0));
+    assert_eq!(book.library_id, Some(report.library_id));
+    assert!(book.cover_image_path.is_some());
+
+    let series = SeriesRepo::new()
+        .await
+        .get_by_id(book.series_id.unwrap())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(series.name, "Dahl Classics");
+
+    let authors = BookAuthorRepo::new()
+        .await
+        .get_authors_by_book(book_id)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(authors.len(), 2);
+    assert!(authors
+        .iter()
+        .any(|a| a.name == "Roald Dahl" && a.sort_name.as_deref() == Some("Dahl, Roald")));
+
+    let tag_names: Vec<String> = BookTagRepo::new()
+        .await
+        .get_tags_by_book(book_id)
+        .await
+        .unwrap()
+        .unwrap()
+        .into_iter()
+        .map(|t| t.name)
+        .collect();
+    assert!(tag_names.contains(&"Children".to_string()));
+    assert!(tag_names.contains(&"Favourites".to_string()));
+
+    let identifiers = BookIdentifierRepo::new()
+        .await
+        .get_by_book(book_id)
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(identifiers
+        .iter()
+        .any(|i| i.scheme == "goodreads" && i.value == "6693"));
+
+    let files = BookFileRepo::new()
+        .await
+        .get_by_book(book_id)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(files.len(), 2);
+
+    // Without own comments the user column fills the description, the placeholder date is dropped
+    let notes = BookRepo::new()
+        .await
+        .get_by_id(find_book_id("Notes").await)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(notes.description.as_deref(), Some("Scribbles"));
+    assert_eq!(notes.published_date, None);
+
+    if let Some(cover) = book.cover_image_path {
+        let _ = std::fs::remove_file(cover);
+    }
+    let _ = std::fs::remove_dir_all(root);
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn test_import_calibre_twice_skips_existing_books() {
+    setup().await.expect("Setup failed");
+    let user_id = create_user("calibre_user", "user").await;
+    let root = create_calibre_library("reimport");
+    let service = LibraryService::new().await;
+
+    let first = service
+        .import_calibre(root.clone(), user_id, CalibreImportOptions::default())
+        .await
+        .expect("First import failed");
+    let second = service
+        .import_calibre(root.clone(), user_id, CalibreImportOptions::default())
+        .await
+        .expect("Second import failed");
+
+    assert_eq!(first.imported, 2);
+    assert_eq!(second.imported, 0);
+    assert_eq!(second.skipped, 2);
+    assert_eq!(first.library_id, second.library_id);
+
+    let cover = BookRepo::new()
+        .await
+        .get_by_id(find_book_id("Fantastic Mr Fox").await)
+        .await
+        .unwrap()
+        .unwrap()
+        .cover_image_path;
+    if let Some(cover) = cover {
+        let _ = std::fs::remove_file(cover);
+    }
+    let _ = std::fs::remove_dir_all(root);
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn test_import_calibre_column_mapping_override() {
+    setup().await.expect("Setup failed");
+    let user_id = create_user("calibre_user", "user").await;
+    let root = create_calibre_library("mapping");
+
+    let options = CalibreImportOptions {
+        column_mapping: HashMap::from([
+            ("shelf".to_string(), ColumnTarget::Ignore),
+            ("pages".to_string(), ColumnTarget::Tags),
+        ]),
+    };
+    let report = LibraryService::new()
+        .await
+        .import_calibre(root.clone(), user_id, options)
+        .await
+        .expect("Import failed");
+
+    assert_eq!(report.unmapped_columns.len(), 1);
+    assert_eq!(report.unmapped_columns[0].label, "shelf");
+
+    let book_id = find_book_id("Fantastic Mr Fox").await;
+    let tag_names: Vec<String> = BookTagRepo::new()
+        .await
+        .get_tags_by_book(book_id)
+        .await
+        .unwrap()
+        .unwrap()
+        .into_iter()
+        .map(|t| t.name)
+        .collect();
+    assert!(!tag_names.contains(&"Favourites".to_string()));
+    assert!(tag_names.contains(&"96".to_string()));
+
+    let cover = BookRepo::new()
+        .await
+        .get_by_id(book_id)
+        .await
+        .unwrap()
+        .unwrap()
+        .cover_image_path;
+    if let Some(cover) = cover {
+        let _ = std::fs::remove_file(cover);
+    }
+    let _ = std::fs::remove_dir_all(root);
+}
@@ -0,0 +1,157 @@
+// Helpers shared by the integration tests, each test file uses some of them
+#![allow(dead_code)]
+
+use diesel::result::Error;
+use diesel_async::RunQueryDsl;
+
+use stellaron_lib::data::database;
+use stellaron_lib::data::models::books::NewBook;
+use stellaron_lib::data::models::libraries::{NewLibrary, Visibility};
+use stellaron_lib::data::models::users::NewUser;
+use stellaron_lib::data::repos::implementors::book_repo::BookRepo;
+use stellaron_lib::data::repos::implementors::library_repo::LibraryRepo;
+use stellaron_lib::data::repos::implementors::user_repo::UserRepo;
+use stellaron_lib::data::repos::traits::repository::Repository;
+use stellaron_lib::services::authentication_service::AuthenticationService;
+
+/// Helper function to clear every table before each test, the server settings are kept
+pub async fn setup() -> Result<(), Error> {
+    let mut conn = database::connect_from_pool()
+        .await
+        .expect("Failed to get connection from pool for test setup");
+
+    use stellaron_lib::data::models::schema::*;
+
+    // Rows that reference other tables go first
+    diesel::delete(annotations::table)
+        .execute(&mut conn)
+        .await?;
+    diesel::delete(bookmarks::table).execute(&mut conn).await?;
+    diesel::delete(reading_progress::table)
+        .execute(&mut conn)
+        .await?;
+    diesel::delete(user_library::table)
+        .execute(&mut conn)
+        .await?;
+    diesel::delete(book_metadata_sources::table)
+        .execute(&mut conn)
+        .await?;
+    diesel::delete(metadata_history::table)
+        .execute(&mut conn)
+        .await?;
+    diesel::delete(book_tags::table).execute(&mut conn).await?;
+    diesel::delete(book_identifiers::table)
+        .execute(&mut conn)
+        .await?;
+    diesel::delete(book_files::table).execute(&mut conn).await?;
+    diesel::delete(book_authors::table)
+        .execute(&mut conn)
+        .await?;
+    diesel::delete(books::table).execute(&mut conn).await?;
+    diesel::delete(library_shares::table)
+        .execute(&mut conn)
+        .await?;
+    diesel::delete(libraries::table).execute(&mut conn).await?;
+    diesel::delete(authors::table).execute(&mut conn).await?;
+    diesel::delete(publishers::table).execute(&mut conn).await?;
+    diesel::delete(tags::table).execute(&mut conn).await?;
+    diesel::delete(series::table).execute(&mut conn).await?;
+    diesel::delete(api_keys::table).execute(&mut conn).await?;
+    diesel::delete(sessions::table).execute(&mut conn).await?;
+    diesel::delete(password_resets::table)
+        .execute(&mut conn)
+        .await?;
+    diesel::delete(login_challenges::table)
+        .execute(&mut conn)
+        .await?;
+    diesel::delete(recovery_codes::table)
+        .execute(&mut conn)
+        .await?;
+    diesel::delete(user_totp::table).execute(&mut conn).await?;
+    diesel::delete(invites::table).execute(&mut conn).await?;
+    diesel::delete(revoked_tokens::table)
+        .execute(&mut conn)
+        .await?;
+    diesel::delete(audit_events::table)
+        .execute(&mut conn)
+        .await?;
+    diesel::delete(users::table).execute(&mut conn).await?;
+
+    Ok(())
+}
+
+/// Helper function to clear the server settings, e.g. the registration policy
+pub async fn clear_settings() -> Result<(), Error> {
+    let mut conn = database::connect_from_pool()
+        .await
+        .expect("Failed to get connection from pool for test setup");
+
+    use stellaron_lib::data::models::schema::server_settings;
+    diesel::delete(server_settings::table)
+        .execute(&mut conn)
+        .await?;
+
+    Ok(())
+}
+
+async fn insert_user(username: &str, role: &str, password_hash: &str) -> i32 {
+    let repo = UserRepo::new().await;
+    repo.add(NewUser {
+        username,
+        email: &format!("{}@test.com", username),
+        role,
+        password_hash,
+    })
+    .await
+    .expect("Failed to create test user");
+    let users = repo.get_all().await.expect("Failed to get users").unwrap();
+    users
+        .iter()
+        .find(|u| u.username == username)
+        .unwrap()
+        .user_id
+}
+
+/// Helper function to create a user whose password hash is a placeholder
+pub async fn create_user(username: &str, role: &str) -> i32 {
+    insert_user(username, role, "password").await
+}
+
+/// Helper function to create a user that signs in with `password`
+pub async fn create_user_with_password(username: &str, role: &str, password: &str) -> i32 {
+    let hash = AuthenticationService::new()
+        .hash_password(password)
+        .expect("Failed to hash password");
+    insert_user(username, role, &hash).await
+}
+
+/// Helper function to create a library at `/books/{name}`
+pub async fn create_library(name: &str, owner: Option<i32>, visibility: Visibility) -> i32 {
+    let repo = LibraryRepo::new().await;
+    let path = format!("/books/{}", name);
+    repo.add(NewLibrary {
+        name,
+        path: &path,
+        added_by: owner,
+        visibility: Some(visibility.as_str()),
+    })
+    .await
+    .expect("Failed to create library");
+    repo.get_by_path(&path).await.unwrap().unwrap().library_id
+}
+
+/// Helper function to create an EPUB book whose file doesn't exist
+pub async fn create_book(title: &str, library: Option<i32>) -> i32 {
+    BookRepo::new()
+        .await
+        .add_returning_id(NewBook {
+            title,
+            file_type: Some("epub"),
+            file_path: Some("/books/missing.epub"),
+            cover_image_path: Some("/books/cover.jpg"),
+            library_id: library,
+            ..Default::default()
+        })
+        .await
+        .expect("Failed to create book")
+}
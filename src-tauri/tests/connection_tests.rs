@@ -1,5 +1,14 @@
+use diesel::sql_types::Integer;
+use diesel::QueryableByName;
+use diesel_async::RunQueryDsl;
 use stellaron_lib::data::database;
 
+#[derive(QueryableByName)]
+struct ForeignKeys {
+    #[diesel(sql_type = Integer)]
+    foreign_keys: i32,
+}
+
 #[tokio::test]
 #[serial_test::serial]
 async fn test_sqlite_connection() {
@@ -7,3 +16,21 @@ async fn test_sqlite_connection() {
 
     assert!(_conn.is_ok());
 }
+
+#[tokio::test]
+#[serial_test::serial]
+async fn test_every_connection_enforces_foreign_keys() {
+    // Held at the same time, so the pool has to open new connections
+    let mut connections = Vec::new();
+    for _ in 0..2 {
+        connections.push(database::connect_from_pool().await.unwrap());
+    }
+
+    for conn in connections.iter_mut() {
+        let pragma = diesel::sql_query("PRAGMA foreign_keys")
+            .get_result::<ForeignKeys>(&mut **conn)
+            .await
+            .unwrap();
+        assert_eq!(pragma.foreign_keys, 1);
+    }
+}
@@ -0,0 +1,275 @@
+mod common;
+
+use axum::body::to_bytes;
+use axum::extract::FromRequestParts;
+use axum::http::{header, request::Parts, HeaderMap, HeaderValue, Method, Request, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use axum_extra::extract::CookieJar;
+
+use stellaron_lib::controllers::auth_controller::{self, RefreshTokenDTO};
+use stellaron_lib::controllers::auth_cookies::{
+    ACCESS_COOKIE, CSRF_COOKIE, CSRF_HEADER, REFRESH_COOKIE,
+};
+use stellaron_lib::controllers::auth_middleware::{AuthUser, MemberUser};
+use stellaron_lib::controllers::dto::login_dto::LoginDTO;
+use stellaron_lib::services::audit_service::RequestOrigin;
+use stellaron_lib::services::token_service::Tokenizer;
+
+use common::{create_user_with_password, setup};
+
+/// `Set-Cookie` headers of a response
+fn set_cookies(response: &Response) -> Vec<String> {
+    response
+        .headers()
+        .get_all(header::SET_COOKIE)
+        .iter()
+        .map(|v| v.to_str().unwrap().to_string())
+        .collect()
+}
+
+fn cookie<'a>(cookies: &'a [String], name: &str) -> &'a str {
+    cookies
+        .iter()
+        .find(|c| c.starts_with(&format!("{}=", name)))
+        .unwrap_or_else(|| panic!("No {} cookie in {:?}", name, cookies))
+}
+
+fn value<'a>(cookies: &'a [String], name: &str) -> &'a str {
+    let cookie = cookie(cookies, name);
+    cookie[name.len() + 1..].split(';').next().unwrap()
+}
+
+async fn json(response: Response) -> serde_json::Value {
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    serde_json::from_slice(&body).unwrap()
+}
+
+/// Signs in with cookies, returning the cookies and the CSRF token of the body
+async fn cookie_login(username: &str) -> (Vec<String>, String) {
+    let response = auth_controller::login(
+        RequestOrigin::default(),
+        Json(LoginDTO {
+            username: username.to_string(),
+            password: "secret".to_string(),
+            device_name: None,
+            use_cookies: true,
+        }),
+    )
+    .await
+    .into_response();
+    assert_eq!(response.status(), StatusCode::OK);
+    let cookies = set_cookies(&response);
+    let body = json(response).await;
+    (cookies, body["csrf_token"].as_str().unwrap().to_string())
+}
+
+/// Headers a browser sends back, with the CSRF header if `csrf` is given
+fn browser_headers(cookies: &[String], csrf: Option<&str>) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    let pairs: Vec<String> = [ACCESS_COOKIE, REFRESH_COOKIE, CSRF_COOKIE]
+        .iter()
+        .map(|name| format!("{}={}", name, value(cookies, name)))
+        .collect();
+    headers.insert(
+        header::COOKIE,
+        HeaderValue::from_str(&pairs.join("; ")).unwrap(),
+    );
+    if let Some(csrf) = csrf {
+        headers.insert(CSRF_HEADER, HeaderValue::from_str(csrf).unwrap());
+    }
+    headers
+}
+
+fn request(method: Method, headers: HeaderMap) -> Parts {
+    let (mut parts, _) = Request::builder()
+        .method(method)
+        .body(())
+        .unwrap()
+        .into_parts();
+    parts.headers = headers;
+    parts
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn test_login_sets_cookies() {
+    setup().await.expect("Setup failed");
+    create_user_with_password("browser", "user", "secret").await;
+
+    let response = auth_controller::login(
+        RequestOrigin::default(),
+        Json(LoginDTO {
+            username: "browser".to_string(),
+            password: "secret".to_string(),
+            device_name: None,
+            use_cookies: true,
+        }),
+    )
+    .await
+    .into_response();
+    assert_eq!(response.status(), StatusCode::OK);
+    let cookies = set_cookies(&response);
+    let body = json(response).await;
+
+    // Scripts get the CSRF token, never the tokens
+    assert!(body.get("access_token").is_none());
+    assert!(body.get("refresh_token").is_none());
+    assert!(body["session_id"].is_i64());
+    assert_eq!(
+        body["csrf_token"].as_str(),
+        Some(value(&cookies, CSRF_COOKIE))
+    );
+
+    for name in [ACCESS_COOKIE, REFRESH_COOKIE] {
+        let cookie = cookie(&cookies, name);
+        assert!(cookie.contains("HttpOnly"), "{}", cookie);
+        assert!(cookie.contains("SameSite=Strict"), "{}", cookie);
+        assert!(cookie.contains("Secure"), "{}", cookie);
+    }
+    let csrf = cookie(&cookies, CSRF_COOKIE);
+    assert!(!csrf.contains("HttpOnly") && csrf.contains("SameSite=Strict"));
+
+    // Without the option tokens are returned as before and no cookie is set
+    let response = auth_controller::login(
+        RequestOrigin::default(),
+        Json(LoginDTO {
+            username: "browser".to_string(),
+            password: "secret".to_string(),
+            device_name: None,
+            use_cookies: false,
+        }),
+    )
+    .await
+    .into_response();
+    assert!(set_cookies(&response).is_empty());
+    assert!(json(response).await["access_token"].is_string());
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn test_cookie_requests_need_csrf_token() {
+    setup().await.expect("Setup failed");
+    let user_id = create_user_with_password("reader", "user", "secret").await;
+    let (cookies, csrf) = cookie_login("reader").await;
+
+    // Reading needs no CSRF token
+    let mut parts = request(Method::GET, browser_headers(&cookies, None));
+    let user = AuthUser::from_request_parts(&mut parts, &()).await;
+    assert_eq!(user.map(|u| u.id).ok(), Some(user_id));
+
+    let mut parts = request(Method::POST, browser_headers(&cookies, None));
+    let rejected = MemberUser::from_request_parts(&mut parts, &())
+        .await
+        .map(|_| ())
+        .unwrap_err()
+        .into_response();
+    assert_eq!(rejected.status(), StatusCode::FORBIDDEN);
+    let mut parts = request(Method::PUT, browser_headers(&cookies, Some("forged")));
+    assert!(MemberUser::from_request_parts(&mut parts, &())
+        .await
+        .is_err());
+
+    let mut parts = request(Method::POST, browser_headers(&cookies, Some(&csrf)));
+    let user = MemberUser::from_request_parts(&mut parts, &()).await;
+    assert_eq!(user.map(|u| u.id).ok(), Some(user_id));
+
+    // Bearer tokens aren't sent by the browser on its own, they need no CSRF token
+    let token = Tokenizer::get_instance()
+        .await
+        .generate_token(user_id)
+        .unwrap();
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        header::AUTHORIZATION,
+        HeaderValue::from_str(&format!("Bearer {}", token)).unwrap(),
+    );
+    let mut parts = request(Method::DELETE, headers);
+    assert!(MemberUser::from_request_parts(&mut parts, &())
+        .await
+        .is_ok());
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn test_refresh_and_logout_with_cookies() {
+    setup().await.expect("Setup failed");
+    create_user_with_password("tabbed", "user", "secret").await;
+    let (cookies, csrf) = cookie_login("tabbed").await;
+
+    let refresh = |headers: HeaderMap| async move {
+        auth_controller::refresh(
+            RequestOrigin::default(),
+            CookieJar::from_headers(&headers),
+            headers,
+            None,
+        )
+        .await
+        .into_response()
+    };
+    assert_eq!(
+        refresh(browser_headers(&cookies, None)).await.status(),
+        StatusCode::FORBIDDEN
+    );
+    // A token that doesn't match the cookie is no better than none
+    assert_eq!(
+        refresh(browser_headers(&cookies, Some("forged")))
+            .await
+            .status(),
+        StatusCode::FORBIDDEN
+    );
+    let response = refresh(browser_headers(&cookies, Some(&csrf))).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let rotated = set_cookies(&response);
+    let new_csrf = json(response).await["csrf_token"]
+        .as_str()
+        .unwrap()
+        .to_string();
+    assert_ne!(
+        value(&rotated, REFRESH_COOKIE),
+        value(&cookies, REFRESH_COOKIE)
+    );
+    assert_ne!(new_csrf, csrf);
+
+    // The replaced refresh token is rejected and the cookies are cleared
+    let response = refresh(browser_headers(&cookies, Some(&csrf))).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    assert!(cookie(&set_cookies(&response), ACCESS_COOKIE).contains("Max-Age=0"));
+
+    let (cookies, csrf) = cookie_login("tabbed").await;
+    let headers = browser_headers(&cookies, Some(&csrf));
+    let response = auth_controller::logout(
+        RequestOrigin::default(),
+        CookieJar::from_headers(&headers),
+        headers,
+        None,
+    )
+    .await
+    .into_response();
+    assert_eq!(response.status(), StatusCode::OK);
+    let cleared = set_cookies(&response);
+    for name in [ACCESS_COOKIE, REFRESH_COOKIE, CSRF_COOKIE] {
+        assert!(cookie(&cleared, name).contains("Max-Age=0"));
+    }
+    assert_eq!(
+        refresh(browser_headers(&cookies, Some(&csrf)))
+            .await
+            .status(),
+        StatusCode::UNAUTHORIZED
+    );
+
+    // API clients still send the refresh token in the body
+    let (cookies, _) = cookie_login("tabbed").await;
+    let response = auth_controller::logout(
+        RequestOrigin::default(),
+        CookieJar::new(),
+        HeaderMap::new(),
+        Some(Json(RefreshTokenDTO {
+            refresh_token: value(&cookies, REFRESH_COOKIE).to_string(),
+        })),
+    )
+    .await
+    .into_response();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(set_cookies(&response).is_empty());
+}
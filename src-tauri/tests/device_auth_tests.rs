@@ -0,0 +1,127 @@
+mod common;
+
+use axum::extract::FromRequestParts;
+use axum::http::{header, Request, StatusCode};
+use axum::response::IntoResponse;
+use diesel::prelude::*;
+use diesel_async::RunQueryDsl;
+
+use stellaron_lib::controllers::auth_middleware::DeviceUser;
+use stellaron_lib::data::database;
+use stellaron_lib::services::authentication_service::{AuthenticationService, LoginOutcome};
+
+use common::{create_user_with_password, setup};
+
+/// Runs the extractor on a request with the given Authorization header
+async fn extract(authorization: Option<&str>) -> Result<DeviceUser, axum::response::Response> {
+    let mut request = Request::builder().uri("/opds");
+    if let Some(value) = authorization {
+        request = request.header(header::AUTHORIZATION, value);
+    }
+    let (mut parts, _) = request.body(()).unwrap().into_parts();
+    DeviceUser::from_request_parts(&mut parts, &())
+        .await
+        .map_err(|e| e.into_response())
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn test_verify_credentials() {
+    setup().await.expect("Setup failed");
+    let user_id = create_user_with_password("reader", "user", "secret").await;
+    let service = AuthenticationService::new();
+
+    let verified = service
+        .verify_credentials("reader", "secret", None)
+        .await
+        .unwrap();
+    assert_eq!(verified, LoginOutcome::Success(user_id));
+    // The second check is answered from the cache
+    let verified = service
+        .verify_credentials("reader", "secret", None)
+        .await
+        .unwrap();
+    assert_eq!(verified, LoginOutcome::Success(user_id));
+
+    assert_eq!(
+        service
+            .verify_credentials("reader", "wrong", None)
+            .await
+            .unwrap(),
+        LoginOutcome::InvalidCredentials
+    );
+    assert_eq!(
+        service
+            .verify_credentials("nobody", "secret", None)
+            .await
+            .unwrap(),
+        LoginOutcome::InvalidCredentials
+    );
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn test_password_change_invalidates_cache() {
+    setup().await.expect("Setup failed");
+    let id = create_user_with_password("reader", "user", "secret").await;
+    let service = AuthenticationService::new();
+    assert_eq!(
+        service
+            .verify_credentials("reader", "secret", None)
+            .await
+            .unwrap(),
+        LoginOutcome::Success(id)
+    );
+
+    let new_hash = service.hash_password("changed").unwrap();
+    {
+        use stellaron_lib::data::models::schema::users::dsl::*;
+        let mut conn = database::connect_from_pool().await.unwrap();
+        diesel::update(users.filter(user_id.eq(id)))
+            .set(password_hash.eq(&new_hash))
+            .execute(&mut conn)
+            .await
+            .unwrap();
+    }
+
+    assert_eq!(
+        service
+            .verify_credentials("reader", "secret", None)
+            .await
+            .unwrap(),
+        LoginOutcome::InvalidCredentials
+    );
+    assert_eq!(
+        service
+            .verify_credentials("reader", "changed", None)
+            .await
+            .unwrap(),
+        LoginOutcome::Success(id)
+    );
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn test_basic_extractor() {
+    setup().await.expect("Setup failed");
+    let user_id = create_user_with_password("reader", "user", "secret").await;
+
+    // reader:secret
+    let user = extract(Some("Basic cmVhZGVyOnNlY3JldA=="))
+        .await
+        .expect("Valid credentials rejected");
+    assert_eq!(user.id, user_id);
+
+    // reader:wrong
+    for authorization in [Some("Basic cmVhZGVyOndyb25n"), None] {
+        let response = extract(authorization).await.unwrap_err();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        let challenge = response
+            .headers()
+            .get(header::WWW_AUTHENTICATE)
+            .expect("Missing challenge")
+            .to_str()
+            .unwrap();
+        assert!(challenge.starts_with("Basic realm=\"Stellaron\""));
+    }
+}
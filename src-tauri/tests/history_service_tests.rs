@@ -0,0 +1,258 @@
+mod common;
+
+use std::sync::Arc;
+
+use stellaron_lib::data::models::books::NewBook;
+use stellaron_lib::data::repos::implementors::book_author_repo::BookAuthorRepo;
+use stellaron_lib::data::repos::implementors::book_repo::BookRepo;
+use stellaron_lib::data::repos::traits::repository::Repository;
+use stellaron_lib::services::history_service::{
+    HistoryBatch, HistoryService, SOURCE_PROVIDER, SOURCE_SCAN, SOURCE_USER,
+};
+use stellaron_lib::services::metadata_service::{
+    FixtureMetadataProvider, MetadataField, MetadataService,
+};
+
+use common::{create_user, setup};
+
+async fn create_test_book(title_val: &str) -> i32 {
+    BookRepo::new()
+        .await
+        .add_returning_id(NewBook {
+            title: title_val,
+            isbn: Some("9780140328721"),
+            ..Default::default()
+        })
+        .await
+        .expect("Failed to create test book")
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn test_edit_records_changes() {
+    setup().await.expect("Failed to set up test");
+    let user_id = create_user("history_editor", "user").await;
+    let book_id = create_test_book("Fantastic Mr Fox").await;
+
+    let service = HistoryService::new().await;
+    let changes = service
+        .edit(
+            book_id,
+            user_id,
+            &[
+                (MetadataField::Title, Some("Fantastic Mr. Fox".to_string())),
+                (MetadataField::Series, Some("Dahl Classics".to_string())),
+                (
+                    MetadataField::Tags,
+                    Some(r#"["Children","Foxes"]"#.to_string()),
+                ),
+                // Unchanged values are not recorded
+                (MetadataField::Isbn, Some("9780140328721".to_string())),
+            ],
+        )
+        .await
+        .expect("Failed to edit book")
+        .expect("Book not found");
+
+    assert_eq!(changes.len(), 3);
+    assert!(changes.iter().all(|c| c.source == SOURCE_USER));
+    assert!(changes.iter().all(|c| c.user_id == Some(user_id)));
+    assert!(changes.iter().all(|c| c.batch_id == changes[0].batch_id));
+
+    let title = changes.iter().find(|c| c.field == "title").unwrap();
+    assert_eq!(title.old_value.as_deref(), Some("Fantastic Mr Fox"));
+    assert_eq!(title.new_value.as_deref(), Some("Fantastic Mr. Fox"));
+
+    let history = service
+        .get_history(book_id)
+        .await
+        .expect("Failed to get history")
+        .unwrap();
+    assert_eq!(history.len(), 3);
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn test_failed_edit_writes_nothing() {
+    setup().await.expect("Failed to set up test");
+    let user_id = create_user("history_editor", "user").await;
+    let book_id = create_test_book("Fantastic Mr Fox").await;
+
+    // The authors are written before the ISBN is refused, nothing is kept
+    let service = HistoryService::new().await;
+    assert!(service
+        .edit(
+            book_id,
+            user_id,
+            &[
+                (MetadataField::Title, Some("Fantastic Mr. Fox".to_string())),
+                (
+                    MetadataField::Authors,
+                    Some(r#"["Roald Dahl"]"#.to_string())
+                ),
+                (MetadataField::Isbn, Some("not an isbn".to_string())),
+            ],
+        )
+        .await
+        .is_err());
+
+    let book = BookRepo::new()
+        .await
+        .get_by_id(book_id)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(book.title, "Fantastic Mr Fox");
+    assert!(BookAuthorRepo::new()
+        .await
+        .get_authors_by_book(book_id)
+        .await
+        .unwrap()
+        .is_none_or(|authors| authors.is_empty()));
+    assert!(service
+        .get_history(book_id)
+        .await
+        .unwrap()
+        .unwrap()
+        .is_empty());
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn test_revert_single_change() {
+    setup().await.expect("Failed to set up test");
+    let user_id = create_user("history_reverter", "user").await;
+    let book_id = create_test_book("Matilda").await;
+
+    let service = HistoryService::new().await;
+    let changes = service
+        .edit(
+            book_id,
+            user_id,
+            &[
+                (MetadataField::Title, Some("Matilda!".to_string())),
+                (
+                    MetadataField::Description,
+                    Some("A clever girl".to_string()),
+                ),
+            ],
+        )
+        .await
+        .unwrap()
+        .unwrap();
+    let description = changes.iter().find(|c| c.field == "description").unwrap();
+
+    let reverted = service
+        .revert_change(description.history_id, user_id)
+        .await
+        .expect("Failed to revert")
+        .unwrap();
+    assert_eq!(reverted.len(), 1);
+    assert_eq!(reverted[0].old_value.as_deref(), Some("A clever girl"));
+    assert_eq!(reverted[0].new_value, None);
+
+    let book = BookRepo::new()
+        .await
+        .get_by_id(book_id)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(book.title, "Matilda!");
+    assert_eq!(book.description, None);
+
+    // A change can only be reverted once
+    let again = service
+        .revert_change(description.history_id, user_id)
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(again.is_empty());
+
+    assert!(service.revert_change(-1, user_id).await.unwrap().is_none());
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn test_revert_provider_batch() {
+    setup().await.expect("Failed to set up test");
+    let user_id = create_user("history_batch", "user").await;
+    let book_id = create_test_book("Unknown Title").await;
+
+    let provider = FixtureMetadataProvider::new(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/fixtures/openlibrary_search.json"
+    ));
+    let metadata = MetadataService::new(Arc::new(provider));
+    let candidate = metadata
+        .find_matches(book_id, None, None, None)
+        .await
+        .unwrap()
+        .unwrap()
+        .remove(0)
+        .candidate;
+    metadata
+        .apply_match(
+            book_id,
+            &candidate,
+            Some(&[MetadataField::Title, MetadataField::Authors]),
+            Some(user_id),
+        )
+        .await
+        .expect("Failed to apply match");
+
+    let service = HistoryService::new().await;
+    let history = service.get_history(book_id).await.unwrap().unwrap();
+    assert_eq!(history.len(), 2);
+    assert!(history.iter().all(|c| c.source == SOURCE_PROVIDER));
+
+    service
+        .revert_batch(&history[0].batch_id, user_id)
+        .await
+        .expect("Failed to revert batch")
+        .unwrap();
+
+    let book = BookRepo::new()
+        .await
+        .get_by_id(book_id)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(book.title, "Unknown Title");
+    let authors = BookAuthorRepo::new()
+        .await
+        .get_authors_by_book(book_id)
+        .await
+        .unwrap();
+    assert!(authors.is_none());
+
+    let history = service.get_history(book_id).await.unwrap().unwrap();
+    assert_eq!(history.len(), 4);
+    assert_eq!(
+        history
+            .iter()
+            .filter(|c| c.source == SOURCE_PROVIDER && c.reverted_at.is_some())
+            .count(),
+        2
+    );
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn test_record_created_values() {
+    setup().await.expect("Failed to set up test");
+    let book_id = create_test_book("The Witches").await;
+
+    let service = HistoryService::new().await;
+    let batch = HistoryBatch::new(SOURCE_SCAN, None);
+    service
+        .record_created(book_id, &batch)
+        .await
+        .expect("Failed to record values");
+
+    let history = service.get_history(book_id).await.unwrap().unwrap();
+    let mut fields: Vec<&str> = history.iter().map(|c| c.field.as_str()).collect();
+    fields.sort_unstable();
+    assert_eq!(fields, vec!["isbn", "title"]);
+    assert!(history.iter().all(|c| c.old_value.is_none()));
+    assert!(history.iter().all(|c| c.batch_id == batch.batch_id));
+}
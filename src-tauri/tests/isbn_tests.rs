@@ -0,0 +1,82 @@
+use stellaron_lib::parsers::isbn;
+
+#[test]
+fn test_strip_prefixes_and_hyphens() {
+    assert_eq!(isbn::strip("urn:isbn:978-0-14-032872-1"), "9780140328721");
+    assert_eq!(isbn::strip("ISBN-13: 978 0 14 032872 1"), "9780140328721");
+    assert_eq!(isbn::strip("ISBN 0-8044-2957-x"), "080442957X");
+    assert_eq!(isbn::strip("isbn10:0140328726"), "0140328726");
+}
+
+#[test]
+fn test_validate_isbn10() {
+    assert!(isbn::is_valid_isbn10("0140328726"));
+    assert!(isbn::is_valid_isbn10("080442957X"));
+    assert!(!isbn::is_valid_isbn10("0140328727"));
+    assert!(!isbn::is_valid_isbn10("014032872"));
+    assert!(!isbn::is_valid_isbn10("X140328726"));
+}
+
+#[test]
+fn test_validate_isbn13() {
+    assert!(isbn::is_valid_isbn13("9780140328721"));
+    assert!(isbn::is_valid_isbn13("9791032305690"));
+    assert!(!isbn::is_valid_isbn13("9780140328722"));
+    // Valid check digit but not a Bookland prefix
+    assert!(!isbn::is_valid_isbn13("4006381333931"));
+}
+
+#[test]
+fn test_convert_between_isbn10_and_isbn13() {
+    assert_eq!(
+        isbn::isbn10_to_isbn13("0-14-032872-6").as_deref(),
+        Some("9780140328721")
+    );
+    assert_eq!(
+        isbn::isbn10_to_isbn13("080442957X").as_deref(),
+        Some("9780804429573")
+    );
+    assert_eq!(
+        isbn::isbn13_to_isbn10("9780804429573").as_deref(),
+        Some("080442957X")
+    );
+    assert_eq!(isbn::isbn13_to_isbn10("9791032305690"), None);
+    assert_eq!(isbn::isbn10_to_isbn13("0140328727"), None);
+}
+
+#[test]
+fn test_normalize() {
+    assert_eq!(
+        isbn::normalize("urn:isbn:0140328726").as_deref(),
+        Some("9780140328721")
+    );
+    assert_eq!(
+        isbn::normalize("978-0-14-032872-1").as_deref(),
+        Some("9780140328721")
+    );
+    assert_eq!(isbn::normalize("978-0-14-032872-2"), None);
+    assert_eq!(isbn::normalize(""), None);
+}
+
+#[test]
+fn test_from_identifier() {
+    assert_eq!(
+        isbn::from_identifier("0140328726", Some("ISBN")).as_deref(),
+        Some("9780140328721")
+    );
+    // ONIX codelist 5 code for ISBN-13 in legacy refinements
+    assert_eq!(
+        isbn::from_identifier("978-0-14-032872-1", Some("15")).as_deref(),
+        Some("9780140328721")
+    );
+    assert_eq!(
+        isbn::from_identifier("urn:isbn:9780140328721", None).as_deref(),
+        Some("9780140328721")
+    );
+    assert_eq!(
+        isbn::from_identifier("9780140328721", None).as_deref(),
+        Some("9780140328721")
+    );
+    assert_eq!(isbn::from_identifier("urn:uuid:0140328726", None), None);
+    assert_eq!(isbn::from_identifier("B000FC1PJI", Some("ASIN")), None);
+}
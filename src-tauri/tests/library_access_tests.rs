@@ -0,0 +1,358 @@
+mod common;
+
+use axum::body::to_bytes;
+use axum::extract::{Path, Query};
+use axum::http::StatusCode;
+use axum::response::IntoResponse;
+use axum::Json;
+
+use stellaron_lib::controllers::auth_middleware::{AuthUser, MemberUser};
+use stellaron_lib::controllers::dto::annotation_dto::NewAnnotationDTO;
+use stellaron_lib::controllers::dto::bookmark_dto::NewBookmarkDTO;
+use stellaron_lib::controllers::dto::reading_progress_dto::UpdateProgressDTO;
+use stellaron_lib::controllers::{
+    annotation_controller, bookmark_controller, metadata_controller, reading_progress_controller,
+    search_controller,
+};
+
+use stellaron_lib::data::models::libraries::Visibility;
+use stellaron_lib::data::models::users::Role;
+use stellaron_lib::data::repos::implementors::book_repo::{
+    BookFacets, BookFilter, BookOrder, BookRepo, BookScope,
+};
+use stellaron_lib::opds::facets::FacetSelection;
+use stellaron_lib::opds::search::SearchQuery;
+use stellaron_lib::services::library_access_service::{
+    LibraryAccessService, LibraryChange, ShareTarget,
+};
+use stellaron_lib::services::opds_service::OpdsService;
+
+use common::{create_book, create_library, create_user, setup};
+
+fn ids(scope: &BookScope) -> Vec<i32> {
+    match scope {
+        BookScope::All => panic!("Expected a limited scope"),
+        BookScope::Libraries(ids) => ids.clone(),
+    }
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn test_scope_by_owner_share_and_role() {
+    setup().await.expect("Setup failed");
+    let admin = create_user("admin", "admin").await;
+    let owner = create_user("owner", "user").await;
+    let friend = create_user("friend", "user").await;
+    let guest = create_user("guest", "guest").await;
+
+    let public = create_library("public", Some(admin), Visibility::Public).await;
+    let personal = create_library("personal", Some(owner), Visibility::Private).await;
+    let for_friend = create_library("for_friend", Some(admin), Visibility::Private).await;
+    let for_users = create_library("for_users", Some(admin), Visibility::Private).await;
+    let for_guests = create_library("for_guests", Some(admin), Visibility::Private).await;
+
+    let service = LibraryAccessService::new().await;
+    for (library, target) in [
+        (for_friend, ShareTarget::User(friend)),
+        (for_users, ShareTarget::Role(Role::User)),
+        (for_guests, ShareTarget::Role(Role::Guest)),
+    ] {
+        assert!(matches!(
+            service.share(admin, library, target).await.unwrap(),
+            LibraryChange::Done(_)
+        ));
+    }
+
+    assert_eq!(service.scope(admin).await.unwrap(), BookScope::All);
+    assert_eq!(
+        ids(&service.scope(owner).await.unwrap()),
+        vec![public, personal, for_users, for_guests]
+    );
+    // A share with a role covers the roles above it
+    assert_eq!(
+        ids(&service.scope(friend).await.unwrap()),
+        vec![public, for_friend, for_users, for_guests]
+    );
+    assert_eq!(
+        ids(&service.scope(guest).await.unwrap()),
+        vec![public, for_guests]
+    );
+    assert!(ids(&service.scope(-1).await.unwrap()).is_empty());
+
+    let names: Vec<String> = service
+        .libraries(guest)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|l| l.name)
+        .collect();
+    assert_eq!(names, ["public", "for_guests"]);
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn test_private_books_are_hidden() {
+    setup().await.expect("Setup failed");
+    let admin = create_user("admin", "admin").await;
+    let owner = create_user("owner", "user").await;
+    let other = create_user("other", "user").await;
+    let public = create_library("public", Some(admin), Visibility::Public).await;
+    let personal = create_library("personal", Some(owner), Visibility::Private).await;
+
+    let hidden = create_book("Hidden Diary", Some(personal)).await;
+    let shelved = create_book("Shelved Novel", Some(public)).await;
+    let loose = create_book("Loose Pamphlet", None).await;
+
+    let access = LibraryAccessService::new().await;
+    assert!(access.book(owner, hidden).await.unwrap().is_some());
+    assert!(access.book(other, hidden).await.unwrap().is_none());
+    assert!(access.book(other, shelved).await.unwrap().is_some());
+    // Books outside any library are only visible to admins
+    assert!(access.book(other, loose).await.unwrap().is_none());
+    assert!(access.book(admin, loose).await.unwrap().is_some());
+
+    let scope = access.scope(other).await.unwrap();
+    let (books, total) = BookRepo::new()
+        .await
+        .get_page(
+            BookFilter::All,
+            &BookFacets {
+                scope: scope.clone(),
+                ..Default::default()
+            },
+            BookOrder::Title,
+            0,
+            10,
+        )
+        .await
+        .unwrap();
+    assert_eq!(total, 1);
+    assert_eq!(books[0].book_id, shelved);
+
+    // Every OPDS route answers as if the book didn't exist
+    let opds = OpdsService::new().await.for_user(other);
+    assert!(opds.book_details(hidden).await.unwrap().is_none());
+    assert!(opds.download(hidden, "epub").await.unwrap().is_none());
+    assert!(opds.cover(hidden).await.unwrap().is_none());
+    assert!(opds.book_details(loose).await.unwrap().is_none());
+    assert!(opds
+        .library_books(personal, &FacetSelection::default(), 1)
+        .await
+        .unwrap()
+        .is_none());
+
+    let xml = opds.all_books(&FacetSelection::default(), 1).await.unwrap();
+    assert!(xml.contains("Shelved Novel"));
+    assert!(!xml.contains("Hidden Diary"));
+    assert!(!xml.contains("Loose Pamphlet"));
+
+    let query = SearchQuery::new(Some("Diary".into()), None, None);
+    let (found, _) = opds.load_search(&query, 1).await.unwrap();
+    assert!(found.is_empty());
+    let query = SearchQuery::new(Some("Pamphlet".into()), None, None);
+    let (found, _) = opds.load_search(&query, 1).await.unwrap();
+    assert!(found.is_empty());
+    let query = SearchQuery::new(Some("Diary".into()), None, None);
+
+    // The REST search leaves them out as well
+    for title in ["Hidden Diary", "Loose Pamphlet"] {
+        let response = search_controller::search_books(
+            AuthUser { id: other },
+            Query(search_controller::SearchQuery {
+                q: None,
+                title: Some(title.into()),
+                author: None,
+                publisher: None,
+                isbn: None,
+            }),
+        )
+        .await
+        .into_response();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(&body[..], b"[]");
+    }
+
+    let own = OpdsService::new().await.for_user(owner);
+    let (found, _) = own.load_search(&query, 1).await.unwrap();
+    assert_eq!(found.len(), 1);
+    assert!(own.download(hidden, "epub").await.unwrap().is_some());
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn test_hidden_books_reject_reading_data() {
+    setup().await.expect("Setup failed");
+    let owner = create_user("owner", "user").await;
+    let other = create_user("other", "user").await;
+    let personal = create_library("personal", Some(owner), Visibility::Private).await;
+    let hidden = create_book("Hidden Diary", Some(personal)).await;
+
+    let statuses = |id: i32| async move {
+        let progress = reading_progress_controller::update_progress(
+            MemberUser { id },
+            Json(UpdateProgressDTO {
+                book_id: hidden,
+                current_position: "epubcfi(/6/2)".into(),
+                chapter_title: None,
+                page_number: None,
+                progress_percentage: Some(0.1),
+            }),
+        )
+        .await
+        .into_response()
+        .status();
+        let bookmark = bookmark_controller::create_bookmark(
+            MemberUser { id },
+            Json(NewBookmarkDTO {
+                book_id: hidden,
+                chapter_title: None,
+                page_number: None,
+                position: "epubcfi(/6/2)".into(),
+            }),
+        )
+        .await
+        .into_response()
+        .status();
+        let annotation = annotation_controller::create_annotation(
+            MemberUser { id },
+            Json(NewAnnotationDTO {
+                book_id: hidden,
+                chapter_title: None,
+                start_position: "epubcfi(/6/2)".into(),
+                end_position: "epubcfi(/6/4)".into(),
+                highlighted_text: None,
+                note: None,
+                color: None,
+            }),
+        )
+        .await
+        .into_response()
+        .status();
+        let sources = metadata_controller::get_metadata_sources(AuthUser { id }, Path(hidden))
+            .await
+            .into_response()
+            .status();
+        [progress, bookmark, annotation, sources]
+    };
+
+    // Others get the same answer as for a book that doesn't exist
+    assert_eq!(statuses(other).await, [StatusCode::NOT_FOUND; 4]);
+    assert_eq!(
+        statuses(owner).await,
+        [
+            StatusCode::OK,
+            StatusCode::CREATED,
+            StatusCode::CREATED,
+            StatusCode::OK
+        ]
+    );
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn test_managing_access() {
+    setup().await.expect("Setup failed");
+    let admin = create_user("admin", "admin").await;
+    let owner = create_user("owner", "user").await;
+    let other = create_user("other", "user").await;
+    let library = create_library("shelf", Some(owner), Visibility::Public).await;
+    let service = LibraryAccessService::new().await;
+
+    // Others can see a public library but not change it
+    assert_eq!(
+        service
+            .set_access(other, library, Some(Visibility::Private), None)
+            .await
+            .unwrap(),
+        LibraryChange::Forbidden
+    );
+    match service
+        .set_access(owner, library, Some(Visibility::Private), None)
+        .await
+        .unwrap()
+    {
+        LibraryChange::Done(l) => assert_eq!(l.visibility(), Visibility::Private),
+        other => panic!("Expected the change to go through, got {:?}", other),
+    }
+    // Once it is private it is hidden from them entirely
+    assert_eq!(
+        service.shares(other, library).await.unwrap(),
+        LibraryChange::NotFound
+    );
+    // Only admins hand libraries to someone else
+    assert_eq!(
+        service
+            .set_access(owner, library, None, Some(other))
+            .await
+            .unwrap(),
+        LibraryChange::Forbidden
+    );
+    assert_eq!(
+        service
+            .set_access(admin, library, None, Some(-1))
+            .await
+            .unwrap(),
+        LibraryChange::Invalid("Owner doesn't exist")
+    );
+
+    let share = match service
+        .share(owner, library, ShareTarget::User(other))
+        .await
+        .unwrap()
+    {
+        LibraryChange::Done(share) => share,
+        other => panic!("Expected a share, got {:?}", other),
+    };
+    assert_eq!(share.user_id, Some(other));
+    assert_eq!(
+        service
+            .share(owner, library, ShareTarget::User(other))
+            .await
+            .unwrap(),
+        LibraryChange::Conflict
+    );
+    assert_eq!(
+        service
+            .share(owner, library, ShareTarget::User(-1))
+            .await
+            .unwrap(),
+        LibraryChange::Invalid("User doesn't exist")
+    );
+    assert_eq!(ids(&service.scope(other).await.unwrap()), vec![library]);
+
+    // Shared users see the library, but it still isn't theirs to manage
+    assert_eq!(
+        service
+            .unshare(other, library, share.share_id)
+            .await
+            .unwrap(),
+        LibraryChange::Forbidden
+    );
+    assert_eq!(
+        service
+            .unshare(owner, library, share.share_id)
+            .await
+            .unwrap(),
+        LibraryChange::Done(())
+    );
+    assert_eq!(
+        service
+            .unshare(owner, library, share.share_id)
+            .await
+            .unwrap(),
+        LibraryChange::NotFound
+    );
+    assert!(ids(&service.scope(other).await.unwrap()).is_empty());
+
+    match service
+        .set_access(admin, library, None, Some(other))
+        .await
+        .unwrap()
+    {
+        LibraryChange::Done(l) => assert_eq!(l.added_by, Some(other)),
+        other => panic!("Expected the change to go through, got {:?}", other),
+    }
+    assert_eq!(ids(&service.scope(other).await.unwrap()), vec![library]);
+    assert!(ids(&service.scope(owner).await.unwrap()).is_empty());
+}
@@ -40,6 +40,7 @@ async fn test_set_and_get_book_path() {
         name: "Test Library",
         path: test_path,
         added_by: None,
+        visibility: None,
     };
 
     let result = repo.add(new_library).await;
@@ -63,6 +64,7 @@ async fn test_get_all_configurations_with_data() {
         name: "Another Library",
         path: test_path,
         added_by: None,
+        visibility: None,
     };
 
     let result = repo.add(new_library).await;
@@ -87,6 +89,7 @@ async fn test_update_book_path() {
         name: "Initial Library",
         path: initial_path,
         added_by: None,
+        visibility: None,
     };
 
     repo.add(new_library).await.expect("Failed to add library");
@@ -103,6 +106,7 @@ async fn test_update_book_path() {
         name: None,
         path: Some(updated_path),
         added_by: None,
+        visibility: None,
     };
 
     let result = repo.update(library_id, update).await;
@@ -0,0 +1,331 @@
+mod common;
+
+use std::sync::Arc;
+
+use stellaron_lib::data::models::books::NewBook;
+use stellaron_lib::data::repos::implementors::book_author_repo::BookAuthorRepo;
+use stellaron_lib::data::repos::implementors::book_repo::BookRepo;
+use stellaron_lib::data::repos::implementors::publisher_repo::PublisherRepo;
+use stellaron_lib::data::repos::traits::repository::Repository;
+use stellaron_lib::services::metadata_service::{
+    FieldValue, FixtureMetadataProvider, MetadataField, MetadataProvider, MetadataService,
+    OpenLibraryProvider,
+};
+
+use common::setup;
+
+fn fixture_provider() -> FixtureMetadataProvider {
+    FixtureMetadataProvider::new(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/fixtures/openlibrary_search.json"
+    ))
+}
+
+/// Helper function to create a poorly tagged book and return its ID
+async fn create_test_book(title_val: &str) -> i32 {
+    let repo = BookRepo::new().await;
+    let new_book = NewBook {
+        title: title_val,
+        published_date: None,
+        publisher_id: None,
+        isbn: Some("978-0-14-032872-1"),
+        file_type: Some("epub"),
+        file_path: None,
+        cover_image_path: None,
+        ..Default::default()
+    };
+    repo.add(new_book)
+        .await
+        .expect("Failed to create test book");
+
+    repo.search_by_title(title_val)
+        .await
+        .expect("Failed to search books")
+        .unwrap()[0]
+        .book_id
+}
+
+#[tokio::test]
+async fn test_fixture_search_by_isbn() {
+    let provider = fixture_provider();
+
+    let candidates = provider
+        .search_by_isbn("978-0-14-032872-1")
+        .await
+        .expect("Failed to search fixture");
+
+    assert_eq!(candidates.len(), 1);
+    assert_eq!(candidates[0].title, "Fantastic Mr Fox");
+    assert_eq!(candidates[0].isbn.as_deref(), Some("9780140328721"));
+    assert_eq!(candidates[0].published_date.as_deref(), Some("1970"));
+}
+
+#[tokio::test]
+async fn test_fixture_search_by_title_author() {
+    let provider = fixture_provider();
+
+    let candidates = provider
+        .search_by_title_author("hobbit", Some("tolkien"))
+        .await
+        .expect("Failed to search fixture");
+    assert_eq!(candidates.len(), 1);
+    assert_eq!(candidates[0].authors, vec!["J.R.R. Tolkien".to_string()]);
+
+    let candidates = provider
+        .search_by_title_author("hobbit", Some("dahl"))
+        .await
+        .expect("Failed to search fixture");
+    assert!(candidates.is_empty());
+}
+
+#[tokio::test]
+async fn test_fixture_cover_missing_file() {
+    let provider = fixture_provider();
+    let candidates = provider
+        .search_by_isbn("9780140328721")
+        .await
+        .expect("Failed to search fixture");
+
+    let cover = provider
+        .fetch_cover(&candidates[0])
+        .await
+        .expect("Failed to fetch cover");
+    assert!(cover.is_none());
+}
+
+#[tokio::test]
+async fn test_resolve_candidate_from_provider() {
+    let service = MetadataService::new(Arc::new(fixture_provider()));
+
+    let by_id = service
+        .resolve(Some("/works/OL262758W"), None)
+        .await
+        .unwrap()
+        .expect("Candidate should be found by id");
+    assert_eq!(by_id.title, "The Hobbit");
+    let by_isbn = service
+        .resolve(None, Some("0-14-032872-6"))
+        .await
+        .unwrap()
+        .expect("Candidate should be found by ISBN");
+    assert_eq!(by_isbn.id.as_deref(), Some("/works/OL45883W"));
+
+    assert!(service
+        .resolve(Some("/works/OL0W"), None)
+        .await
+        .unwrap()
+        .is_none());
+    assert!(service.resolve(None, None).await.unwrap().is_none());
+}
+
+#[tokio::test]
+async fn test_covers_outside_provider_are_refused() {
+    let provider = fixture_provider();
+    let mut candidate = provider
+        .search_by_isbn("9780140328721")
+        .await
+        .unwrap()
+        .remove(0);
+
+    for path in [
+        "/etc/passwd".to_string(),
+        format!(
+            "{}/tests/fixtures/covers/../openlibrary_search.json",
+            env!("CARGO_MANIFEST_DIR")
+        ),
+    ] {
+        candidate.cover_url = Some(path);
+        assert!(provider.fetch_cover(&candidate).await.is_err());
+    }
+
+    // Refused before any request is made
+    let provider = OpenLibraryProvider::new("http://127.0.0.1:9", "http://127.0.0.1:9");
+    for url in [
+        "http://169.254.169.254/b/id/1-L.jpg",
+        "http://127.0.0.1:9/admin",
+        "file:///etc/passwd",
+    ] {
+        candidate.cover_url = Some(url.to_string());
+        let error = provider.fetch_cover(&candidate).await.unwrap_err();
+        assert!(error.to_string().contains("not on"), "{}", error);
+    }
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn test_find_matches_reports_diff() {
+    setup().await.expect("Failed to set up test");
+    let book_id = create_test_book("Unknown Title").await;
+
+    let service = MetadataService::new(Arc::new(fixture_provider()));
+    let matches = service
+        .find_matches(book_id, None, None, None)
+        .await
+        .expect("Failed to find matches")
+        .unwrap();
+
+    assert_eq!(matches.len(), 1);
+    let title_change = matches[0]
+        .changes
+        .iter()
+        .find(|c| c.field == MetadataField::Title)
+        .expect("Title should differ");
+    assert_eq!(
+        title_change.current,
+        Some(FieldValue::Text("Unknown Title".to_string()))
+    );
+    assert_eq!(
+        title_change.proposed,
+        Some(FieldValue::Text("Fantastic Mr Fox".to_string()))
+    );
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn test_apply_match_selected_fields() {
+    setup().await.expect("Failed to set up test");
+    let book_id = create_test_book("Unknown Title").await;
+
+    let service = MetadataService::new(Arc::new(fixture_provider()));
+    let candidate = service
+        .find_matches(book_id, None, None, None)
+        .await
+        .expect("Failed to find matches")
+        .unwrap()
+        .remove(0)
+        .candidate;
+
+    let applied = service
+        .apply_match(
+            book_id,
+            &candidate,
+            Some(&[
+                MetadataField::Title,
+                MetadataField::Authors,
+                MetadataField::Publisher,
+            ]),
+            None,
+        )
+        .await
+        .expect("Failed to apply match")
+        .unwrap();
+    assert_eq!(applied.len(), 3);
+
+    let book = BookRepo::new()
+        .await
+        .get_by_id(book_id)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(book.title, "Fantastic Mr Fox");
+    assert_eq!(book.published_date, None);
+
+    let publisher = PublisherRepo::new()
+        .await
+        .get_by_id(book.publisher_id.unwrap())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(publisher.name, "Puffin");
+
+    let authors = BookAuthorRepo::new()
+        .await
+        .get_authors_by_book(book_id)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(authors.len(), 1);
+    assert_eq!(authors[0].name, "Roald Dahl");
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn test_apply_match_unknown_book() {
+    setup().await.expect("Failed to set up test");
+
+    let service = MetadataService::new(Arc::new(fixture_provider()));
+    let candidate = fixture_provider()
+        .search_by_isbn("9780618002214")
+        .await
+        .unwrap()
+        .remove(0);
+
+    let result = service
+        .apply_match(-1, &candidate, None, None)
+        .await
+        .expect("Failed to apply match");
+    assert!(result.is_none());
+}
+
+/// Helper function to serve covers on a local port: `big` is over the size limit,
+/// any other cover fails
+async fn cover_server() -> String {
+    use axum::{extract::Path, http::StatusCode, response::IntoResponse, routing::get, Router};
+
+    let app = Router::new().route(
+        "/b/id/{name}",
+        get(|Path(name): Path<String>| async move {
+            if name.starts_with("big") {
+                vec![0u8; 10 * 1024 * 1024 + 1].into_response()
+            } else {
+                StatusCode::INTERNAL_SERVER_ERROR.into_response()
+            }
+        }),
+    );
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let base = format!("http://{}", listener.local_addr().unwrap());
+    tokio::spawn(async move { axum::serve(listener, app).await });
+    base
+}
+
+#[tokio::test]
+async fn test_oversized_cover_is_refused() {
+    let base = cover_server().await;
+    let provider = OpenLibraryProvider::new(&base, &base);
+    let mut candidate = fixture_provider()
+        .search_by_isbn("9780140328721")
+        .await
+        .unwrap()
+        .remove(0);
+    candidate.cover_url = Some(format!("{}/b/id/big-L.jpg", base));
+
+    let error = provider.fetch_cover(&candidate).await.unwrap_err();
+    assert!(error.to_string().contains("larger than"), "{}", error);
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn test_failed_cover_leaves_book_unchanged() {
+    setup().await.expect("Failed to set up test");
+    let book_id = create_test_book("Unknown Title").await;
+    let base = cover_server().await;
+
+    let mut candidate = fixture_provider()
+        .search_by_isbn("9780140328721")
+        .await
+        .unwrap()
+        .remove(0);
+    candidate.cover_url = Some(format!("{}/b/id/broken-L.jpg", base));
+
+    let service = MetadataService::new(Arc::new(OpenLibraryProvider::new(&base, &base)));
+    assert!(service
+        .apply_match(book_id, &candidate, None, None)
+        .await
+        .is_err());
+
+    // Nothing was written before the cover failed
+    let book = BookRepo::new()
+        .await
+        .get_by_id(book_id)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(book.title, "Unknown Title");
+    assert_eq!(book.publisher_id, None);
+    assert!(BookAuthorRepo::new()
+        .await
+        .get_authors_by_book(book_id)
+        .await
+        .unwrap()
+        .is_none_or(|authors| authors.is_empty()));
+}
@@ -0,0 +1,198 @@
+mod common;
+
+use serde_json::{json, Value};
+
+use stellaron_lib::data::models::books::NewBook;
+use stellaron_lib::data::repos::implementors::author_repo::AuthorRepo;
+use stellaron_lib::data::repos::implementors::book_author_repo::BookAuthorRepo;
+use stellaron_lib::data::repos::implementors::book_repo::{BookOrder, BookRepo};
+use stellaron_lib::data::repos::implementors::publisher_repo::PublisherRepo;
+use stellaron_lib::opds::search::SearchQuery;
+use stellaron_lib::services::opds2_service::Opds2Service;
+
+use common::setup;
+
+/// Creates a book by Roald Dahl and returns its id
+async fn add_book(title: &str, added_at: &str) -> i32 {
+    let book_id = BookRepo::new()
+        .await
+        .add_returning_id(NewBook {
+            title,
+            added_at: Some(added_at),
+            ..Default::default()
+        })
+        .await
+        .expect("Failed to create book");
+    let author = AuthorRepo::new()
+        .await
+        .get_or_create("Roald Dahl")
+        .await
+        .expect("Failed to create author");
+    BookAuthorRepo::new()
+        .await
+        .replace_authors(book_id, &[author.author_id])
+        .await
+        .expect("Failed to link author");
+    book_id
+}
+
+fn link_with_rel<'a>(links: &'a Value, rel: &str) -> Option<&'a Value> {
+    links
+        .as_array()?
+        .iter()
+        .find(|l| l["rel"].as_str() == Some(rel))
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn test_root_feed() {
+    setup().await.expect("Failed to set up test");
+    let book_id = add_book("Matilda", "2025-11-20 10:00:00").await;
+
+    let feed = serde_json::to_value(Opds2Service::new().await.root().await.unwrap()).unwrap();
+
+    assert_eq!(feed["metadata"]["title"], "Stellaron");
+    assert_eq!(
+        link_with_rel(&feed["links"], "self").unwrap()["href"],
+        "/opds/v2"
+    );
+    assert_eq!(
+        link_with_rel(&feed["links"], "search").unwrap(),
+        &json!({
+            "href": "/opds/v2/search{?query,title,author}",
+            "type": "application/opds+json",
+            "rel": "search",
+            "templated": true
+        })
+    );
+
+    let navigation: Vec<&str> = feed["navigation"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|l| l["href"].as_str().unwrap())
+        .collect();
+    assert_eq!(
+        navigation,
+        [
+            "/opds/v2/recent",
+            "/opds/v2/books",
+            "/opds/v2/authors",
+            "/opds/v2/publishers",
+            "/opds/v2/libraries"
+        ]
+    );
+
+    let group = &feed["groups"][0];
+    assert_eq!(group["metadata"]["title"], "Recently added");
+    assert_eq!(group["publications"][0]["metadata"]["title"], "Matilda");
+    assert_eq!(
+        group["publications"][0]["links"][0]["href"],
+        format!("/opds/v2/books/{}", book_id)
+    );
+    // A root feed is a navigation feed, it has no publications of its own
+    assert!(feed.get("publications").is_none());
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn test_publication() {
+    setup().await.expect("Failed to set up test");
+
+    let publisher = PublisherRepo::new()
+        .await
+        .get_or_create("Jonathan Cape")
+        .await
+        .expect("Failed to create publisher");
+    let book_id = BookRepo::new()
+        .await
+        .add_returning_id(NewBook {
+            title: "Matilda",
+            publisher_id: Some(publisher.publisher_id),
+            isbn: Some("9780224025720"),
+            published_date: Some("1988-10-01"),
+            language: Some("en"),
+            description: Some("<p>A girl who loves books.</p>"),
+            cover_image_path: Some("/covers/matilda.jpg"),
+            file_type: Some("epub"),
+            file_path: Some("/books/matilda.epub"),
+            added_at: Some("2025-11-20 10:00:00"),
+            ..Default::default()
+        })
+        .await
+        .expect("Failed to create book");
+
+    let service = Opds2Service::new().await;
+    let publication =
+        serde_json::to_value(service.publication(book_id).await.unwrap().unwrap()).unwrap();
+
+    assert_eq!(
+        publication["metadata"],
+        json!({
+            "@type": "http://schema.org/Book",
+            "identifier": "urn:isbn:9780224025720",
+            "title": "Matilda",
+            "publisher": [{ "name": "Jonathan Cape" }],
+            "language": "en",
+            "published": "1988-10-01",
+            "modified": "2025-11-20T10:00:00Z",
+            "description": "A girl who loves books."
+        })
+    );
+    assert_eq!(
+        link_with_rel(&publication["links"], "http://opds-spec.org/acquisition").unwrap(),
+        &json!({
+            "href": format!("/opds/books/{}/download/epub", book_id),
+            "type": "application/epub+zip",
+            "rel": "http://opds-spec.org/acquisition",
+            "title": "EPUB"
+        })
+    );
+    assert_eq!(
+        publication["images"],
+        json!([{ "href": format!("/opds/books/{}/cover", book_id), "type": "image/jpeg" }])
+    );
+
+    assert!(service.publication(-1).await.unwrap().is_none());
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn test_sorted_feed_and_search() {
+    setup().await.expect("Failed to set up test");
+    add_book("The Witches", "2025-11-20 10:00:00").await;
+    add_book("Matilda", "2025-11-21 10:00:00").await;
+
+    let service = Opds2Service::new().await;
+
+    let feed = serde_json::to_value(service.books(BookOrder::Newest, 1).await.unwrap()).unwrap();
+    assert_eq!(feed["metadata"]["numberOfItems"], 2);
+    assert_eq!(feed["metadata"]["currentPage"], 1);
+    assert_eq!(feed["publications"][0]["metadata"]["title"], "Matilda");
+    assert_eq!(
+        link_with_rel(&feed["links"], "first").unwrap()["href"],
+        "/opds/v2/books?order=newest&page=1"
+    );
+    assert!(link_with_rel(&feed["links"], "next").is_none());
+
+    let facet = &feed["facets"][0];
+    assert_eq!(facet["metadata"]["title"], "Sort by");
+    assert_eq!(
+        link_with_rel(&facet["links"], "self").unwrap()["href"],
+        "/opds/v2/books?order=newest"
+    );
+
+    let query = SearchQuery::new(Some("witch".to_string()), None, None);
+    let feed = serde_json::to_value(service.search(&query, 1).await.unwrap()).unwrap();
+    assert_eq!(
+        link_with_rel(&feed["links"], "self").unwrap()["href"],
+        "/opds/v2/search?query=witch"
+    );
+    let titles: Vec<&str> = feed["publications"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|p| p["metadata"]["title"].as_str().unwrap())
+        .collect();
+    assert_eq!(titles, ["The Witches"]);
+}
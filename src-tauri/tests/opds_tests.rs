@@ -0,0 +1,602 @@
+mod common;
+
+use stellaron_lib::data::models::book_files::NewBookFile;
+use stellaron_lib::data::models::books::NewBook;
+use stellaron_lib::data::models::libraries::Visibility;
+use stellaron_lib::data::models::reading_progress::NewReadingProgress;
+use stellaron_lib::data::repos::implementors::author_repo::AuthorRepo;
+use stellaron_lib::data::repos::implementors::book_author_repo::BookAuthorRepo;
+use stellaron_lib::data::repos::implementors::book_file_repo::BookFileRepo;
+use stellaron_lib::data::repos::implementors::book_identifier_repo::BookIdentifierRepo;
+use stellaron_lib::data::repos::implementors::book_repo::{
+    BookFacets, BookFilter, BookOrder, BookRepo, BookScope, ReadState,
+};
+use stellaron_lib::data::repos::implementors::book_tag_repo::BookTagRepo;
+use stellaron_lib::data::repos::implementors::publisher_repo::PublisherRepo;
+use stellaron_lib::data::repos::implementors::reading_progress_repo::ReadingProgressRepo;
+use stellaron_lib::data::repos::implementors::series_repo::SeriesRepo;
+use stellaron_lib::data::repos::implementors::tag_repo::TagRepo;
+use stellaron_lib::data::repos::traits::repository::Repository;
+use stellaron_lib::opds::acquisition::{books_feed, Page};
+use stellaron_lib::opds::facets::FacetSelection;
+use stellaron_lib::opds::feed_builder::{
+    escape, timestamp, Entry, FeedBuilder, Link, NAVIGATION_TYPE, REL_SELF,
+};
+use stellaron_lib::opds::navigation;
+use stellaron_lib::opds::search::{self, SearchQuery};
+use stellaron_lib::services::opds_service::OpdsService;
+
+use common::{create_library, create_user, setup};
+
+#[test]
+fn test_escape_and_timestamps() {
+    assert_eq!(
+        escape(r#"Tom & Jerry <"quoted"> 'single'"#),
+        "Tom &amp; Jerry &lt;&quot;quoted&quot;&gt; &apos;single&apos;"
+    );
+    // Control characters are not valid XML and are dropped
+    assert_eq!(escape("a\u{0008}b\nc"), "ab\nc");
+
+    assert_eq!(
+        timestamp(Some("2025-11-22 08:00:00")),
+        "2025-11-22T08:00:00Z"
+    );
+    assert!(timestamp(Some("not a date")).ends_with('Z'));
+}
+
+#[test]
+fn test_feed_builder_output() {
+    let xml = FeedBuilder::new("urn:test:feed", "Fish & Chips")
+        .updated("2025-01-01T00:00:00Z")
+        .author("Stellaron")
+        .link(Link::new(REL_SELF, "/opds?a=1&b=2", NAVIGATION_TYPE).title("Self"))
+        .entry(Entry::new("urn:test:entry", "Entry <1>", "2025-01-01T00:00:00Z").content("Text"))
+        .build();
+
+    assert!(xml.starts_with("<?xml version=\"1.0\" encoding=\"UTF-8\"?>"));
+    assert!(xml.contains("<feed xmlns=\"http://www.w3.org/2005/Atom\""));
+    assert!(xml.contains("xmlns:opds=\"http://opds-spec.org/2010/catalog\""));
+    assert!(xml.contains("<title>Fish &amp; Chips</title>"));
+    assert!(xml.contains("<author><name>Stellaron</name></author>"));
+    assert!(xml.contains(
+        "<link rel=\"self\" href=\"/opds?a=1&amp;b=2\" type=\"application/atom+xml;profile=opds-catalog;kind=navigation\" title=\"Self\"/>"
+    ));
+    assert!(xml.contains("<title>Entry &lt;1&gt;</title>"));
+    assert!(xml.contains("<content type=\"text\">Text</content>"));
+    assert!(xml.trim_end().ends_with("</feed>"));
+}
+
+#[test]
+fn test_root_feed_links() {
+    let xml = navigation::root_feed().build();
+
+    assert!(xml.contains("rel=\"start\" href=\"/opds\""));
+    assert!(xml.contains("rel=\"http://opds-spec.org/sort/new\" href=\"/opds/recent\""));
+    for path in [
+        "/opds/books",
+        "/opds/authors",
+        "/opds/publishers",
+        "/opds/libraries",
+    ] {
+        assert!(
+            xml.contains(&format!("rel=\"subsection\" href=\"{}\"", path)),
+            "Missing subsection {}",
+            path
+        );
+    }
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn test_author_feeds() {
+    setup().await.expect("Failed to set up test");
+
+    let book_id = BookRepo::new()
+        .await
+        .add_returning_id(NewBook {
+            title: "The Twits",
+            ..Default::default()
+        })
+        .await
+        .expect("Failed to create book");
+    let author = AuthorRepo::new()
+        .await
+        .get_or_create("Roald Dahl")
+        .await
+        .expect("Failed to create author");
+    BookAuthorRepo::new()
+        .await
+        .replace_authors(book_id, &[author.author_id])
+        .await
+        .expect("Failed to link author");
+
+    let service = OpdsService::new().await;
+
+    let authors = service.authors().await.expect("Failed to build feed");
+    assert!(authors.contains("<title>Roald Dahl</title>"));
+    assert!(authors.contains(&format!("href=\"/opds/authors/{}\"", author.author_id)));
+
+    let books = service
+        .author_books(author.author_id, &FacetSelection::default(), 1)
+        .await
+        .expect("Failed to build feed")
+        .expect("Author not found");
+    assert!(books.contains("kind=acquisition"));
+    assert!(books.contains(&format!("<id>urn:stellaron:book:{}</id>", book_id)));
+    assert!(books.contains("<title>The Twits</title>"));
+    assert!(books.contains("<author><name>Roald Dahl</name></author>"));
+
+    assert!(service
+        .author_books(-1, &FacetSelection::default(), 1)
+        .await
+        .unwrap()
+        .is_none());
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn test_acquisition_entry() {
+    setup().await.expect("Failed to set up test");
+
+    let publisher = PublisherRepo::new()
+        .await
+        .get_or_create("Jonathan Cape")
+        .await
+        .expect("Failed to create publisher");
+    let book_id = BookRepo::new()
+        .await
+        .add_returning_id(NewBook {
+            title: "Matilda",
+            publisher_id: Some(publisher.publisher_id),
+            isbn: Some("9780224025720"),
+            published_date: Some("1988-10-01"),
+            language: Some("en"),
+            description: Some("<p>A girl who <b>loves</b> books.</p>"),
+            cover_image_path: Some("/covers/matilda.png"),
+            file_type: Some("epub"),
+            file_path: Some("/books/matilda.epub"),
+            ..Default::default()
+        })
+        .await
+        .expect("Failed to create book");
+    let tag = TagRepo::new()
+        .await
+        .get_or_create("Children")
+        .await
+        .expect("Failed to create tag");
+    BookTagRepo::new()
+        .await
+        .replace_tags(book_id, &[tag.tag_id])
+        .await
+        .expect("Failed to link tag");
+    BookIdentifierRepo::new()
+        .await
+        .set_identifier(book_id, "calibre", "0f6a7c3e-1d2b-4c5d-8e9f-a0b1c2d3e4f5")
+        .await
+        .expect("Failed to set identifier");
+    let book_file_repo = BookFileRepo::new().await;
+    for (format, path) in [
+        ("EPUB", "/books/matilda.epub"),
+        ("PDF", "/books/matilda.pdf"),
+    ] {
+        book_file_repo
+            .add(NewBookFile {
+                book_id,
+                format,
+                file_path: path,
+                file_size: None,
+            })
+            .await
+            .expect("Failed to add file");
+    }
+
+    let xml = OpdsService::new()
+        .await
+        .all_books(&FacetSelection::default(), 1)
+        .await
+        .expect("Failed to build feed");
+
+    assert!(xml.contains("<dc:publisher>Jonathan Cape</dc:publisher>"));
+    assert!(xml.contains("<dc:language>en</dc:language>"));
+    assert!(xml.contains("<dc:issued>1988-10-01</dc:issued>"));
+    assert!(xml.contains("<dc:identifier>urn:isbn:9780224025720</dc:identifier>"));
+    assert!(xml
+        .contains("<dc:identifier>urn:uuid:0f6a7c3e-1d2b-4c5d-8e9f-a0b1c2d3e4f5</dc:identifier>"));
+    assert!(xml.contains("<category term=\"Children\" label=\"Children\"/>"));
+    assert!(xml.contains("<summary type=\"text\">A girl who loves books.</summary>"));
+    assert!(xml.contains(&format!(
+        "rel=\"http://opds-spec.org/image\" href=\"/opds/books/{}/cover\" type=\"image/png\"",
+        book_id
+    )));
+    assert!(xml.contains(&format!(
+        "rel=\"http://opds-spec.org/image/thumbnail\" href=\"/opds/books/{}/thumbnail\"",
+        book_id
+    )));
+    assert!(xml.contains(&format!(
+        "rel=\"http://opds-spec.org/acquisition\" href=\"/opds/books/{}/download/epub\" type=\"application/epub+zip\" title=\"EPUB\"",
+        book_id
+    )));
+    assert!(xml.contains(&format!(
+        "href=\"/opds/books/{}/download/pdf\" type=\"application/pdf\"",
+        book_id
+    )));
+
+    let service = OpdsService::new().await;
+    let file = service
+        .download(book_id, "PDF")
+        .await
+        .expect("Failed to find file")
+        .expect("File missing");
+    assert_eq!(file.path, "/books/matilda.pdf");
+    assert!(service.download(book_id, "mobi").await.unwrap().is_none());
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn test_pagination() {
+    setup().await.expect("Failed to set up test");
+
+    let book_repo = BookRepo::new().await;
+    for title in ["delta", "Alpha", "charlie", "Bravo", "echo"] {
+        book_repo
+            .add_returning_id(NewBook {
+                title,
+                ..Default::default()
+            })
+            .await
+            .expect("Failed to create book");
+    }
+
+    let (page, total) = book_repo
+        .get_page(
+            BookFilter::All,
+            &BookFacets::default(),
+            BookOrder::Title,
+            2,
+            2,
+        )
+        .await
+        .expect("Failed to load page");
+    assert_eq!(total, 5);
+    let titles: Vec<&str> = page.iter().map(|b| b.title.as_str()).collect();
+    assert_eq!(titles, ["charlie", "delta"]);
+
+    let (page, total) = book_repo
+        .get_page(
+            BookFilter::Author(-1),
+            &BookFacets::default(),
+            BookOrder::Title,
+            0,
+            2,
+        )
+        .await
+        .expect("Failed to load page");
+    assert!(page.is_empty());
+    assert_eq!(total, 0);
+
+    let page = |number| Page {
+        number,
+        size: 2,
+        total: 5,
+    };
+    let xml = books_feed("urn:test", "Books", "/books", &[], page(2)).build();
+    assert!(xml.contains("rel=\"first\" href=\"/opds/books?page=1\""));
+    assert!(xml.contains("rel=\"previous\" href=\"/opds/books?page=1\""));
+    assert!(xml.contains("rel=\"next\" href=\"/opds/books?page=3\""));
+    assert!(xml.contains("rel=\"last\" href=\"/opds/books?page=3\""));
+
+    let xml = books_feed("urn:test", "Books", "/books", &[], page(1)).build();
+    assert!(!xml.contains("rel=\"previous\""));
+    let xml = books_feed("urn:test", "Books", "/books", &[], page(3)).build();
+    assert!(!xml.contains("rel=\"next\""));
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn test_facets() {
+    setup().await.expect("Failed to set up test");
+
+    let user_id = create_user("facets", "user").await;
+    let library = create_library("shelf", None, Visibility::Public).await;
+    let series = SeriesRepo::new()
+        .await
+        .get_or_create("Discworld")
+        .await
+        .expect("Failed to create series");
+    let tag = TagRepo::new()
+        .await
+        .get_or_create("Fantasy")
+        .await
+        .expect("Failed to create tag");
+
+    let book_repo = BookRepo::new().await;
+    let mut ids = Vec::new();
+    for (title, language, published, file_type) in [
+        ("Mort", "en", "1987-11-12", "EPUB"),
+        ("Sourcery", "en", "1988-05-12", "pdf"),
+        ("Wyrd Sisters", "de", "1988-11-12", "epub"),
+    ] {
+        ids.push(
+            book_repo
+                .add_returning_id(NewBook {
+                    title,
+                    library_id: Some(library),
+                    language: Some(language),
+                    published_date: Some(published),
+                    file_type: Some(file_type),
+                    series_id: Some(series.series_id),
+                    ..Default::default()
+                })
+                .await
+                .expect("Failed to create book"),
+        );
+    }
+    book_repo
+        .add_returning_id(NewBook {
+            title: "Eric",
+            library_id: Some(library),
+            ..Default::default()
+        })
+        .await
+        .expect("Failed to create book");
+    BookTagRepo::new()
+        .await
+        .replace_tags(ids[0], &[tag.tag_id])
+        .await
+        .expect("Failed to link tag");
+    // The recorded files take precedence over the main file's type
+    BookFileRepo::new()
+        .await
+        .add(NewBookFile {
+            book_id: ids[2],
+            format: "MOBI",
+            file_path: "/books/wyrd.mobi",
+            file_size: None,
+        })
+        .await
+        .expect("Failed to add file");
+    let progress_repo = ReadingProgressRepo::new().await;
+    for (book_id, percentage) in [(ids[0], 100.0), (ids[1], 40.0)] {
+        progress_repo
+            .upsert(NewReadingProgress {
+                user_id,
+                book_id,
+                current_position: "",
+                chapter_title: None,
+                page_number: None,
+                progress_percentage: Some(percentage),
+            })
+            .await
+            .expect("Failed to save progress");
+    }
+
+    let titles = |facets: BookFacets, order: BookOrder| {
+        let book_repo = &book_repo;
+        async move {
+            let (books, _) = book_repo
+                .get_page(BookFilter::All, &facets, order, 0, 10)
+                .await
+                .expect("Failed to load page");
+            books.into_iter().map(|b| b.title).collect::<Vec<_>>()
+        }
+    };
+    let facets = |f: fn(&mut BookFacets)| {
+        let mut facets = BookFacets {
+            user_id: Some(user_id),
+            ..Default::default()
+        };
+        f(&mut facets);
+        facets
+    };
+
+    assert_eq!(
+        titles(facets(|f| f.format = Some("epub".into())), BookOrder::Title).await,
+        ["Mort"]
+    );
+    assert_eq!(
+        titles(facets(|f| f.format = Some("mobi".into())), BookOrder::Title).await,
+        ["Wyrd Sisters"]
+    );
+    assert_eq!(
+        titles(facets(|f| f.language = Some("en".into())), BookOrder::Title).await,
+        ["Mort", "Sourcery"]
+    );
+    assert_eq!(
+        titles(facets(|f| f.tag_id = Some(-1)), BookOrder::Title).await,
+        Vec::<String>::new()
+    );
+    assert_eq!(
+        titles(
+            facets(|f| f.read_state = Some(ReadState::Unread)),
+            BookOrder::Title
+        )
+        .await,
+        ["Eric", "Wyrd Sisters"]
+    );
+    assert_eq!(
+        titles(
+            facets(|f| f.read_state = Some(ReadState::Reading)),
+            BookOrder::Title
+        )
+        .await,
+        ["Sourcery"]
+    );
+    assert_eq!(
+        titles(
+            facets(|f| f.read_state = Some(ReadState::Finished)),
+            BookOrder::Title
+        )
+        .await,
+        ["Mort"]
+    );
+    assert_eq!(
+        titles(BookFacets::default(), BookOrder::Published).await,
+        ["Wyrd Sisters", "Sourcery", "Mort", "Eric"]
+    );
+    let in_series = BookFacets {
+        series_id: Some(series.series_id),
+        tag_id: Some(tag.tag_id),
+        ..Default::default()
+    };
+    assert_eq!(titles(in_series, BookOrder::Title).await, ["Mort"]);
+
+    let values = book_repo
+        .facet_values(BookFilter::All, &BookScope::All)
+        .await
+        .expect("Failed to load facet values");
+    assert_eq!(values.formats, ["epub", "mobi", "pdf"]);
+    assert_eq!(values.languages, ["de", "en"]);
+    assert_eq!(values.tags, [(tag.tag_id, "Fantasy".to_string())]);
+    assert_eq!(values.series, [(series.series_id, "Discworld".to_string())]);
+
+    let selection = FacetSelection {
+        order: BookOrder::Newest,
+        facets: BookFacets {
+            language: Some("en".into()),
+            ..Default::default()
+        },
+    };
+    let xml = OpdsService::new()
+        .await
+        .for_user(user_id)
+        .all_books(&selection, 1)
+        .await
+        .expect("Failed to build feed");
+    assert!(xml.contains("rel=\"self\" href=\"/opds/books?sort=newest&amp;language=en\""));
+    assert!(
+        xml.contains("rel=\"last\" href=\"/opds/books?sort=newest&amp;language=en&amp;page=1\"")
+    );
+    assert!(xml.contains(
+        "href=\"/opds/books?sort=newest&amp;language=en\" type=\"application/atom+xml;profile=opds-catalog;kind=acquisition\" title=\"Recently added\" opds:facetGroup=\"Sort by\" opds:activeFacet=\"true\""
+    ));
+    assert!(xml.contains(
+        "href=\"/opds/books?sort=title&amp;language=en\" type=\"application/atom+xml;profile=opds-catalog;kind=acquisition\" title=\"Title\" opds:facetGroup=\"Sort by\"/>"
+    ));
+    assert!(xml.contains(
+        "href=\"/opds/books?sort=newest\" type=\"application/atom+xml;profile=opds-catalog;kind=acquisition\" title=\"All\" opds:facetGroup=\"Language\"/>"
+    ));
+    assert!(xml.contains("title=\"MOBI\" opds:facetGroup=\"Format\""));
+    assert!(xml.contains(
+        "href=\"/opds/books?sort=newest&amp;language=en&amp;status=unread\" type=\"application/atom+xml;profile=opds-catalog;kind=acquisition\" title=\"Unread\" opds:facetGroup=\"Status\""
+    ));
+    assert!(xml.contains(&format!(
+        "href=\"/opds/books?sort=newest&amp;language=en&amp;series={}\"",
+        series.series_id
+    )));
+    assert!(!xml.contains("Wyrd Sisters"));
+
+    // Feeds built without a user have no read status to filter by
+    let xml = OpdsService::new()
+        .await
+        .all_books(&FacetSelection::default(), 1)
+        .await
+        .expect("Failed to build feed");
+    assert!(!xml.contains("opds:facetGroup=\"Status\""));
+}
+
+#[test]
+fn test_opensearch_description() {
+    let xml = search::description();
+    assert!(xml.contains("<OpenSearchDescription xmlns=\"http://a9.com/-/spec/opensearch/1.1/\""));
+    assert!(xml.contains(
+        "template=\"/opds/search?q={searchTerms}&amp;author={atom:author?}&amp;title={atom:title?}\""
+    ));
+
+    let root = navigation::root_feed().build();
+    assert!(root.contains(
+        "rel=\"search\" href=\"/opds/opensearch.xml\" type=\"application/opensearchdescription+xml\""
+    ));
+
+    let query = SearchQuery::new(
+        Some("Fish & Chips".to_string()),
+        Some(" ".to_string()),
+        None,
+    );
+    assert_eq!(query.author, None);
+    assert_eq!(query.path(), "/search?q=Fish%20%26%20Chips");
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn test_search_feed() {
+    setup().await.expect("Failed to set up test");
+
+    let book_repo = BookRepo::new().await;
+    let author_repo = AuthorRepo::new().await;
+    let book_author_repo = BookAuthorRepo::new().await;
+    let mut ids = Vec::new();
+    for (title, author, isbn) in [
+        ("Matilda", "Roald Dahl", "9780224025720"),
+        ("The Witches", "Roald Dahl", "9780224022125"),
+        ("Matilda Bone", "Karen Cushman", "9780395881507"),
+    ] {
+        let book_id = book_repo
+            .add_returning_id(NewBook {
+                title,
+                isbn: Some(isbn),
+                ..Default::default()
+            })
+            .await
+            .expect("Failed to create book");
+        let author = author_repo
+            .get_or_create(author)
+            .await
+            .expect("Failed to create author");
+        book_author_repo
+            .replace_authors(book_id, &[author.author_id])
+            .await
+            .expect("Failed to link author");
+        ids.push(book_id);
+    }
+
+    let service = OpdsService::new().await;
+    let search = |terms: Option<&str>, author: Option<&str>, title: Option<&str>| {
+        SearchQuery::new(
+            terms.map(str::to_string),
+            author.map(str::to_string),
+            title.map(str::to_string),
+        )
+    };
+    let entry = |id: i32| format!("<id>urn:stellaron:book:{}</id>", id);
+
+    let xml = service
+        .search(&search(Some("matilda"), None, None), 1)
+        .await
+        .expect("Failed to search");
+    assert!(xml.contains("<title>Search results for &quot;matilda&quot;</title>"));
+    assert!(xml.contains(&entry(ids[0])) && xml.contains(&entry(ids[2])));
+    assert!(!xml.contains(&entry(ids[1])));
+
+    // Search terms also match authors
+    let xml = service
+        .search(&search(Some("dahl"), None, None), 1)
+        .await
+        .unwrap();
+    assert!(xml.contains(&entry(ids[0])) && xml.contains(&entry(ids[1])));
+    assert!(!xml.contains(&entry(ids[2])));
+
+    // Every given part has to match
+    let xml = service
+        .search(&search(Some("matilda"), Some("dahl"), None), 1)
+        .await
+        .unwrap();
+    assert!(xml.contains(&entry(ids[0])));
+    assert!(!xml.contains(&entry(ids[1])) && !xml.contains(&entry(ids[2])));
+
+    let xml = service
+        .search(&search(None, None, Some("witches")), 1)
+        .await
+        .unwrap();
+    assert!(xml.contains(&entry(ids[1])));
+    assert!(xml.contains("href=\"/opds/search?title=witches\""));
+
+    let xml = service
+        .search(&search(Some("0-224-02212-1"), None, None), 1)
+        .await
+        .unwrap();
+    assert!(xml.contains(&entry(ids[1])));
+    assert!(!xml.contains(&entry(ids[0])));
+
+    let xml = service.search(&search(None, None, None), 1).await.unwrap();
+    assert!(!xml.contains("<entry>"));
+}
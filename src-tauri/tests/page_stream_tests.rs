@@ -0,0 +1,335 @@
+mod common;
+
+use axum::extract::{FromRequestParts, Path, Query};
+use axum::http::{header, Request, StatusCode};
+use axum::response::IntoResponse;
+use std::io::{Cursor, Write};
+use std::path::PathBuf;
+
+use stellaron_lib::controllers::auth_middleware::DownloadUser;
+use stellaron_lib::controllers::dto::opds_dto::PageStreamDTO;
+use stellaron_lib::controllers::opds_controller;
+use stellaron_lib::data::models::api_keys::ApiScope;
+use stellaron_lib::data::models::books::NewBook;
+use stellaron_lib::data::models::libraries::Visibility;
+use stellaron_lib::data::repos::implementors::book_repo::BookRepo;
+use stellaron_lib::data::repos::implementors::reading_progress_repo::ReadingProgressRepo;
+use stellaron_lib::handlers::{comic_handler, pdf_handler};
+use stellaron_lib::opds::facets::FacetSelection;
+use stellaron_lib::services::api_key_service::{ApiKeyCreation, ApiKeyService};
+use stellaron_lib::services::opds_service::OpdsService;
+use stellaron_lib::services::page_service::{downscale_png, PageService};
+
+use common::{create_library, create_user, setup};
+
+/// Helper function to encode a grey RGB image of the given size
+fn png(width: u32, height: u32, shade: u8) -> Vec<u8> {
+    let mut out = Vec::new();
+    {
+        let mut encoder = png::Encoder::new(&mut out, width, height);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header().unwrap();
+        writer
+            .write_image_data(&vec![shade; (width * height * 3) as usize])
+            .unwrap();
+    }
+    out
+}
+
+fn png_size(data: &[u8]) -> (u32, u32) {
+    let reader = png::Decoder::new(Cursor::new(data)).read_info().unwrap();
+    (reader.info().width, reader.info().height)
+}
+
+/// Helper function to write a CBZ with pages stored out of order
+fn create_cbz(name: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("stellaron_pse_{}.cbz", name));
+    let mut zip = zip::ZipWriter::new(std::fs::File::create(&path).unwrap());
+    let options = zip::write::SimpleFileOptions::default();
+    for (entry, shade) in [
+        ("comic/page10.png", 30),
+        ("comic/page2.png", 20),
+        ("comic/page1.png", 10),
+        ("comic/notes.txt", 0),
+        ("__MACOSX/comic/._page1.png", 0),
+    ] {
+        zip.start_file(entry, options).unwrap();
+        zip.write_all(&png(8, 4, shade)).unwrap();
+    }
+    zip.finish().unwrap();
+    path
+}
+
+/// Helper function to build a PDF with one JPEG per page, as scanners write them
+fn scanned_pdf(pages: &[&[u8]], extra_images: usize) -> Vec<u8> {
+    let mut pdf = b"%PDF-1.4\n1 0 obj\n<< /Type /Catalog /Pages 2 0 R >>\nendobj\n".to_vec();
+    pdf.extend_from_slice(
+        format!(
+            "2 0 obj\n<< /Type /Pages /Count {} >>\nendobj\n",
+            pages.len()
+        )
+        .as_bytes(),
+    );
+    let mut images: Vec<&[u8]> = pages.to_vec();
+    images.extend(std::iter::repeat_n(b"logo".as_slice(), extra_images));
+    for (i, image) in images.iter().enumerate() {
+        let id = 3 + i * 2;
+        if i < pages.len() {
+            pdf.extend_from_slice(
+                format!(
+                    "{} 0 obj\n<< /Type /Page /Parent 2 0 R /Resources << /XObject << /Im0 {} 0 R >> >> >>\nendobj\n",
+                    id,
+                    id + 1
+                )
+                .as_bytes(),
+            );
+        }
+        pdf.extend_from_slice(
+            format!(
+                "{} 0 obj\n<< /Type /XObject /Subtype /Image /Filter /DCTDecode /Length {} >>\nstream\n",
+                id + 1,
+                image.len()
+            )
+            .as_bytes(),
+        );
+        pdf.extend_from_slice(image);
+        pdf.extend_from_slice(b"\nendstream\nendobj\n");
+    }
+    pdf.extend_from_slice(b"%%EOF\n");
+    pdf
+}
+
+#[test]
+fn test_natural_order() {
+    let mut names = vec!["p10.jpg", "P2.jpg", "p1.jpg", "p01b.jpg"];
+    names.sort_by(|a, b| comic_handler::natural_cmp(a, b));
+    assert_eq!(names, ["p1.jpg", "p01b.jpg", "P2.jpg", "p10.jpg"]);
+}
+
+#[test]
+fn test_pdf_pages() {
+    let pdf = scanned_pdf(&[b"first page", b"second >> stream\npage"], 0);
+    let pages = pdf_handler::page_images(&pdf).expect("PDF should be streamable");
+    assert_eq!(pages.len(), 2);
+    assert_eq!(&pdf[pages[0].clone()], b"first page");
+    assert_eq!(&pdf[pages[1].clone()], b"second >> stream\npage");
+
+    // Images that aren't pages make it impossible to tell which image is which page
+    assert!(pdf_handler::page_images(&scanned_pdf(&[b"only page"], 1)).is_none());
+    assert!(pdf_handler::page_images(b"%PDF-1.4\n%%EOF\n").is_none());
+
+    // A length running past the end of the file falls back to the endstream marker
+    let pdf = String::from_utf8(scanned_pdf(&[b"page"], 0))
+        .unwrap()
+        .replace("/Length 4", &format!("/Length {}", usize::MAX))
+        .into_bytes();
+    let pages = pdf_handler::page_images(&pdf).expect("PDF should be streamable");
+    assert_eq!(&pdf[pages[0].clone()], b"page");
+}
+
+#[test]
+fn test_downscale_png() {
+    let scaled = downscale_png(&png(8, 4, 100), 4)
+        .unwrap()
+        .expect("Image should be scaled");
+    assert_eq!(png_size(&scaled), (4, 2));
+
+    let mut reader = png::Decoder::new(Cursor::new(&scaled)).read_info().unwrap();
+    let mut pixels = vec![0; reader.output_buffer_size()];
+    reader.next_frame(&mut pixels).unwrap();
+    assert!(pixels.iter().all(|p| *p == 100));
+
+    assert!(downscale_png(&png(8, 4, 100), 8).unwrap().is_none());
+}
+
+#[test]
+fn test_downscale_png_to_few_pixels() {
+    // Each target pixel covers 4200 * 4200 white pixels, more than a u32 sum can hold
+    let scaled = downscale_png(&png(4200, 4200, 255), 1).unwrap().unwrap();
+
+    let mut reader = png::Decoder::new(Cursor::new(&scaled)).read_info().unwrap();
+    let mut pixels = vec![0; reader.output_buffer_size()];
+    reader.next_frame(&mut pixels).unwrap();
+    assert_eq!(pixels, vec![255; 3]);
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn test_thumbnails_are_scaled_covers() {
+    setup().await.expect("Failed to set up test");
+    let user_id = create_user("thumbnail_viewer", "user").await;
+    let library = create_library("shelf", None, Visibility::Public).await;
+
+    let png_cover = std::env::temp_dir().join("stellaron_thumbnail_cover.png");
+    std::fs::write(&png_cover, png(480, 720, 50)).unwrap();
+    let book_repo = BookRepo::new().await;
+    let book_id = book_repo
+        .add_returning_id(NewBook {
+            title: "Drawn",
+            library_id: Some(library),
+            cover_image_path: Some(png_cover.to_str().unwrap()),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    let photo_id = book_repo
+        .add_returning_id(NewBook {
+            title: "Photographed",
+            library_id: Some(library),
+            cover_image_path: Some("/covers/photographed.jpg"),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+    let service = OpdsService::new().await.for_user(user_id);
+    let thumbnail = service.thumbnail(book_id).await.unwrap().unwrap();
+    assert_eq!(png_size(&thumbnail), (240, 360));
+    // JPEG covers can't be scaled, they have no thumbnail link
+    assert!(service.thumbnail(photo_id).await.unwrap().is_none());
+    let xml = service
+        .all_books(&FacetSelection::default(), 1)
+        .await
+        .unwrap();
+    assert!(xml.contains(&format!("/opds/books/{}/thumbnail", book_id)));
+    assert!(!xml.contains(&format!("/opds/books/{}/thumbnail", photo_id)));
+    assert!(xml.contains(&format!("/opds/books/{}/cover", photo_id)));
+}
+
+/// Streams page `index` of a book through the OPDS route with an API key of `scopes`
+async fn stream_with_key(user_id: i32, book_id: i32, index: usize, scopes: &[ApiScope]) {
+    let ApiKeyCreation::Created(created) = ApiKeyService::new()
+        .await
+        .create(user_id, "Reader", scopes, None)
+        .await
+        .unwrap()
+    else {
+        panic!("Expected an API key");
+    };
+    let (mut parts, _) = Request::builder()
+        .header(header::AUTHORIZATION, format!("Bearer {}", created.key))
+        .body(())
+        .unwrap()
+        .into_parts();
+    let user = DownloadUser::from_request_parts(&mut parts, &())
+        .await
+        .map_err(|e| e.into_response().status())
+        .unwrap();
+    let response = opds_controller::page(
+        user,
+        Path((book_id, index)),
+        Query(PageStreamDTO::default()),
+    )
+    .await
+    .into_response();
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn test_download_keys_without_progress_scope_keep_position() {
+    setup().await.expect("Failed to set up test");
+    let user_id = create_user("pse_key_reader", "user").await;
+    let library = create_library("shelf", None, Visibility::Public).await;
+    let path = create_cbz("key_stream");
+    let book_id = BookRepo::new()
+        .await
+        .add_returning_id(NewBook {
+            title: "Keyed Comic",
+            library_id: Some(library),
+            file_type: Some("cbz"),
+            file_path: Some(path.to_str().unwrap()),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    let progress = || async {
+        ReadingProgressRepo::new()
+            .await
+            .get_by_user_and_book(user_id, book_id)
+            .await
+            .unwrap()
+            .and_then(|p| p.page_number)
+    };
+
+    stream_with_key(user_id, book_id, 1, &[ApiScope::Download]).await;
+    assert_eq!(progress().await, None);
+
+    stream_with_key(
+        user_id,
+        book_id,
+        2,
+        &[ApiScope::Download, ApiScope::Progress],
+    )
+    .await;
+    assert_eq!(progress().await, Some(3));
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn test_comic_page_stream() {
+    setup().await.expect("Failed to set up test");
+    let user_id = create_user("pse_reader", "user").await;
+    let library = create_library("shelf", None, Visibility::Public).await;
+
+    let path = create_cbz("stream");
+    let book_id = BookRepo::new()
+        .await
+        .add_returning_id(NewBook {
+            title: "Comic",
+            library_id: Some(library),
+            file_type: Some("cbz"),
+            file_path: Some(path.to_str().unwrap()),
+            ..Default::default()
+        })
+        .await
+        .expect("Failed to create book");
+
+    let xml = OpdsService::new()
+        .await
+        .for_user(user_id)
+        .all_books(&FacetSelection::default(), 1)
+        .await
+        .expect("Failed to build feed");
+    assert!(xml.contains("xmlns:pse=\"http://vaemendis.net/opds-pse/ns\""));
+    assert!(xml.contains(&format!(
+        "<link rel=\"http://vaemendis.net/opds-pse/stream\" href=\"/opds/books/{}/pages/{{pageNumber}}?maxWidth={{maxWidth}}\" type=\"image/jpeg\" pse:count=\"3\"/>",
+        book_id
+    )));
+
+    let service = PageService::new().await;
+    let first = service
+        .page(book_id, 0, None)
+        .await
+        .expect("Failed to read page")
+        .expect("Page missing");
+    assert_eq!(first.mime_type, "image/png");
+    assert_eq!(first.data, png(8, 4, 10));
+    let last = service.page(book_id, 2, None).await.unwrap().unwrap();
+    assert_eq!(last.data, png(8, 4, 30));
+    assert!(service.page(book_id, 3, None).await.unwrap().is_none());
+
+    let scaled = service.page(book_id, 1, Some(2)).await.unwrap().unwrap();
+    assert_eq!(png_size(&scaled.data), (2, 1));
+
+    service
+        .record_progress(user_id, book_id, 1)
+        .await
+        .expect("Failed to record progress");
+    let progress = ReadingProgressRepo::new()
+        .await
+        .get_by_user_and_book(user_id, book_id)
+        .await
+        .unwrap()
+        .expect("Progress missing");
+    assert_eq!(progress.page_number, Some(2));
+
+    let xml = OpdsService::new()
+        .await
+        .for_user(user_id)
+        .all_books(&FacetSelection::default(), 1)
+        .await
+        .unwrap();
+    assert!(xml.contains("pse:count=\"3\" pse:lastRead=\"1\" pse:lastReadDate=\""));
+}
@@ -0,0 +1,334 @@
+mod common;
+
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use diesel_async::RunQueryDsl;
+
+use stellaron_lib::data::database;
+use stellaron_lib::data::models::password_resets::NewPasswordReset;
+use stellaron_lib::data::repos::implementors::password_reset_repo::PasswordResetRepo;
+use stellaron_lib::data::repos::implementors::user_repo::UserRepo;
+use stellaron_lib::data::repos::traits::repository::Repository;
+use stellaron_lib::services::authentication_service::AuthenticationService;
+use stellaron_lib::services::mail_service::{LogMailer, MailError, MailMessage, Mailer};
+use stellaron_lib::services::password_service::{PasswordChange, PasswordReset, PasswordService};
+use stellaron_lib::services::session_service::{hash_token, RefreshOutcome, SessionService};
+
+use common::{create_user_with_password, setup};
+
+/// Keeps sent mails in memory
+#[derive(Default)]
+struct RecordingMailer {
+    sent: Mutex<Vec<MailMessage>>,
+}
+
+#[async_trait]
+impl Mailer for RecordingMailer {
+    async fn send(&self, message: &MailMessage) -> Result<(), MailError> {
+        self.sent.lock().unwrap().push(message.clone());
+        Ok(())
+    }
+}
+
+impl RecordingMailer {
+    fn count(&self) -> usize {
+        self.sent.lock().unwrap().len()
+    }
+
+    /// Reset token of the last mail
+    fn last_token(&self) -> String {
+        let sent = self.sent.lock().unwrap();
+        let body = &sent.last().expect("No mail sent").body;
+        body.split(|c: char| !c.is_ascii_hexdigit())
+            .find(|word| word.len() == 64)
+            .expect("No token in mail")
+            .to_string()
+    }
+}
+
+async fn password_matches(user_id: i32, password: &str) -> bool {
+    let user = UserRepo::new()
+        .await
+        .get_by_id(user_id)
+        .await
+        .unwrap()
+        .unwrap();
+    AuthenticationService::new()
+        .verify_password(password, &user.password_hash)
+        .unwrap()
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn test_change_password() {
+    setup().await.expect("Setup failed");
+    let uid = create_user_with_password("pw_change", "user", "old password").await;
+    let service = PasswordService::new(Arc::new(RecordingMailer::default()));
+
+    assert_eq!(
+        service
+            .change_password(uid, "wrong", "new password")
+            .await
+            .unwrap(),
+        PasswordChange::WrongPassword
+    );
+    assert_eq!(
+        service
+            .change_password(uid, "old password", "short")
+            .await
+            .unwrap(),
+        PasswordChange::TooShort
+    );
+    assert!(password_matches(uid, "old password").await);
+
+    assert_eq!(
+        service
+            .change_password(uid, "old password", "new password")
+            .await
+            .unwrap(),
+        PasswordChange::Changed
+    );
+    assert!(password_matches(uid, "new password").await);
+    assert_eq!(
+        service
+            .change_password(uid + 1000, "x", "new password")
+            .await
+            .unwrap(),
+        PasswordChange::UserNotFound
+    );
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn test_reset_flow_revokes_sessions() {
+    setup().await.expect("Setup failed");
+    let uid = create_user_with_password("pw_reset", "user", "old password").await;
+    let mailer = Arc::new(RecordingMailer::default());
+    let service = PasswordService::new(mailer.clone());
+    let sessions = SessionService::new().await;
+    let tokens = sessions.create(uid, None, None).await.unwrap();
+
+    service.request_reset("pw_reset@test.com").await.unwrap();
+    assert_eq!(mailer.count(), 1);
+    assert_eq!(mailer.sent.lock().unwrap()[0].to, "pw_reset@test.com");
+    let token = mailer.last_token();
+
+    // Only the hash is stored
+    let repo = PasswordResetRepo::new().await;
+    assert!(repo.get_by_token_hash(&token).await.unwrap().is_none());
+    assert!(repo
+        .get_by_token_hash(&hash_token(&token))
+        .await
+        .unwrap()
+        .is_some());
+
+    assert_eq!(
+        service.reset_password(&token, "short").await.unwrap(),
+        PasswordReset::TooShort
+    );
+    assert_eq!(
+        service
+            .reset_password(&token, "new password")
+            .await
+            .unwrap(),
+        PasswordReset::Reset
+    );
+    assert!(password_matches(uid, "new password").await);
+    assert_eq!(
+        sessions.refresh(&tokens.refresh_token, None).await.unwrap(),
+        RefreshOutcome::Invalid
+    );
+
+    // Tokens are single-use
+    assert_eq!(
+        service
+            .reset_password(&token, "another password")
+            .await
+            .unwrap(),
+        PasswordReset::InvalidToken
+    );
+    assert!(password_matches(uid, "new password").await);
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn test_unknown_email_sends_nothing() {
+    setup().await.expect("Setup failed");
+    create_user_with_password("pw_unknown", "user", "password").await;
+    let mailer = Arc::new(RecordingMailer::default());
+    let service = PasswordService::new(mailer.clone());
+
+    service.request_reset("nobody@test.com").await.unwrap();
+    assert_eq!(mailer.count(), 0);
+    assert_eq!(
+        service
+            .reset_password("not-a-token", "new password")
+            .await
+            .unwrap(),
+        PasswordReset::InvalidToken
+    );
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn test_new_request_replaces_old_token() {
+    setup().await.expect("Setup failed");
+    let uid = create_user_with_password("pw_admin", "user", "password").await;
+    let mailer = Arc::new(RecordingMailer::default());
+    let service = PasswordService::new(mailer.clone());
+
+    assert!(service.request_reset_for_user(uid).await.unwrap());
+    let first = mailer.last_token();
+    assert!(service.request_reset_for_user(uid).await.unwrap());
+    let second = mailer.last_token();
+    assert_ne!(first, second);
+    assert!(!service.request_reset_for_user(uid + 1000).await.unwrap());
+
+    assert_eq!(
+        service
+            .reset_password(&first, "new password")
+            .await
+            .unwrap(),
+        PasswordReset::InvalidToken
+    );
+    assert_eq!(
+        service
+            .reset_password(&second, "new password")
+            .await
+            .unwrap(),
+        PasswordReset::Reset
+    );
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn test_repeated_request_keeps_pending_token() {
+    setup().await.expect("Setup failed");
+    let uid = create_user_with_password("pw_repeat", "user", "password").await;
+    let mailer = Arc::new(RecordingMailer::default());
+    let service = PasswordService::new(mailer.clone());
+
+    service.request_reset("pw_repeat@test.com").await.unwrap();
+    let first = mailer.last_token();
+    // Asking again right away neither mails nor invalidates the first token
+    service.request_reset("pw_repeat@test.com").await.unwrap();
+    assert_eq!(mailer.count(), 1);
+    assert_eq!(
+        service
+            .reset_password(&first, "new password")
+            .await
+            .unwrap(),
+        PasswordReset::Reset
+    );
+    assert!(password_matches(uid, "new password").await);
+}
+
+#[tokio::test]
+async fn test_reset_requests_are_throttled() {
+    let service = PasswordService::new(Arc::new(RecordingMailer::default()));
+    let ip = Some("192.0.2.40".parse().unwrap());
+
+    assert!(service
+        .throttle_reset_request("throttled@test.com", ip)
+        .is_ok());
+    assert!(service
+        .throttle_reset_request("throttled@test.com", ip)
+        .is_err());
+    // Another address from the same client still gets through a few times
+    assert!(service.throttle_reset_request("other@test.com", ip).is_ok());
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn test_expired_token() {
+    setup().await.expect("Setup failed");
+    let uid = create_user_with_password("pw_expired", "user", "password").await;
+    let service = PasswordService::new(Arc::new(RecordingMailer::default()));
+
+    let token = "expired-reset-token";
+    PasswordResetRepo::new()
+        .await
+        .add(NewPasswordReset {
+            user_id: uid,
+            token_hash: &hash_token(token),
+            expires_at: "2020-01-01 00:00:00",
+        })
+        .await
+        .expect("Failed to add reset token");
+
+    assert_eq!(
+        service.reset_password(token, "new password").await.unwrap(),
+        PasswordReset::InvalidToken
+    );
+    assert!(password_matches(uid, "password").await);
+}
+
+#[tokio::test]
+async fn test_log_mailer_appends_to_file() {
+    let path = std::env::temp_dir().join(format!("stellaron_mail_{}.log", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let mailer = LogMailer::new(Some(&path));
+
+    for subject in ["First", "Second"] {
+        mailer
+            .send(&MailMessage {
+                to: "reader@test.com".to_string(),
+                subject: subject.to_string(),
+                body: "Hello".to_string(),
+            })
+            .await
+            .unwrap();
+    }
+
+    let log = std::fs::read_to_string(&path).unwrap();
+    assert!(log.contains("To: reader@test.com"));
+    assert!(log.contains("Subject: First") && log.contains("Subject: Second"));
+    let _ = std::fs::remove_file(&path);
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn test_forgot_password_answers_before_mailing() {
+    use axum::{http::StatusCode, response::IntoResponse, Json};
+    use diesel::{ExpressionMethods, QueryDsl};
+    use stellaron_lib::controllers::{dto::password_dto::ForgotPasswordDTO, password_controller};
+    use stellaron_lib::data::models::schema::password_resets::dsl::*;
+    use stellaron_lib::services::audit_service::RequestOrigin;
+
+    setup().await.expect("Setup failed");
+    let uid = create_user_with_password("pw_forgot", "user", "password").await;
+    // The route is only served with a configured mailer
+    let path = std::env::temp_dir().join("stellaron_forgot_mail.log");
+    std::env::set_var("MAILER", "log");
+    std::env::set_var("MAIL_LOG_PATH", &path);
+
+    for address in ["nobody@test.com", "pw_forgot@test.com"] {
+        let response = password_controller::forgot_password(
+            RequestOrigin::default(),
+            Json(ForgotPasswordDTO {
+                email: address.to_string(),
+            }),
+        )
+        .await
+        .into_response();
+        assert_eq!(response.status(), StatusCode::ACCEPTED);
+    }
+
+    // The token of the known address is still created, after the response
+    let mut conn = database::connect_from_pool().await.unwrap();
+    for _ in 0..100 {
+        let tokens: i64 = password_resets
+            .filter(user_id.eq(uid))
+            .count()
+            .get_result(&mut conn)
+            .await
+            .unwrap();
+        if tokens == 1 {
+            let _ = std::fs::remove_file(&path);
+            return;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+    }
+    panic!("No reset token was created");
+}
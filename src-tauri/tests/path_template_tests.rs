@@ -0,0 +1,134 @@
+mod common;
+
+use std::path::Path;
+
+use stellaron_lib::data::repos::implementors::book_metadata_source_repo::BookMetadataSourceRepo;
+use stellaron_lib::data::repos::implementors::book_repo::BookRepo;
+use stellaron_lib::data::repos::traits::repository::Repository;
+use stellaron_lib::handlers::epub_handler::BookMetadata;
+use stellaron_lib::parsers::path_template::{
+    infer_from_path, parse_templates, PathTemplate, DEFAULT_TEMPLATES,
+};
+use stellaron_lib::services::book_service;
+use stellaron_lib::services::history_service::{HistoryBatch, SOURCE_SCAN};
+
+use common::setup;
+
+#[test]
+fn test_parse_template_errors() {
+    assert!(PathTemplate::parse("{author}/{title}.{ext}").is_ok());
+    assert!(PathTemplate::parse("").is_err());
+    assert!(PathTemplate::parse("{author}/{name}.{ext}").is_err());
+    assert!(PathTemplate::parse("{author}/{title.{ext}").is_err());
+    // Without a title nothing useful can be inferred
+    assert!(PathTemplate::parse("{author}/{series}.{ext}").is_err());
+
+    let (templates, errors) = parse_templates(&["{title}.{ext}", "{bogus}"]);
+    assert_eq!(templates.len(), 1);
+    assert_eq!(errors.len(), 1);
+}
+
+#[test]
+fn test_infer_series_from_nested_path() {
+    let template = PathTemplate::parse("{author}/{series}/{series_index} - {title}.{ext}").unwrap();
+
+    let inferred = template
+        .infer(Path::new(
+            "Fiction/Terry Pratchett/Discworld/2.5 - The_Light_Fantastic.epub",
+        ))
+        .expect("Template should match");
+
+    assert_eq!(inferred.authors, vec!["Terry Pratchett".to_string()]);
+    assert_eq!(inferred.series.as_deref(), Some("Discworld"));
+    assert_eq!(inferred.series_index, Some(2.5));
+    assert_eq!(inferred.title.as_deref(), Some("The Light Fantastic"));
+
+    assert!(template.infer(Path::new("Loose File.epub")).is_none());
+}
+
+#[test]
+fn test_infer_multiple_authors_and_year() {
+    let template = PathTemplate::parse("{author}/{title} ({year}).{ext}").unwrap();
+
+    let inferred = template
+        .infer(Path::new(
+            "Neil Gaiman & Terry Pratchett/Good Omens (1990).epub",
+        ))
+        .expect("Template should match");
+
+    assert_eq!(
+        inferred.authors,
+        vec!["Neil Gaiman".to_string(), "Terry Pratchett".to_string()]
+    );
+    assert_eq!(inferred.title.as_deref(), Some("Good Omens"));
+    assert_eq!(inferred.published_date.as_deref(), Some("1990"));
+}
+
+#[test]
+fn test_default_templates_fall_through() {
+    let (templates, errors) = parse_templates(&DEFAULT_TEMPLATES);
+    assert!(errors.is_empty());
+
+    let inferred = infer_from_path(&templates, Path::new("Roald Dahl - Matilda.epub")).unwrap();
+    assert_eq!(inferred.authors, vec!["Roald Dahl".to_string()]);
+    assert_eq!(inferred.title.as_deref(), Some("Matilda"));
+
+    let inferred = infer_from_path(&templates, Path::new("Matilda.epub")).unwrap();
+    assert!(inferred.authors.is_empty());
+    assert_eq!(inferred.title.as_deref(), Some("Matilda"));
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn test_store_metadata_records_sources() {
+    setup().await.expect("Setup failed");
+
+    let template = PathTemplate::parse("{author}/{series}/{series_index} - {title}.{ext}").unwrap();
+    let inferred = template.infer(Path::new("Roald Dahl/Gobblefunk/1 - The BFG.epub"));
+
+    let metadata = BookMetadata {
+        title: Some("The BFG".to_string()),
+        authors: Vec::new(),
+        published_date: None,
+        publishers: Vec::new(),
+        isbn: None,
+        file_path: "/nonexistent/Roald Dahl/Gobblefunk/1 - The BFG.epub".to_string(),
+        cover_data: None,
+    };
+
+    let book_id = book_service::store_metadata(
+        metadata,
+        inferred,
+        None,
+        &HistoryBatch::new(SOURCE_SCAN, None),
+    )
+    .await
+    .expect("Failed to store metadata");
+
+    let book = BookRepo::new()
+        .await
+        .get_by_id(book_id)
+        .await
+        .expect("Failed to get book")
+        .expect("Book not found");
+    assert_eq!(book.series_index, Some(1.0));
+
+    let sources = BookMetadataSourceRepo::new()
+        .await
+        .get_by_book(book_id)
+        .await
+        .expect("Failed to get sources")
+        .expect("No sources recorded");
+    let source_of = |name: &str| {
+        sources
+            .iter()
+            .find(|s| s.field == name)
+            .map(|s| s.source.as_str())
+    };
+
+    assert_eq!(source_of("title"), Some(book_service::SOURCE_EMBEDDED));
+    assert_eq!(source_of("authors"), Some(book_service::SOURCE_PATH));
+    assert_eq!(source_of("series"), Some(book_service::SOURCE_PATH));
+    assert_eq!(source_of("series_index"), Some(book_service::SOURCE_PATH));
+    assert_eq!(source_of("publisher"), None);
+}
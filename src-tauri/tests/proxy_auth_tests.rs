@@ -0,0 +1,314 @@
+mod common;
+
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+
+use axum::extract::{ConnectInfo, FromRequestParts};
+use axum::http::{header, request::Parts, HeaderMap, HeaderValue, Request, StatusCode};
+use axum::response::IntoResponse;
+
+use stellaron_lib::controllers::auth_middleware::{AdminUser, AuthUser, LoginUser};
+use stellaron_lib::data::models::users::Role;
+use stellaron_lib::data::repos::implementors::user_repo::UserRepo;
+use stellaron_lib::data::repos::traits::repository::Repository;
+use stellaron_lib::services::audit_service::RequestOrigin;
+use stellaron_lib::services::proxy_auth_service::{
+    ProxyAuthConfig, ProxyAuthService, ProxyIdentity, TrustedSource,
+};
+use stellaron_lib::services::token_service::Tokenizer;
+
+use common::{create_user, setup};
+
+async fn role_of(username: &str) -> Role {
+    UserRepo::new()
+        .await
+        .get_by_username(username)
+        .await
+        .unwrap()
+        .unwrap()
+        .role()
+}
+
+fn from(ip: [u8; 4]) -> RequestOrigin {
+    RequestOrigin {
+        ip: Some(IpAddr::V4(Ipv4Addr::from(ip))),
+        user_agent: None,
+    }
+}
+
+fn headers(pairs: &[(&'static str, &str)]) -> HeaderMap {
+    let mut map = HeaderMap::new();
+    for (name, value) in pairs {
+        map.insert(*name, HeaderValue::from_str(value).unwrap());
+    }
+    map
+}
+
+fn service(configure: impl FnOnce(&mut ProxyAuthConfig)) -> ProxyAuthService {
+    let mut config = ProxyAuthConfig::new(vec![TrustedSource::parse("10.0.0.0/8").unwrap()]);
+    configure(&mut config);
+    ProxyAuthService::new(config)
+}
+
+#[test]
+fn test_trusted_sources() {
+    let network = TrustedSource::parse("10.1.0.0/16").unwrap();
+    assert!(network.contains(IpAddr::from([10, 1, 200, 3])));
+    assert!(!network.contains(IpAddr::from([10, 2, 0, 1])));
+    // IPv4 clients of a dual stack listener show up mapped into IPv6
+    assert!(network.contains("::ffff:10.1.0.9".parse().unwrap()));
+
+    let single = TrustedSource::parse(" 127.0.0.1 ").unwrap();
+    assert!(single.contains(IpAddr::from([127, 0, 0, 1])));
+    assert!(!single.contains(IpAddr::from([127, 0, 0, 2])));
+    assert!(TrustedSource::parse("fd00::/8")
+        .unwrap()
+        .contains("fd12::1".parse().unwrap()));
+    assert!(TrustedSource::parse("0.0.0.0/0")
+        .unwrap()
+        .contains(IpAddr::from([8, 8, 8, 8])));
+
+    assert!(TrustedSource::parse("10.0.0.0/33").is_none());
+    assert!(TrustedSource::parse("proxy.local").is_none());
+
+    assert_eq!(
+        ProxyAuthConfig::parse_group_roles("admins=admin, family = guest"),
+        Some(vec![
+            ("admins".to_string(), Role::Admin),
+            ("family".to_string(), Role::Guest),
+        ])
+    );
+    assert_eq!(ProxyAuthConfig::parse_group_roles("admins=owner"), None);
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn test_only_trusted_proxies_assert_users() {
+    setup().await.expect("Setup failed");
+    let user_id = create_user("proxied", "user").await;
+    let service = service(|_| {});
+    let named = headers(&[("remote-user", "proxied")]);
+
+    assert_eq!(
+        service
+            .authenticate(&from([10, 0, 0, 2]), &named)
+            .await
+            .unwrap(),
+        ProxyIdentity::User(user_id)
+    );
+    // Direct clients can send the header too, it is ignored
+    assert_eq!(
+        service
+            .authenticate(&from([192, 0, 2, 7]), &named)
+            .await
+            .unwrap(),
+        ProxyIdentity::Absent
+    );
+    assert_eq!(
+        service
+            .authenticate(&RequestOrigin::default(), &named)
+            .await
+            .unwrap(),
+        ProxyIdentity::Absent
+    );
+    // Requests the proxy lets through unauthenticated fall back to tokens
+    assert_eq!(
+        service
+            .authenticate(&from([10, 0, 0, 2]), &headers(&[("remote-user", " ")]))
+            .await
+            .unwrap(),
+        ProxyIdentity::Absent
+    );
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn test_provisioning_and_group_roles() {
+    setup().await.expect("Setup failed");
+    let service = service(|config| {
+        config.group_roles = vec![
+            ("admins".to_string(), Role::Admin),
+            ("readers".to_string(), Role::User),
+        ];
+        config.default_role = Role::Guest;
+    });
+    let proxy = from([10, 0, 0, 2]);
+
+    let first = service
+        .authenticate(
+            &proxy,
+            &headers(&[
+                ("remote-user", "newcomer"),
+                ("remote-email", "newcomer@example.com"),
+                ("remote-groups", "readers, admins"),
+            ]),
+        )
+        .await
+        .unwrap();
+    let ProxyIdentity::User(user_id) = first else {
+        panic!("Expected a provisioned user, got {:?}", first);
+    };
+    let user = UserRepo::new()
+        .await
+        .get_by_id(user_id)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(user.email, "newcomer@example.com");
+    assert_eq!(user.role(), Role::Admin);
+
+    // Seen again, the account is reused and its role follows the groups
+    create_user("keeper", "admin").await;
+    assert_eq!(
+        service
+            .authenticate(
+                &proxy,
+                &headers(&[("remote-user", "newcomer"), ("remote-groups", "readers")])
+            )
+            .await
+            .unwrap(),
+        ProxyIdentity::User(user_id)
+    );
+    assert_eq!(role_of("newcomer").await, Role::User);
+    // Without a groups header the role stays
+    service
+        .authenticate(&proxy, &headers(&[("remote-user", "newcomer")]))
+        .await
+        .unwrap();
+    assert_eq!(role_of("newcomer").await, Role::User);
+    // Unmapped groups only get the default role
+    service
+        .authenticate(
+            &proxy,
+            &headers(&[("remote-user", "newcomer"), ("remote-groups", "other")]),
+        )
+        .await
+        .unwrap();
+    assert_eq!(role_of("newcomer").await, Role::Guest);
+
+    // Without an email header the account gets a placeholder address
+    let ProxyIdentity::User(bare_id) = service
+        .authenticate(&proxy, &headers(&[("remote-user", "bare")]))
+        .await
+        .unwrap()
+    else {
+        panic!("Expected a provisioned user");
+    };
+    let bare = UserRepo::new()
+        .await
+        .get_by_id(bare_id)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(bare.email, "bare@proxy.invalid");
+    assert_eq!(bare.role(), Role::Guest);
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn test_first_proxy_user_becomes_admin() {
+    setup().await.expect("Setup failed");
+    let service = service(|_| {});
+    let proxy = from([10, 0, 0, 2]);
+
+    // Registration is closed on a new server, the proxy admits users anyway
+    for username in ["founder", "follower"] {
+        assert!(matches!(
+            service
+                .authenticate(&proxy, &headers(&[("remote-user", username)]))
+                .await
+                .unwrap(),
+            ProxyIdentity::User(_)
+        ));
+    }
+    assert_eq!(role_of("founder").await, Role::Admin);
+    assert_eq!(role_of("follower").await, Role::User);
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn test_provisioning_can_be_turned_off() {
+    setup().await.expect("Setup failed");
+    let service = service(|config| config.auto_provision = false);
+
+    assert!(matches!(
+        service
+            .authenticate(
+                &from([10, 9, 9, 9]),
+                &headers(&[("remote-user", "stranger")])
+            )
+            .await
+            .unwrap(),
+        ProxyIdentity::Rejected(_)
+    ));
+    assert!(UserRepo::new()
+        .await
+        .get_by_username("stranger")
+        .await
+        .unwrap()
+        .is_none());
+}
+
+/// Request parts as they arrive from `peer`
+fn parts_from(peer: [u8; 4], headers: &[(&'static str, String)]) -> Parts {
+    let mut builder = Request::builder();
+    for (name, value) in headers {
+        builder = builder.header(*name, value);
+    }
+    let (mut parts, _) = builder.body(()).unwrap().into_parts();
+    parts
+        .extensions
+        .insert(ConnectInfo(SocketAddr::from((peer, 40000))));
+    parts
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn test_extractors_accept_proxy_users_and_tokens() {
+    // Read once per process, every test in this file sees the same configuration
+    std::env::set_var("PROXY_AUTH", "true");
+    std::env::set_var("PROXY_AUTH_TRUSTED", "127.0.0.1, 10.0.0.0/8");
+    std::env::set_var("PROXY_AUTH_GROUP_ROLES", "admins=admin");
+    setup().await.expect("Setup failed");
+    let member_id = create_user("member", "user").await;
+
+    let mut parts = parts_from([127, 0, 0, 1], &[("remote-user", "member".to_string())]);
+    let user = AuthUser::from_request_parts(&mut parts, &()).await;
+    assert_eq!(user.map(|u| u.id).ok(), Some(member_id));
+    let mut parts = parts_from([127, 0, 0, 1], &[("remote-user", "member".to_string())]);
+    assert!(LoginUser::from_request_parts(&mut parts, &()).await.is_ok());
+
+    // A direct client can't claim to be someone
+    let mut parts = parts_from([192, 0, 2, 1], &[("remote-user", "member".to_string())]);
+    let rejected = AuthUser::from_request_parts(&mut parts, &())
+        .await
+        .map(|_| ())
+        .unwrap_err()
+        .into_response();
+    assert_eq!(rejected.status(), StatusCode::UNAUTHORIZED);
+
+    // but signs in with a JWT as before
+    let token = Tokenizer::get_instance()
+        .await
+        .generate_token(member_id)
+        .unwrap();
+    let mut parts = parts_from(
+        [192, 0, 2, 1],
+        &[
+            ("remote-user", "someone_else".to_string()),
+            (header::AUTHORIZATION.as_str(), format!("Bearer {}", token)),
+        ],
+    );
+    let user = AuthUser::from_request_parts(&mut parts, &()).await;
+    assert_eq!(user.map(|u| u.id).ok(), Some(member_id));
+
+    // Groups decide the role of provisioned users
+    let mut parts = parts_from(
+        [10, 1, 2, 3],
+        &[
+            ("remote-user", "boss".to_string()),
+            ("remote-groups", "admins".to_string()),
+        ],
+    );
+    assert!(AdminUser::from_request_parts(&mut parts, &()).await.is_ok());
+    assert_eq!(role_of("boss").await, Role::Admin);
+}
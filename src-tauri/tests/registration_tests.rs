@@ -0,0 +1,195 @@
+mod common;
+
+use diesel::prelude::*;
+use diesel::result::Error;
+use diesel_async::RunQueryDsl;
+
+use stellaron_lib::data::database;
+use stellaron_lib::data::models::users::Role;
+use stellaron_lib::data::repos::implementors::invite_repo::InviteRepo;
+use stellaron_lib::data::repos::implementors::user_repo::UserRepo;
+use stellaron_lib::data::repos::traits::repository::Repository;
+use stellaron_lib::services::registration_service::{
+    CreatedInvite, InviteCreation, Registration, RegistrationPolicy, RegistrationService,
+};
+use stellaron_lib::services::user_service::UserChange;
+
+use common::create_user;
+
+/// Helper function to clear the tables and the registration policy before each test
+async fn setup() -> Result<(), Error> {
+    common::setup().await?;
+    common::clear_settings().await
+}
+
+async fn register(username: &str, invite: Option<&str>) -> Registration {
+    RegistrationService::new()
+        .await
+        .register(
+            username,
+            &format!("{}@test.com", username),
+            "long enough",
+            invite,
+        )
+        .await
+        .expect("Failed to register")
+}
+
+fn registered_role(registration: Registration) -> Role {
+    match registration {
+        Registration::Created(user) => user.role(),
+        other => panic!("Expected an account, got {:?}", other),
+    }
+}
+
+async fn invite(created_by: i32, role: Role, days: Option<i64>) -> CreatedInvite {
+    match RegistrationService::new()
+        .await
+        .create_invite(created_by, role, days)
+        .await
+        .expect("Failed to create invite")
+    {
+        InviteCreation::Created(created) => *created,
+        other => panic!("Expected an invite, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn test_first_account_becomes_admin() {
+    setup().await.expect("Setup failed");
+    let service = RegistrationService::new().await;
+    assert!(service.setup_required().await.unwrap());
+    assert_eq!(service.policy().await.unwrap(), RegistrationPolicy::Closed);
+
+    // Registration is closed, but the first account is always accepted
+    assert_eq!(registered_role(register("owner", None).await), Role::Admin);
+    assert!(!service.setup_required().await.unwrap());
+
+    assert_eq!(register("second", None).await, Registration::Closed);
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn test_open_registration() {
+    setup().await.expect("Setup failed");
+    create_user("admin", "admin").await;
+    let service = RegistrationService::new().await;
+    service.set_policy(RegistrationPolicy::Open).await.unwrap();
+    assert_eq!(service.policy().await.unwrap(), RegistrationPolicy::Open);
+
+    assert_eq!(registered_role(register("reader", None).await), Role::User);
+    assert_eq!(
+        register("reader", None).await,
+        Registration::Rejected(UserChange::Conflict("Username is already taken"))
+    );
+    // A code is optional, but a wrong one is still refused
+    assert_eq!(
+        register("other", Some("not-a-code")).await,
+        Registration::InvalidInvite
+    );
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn test_invite_only_registration() {
+    setup().await.expect("Setup failed");
+    let admin = create_user("admin", "admin").await;
+    let service = RegistrationService::new().await;
+    service
+        .set_policy(RegistrationPolicy::InviteOnly)
+        .await
+        .unwrap();
+
+    assert_eq!(register("reader", None).await, Registration::InvalidInvite);
+
+    let created = invite(admin, Role::Guest, None).await;
+    assert_eq!(service.list_invites().await.unwrap().len(), 1);
+
+    // A rejected registration doesn't use the invite up
+    assert_eq!(
+        register("admin", Some(&created.code)).await,
+        Registration::Rejected(UserChange::Conflict("Username is already taken"))
+    );
+    assert_eq!(
+        registered_role(register("reader", Some(&created.code)).await),
+        Role::Guest
+    );
+    assert_eq!(
+        register("another", Some(&created.code)).await,
+        Registration::InvalidInvite
+    );
+    assert!(service.list_invites().await.unwrap().is_empty());
+
+    let used = InviteRepo::new()
+        .await
+        .get_by_id(created.invite.invite_id)
+        .await
+        .unwrap()
+        .unwrap();
+    let reader = UserRepo::new()
+        .await
+        .get_by_username("reader")
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(used.used_by, Some(reader.user_id));
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn test_invite_expiry_and_revocation() {
+    setup().await.expect("Setup failed");
+    let admin = create_user("admin", "admin").await;
+    let service = RegistrationService::new().await;
+    service
+        .set_policy(RegistrationPolicy::InviteOnly)
+        .await
+        .unwrap();
+
+    assert_eq!(
+        service
+            .create_invite(admin, Role::User, Some(0))
+            .await
+            .unwrap(),
+        InviteCreation::InvalidExpiry
+    );
+    for days in [3651, i64::MAX] {
+        assert_eq!(
+            service
+                .create_invite(admin, Role::User, Some(days))
+                .await
+                .unwrap(),
+            InviteCreation::InvalidExpiry
+        );
+    }
+
+    let expired = invite(admin, Role::User, Some(1)).await;
+    {
+        use stellaron_lib::data::models::schema::invites::dsl::*;
+        let mut conn = database::connect_from_pool().await.unwrap();
+        diesel::update(invites.filter(invite_id.eq(expired.invite.invite_id)))
+            .set(expires_at.eq("2000-01-01 00:00:00"))
+            .execute(&mut conn)
+            .await
+            .unwrap();
+    }
+    assert_eq!(
+        register("reader", Some(&expired.code)).await,
+        Registration::InvalidInvite
+    );
+
+    let revoked = invite(admin, Role::User, None).await;
+    assert!(service
+        .revoke_invite(revoked.invite.invite_id)
+        .await
+        .unwrap());
+    assert!(!service
+        .revoke_invite(revoked.invite.invite_id)
+        .await
+        .unwrap());
+    assert_eq!(
+        register("reader", Some(&revoked.code)).await,
+        Registration::InvalidInvite
+    );
+}
@@ -0,0 +1,192 @@
+mod common;
+
+use stellaron_lib::data::models::sessions::NewSession;
+use stellaron_lib::data::repos::implementors::session_repo::SessionRepo;
+use stellaron_lib::data::repos::traits::repository::Repository;
+use stellaron_lib::services::session_service::{hash_token, RefreshOutcome, SessionService};
+
+use common::{create_user, setup};
+
+#[tokio::test]
+#[serial_test::serial]
+async fn test_create_stores_only_hash() {
+    setup().await.expect("Setup failed");
+    let uid = create_user("session_hash", "user").await;
+    let service = SessionService::new().await;
+
+    let tokens = service
+        .create(uid, Some("Kobo"), Some("KoboReader/4.38"))
+        .await
+        .expect("Failed to create session");
+    assert!(!tokens.access_token.is_empty());
+
+    let repo = SessionRepo::new().await;
+    assert!(repo
+        .get_by_token_hash(&tokens.refresh_token)
+        .await
+        .unwrap()
+        .is_none());
+    let session = repo
+        .get_by_token_hash(&hash_token(&tokens.refresh_token))
+        .await
+        .unwrap()
+        .expect("Session should be stored by hash");
+    assert_eq!(session.session_id, tokens.session_id);
+    assert_eq!(session.user_id, uid);
+    assert_eq!(session.device_name.as_deref(), Some("Kobo"));
+    assert_eq!(session.user_agent.as_deref(), Some("KoboReader/4.38"));
+    assert!(session.revoked_at.is_none());
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn test_refresh_rotates_token() {
+    setup().await.expect("Setup failed");
+    let uid = create_user("session_rotate", "user").await;
+    let service = SessionService::new().await;
+
+    let first = service.create(uid, Some("Phone"), None).await.unwrap();
+    let second = match service.refresh(&first.refresh_token, Some("App/2")).await {
+        Ok(RefreshOutcome::Rotated(tokens)) => tokens,
+        other => panic!("Expected rotation, got {:?}", other),
+    };
+    assert_ne!(first.refresh_token, second.refresh_token);
+    assert_ne!(first.session_id, second.session_id);
+
+    let repo = SessionRepo::new().await;
+    let old = repo.get_by_id(first.session_id).await.unwrap().unwrap();
+    assert!(old.revoked_at.is_some());
+    assert_eq!(old.replaced_by, Some(second.session_id));
+
+    let new = repo.get_by_id(second.session_id).await.unwrap().unwrap();
+    assert_eq!(new.family_id, old.family_id);
+    assert_eq!(new.device_name.as_deref(), Some("Phone"));
+    assert_eq!(new.user_agent.as_deref(), Some("App/2"));
+    assert_eq!(new.created_at, old.created_at);
+
+    let sessions = service.list(uid).await.unwrap();
+    assert_eq!(sessions.len(), 1);
+    assert_eq!(sessions[0].session_id, second.session_id);
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn test_reuse_revokes_family() {
+    setup().await.expect("Setup failed");
+    let uid = create_user("session_reuse", "user").await;
+    let service = SessionService::new().await;
+
+    let first = service.create(uid, None, None).await.unwrap();
+    let other = service.create(uid, Some("Other"), None).await.unwrap();
+    let second = match service.refresh(&first.refresh_token, None).await.unwrap() {
+        RefreshOutcome::Rotated(tokens) => tokens,
+        outcome => panic!("Expected rotation, got {:?}", outcome),
+    };
+
+    assert_eq!(
+        service.refresh(&first.refresh_token, None).await.unwrap(),
+        RefreshOutcome::Reused
+    );
+    // The thief's successor is revoked too, sessions from other logins are untouched
+    assert_eq!(
+        service.refresh(&second.refresh_token, None).await.unwrap(),
+        RefreshOutcome::Invalid
+    );
+    let sessions = service.list(uid).await.unwrap();
+    assert_eq!(sessions.len(), 1);
+    assert_eq!(sessions[0].session_id, other.session_id);
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn test_unknown_and_logged_out_tokens() {
+    setup().await.expect("Setup failed");
+    let uid = create_user("session_logout", "user").await;
+    let service = SessionService::new().await;
+
+    assert_eq!(
+        service.refresh("not-a-token", None).await.unwrap(),
+        RefreshOutcome::Invalid
+    );
+
+    let tokens = service.create(uid, None, None).await.unwrap();
+    assert!(service.revoke_token(&tokens.refresh_token).await.unwrap());
+    assert!(!service.revoke_token(&tokens.refresh_token).await.unwrap());
+    assert_eq!(
+        service.refresh(&tokens.refresh_token, None).await.unwrap(),
+        RefreshOutcome::Invalid
+    );
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn test_revoke_one_and_all() {
+    setup().await.expect("Setup failed");
+    let uid = create_user("session_owner", "user").await;
+    let intruder = create_user("session_intruder", "user").await;
+    let service = SessionService::new().await;
+
+    let a = service.create(uid, Some("A"), None).await.unwrap();
+    let b = service.create(uid, Some("B"), None).await.unwrap();
+    let c = service.create(uid, Some("C"), None).await.unwrap();
+    assert_eq!(service.list(uid).await.unwrap().len(), 3);
+
+    // Another user can't revoke someone else's session
+    assert!(!service.revoke(intruder, a.session_id).await.unwrap());
+    assert!(service.revoke(uid, a.session_id).await.unwrap());
+    assert!(!service.revoke(uid, a.session_id).await.unwrap());
+
+    let remaining: Vec<i32> = service
+        .list(uid)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|s| s.session_id)
+        .collect();
+    assert_eq!(remaining.len(), 2);
+    assert!(remaining.contains(&b.session_id) && remaining.contains(&c.session_id));
+
+    service.revoke_all(uid).await.unwrap();
+    assert!(service.list(uid).await.unwrap().is_empty());
+    assert_eq!(
+        service.refresh(&c.refresh_token, None).await.unwrap(),
+        RefreshOutcome::Invalid
+    );
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn test_expired_sessions() {
+    setup().await.expect("Setup failed");
+    let uid = create_user("session_expired", "user").await;
+    let repo = SessionRepo::new().await;
+    let service = SessionService::new().await;
+
+    let token = "expired-refresh-token";
+    repo.add(NewSession {
+        user_id: uid,
+        family_id: "expired-family",
+        token_hash: &hash_token(token),
+        device_name: None,
+        user_agent: None,
+        created_at: Some("2020-01-01 00:00:00"),
+        expires_at: "2020-01-31 00:00:00",
+        access_jti: None,
+    })
+    .await
+    .expect("Failed to add session");
+
+    assert!(service.list(uid).await.unwrap().is_empty());
+    assert_eq!(
+        service.refresh(token, None).await.unwrap(),
+        RefreshOutcome::Invalid
+    );
+
+    // Signing in again clears the expired rows
+    service.create(uid, None, None).await.unwrap();
+    assert!(repo
+        .get_by_token_hash(&hash_token(token))
+        .await
+        .unwrap()
+        .is_none());
+}
@@ -0,0 +1,188 @@
+mod common;
+
+use std::net::{IpAddr, Ipv4Addr};
+use std::time::Duration;
+
+use stellaron_lib::services::authentication_service::{AuthenticationService, LoginOutcome};
+use stellaron_lib::services::throttle_service::{LoginThrottle, ThrottlePolicy, Throttled};
+
+use common::{create_user_with_password, setup};
+
+const ACCOUNT: ThrottlePolicy = ThrottlePolicy {
+    free_attempts: 2,
+    base_delay: Duration::from_millis(100),
+    max_delay: Duration::from_millis(400),
+    lockout_after: Some(5),
+    lockout_for: Duration::from_secs(60),
+    forget_after: Duration::from_secs(60),
+};
+
+const IP: ThrottlePolicy = ThrottlePolicy {
+    free_attempts: 3,
+    base_delay: Duration::from_secs(30),
+    max_delay: Duration::from_secs(30),
+    lockout_after: None,
+    lockout_for: Duration::ZERO,
+    forget_after: Duration::from_secs(60),
+};
+
+fn ip(last: u8) -> Option<IpAddr> {
+    Some(IpAddr::V4(Ipv4Addr::new(192, 0, 2, last)))
+}
+
+#[test]
+fn test_backoff_grows_until_lockout() {
+    let throttle = LoginThrottle::new(ACCOUNT, IP);
+
+    for _ in 0..2 {
+        throttle.record_failure("alice", None);
+        assert_eq!(throttle.check("alice", None), Ok(()));
+    }
+
+    throttle.record_failure("alice", None);
+    let first = match throttle.check("Alice", None) {
+        Err(Throttled::Wait(d)) => d,
+        other => panic!("Expected a delay, got {:?}", other),
+    };
+    assert!(first <= Duration::from_millis(100));
+
+    throttle.record_failure("alice", None);
+    let second = throttle.check("alice", None).unwrap_err().retry_after();
+    assert!(second > first && second <= Duration::from_millis(200));
+    assert!(!throttle.is_locked("alice"));
+
+    throttle.record_failure("alice", None);
+    assert!(matches!(
+        throttle.check("alice", None),
+        Err(Throttled::Locked(_))
+    ));
+    assert!(throttle.is_locked("alice"));
+    // Other accounts are unaffected
+    assert_eq!(throttle.check("bob", None), Ok(()));
+
+    assert!(throttle.unlock("alice"));
+    assert_eq!(throttle.check("alice", None), Ok(()));
+    assert!(!throttle.unlock("alice"));
+}
+
+#[test]
+fn test_delay_expires() {
+    let throttle = LoginThrottle::new(ACCOUNT, IP);
+    for _ in 0..3 {
+        throttle.record_failure("carol", None);
+    }
+    assert!(throttle.check("carol", None).is_err());
+
+    std::thread::sleep(Duration::from_millis(150));
+    assert_eq!(throttle.check("carol", None), Ok(()));
+}
+
+#[test]
+fn test_address_is_throttled_across_accounts() {
+    let throttle = LoginThrottle::new(ACCOUNT, IP);
+    for name in ["a", "b", "c", "d"] {
+        throttle.record_failure(name, ip(1));
+    }
+
+    // A fresh account from the same address waits, another address doesn't
+    assert!(matches!(
+        throttle.check("e", ip(1)),
+        Err(Throttled::Wait(_))
+    ));
+    assert_eq!(throttle.check("e", ip(2)), Ok(()));
+
+    // Signing in clears the account, not the address
+    throttle.record_success("a");
+    assert!(throttle.check("a", ip(1)).is_err());
+    assert_eq!(throttle.check("a", None), Ok(()));
+}
+
+#[test]
+fn test_oldest_entries_make_room() {
+    let strict = ThrottlePolicy {
+        free_attempts: 0,
+        ..ACCOUNT
+    };
+    let throttle = LoginThrottle::new(strict, IP);
+    throttle.record_failure("first", None);
+    std::thread::sleep(Duration::from_millis(5));
+    for i in 1..10_000 {
+        throttle.record_failure(&format!("user{}", i), None);
+    }
+    assert!(throttle.check("first", None).is_err());
+
+    // The table is full of recent failures, the oldest one is forgotten
+    throttle.record_failure("latest", None);
+    assert_eq!(throttle.check("first", None), Ok(()));
+    assert!(throttle.check("user1", None).is_err());
+    assert!(throttle.check("latest", None).is_err());
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn test_login_is_throttled() {
+    setup().await.expect("Setup failed");
+    let id = create_user_with_password("throttled_login", "user", "secret").await;
+    let service = AuthenticationService::new();
+
+    assert_eq!(
+        service
+            .login("throttled_login", "secret", None)
+            .await
+            .unwrap(),
+        LoginOutcome::Success(id)
+    );
+
+    for _ in 0..6 {
+        assert_eq!(
+            service
+                .login("throttled_login", "wrong", None)
+                .await
+                .unwrap(),
+            LoginOutcome::InvalidCredentials
+        );
+    }
+    // Even the right password is refused until the delay passed
+    assert!(matches!(
+        service
+            .login("throttled_login", "secret", None)
+            .await
+            .unwrap(),
+        LoginOutcome::Throttled(Throttled::Wait(_))
+    ));
+    assert!(matches!(
+        service
+            .verify_credentials("throttled_login", "secret", None)
+            .await
+            .unwrap(),
+        LoginOutcome::Throttled(_)
+    ));
+
+    LoginThrottle::global().unlock("throttled_login");
+    assert_eq!(
+        service
+            .login("throttled_login", "secret", None)
+            .await
+            .unwrap(),
+        LoginOutcome::Success(id)
+    );
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn test_unknown_user_is_treated_alike() {
+    setup().await.expect("Setup failed");
+    let service = AuthenticationService::new();
+
+    for _ in 0..6 {
+        assert_eq!(
+            service.login("no_such_user", "secret", None).await.unwrap(),
+            LoginOutcome::InvalidCredentials
+        );
+    }
+    assert!(matches!(
+        service.login("no_such_user", "secret", None).await.unwrap(),
+        LoginOutcome::Throttled(_)
+    ));
+    LoginThrottle::global().unlock("no_such_user");
+}
@@ -0,0 +1,243 @@
+mod common;
+
+use std::fs;
+
+use jsonwebtoken::{decode_header, encode, Algorithm, EncodingKey, Header};
+
+use stellaron_lib::data::repos::implementors::setting_repo::SettingRepo;
+use stellaron_lib::services::session_service::SessionService;
+use stellaron_lib::services::token_service::{Claims, Keyring, Tokenizer};
+
+use common::{create_user, setup};
+
+const SECRET: &[u8] = b"current-secret-with-at-least-32-bytes";
+const OLD_SECRET: &[u8] = b"previous-secret-with-at-least-32-bytes";
+
+fn fixture(name: &str) -> Vec<u8> {
+    fs::read(format!(
+        "{}/tests/fixtures/jwt/{}",
+        env!("CARGO_MANIFEST_DIR"),
+        name
+    ))
+    .expect("Missing key fixture")
+}
+
+fn tokenizer(keyring: Keyring) -> Tokenizer {
+    Tokenizer::new(keyring, 3600, "stellaron", "stellaron")
+}
+
+#[test]
+fn test_standard_claims_and_kid() {
+    let tokenizer = tokenizer(Keyring::hmac(SECRET));
+    let (token, claims) = tokenizer.issue(7).unwrap();
+
+    let header = decode_header(&token).unwrap();
+    assert_eq!(header.alg, Algorithm::HS256);
+    assert_eq!(
+        header.kid.as_deref(),
+        Some(Keyring::hmac(SECRET).key_ids()[0])
+    );
+
+    let decoded = tokenizer.decode_token(&token).unwrap();
+    assert_eq!(decoded.sub, 7);
+    assert_eq!(decoded.iss, "stellaron");
+    assert_eq!(decoded.aud, "stellaron");
+    assert_eq!(decoded.jti, claims.jti);
+    assert!(decoded.iat <= decoded.exp);
+
+    // Every token gets its own id
+    let (_, other) = tokenizer.issue(7).unwrap();
+    assert_ne!(other.jti, claims.jti);
+}
+
+#[test]
+fn test_rejects_foreign_and_malformed_tokens() {
+    let tokenizer = tokenizer(Keyring::hmac(SECRET));
+    let (token, claims) = tokenizer.issue(7).unwrap();
+
+    // Signed by another server
+    let other = Tokenizer::new(Keyring::hmac(SECRET), 3600, "elsewhere", "elsewhere");
+    assert!(tokenizer
+        .decode_token(&other.generate_token(7).unwrap())
+        .is_err());
+
+    // Expired
+    let expired = Tokenizer::new(Keyring::hmac(SECRET), -3600, "stellaron", "stellaron");
+    assert!(tokenizer
+        .decode_token(&expired.generate_token(7).unwrap())
+        .is_err());
+
+    // Tokens without a kid, like the ones issued before keyrings, are refused
+    let unnamed = encode(
+        &Header::default(),
+        &claims,
+        &EncodingKey::from_secret(SECRET),
+    )
+    .unwrap();
+    assert!(tokenizer.decode_token(&unnamed).is_err());
+
+    // A known kid with the wrong algorithm
+    let mut header = decode_header(&token).unwrap();
+    header.alg = Algorithm::HS512;
+    let confused = encode(&header, &claims, &EncodingKey::from_secret(SECRET)).unwrap();
+    assert!(tokenizer.decode_token(&confused).is_err());
+
+    // Missing standard claims
+    #[derive(serde::Serialize)]
+    struct Bare {
+        sub: i32,
+        exp: usize,
+    }
+    let header = Header {
+        kid: decode_header(&token).unwrap().kid,
+        ..Default::default()
+    };
+    let bare = encode(
+        &header,
+        &Bare {
+            sub: 7,
+            exp: claims.exp,
+        },
+        &EncodingKey::from_secret(SECRET),
+    )
+    .unwrap();
+    assert!(tokenizer.decode_token(&bare).is_err());
+}
+
+#[test]
+fn test_rotation_keeps_previous_keys() {
+    let old = tokenizer(Keyring::hmac(OLD_SECRET));
+    let token = old.generate_token(7).unwrap();
+
+    let rotated = tokenizer(Keyring::hmac(SECRET).accept_hmac(OLD_SECRET));
+    assert_eq!(rotated.decode_token(&token).unwrap().sub, 7);
+    // New tokens use the new key
+    let new_token = rotated.generate_token(7).unwrap();
+    assert!(old.decode_token(&new_token).is_err());
+
+    // Once the old key is dropped its tokens stop working
+    let finished = tokenizer(Keyring::hmac(SECRET));
+    assert!(finished.decode_token(&token).is_err());
+    assert_eq!(finished.decode_token(&new_token).unwrap().sub, 7);
+}
+
+#[test]
+fn test_asymmetric_keys() {
+    let eddsa = tokenizer(
+        Keyring::from_pem(
+            Algorithm::EdDSA,
+            &fixture("ed25519_private.pem"),
+            &fixture("ed25519_public.pem"),
+        )
+        .unwrap(),
+    );
+    let token = eddsa.generate_token(7).unwrap();
+    assert_eq!(decode_header(&token).unwrap().alg, Algorithm::EdDSA);
+    assert_eq!(eddsa.decode_token(&token).unwrap().sub, 7);
+
+    let rsa = tokenizer(
+        Keyring::from_pem(
+            Algorithm::RS256,
+            &fixture("rsa_private.pem"),
+            &fixture("rsa_public.pem"),
+        )
+        .unwrap(),
+    );
+    let rsa_token = rsa.generate_token(7).unwrap();
+    assert_eq!(decode_header(&rsa_token).unwrap().alg, Algorithm::RS256);
+    assert_eq!(rsa.decode_token(&rsa_token).unwrap().sub, 7);
+    assert!(eddsa.decode_token(&rsa_token).is_err());
+
+    // Rotating from an old key pair
+    let old = tokenizer(
+        Keyring::from_pem(
+            Algorithm::EdDSA,
+            &fixture("ed25519_old_private.pem"),
+            &fixture("ed25519_old_public.pem"),
+        )
+        .unwrap(),
+    );
+    let old_token = old.generate_token(7).unwrap();
+    assert!(eddsa.decode_token(&old_token).is_err());
+    let rotated = tokenizer(
+        Keyring::from_pem(
+            Algorithm::EdDSA,
+            &fixture("ed25519_private.pem"),
+            &fixture("ed25519_public.pem"),
+        )
+        .unwrap()
+        .accept_pem(Algorithm::EdDSA, &fixture("ed25519_old_public.pem"))
+        .unwrap(),
+    );
+    assert_eq!(rotated.decode_token(&old_token).unwrap().sub, 7);
+
+    assert!(Keyring::from_pem(
+        Algorithm::HS256,
+        &fixture("rsa_private.pem"),
+        &fixture("rsa_public.pem"),
+    )
+    .is_err());
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn test_generated_secret_is_persisted() {
+    if std::env::var("JWT_SECRET").is_ok() {
+        return;
+    }
+    let token = Tokenizer::get_instance().await.generate_token(7).unwrap();
+
+    let secret = SettingRepo::new()
+        .await
+        .get("jwt_secret")
+        .await
+        .unwrap()
+        .expect("Secret was not stored");
+    assert_eq!(secret.value.len(), 64);
+    // A restart reading the stored secret accepts earlier tokens
+    let restarted = tokenizer(Keyring::hmac(secret.value.as_bytes()));
+    assert_eq!(restarted.decode_token(&token).unwrap().sub, 7);
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn test_revoked_tokens_are_refused() {
+    setup().await.expect("Setup failed");
+    let uid = create_user("token_revoked", "user").await;
+    let tokenizer = Tokenizer::get_instance().await;
+
+    let (token, claims): (String, Claims) = tokenizer.issue(uid).unwrap();
+    assert!(tokenizer.verify(&token).await.unwrap().is_some());
+    tokenizer.revoke(vec![claims.jti.clone()]).await.unwrap();
+    assert!(tokenizer.verify(&token).await.unwrap().is_none());
+    // Still correctly signed, only the denylist refuses it
+    assert!(tokenizer.decode_token(&token).is_ok());
+
+    // Signing out denies the access token issued with the session
+    let service = SessionService::new().await;
+    let tokens = service.create(uid, None, None).await.unwrap();
+    let kept = service.create(uid, None, None).await.unwrap();
+    assert!(tokenizer
+        .verify(&tokens.access_token)
+        .await
+        .unwrap()
+        .is_some());
+    assert!(service.revoke_token(&tokens.refresh_token).await.unwrap());
+    assert!(tokenizer
+        .verify(&tokens.access_token)
+        .await
+        .unwrap()
+        .is_none());
+    assert!(tokenizer
+        .verify(&kept.access_token)
+        .await
+        .unwrap()
+        .is_some());
+
+    service.revoke_all(uid).await.unwrap();
+    assert!(tokenizer
+        .verify(&kept.access_token)
+        .await
+        .unwrap()
+        .is_none());
+}
@@ -0,0 +1,306 @@
+mod common;
+
+use axum::extract::FromRequestParts;
+use axum::http::{header, Request, StatusCode};
+use axum::response::IntoResponse;
+use base64::Engine;
+
+use stellaron_lib::controllers::auth_middleware::DeviceUser;
+use stellaron_lib::data::models::api_keys::ApiScope;
+use stellaron_lib::data::repos::implementors::recovery_code_repo::RecoveryCodeRepo;
+use stellaron_lib::services::api_key_service::{ApiKeyCreation, ApiKeyService};
+use stellaron_lib::services::authentication_service::{AuthenticationService, LoginOutcome};
+use stellaron_lib::services::throttle_service::LoginThrottle;
+use stellaron_lib::services::two_factor_service::{
+    current_step, totp_code, ChallengeOutcome, TwoFactorChange, TwoFactorService,
+    MAX_CHALLENGE_ATTEMPTS, RECOVERY_CODE_COUNT,
+};
+
+use common::{create_user_with_password, setup};
+
+/// Enrolls and confirms a user, returning the secret, the step of the code it was
+/// confirmed with and the recovery codes
+async fn enable(user_id: i32) -> (String, i64, Vec<String>) {
+    let service = TwoFactorService::new().await;
+    let enrollment = match service.enroll(user_id).await.unwrap() {
+        TwoFactorChange::Done(enrollment) => enrollment,
+        other => panic!("Expected an enrollment, got {:?}", other),
+    };
+    let step = current_step();
+    let code = totp_code(&enrollment.secret, step).unwrap();
+    match service.confirm(user_id, &code).await.unwrap() {
+        TwoFactorChange::Done(codes) => (enrollment.secret, step, codes),
+        other => panic!("Expected recovery codes, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_totp_matches_rfc_6238() {
+    // The SHA1 seed of RFC 6238 appendix B, "12345678901234567890"
+    let secret = "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ";
+    assert_eq!(totp_code(secret, 59 / 30).unwrap(), "287082");
+    assert_eq!(totp_code(secret, 1111111109 / 30).unwrap(), "081804");
+    assert_eq!(totp_code(secret, 2000000000 / 30).unwrap(), "279037");
+    // Lowercase and spaced the way people copy it
+    assert_eq!(
+        totp_code("gezd gnbv gy3t qojq gezd gnbv gy3t qojq", 1).unwrap(),
+        "287082"
+    );
+    assert!(totp_code("not base32!", 1).is_none());
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn test_enrollment() {
+    setup().await.expect("Setup failed");
+    let user_id = create_user_with_password("enrolling", "user", "secret").await;
+    let service = TwoFactorService::new().await;
+
+    assert_eq!(
+        service.confirm(user_id, "123456").await.unwrap(),
+        TwoFactorChange::NotEnrolled
+    );
+    let enrollment = match service.enroll(user_id).await.unwrap() {
+        TwoFactorChange::Done(enrollment) => enrollment,
+        other => panic!("Expected an enrollment, got {:?}", other),
+    };
+    assert_eq!(enrollment.secret.len(), 32);
+    assert!(enrollment
+        .uri
+        .starts_with("otpauth://totp/Stellaron:enrolling?secret="));
+    assert!(enrollment.uri.contains(&enrollment.secret));
+
+    // Pending enrollments don't change how the user signs in
+    let status = service.status(user_id).await.unwrap();
+    assert!(status.pending && !status.enabled);
+    assert_eq!(
+        AuthenticationService::new()
+            .login("enrolling", "secret", None)
+            .await
+            .unwrap(),
+        LoginOutcome::Success(user_id)
+    );
+
+    // Not the code of any step around now
+    let step = current_step();
+    let window: Vec<String> = (step - 2..=step + 2)
+        .map(|s| totp_code(&enrollment.secret, s).unwrap())
+        .collect();
+    let wrong = ["000000", "111111", "222222", "333333", "444444", "555555"]
+        .into_iter()
+        .find(|c| !window.iter().any(|w| w == c))
+        .unwrap();
+    assert_eq!(
+        service.confirm(user_id, wrong).await.unwrap(),
+        TwoFactorChange::InvalidCode
+    );
+    let code = totp_code(&enrollment.secret, current_step()).unwrap();
+    let codes = match service.confirm(user_id, &code).await.unwrap() {
+        TwoFactorChange::Done(codes) => codes,
+        other => panic!("Expected recovery codes, got {:?}", other),
+    };
+    assert_eq!(codes.len(), RECOVERY_CODE_COUNT);
+    assert!(codes
+        .iter()
+        .all(|c| c.len() == 19 && c.split('-').all(|group| group.len() == 4)));
+    // Stored as argon2 hashes, never the codes themselves
+    let stored = RecoveryCodeRepo::new()
+        .await
+        .get_unused(user_id)
+        .await
+        .unwrap();
+    assert_eq!(stored.len(), RECOVERY_CODE_COUNT);
+    assert!(stored.iter().all(|c| c.code_hash.starts_with("$argon2")));
+
+    let status = service.status(user_id).await.unwrap();
+    assert!(status.enabled && !status.pending);
+    assert_eq!(status.recovery_codes_left, RECOVERY_CODE_COUNT as i64);
+    assert_eq!(
+        service.enroll(user_id).await.unwrap(),
+        TwoFactorChange::AlreadyEnabled
+    );
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn test_login_challenge() {
+    setup().await.expect("Setup failed");
+    let user_id = create_user_with_password("challenged", "user", "secret").await;
+    let (secret, step, codes) = enable(user_id).await;
+    let service = TwoFactorService::new().await;
+
+    assert_eq!(
+        AuthenticationService::new()
+            .login("challenged", "secret", None)
+            .await
+            .unwrap(),
+        LoginOutcome::TwoFactorRequired(user_id)
+    );
+
+    let challenge = service
+        .start_challenge(user_id, Some("Phone"), None)
+        .await
+        .unwrap();
+    // The code used to confirm can't be used again
+    let used = totp_code(&secret, step).unwrap();
+    assert_eq!(
+        service
+            .complete_challenge(&challenge.token, &used, None)
+            .await
+            .unwrap(),
+        ChallengeOutcome::InvalidCode
+    );
+    let next = totp_code(&secret, step + 1).unwrap();
+    assert_eq!(
+        service
+            .complete_challenge(&challenge.token, &next, None)
+            .await
+            .unwrap(),
+        ChallengeOutcome::Completed {
+            user_id,
+            device_name: Some("Phone".to_string()),
+            user_agent: None,
+        }
+    );
+    // A challenge turns into one session only
+    assert_eq!(
+        service
+            .complete_challenge(&challenge.token, &next, None)
+            .await
+            .unwrap(),
+        ChallengeOutcome::InvalidChallenge
+    );
+    // Nor can a code that completed a challenge, even within the same step
+    let challenge = service.start_challenge(user_id, None, None).await.unwrap();
+    assert_eq!(
+        service
+            .complete_challenge(&challenge.token, &next, None)
+            .await
+            .unwrap(),
+        ChallengeOutcome::InvalidCode
+    );
+
+    // Recovery codes work once, however they are typed
+    let challenge = service.start_challenge(user_id, None, None).await.unwrap();
+    let recovery = codes[0].to_uppercase().replace('-', " ");
+    assert!(matches!(
+        service
+            .complete_challenge(&challenge.token, &recovery, None)
+            .await
+            .unwrap(),
+        ChallengeOutcome::Completed { .. }
+    ));
+    let challenge = service.start_challenge(user_id, None, None).await.unwrap();
+    assert_eq!(
+        service
+            .complete_challenge(&challenge.token, &codes[0], None)
+            .await
+            .unwrap(),
+        ChallengeOutcome::InvalidCode
+    );
+    assert_eq!(
+        service.status(user_id).await.unwrap().recovery_codes_left,
+        RECOVERY_CODE_COUNT as i64 - 1
+    );
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn test_challenge_is_dropped_after_wrong_codes() {
+    setup().await.expect("Setup failed");
+    let user_id = create_user_with_password("guessing", "user", "secret").await;
+    enable(user_id).await;
+    let service = TwoFactorService::new().await;
+
+    let challenge = service.start_challenge(user_id, None, None).await.unwrap();
+    for _ in 0..MAX_CHALLENGE_ATTEMPTS {
+        assert_eq!(
+            service
+                .complete_challenge(&challenge.token, "aaaaa-aaaaa", None)
+                .await
+                .unwrap(),
+            ChallengeOutcome::InvalidCode
+        );
+    }
+    assert_eq!(
+        service
+            .complete_challenge(&challenge.token, "aaaaa-aaaaa", None)
+            .await
+            .unwrap(),
+        ChallengeOutcome::InvalidChallenge
+    );
+    // Wrong codes count against the account like wrong passwords, the next one is
+    // past the free attempts
+    let challenge = service.start_challenge(user_id, None, None).await.unwrap();
+    assert_eq!(
+        service
+            .complete_challenge(&challenge.token, "aaaaa-aaaaa", None)
+            .await
+            .unwrap(),
+        ChallengeOutcome::InvalidCode
+    );
+    assert!(matches!(
+        service
+            .complete_challenge(&challenge.token, "aaaaa-aaaaa", None)
+            .await
+            .unwrap(),
+        ChallengeOutcome::Throttled(_)
+    ));
+    LoginThrottle::global().unlock("guessing");
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn test_disable_and_device_credentials() {
+    setup().await.expect("Setup failed");
+    let user_id = create_user_with_password("device_owner", "user", "secret").await;
+    let (_, _, codes) = enable(user_id).await;
+    let service = TwoFactorService::new().await;
+
+    let created = match ApiKeyService::new()
+        .await
+        .create(user_id, "Kobo", &[ApiScope::Catalog], None)
+        .await
+        .unwrap()
+    {
+        ApiKeyCreation::Created(created) => *created,
+        _ => panic!("Expected an API key"),
+    };
+    let basic = |password: &str| {
+        format!(
+            "Basic {}",
+            base64::engine::general_purpose::STANDARD.encode(format!("device_owner:{}", password))
+        )
+    };
+    let extract = |authorization: String| async move {
+        let (mut parts, _) = Request::builder()
+            .uri("/opds")
+            .header(header::AUTHORIZATION, authorization)
+            .body(())
+            .unwrap()
+            .into_parts();
+        DeviceUser::from_request_parts(&mut parts, &())
+            .await
+            .map_err(|e| e.into_response())
+    };
+
+    // The password alone no longer signs devices in, API keys still do
+    let rejected = extract(basic("secret")).await.unwrap_err();
+    assert_eq!(rejected.status(), StatusCode::UNAUTHORIZED);
+    assert_eq!(extract(basic(&created.key)).await.unwrap().id, user_id);
+
+    assert_eq!(
+        service.disable(user_id, "aaaaa-aaaaa").await.unwrap(),
+        TwoFactorChange::InvalidCode
+    );
+    assert_eq!(
+        service.disable(user_id, &codes[1]).await.unwrap(),
+        TwoFactorChange::Done(())
+    );
+    assert_eq!(
+        service.disable(user_id, &codes[2]).await.unwrap(),
+        TwoFactorChange::NotEnabled
+    );
+    assert_eq!(extract(basic("secret")).await.unwrap().id, user_id);
+    LoginThrottle::global().unlock("device_owner");
+}
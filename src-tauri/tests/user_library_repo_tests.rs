@@ -41,7 +41,7 @@ async fn create_test_user(username_val: &str) -> i32 {
     let new_user = NewUser {
         username: username_val,
         email: &format!("{}@test.com", username_val),
-        role: Some("user"),
+        role: "user",
         password_hash: "password",
     };
     repo.add(new_user)
@@ -85,6 +85,7 @@ async fn create_test_book(title_val: &str, publisher_id_val: i32) -> i32 {
         file_type: None,
         file_path: None,
         cover_image_path: None,
+        ..Default::default()
     };
     repo.add(new_book)
         .await
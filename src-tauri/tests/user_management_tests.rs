@@ -0,0 +1,398 @@
+mod common;
+
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use axum::extract::FromRequestParts;
+use axum::http::{header, request::Parts, Request, StatusCode};
+use axum::response::IntoResponse;
+use diesel_async::RunQueryDsl;
+
+use stellaron_lib::controllers::auth_middleware::{AuthUser, LoginUser};
+use stellaron_lib::data::database;
+use stellaron_lib::data::models::books::NewBook;
+use stellaron_lib::data::models::libraries::NewLibrary;
+use stellaron_lib::data::models::reading_progress::NewReadingProgress;
+use stellaron_lib::data::models::user_library::NewUserLibrary;
+use stellaron_lib::data::models::users::Role;
+use stellaron_lib::data::repos::implementors::book_repo::BookRepo;
+use stellaron_lib::data::repos::implementors::library_repo::LibraryRepo;
+use stellaron_lib::data::repos::implementors::reading_progress_repo::ReadingProgressRepo;
+use stellaron_lib::data::repos::implementors::user_library_repo::UserLibraryRepo;
+use stellaron_lib::data::repos::implementors::user_repo::UserRepo;
+use stellaron_lib::data::repos::traits::repository::Repository;
+use stellaron_lib::services::authentication_service::{AuthenticationService, LoginOutcome};
+use stellaron_lib::services::mail_service::{MailError, MailMessage, Mailer};
+use stellaron_lib::services::password_service::PasswordService;
+use stellaron_lib::services::session_service::{RefreshOutcome, SessionService};
+use stellaron_lib::services::token_service::Tokenizer;
+use stellaron_lib::services::user_service::{UserChange, UserEdit, UserService};
+
+use common::{create_user_with_password, setup};
+
+/// Keeps sent mails in memory
+#[derive(Default)]
+struct RecordingMailer {
+    sent: Mutex<Vec<MailMessage>>,
+}
+
+#[async_trait]
+impl Mailer for RecordingMailer {
+    async fn send(&self, message: &MailMessage) -> Result<(), MailError> {
+        self.sent.lock().unwrap().push(message.clone());
+        Ok(())
+    }
+}
+
+async fn parts_for(user_id: i32) -> Parts {
+    let token = Tokenizer::get_instance()
+        .await
+        .generate_token(user_id)
+        .expect("Failed to generate token");
+    let (parts, _) = Request::builder()
+        .header(header::AUTHORIZATION, format!("Bearer {}", token))
+        .body(())
+        .unwrap()
+        .into_parts();
+    parts
+}
+
+fn done(change: UserChange) -> i32 {
+    match change {
+        UserChange::Done(user) => user.user_id,
+        other => panic!("Expected a changed user, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn test_create_user() {
+    setup().await.expect("Setup failed");
+    create_user_with_password("admin", "admin", "password").await;
+    let service = UserService::new().await;
+
+    let id = done(
+        service
+            .create("editor", "editor@test.com", "long enough", Role::Admin)
+            .await
+            .unwrap(),
+    );
+    let user = UserRepo::new().await.get_by_id(id).await.unwrap().unwrap();
+    assert_eq!(user.role(), Role::Admin);
+    assert!(!user.is_disabled());
+
+    assert_eq!(
+        service
+            .create("editor", "other@test.com", "long enough", Role::User)
+            .await
+            .unwrap(),
+        UserChange::Conflict("Username is already taken")
+    );
+    assert_eq!(
+        service
+            .create("other", "editor@test.com", "long enough", Role::User)
+            .await
+            .unwrap(),
+        UserChange::Conflict("Email address is already in use")
+    );
+    assert_eq!(
+        service
+            .create("other", "other@test.com", "short", Role::User)
+            .await
+            .unwrap(),
+        UserChange::Invalid("Password is too short")
+    );
+    assert_eq!(
+        service
+            .create(" ", "other@test.com", "long enough", Role::User)
+            .await
+            .unwrap(),
+        UserChange::Invalid("Username can't be empty")
+    );
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn test_update_user_and_profile() {
+    setup().await.expect("Setup failed");
+    create_user_with_password("admin", "admin", "password").await;
+    let reader = create_user_with_password("reader", "user", "password").await;
+    create_user_with_password("taken", "user", "password").await;
+    let service = UserService::new().await;
+
+    done(
+        service
+            .update(
+                reader,
+                UserEdit {
+                    username: Some("renamed"),
+                    role: Some(Role::Guest),
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap(),
+    );
+    let user = UserRepo::new()
+        .await
+        .get_by_id(reader)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(user.username, "renamed");
+    assert_eq!(user.email, "reader@test.com");
+    assert_eq!(user.role(), Role::Guest);
+
+    // Keeping one's own email is not a conflict
+    done(
+        service
+            .update_profile(reader, None, Some("reader@test.com"))
+            .await
+            .unwrap(),
+    );
+    assert_eq!(
+        service
+            .update_profile(reader, Some("taken"), None)
+            .await
+            .unwrap(),
+        UserChange::Conflict("Username is already taken")
+    );
+    assert_eq!(
+        service
+            .update_profile(reader, None, Some("not an address"))
+            .await
+            .unwrap(),
+        UserChange::Invalid("Email address is invalid")
+    );
+    assert_eq!(
+        service
+            .update_profile(-1, Some("ghost"), None)
+            .await
+            .unwrap(),
+        UserChange::NotFound
+    );
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn test_last_admin_is_kept() {
+    setup().await.expect("Setup failed");
+    let admin = create_user_with_password("admin", "admin", "password").await;
+    let service = UserService::new().await;
+
+    let demote = UserEdit {
+        role: Some(Role::User),
+        ..Default::default()
+    };
+    assert_eq!(
+        service.update(admin, demote.clone()).await.unwrap(),
+        UserChange::LastAdmin
+    );
+    assert_eq!(
+        service.set_disabled(admin, true).await.unwrap(),
+        UserChange::LastAdmin
+    );
+    assert_eq!(service.delete(admin).await.unwrap(), UserChange::LastAdmin);
+
+    // With a second admin the first one can step down
+    create_user_with_password("second", "admin", "password").await;
+    done(service.update(admin, demote).await.unwrap());
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn test_disabled_user_is_locked_out() {
+    setup().await.expect("Setup failed");
+    create_user_with_password("admin", "admin", "password").await;
+    let reader = create_user_with_password("reader", "user", "password").await;
+    let sessions = SessionService::new().await;
+    let tokens = sessions.create(reader, None, None).await.unwrap();
+    let service = UserService::new().await;
+
+    done(service.set_disabled(reader, true).await.unwrap());
+
+    let auth = AuthenticationService::new();
+    assert_eq!(
+        auth.login("reader", "password", None).await.unwrap(),
+        LoginOutcome::Disabled
+    );
+    // A wrong password doesn't reveal that the account is disabled
+    assert_eq!(
+        auth.login("reader", "wrong", None).await.unwrap(),
+        LoginOutcome::InvalidCredentials
+    );
+    assert_eq!(
+        auth.verify_credentials("reader", "password", None)
+            .await
+            .unwrap(),
+        LoginOutcome::Disabled
+    );
+    assert_eq!(
+        sessions.refresh(&tokens.refresh_token, None).await.unwrap(),
+        RefreshOutcome::Invalid
+    );
+    // Access tokens issued before are refused too
+    let response = AuthUser::from_request_parts(&mut parts_for(reader).await, &())
+        .await
+        .map_err(|e| e.into_response())
+        .unwrap_err();
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+    done(service.set_disabled(reader, false).await.unwrap());
+    assert_eq!(
+        auth.login("reader", "password", None).await.unwrap(),
+        LoginOutcome::Success(reader)
+    );
+    assert!(
+        LoginUser::from_request_parts(&mut parts_for(reader).await, &())
+            .await
+            .is_ok()
+    );
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn test_force_password_reset() {
+    setup().await.expect("Setup failed");
+    let reader = create_user_with_password("reader", "user", "password").await;
+    let tokens = SessionService::new()
+        .await
+        .create(reader, None, None)
+        .await
+        .unwrap();
+    let mailer = Arc::new(RecordingMailer::default());
+    let service = PasswordService::new(mailer.clone());
+
+    assert!(service.force_reset(reader).await.unwrap());
+    assert!(!service.force_reset(-1).await.unwrap());
+
+    assert_eq!(mailer.sent.lock().unwrap().len(), 1);
+    assert_eq!(
+        AuthenticationService::new()
+            .login("reader", "password", None)
+            .await
+            .unwrap(),
+        LoginOutcome::InvalidCredentials
+    );
+    assert_eq!(
+        SessionService::new()
+            .await
+            .refresh(&tokens.refresh_token, None)
+            .await
+            .unwrap(),
+        RefreshOutcome::Invalid
+    );
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn test_delete_user() {
+    setup().await.expect("Setup failed");
+    create_user_with_password("admin", "admin", "password").await;
+    let reader = create_user_with_password("reader", "user", "password").await;
+
+    LibraryRepo::new()
+        .await
+        .add(NewLibrary {
+            name: "Shelf",
+            path: "/books/shelf",
+            added_by: Some(reader),
+            visibility: None,
+        })
+        .await
+        .unwrap();
+    let book_repo = BookRepo::new().await;
+    book_repo
+        .add(NewBook {
+            title: "Kept",
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    let book_id = book_repo.get_all().await.unwrap().unwrap()[0].book_id;
+    UserLibraryRepo::new()
+        .await
+        .add(NewUserLibrary {
+            user_id: reader,
+            book_id,
+        })
+        .await
+        .unwrap();
+    ReadingProgressRepo::new()
+        .await
+        .add(NewReadingProgress {
+            user_id: reader,
+            book_id,
+            current_position: "epubcfi(/6/2)",
+            chapter_title: None,
+            page_number: None,
+            progress_percentage: None,
+        })
+        .await
+        .unwrap();
+
+    let service = UserService::new().await;
+    done(service.delete(reader).await.unwrap());
+    assert_eq!(service.delete(reader).await.unwrap(), UserChange::NotFound);
+
+    assert!(UserRepo::new()
+        .await
+        .get_by_id(reader)
+        .await
+        .unwrap()
+        .is_none());
+    assert!(ReadingProgressRepo::new()
+        .await
+        .get_all()
+        .await
+        .unwrap()
+        .is_none());
+    assert!(UserLibraryRepo::new()
+        .await
+        .get_all()
+        .await
+        .unwrap()
+        .is_none());
+    // The library and the book stay, without an author
+    let library = LibraryRepo::new()
+        .await
+        .get_by_path("/books/shelf")
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(library.added_by, None);
+    assert!(book_repo.get_by_id(book_id).await.unwrap().is_some());
+}
+
+#[tokio::test]
+#[serial_test::serial]
+async fn test_delete_user_on_new_connection() {
+    setup().await.expect("Setup failed");
+    create_user_with_password("admin", "admin", "password").await;
+    let reader = create_user_with_password("reader", "user", "password").await;
+    let tokens = SessionService::new()
+        .await
+        .create(reader, None, None)
+        .await
+        .unwrap();
+
+    // With the connection used so far busy, the delete runs on a new one
+    let held = database::connect_from_pool().await.unwrap();
+    done(UserService::new().await.delete(reader).await.unwrap());
+    drop(held);
+
+    // A user created later may get the same id, the old session must not carry over
+    assert_eq!(
+        SessionService::new()
+            .await
+            .refresh(&tokens.refresh_token, None)
+            .await
+            .unwrap(),
+        RefreshOutcome::Invalid
+    );
+    use diesel::QueryDsl;
+    use stellaron_lib::data::models::schema::sessions::dsl::*;
+    let mut conn = database::connect_from_pool().await.unwrap();
+    let remaining: i64 = sessions.count().get_result(&mut conn).await.unwrap();
+    assert_eq!(remaining, 0);
+}
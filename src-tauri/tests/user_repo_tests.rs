@@ -34,7 +34,7 @@ async fn create_test_user(
     username_val: &str,
     email_val: &str,
     password_val: &str,
-    role_val: Option<&str>,
+    role_val: &str,
 ) -> Result<(), Error> {
     let repo = UserRepo::new().await;
     let new_user = NewUser {
@@ -57,7 +57,7 @@ async fn test_create_user() {
     let password = "hashedpassword123";
     let role = "admin";
 
-    let result = create_test_user(username, email, password, Some(role)).await;
+    let result = create_test_user(username, email, password, role).await;
     assert!(result.is_ok());
 
     let repo = UserRepo::new().await;
@@ -92,7 +92,7 @@ async fn test_get_user_by_id() {
     let email = "test@example.com";
     let password = "hashedpassword123";
 
-    create_test_user(username, email, password, None)
+    create_test_user(username, email, password, "admin")
         .await
         .expect("Failed to create test user");
 
@@ -136,7 +136,7 @@ async fn test_get_user_by_username() {
     let password = "uniquepass123";
     let role = "user";
 
-    create_test_user(username, email, password, Some(role))
+    create_test_user(username, email, password, role)
         .await
         .expect("Failed to create test user");
 
@@ -185,7 +185,7 @@ async fn test_get_all_users_multiple() {
     let roles = ["user", "admin", "user"];
 
     for i in 0..3 {
-        create_test_user(usernames[i], emails[i], passwords[i], Some(roles[i]))
+        create_test_user(usernames[i], emails[i], passwords[i], roles[i])
             .await
             .expect("Failed to create test user");
     }